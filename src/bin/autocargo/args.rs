@@ -11,53 +11,388 @@ use std::path::Path;
 use std::path::PathBuf;
 
 use anyhow::Result;
+use autocargo::buck_processing::BuckFixtures;
+use autocargo::buck_processing::BuckOptions;
+use autocargo::buck_processing::ThriftCratemapCache;
+use autocargo::buck_processing::is_target_pattern;
+use autocargo::buck_processing::resolve_target_patterns;
 use autocargo::config::AllProjects;
+use autocargo::config::GlobalConf;
 use autocargo::config::ProjectConf;
+use autocargo::error::AutocargoError;
+use autocargo::paths::BuildFilenames;
 use autocargo::paths::FbcodeRoot;
 use autocargo::paths::FbsourceRoot;
 use autocargo::paths::PathInFbcode;
 use autocargo::paths::process_input_paths;
 use clap::Parser;
+use clap::Subcommand;
+use clap::ValueEnum;
+use slog::Logger;
 
 const DEFAULT_CONF: &str = "fbcode/common/rust/cargo_from_buck/project_configs";
 
 const DEFAULT_UTD_MAP: &str = "tools/utd/migrated_nbtd_jobs/autocargo_verification.json";
 
 #[derive(Parser, Debug)]
-#[command(about = "Generates Cargo.toml files out of Buck build rules")]
+#[command(
+    about = "Generates Cargo.toml files out of Buck build rules",
+    args_conflicts_with_subcommands = true,
+    after_help = "Exit codes: 0 success, 1 unclassified failure, 2 config error, \
+        3 buck failure, 4 generation conflict, 5 differences found with --check, \
+        6 partial success with --keep-going, 7 problems found with --verify, \
+        8 files generated from more than one build file"
+)]
 pub struct AutocargoArgs {
+    #[command(subcommand)]
+    pub command: Option<AutocargoCommand>,
+
     /// Use a custom config dir
     #[clap(long, short)]
     config: Option<PathBuf>,
 
+    /// Read the project configs from a single bundle file instead of the
+    /// config dir, either a JSON array of configs or TOML with a table per
+    /// config. The global config is still read from the config dir
+    #[clap(long, value_name = "PATH", conflicts_with = "lazy_configs")]
+    project_config_bundle: Option<PathBuf>,
+
     /// Use a custom UTD map file
     #[clap(long)]
     utd_map: Option<PathBuf>,
 
+    /// Recompute the whole UTD map, by default when only some projects are
+    /// processed the entries of other projects are kept as they are
+    #[clap(long)]
+    pub utd_full_rebuild: bool,
+
     /// Run buck commands in an isolation dir
     #[clap(long, short, alias = "use_isolation_dir")]
     pub use_isolation_dir: bool,
 
+    /// Stream progress of the Buck commands through the log, rate-limited to
+    /// one line per second, to tell a slow Buck build from a hung run
+    #[clap(long)]
+    pub show_buck_output: bool,
+
+    /// Record the Buck commands together with their outputs, including the
+    /// built rust manifests, into a JSON fixture, e.g. to reproduce a bug
+    /// offline with --replay-buck
+    #[clap(long, value_name = "PATH", conflicts_with = "replay_buck")]
+    record_buck: Option<PathBuf>,
+
+    /// Replay the Buck commands from a fixture written by --record-buck
+    /// instead of running Buck
+    #[clap(long, value_name = "PATH")]
+    replay_buck: Option<PathBuf>,
+
+    /// Keep the thrift cratemaps built by Buck in this dir and reuse them on
+    /// later runs for the thrift rules whose sources and dependencies didn't
+    /// change, instead of building them again
+    #[clap(long, value_name = "DIR")]
+    thrift_cratemap_cache: Option<PathBuf>,
+
+    /// Maximum number of filesystem operations, like glob-searching project
+    /// files or reading Buck outputs, to run at the same time. Unlimited by
+    /// default
+    #[clap(long, value_name = "N")]
+    pub max_concurrency: Option<usize>,
+
+    /// Don't access the network, so that autocargo can run in hermetic
+    /// environments: skip prefetching files with EdenFS and never run Buck,
+    /// its commands have to be replayed with --replay-buck from a fixture
+    /// recorded by --record-buck. Third-party/rust/Cargo.toml has to be
+    /// present in the checkout. Cargo.lock generation is always offline, it
+    /// only uses the vendored third-party sources
+    #[clap(long, conflicts_with = "record_buck")]
+    pub offline: bool,
+
+    /// Skip selected projects whose roots are missing from the checkout, e.g.
+    /// a sparse one, instead of failing
+    #[clap(long)]
+    pub allow_missing_roots: bool,
+
+    /// Only fully load and validate the configs of the projects selected by
+    /// the paths and project names and of their dependencies, which is much
+    /// faster with many projects. Other configs are only checked for the
+    /// fields needed for the selection. Subcommands other than graph always
+    /// load all configs
+    #[clap(long)]
+    pub lazy_configs: bool,
+
+    /// Filename recognized as a Buck build file, can be repeated. Overrides
+    /// build_filenames of the global config, which defaults to TARGETS, BUCK,
+    /// TARGETS.v2 and BUCK.v2
+    #[clap(long = "build-filename", value_name = "FILENAME")]
+    build_filenames: Vec<String>,
+
+    /// Don't write any files, instead exit with a dedicated code if any of the
+    /// generated files differ from the ones on disk
+    #[clap(long)]
+    pub check: bool,
+
+    /// Instead of regenerating the Cargo.lock files of the selected projects
+    /// compare them with the resolved ones and exit with the code of --check
+    /// if any of them is not up to date. Nothing is written to the locks
+    #[clap(long)]
+    pub locked_check: bool,
+
+    /// Put a reproducibility stamp with the autocargo version and hashes of
+    /// the configs and of fbsource/third-party/rust/Cargo.toml in the
+    /// generated Cargo.toml files, see the verify-stamp subcommand
+    #[clap(long)]
+    pub stamp: bool,
+
+    /// With --check only compare the files generated from the TARGETS files
+    /// among the provided paths, which is much faster than checking whole
+    /// projects. Workspace manifests, crate indexes and files that are no
+    /// longer generated are not checked
+    #[clap(long, requires = "check")]
+    pub changed_only: bool,
+
+    /// Keep previously generated files that are no longer generated by any
+    /// project instead of deleting them
+    #[clap(long)]
+    pub no_delete: bool,
+
+    /// Abort instead of deleting more than this many files that are no longer
+    /// generated, per project or among files not covered by any project.
+    /// Projects can override it with max_deletions in their config
+    #[clap(long)]
+    pub max_deletions: Option<usize>,
+
+    /// Delete files that are no longer generated even if there are more of
+    /// them than allowed by --max-deletions
+    #[clap(long)]
+    pub force: bool,
+
+    /// Check that the generated Cargo.toml files are valid for Cargo, i.e.
+    /// that path dependencies, workspace members and features refer to
+    /// existing packages, features and dependencies
+    #[clap(long)]
+    pub verify: bool,
+
+    /// Fail without writing any files if the oss-ready Cargo.toml files lack
+    /// a license, reference missing license files or let published packages
+    /// depend on unpublished ones. By default these problems are only
+    /// reported
+    #[clap(long)]
+    pub strict_oss: bool,
+
+    /// Don't stop on failures of Cargo.lock or UTD map generation, instead
+    /// exit with a dedicated code once everything else is done
+    #[clap(long)]
+    pub keep_going: bool,
+
+    /// How to print the error that autocargo fails with and the diagnostics of
+    /// skipped rules, json includes the build file, rule and config key
+    /// involved when they are known, so that wrappers can point to them
+    #[clap(long, value_enum, default_value_t)]
+    pub error_format: ErrorFormat,
+
+    /// Print the time spent in each phase of the run together with counts of
+    /// the processed files, to find what to optimize
+    #[clap(long, value_enum, value_name = "FORMAT", num_args = 0..=1, default_missing_value = "text")]
+    pub stats: Option<StatsFormat>,
+
     /// Project name to regenerate, including dependencies
     #[clap(long = "project", short, value_name = "PROJECT")]
     pub projects: Vec<String>,
 
-    /// Paths to be checked
+    /// Paths to be checked, or Buck target patterns like //foo/... whose build
+    /// files are checked
     // These paths are paths in the repo, so must be valid UTF-8.
     pub paths: Vec<String>,
 }
 
+#[derive(Subcommand, Debug)]
+pub enum AutocargoCommand {
+    /// Print documentation of an autocargo rule attribute or project config
+    /// field, e.g. `autocargo doc extra_buck_dependencies.target`
+    Doc {
+        /// Dot-separated path to the field, it may omit leading components.
+        /// Lists all documented fields if not provided
+        field: Option<String>,
+    },
+    /// Print the effective configuration of a project, i.e. the config with
+    /// defaults applied, the patterns it covers, its dependency closure and
+    /// the TARGETS directories it owns
+    PrintConfig {
+        /// Name of the project or a path covered by the projects to print
+        project_or_path: String,
+
+        /// Output format, text prints the config as TOML
+        #[clap(long, value_enum, default_value_t)]
+        format: OutputFormat,
+    },
+    /// Print the dependency graph of the selected projects, as declared in
+    /// their configs, and optionally of the crates generated for them
+    Graph {
+        /// Output format of the graphs
+        #[clap(long, value_enum, default_value_t)]
+        format: GraphFormat,
+
+        /// Also print the graph of path dependencies between the generated
+        /// crates, this runs the whole generation without writing any files
+        #[clap(long)]
+        crates: bool,
+    },
+    /// List the crates generated for the selected projects with their version,
+    /// Cargo.toml path, Buck rules and whether they are published to OSS. This
+    /// runs the whole generation without writing any files
+    List {
+        /// Output format of the list
+        #[clap(long, value_enum, default_value_t)]
+        format: OutputFormat,
+    },
+    /// Explain where a dependency of a generated Cargo.toml file comes from:
+    /// the Buck rules and attributes declaring it, the extra_buck_dependencies
+    /// and dependencies_override entries touching it and its final form. This
+    /// runs the generation of the projects covering the file without writing
+    /// any files
+    Explain {
+        /// Path to the generated Cargo.toml file or its directory
+        cargo_toml: String,
+
+        /// Name of the dependency in the Cargo.toml file
+        name: String,
+
+        /// Output format of the explanation
+        #[clap(long, value_enum, default_value_t)]
+        format: OutputFormat,
+    },
+    /// Check that the generated Cargo.toml files of the selected projects have
+    /// the stamp of the current autocargo, configs and third-party crates,
+    /// i.e. were generated with --stamp since any of them last changed. Exits
+    /// with the code of --check if any of them need to be regenerated
+    VerifyStamp,
+    /// Write a skeleton config of a new project covering the given directory,
+    /// based on the Buck build files and Cargo.toml files found in it
+    InitProject {
+        /// Directory that the project should cover
+        path: String,
+
+        /// Name of the project, defaults to the name of the directory
+        #[clap(long)]
+        name: Option<String>,
+
+        /// Oncall responsible for the project
+        #[clap(long)]
+        oncall: String,
+
+        /// Use a custom config dir, the config is written into it
+        #[clap(long, short)]
+        config: Option<PathBuf>,
+    },
+}
+
+/// Format of the error printed when autocargo fails and of the diagnostics of
+/// skipped rules.
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum ErrorFormat {
+    /// The whole error chain with its backtrace
+    #[default]
+    Human,
+    /// Single line JSON object with the message, exit code and location
+    Json,
+}
+
+/// Output format of --stats.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum StatsFormat {
+    /// Table of phases followed by the counts
+    Text,
+    /// JSON object with the phases and counts
+    Json,
+}
+
+/// Output format of `autocargo graph`.
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum GraphFormat {
+    /// DOT language of Graphviz
+    #[default]
+    Dot,
+    /// JSON with lists of nodes and edges
+    Json,
+}
+
+/// Output format of `autocargo list`, `autocargo explain` and
+/// `autocargo print-config`.
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// Plain text meant for humans
+    #[default]
+    Text,
+    /// Pretty-printed JSON
+    Json,
+}
+
 impl AutocargoArgs {
-    pub async fn project_confs(&self, fbsource_root: &FbsourceRoot) -> Result<AllProjects> {
-        let conf_path = self
-            .config
+    pub async fn project_confs(
+        &self,
+        fbsource_root: &FbsourceRoot,
+        paths: &[PathInFbcode],
+    ) -> Result<AllProjects, AutocargoError> {
+        let config_dir = self.config_dir(fbsource_root);
+        if let Some(bundle) = &self.project_config_bundle {
+            return ProjectConf::from_bundle(bundle, config_dir).await;
+        }
+        match &self.command {
+            None | Some(AutocargoCommand::Graph { .. }) if self.lazy_configs => {
+                ProjectConf::from_dir_lazy(config_dir, paths, &self.projects).await
+            }
+            _ => ProjectConf::from_dir(config_dir).await,
+        }
+    }
+
+    pub fn config_dir(&self, fbsource_root: &FbsourceRoot) -> PathBuf {
+        let config = match &self.command {
+            Some(AutocargoCommand::InitProject { config, .. }) => config,
+            _ => &self.config,
+        };
+        config
             .clone()
-            .unwrap_or_else(|| Path::join(fbsource_root.as_ref(), DEFAULT_CONF));
-        ProjectConf::from_dir(conf_path).await
+            .unwrap_or_else(|| Path::join(fbsource_root.as_ref(), DEFAULT_CONF))
+    }
+
+    /// Paths of the inputs, with Buck target patterns resolved to the build
+    /// files that define the matching targets.
+    pub async fn process_input_paths(
+        &self,
+        logger: &Logger,
+        fbcode_root: &FbcodeRoot,
+        buck: BuckOptions<'_>,
+    ) -> Result<Vec<PathInFbcode>> {
+        let (patterns, paths): (Vec<_>, Vec<_>) = self
+            .paths
+            .iter()
+            .map(String::as_str)
+            .partition(|input| is_target_pattern(input));
+        let mut paths = process_input_paths(paths, fbcode_root).await?;
+        paths.extend(resolve_target_patterns(logger, fbcode_root, buck, &patterns).await?);
+        Ok(paths)
+    }
+
+    pub fn build_filenames(&self, global_config: &GlobalConf) -> Result<BuildFilenames> {
+        if self.build_filenames.is_empty() {
+            Ok(global_config.build_filenames().clone())
+        } else {
+            BuildFilenames::new(&self.build_filenames)
+        }
+    }
+
+    pub fn buck_fixtures(&self) -> Result<Option<BuckFixtures>> {
+        match (&self.record_buck, &self.replay_buck) {
+            (Some(path), _) => Ok(Some(BuckFixtures::record(path))),
+            (None, Some(path)) => BuckFixtures::replay(path).map(Some),
+            (None, None) => Ok(None),
+        }
     }
 
-    pub async fn process_input_paths(&self, fbcode_root: &FbcodeRoot) -> Result<Vec<PathInFbcode>> {
-        process_input_paths(self.paths.iter().map(String::as_str), fbcode_root).await
+    pub fn thrift_cratemap_cache(&self) -> Option<ThriftCratemapCache> {
+        self.thrift_cratemap_cache
+            .as_ref()
+            .map(ThriftCratemapCache::new)
     }
 
     pub fn utd_map(&self, fbsource_root: &FbsourceRoot) -> PathBuf {
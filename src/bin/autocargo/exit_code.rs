@@ -0,0 +1,134 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Exit codes of the autocargo process, so that wrapper scripts can branch on
+//! the class of failure without scraping the logs:
+//!
+//! | Code | Meaning                                                        |
+//! |------|----------------------------------------------------------------|
+//! | 0    | Success                                                        |
+//! | 1    | Unclassified failure, e.g. failing to write files              |
+//! | 2    | Invalid command line arguments or project configs              |
+//! | 3    | Buck commands failed or their output couldn't be processed     |
+//! | 4    | Generation failed, e.g. conflicting packages or dependencies   |
+//! | 5    | `--check` found files that differ from the generated ones,     |
+//! |      | `--locked-check` found Cargo.lock files that are out of date,  |
+//! |      | or `verify-stamp` found files that need to be regenerated      |
+//! | 6    | `--keep-going` was used and some of the post-generation steps  |
+//! |      | (Cargo.lock or UTD map generation) failed                      |
+//! | 7    | `--verify` found problems in the generated Cargo.toml files,   |
+//! |      | or `--strict-oss` found problems in the oss-ready ones         |
+//! | 8    | Files were generated from more than one build file, e.g. by    |
+//! |      | projects with overlapping coverage                             |
+
+use std::process::ExitCode;
+
+use anyhow::Error;
+use anyhow::Result;
+use autocargo::error::AutocargoError;
+
+/// Distinct process exit codes, see module docs for details.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AutocargoExitCode {
+    Success = 0,
+    Failure = 1,
+    ConfigError = 2,
+    BuckFailure = 3,
+    GenerationConflict = 4,
+    CheckDifferences = 5,
+    PartialSuccess = 6,
+    VerificationFailed = 7,
+    OutputConflict = 8,
+}
+
+impl From<AutocargoExitCode> for ExitCode {
+    fn from(code: AutocargoExitCode) -> Self {
+        ExitCode::from(code as u8)
+    }
+}
+
+/// Error together with the exit code that the process should end with.
+#[derive(Debug)]
+pub struct ClassifiedError {
+    pub code: AutocargoExitCode,
+    pub error: Error,
+}
+
+impl From<&AutocargoError> for AutocargoExitCode {
+    fn from(error: &AutocargoError) -> Self {
+        match error {
+            AutocargoError::Config(_) => AutocargoExitCode::ConfigError,
+            AutocargoError::Buck(_) => AutocargoExitCode::BuckFailure,
+            AutocargoError::Generation(_) => AutocargoExitCode::GenerationConflict,
+            AutocargoError::OutputConflicts(_) => AutocargoExitCode::OutputConflict,
+            AutocargoError::Io(_) => AutocargoExitCode::Failure,
+        }
+    }
+}
+
+impl From<AutocargoError> for ClassifiedError {
+    fn from(error: AutocargoError) -> Self {
+        Self {
+            code: AutocargoExitCode::from(&error),
+            error: Error::from(error),
+        }
+    }
+}
+
+/// Helper for attaching exit codes to errors coming from different stages of
+/// the autocargo run.
+pub trait WithExitCode<T> {
+    /// Attach the code to the error, unless it was caused by an
+    /// [AutocargoError], e.g. from a pipeline step of a subcommand, whose class
+    /// is kept.
+    fn exit_code(self, code: AutocargoExitCode) -> Result<T, ClassifiedError>;
+}
+
+impl<T> WithExitCode<T> for Result<T> {
+    fn exit_code(self, code: AutocargoExitCode) -> Result<T, ClassifiedError> {
+        self.map_err(|error| ClassifiedError {
+            code: error
+                .downcast_ref::<AutocargoError>()
+                .map_or(code, AutocargoExitCode::from),
+            error,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Context;
+    use anyhow::anyhow;
+    use autocargo::error::ErrorDetails;
+
+    use super::*;
+
+    #[test]
+    fn exit_code_test() {
+        let buck_failure = Err::<(), _>(AutocargoError::Buck(ErrorDetails::new(
+            "Failed to build manifests",
+            None,
+        )))
+        .context("While explaining foo/Cargo.toml");
+        assert_eq!(
+            buck_failure
+                .exit_code(AutocargoExitCode::Failure)
+                .unwrap_err()
+                .code,
+            AutocargoExitCode::BuckFailure
+        );
+        assert_eq!(
+            Err::<(), _>(anyhow!("No such Cargo.toml"))
+                .exit_code(AutocargoExitCode::Failure)
+                .unwrap_err()
+                .code,
+            AutocargoExitCode::Failure
+        );
+    }
+}
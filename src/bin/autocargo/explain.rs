@@ -0,0 +1,81 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use anyhow::Result;
+use anyhow::anyhow;
+use autocargo::cargo_generator::explain_dependency;
+use autocargo::paths::CargoTomlPath;
+use autocargo::paths::FbcodeRoot;
+use autocargo::paths::process_input_paths;
+use autocargo::run::GenerationSession;
+
+use crate::args::OutputFormat;
+
+/// Print where the dependency of the given name in the generated Cargo.toml
+/// file comes from, which requires running the generation of the projects
+/// covering the file.
+pub(crate) async fn explain(
+    session: &GenerationSession<'_>,
+    cargo_toml: &str,
+    name: &str,
+    format: OutputFormat,
+) -> Result<()> {
+    let fbcode_root = FbcodeRoot::from(session.fbsource_root.clone());
+    let path = process_input_paths([cargo_toml], &fbcode_root)
+        .await?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("No path provided"))?;
+    let cargo_toml_path = if path.as_ref().ends_with(CargoTomlPath::filename()) {
+        CargoTomlPath::new(path)?
+    } else {
+        CargoTomlPath::new(path.join_to_path_in_fbcode(CargoTomlPath::filename()))?
+    };
+
+    let selected = session
+        .all_configs
+        .select_based_on_paths_and_names(&[cargo_toml_path.as_dir().clone()], &[])?;
+    let loaded = session.load_projects(selected).await?;
+    let processed = session.process_targets(&loaded).await?;
+    let generator = session.cargo_generator(&loaded, &processed).await?;
+    let generated = session.generate(&generator, &loaded, &processed)?;
+    let explanation = explain_dependency(
+        &generator,
+        &processed.processed_manifests,
+        &generated,
+        &cargo_toml_path,
+        name,
+    )?;
+
+    match format {
+        OutputFormat::Text => {
+            println!(
+                "Dependency {} of {}",
+                explanation.name, explanation.cargo_toml_path
+            );
+            println!("Declared by:");
+            for declaration in &explanation.declarations {
+                println!(
+                    "  {} {}: {}",
+                    declaration.rule, declaration.attribute, declaration.dependency
+                );
+            }
+            println!("Overridden by:");
+            for entry in &explanation.overrides {
+                println!("  {entry}");
+            }
+            println!("Resolved as:");
+            for (section, dependency) in &explanation.resolved {
+                println!("  [{section}] {} = {dependency}", explanation.name);
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&explanation)?),
+    }
+    Ok(())
+}
@@ -38,12 +38,20 @@ use tokio::task::spawn_blocking;
 use toml::Table;
 
 /// Generate a Cargo.lock for each directory specified in the ProjectConf's
-/// cargo_locks field.
+/// cargo_locks field. With `check` set nothing is written, instead the locks
+/// that would be changed are logged and the returned value tells if there
+/// were any.
 pub(crate) async fn generate_cargo_locks(
     logger: &Logger,
     fbsource: &FbsourceRoot,
     selected_projects: &SelectedProjects<'_>,
-) -> Result<()> {
+    check: bool,
+) -> Result<bool> {
+    let step = if check {
+        "check_cargo_lock"
+    } else {
+        "generate_cargo_lock"
+    };
     let homedir = cargo::util::context::homedir(fbsource.as_ref()).context(
         "Couldn't find your home directory. This probably means that $HOME was not set.",
     )?;
@@ -56,25 +64,27 @@ pub(crate) async fn generate_cargo_locks(
         .collect::<FuturesOrdered<_>>()
         // Run serially because cargo holds a lock on the package cache,
         // and warns on concurrent access.
-        .try_for_each(|path| {
+        .try_fold(false, |any_stale, path| {
             info!(
                 logger,
-                "Running 'generate_cargo_lock' for '{}'",
-                path.as_ref().display(),
+                "Running '{}' for '{}'",
+                step,
+                path.as_ref().display()
             );
             let homedir = homedir.clone();
             async move {
-                future_soft_timeout(
+                let is_stale = future_soft_timeout(
                     spawn_blocking({
                         let path = path.clone();
                         let fbsource = fbsource.clone();
-                        move || generate_cargo_lock(&fbsource, &homedir, &path)
+                        move || generate_cargo_lock(&fbsource, &homedir, &path, check)
                     }),
                     Duration::from_secs(10),
                     |duration| {
                         warn!(
                             logger,
-                            "'generate_cargo_lock' for '{}' running for more than {:.1?}",
+                            "'{}' for '{}' running for more than {:.1?}",
+                            step,
                             path.as_ref().display(),
                             duration
                         )
@@ -82,7 +92,8 @@ pub(crate) async fn generate_cargo_locks(
                     |duration| {
                         warn!(
                             logger,
-                            "'generate_cargo_lock' for '{}' finished after {:.1?}",
+                            "'{}' for '{}' finished after {:.1?}",
+                            step,
                             path.as_ref().display(),
                             duration
                         )
@@ -91,11 +102,16 @@ pub(crate) async fn generate_cargo_locks(
                 .await
                 .context("Failed spawn_blocking")?
                 .with_context(|| {
-                    format!(
-                        "While running 'generate_cargo_lock' for '{}'",
-                        path.as_ref().display(),
-                    )
-                })
+                    format!("While running '{}' for '{}'", step, path.as_ref().display())
+                })?;
+                if is_stale {
+                    warn!(
+                        logger,
+                        "Cargo.lock is not up to date: {}",
+                        path.join_to_path_in_fbcode("Cargo.lock"),
+                    );
+                }
+                Ok(any_stale || is_stale)
             }
         })
         .await
@@ -103,6 +119,8 @@ pub(crate) async fn generate_cargo_locks(
 
 /// Do `cargo generate-lockfile` on the given path. This uses the internal cargo
 /// crate to do the work rather than calling out to an external cargo binary.
+/// With `check` set the existing Cargo.lock is compared with the resolved one
+/// instead of being overwritten, returns true if they differ.
 ///
 /// We don't require .cargo/config.toml to be set up in the target directory -
 /// instead we force a virtual config to point directly at
@@ -110,10 +128,16 @@ pub(crate) async fn generate_cargo_locks(
 /// a project requires some custom values (such as needing to override some
 /// other fbcode project) since cargo doesn't provide a way to "merge" configs
 /// or set individual values.
-fn generate_cargo_lock(fbsource: &FbsourceRoot, homedir: &Path, path: &PathInFbcode) -> Result<()> {
+fn generate_cargo_lock(
+    fbsource: &FbsourceRoot,
+    homedir: &Path,
+    path: &PathInFbcode,
+    check: bool,
+) -> Result<bool> {
     let fbsource: &Path = fbsource.as_ref();
     let target_dir = fbsource.join(FbcodeRoot::dirname()).join(path.as_ref());
     let path = target_dir.join("Cargo.toml");
+    let lock_path = target_dir.join("Cargo.lock");
     let shell = Shell::new();
     let mut cfg = Config::new(shell, target_dir.clone(), homedir.to_path_buf());
     let rustc = fbsource.join("xplat/rust/toolchain/current/basic/bin/rustc");
@@ -167,9 +191,14 @@ fn generate_cargo_lock(fbsource: &FbsourceRoot, homedir: &Path, path: &PathInFbc
         &[],
         true,
     )?;
+    if check {
+        let resolved = cargo::ops::resolve_to_string(&ws, &new_resolve)?;
+        let existing = fs::read_to_string(lock_path).ok();
+        return Ok(existing.as_deref() != Some(resolved.as_str()));
+    }
     cargo::ops::write_pkg_lockfile(&ws, &mut new_resolve)?;
 
-    Ok(())
+    Ok(false)
 }
 
 fn deserialize_config_toml(fbsource: &Path) -> Result<Table> {
@@ -7,33 +7,84 @@
  * of this source tree.
  */
 
+use std::collections::HashMap;
 use std::collections::HashSet;
 use std::io::Write as _;
 use std::path::Path;
 
 use anyhow::Result;
+use anyhow::ensure;
 use autocargo::config::AllProjects;
 use autocargo::config::ProjectConf;
+use autocargo::config::SelectedProjects;
 use autocargo::paths::FbcodeRoot;
 use glob::Pattern;
+use itertools::Itertools;
 use serde::Serialize;
 use serde::Serializer;
 use serde::ser::Error;
 use serde::ser::SerializeMap;
 use serde::ser::SerializeSeq;
+use serde_json::Value;
 use slog::Logger;
 use slog::info;
 
 /// Generate the "UTD map" - a mapping of all project include and exclude
 /// globs suitable for [`autocargo_verification.td`].
 ///
+/// If `updated_projects` is provided then only entries of those projects are
+/// recomputed, entries of other projects are copied from the existing map.
+///
 /// [`autocargo_verification.td`]:
 ///     https://www.internalfb.com/code/fbsource/tools/utd/migrated_nbtd_jobs/autocargo_verification.td
 pub(crate) async fn generate_utd_map(
     logger: &Logger,
     all_configs: &AllProjects,
+    updated_projects: Option<&SelectedProjects<'_>>,
     utd_map_path: &Path,
 ) -> Result<()> {
+    let existing = tokio::fs::read(utd_map_path).await.ok();
+
+    let preserved = match (updated_projects, &existing) {
+        (None, _) => HashMap::new(),
+        (Some(_), None) => {
+            info!(
+                logger,
+                "No existing UTD map at '{}', generating it from scratch",
+                utd_map_path.display()
+            );
+            HashMap::new()
+        }
+        (Some(updated_projects), Some(data)) => match existing_entries(data) {
+            Some(mut entries) => {
+                for project in updated_projects.projects() {
+                    entries.remove(project.name());
+                }
+                entries
+            }
+            None => {
+                info!(
+                    logger,
+                    "Failed to parse existing UTD map at '{}', generating it from scratch",
+                    utd_map_path.display()
+                );
+                HashMap::new()
+            }
+        },
+    };
+
+    let missing: Vec<_> = all_configs
+        .unloaded_projects()
+        .iter()
+        .filter(|name| !preserved.contains_key(*name))
+        .collect();
+    ensure!(
+        missing.is_empty(),
+        "UTD map needs configs of projects {} that were not loaded, run without \
+        --lazy-configs",
+        missing.iter().join(", "),
+    );
+
     let w = Vec::new();
     let mut serializer = serde_json::Serializer::pretty(w);
 
@@ -43,6 +94,7 @@ pub(crate) async fn generate_utd_map(
     seq.serialize_element(&UtdMap {
         prefix: FbcodeRoot::dirname(),
         all_configs,
+        preserved: &preserved,
     })?;
     SerializeSeq::end(seq)?;
 
@@ -50,10 +102,7 @@ pub(crate) async fn generate_utd_map(
     w.write_all(b"\n")?;
     w.flush()?;
 
-    if !tokio::fs::read(utd_map_path)
-        .await
-        .is_ok_and(|data| data == w)
-    {
+    if existing.is_none_or(|data| data != w) {
         info!(logger, "Updating UTD map at '{}'", utd_map_path.display());
         tokio::fs::write(utd_map_path, w).await?;
     }
@@ -61,9 +110,21 @@ pub(crate) async fn generate_utd_map(
     Ok(())
 }
 
+/// Read project entries from a previously generated UTD map, keyed by project
+/// name. Returns None if the map doesn't have the expected structure.
+fn existing_entries(data: &[u8]) -> Option<HashMap<String, Value>> {
+    let map: Vec<Value> = serde_json::from_slice(data).ok()?;
+    let entries = map.first()?.get("project_configs")?.as_array()?;
+    entries
+        .iter()
+        .map(|entry| Some((entry.get("name")?.as_str()?.to_owned(), entry.clone())))
+        .collect()
+}
+
 struct UtdMap<'a> {
     prefix: &'a str,
     all_configs: &'a AllProjects,
+    preserved: &'a HashMap<String, Value>,
 }
 
 impl Serialize for UtdMap<'_> {
@@ -84,6 +145,7 @@ impl Serialize for UtdMap<'_> {
             &ProjectConfigs {
                 prefix: self.prefix,
                 all_configs: self.all_configs,
+                preserved: self.preserved,
             },
         )?;
 
@@ -94,18 +156,34 @@ impl Serialize for UtdMap<'_> {
 struct ProjectConfigs<'a> {
     prefix: &'a str,
     all_configs: &'a AllProjects,
+    /// Entries copied as they are instead of being computed from configs.
+    preserved: &'a HashMap<String, Value>,
 }
 
 impl Serialize for ProjectConfigs<'_> {
     fn serialize<S: Serializer>(&self, ser: S) -> Result<S::Ok, S::Error> {
         let projects = self.all_configs.select_all();
         let projects = projects.projects();
-        let mut seq = ser.serialize_seq(Some(projects.len()))?;
-        for project in projects {
-            seq.serialize_element(&ProjectEntry {
-                prefix: self.prefix,
-                project,
-            })?;
+        let unloaded = self.all_configs.unloaded_projects();
+        let mut seq = ser.serialize_seq(Some(projects.len() + unloaded.len()))?;
+        // Entries of projects whose configs were not loaded are preserved.
+        let names = projects
+            .iter()
+            .map(|project| (project.name(), Some(*project)))
+            .merge_by(unloaded.iter().map(|name| (name, None)), |a, b| a.0 <= b.0);
+        for (name, project) in names {
+            match (self.preserved.get(name), project) {
+                (Some(entry), _) => seq.serialize_element(entry)?,
+                (None, Some(project)) => seq.serialize_element(&ProjectEntry {
+                    prefix: self.prefix,
+                    project,
+                })?,
+                (None, None) => {
+                    return Err(S::Error::custom(format!(
+                        "Config of project {name} was not loaded"
+                    )));
+                }
+            }
         }
         seq.end()
     }
@@ -0,0 +1,50 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use anyhow::Result;
+use autocargo::graph::DependencyGraph;
+use autocargo::run::GenerationSession;
+use serde_json::json;
+
+use crate::args::GraphFormat;
+
+/// Print the dependency graph of the selected projects and, if requested, the
+/// graph of the crates generated for them, which requires running the whole
+/// generation.
+pub(crate) async fn print_graph(
+    session: &GenerationSession<'_>,
+    format: GraphFormat,
+    crates: bool,
+) -> Result<()> {
+    let projects = DependencyGraph::projects(&session.select_projects()?);
+    let crates = if crates {
+        Some(DependencyGraph::crates(&session.run().await?.generated))
+    } else {
+        None
+    };
+
+    match format {
+        GraphFormat::Dot => {
+            print!("{}", projects.to_dot("projects"));
+            if let Some(crates) = crates {
+                print!("{}", crates.to_dot("crates"));
+            }
+        }
+        GraphFormat::Json => {
+            println!(
+                "{}",
+                serde_json::to_string_pretty(&json!({
+                    "projects": projects,
+                    "crates": crates,
+                }))?
+            );
+        }
+    }
+    Ok(())
+}
@@ -14,10 +14,16 @@ use std::hash::Hash;
 use std::path::Path;
 
 use anyhow::Context;
+use anyhow::Error;
 use anyhow::Result;
-use autocargo::cargo_generator::GENERATED_PREAMBLE;
+use anyhow::bail;
 use autocargo::cargo_generator::GenerationOutput;
+use autocargo::cargo_generator::is_generated_content;
+use autocargo::cargo_generator::preserve_manual_sections;
+use autocargo::cargo_generator::render_file_manifests;
 use autocargo::config::ProjectConf;
+use autocargo::events::AutocargoEvent;
+use autocargo::events::EventSender;
 use autocargo::paths::CargoTomlPath;
 use autocargo::paths::FbcodeRoot;
 use autocargo::paths::PathInFbcode;
@@ -27,6 +33,8 @@ use futures::FutureExt;
 use futures::TryStreamExt;
 use futures::future::BoxFuture;
 use futures::stream::FuturesUnordered;
+use itertools::Itertools;
+use regex::Regex;
 use slog::Logger;
 use slog::info;
 use slog::warn;
@@ -36,15 +44,88 @@ use tokio::fs::read_to_string;
 use tokio::fs::remove_file;
 use tokio::fs::write;
 
+/// How the generated files should be handled.
+pub struct HandleOptions<'a> {
+    /// Don't write anything, instead log the files that would be changed.
+    pub check: bool,
+    /// Keep files that are no longer generated.
+    pub no_delete: bool,
+    /// Only some of the crates were generated, so don't look for files that
+    /// are no longer generated.
+    pub changed_only: bool,
+    /// Default limit of files that may be deleted per project, projects can
+    /// override it in their config.
+    pub max_deletions: Option<usize>,
+    /// Ignore the limit of deleted files.
+    pub force: bool,
+    /// First lines of files generated by older versions of autocargo, which
+    /// are deleted like the current ones once they are no longer generated.
+    pub legacy_preambles: &'a [Regex],
+}
+
+/// Persist the generated files on disk and remove the ones that are no longer
+/// generated, unless `no_delete` is set. With `check` set nothing is written,
+/// instead the files that would be changed are logged and the returned value
+/// tells if there were any.
 pub async fn handle_generation_results<'a>(
     logger: &Logger,
+    events: &EventSender,
     fbcode_root: &'a FbcodeRoot,
     generated: &'a GenerationOutput,
     project_files: &'a [ProjectFiles<'a>],
     projectless_files: &'a ProjectlessFiles,
-) -> Result<()> {
-    let files_to_delete: HashSet<_> =
-        get_files_to_delete(fbcode_root, generated, project_files, projectless_files).await?;
+    HandleOptions {
+        check,
+        no_delete,
+        changed_only,
+        max_deletions,
+        force,
+        legacy_preambles,
+    }: HandleOptions<'a>,
+) -> Result<bool> {
+    let mut files_to_delete: HashSet<_> = if changed_only {
+        HashSet::new()
+    } else {
+        get_files_to_delete(
+            fbcode_root,
+            generated,
+            project_files,
+            projectless_files,
+            legacy_preambles,
+        )
+        .await?
+    };
+
+    if no_delete {
+        let mut kept_files: Vec<_> = files_to_delete.drain().collect();
+        kept_files.sort_unstable();
+        for path in kept_files {
+            warn!(
+                logger,
+                "Keeping file that is no longer generated by any project: {}", path
+            );
+        }
+    }
+
+    let files_to_save = read_files_to_save(
+        fbcode_root.as_ref(),
+        files_to_save(generated),
+        project_files.iter().map(|project| *project.conf()),
+    )
+    .await?;
+
+    if check {
+        return Ok(check_generation(logger, files_to_save, files_to_delete));
+    }
+
+    if !force {
+        check_deletion_threshold(
+            &files_to_delete,
+            project_files,
+            projectless_files,
+            max_deletions,
+        )?;
+    }
 
     if !files_to_delete.is_empty() {
         warn!(
@@ -57,7 +138,84 @@ pub async fn handle_generation_results<'a>(
     let manifests_count = generated.cargo_manifests.len();
     let additional_files = generated.additional_files.len();
 
-    let files_to_save = generated
+    persist_generation(
+        logger,
+        events,
+        fbcode_root,
+        manifests_count,
+        additional_files,
+        files_to_save,
+        files_to_delete,
+    )
+    .await?;
+
+    Ok(false)
+}
+
+/// Fail if the files of any project, or the files not covered by any project,
+/// would have more deletions than allowed by max_deletions of the project or
+/// the default. This protects against config mistakes, like a mis-set
+/// public_cargo_dir, that would wipe out many files at once.
+fn check_deletion_threshold(
+    files_to_delete: &HashSet<&PathInFbcode>,
+    project_files: &[ProjectFiles<'_>],
+    projectless_files: &ProjectlessFiles,
+    default_max: Option<usize>,
+) -> Result<()> {
+    let groups = project_files
+        .iter()
+        .map(|project| {
+            (
+                format!("project {}", project.conf().name()),
+                project.conf().max_deletions().or(default_max),
+                project.cargo(),
+                project.additional(),
+            )
+        })
+        .chain(Some((
+            "projectless files".to_owned(),
+            default_max,
+            projectless_files.cargo(),
+            projectless_files.additional(),
+        )));
+
+    let mut errors = Vec::new();
+    for (name, max, cargo, additional) in groups {
+        let Some(max) = max else {
+            continue;
+        };
+        let mut deleted: Vec<_> = cargo
+            .iter()
+            .map(CargoTomlPath::as_file)
+            .chain(additional)
+            .filter(|path| files_to_delete.contains(path))
+            .collect();
+        if deleted.len() > max {
+            deleted.sort_unstable();
+            errors.push(format!(
+                "{} files of {} would be deleted, which is more than the limit of {}:\n{}",
+                deleted.len(),
+                name,
+                max,
+                deleted.iter().map(|path| format!("  {path}")).join("\n"),
+            ));
+        }
+    }
+
+    if !errors.is_empty() {
+        bail!(
+            "Refusing to delete files that are no longer generated, pass --force to delete \
+            them anyway\n{}",
+            errors.join("\n"),
+        );
+    }
+    Ok(())
+}
+
+fn files_to_save(
+    generated: &GenerationOutput,
+) -> impl Iterator<Item = (&PathInFbcode, String)> + '_ {
+    generated
         .cargo_manifests
         .iter()
         .map(|(path, content)| (path.as_file(), content.to_toml_string()))
@@ -66,25 +224,119 @@ pub async fn handle_generation_results<'a>(
                 .additional_files
                 .iter()
                 .map(|(path, content)| (path, content.clone())),
-        );
+        )
+}
 
-    persist_generation(
-        logger,
-        fbcode_root,
-        manifests_count,
-        additional_files,
-        files_to_save,
-        files_to_delete,
-    )
-    .await
+/// Read the file that the generated content will be saved to. If it is a TOML
+/// file then its manual sections are copied into the returned content.
+async fn read_existing(path: &Path, content: String) -> Result<(Option<Vec<u8>>, String)> {
+    let existing = read(path).await.ok();
+    let content = match &existing {
+        Some(existing) if path.extension().is_some_and(|ext| ext == "toml") => {
+            preserve_manual_sections(&String::from_utf8_lossy(existing), &content).with_context(
+                || format!("While preserving manual sections of {}", path.display()),
+            )?
+        }
+        _ => content,
+    };
+    Ok((existing, content))
+}
+
+/// A generated file with the content that will be written to disk together
+/// with the content of the file on disk, if any.
+struct FileToSave<'a> {
+    path: &'a PathInFbcode,
+    existing: Option<Vec<u8>>,
+    content: String,
+}
+
+impl FileToSave<'_> {
+    fn is_up_to_date(&self) -> bool {
+        self.existing
+            .as_ref()
+            .is_some_and(|x| x == self.content.as_bytes())
+    }
+}
+
+/// Read the files that the generated content will be saved to, preserving
+/// their manual sections in the content. The file manifests of the projects
+/// are then rendered again, so that they have the hashes of the content that
+/// will actually be written.
+async fn read_files_to_save<'a>(
+    fbcode_root: &Path,
+    files_to_save: impl IntoIterator<Item = (&'a PathInFbcode, String)>,
+    project_confs: impl IntoIterator<Item = &'a ProjectConf>,
+) -> Result<Vec<FileToSave<'a>>> {
+    let mut files: Vec<_> = files_to_save
+        .into_iter()
+        .map(|(path, content)| async move {
+            let (existing, content) =
+                read_existing(&Path::join(fbcode_root, path.as_ref()), content).await?;
+            Ok::<_, Error>(FileToSave {
+                path,
+                existing,
+                content,
+            })
+        })
+        .collect::<FuturesUnordered<_>>()
+        .try_collect()
+        .await?;
+
+    let contents: Vec<_> = files
+        .iter()
+        .map(|file| (file.path, file.content.as_str()))
+        .collect();
+    let file_manifests: HashMap<_, _> = render_file_manifests(project_confs, &contents)?
+        .into_iter()
+        .map(|(_, path, content)| (path, content))
+        .collect();
+    for file in &mut files {
+        if let Some(content) = file_manifests.get(file.path) {
+            file.content = content.clone();
+        }
+    }
+    Ok(files)
+}
+
+/// Compare the generated files with the ones on disk without modifying them,
+/// returns true if any of them differ or should be deleted.
+fn check_generation<'a>(
+    logger: &Logger,
+    files_to_save: Vec<FileToSave<'a>>,
+    files_to_delete: impl IntoIterator<Item = &'a PathInFbcode>,
+) -> bool {
+    let mut changed_files: Vec<_> = files_to_save
+        .iter()
+        .filter(|file| !file.is_up_to_date())
+        .map(|file| file.path)
+        .collect();
+    changed_files.sort_unstable();
+
+    let mut deleted_files: Vec<_> = files_to_delete.into_iter().collect();
+    deleted_files.sort_unstable();
+
+    for path in &changed_files {
+        warn!(logger, "File is not up to date: {}", path);
+    }
+    for path in &deleted_files {
+        warn!(logger, "File is no longer generated: {}", path);
+    }
+
+    if changed_files.is_empty() && deleted_files.is_empty() {
+        info!(logger, "All generated files are up to date");
+        false
+    } else {
+        true
+    }
 }
 
 async fn persist_generation<'a>(
     logger: &Logger,
+    events: &EventSender,
     fbcode_root: &'a FbcodeRoot,
     manifests_count: usize,
     additional_files: usize,
-    files_to_save: impl IntoIterator<Item = (&'a PathInFbcode, String)>,
+    files_to_save: Vec<FileToSave<'a>>,
     files_to_delete: impl IntoIterator<Item = &'a PathInFbcode>,
 ) -> Result<()> {
     files_to_delete
@@ -99,17 +351,19 @@ async fn persist_generation<'a>(
 
     files_to_save
         .into_iter()
-        .map(|(path, content)| {
-            let path = Path::join(fbcode_root.as_ref(), path.as_ref());
+        .map(|file| {
+            let path = Path::join(fbcode_root.as_ref(), file.path.as_ref());
             async move {
                 // Avoid triggering file watchers for files without changes.
-                if read(&path).await.is_ok_and(|x| x == content.as_bytes()) {
+                if file.is_up_to_date() {
                     Ok(())
                 } else {
                     if let Some(dir) = path.parent() {
                         create_dir_all(dir).await?;
                     }
-                    write(path, content.as_bytes()).await
+                    write(path, file.content.as_bytes()).await?;
+                    events.emit(AutocargoEvent::FileWritten(file.path.clone()));
+                    Ok::<_, Error>(())
                 }
             }
         })
@@ -147,43 +401,49 @@ async fn get_files_to_delete<'a>(
     generated: &'a GenerationOutput,
     project_files: &'a [ProjectFiles<'a>],
     projectless_files: &'a ProjectlessFiles,
+    legacy_preambles: &'a [Regex],
 ) -> Result<HashSet<&'a PathInFbcode>> {
     let GenerationOutput {
         cargo_manifests,
         additional_files,
+        crates: _,
     } = generated;
 
     project_files
         .iter()
         .flat_map(|project_files| {
             get_files_to_delete_helper(
-                fbcode_root,
+                fbcode_root.as_ref(),
                 cargo_manifests,
                 Some(*project_files.conf()),
                 project_files.cargo(),
                 CargoTomlPath::as_file,
+                legacy_preambles,
             )
             .chain(get_files_to_delete_helper(
-                fbcode_root,
+                fbcode_root.as_ref(),
                 additional_files,
                 Some(*project_files.conf()),
                 project_files.additional(),
                 identity,
+                legacy_preambles,
             ))
         })
         .chain(get_files_to_delete_helper(
-            fbcode_root,
+            fbcode_root.as_ref(),
             cargo_manifests,
             None,
             projectless_files.cargo(),
             CargoTomlPath::as_file,
+            legacy_preambles,
         ))
         .chain(get_files_to_delete_helper(
-            fbcode_root,
+            fbcode_root.as_ref(),
             additional_files,
             None,
             projectless_files.additional(),
             identity,
+            legacy_preambles,
         ))
         .collect::<FuturesUnordered<_>>()
         .try_filter_map(|p| async move { Ok(p) })
@@ -192,11 +452,12 @@ async fn get_files_to_delete<'a>(
 }
 
 fn get_files_to_delete_helper<'a, P, T>(
-    fbcode_root: &'a FbcodeRoot,
+    fbcode_root: &'a Path,
     generated: &'a HashMap<P, T>,
     project_conf: Option<&'a ProjectConf>,
     paths: impl IntoIterator<Item = &'a P> + 'a,
     to_path_in_fbcode: impl Fn(&'a P) -> &'a PathInFbcode + 'a,
+    legacy_preambles: &'a [Regex],
 ) -> impl Iterator<Item = BoxFuture<'a, Result<Option<&'a PathInFbcode>>>> + 'a
 where
     P: Eq + Hash + Sync,
@@ -210,34 +471,22 @@ where
                 // should be deleted.
                 None
             } else {
-                let is_inside_public_dir = project_conf
-                    .and_then(|conf| conf.oss_git_config().as_ref())
-                    .and_then(|oss| oss.public_cargo_dir.as_ref())
-                    .is_some_and(|public_cargo_dir| {
-                        path_in_fbcode
-                            .as_ref()
-                            .starts_with(public_cargo_dir.as_ref())
-                    });
-                if is_inside_public_dir {
-                    // We relentlessly remove all files from public dir if they
-                    // were not regenerated.
+                if is_always_deleted(project_conf, path_in_fbcode) {
                     Some(path_in_fbcode)
                 } else {
-                    let is_content_autocargo_generated =
-                        read_to_string(Path::join(fbcode_root.as_ref(), path_in_fbcode.as_ref()))
-                            .await
-                            .with_context(|| {
-                                format!(
-                                    "While processing files to delete for {}",
-                                    project_conf.map_or_else(
-                                        || "projectless files".to_owned(),
-                                        |conf| format!("project {}", conf.name())
-                                    )
+                    let content = read_to_string(Path::join(fbcode_root, path_in_fbcode.as_ref()))
+                        .await
+                        .with_context(|| {
+                            format!(
+                                "While processing files to delete for {}",
+                                project_conf.map_or_else(
+                                    || "projectless files".to_owned(),
+                                    |conf| format!("project {}", conf.name())
                                 )
-                            })?
-                            .lines()
-                            .next()
-                            .is_some_and(|line| line.contains(GENERATED_PREAMBLE));
+                            )
+                        })?;
+                    let is_content_autocargo_generated =
+                        is_generated_content(&content, legacy_preambles);
                     if is_content_autocargo_generated {
                         Some(path_in_fbcode)
                     } else {
@@ -249,3 +498,237 @@ where
         .boxed()
     })
 }
+
+/// True if the file, which was not regenerated, is deleted without checking
+/// that its content was generated by autocargo. We relentlessly remove all
+/// files from public dir, except for the ones with names that are commonly
+/// written by hand too, see [PathInFbcode::is_hand_writable].
+fn is_always_deleted(project_conf: Option<&ProjectConf>, path: &PathInFbcode) -> bool {
+    let is_inside_public_dir = project_conf
+        .and_then(|conf| conf.oss_git_config().as_ref())
+        .and_then(|oss| oss.public_cargo_dir.as_ref())
+        .is_some_and(|public_cargo_dir| path.as_ref().starts_with(public_cargo_dir.as_ref()));
+    is_inside_public_dir && !path.is_hand_writable()
+}
+
+#[cfg(test)]
+mod test {
+    use autocargo::cargo_generator::GENERATED_PREAMBLE;
+    use autocargo::cargo_generator::MANUAL_SECTION_BEGIN;
+    use autocargo::cargo_generator::MANUAL_SECTION_END;
+    use futures::future::try_join_all;
+    use serde_json::from_value;
+    use serde_json::json;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn get_files_to_delete_helper_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let conf: ProjectConf = from_value(json!({
+            "name": "proj",
+            "oncall": "oncall_name",
+            "roots": ["proj"],
+            "oss_git_config": {
+                "git": "https://github.com/facebook/proj.git",
+                "public_cargo_dir": "proj/public_autocargo",
+            },
+        }))
+        .unwrap();
+        let generated_content = format!("# {GENERATED_PREAMBLE}\n\nmax_width = 100\n");
+        let generated_readme = format!("<!-- {GENERATED_PREAMBLE} -->\n\n# foo\n");
+        let files = [
+            (
+                "proj/public_autocargo/foo/thrift_build.rs",
+                "fn main() {}\n",
+            ),
+            ("proj/public_autocargo/rustfmt.toml", "max_width = 100\n"),
+            (
+                "proj/public_autocargo/clippy.toml",
+                generated_content.as_str(),
+            ),
+            ("proj/foo/clippy.toml", "msrv = \"1.80\"\n"),
+            (
+                "proj/public_autocargo/.cargo/config.toml",
+                "[net]\noffline = true\n",
+            ),
+            (
+                "proj/public_autocargo/.config/nextest.toml",
+                "[profile.ci]\n",
+            ),
+            ("proj/public_autocargo/README.md", "# Proj\n"),
+            (
+                "proj/public_autocargo/foo/README.md",
+                generated_readme.as_str(),
+            ),
+            ("proj/public_autocargo/foo/LICENSE", "MIT License\n"),
+        ];
+        let paths: Vec<PathInFbcode> = files
+            .iter()
+            .map(|(path, content)| {
+                let absolute = dir.path().join(path);
+                std::fs::create_dir_all(absolute.parent().unwrap()).unwrap();
+                std::fs::write(absolute, content).unwrap();
+                from_value(json!(path)).unwrap()
+            })
+            .collect();
+
+        let generated: HashMap<PathInFbcode, String> = HashMap::new();
+        let to_delete: Vec<_> = try_join_all(get_files_to_delete_helper(
+            dir.path(),
+            &generated,
+            Some(&conf),
+            &paths,
+            identity,
+            &[],
+        ))
+        .await
+        .unwrap()
+        .into_iter()
+        .flatten()
+        .collect();
+        // The hand-written rustfmt.toml, .cargo/config.toml,
+        // .config/nextest.toml and README.md in the public dir survive.
+        assert_eq!(to_delete, vec![&paths[0], &paths[2], &paths[7], &paths[8]]);
+    }
+
+    #[tokio::test]
+    async fn read_files_to_save_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let conf: ProjectConf = from_value(json!({
+            "name": "proj",
+            "oncall": "oncall_name",
+            "roots": ["proj"],
+            "file_manifest_dir": "proj",
+        }))
+        .unwrap();
+        let cargo_toml: PathInFbcode = from_value(json!("proj/Cargo.toml")).unwrap();
+        let manifest_path = conf.file_manifest_path().unwrap();
+
+        let generated = format!("# {GENERATED_PREAMBLE}\n[package]\nname = \"proj\"\n");
+        let written = format!(
+            "{generated}{MANUAL_SECTION_BEGIN}\nversion = \"0.1.0\"\n{MANUAL_SECTION_END}\n"
+        );
+        std::fs::create_dir_all(dir.path().join("proj")).unwrap();
+        std::fs::write(dir.path().join("proj/Cargo.toml"), &written).unwrap();
+        let render = |content: &str| {
+            render_file_manifests([&conf], &[(&cargo_toml, content)])
+                .unwrap()
+                .remove(0)
+                .2
+        };
+
+        let files = read_files_to_save(
+            dir.path(),
+            [
+                (&cargo_toml, generated.clone()),
+                (&manifest_path, render(&generated)),
+            ],
+            [&conf],
+        )
+        .await
+        .unwrap();
+        let content = |path| {
+            files
+                .iter()
+                .find(|file| file.path == path)
+                .map(|file| file.content.as_str())
+                .unwrap()
+        };
+        // The manual section is preserved and the file manifest has the hash
+        // of the content with it.
+        assert_eq!(content(&cargo_toml), written);
+        assert_eq!(content(&manifest_path), render(&written));
+        assert_ne!(render(&written), render(&generated));
+        assert!(
+            files
+                .iter()
+                .find(|file| file.path == &cargo_toml)
+                .unwrap()
+                .is_up_to_date()
+        );
+    }
+
+    #[test]
+    fn check_deletion_threshold_test() {
+        let conf = |name: &str, max_deletions: Option<usize>| -> ProjectConf {
+            from_value(json!({
+                "name": name,
+                "oncall": "oncall_name",
+                "roots": [name],
+                "max_deletions": max_deletions,
+            }))
+            .unwrap()
+        };
+        let path = |path: &str| -> PathInFbcode { from_value(json!(path)).unwrap() };
+        let cargo = |dir: &str| CargoTomlPath::new(path(&format!("{dir}/Cargo.toml"))).unwrap();
+        let (conf_a, conf_b) = (conf("a", Some(1)), conf("b", None));
+        let project_files = [
+            ProjectFiles::new(
+                &conf_a,
+                vec![cargo("a/x"), cargo("a/y")],
+                Vec::new(),
+                vec![path("a/x/thrift_build.rs")],
+            ),
+            ProjectFiles::new(
+                &conf_b,
+                vec![cargo("b/x"), cargo("b/y")],
+                Vec::new(),
+                Vec::new(),
+            ),
+        ];
+        let projectless_files =
+            ProjectlessFiles::new(vec![cargo("c")], Vec::new(), vec![path("c/README.md")]);
+        let check = |deleted: &[&str], default_max: Option<usize>| {
+            let deleted: Vec<_> = deleted.iter().map(|p| path(p)).collect();
+            check_deletion_threshold(
+                &deleted.iter().collect(),
+                &project_files,
+                &projectless_files,
+                default_max,
+            )
+            .map_err(|err| err.to_string())
+        };
+
+        // Within the limit of project a, project b has no limit by default.
+        assert!(
+            check(
+                &["a/x/Cargo.toml", "b/x/Cargo.toml", "b/y/Cargo.toml"],
+                None
+            )
+            .is_ok()
+        );
+        // max_deletions of the project counts its additional files and takes
+        // precedence over the default.
+        let err = check(&["a/x/Cargo.toml", "a/x/thrift_build.rs"], Some(5)).unwrap_err();
+        assert!(
+            err.ends_with(
+                "2 files of project a would be deleted, which is more than the limit of 1:\n  \
+                a/x/Cargo.toml\n  a/x/thrift_build.rs"
+            ),
+            "{err}"
+        );
+        // Projects without max_deletions and the projectless files use the
+        // default.
+        let err = check(
+            &[
+                "b/x/Cargo.toml",
+                "b/y/Cargo.toml",
+                "c/Cargo.toml",
+                "c/README.md",
+            ],
+            Some(1),
+        )
+        .unwrap_err();
+        assert!(err.starts_with("Refusing to delete files"), "{err}");
+        assert!(
+            err.contains("2 files of project b would be deleted"),
+            "{err}"
+        );
+        assert!(
+            err.contains("2 files of projectless files would be deleted"),
+            "{err}"
+        );
+        assert!(check(&["b/x/Cargo.toml", "c/Cargo.toml"], Some(1)).is_ok());
+    }
+}
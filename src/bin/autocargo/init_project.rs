@@ -0,0 +1,179 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::collections::BTreeSet;
+use std::fs::read_to_string;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::anyhow;
+use anyhow::ensure;
+use autocargo::cargo_generator::is_generated_content;
+use autocargo::config::ProjectConf;
+use autocargo::paths::CargoTomlPath;
+use autocargo::paths::FbcodeRoot;
+use autocargo::paths::PathInFbcode;
+use autocargo::paths::process_input_paths;
+use autocargo::run::GenerationSession;
+use glob::Pattern;
+use glob::glob;
+use itertools::Itertools;
+use slog::info;
+use toml::Value;
+
+/// Scan the directory for Buck build files and Cargo.toml files and write a
+/// skeleton config of a new project covering it into the config dir.
+pub(crate) async fn init_project(
+    session: &GenerationSession<'_>,
+    config_dir: &Path,
+    path: &str,
+    name: Option<&str>,
+    oncall: &str,
+) -> Result<()> {
+    let fbcode_root = FbcodeRoot::from(session.fbsource_root.clone());
+    let dir = process_input_paths([path], &fbcode_root)
+        .await?
+        .pop()
+        .ok_or_else(|| anyhow!("No path provided"))?;
+    let name = match name {
+        Some(name) => name.to_owned(),
+        None => dir
+            .as_ref()
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow!("Can't derive project name from {dir}, provide --name"))?
+            .to_owned(),
+    };
+
+    ensure!(
+        !session.all_configs.projects().contains_key(&name),
+        "Project {} already exists",
+        name
+    );
+    let covering = session.all_configs.projects_covering_path(&dir);
+    ensure!(
+        covering.is_empty(),
+        "{} is already covered by projects: {}",
+        dir,
+        covering.iter().map(|conf| conf.name()).join(", ")
+    );
+    let config_path = config_dir.join(format!("{name}.toml"));
+    ensure!(
+        !config_path.exists(),
+        "Config file {} already exists",
+        config_path.display()
+    );
+
+    let build_dirs: BTreeSet<PathBuf> = session
+        .build_filenames
+        .iter()
+        .map(|filename| find_files(&fbcode_root, &dir, filename))
+        .flatten_ok()
+        .map_ok(|path| path.parent().map(Path::to_owned).unwrap_or_default())
+        .collect::<Result<_>>()?;
+    ensure!(
+        !build_dirs.is_empty(),
+        "No Buck build files found in {}",
+        dir
+    );
+    let manual_cargo_tomls = find_files(&fbcode_root, &dir, CargoTomlPath::filename())?
+        .into_iter()
+        .map(|path| {
+            let content = read_to_string(Path::join(fbcode_root.as_ref(), &path))
+                .with_context(|| format!("While reading {}", path.display()))?;
+            let is_generated =
+                is_generated_content(&content, session.all_configs.global().legacy_preambles());
+            Ok((!is_generated).then_some(path))
+        })
+        .filter_map_ok(|path| path)
+        .collect::<Result<Vec<_>>>()?;
+
+    let content = render_project_conf(&name, oncall, &dir, &build_dirs, &manual_cargo_tomls);
+    toml::from_str::<ProjectConf>(&content)
+        .context("Logic error: the generated project config is invalid")?;
+    std::fs::write(&config_path, content)
+        .with_context(|| format!("While writing {}", config_path.display()))?;
+    info!(
+        session.logger,
+        "Wrote config of project {} covering {} directories with Buck build files to {}, \
+        review it before generating the project",
+        name,
+        build_dirs.len(),
+        config_path.display()
+    );
+    Ok(())
+}
+
+/// Paths relative to fbcode of all files with the given name inside dir.
+fn find_files(
+    fbcode_root: &FbcodeRoot,
+    dir: &PathInFbcode,
+    filename: &str,
+) -> Result<Vec<PathBuf>> {
+    let abs_dir = Path::join(fbcode_root.as_ref(), dir.as_ref());
+    let pattern = format!(
+        "{}/**/{}",
+        Pattern::escape(&abs_dir.to_string_lossy()),
+        Pattern::escape(filename)
+    );
+    glob(&pattern)?
+        .map(|path| {
+            let path = path?;
+            Ok(path.strip_prefix::<&Path>(fbcode_root.as_ref())?.to_owned())
+        })
+        .collect()
+}
+
+/// Propose the project to cover dir with roots if dir itself has a build file,
+/// otherwise with include_globs for its subdirectories that have some.
+fn render_project_conf(
+    name: &str,
+    oncall: &str,
+    dir: &PathInFbcode,
+    build_dirs: &BTreeSet<PathBuf>,
+    manual_cargo_tomls: &[PathBuf],
+) -> String {
+    let quote = |s: &str| Value::String(s.to_owned()).to_string();
+    let dir_path: &Path = dir.as_ref();
+
+    let mut content = format!(
+        "# Skeleton generated by `autocargo init-project`, review it before use.\n\
+        name = {}\n\
+        oncall = {}\n",
+        quote(name),
+        quote(oncall),
+    );
+    if build_dirs.contains(dir_path) {
+        content += &format!("roots = [{}]\n", quote(&dir.to_string()));
+    } else {
+        let subdirs = build_dirs
+            .iter()
+            .filter_map(|build_dir| {
+                let subdir = build_dir.strip_prefix(dir_path).ok()?.components().next()?;
+                Some(dir_path.join(subdir))
+            })
+            .dedup()
+            .map(|subdir| format!("  {},\n", quote(&format!("{}/**", subdir.display()))))
+            .join("");
+        content += &format!("include_globs = [\n{subdirs}]\n");
+    }
+
+    if !manual_cargo_tomls.is_empty() {
+        content += "\n# Existing Cargo.toml files that are not generated by autocargo. They\n\
+            # will be overwritten if a Rust rule is defined next to them, either\n\
+            # exclude their directories or make another project with\n\
+            # manual_cargo_toml = true cover them:\n";
+        for path in manual_cargo_tomls {
+            content += &format!("#   {}\n", path.display());
+        }
+    }
+    content
+}
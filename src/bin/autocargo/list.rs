@@ -0,0 +1,41 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use anyhow::Result;
+use autocargo::inventory::crate_inventory;
+use autocargo::run::GenerationSession;
+
+use crate::args::OutputFormat;
+
+/// Print the crates generated for the selected projects, which requires
+/// running the whole generation.
+pub(crate) async fn print_crate_list(
+    session: &GenerationSession<'_>,
+    format: OutputFormat,
+) -> Result<()> {
+    let inventory = crate_inventory(&session.run().await?.generated);
+
+    match format {
+        OutputFormat::Text => {
+            for entry in &inventory {
+                println!(
+                    "{} {} {} {} {}{}",
+                    entry.project,
+                    entry.name,
+                    entry.version.as_deref().unwrap_or("workspace"),
+                    entry.cargo_toml_path,
+                    entry.rules.join(","),
+                    if entry.is_oss_published() { " oss" } else { "" },
+                );
+            }
+        }
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&inventory)?),
+    }
+    Ok(())
+}
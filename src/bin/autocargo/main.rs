@@ -11,139 +11,426 @@
 //! user input, parsing Buck files, and generating Cargo.toml files.
 
 mod args;
+mod exit_code;
+mod explain;
 mod generate_cargo_locks;
 mod generate_utd_map;
+mod graph;
 mod handle_generation_results;
+mod init_project;
+mod list;
 mod logger;
+mod print_config;
+mod stats;
+mod verify_generated;
+mod verify_stamp;
+
+use std::process::ExitCode;
 
 use anyhow::Result;
-use autocargo::buck_processing::ProcessOutput;
-use autocargo::buck_processing::process_targets;
-use autocargo::cargo_generator::CargoGenerator;
+use anyhow::anyhow;
+use autocargo::ConcurrencyLimit;
+use autocargo::buck_processing::BuckOptions;
+use autocargo::buck_processing::Diagnostics;
+use autocargo::buck_processing::Severity;
+use autocargo::cargo_generator::ErrorLocation;
+use autocargo::cargo_generator::GenerationError;
+use autocargo::cargo_generator::OssProblem;
+use autocargo::cargo_generator::StampInputs;
+use autocargo::cargo_generator::validate_oss_packages;
+use autocargo::config::GlobalConf;
+use autocargo::docs::DocRegistry;
+use autocargo::events::AutocargoEvent;
+use autocargo::events::EventSender;
+use autocargo::events::Phase;
 use autocargo::paths::FbcodeRoot;
 use autocargo::paths::FbsourceRoot;
-use autocargo::project_loader::ProjectLoader;
+use autocargo::run::GenerationSession;
+use autocargo::run::LoadedProjects;
 use clap::Parser;
+use slog::Logger;
 use slog::info;
+use slog::warn;
 
 use crate::args::AutocargoArgs;
+use crate::args::AutocargoCommand;
+use crate::args::ErrorFormat;
+use crate::exit_code::AutocargoExitCode;
+use crate::exit_code::ClassifiedError;
+use crate::exit_code::WithExitCode;
+use crate::handle_generation_results::HandleOptions;
 use crate::handle_generation_results::handle_generation_results;
 use crate::logger::logger;
+use crate::stats::Stats;
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> ExitCode {
     let args = AutocargoArgs::parse();
     let logger = logger();
 
+    let (events, stats) = match args.stats {
+        Some(_) => {
+            let (events, receiver) = EventSender::channel();
+            (events, Some(Stats::collect(receiver)))
+        }
+        // The binary reports progress through logs, the events are meant for
+        // tools embedding the pipeline.
+        None => (EventSender::default(), None),
+    };
+
+    let result = run(&logger, &args, events).await;
+
+    if let (Some(format), Some(stats)) = (args.stats, stats) {
+        match stats.await {
+            Ok(stats) => {
+                if let Err(error) = stats.print(format) {
+                    warn!(logger, "Failed to print stats: {:?}", error);
+                }
+            }
+            Err(error) => warn!(logger, "Failed to collect stats: {:?}", error),
+        }
+    }
+
+    match result {
+        Ok(code) => code.into(),
+        Err(ClassifiedError { code, error }) => {
+            match args.error_format {
+                ErrorFormat::Human => eprintln!("Error: {error:?}"),
+                ErrorFormat::Json => {
+                    let ErrorLocation {
+                        targets_path,
+                        rule,
+                        config_key,
+                    } = GenerationError::locate(&error);
+                    eprintln!(
+                        "{}",
+                        serde_json::json!({
+                            "exit_code": code as u8,
+                            "message": format!("{error:#}"),
+                            "targets_path": targets_path.map(|path| path.as_dir().to_string()),
+                            "rule": rule,
+                            "config_key": config_key,
+                        })
+                    );
+                }
+            }
+            code.into()
+        }
+    }
+}
+
+async fn run(
+    logger: &Logger,
+    args: &AutocargoArgs,
+    events: EventSender,
+) -> Result<AutocargoExitCode, ClassifiedError> {
+    if let Some(AutocargoCommand::Doc { field }) = &args.command {
+        print_doc(field.as_deref()).exit_code(AutocargoExitCode::ConfigError)?;
+        return Ok(AutocargoExitCode::Success);
+    }
+
     info!(logger, "Using isolation dir: {:?}", args.use_isolation_dir);
 
-    let fbsource_root = FbsourceRoot::new().await?;
+    let fbsource_root = FbsourceRoot::new()
+        .await
+        .exit_code(AutocargoExitCode::Failure)?;
     let fbcode_root = FbcodeRoot::from(fbsource_root.clone());
     info!(logger, "{:?}", fbsource_root);
 
-    let all_configs = args.project_confs(&fbsource_root).await?;
-    let paths = args.process_input_paths(&fbcode_root).await?;
-    let paths_provided = !paths.is_empty();
-    let selected_configs = if paths_provided || !args.projects.is_empty() {
-        info!(
-            logger,
-            "Processing projects selected based on input paths and project names provided"
-        );
-        all_configs.select_based_on_paths_and_names(&paths, &args.projects)?
-    } else {
-        info!(
+    let global_config = GlobalConf::from_dir(args.config_dir(&fbsource_root)).await?;
+    let buck_fixtures = args
+        .buck_fixtures()
+        .exit_code(AutocargoExitCode::ConfigError)?;
+    let cratemap_cache = args.thrift_cratemap_cache();
+    let paths = args
+        .process_input_paths(
             logger,
-            "Processing all projects since no input paths or project names were provided"
-        );
-        all_configs.select_all()
-    };
+            &fbcode_root,
+            BuckOptions {
+                config: global_config.buck(),
+                use_isolation_dir: args.use_isolation_dir,
+                show_output: args.show_buck_output,
+                fixtures: buck_fixtures.as_ref(),
+                offline: args.offline,
+                cratemap_cache: cratemap_cache.as_ref(),
+            },
+        )
+        .await
+        .exit_code(AutocargoExitCode::ConfigError)?;
+    let all_configs = args.project_confs(&fbsource_root, &paths).await?;
 
     let utd_map_path = args.utd_map(&fbsource_root);
+    let build_filenames = args
+        .build_filenames(&global_config)
+        .exit_code(AutocargoExitCode::ConfigError)?;
 
-    let (project_files, projectless_files) = ProjectLoader {
-        logger: &logger,
+    let stamp = if args.stamp || matches!(args.command, Some(AutocargoCommand::VerifyStamp)) {
+        Some(
+            StampInputs::compute(&fbsource_root, &args.config_dir(&fbsource_root))
+                .await
+                .exit_code(AutocargoExitCode::ConfigError)?,
+        )
+    } else {
+        None
+    };
+
+    let session = GenerationSession {
+        logger,
+        events: &events,
         fbsource_root: &fbsource_root,
-        fbcode_root: &fbcode_root,
-        configs: &selected_configs,
+        all_configs: &all_configs,
         input_paths: paths,
+        project_names: args.projects.clone(),
+        use_isolation_dir: args.use_isolation_dir,
+        show_buck_output: args.show_buck_output,
+        buck_fixtures: buck_fixtures.as_ref(),
+        concurrency: &ConcurrencyLimit::new(args.max_concurrency),
+        build_filenames: &build_filenames,
+        offline: args.offline,
+        cratemap_cache: cratemap_cache.as_ref(),
+        allow_missing_roots: args.allow_missing_roots,
+        stamp,
+    };
+
+    if let Some(AutocargoCommand::PrintConfig {
+        project_or_path,
+        format,
+    }) = &args.command
+    {
+        print_config::print_config(&session, project_or_path, *format)
+            .await
+            .exit_code(AutocargoExitCode::ConfigError)?;
+        return Ok(AutocargoExitCode::Success);
     }
-    .load()
-    .await?;
 
+    if let Some(AutocargoCommand::InitProject {
+        path, name, oncall, ..
+    }) = &args.command
     {
-        let max_proj_name_len = selected_configs
-            .projects()
-            .iter()
-            .map(|conf| conf.name().len())
-            .chain(Some(12)) // The minimal will be 12
-            .max()
-            .unwrap_or(0);
-        for proj in &project_files {
-            info!(
-                logger,
-                "{:>4$} found Cargo.toml {:>3} TARGETS {:>3} additional {:>3}",
-                proj.conf().name(),
-                proj.cargo().len(),
-                proj.targets().len(),
-                proj.additional().len(),
-                max_proj_name_len,
-            );
-        }
-        if paths_provided {
-            info!(
-                logger,
-                "Files from input for which no project was found - \
-                Cargo.toml {:>3} TARGETS {:>3} additional {:>3}",
-                projectless_files.cargo().len(),
-                projectless_files.targets().len(),
-                projectless_files.additional().len()
-            );
-        }
+        init_project::init_project(
+            &session,
+            &args.config_dir(&fbsource_root),
+            path,
+            name.as_deref(),
+            oncall,
+        )
+        .await
+        .exit_code(AutocargoExitCode::ConfigError)?;
+        return Ok(AutocargoExitCode::Success);
     }
 
-    let ProcessOutput {
-        processed_manifests,
-        unprocessed_paths,
-    } = process_targets(
-        &logger,
-        &fbcode_root,
-        args.use_isolation_dir,
-        project_files.iter().flat_map(|p| p.targets().iter()),
-    )
-    .await?;
+    if let (Some(AutocargoCommand::VerifyStamp), Some(stamp)) = (&args.command, &session.stamp) {
+        let outdated = verify_stamp::verify_stamp(logger, &session, stamp)
+            .await
+            .exit_code(AutocargoExitCode::Failure)?;
+        return Ok(if outdated {
+            AutocargoExitCode::CheckDifferences
+        } else {
+            AutocargoExitCode::Success
+        });
+    }
 
-    info!(
-        logger,
-        "Processed {} manifests; {} paths were out of scope of selected \
-        projects",
-        processed_manifests.values().flatten().count(),
-        unprocessed_paths.len(),
-    );
-
-    let generator = CargoGenerator::new(
-        &logger,
-        &fbsource_root,
-        &all_configs,
-        &project_files,
-        &unprocessed_paths,
-    )
-    .await?;
+    if let Some(AutocargoCommand::Explain {
+        cargo_toml,
+        name,
+        format,
+    }) = &args.command
+    {
+        explain::explain(&session, cargo_toml, name, *format)
+            .await
+            .exit_code(AutocargoExitCode::Failure)?;
+        return Ok(AutocargoExitCode::Success);
+    }
 
-    let generated =
-        generator.generate_for_projects(&logger, &selected_configs, &processed_manifests)?;
+    if let Some(AutocargoCommand::List { format }) = &args.command {
+        list::print_crate_list(&session, *format)
+            .await
+            .exit_code(AutocargoExitCode::Failure)?;
+        return Ok(AutocargoExitCode::Success);
+    }
+
+    if let Some(AutocargoCommand::Graph { format, crates }) = &args.command {
+        graph::print_graph(&session, *format, *crates)
+            .await
+            .exit_code(AutocargoExitCode::Failure)?;
+        return Ok(AutocargoExitCode::Success);
+    }
+
+    let loaded = session.load_projects(session.select_projects()?).await?;
+    let processed = if args.changed_only {
+        session.process_changed_targets(&loaded).await?
+    } else {
+        session.process_targets(&loaded).await?
+    };
+    report_diagnostics(logger, args.error_format, &processed.diagnostics);
+    let generator = session.cargo_generator(&loaded, &processed).await?;
+    let generated = if args.changed_only {
+        session.generate_changed(&generator, &loaded, &processed)?
+    } else {
+        session.generate(&generator, &loaded, &processed)?
+    };
+    let oss_problems = validate_oss_packages(&fbcode_root, &generated);
+    report_oss_problems(logger, args.error_format, &oss_problems);
+    if args.strict_oss && !oss_problems.is_empty() {
+        return Err(ClassifiedError {
+            code: AutocargoExitCode::VerificationFailed,
+            error: anyhow!(
+                "Found {} problems in the oss-ready Cargo.toml files",
+                oss_problems.len()
+            ),
+        });
+    }
+    let LoadedProjects {
+        selected_configs,
+        project_files,
+        projectless_files,
+        skipped_projects,
+    } = &loaded;
 
-    handle_generation_results(
-        &logger,
+    events.emit(AutocargoEvent::PhaseStarted(Phase::WriteFiles));
+    let differences_found = handle_generation_results(
+        logger,
+        &events,
         &fbcode_root,
         &generated,
-        &project_files,
-        &projectless_files,
+        project_files,
+        projectless_files,
+        HandleOptions {
+            check: args.check,
+            no_delete: args.no_delete,
+            changed_only: args.changed_only,
+            max_deletions: args.max_deletions,
+            force: args.force,
+            legacy_preambles: all_configs.global().legacy_preambles(),
+        },
     )
-    .await?;
+    .await
+    .exit_code(AutocargoExitCode::Failure)?;
 
-    generate_cargo_locks::generate_cargo_locks(&logger, &fbsource_root, &selected_configs).await?;
+    if args.verify {
+        verify_generated::verify_generated(logger, &fbcode_root, &generated)
+            .exit_code(AutocargoExitCode::VerificationFailed)?;
+    }
+
+    if !skipped_projects.is_empty() {
+        warn!(
+            logger,
+            "Skipped {} projects since their roots are missing from the checkout: {}",
+            skipped_projects.len(),
+            skipped_projects.join(", "),
+        );
+    }
 
-    generate_utd_map::generate_utd_map(&logger, &all_configs, &utd_map_path).await?;
+    if args.check {
+        let stale_locks = args.locked_check
+            && generate_cargo_locks::generate_cargo_locks(
+                logger,
+                &fbsource_root,
+                selected_configs,
+                true,
+            )
+            .await
+            .exit_code(AutocargoExitCode::Failure)?;
+        return Ok(if differences_found || stale_locks {
+            AutocargoExitCode::CheckDifferences
+        } else {
+            AutocargoExitCode::Success
+        });
+    }
+
+    let mut exit_code = AutocargoExitCode::Success;
+    let mut handle_step = |step: &str, result: Result<()>| match result {
+        Ok(()) => Ok(()),
+        Err(error) if args.keep_going => {
+            warn!(logger, "{} failed, continuing: {:?}", step, error);
+            exit_code = AutocargoExitCode::PartialSuccess;
+            Ok(())
+        }
+        Err(error) => Err(ClassifiedError {
+            code: AutocargoExitCode::Failure,
+            error,
+        }),
+    };
 
+    events.emit(AutocargoEvent::PhaseStarted(Phase::GenerateCargoLocks));
+    let mut stale_locks = false;
+    handle_step(
+        "Cargo.lock generation",
+        generate_cargo_locks::generate_cargo_locks(
+            logger,
+            &fbsource_root,
+            selected_configs,
+            args.locked_check,
+        )
+        .await
+        .map(|stale| stale_locks = stale),
+    )?;
+    events.emit(AutocargoEvent::PhaseStarted(Phase::GenerateUtdMap));
+    handle_step(
+        "UTD map generation",
+        generate_utd_map::generate_utd_map(
+            logger,
+            &all_configs,
+            (session.is_partial() && !args.utd_full_rebuild).then_some(selected_configs),
+            &utd_map_path,
+        )
+        .await,
+    )?;
+
+    if stale_locks && exit_code == AutocargoExitCode::Success {
+        exit_code = AutocargoExitCode::CheckDifferences;
+    }
+    Ok(exit_code)
+}
+
+/// Tell the user about the skipped rules and attributes of rules, in json as
+/// one object per line on stderr.
+fn report_diagnostics(logger: &Logger, format: ErrorFormat, diagnostics: &Diagnostics) {
+    for diagnostic in diagnostics.sorted() {
+        match (format, diagnostic.severity) {
+            (ErrorFormat::Human, Severity::Info) => {
+                info!(logger, "{}: {}", diagnostic.rule, diagnostic.reason)
+            }
+            (ErrorFormat::Human, Severity::Warning) => warn!(
+                logger,
+                "{}: {}, no Cargo.toml is generated for it", diagnostic.rule, diagnostic.reason
+            ),
+            (ErrorFormat::Json, _) => eprintln!("{}", serde_json::json!(diagnostic)),
+        }
+    }
+}
+
+/// Tell the user about the problems of the oss-ready Cargo.toml files, in json
+/// as one object per line on stderr.
+fn report_oss_problems(logger: &Logger, format: ErrorFormat, problems: &[OssProblem]) {
+    for problem in problems {
+        match format {
+            ErrorFormat::Human => warn!(logger, "{}: {}", problem.cargo_toml_path, problem.problem),
+            ErrorFormat::Json => eprintln!("{}", serde_json::json!(problem)),
+        }
+    }
+}
+
+/// Print documentation of fields matching the query, or list all of them.
+fn print_doc(field: Option<&str>) -> Result<()> {
+    let registry = DocRegistry::new();
+    match field {
+        Some(field) => {
+            let found = registry.lookup(field)?;
+            println!(
+                "{}",
+                found
+                    .iter()
+                    .map(|doc| doc.render())
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+        }
+        None => {
+            for doc in registry.fields() {
+                println!("{}", doc.path);
+            }
+        }
+    }
     Ok(())
 }
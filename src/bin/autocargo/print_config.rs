@@ -0,0 +1,234 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use anyhow::Result;
+use anyhow::ensure;
+use autocargo::config::ProjectConf;
+use autocargo::paths::FbcodeRoot;
+use autocargo::paths::process_input_paths;
+use autocargo::run::GenerationSession;
+use autocargo::run::LoadedProjects;
+use itertools::Itertools;
+use serde::Serialize;
+
+use crate::args::OutputFormat;
+
+/// Effective configuration of a single project.
+#[derive(Serialize)]
+struct EffectiveConfig<'a> {
+    /// The config with defaults applied.
+    config: &'a ProjectConf,
+    /// Patterns of the paths covered by the project.
+    include: Vec<String>,
+    /// Patterns of the paths excluded from the included ones.
+    exclude: Vec<String>,
+    /// Names of the other projects in the dependency closure of the project.
+    dependency_closure: Vec<String>,
+    /// TARGETS directories owned by the project.
+    owned_targets_dirs: Vec<String>,
+}
+
+/// Print the effective configuration of the project with the given name, or of
+/// the projects covering the given path: the config with defaults applied, the
+/// patterns it covers, its dependency closure and the TARGETS directories it
+/// owns.
+pub(crate) async fn print_config(
+    session: &GenerationSession<'_>,
+    query: &str,
+    format: OutputFormat,
+) -> Result<()> {
+    let fbcode_root = FbcodeRoot::from(session.fbsource_root.clone());
+    let projects: Vec<&ProjectConf> = match session.all_configs.projects().get(query) {
+        Some(conf) => vec![conf],
+        None => {
+            let projects = process_input_paths([query], &fbcode_root)
+                .await?
+                .iter()
+                .flat_map(|path| session.all_configs.projects_covering_path(path))
+                .sorted_by_key(|conf| conf.name())
+                .dedup_by(|a, b| a.name() == b.name())
+                .collect::<Vec<_>>();
+            ensure!(
+                !projects.is_empty(),
+                "{} is neither a project name nor a path covered by any project",
+                query
+            );
+            projects
+        }
+    };
+
+    let mut configs = Vec::new();
+    for conf in projects {
+        let dependency_closure = session
+            .all_configs
+            .select_based_on_paths_and_names(&[], &[conf.name().clone()])?;
+        let loaded = session.load_projects(dependency_closure).await?;
+        configs.push(effective_config(conf, &loaded)?);
+    }
+
+    print!("{}", render_effective_configs(&configs, format)?);
+    Ok(())
+}
+
+fn effective_config<'a>(
+    conf: &'a ProjectConf,
+    loaded: &LoadedProjects<'_>,
+) -> Result<EffectiveConfig<'a>> {
+    let mut include: Vec<String> = conf
+        .include_globs()
+        .iter()
+        .map(|p| p.as_str().to_owned())
+        .sorted()
+        .collect();
+    include.extend(
+        conf.root_patterns()?
+            .iter()
+            .map(|p| p.as_str().to_owned())
+            .sorted(),
+    );
+    if let Some(public_cargo_dir) = conf
+        .oss_git_config()
+        .as_ref()
+        .and_then(|c| c.public_cargo_dir.as_ref())
+    {
+        include.push(format!("{public_cargo_dir}/**"));
+    }
+
+    Ok(EffectiveConfig {
+        config: conf,
+        include,
+        exclude: conf
+            .exclude_globs()
+            .iter()
+            .map(|p| p.as_str().to_owned())
+            .sorted()
+            .collect(),
+        dependency_closure: loaded
+            .selected_configs
+            .projects()
+            .iter()
+            .map(|dep| dep.name())
+            .filter(|name| *name != conf.name())
+            .cloned()
+            .collect(),
+        owned_targets_dirs: loaded
+            .project_files
+            .iter()
+            .filter(|project_files| project_files.conf().name() == conf.name())
+            .flat_map(|project_files| project_files.targets())
+            .map(|targets| targets.as_dir().to_string())
+            .collect(),
+    })
+}
+
+/// Render the configs as a JSON array or, for text, each of them as TOML
+/// followed by the lists of its patterns, dependencies and TARGETS directories.
+fn render_effective_configs(
+    configs: &[EffectiveConfig<'_>],
+    format: OutputFormat,
+) -> Result<String> {
+    match format {
+        OutputFormat::Text => {
+            let mut out = String::new();
+            for config in configs {
+                out += &format!(
+                    "Project {}:\n{}\n",
+                    config.config.name(),
+                    toml::to_string_pretty(config.config)?
+                );
+                out += "Covered patterns:\n";
+                for pattern in &config.include {
+                    out += &format!("  include {pattern}\n");
+                }
+                for pattern in &config.exclude {
+                    out += &format!("  exclude {pattern}\n");
+                }
+                out += "\nDependency closure:\n";
+                for dep in &config.dependency_closure {
+                    out += &format!("  {dep}\n");
+                }
+                out += "\nOwned TARGETS directories:\n";
+                for dir in &config.owned_targets_dirs {
+                    out += &format!("  {dir}\n");
+                }
+                out += "\n";
+            }
+            Ok(out)
+        }
+        OutputFormat::Json => Ok(format!("{}\n", serde_json::to_string_pretty(configs)?)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::Value;
+    use serde_json::from_str;
+    use serde_json::from_value;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn render_effective_configs_test() {
+        let conf: ProjectConf = from_value(json!({
+            "name": "foo",
+            "oncall": "rust_foundation",
+            "roots": ["common/rust/foo", "common/rust/bar"],
+            "dependencies": ["baz"],
+            "exclude_globs": ["common/rust/foo/fixtures/**"],
+            "oss_git_config": {
+                "git": "https://github.com/facebook/foo.git",
+                "oss_dependency_overrides": [{"action": "remove", "pattern": "fbinit*"}],
+            },
+        }))
+        .unwrap();
+        let configs = [EffectiveConfig {
+            config: &conf,
+            include: vec!["common/rust/foo/**".to_owned()],
+            exclude: vec!["common/rust/foo/fixtures/**".to_owned()],
+            dependency_closure: vec!["baz".to_owned()],
+            owned_targets_dirs: vec!["common/rust/foo".to_owned()],
+        }];
+
+        let text = render_effective_configs(&configs, OutputFormat::Text).unwrap();
+        let (config_toml, rest) = text
+            .strip_prefix("Project foo:\n")
+            .unwrap()
+            .split_once("\nCovered patterns:\n")
+            .unwrap();
+        assert_eq!(
+            rest,
+            "  include common/rust/foo/**\n  exclude common/rust/foo/fixtures/**\n\n\
+            Dependency closure:\n  baz\n\n\
+            Owned TARGETS directories:\n  common/rust/foo\n\n",
+        );
+        assert!(
+            config_toml
+                .contains("roots = [\n    \"common/rust/bar\",\n    \"common/rust/foo\",\n]\n")
+        );
+        assert!(config_toml.contains("exclude_globs = [\"common/rust/foo/fixtures/**\"]\n"));
+        // The printed config is a valid config that reads back into itself.
+        let reread: ProjectConf = toml::from_str(config_toml).unwrap();
+        assert_eq!(toml::to_string_pretty(&reread).unwrap(), config_toml);
+
+        let json: Value =
+            from_str(&render_effective_configs(&configs, OutputFormat::Json).unwrap()).unwrap();
+        assert_eq!(json[0]["config"]["name"], json!("foo"));
+        assert_eq!(
+            json[0]["config"]["roots"],
+            json!(["common/rust/bar", "common/rust/foo"])
+        );
+        assert_eq!(
+            json[0]["config"]["oss_git_config"]["oss_dependency_overrides"],
+            json!([{"action": "remove", "pattern": "fbinit*"}]),
+        );
+        assert_eq!(json[0]["dependency_closure"], json!(["baz"]));
+        assert_eq!(json[0]["owned_targets_dirs"], json!(["common/rust/foo"]));
+    }
+}
@@ -0,0 +1,121 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::time::Duration;
+use std::time::Instant;
+
+use anyhow::Result;
+use autocargo::events::AutocargoEvent;
+use autocargo::events::Phase;
+use serde::Serialize;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::task::JoinHandle;
+
+use crate::args::StatsFormat;
+
+/// Time spent in each phase of the pipeline together with counts of the
+/// processed files, collected from the events of the run.
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct Stats {
+    phases: Vec<PhaseStats>,
+    total_secs: f64,
+    projects: usize,
+    cargo_files: usize,
+    targets_files: usize,
+    additional_files: usize,
+    manifests: usize,
+    unprocessed_paths: usize,
+    files_written: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct PhaseStats {
+    phase: String,
+    secs: f64,
+}
+
+impl Stats {
+    /// Collect the stats in a background task until all senders of the events
+    /// are dropped, so that the phases are timed as they happen.
+    pub(crate) fn collect(mut receiver: UnboundedReceiver<AutocargoEvent>) -> JoinHandle<Self> {
+        tokio::spawn(async move {
+            let start = Instant::now();
+            let mut stats = Self::default();
+            let mut current: Option<(Phase, Instant)> = None;
+            while let Some(event) = receiver.recv().await {
+                match event {
+                    AutocargoEvent::PhaseStarted(phase) => {
+                        stats.finish_phase(current.replace((phase, Instant::now())));
+                    }
+                    AutocargoEvent::ProjectLoaded {
+                        name: _,
+                        cargo,
+                        targets,
+                        additional,
+                    } => {
+                        stats.projects += 1;
+                        stats.cargo_files += cargo;
+                        stats.targets_files += targets;
+                        stats.additional_files += additional;
+                    }
+                    AutocargoEvent::TargetsProcessed {
+                        manifests,
+                        unprocessed_paths,
+                    } => {
+                        stats.manifests += manifests;
+                        stats.unprocessed_paths += unprocessed_paths;
+                    }
+                    AutocargoEvent::FileWritten(_) => stats.files_written += 1,
+                    AutocargoEvent::Warning(_) => {}
+                }
+            }
+            stats.finish_phase(current);
+            stats.total_secs = start.elapsed().as_secs_f64();
+            stats
+        })
+    }
+
+    fn finish_phase(&mut self, phase: Option<(Phase, Instant)>) {
+        if let Some((phase, started)) = phase {
+            self.phases.push(PhaseStats {
+                phase: format!("{phase:?}"),
+                secs: started.elapsed().as_secs_f64(),
+            });
+        }
+    }
+
+    /// Print the stats to stdout.
+    pub(crate) fn print(&self, format: StatsFormat) -> Result<()> {
+        match format {
+            StatsFormat::Text => {
+                for PhaseStats { phase, secs } in &self.phases {
+                    println!("{:<20} {:>10}", phase, format_secs(*secs));
+                }
+                println!("{:<20} {:>10}", "Total", format_secs(self.total_secs));
+                println!(
+                    "Projects {}, Cargo.toml {}, TARGETS {}, additional {}, manifests {}, \
+                    unprocessed TARGETS {}, files written {}",
+                    self.projects,
+                    self.cargo_files,
+                    self.targets_files,
+                    self.additional_files,
+                    self.manifests,
+                    self.unprocessed_paths,
+                    self.files_written,
+                );
+            }
+            StatsFormat::Json => println!("{}", serde_json::to_string_pretty(self)?),
+        }
+        Ok(())
+    }
+}
+
+fn format_secs(secs: f64) -> String {
+    format!("{:.3?}", Duration::from_secs_f64(secs))
+}
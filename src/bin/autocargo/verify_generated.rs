@@ -0,0 +1,397 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::fs::read_to_string;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::bail;
+use autocargo::cargo_generator::GenerationOutput;
+use autocargo::paths::CargoTomlPath;
+use autocargo::paths::FbcodeRoot;
+use autocargo::paths::PathInFbcode;
+use cargo_toml::DepsSet;
+use cargo_toml::Manifest;
+use glob::Pattern;
+use slog::Logger;
+use slog::info;
+use slog::warn;
+
+/// Check that the generated Cargo.toml files would be accepted by Cargo:
+/// path dependencies point to packages with matching names, workspaces don't
+/// contain duplicate package names and features only refer to existing
+/// features and dependencies. Each problem is logged and an error is returned
+/// if any were found.
+pub(crate) fn verify_generated(
+    logger: &Logger,
+    fbcode_root: &FbcodeRoot,
+    generated: &GenerationOutput,
+) -> Result<()> {
+    let manifests = generated
+        .cargo_manifests
+        .iter()
+        .map(|(path, manifest)| {
+            let parsed = Manifest::from_str(&manifest.to_toml_string())
+                .with_context(|| format!("While parsing generated {}", path.as_file()))?;
+            Ok((path.as_dir().clone(), parsed))
+        })
+        .collect::<Result<HashMap<_, _>>>()?;
+
+    let mut verifier = Verifier {
+        fbcode_root: fbcode_root.as_ref(),
+        manifests,
+        on_disk: HashMap::new(),
+        problems: Vec::new(),
+    };
+    let mut dirs: Vec<_> = verifier.manifests.keys().cloned().collect();
+    dirs.sort();
+    for dir in &dirs {
+        verifier.verify(dir);
+    }
+
+    for problem in &verifier.problems {
+        warn!(logger, "{}", problem);
+    }
+    if !verifier.problems.is_empty() {
+        bail!(
+            "Verification found {} problems in the generated Cargo.toml files",
+            verifier.problems.len(),
+        );
+    }
+    info!(logger, "Verified {} generated Cargo.toml files", dirs.len());
+    Ok(())
+}
+
+struct Verifier<'a> {
+    fbcode_root: &'a Path,
+    /// Generated manifests keyed by their directory.
+    manifests: HashMap<PathInFbcode, Manifest>,
+    /// Package names of manifests read from disk, None if there is no package.
+    on_disk: HashMap<PathInFbcode, Option<String>>,
+    problems: Vec<String>,
+}
+
+impl Verifier<'_> {
+    fn verify(&mut self, dir: &PathInFbcode) {
+        let manifest = self.manifests[dir].clone();
+        let cargo_toml = dir.join_to_path_in_fbcode(CargoTomlPath::filename());
+
+        for (kind, deps) in deps_sets(&manifest) {
+            self.verify_path_deps(&cargo_toml, dir, &kind, deps);
+        }
+        if let Some(workspace) = &manifest.workspace {
+            self.verify_path_deps(
+                &cargo_toml,
+                dir,
+                "workspace.dependencies",
+                &workspace.dependencies,
+            );
+            self.verify_workspace_members(&cargo_toml, dir, &workspace.members);
+        }
+        self.verify_features(&cargo_toml, &manifest);
+    }
+
+    fn verify_path_deps(
+        &mut self,
+        cargo_toml: &PathInFbcode,
+        dir: &PathInFbcode,
+        kind: &str,
+        deps: &DepsSet,
+    ) {
+        for (name, dep) in deps {
+            let Some(path) = dep.detail().and_then(|detail| detail.path.as_ref()) else {
+                continue;
+            };
+            let expected = dep.package().unwrap_or(name);
+            match self.package_name(&dir.join_to_path_in_fbcode(path)) {
+                Ok(Some(actual)) if actual == expected => {}
+                Ok(Some(actual)) => self.problems.push(format!(
+                    "{cargo_toml}: {kind} {name:?} expects package {expected:?} at {path:?}, \
+                    but found {actual:?}",
+                )),
+                Ok(None) => self.problems.push(format!(
+                    "{cargo_toml}: {kind} {name:?} points to {path:?} which has no package",
+                )),
+                Err(error) => self.problems.push(format!(
+                    "{cargo_toml}: {kind} {name:?} points to {path:?} which is broken: {error:#}",
+                )),
+            }
+        }
+    }
+
+    fn verify_workspace_members(
+        &mut self,
+        cargo_toml: &PathInFbcode,
+        dir: &PathInFbcode,
+        members: &[String],
+    ) {
+        let mut names = BTreeMap::new();
+        for (member, member_dir) in self.expand_workspace_members(cargo_toml, dir, members) {
+            match self.package_name(&member_dir) {
+                Ok(Some(name)) => names.entry(name).or_insert_with(Vec::new).push(member),
+                Ok(None) => {}
+                Err(error) => self.problems.push(format!(
+                    "{cargo_toml}: workspace member {member:?} is broken: {error:#}",
+                )),
+            }
+        }
+        for (name, members) in names {
+            if members.len() > 1 {
+                self.problems.push(format!(
+                    "{cargo_toml}: package {name:?} is defined by multiple workspace \
+                    members {members:?}",
+                ));
+            }
+        }
+    }
+
+    /// The workspace members together with their directories, glob members
+    /// like `crates/*` are expanded to the directories with a generated or
+    /// existing Cargo.toml that they match, like Cargo does.
+    fn expand_workspace_members(
+        &mut self,
+        cargo_toml: &PathInFbcode,
+        dir: &PathInFbcode,
+        members: &[String],
+    ) -> Vec<(String, PathInFbcode)> {
+        let mut expanded = Vec::new();
+        for member in members {
+            if !member.contains(['*', '?', '[']) {
+                expanded.push((member.clone(), dir.join_to_path_in_fbcode(member)));
+                continue;
+            }
+            match self.match_glob_member(dir, member) {
+                Ok(matched) => expanded.extend(matched.into_iter().map(|relative| {
+                    (
+                        relative.display().to_string(),
+                        dir.join_to_path_in_fbcode(relative),
+                    )
+                })),
+                Err(error) => self.problems.push(format!(
+                    "{cargo_toml}: workspace member {member:?} is broken: {error:#}",
+                )),
+            }
+        }
+        expanded
+    }
+
+    /// Paths relative to the workspace dir of the directories matching the
+    /// glob member that have a generated or existing Cargo.toml.
+    fn match_glob_member(&self, dir: &PathInFbcode, member: &str) -> Result<BTreeSet<PathBuf>> {
+        let pattern = Pattern::new(member)?;
+        let mut matched: BTreeSet<_> = self
+            .manifests
+            .keys()
+            .filter_map(|member_dir| member_dir.as_ref().strip_prefix(dir.as_ref()).ok())
+            .filter(|relative| pattern.matches_path(relative))
+            .map(Path::to_path_buf)
+            .collect();
+
+        let workspace_dir = Path::join(self.fbcode_root, dir.as_ref());
+        let on_disk = format!(
+            "{}/{}/{}",
+            Pattern::escape(&workspace_dir.to_string_lossy()),
+            member,
+            CargoTomlPath::filename(),
+        );
+        for path in glob::glob(&on_disk)? {
+            let path = path?;
+            if let Some(relative) = path
+                .parent()
+                .and_then(|member_dir| member_dir.strip_prefix(&workspace_dir).ok())
+            {
+                matched.insert(relative.to_path_buf());
+            }
+        }
+        Ok(matched)
+    }
+
+    fn verify_features(&mut self, cargo_toml: &PathInFbcode, manifest: &Manifest) {
+        // Dev-dependencies can't be referenced by features.
+        let deps: HashMap<&str, bool> = std::iter::once(&manifest.dependencies)
+            .chain(std::iter::once(&manifest.build_dependencies))
+            .chain(
+                manifest
+                    .target
+                    .values()
+                    .flat_map(|target| [&target.dependencies, &target.build_dependencies]),
+            )
+            .flat_map(|deps| deps.iter())
+            .map(|(name, dep)| (name.as_str(), dep.optional()))
+            .collect();
+
+        for (feature, values) in &manifest.features {
+            for value in values {
+                let problem = if let Some(dep) = value.strip_prefix("dep:") {
+                    match deps.get(dep) {
+                        Some(true) => None,
+                        Some(false) => Some(format!("{dep:?} is not an optional dependency")),
+                        None => Some(format!("{dep:?} is not a dependency")),
+                    }
+                } else if let Some((dep, _)) = value.split_once('/') {
+                    let dep = dep.strip_suffix('?').unwrap_or(dep);
+                    (!deps.contains_key(dep)).then(|| format!("{dep:?} is not a dependency"))
+                } else if manifest.features.contains_key(value)
+                    || deps.get(value.as_str()) == Some(&true)
+                {
+                    None
+                } else {
+                    Some(format!(
+                        "{value:?} is neither a feature nor an optional dependency"
+                    ))
+                };
+                if let Some(problem) = problem {
+                    self.problems.push(format!(
+                        "{cargo_toml}: feature {feature:?} includes {value:?}, but {problem}",
+                    ));
+                }
+            }
+        }
+    }
+
+    /// Name of the package in the given directory, either generated or read
+    /// from disk.
+    fn package_name(&mut self, dir: &PathInFbcode) -> Result<Option<String>> {
+        if let Some(manifest) = self.manifests.get(dir) {
+            return Ok(manifest.package.as_ref().map(|p| p.name.clone()));
+        }
+        if let Some(name) = self.on_disk.get(dir) {
+            return Ok(name.clone());
+        }
+        let path = Path::join(
+            self.fbcode_root,
+            dir.join_to_path_in_fbcode(CargoTomlPath::filename())
+                .as_ref(),
+        );
+        let content =
+            read_to_string(&path).with_context(|| format!("While reading {}", path.display()))?;
+        let name = Manifest::from_str(&content)
+            .with_context(|| format!("While parsing {}", path.display()))?
+            .package
+            .map(|p| p.name);
+        self.on_disk.insert(dir.clone(), name.clone());
+        Ok(name)
+    }
+}
+
+fn deps_sets(manifest: &Manifest) -> Vec<(String, &DepsSet)> {
+    let mut sets = vec![
+        ("dependencies".to_owned(), &manifest.dependencies),
+        ("dev-dependencies".to_owned(), &manifest.dev_dependencies),
+        (
+            "build-dependencies".to_owned(),
+            &manifest.build_dependencies,
+        ),
+    ];
+    for (target, deps) in &manifest.target {
+        sets.push((format!("target.{target}.dependencies"), &deps.dependencies));
+        sets.push((
+            format!("target.{target}.dev-dependencies"),
+            &deps.dev_dependencies,
+        ));
+        sets.push((
+            format!("target.{target}.build-dependencies"),
+            &deps.build_dependencies,
+        ));
+    }
+    sets
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::from_value;
+    use serde_json::json;
+
+    use super::*;
+
+    fn problems(root: &Path, generated: &[(&str, &str)], on_disk: &[(&str, &str)]) -> Vec<String> {
+        for (dir, content) in on_disk {
+            let dir = root.join(dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(dir.join("Cargo.toml"), content).unwrap();
+        }
+        let mut verifier = Verifier {
+            fbcode_root: root,
+            manifests: generated
+                .iter()
+                .map(|(dir, content)| {
+                    (
+                        from_value(json!(dir)).unwrap(),
+                        Manifest::from_str(content).unwrap(),
+                    )
+                })
+                .collect(),
+            on_disk: HashMap::new(),
+            problems: Vec::new(),
+        };
+        verifier.verify(&from_value(json!("ws")).unwrap());
+        verifier.problems
+    }
+
+    #[test]
+    fn verify_workspace_members_test() {
+        let package = |name: &str| format!("[package]\nname = \"{name}\"\nversion = \"0.1.0\"\n");
+        let workspace = |members: &[&str]| format!("[workspace]\nmembers = {members:?}\n");
+
+        // Members that are generated or exist on disk.
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            problems(
+                dir.path(),
+                &[("ws", &workspace(&["a", "b"])), ("ws/a", &package("a"))],
+                &[("ws/b", &package("b"))],
+            ),
+            Vec::<String>::new(),
+        );
+
+        // Glob members match both generated and existing packages.
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            problems(
+                dir.path(),
+                &[
+                    ("ws", &workspace(&["crates/*"])),
+                    ("ws/crates/a", &package("a")),
+                ],
+                &[("ws/crates/b", &package("b"))],
+            ),
+            Vec::<String>::new(),
+        );
+        let dir = tempfile::tempdir().unwrap();
+        assert_eq!(
+            problems(
+                dir.path(),
+                &[
+                    ("ws", &workspace(&["crates/*"])),
+                    ("ws/crates/a", &package("a")),
+                ],
+                &[("ws/crates/b", &package("a"))],
+            ),
+            vec![
+                "ws/Cargo.toml: package \"a\" is defined by multiple workspace members \
+                [\"crates/a\", \"crates/b\"]"
+                    .to_owned()
+            ],
+        );
+
+        // Members without a Cargo.toml are reported.
+        let dir = tempfile::tempdir().unwrap();
+        let problems = problems(dir.path(), &[("ws", &workspace(&["missing"]))], &[]);
+        assert_eq!(problems.len(), 1);
+        assert!(
+            problems[0].starts_with("ws/Cargo.toml: workspace member \"missing\" is broken:"),
+            "{problems:?}",
+        );
+    }
+}
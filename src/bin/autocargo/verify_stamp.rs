@@ -0,0 +1,104 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::path::Path;
+
+use anyhow::Context;
+use anyhow::Result;
+use autocargo::cargo_generator::Stamp;
+use autocargo::cargo_generator::StampInputs;
+use autocargo::cargo_generator::is_generated_content;
+use autocargo::paths::CargoTomlPath;
+use autocargo::paths::FbcodeRoot;
+use autocargo::run::GenerationSession;
+use regex::Regex;
+use slog::Logger;
+use slog::info;
+use slog::warn;
+use tokio::fs::read_to_string;
+
+/// Compare the reproducibility stamps of the generated Cargo.toml files of the
+/// selected projects with the current ones, logging the files that were
+/// generated by another autocargo build or with older configs, or without a
+/// stamp. Returns true if there were any.
+pub(crate) async fn verify_stamp(
+    logger: &Logger,
+    session: &GenerationSession<'_>,
+    inputs: &StampInputs,
+) -> Result<bool> {
+    let fbcode_root = FbcodeRoot::from(session.fbsource_root.clone());
+    let legacy_preambles = session.all_configs.global().legacy_preambles();
+    let loaded = session.load_projects(session.select_projects()?).await?;
+
+    let mut outdated = 0;
+    for files in &loaded.project_files {
+        let current = inputs.stamp(files.conf())?;
+        for cargo_toml_path in files.cargo() {
+            if check_stamp(
+                logger,
+                &fbcode_root,
+                legacy_preambles,
+                cargo_toml_path,
+                &current,
+            )
+            .await?
+            {
+                outdated += 1;
+            }
+        }
+        info!(
+            logger,
+            "Current stamp of project {} is {}",
+            files.conf().name(),
+            current
+        );
+    }
+
+    info!(
+        logger,
+        "{} generated Cargo.toml files need to be regenerated", outdated
+    );
+    Ok(outdated > 0)
+}
+
+/// Check the stamp of the Cargo.toml file, returns true and logs the reason if
+/// it's generated and outdated.
+async fn check_stamp(
+    logger: &Logger,
+    fbcode_root: &FbcodeRoot,
+    legacy_preambles: &[Regex],
+    cargo_toml_path: &CargoTomlPath,
+    current: &Stamp,
+) -> Result<bool> {
+    let path = Path::join(fbcode_root.as_ref(), cargo_toml_path.as_file().as_ref());
+    let content = read_to_string(&path)
+        .await
+        .with_context(|| format!("While reading {}", path.display()))?;
+    if !is_generated_content(&content, legacy_preambles) {
+        return Ok(false);
+    }
+    match Stamp::from_content(&content) {
+        Some(Ok(stamp)) if stamp == *current => return Ok(false),
+        Some(Ok(stamp)) => warn!(
+            logger,
+            "{} is stamped with {}, expected {}",
+            cargo_toml_path.as_file(),
+            stamp,
+            current
+        ),
+        Some(Err(err)) => warn!(
+            logger,
+            "{} has an invalid stamp: {:#}",
+            cargo_toml_path.as_file(),
+            err
+        ),
+        None => warn!(logger, "{} has no stamp", cargo_toml_path.as_file()),
+    }
+    Ok(true)
+}
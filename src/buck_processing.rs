@@ -12,10 +12,14 @@
 //! processing.
 
 mod commands;
+mod cratemap_cache;
+mod diagnostics;
+mod fixtures;
 mod loader;
 mod manifest;
 mod raw_manifest;
 mod rules;
+mod target_patterns;
 #[cfg(test)]
 mod test_utils;
 
@@ -23,24 +27,39 @@ use std::collections::HashMap;
 use std::collections::HashSet;
 
 use anyhow::Result;
+pub use cratemap_cache::ThriftCratemapCache;
+pub use diagnostics::Diagnostic;
+pub use diagnostics::Diagnostics;
+pub use diagnostics::Severity;
+pub use fixtures::BuckFixtures;
+pub use fixtures::BuckFixturesMode;
+pub use manifest::ArtifactDependencies;
 pub use manifest::BuckDependency;
 pub use manifest::BuckDependencyOverride;
 pub use manifest::BuckManifest;
 pub use manifest::BuckTargetDependencies;
 pub use manifest::CODEGEN_INCLUDER_PROC_MACRO_RULE;
+pub use manifest::CRITERION_CRATE;
+pub use manifest::CXX_BUILD_CRATE;
+pub use manifest::CXX_CRATE;
 pub use manifest::ExtraBuckDependencies;
 pub use manifest::FbconfigRuleType;
 pub use manifest::OsDepsPlatform;
 pub use manifest::THRIFT_COMPILER_RULE;
 pub use manifest::ThriftConfig;
+pub use raw_manifest::AutocargoBuildScript;
 pub use raw_manifest::AutocargoCargoTomlConfig;
+pub use raw_manifest::AutocargoCxx;
 pub use raw_manifest::AutocargoField;
 pub use raw_manifest::AutocargoPackageConfig;
+pub use raw_manifest::AutocargoProtobuf;
 pub use raw_manifest::AutocargoTargetConfig;
 pub use raw_manifest::AutocargoThrift;
 pub use raw_manifest::AutocargoThriftOptions;
+pub use raw_manifest::BinaryProductType;
 pub use raw_manifest::CargoDependencyOverride;
 pub use raw_manifest::DependenciesOverride;
+pub use raw_manifest::RawArtifactDependency;
 pub use raw_manifest::RawBuckManifest;
 pub use raw_manifest::RawBuckManifestDependencies;
 pub use raw_manifest::RawBuckManifestRustConfig;
@@ -48,13 +67,23 @@ pub use raw_manifest::RawBuckManifestSources;
 pub use raw_manifest::RawFbconfigRuleType;
 pub use raw_manifest::RawOsDepsPlatform;
 pub use raw_manifest::TargetDependenciesOverride;
+#[cfg(test)]
+pub(crate) use rules::FbcodeBuckRule;
 use slog::Logger;
+pub use target_patterns::is_target_pattern;
+pub use target_patterns::resolve_target_patterns;
 
 use self::loader::BuckManifestLoader;
-use self::manifest::process_raw_manifests;
+pub(crate) use self::manifest::process_raw_manifests;
+use crate::config::BuckConfig;
+use crate::error::AutocargoError;
+use crate::events::AutocargoEvent;
+use crate::events::EventSender;
+use crate::events::Phase;
 use crate::paths::FbcodeRoot;
 use crate::paths::TargetsPath;
 use crate::util::command_runner::MockableCommandRunner;
+use crate::util::concurrency::ConcurrencyLimit;
 
 /// Result of processing buck's rust manifests from given TARGETS files.
 pub struct ProcessOutput {
@@ -69,6 +98,29 @@ pub struct ProcessOutput {
     /// check if they are covered by any project and that information will be fed
     /// into cargo generator.
     pub unprocessed_paths: HashSet<TargetsPath>,
+    /// Explanations of the rules and attributes of rules that were skipped
+    /// because they are not supported.
+    pub diagnostics: Diagnostics,
+}
+
+/// How to run the Buck commands.
+#[derive(Clone, Copy, Debug)]
+pub struct BuckOptions<'a> {
+    /// Binary and extra arguments of Buck.
+    pub config: &'a BuckConfig,
+    /// Run buck commands in an isolation dir.
+    pub use_isolation_dir: bool,
+    /// Log the progress that Buck prints to stderr instead of letting it print
+    /// directly.
+    pub show_output: bool,
+    /// Record the Buck commands into or replay them from the fixtures instead
+    /// of just running them.
+    pub fixtures: Option<&'a BuckFixtures>,
+    /// Never run Buck, the commands can only be replayed from the fixtures.
+    pub offline: bool,
+    /// Reuse the thrift cratemaps built by previous runs from the cache and
+    /// store the newly built ones into it.
+    pub cratemap_cache: Option<&'a ThriftCratemapCache>,
 }
 
 /// Uses Buck for querying and building of rust manifests contained in provided
@@ -76,19 +128,42 @@ pub struct ProcessOutput {
 /// outside of the provided TARGETS.
 pub async fn process_targets<'a>(
     logger: &'a Logger,
+    events: &'a EventSender,
+    fbcode_root: &'a FbcodeRoot,
+    concurrency: &'a ConcurrencyLimit,
+    buck: BuckOptions<'a>,
+    targets: impl IntoIterator<Item = &'a TargetsPath> + 'a,
+) -> Result<ProcessOutput, AutocargoError> {
+    process_targets_impl(logger, events, fbcode_root, concurrency, buck, targets)
+        .await
+        .map_err(AutocargoError::buck)
+}
+
+async fn process_targets_impl<'a>(
+    logger: &'a Logger,
+    events: &'a EventSender,
     fbcode_root: &'a FbcodeRoot,
-    use_isolation_dir: bool,
+    concurrency: &'a ConcurrencyLimit,
+    buck: BuckOptions<'a>,
     targets: impl IntoIterator<Item = &'a TargetsPath> + 'a,
 ) -> Result<ProcessOutput> {
+    events.emit(AutocargoEvent::PhaseStarted(Phase::ProcessTargets));
     let raw_manifests = BuckManifestLoader::from_targets_paths(
         logger,
         fbcode_root,
-        use_isolation_dir,
+        buck,
         targets,
         MockableCommandRunner::default(),
     )
     .await?
-    .load()
+    .load(concurrency)
     .await?;
-    process_raw_manifests(logger, fbcode_root, use_isolation_dir, raw_manifests).await
+    events.emit(AutocargoEvent::PhaseStarted(Phase::ProcessManifests));
+    let output =
+        process_raw_manifests(logger, fbcode_root, concurrency, buck, raw_manifests).await?;
+    events.emit(AutocargoEvent::TargetsProcessed {
+        manifests: output.processed_manifests.values().flatten().count(),
+        unprocessed_paths: output.unprocessed_paths.len(),
+    });
+    Ok(output)
 }
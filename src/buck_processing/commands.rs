@@ -9,22 +9,30 @@
 
 use std::process::Output;
 use std::process::Stdio;
+use std::time::Duration;
+use std::time::Instant;
 
 use anyhow::Context as _;
 use anyhow::Result;
+use anyhow::bail;
 use futures::future;
+use slog::Logger;
+use slog::info;
+use tokio::io::AsyncBufReadExt as _;
+use tokio::io::AsyncRead;
 use tokio::io::AsyncWriteExt as _;
+use tokio::io::BufReader;
 use tokio::io::BufWriter;
 use tokio::process::Command;
 
+use super::BuckFixturesMode;
+use super::BuckOptions;
 use super::rules::BuckManifestRule;
 use super::rules::FbcodeBuckRule;
 use super::rules::ThriftCratemapRule;
 use crate::paths::FbcodeRoot;
 use crate::paths::TargetsPath;
 
-const BUCK_CMD: &str = "buck2";
-
 const BUCK_ATTRIBUTION_ARGS: &[&str] = &["--oncall=autocargo", "--client-metadata=id=autocargo"];
 
 // For autocargo purposes, the mode file used doesn't matter.
@@ -42,15 +50,20 @@ const BUCK_MODE_ARGS: &[&str] = if cfg!(target_os = "macos") {
 
 const BUCK_ISOLATION_ARGS: &[&str] = &["--isolation-dir=autocargo"];
 
+/// Minimal time between two lines of Buck output that are logged.
+const BUCK_OUTPUT_LOG_INTERVAL: Duration = Duration::from_secs(1);
+
 /// Command for running buck build of *-rust-manifest files.
 pub async fn buck_build_manifests_cmd<'a>(
     fbcode_root: &FbcodeRoot,
-    use_isolation_dir: bool,
+    buck: BuckOptions<'_>,
+    logger: &Logger,
     rules: impl IntoIterator<Item = &'a BuckManifestRule>,
 ) -> Result<(Command, Output)> {
     buck_build_cmd(
         fbcode_root,
-        use_isolation_dir,
+        buck,
+        logger,
         rules.into_iter().map(|rule| rule.as_ref().clone()),
     )
     .await
@@ -59,12 +72,14 @@ pub async fn buck_build_manifests_cmd<'a>(
 /// Command for running buck build of *-rust-dep-map files.
 pub async fn buck_build_cratemaps_cmd<'a>(
     fbcode_root: &FbcodeRoot,
-    use_isolation_dir: bool,
+    buck: BuckOptions<'_>,
+    logger: &Logger,
     rules: impl IntoIterator<Item = &'a ThriftCratemapRule>,
 ) -> Result<(Command, Output)> {
     buck_build_cmd(
         fbcode_root,
-        use_isolation_dir,
+        buck,
+        logger,
         rules.into_iter().map(|rule| rule.fbcode_buck_rule()),
     )
     .await
@@ -80,89 +95,175 @@ pub async fn buck_build_cratemaps_cmd<'a>(
 // https://fb.workplace.com/groups/buck2eng/permalink/3044383762525770/ for
 // details.
 
-async fn buck_build_cmd(
-    fbcode_root: &FbcodeRoot,
-    use_isolation_dir: bool,
-    rules: impl IntoIterator<Item = FbcodeBuckRule>,
-) -> Result<(Command, Output)> {
-    let mut command = Command::new(BUCK_CMD);
+/// Buck command running the given subcommand, to which the caller only needs
+/// to add the subcommand specific args.
+fn buck_command(fbcode_root: &FbcodeRoot, buck: BuckOptions<'_>, subcommand: &str) -> Command {
+    let mut command = Command::new(&buck.config.binary);
     command.stdin(Stdio::piped());
     command.stdout(Stdio::piped());
     command.stderr(Stdio::inherit());
     command.current_dir(fbcode_root);
-    if use_isolation_dir {
+    if buck.use_isolation_dir {
         // See [Note: Why do we pass `--isolation-dir=autocargo` here?]
         command.args(BUCK_ISOLATION_ARGS);
     }
-    command.arg("build");
+    command.args(&buck.config.args);
+    command.arg(subcommand);
     command.args(BUCK_ATTRIBUTION_ARGS);
     command.args(BUCK_MODE_ARGS);
-    command.args(["--show-full-json-output", "@-"]);
-
-    let mut child = command
-        .spawn()
-        .with_context(|| format!("Spawning command: {:?}", command.as_std()))?;
+    command.args(&buck.config.command_args);
+    command
+}
 
-    let mut stdin = BufWriter::new(child.stdin.take().unwrap());
+async fn buck_build_cmd(
+    fbcode_root: &FbcodeRoot,
+    buck: BuckOptions<'_>,
+    logger: &Logger,
+    rules: impl IntoIterator<Item = FbcodeBuckRule>,
+) -> Result<(Command, Output)> {
+    let mut command = buck_command(fbcode_root, buck, "build");
+    command.args(["--show-full-json-output", "@-"]);
 
-    let (_, output) = future::try_join(
-        async move {
-            for rule in rules {
-                let line = format!("{rule}\n");
-                stdin.write_all(line.as_bytes()).await?;
-            }
-            stdin.flush().await
-        },
-        child.wait_with_output(),
+    run_buck_command(
+        command,
+        buck,
+        logger,
+        rules.into_iter().map(|rule| format!("{rule}\n")),
     )
     .await
-    .with_context(|| format!("Executing command: {:?}", command.as_std()))?;
-
-    Ok((command, output))
 }
 
 /// Command for running buck query in search of *-rust-manifest files.
 pub async fn buck_query_manifests_cmd<'a>(
     fbcode_root: &FbcodeRoot,
-    use_isolation_dir: bool,
+    buck: BuckOptions<'_>,
+    logger: &Logger,
     targets_paths: impl IntoIterator<Item = &'a TargetsPath>,
 ) -> Result<(Command, Output)> {
-    let mut command = Command::new(BUCK_CMD);
-    command.stdin(Stdio::piped());
-    command.stdout(Stdio::piped());
-    command.stderr(Stdio::inherit());
-    command.current_dir(fbcode_root);
-    if use_isolation_dir {
-        // See [Note: Why do we pass `--isolation-dir=autocargo` here?]
-        command.args(BUCK_ISOLATION_ARGS);
-    }
-    command.arg("uquery");
-    command.args(BUCK_ATTRIBUTION_ARGS);
-    command.args(BUCK_MODE_ARGS);
+    let mut command = buck_command(fbcode_root, buck, "uquery");
     command.args([
         "--output-format=json",
         "attrfilter('labels', 'rust_manifest', kind('^(genrule|write_file)$', %Ss))",
         "@-",
     ]);
 
+    run_buck_command(
+        command,
+        buck,
+        logger,
+        targets_paths
+            .into_iter()
+            .map(|path| format!("fbcode//{}:\n", path.as_dir().as_ref().display())),
+    )
+    .await
+}
+
+/// Command for running buck query of the build files that define targets
+/// matching the patterns.
+pub async fn buck_query_buildfiles_cmd<'a>(
+    fbcode_root: &FbcodeRoot,
+    buck: BuckOptions<'_>,
+    logger: &Logger,
+    patterns: impl IntoIterator<Item = &'a str>,
+) -> Result<(Command, Output)> {
+    let mut command = buck_command(fbcode_root, buck, "uquery");
+    command.args(["--output-format=json", "buildfile(%Ss)", "@-"]);
+
+    run_buck_command(
+        command,
+        buck,
+        logger,
+        patterns.into_iter().map(|pattern| format!("{pattern}\n")),
+    )
+    .await
+}
+
+/// Run the Buck command with the lines written to its stdin, through the
+/// fixtures if there are any.
+async fn run_buck_command(
+    command: Command,
+    buck: BuckOptions<'_>,
+    logger: &Logger,
+    lines: impl IntoIterator<Item = String>,
+) -> Result<(Command, Output)> {
+    let output_logger = buck.show_output.then_some(logger);
+    match buck.fixtures {
+        Some(fixtures) if !buck.offline || fixtures.mode() == BuckFixturesMode::Replay => {
+            fixtures
+                .run(command, output_logger, lines.into_iter().collect())
+                .await
+        }
+        _ if buck.offline => bail!(
+            "Buck can't be run in offline mode, replay its commands with --replay-buck from a \
+            fixture recorded by --record-buck instead: {:?}",
+            command.as_std()
+        ),
+        _ => run_with_stdin_lines(command, output_logger, lines).await,
+    }
+}
+
+/// Spawn the command writing the lines to its stdin. If output_logger is given
+/// then stderr of the command is logged as it comes instead of being printed
+/// directly, so that users can tell a slow Buck build from a hung one.
+pub(super) async fn run_with_stdin_lines(
+    mut command: Command,
+    output_logger: Option<&Logger>,
+    lines: impl IntoIterator<Item = String>,
+) -> Result<(Command, Output)> {
+    if output_logger.is_some() {
+        command.stderr(Stdio::piped());
+    }
     let mut child = command
         .spawn()
         .with_context(|| format!("Spawning command: {:?}", command.as_std()))?;
 
     let mut stdin = BufWriter::new(child.stdin.take().unwrap());
+    let stderr = child.stderr.take();
 
-    let (_, output) = future::try_join(
+    let (_, mut output, streamed_stderr) = future::try_join3(
         async move {
-            for path in targets_paths {
-                let line = format!("fbcode//{}:\n", path.as_dir().as_ref().display());
+            for line in lines {
                 stdin.write_all(line.as_bytes()).await?;
             }
             stdin.flush().await
         },
         child.wait_with_output(),
+        async move {
+            match (output_logger, stderr) {
+                (Some(logger), Some(stderr)) => log_output_lines(logger, stderr).await,
+                _ => Ok(Vec::new()),
+            }
+        },
     )
     .await
     .with_context(|| format!("Executing command: {:?}", command.as_std()))?;
+    // Keep the stderr in the output for reporting failures.
+    if output_logger.is_some() {
+        output.stderr = streamed_stderr;
+    }
 
     Ok((command, output))
 }
+
+/// Log the lines of output at info level, at most one per
+/// [BUCK_OUTPUT_LOG_INTERVAL] to not flood the console, and return all of it.
+async fn log_output_lines(
+    logger: &Logger,
+    output: impl AsyncRead + Unpin,
+) -> std::io::Result<Vec<u8>> {
+    let mut lines = BufReader::new(output).split(b'\n');
+    let mut all_output = Vec::new();
+    let mut last_logged: Option<Instant> = None;
+    while let Some(line) = lines.next_segment().await? {
+        if last_logged.is_none_or(|instant| instant.elapsed() >= BUCK_OUTPUT_LOG_INTERVAL) {
+            let line = String::from_utf8_lossy(&line);
+            if !line.trim().is_empty() {
+                info!(logger, "buck: {}", line.trim_end());
+                last_logged = Some(Instant::now());
+            }
+        }
+        all_output.extend(line);
+        all_output.push(b'\n');
+    }
+    Ok(all_output)
+}
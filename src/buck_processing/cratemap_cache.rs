@@ -0,0 +1,326 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! On-disk cache of the thrift cratemaps built by Buck, so that the cratemaps
+//! of unchanged thrift rules are not built again on the next run.
+//!
+//! A cratemap maps the thrift files included by the rule, transitively, to the
+//! crates of their thrift rules. So it is keyed by the rule, its thrift config,
+//! the content of its thrift sources and its dependencies together with the
+//! keys of the ones that are thrift rules too, recursively, and the version of
+//! autocargo. Thrift dependencies whose manifests were not loaded are only
+//! identified by their rule, so the content of the thrift files listed in a
+//! cached cratemap is checked too before it is reused.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+use futures::StreamExt;
+use futures::future;
+use futures::future::join_all;
+use futures::stream::FuturesUnordered;
+use itertools::Itertools;
+use serde::Deserialize;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+use tokio::fs::create_dir_all;
+use tokio::fs::read;
+use tokio::fs::write;
+
+use super::manifest::UnprocessedBuckDependency;
+use super::raw_manifest::RawBuckManifest;
+use super::rules::FbcodeBuckRule;
+use crate::paths::FbcodeRoot;
+
+/// Version of the format of the cached cratemaps, bump it when changing how
+/// they are keyed or written.
+const CACHE_FORMAT_VERSION: u32 = 2;
+
+/// Directory with the cached cratemaps, one file per key.
+#[derive(Debug)]
+pub struct ThriftCratemapCache {
+    dir: PathBuf,
+}
+
+/// Content of a file of the cache.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct CachedCratemap {
+    cratemap: String,
+    /// Hashes of the content of the thrift files listed in the cratemap keyed
+    /// by their paths, None for the files that can't be read.
+    files: BTreeMap<String, Option<String>>,
+}
+
+impl ThriftCratemapCache {
+    /// Cache the cratemaps in the dir, which is created if missing.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Keys of the cratemaps of the thrift rules among the manifests, rules
+    /// whose thrift sources, or the ones of their thrift dependencies, can't
+    /// be read have none.
+    pub(super) async fn keys(
+        fbcode_root: &FbcodeRoot,
+        manifests: &HashMap<&FbcodeBuckRule, &RawBuckManifest>,
+    ) -> HashMap<FbcodeBuckRule, String> {
+        let sources: HashMap<&FbcodeBuckRule, String> = manifests
+            .iter()
+            .filter_map(|(rule, raw)| Some((*rule, raw.autocargo.thrift.as_ref()?)))
+            .map(|(rule, thrift)| async move {
+                let hashes = thrift.thrift_srcs.keys().sorted().map(|src| async move {
+                    let path = rule.path.as_dir().join_to_path_in_fbcode(src);
+                    let hash = hash_file(fbcode_root, path.as_ref()).await?;
+                    Some(format!("{src}={hash}"))
+                });
+                let hashes: Option<Vec<_>> = join_all(hashes).await.into_iter().collect();
+                Some((rule, hashes?.join(",")))
+            })
+            .collect::<FuturesUnordered<_>>()
+            .filter_map(future::ready)
+            .collect()
+            .await;
+
+        let mut keys = HashMap::new();
+        for rule in sources.keys() {
+            memoized_key(rule, manifests, &sources, &mut keys);
+        }
+        keys.into_iter()
+            .filter_map(|(rule, key)| Some((rule.clone(), key?)))
+            .collect()
+    }
+
+    /// The cached cratemap, None if there is none, it can't be read or the
+    /// content of the thrift files it lists changed.
+    pub(super) async fn get(&self, fbcode_root: &FbcodeRoot, key: &str) -> Option<String> {
+        let content = read(self.dir.join(key)).await.ok()?;
+        let cached: CachedCratemap = serde_json::from_slice(&content).ok()?;
+        (listed_file_hashes(fbcode_root, &cached.cratemap).await == cached.files)
+            .then_some(cached.cratemap)
+    }
+
+    /// Store the cratemap under the key.
+    pub(super) async fn put(
+        &self,
+        fbcode_root: &FbcodeRoot,
+        key: &str,
+        cratemap: &str,
+    ) -> Result<()> {
+        create_dir_all(&self.dir)
+            .await
+            .with_context(|| format!("While creating dir {}", self.dir.display()))?;
+        let cached = CachedCratemap {
+            cratemap: cratemap.to_owned(),
+            files: listed_file_hashes(fbcode_root, cratemap).await,
+        };
+        let path = self.dir.join(key);
+        write(&path, serde_json::to_vec(&cached)?)
+            .await
+            .with_context(|| format!("While writing cached cratemap {}", path.display()))
+    }
+}
+
+/// Key of the thrift rule, see [key], memoized in keys.
+fn memoized_key<'a>(
+    rule: &'a FbcodeBuckRule,
+    manifests: &HashMap<&'a FbcodeBuckRule, &'a RawBuckManifest>,
+    sources: &HashMap<&FbcodeBuckRule, String>,
+    keys: &mut HashMap<&'a FbcodeBuckRule, Option<String>>,
+) -> Option<String> {
+    if let Some(key) = keys.get(rule) {
+        return key.clone();
+    }
+    let key = key(rule, manifests, sources, keys);
+    keys.insert(rule, key.clone());
+    key
+}
+
+/// Key of the thrift rule made of its config, the hashes of its sources and
+/// its dependencies, with the keys of the thrift ones among the manifests.
+fn key<'a>(
+    rule: &'a FbcodeBuckRule,
+    manifests: &HashMap<&'a FbcodeBuckRule, &'a RawBuckManifest>,
+    sources: &HashMap<&FbcodeBuckRule, String>,
+    keys: &mut HashMap<&'a FbcodeBuckRule, Option<String>>,
+) -> Option<String> {
+    let raw = manifests.get(rule)?;
+    let thrift = raw.autocargo.thrift.as_ref()?;
+    let deps = &raw.dependencies;
+
+    let mut thrift_dep_keys = Vec::new();
+    for dep in deps.deps.iter().chain(deps.named_deps.values()) {
+        let dep_rule = UnprocessedBuckDependency::try_from_rule(&rule.path, dep);
+        let Some((dep_rule, dep_raw)) = dep_rule
+            .as_ref()
+            .and_then(UnprocessedBuckDependency::fbcode_crate)
+            .and_then(|dep_rule| manifests.get_key_value(dep_rule))
+        else {
+            continue;
+        };
+        if dep_raw.autocargo.thrift.is_some() {
+            let dep_key = memoized_key(dep_rule, manifests, sources, keys)?;
+            thrift_dep_keys.push(format!("{dep_rule}={dep_key}"));
+        }
+    }
+
+    let inputs = [
+        env!("CARGO_PKG_VERSION").to_owned(),
+        CACHE_FORMAT_VERSION.to_string(),
+        rule.to_string(),
+        thrift.base_path.clone(),
+        thrift.unsuffixed_name.clone(),
+        thrift.options.types_crate.clone(),
+        sources.get(rule)?.clone(),
+        deps.deps
+            .iter()
+            .sorted()
+            .map(|dep| format!("{dep:?}"))
+            .join(","),
+        deps.named_deps
+            .iter()
+            .sorted()
+            .map(|(alias, dep)| format!("{alias}={dep:?}"))
+            .join(","),
+        thrift_dep_keys.into_iter().sorted().join(","),
+    ];
+    Some(format!("{:x}", Sha256::digest(inputs.join("\n"))))
+}
+
+/// Hashes of the content of the thrift files listed in the cratemap, whose
+/// lines are made of the path of a thrift file and the name of its crate.
+async fn listed_file_hashes(
+    fbcode_root: &FbcodeRoot,
+    cratemap: &str,
+) -> BTreeMap<String, Option<String>> {
+    cratemap
+        .lines()
+        .filter_map(|line| line.split_whitespace().next())
+        .map(|path| async move { (path.to_owned(), hash_file(fbcode_root, path).await) })
+        .collect::<FuturesUnordered<_>>()
+        .collect()
+        .await
+}
+
+/// Hash of the content of the file at path relative to fbcode, None if it
+/// can't be read.
+async fn hash_file(fbcode_root: &FbcodeRoot, path: impl AsRef<Path>) -> Option<String> {
+    let content = read(Path::join(fbcode_root.as_ref(), path)).await.ok()?;
+    Some(format!("{:x}", Sha256::digest(content)))
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::from_value;
+    use serde_json::json;
+
+    use super::*;
+    use crate::paths::PathInFbcode;
+    use crate::paths::TargetsPath;
+
+    #[tokio::test]
+    async fn thrift_cratemap_cache_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let fbcode_root = FbcodeRoot::new_mock(dir.path());
+        let cache = ThriftCratemapCache::new(dir.path().join("cratemaps"));
+        let rule = |path: &str, name: &str| FbcodeBuckRule {
+            path: TargetsPath::new(PathInFbcode::new_mock(format!("{path}/TARGETS"))).unwrap(),
+            name: name.to_owned(),
+        };
+        let raw = |name: &str, deps: &[&str]| RawBuckManifest {
+            name: format!("{name}-rust"),
+            autocargo: from_value(json!({
+                "thrift": {
+                    "base_path": name,
+                    "gen_context": "types",
+                    "options": {
+                        "cratemap": "unused",
+                        "types_crate": format!("{name}__types"),
+                    },
+                    "thrift_srcs": {format!("{name}.thrift"): []},
+                    "unsuffixed_name": name,
+                },
+            }))
+            .unwrap(),
+            dependencies: from_value(json!({
+                "deps": deps,
+                "named_deps": {},
+                "os_deps": [],
+                "tests": [],
+                "test_deps": [],
+                "test_named_deps": {},
+                "test_os_deps": [],
+            }))
+            .unwrap(),
+            ..RawBuckManifest::empty_test()
+        };
+        let write_file = |path: &str, content: &str| {
+            std::fs::create_dir_all(dir.path().join(path).parent().unwrap()).unwrap();
+            std::fs::write(dir.path().join(path), content).unwrap();
+        };
+        write_file("foo/foo.thrift", "include \"bar/bar.thrift\"\n");
+        write_file("bar/bar.thrift", "struct Bar {}\n");
+
+        let (foo, bar, lib) = (
+            rule("foo", "foo-rust"),
+            rule("bar", "bar-rust"),
+            rule("lib", "lib"),
+        );
+        let foo_raw = raw("foo", &["//bar:bar-rust", "//lib:lib"]);
+        let bar_raw = raw("bar", &[]);
+        let lib_raw = RawBuckManifest::empty_test();
+        let manifests = HashMap::from([(&foo, &foo_raw), (&bar, &bar_raw), (&lib, &lib_raw)]);
+        let keys = ThriftCratemapCache::keys(&fbcode_root, &manifests).await;
+        assert_eq!(keys.keys().sorted().collect::<Vec<_>>(), vec![&bar, &foo]);
+
+        // Changing the content of the sources of the rule or of its thrift
+        // dependencies changes the key.
+        write_file(
+            "foo/foo.thrift",
+            "include \"bar/bar.thrift\"\nstruct Foo {}\n",
+        );
+        let changed_source = ThriftCratemapCache::keys(&fbcode_root, &manifests).await;
+        assert_ne!(changed_source[&foo], keys[&foo]);
+        assert_eq!(changed_source[&bar], keys[&bar]);
+        write_file("bar/bar.thrift", "struct Bar { 1: i32 x }\n");
+        let changed_dep = ThriftCratemapCache::keys(&fbcode_root, &manifests).await;
+        assert_ne!(changed_dep[&foo], changed_source[&foo]);
+        assert_ne!(changed_dep[&bar], changed_source[&bar]);
+
+        // Rules whose sources can't be read are not cached, and neither are
+        // the ones depending on them.
+        std::fs::remove_file(dir.path().join("bar/bar.thrift")).unwrap();
+        assert!(
+            ThriftCratemapCache::keys(&fbcode_root, &manifests)
+                .await
+                .is_empty()
+        );
+        write_file("bar/bar.thrift", "struct Bar { 1: i32 x }\n");
+
+        let key = &changed_dep[&foo];
+        assert_eq!(cache.get(&fbcode_root, key).await, None);
+        let cratemap = "foo/foo.thrift foo\nbar/bar.thrift bar\n";
+        cache.put(&fbcode_root, key, cratemap).await.unwrap();
+        assert_eq!(
+            cache.get(&fbcode_root, key).await.as_deref(),
+            Some(cratemap)
+        );
+        assert_eq!(cache.get(&fbcode_root, &changed_dep[&bar]).await, None);
+
+        // The cached cratemap is not reused when a thrift file it lists
+        // changed, even if the key is the same.
+        write_file("bar/bar.thrift", "include \"baz/baz.thrift\"\n");
+        assert_eq!(cache.get(&fbcode_root, key).await, None);
+    }
+}
@@ -0,0 +1,107 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Diagnostics explaining why rules or parts of them were skipped while
+//! processing the Buck manifests, so that users learn why a rule produced no
+//! Cargo.toml or is missing some dependencies in it.
+
+use serde::Serialize;
+
+use super::rules::FbcodeBuckRule;
+
+/// How much of a rule a [Diagnostic] skips.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    /// The rule is generated, but some of its attributes are left out.
+    Info,
+    /// Nothing is generated for the rule.
+    Warning,
+}
+
+/// Explanation of something skipped while processing a rule.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct Diagnostic {
+    /// The rule the diagnostic is about.
+    pub rule: String,
+    /// Why something was skipped.
+    pub reason: String,
+    /// How much of the rule was skipped.
+    pub severity: Severity,
+}
+
+/// Collection of [Diagnostic] of a processing run.
+#[derive(Debug, Default)]
+pub struct Diagnostics(Vec<Diagnostic>);
+
+impl Diagnostics {
+    /// Record a diagnostic about the rule.
+    pub(crate) fn push(
+        &mut self,
+        rule: &FbcodeBuckRule,
+        severity: Severity,
+        reason: impl Into<String>,
+    ) {
+        self.0.push(Diagnostic {
+            rule: rule.to_string(),
+            reason: reason.into(),
+            severity,
+        });
+    }
+
+    /// The collected diagnostics sorted by rule, without duplicates that come
+    /// from attributes repeated within a rule.
+    pub fn sorted(&self) -> Vec<&Diagnostic> {
+        let mut sorted: Vec<_> = self.0.iter().collect();
+        sorted.sort();
+        sorted.dedup();
+        sorted
+    }
+
+    /// True if nothing was skipped.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::paths::PathInFbcode;
+    use crate::paths::TargetsPath;
+
+    #[test]
+    fn diagnostics_sorted_test() {
+        let rule = |name: &str| FbcodeBuckRule {
+            path: TargetsPath::new(PathInFbcode::new_mock("foo/TARGETS")).unwrap(),
+            name: name.to_owned(),
+        };
+        let mut diagnostics = Diagnostics::default();
+        assert!(diagnostics.is_empty());
+        diagnostics.push(&rule("b"), Severity::Info, "os_deps");
+        diagnostics.push(&rule("a"), Severity::Warning, "rule type");
+        diagnostics.push(&rule("b"), Severity::Info, "os_deps");
+
+        assert_eq!(
+            diagnostics.sorted(),
+            vec![
+                &Diagnostic {
+                    rule: "fbcode//foo:a".to_owned(),
+                    reason: "rule type".to_owned(),
+                    severity: Severity::Warning,
+                },
+                &Diagnostic {
+                    rule: "fbcode//foo:b".to_owned(),
+                    reason: "os_deps".to_owned(),
+                    severity: Severity::Info,
+                },
+            ]
+        );
+    }
+}
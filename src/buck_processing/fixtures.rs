@@ -0,0 +1,348 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Recording of the Buck commands run by autocargo into a JSON fixture and
+//! replaying them from it instead of running Buck, so that a run can be
+//! reproduced hermetically, e.g. in tests or when investigating a bug offline.
+//!
+//! Besides stdout and stderr of each command the fixture holds the content of
+//! the files that the output of build commands points to, i.e. the built rust
+//! manifests and thrift cratemaps. On replay they are written to a temporary
+//! dir and the output is changed to point there.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process::ExitStatus;
+use std::process::Output;
+
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::anyhow;
+use serde::Deserialize;
+use serde::Serialize;
+use slog::Logger;
+use tokio::fs::create_dir_all;
+use tokio::fs::write;
+use tokio::process::Command;
+use tokio::sync::Mutex;
+
+use super::commands::run_with_stdin_lines;
+
+/// Whether [BuckFixtures] run Buck and record the commands or replay them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BuckFixturesMode {
+    /// Run the commands and record them together with their outputs.
+    Record,
+    /// Return the recorded outputs instead of running the commands.
+    Replay,
+}
+
+/// Buck commands with their outputs, recorded into or replayed from a JSON
+/// file.
+#[derive(Debug)]
+pub struct BuckFixtures {
+    mode: BuckFixturesMode,
+    path: PathBuf,
+    /// The recorded commands and whether each was already replayed.
+    commands: Mutex<Vec<(RecordedCommand, bool)>>,
+}
+
+/// Content of the fixture file.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct FixtureFile {
+    commands: Vec<RecordedCommand>,
+}
+
+/// A single Buck command and its output.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct RecordedCommand {
+    /// Arguments of the command, without the Buck binary.
+    args: Vec<String>,
+    /// Sorted lines written to stdin of the command.
+    stdin: Vec<String>,
+    /// Exit code of the command.
+    status: i32,
+    stdout: String,
+    stderr: String,
+    /// Content of the files that stdout points to, keyed by their paths in
+    /// stdout.
+    #[serde(default)]
+    outputs: BTreeMap<String, String>,
+}
+
+impl BuckFixtures {
+    /// Run the Buck commands and record them into the file at path, which is
+    /// rewritten after each command so that failing runs are recorded too.
+    pub fn record(path: impl Into<PathBuf>) -> Self {
+        Self {
+            mode: BuckFixturesMode::Record,
+            path: path.into(),
+            commands: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Replay the Buck commands recorded in the file at path. A command is
+    /// matched by its arguments and stdin lines, so the replayed run must use
+    /// the same Buck config and inputs as the recorded one.
+    pub fn replay(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let content = std::fs::read(&path)
+            .with_context(|| format!("While reading Buck fixture {}", path.display()))?;
+        let fixture: FixtureFile = serde_json::from_slice(&content)
+            .with_context(|| format!("While parsing Buck fixture {}", path.display()))?;
+        Ok(Self {
+            mode: BuckFixturesMode::Replay,
+            path,
+            commands: Mutex::new(
+                fixture
+                    .commands
+                    .into_iter()
+                    .map(|command| (command, false))
+                    .collect(),
+            ),
+        })
+    }
+
+    /// Whether the fixtures record or replay the commands.
+    pub fn mode(&self) -> BuckFixturesMode {
+        self.mode
+    }
+
+    /// Path of the fixture file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Run the command with the lines written to its stdin and record it, or
+    /// return its recorded output.
+    pub(super) async fn run(
+        &self,
+        command: Command,
+        output_logger: Option<&Logger>,
+        lines: Vec<String>,
+    ) -> Result<(Command, Output)> {
+        let args: Vec<String> = command
+            .as_std()
+            .get_args()
+            .map(|arg| arg.to_string_lossy().into_owned())
+            .collect();
+        let mut stdin: Vec<String> = lines
+            .iter()
+            .map(|line| line.trim_end().to_owned())
+            .collect();
+        stdin.sort();
+
+        match self.mode {
+            BuckFixturesMode::Record => {
+                let (command, output) = run_with_stdin_lines(command, output_logger, lines).await?;
+                let recorded = RecordedCommand {
+                    args,
+                    stdin,
+                    status: output.status.code().unwrap_or(1),
+                    stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+                    stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+                    outputs: read_outputs(&output.stdout)?,
+                };
+                let mut commands = self.commands.lock().await;
+                commands.push((recorded, false));
+                let fixture = FixtureFile {
+                    commands: commands
+                        .iter()
+                        .map(|(command, _)| command.clone())
+                        .collect(),
+                };
+                write(&self.path, serde_json::to_vec_pretty(&fixture)?)
+                    .await
+                    .with_context(|| {
+                        format!("While writing Buck fixture {}", self.path.display())
+                    })?;
+                Ok((command, output))
+            }
+            BuckFixturesMode::Replay => {
+                let mut commands = self.commands.lock().await;
+                let matching = |(recorded, _): &(RecordedCommand, bool)| {
+                    recorded.args == args && recorded.stdin == stdin
+                };
+                // Prefer commands that were not replayed yet, so that repeated
+                // commands get their outputs in the recorded order.
+                let index = commands
+                    .iter()
+                    .position(|entry| !entry.1 && matching(entry))
+                    .or_else(|| commands.iter().position(matching))
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "Buck fixture {} has no command with args {:?} and stdin {:?}",
+                            self.path.display(),
+                            args,
+                            stdin,
+                        )
+                    })?;
+                commands[index].1 = true;
+                let recorded = &commands[index].0;
+                let stdout = write_outputs(index, recorded).await?;
+                Ok((
+                    command,
+                    Output {
+                        status: exit_status(recorded.status),
+                        stdout,
+                        stderr: recorded.stderr.clone().into_bytes(),
+                    },
+                ))
+            }
+        }
+    }
+}
+
+/// Read the files that stdout points to if it is a JSON object of paths, like
+/// the output of buck build with --show-full-json-output.
+fn read_outputs(stdout: &[u8]) -> Result<BTreeMap<String, String>> {
+    let Ok(paths) = serde_json::from_slice::<BTreeMap<String, String>>(stdout) else {
+        return Ok(BTreeMap::new());
+    };
+    paths
+        .into_values()
+        .filter(|path| Path::new(path).is_file())
+        .map(|path| {
+            let content = std::fs::read_to_string(&path)
+                .with_context(|| format!("While reading output file {path}"))?;
+            Ok((path, content))
+        })
+        .collect()
+}
+
+/// Write the recorded output files into a temporary dir and return stdout of
+/// the command pointing to them.
+async fn write_outputs(index: usize, recorded: &RecordedCommand) -> Result<Vec<u8>> {
+    if recorded.outputs.is_empty() {
+        return Ok(recorded.stdout.clone().into_bytes());
+    }
+    let dir = std::env::temp_dir()
+        .join(format!("autocargo-buck-fixtures-{}", std::process::id()))
+        .join(index.to_string());
+    create_dir_all(&dir)
+        .await
+        .with_context(|| format!("While creating dir {}", dir.display()))?;
+
+    let mut paths: BTreeMap<String, String> = serde_json::from_str(&recorded.stdout)
+        .context("While parsing recorded stdout of a Buck command")?;
+    for (number, (original, content)) in recorded.outputs.iter().enumerate() {
+        let filename = Path::new(original)
+            .file_name()
+            .map_or_else(|| "output".into(), |name| name.to_string_lossy());
+        let path = dir.join(format!("{number}-{filename}"));
+        write(&path, content)
+            .await
+            .with_context(|| format!("While writing {}", path.display()))?;
+        let path = path.to_string_lossy().into_owned();
+        for value in paths.values_mut().filter(|value| *value == original) {
+            *value = path.clone();
+        }
+    }
+    Ok(serde_json::to_vec(&paths)?)
+}
+
+#[cfg(unix)]
+fn exit_status(code: i32) -> ExitStatus {
+    use std::os::unix::process::ExitStatusExt;
+    ExitStatus::from_raw(code << 8)
+}
+
+#[cfg(windows)]
+fn exit_status(code: i32) -> ExitStatus {
+    use std::os::windows::process::ExitStatusExt;
+    ExitStatus::from_raw(code as u32)
+}
+
+#[cfg(test)]
+mod test {
+    use slog::o;
+
+    use super::*;
+    use crate::buck_processing::BuckOptions;
+    use crate::buck_processing::commands::buck_query_buildfiles_cmd;
+    use crate::config::BuckConfig;
+    use crate::paths::FbcodeRoot;
+
+    #[tokio::test]
+    async fn record_and_replay_test() {
+        let logger = Logger::root(slog::Discard, o!());
+        let dir = tempfile::tempdir().unwrap();
+        let fbcode_root = FbcodeRoot::new_mock(dir.path());
+        let fixture_path = dir.path().join("fixture.json");
+        let output_path = dir.path().join("manifest.json");
+        std::fs::write(&output_path, r#"{"name": "bar"}"#).unwrap();
+
+        let buck_config = BuckConfig {
+            binary: PathBuf::from("sh"),
+            args: vec![
+                "-c".to_owned(),
+                format!(
+                    r#"cat > /dev/null; printf '{{"fbcode//foo:bar": "%s"}}' {}"#,
+                    output_path.display()
+                ),
+            ],
+            command_args: Vec::new(),
+        };
+        let recorder = BuckFixtures::record(&fixture_path);
+        let buck = BuckOptions {
+            config: &buck_config,
+            use_isolation_dir: false,
+            show_output: false,
+            fixtures: Some(&recorder),
+            offline: false,
+            cratemap_cache: None,
+        };
+        let (_, recorded) =
+            buck_query_buildfiles_cmd(&fbcode_root, buck, &logger, ["//foo/...", "//bar:"])
+                .await
+                .unwrap();
+        assert!(recorded.status.success());
+        std::fs::remove_file(&output_path).unwrap();
+
+        let replayer = BuckFixtures::replay(&fixture_path).unwrap();
+        assert_eq!(replayer.mode(), BuckFixturesMode::Replay);
+        let buck = BuckOptions {
+            fixtures: Some(&replayer),
+            offline: true,
+            ..buck
+        };
+        // The order of stdin lines doesn't matter.
+        let (_, replayed) =
+            buck_query_buildfiles_cmd(&fbcode_root, buck, &logger, ["//bar:", "//foo/..."])
+                .await
+                .unwrap();
+        assert!(replayed.status.success());
+        let paths: BTreeMap<String, PathBuf> = serde_json::from_slice(&replayed.stdout).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&paths["fbcode//foo:bar"]).unwrap(),
+            r#"{"name": "bar"}"#
+        );
+
+        assert!(
+            buck_query_buildfiles_cmd(&fbcode_root, buck, &logger, ["//biz:"])
+                .await
+                .is_err()
+        );
+
+        // In offline mode Buck is never run, even when recording.
+        for fixtures in [None, Some(&recorder)] {
+            let buck = BuckOptions { fixtures, ..buck };
+            let err = buck_query_buildfiles_cmd(&fbcode_root, buck, &logger, ["//foo/..."])
+                .await
+                .unwrap_err();
+            assert!(
+                err.to_string()
+                    .starts_with("Buck can't be run in offline mode")
+            );
+        }
+    }
+}
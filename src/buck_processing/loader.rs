@@ -17,7 +17,9 @@ use anyhow::Context;
 use anyhow::Result;
 use anyhow::ensure;
 use futures::FutureExt;
+use futures::StreamExt;
 use futures::TryStreamExt;
+use futures::future;
 use futures::future::LocalBoxFuture;
 use futures::stream::FuturesUnordered;
 use itertools::multipeek;
@@ -27,9 +29,11 @@ use thrift_compiler::GenContext;
 use tokio::fs::read;
 use tokio::fs::read_to_string;
 
+use super::BuckOptions;
 use super::commands::buck_build_cratemaps_cmd;
 use super::commands::buck_build_manifests_cmd;
 use super::commands::buck_query_manifests_cmd;
+use super::cratemap_cache::ThriftCratemapCache;
 use super::raw_manifest::AutocargoThrift;
 use super::raw_manifest::RawBuckManifest;
 use super::rules::BuckManifestRule;
@@ -38,13 +42,14 @@ use super::rules::ThriftCratemapRule;
 use crate::paths::FbcodeRoot;
 use crate::paths::TargetsPath;
 use crate::util::command_runner::MockableCommandRunner;
+use crate::util::concurrency::ConcurrencyLimit;
 
 /// Structure responsible for querying, building and parsing rust manifests using
 /// buck.
 pub struct BuckManifestLoader<'input> {
     logger: &'input Logger,
     fbcode_root: &'input FbcodeRoot,
-    use_isolation_dir: bool,
+    buck: BuckOptions<'input>,
     rules: Vec<BuckManifestRule>,
     cmd_runner: MockableCommandRunner,
 }
@@ -55,7 +60,7 @@ impl<'input> BuckManifestLoader<'input> {
     pub fn from_targets_paths<'fut>(
         logger: &'input Logger,
         fbcode_root: &'input FbcodeRoot,
-        use_isolation_dir: bool,
+        buck: BuckOptions<'input>,
         targets: impl IntoIterator<Item = &'fut TargetsPath> + 'fut,
         cmd_runner: MockableCommandRunner,
     ) -> LocalBoxFuture<'fut, Result<Self>>
@@ -69,7 +74,7 @@ impl<'input> BuckManifestLoader<'input> {
                 return Ok(Self {
                     logger,
                     fbcode_root,
-                    use_isolation_dir,
+                    buck,
                     rules: Vec::new(),
                     cmd_runner,
                 });
@@ -80,7 +85,7 @@ impl<'input> BuckManifestLoader<'input> {
                     logger,
                     dbg_name,
                     Duration::from_secs(5),
-                    buck_query_manifests_cmd(fbcode_root, use_isolation_dir, targets).boxed_local(),
+                    buck_query_manifests_cmd(fbcode_root, buck, logger, targets).boxed_local(),
                 )
                 .await?;
 
@@ -92,7 +97,7 @@ impl<'input> BuckManifestLoader<'input> {
             Ok(Self {
                 logger,
                 fbcode_root,
-                use_isolation_dir,
+                buck,
                 rules,
                 cmd_runner,
             })
@@ -110,7 +115,7 @@ impl<'input> BuckManifestLoader<'input> {
     pub fn from_rust_buck_rules<'fut>(
         logger: &'input Logger,
         fbcode_root: &'input FbcodeRoot,
-        use_isolation_dir: bool,
+        buck: BuckOptions<'input>,
         input_rules: impl IntoIterator<Item = &'fut FbcodeBuckRule>,
         cmd_runner: MockableCommandRunner,
     ) -> LocalBoxFuture<'fut, Result<Self>>
@@ -128,14 +133,8 @@ impl<'input> BuckManifestLoader<'input> {
                 .map(|rule| &rule.as_ref().path)
                 .collect::<HashSet<_>>();
 
-            let mut loader = Self::from_targets_paths(
-                logger,
-                fbcode_root,
-                use_isolation_dir,
-                targets,
-                cmd_runner,
-            )
-            .await?;
+            let mut loader =
+                Self::from_targets_paths(logger, fbcode_root, buck, targets, cmd_runner).await?;
             loader.rules.retain(|rule| input_rules.contains(rule));
             Ok(loader)
         }
@@ -144,14 +143,24 @@ impl<'input> BuckManifestLoader<'input> {
 
     /// Builds rust manifests using buck (they are already validated to exist
     /// thanks to buck query call when creating this structure) and then reads
-    /// and parses the results into RawBuckManifest.
-    pub async fn load(self) -> Result<HashMap<FbcodeBuckRule, RawBuckManifest>> {
+    /// and parses the results into RawBuckManifest. Reading of the files is
+    /// limited by concurrency.
+    ///
+    /// The parsed manifests are not cached between runs: parsing a manifest
+    /// takes ~13µs, so even 10k of them take ~130ms next to the seconds spent
+    /// in Buck, and decoding them from a self-describing binary format, which
+    /// the flatten and untagged attributes of RawBuckManifest require, is
+    /// barely faster.
+    pub async fn load(
+        self,
+        concurrency: &ConcurrencyLimit,
+    ) -> Result<HashMap<FbcodeBuckRule, RawBuckManifest>> {
         self.build()
             .await?
             .into_iter()
             .map(|(rule, out_path)| async move {
                 let try_parsed: Result<RawBuckManifest> =
-                    try { from_slice(&read(&out_path).await?)? };
+                    try { from_slice(&concurrency.run(read(&out_path)).await?)? };
                 let parsed = try_parsed
                     .with_context(|| format!("While reading file {}", out_path.display()))?;
                 let rule = FbcodeBuckRule::try_from(rule)
@@ -173,7 +182,7 @@ impl<'input> BuckManifestLoader<'input> {
         let Self {
             logger,
             fbcode_root,
-            use_isolation_dir,
+            buck,
             rules,
             cmd_runner,
         } = self;
@@ -188,7 +197,7 @@ impl<'input> BuckManifestLoader<'input> {
                 logger,
                 dbg_name,
                 Duration::from_secs(5),
-                buck_build_manifests_cmd(fbcode_root, use_isolation_dir, &rules).boxed_local(),
+                buck_build_manifests_cmd(fbcode_root, buck, logger, &rules).boxed_local(),
             )
             .await?;
 
@@ -203,8 +212,10 @@ impl<'input> BuckManifestLoader<'input> {
 pub struct ThriftCratemapLoader<'input> {
     logger: &'input Logger,
     fbcode_root: &'input FbcodeRoot,
-    use_isolation_dir: bool,
+    buck: BuckOptions<'input>,
     rules: Vec<ThriftCratemapRule>,
+    /// Keys of the rules in the cratemap cache, empty without the cache.
+    cache_keys: HashMap<ThriftCratemapRule, String>,
     cmd_runner: MockableCommandRunner,
 }
 
@@ -214,57 +225,109 @@ impl<'input> ThriftCratemapLoader<'input> {
     pub fn from_rules_and_raw<'a>(
         logger: &'input Logger,
         fbcode_root: &'input FbcodeRoot,
-        use_isolation_dir: bool,
+        buck: BuckOptions<'input>,
         rules_and_raw: impl IntoIterator<Item = (&'a FbcodeBuckRule, &'a RawBuckManifest)>,
         cmd_runner: MockableCommandRunner,
     ) -> Self {
+        let mut rules = Vec::new();
+        for (rule, raw) in rules_and_raw {
+            if let Some(AutocargoThrift {
+                gen_context: GenContext::Types,
+                ..
+            }) = raw.autocargo.thrift
+            {
+                rules.push(ThriftCratemapRule::from_library_rule(rule.clone()));
+            }
+        }
         Self {
             logger,
             fbcode_root,
-            use_isolation_dir,
-            rules: rules_and_raw
-                .into_iter()
-                .filter_map(|(rule, raw)| {
-                    if let Some(AutocargoThrift {
-                        gen_context: GenContext::Types,
-                        ..
-                    }) = raw.autocargo.thrift
-                    {
-                        Some(ThriftCratemapRule::from_library_rule(rule.clone()))
-                    } else {
-                        None
-                    }
-                })
-                .collect(),
+            buck,
+            rules,
+            cache_keys: HashMap::new(),
             cmd_runner,
         }
     }
 
+    /// Computes the keys of the rules in the cratemap cache, if any, from
+    /// the manifests of the rules and of their dependencies.
+    pub async fn with_cache_keys(
+        mut self,
+        manifests: &HashMap<&FbcodeBuckRule, &RawBuckManifest>,
+    ) -> Self {
+        if self.buck.cratemap_cache.is_some() {
+            let mut keys = ThriftCratemapCache::keys(self.fbcode_root, manifests).await;
+            self.cache_keys = self
+                .rules
+                .iter()
+                .filter_map(|rule| Some((rule.clone(), keys.remove(&rule.to_library_rule())?)))
+                .collect();
+        }
+        self
+    }
+
     /// Builds cratemaps using buck and returns map from rule to their
-    /// corresponding cratemap content.
-    pub async fn load(self) -> Result<HashMap<FbcodeBuckRule, String>> {
-        self.build()
+    /// corresponding cratemap content. Cratemaps found in the cratemap cache
+    /// are not built, the built ones are stored into it. Reading of the files
+    /// is limited by concurrency.
+    pub async fn load(
+        mut self,
+        concurrency: &ConcurrencyLimit,
+    ) -> Result<HashMap<FbcodeBuckRule, String>> {
+        let cache = self.buck.cratemap_cache;
+        let fbcode_root = self.fbcode_root;
+        let cache_keys = &std::mem::take(&mut self.cache_keys);
+
+        let mut result: HashMap<FbcodeBuckRule, String> = HashMap::new();
+        if let Some(cache) = cache {
+            let cached: Vec<_> = self
+                .rules
+                .iter()
+                .filter_map(|rule| Some((rule, cache_keys.get(rule)?)))
+                .map(|(rule, key)| async move {
+                    let content = concurrency.run(cache.get(fbcode_root, key)).await?;
+                    Some((rule.to_library_rule(), content))
+                })
+                .collect::<FuturesUnordered<_>>()
+                .filter_map(future::ready)
+                .collect()
+                .await;
+            result.extend(cached);
+            self.rules
+                .retain(|rule| !result.contains_key(&rule.to_library_rule()));
+        }
+
+        let built: Vec<_> = self
+            .build()
             .await?
             .into_iter()
             .map(|(rule, out_path)| async move {
-                let rule = rule.to_library_rule();
-                let content = read_to_string(&out_path)
+                let content = concurrency
+                    .run(read_to_string(&out_path))
                     .await
                     .with_context(|| format!("While reading file {}", out_path.display()))?;
-                Ok((rule, content))
+                if let (Some(cache), Some(key)) = (cache, cache_keys.get(&rule)) {
+                    concurrency
+                        .run(cache.put(fbcode_root, key, &content))
+                        .await?;
+                }
+                Ok::<_, anyhow::Error>((rule.to_library_rule(), content))
             })
             .collect::<FuturesUnordered<_>>()
             .try_collect()
-            .await
+            .await?;
+        result.extend(built);
+        Ok(result)
     }
 
     async fn build(self) -> Result<HashMap<ThriftCratemapRule, PathBuf>> {
         let Self {
             logger,
             fbcode_root,
-            use_isolation_dir,
+            buck,
             rules,
             cmd_runner,
+            ..
         } = self;
         let dbg_name = "buck build thrift cratemaps";
 
@@ -277,7 +340,7 @@ impl<'input> ThriftCratemapLoader<'input> {
                 logger,
                 dbg_name,
                 Duration::from_secs(5),
-                buck_build_cratemaps_cmd(fbcode_root, use_isolation_dir, &rules).boxed_local(),
+                buck_build_cratemaps_cmd(fbcode_root, buck, logger, &rules).boxed_local(),
             )
             .await?;
 
@@ -323,12 +386,14 @@ mod test {
     use assert_matches::assert_matches;
     use itertools::Itertools;
     use maplit::hashmap;
+    use serde_json::from_value;
     use serde_json::json;
     use serde_json::to_vec;
     use slog::o;
 
     use super::*;
     use crate::buck_processing::test_utils::TmpManifests;
+    use crate::config::BuckConfig;
     use crate::paths::PathInFbcode;
 
     #[tokio::test]
@@ -342,7 +407,14 @@ mod test {
             BuckManifestLoader::from_targets_paths(
                 &logger,
                 &fbcode_root,
-                false, // use_isolation_dir
+                BuckOptions {
+                    config: &BuckConfig::default(),
+                    use_isolation_dir: false,
+                    show_output: false,
+                    fixtures: None,
+                    offline: false,
+                    cratemap_cache: None,
+                },
                 &Vec::<TargetsPath>::new(),
                 MockableCommandRunner::default(),
             ).await,
@@ -367,7 +439,14 @@ mod test {
             BuckManifestLoader::from_targets_paths(
                 &logger,
                 &fbcode_root,
-                false, // use_isolation_dir
+                BuckOptions {
+                    config: &BuckConfig::default(),
+                    use_isolation_dir: false,
+                    show_output: false,
+                    fixtures: None,
+                    offline: false,
+                    cratemap_cache: None,
+                },
                 &vec![tp("unimportant/TARGETS")],
                 cmd_runner,
             ).await,
@@ -407,7 +486,14 @@ mod test {
             BuckManifestLoader::from_rust_buck_rules(
                 &logger,
                 &fbcode_root,
-                false, // use_isolation_dir
+                BuckOptions {
+                    config: &BuckConfig::default(),
+                    use_isolation_dir: false,
+                    show_output: false,
+                    fixtures: None,
+                    offline: false,
+                    cratemap_cache: None,
+                },
                 &vec![FbcodeBuckRule {
                     path: tp("fiz/TARGETS"),
                     name: "biz2".to_owned()
@@ -437,7 +523,14 @@ mod test {
             BuckManifestLoader {
                 logger: &Logger::root(slog::Discard, o!()),
                 fbcode_root: &FbcodeRoot::new_mock("/foo/bar"),
-                use_isolation_dir: false,
+                buck: BuckOptions {
+                    config: &BuckConfig::default(),
+                    use_isolation_dir: false,
+                    show_output: false,
+                    fixtures: None,
+                    offline: false,
+                    cratemap_cache: None,
+                },
                 rules: vec![make_rule()],
                 cmd_runner: {
                     let mut cmd_runner = MockableCommandRunner::default();
@@ -477,7 +570,14 @@ mod test {
             BuckManifestLoader {
                 logger: &Logger::root(slog::Discard, o!()),
                 fbcode_root: &FbcodeRoot::new_mock("/foo/bar"),
-                use_isolation_dir: false,
+                buck: BuckOptions {
+                    config: &BuckConfig::default(),
+                    use_isolation_dir: false,
+                    show_output: false,
+                    fixtures: None,
+                    offline: false,
+                    cratemap_cache: None,
+                },
                 rules: vec![
                     BuckManifestRule::from(&FbcodeBuckRule {
                         path: tp("fiz/TARGETS"),
@@ -502,7 +602,7 @@ mod test {
                     });
                     cmd_runner
                 }
-            }.load().await,
+            }.load(&ConcurrencyLimit::default()).await,
             Ok(map) => {
                 assert_eq!(
                     map.into_iter()
@@ -529,4 +629,106 @@ mod test {
             }
         );
     }
+
+    #[tokio::test]
+    async fn thrift_cratemap_loader_test_cache() {
+        let tp = |path: &str| TargetsPath::new(PathInFbcode::new_mock(path)).unwrap();
+        let rule = |name: &str| FbcodeBuckRule {
+            path: tp("fiz/TARGETS"),
+            name: format!("{name}-rust"),
+        };
+        let raw = |name: &str| RawBuckManifest {
+            name: format!("{name}-rust"),
+            autocargo: from_value(json!({
+                "thrift": {
+                    "base_path": "fiz",
+                    "gen_context": "types",
+                    "options": {
+                        "cratemap": "unused",
+                        "types_crate": format!("{name}__types"),
+                    },
+                    "thrift_srcs": {format!("{name}.thrift"): []},
+                    "unsuffixed_name": name,
+                },
+            }))
+            .unwrap(),
+            ..RawBuckManifest::empty_test()
+        };
+        let (cached, built) = (rule("cached"), rule("built"));
+        let (cached_raw, built_raw) = (raw("cached"), raw("built"));
+
+        let dir = tempfile::tempdir().unwrap();
+        let built_path = dir.path().join("built-cratemap");
+        std::fs::write(&built_path, "built.thrift fiz").unwrap();
+        std::fs::create_dir(dir.path().join("fiz")).unwrap();
+        std::fs::write(dir.path().join("fiz/cached.thrift"), "struct Cached {}").unwrap();
+        std::fs::write(dir.path().join("fiz/built.thrift"), "struct Built {}").unwrap();
+        let fbcode_root = FbcodeRoot::new_mock(dir.path());
+        let manifests = HashMap::from([(&cached, &cached_raw), (&built, &built_raw)]);
+        let cache = ThriftCratemapCache::new(dir.path().join("cache"));
+        let cached_key = ThriftCratemapCache::keys(&fbcode_root, &manifests)
+            .await
+            .remove(&cached)
+            .unwrap();
+        cache
+            .put(&fbcode_root, &cached_key, "cached.thrift fiz")
+            .await
+            .unwrap();
+
+        let logger = Logger::root(slog::Discard, o!());
+        let buck = BuckOptions {
+            config: &BuckConfig::default(),
+            use_isolation_dir: false,
+            show_output: false,
+            fixtures: None,
+            offline: false,
+            cratemap_cache: Some(&cache),
+        };
+        let mut cmd_runner = MockableCommandRunner::default();
+        cmd_runner.expect_run().times(1).return_once({
+            let built_path = built_path.clone();
+            move |_, _, _, _| {
+                Ok(Output {
+                    status: ExitStatus::from_raw(0),
+                    stderr: vec![],
+                    stdout: to_vec(&json!({"//fiz:built-rust-dep-map": built_path})).unwrap(),
+                })
+            }
+        });
+        let loader = ThriftCratemapLoader::from_rules_and_raw(
+            &logger,
+            &fbcode_root,
+            buck,
+            [(&cached, &cached_raw), (&built, &built_raw)],
+            cmd_runner,
+        )
+        .with_cache_keys(&manifests)
+        .await;
+        assert_eq!(
+            loader.load(&ConcurrencyLimit::default()).await.unwrap(),
+            hashmap! {
+                cached.clone() => "cached.thrift fiz".to_owned(),
+                built.clone() => "built.thrift fiz".to_owned(),
+            }
+        );
+
+        // Both cratemaps are cached now, so Buck is not run again.
+        std::fs::remove_file(&built_path).unwrap();
+        let loader = ThriftCratemapLoader::from_rules_and_raw(
+            &logger,
+            &fbcode_root,
+            buck,
+            [(&cached, &cached_raw), (&built, &built_raw)],
+            MockableCommandRunner::default(),
+        )
+        .with_cache_keys(&manifests)
+        .await;
+        assert_eq!(
+            loader.load(&ConcurrencyLimit::default()).await.unwrap(),
+            hashmap! {
+                cached => "cached.thrift fiz".to_owned(),
+                built => "built.thrift fiz".to_owned(),
+            }
+        );
+    }
 }
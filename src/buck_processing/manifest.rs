@@ -15,15 +15,23 @@ use std::sync::Arc;
 use std::sync::LazyLock;
 
 use anyhow::Result;
+use anyhow::bail;
+use anyhow::ensure;
 use enum_iterator::Sequence;
 use getset::Getters;
 use itertools::Itertools;
 use slog::Logger;
 use slog::trace;
+use slog::warn;
 
+use super::BuckOptions;
 use super::ProcessOutput;
+use super::diagnostics::Diagnostics;
+use super::diagnostics::Severity;
 use super::loader::BuckManifestLoader;
 use super::loader::ThriftCratemapLoader;
+use super::raw_manifest::RawArtifactDependencies;
+use super::raw_manifest::RawArtifactDependency;
 use super::raw_manifest::RawBuckDependencyOverride;
 use super::raw_manifest::RawBuckManifest;
 use super::raw_manifest::RawBuckManifestDependencies;
@@ -37,6 +45,7 @@ use crate::cargo_manifest::TargetKey;
 use crate::paths::FbcodeRoot;
 use crate::paths::TargetsPath;
 use crate::util::command_runner::MockableCommandRunner;
+use crate::util::concurrency::ConcurrencyLimit;
 
 /// Rule identifying thrift_compiler, used by thrift generation.
 pub static THRIFT_COMPILER_RULE: LazyLock<FbcodeBuckRule> = LazyLock::new(|| FbcodeBuckRule {
@@ -51,6 +60,17 @@ pub static CODEGEN_INCLUDER_PROC_MACRO_RULE: LazyLock<FbcodeBuckRule> =
         name: "codegen_includer_proc_macro".to_owned(),
     });
 
+/// Third-party crate compiling cxx bridges, used as build dependency by cxx
+/// generation.
+pub static CXX_BUILD_CRATE: &str = "cxx-build";
+
+/// Third-party crate required at runtime by cxx bridges.
+pub static CXX_CRATE: &str = "cxx";
+
+/// Third-party benchmarking crate that provides its own main, so benches
+/// depending on it need harness = false.
+pub static CRITERION_CRATE: &str = "criterion";
+
 /// Enum describing type of rule that the manifest describes. Includes only the
 /// ones supported by this library.
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
@@ -65,20 +85,21 @@ pub enum FbconfigRuleType {
 
 impl FbconfigRuleType {
     fn try_from_raw(
-        logger: &'_ Logger,
-        targets_path: &'_ TargetsPath,
+        diagnostics: &'_ mut Diagnostics,
+        rule: &'_ FbcodeBuckRule,
         value: &'_ RawFbconfigRuleType,
     ) -> Option<Self> {
         match value {
             RawFbconfigRuleType::RustBinary => Some(Self::RustBinary),
-            RawFbconfigRuleType::RustLibrary => Some(Self::RustLibrary),
+            RawFbconfigRuleType::RustLibrary
+            | RawFbconfigRuleType::RustCxxLibrary
+            | RawFbconfigRuleType::RustProtobufLibrary => Some(Self::RustLibrary),
             RawFbconfigRuleType::RustUnittest => Some(Self::RustUnittest),
             RawFbconfigRuleType::RustBindgenLibrary | RawFbconfigRuleType::Other => {
-                trace!(
-                    logger,
-                    "Build file at {}: Rule type {:#?} is not supported",
-                    targets_path.as_dir().as_ref().display(),
-                    value
+                diagnostics.push(
+                    rule,
+                    Severity::Warning,
+                    format!("Rule type {value:?} is not supported"),
                 );
                 None
             }
@@ -100,8 +121,9 @@ pub enum OsDepsPlatform {
 
 impl OsDepsPlatform {
     fn try_from_raw(
-        logger: &'_ Logger,
-        targets_path: &'_ TargetsPath,
+        diagnostics: &'_ mut Diagnostics,
+        rule: &'_ FbcodeBuckRule,
+        attribute: &'_ str,
         value: &'_ RawOsDepsPlatform,
     ) -> Option<Self> {
         match value {
@@ -109,11 +131,10 @@ impl OsDepsPlatform {
             RawOsDepsPlatform::Macos => Some(Self::Macos),
             RawOsDepsPlatform::Windows => Some(Self::Windows),
             RawOsDepsPlatform::Other => {
-                trace!(
-                    logger,
-                    "Build file at {}: Os platform {:#?} is not supported",
-                    targets_path.as_dir().as_ref().display(),
-                    value
+                diagnostics.push(
+                    rule,
+                    Severity::Info,
+                    format!("Entries of {attribute} for unsupported platforms are skipped"),
                 );
                 None
             }
@@ -143,6 +164,9 @@ pub enum BuckDependency {
     ThirdPartyCrate(String),
     /// Path to and manifest of a dependency in fbcode.
     FbcodeCrate(Arc<TargetsPath>, Arc<RawBuckManifest>),
+    /// Fully qualified rule of another cell, e.g. `xplat//rust/foo:foo`, that
+    /// is resolved with the cell_dependencies of the global config.
+    CellRule(String),
 }
 
 /// Processed manifest containing the original raw manifest and resolved
@@ -169,6 +193,13 @@ pub struct BuckManifest {
     test_named_deps: HashMap<String, BuckDependency>,
     /// Test dependencies that are platfrom specific.
     test_os_deps: HashMap<OsDepsPlatform, Vec<BuckDependency>>,
+    /// Dependencies from select() branches per constraint value.
+    select_deps: BTreeMap<String, Vec<BuckDependency>>,
+    /// Dependencies of the checked-in build script from
+    /// raw.autocargo.build_script.
+    build_script_deps: Vec<BuckDependency>,
+    /// Features that are platform specific.
+    os_features: HashMap<OsDepsPlatform, Vec<String>>,
     /// Contains processed [RawExtraBuckDependencies], check its documentation
     /// for more.
     extra_buck_dependencies: ExtraBuckDependencies,
@@ -177,12 +208,44 @@ pub struct BuckManifest {
     thrift_config: Option<ThriftConfig>,
 }
 
+impl BuckManifest {
+    /// True if this is a rust_unittest rule marked with
+    /// autocargo.cargo_target_config.bench = true, which is generated as a
+    /// [[bench]] rather than a [[test]].
+    pub fn is_bench(&self) -> bool {
+        self.fbconfig_rule_type == FbconfigRuleType::RustUnittest
+            && self.raw.autocargo.cargo_target_config.bench == Some(true)
+    }
+
+    /// True if the rule depends on the third-party crate directly.
+    pub fn depends_on_third_party(&self, name: &str) -> bool {
+        self.deps
+            .iter()
+            .chain(&self.test_deps)
+            .chain(self.named_deps.values())
+            .chain(self.test_named_deps.values())
+            .any(|dep| matches!(dep, BuckDependency::ThirdPartyCrate(crate_) if crate_ == name))
+    }
+}
+
 /// Proccessed [RawExtraBuckDependencies].
 #[derive(Debug, Default)]
 #[allow(missing_docs)]
 pub struct ExtraBuckDependencies {
     pub deps: BuckTargetDependencies,
     pub target: BTreeMap<TargetKey, BuckTargetDependencies>,
+    pub artifacts: ArtifactDependencies,
+}
+
+/// Processed [RawArtifactDependencies]. The dependencies are also added to
+/// the matching section of [ExtraBuckDependencies::deps], these are only the
+/// artifact keys to set on them.
+#[derive(Debug, Default)]
+#[allow(missing_docs)]
+pub struct ArtifactDependencies {
+    pub dependencies: Vec<(BuckDependency, RawArtifactDependency)>,
+    pub dev_dependencies: Vec<(BuckDependency, RawArtifactDependency)>,
+    pub build_dependencies: Vec<(BuckDependency, RawArtifactDependency)>,
 }
 
 /// Processed [RawBuckTargetDependencies]
@@ -220,13 +283,17 @@ pub struct ThriftConfig {
 pub async fn process_raw_manifests(
     logger: &'_ Logger,
     fbcode_root: &'_ FbcodeRoot,
-    use_isolation_dir: bool,
-    raw_manifests: HashMap<FbcodeBuckRule, RawBuckManifest>,
+    concurrency: &'_ ConcurrencyLimit,
+    buck: BuckOptions<'_>,
+    mut raw_manifests: HashMap<FbcodeBuckRule, RawBuckManifest>,
 ) -> Result<ProcessOutput> {
+    assign_companion_proc_macro_dirs(logger, &mut raw_manifests)?;
+
+    let mut diagnostics = Diagnostics::default();
     let manifest_builders: HashMap<_, _> = raw_manifests
         .into_iter()
         .filter_map(|(k, v)| {
-            let v = BuckManifestBuilder::from_raw_manifest(logger, &k.path, v)?;
+            let v = BuckManifestBuilder::from_raw_manifest(&mut diagnostics, &k.path, v)?;
             Some((k, v))
         })
         .collect();
@@ -234,7 +301,8 @@ pub async fn process_raw_manifests(
     let all_raw_manifests = compute_all_raw_manifests(
         logger,
         fbcode_root,
-        use_isolation_dir,
+        concurrency,
+        buck,
         &manifest_builders,
         MockableCommandRunner::default(),
     )
@@ -242,8 +310,10 @@ pub async fn process_raw_manifests(
     let all_thrift_cratemaps = read_all_thrift_cratemaps(
         logger,
         fbcode_root,
-        use_isolation_dir,
+        concurrency,
+        buck,
         &manifest_builders,
+        &all_raw_manifests,
         MockableCommandRunner::default(),
     )
     .await?;
@@ -253,6 +323,7 @@ pub async fn process_raw_manifests(
         manifest_builders,
         all_raw_manifests,
         all_thrift_cratemaps,
+        diagnostics,
     ))
 }
 
@@ -264,7 +335,8 @@ pub async fn process_raw_manifests(
 async fn compute_all_raw_manifests(
     logger: &'_ Logger,
     fbcode_root: &'_ FbcodeRoot,
-    use_isolation_dir: bool,
+    concurrency: &'_ ConcurrencyLimit,
+    buck: BuckOptions<'_>,
     manifest_builders: &HashMap<FbcodeBuckRule, BuckManifestBuilder>,
     cmd_runner: MockableCommandRunner,
 ) -> Result<HashMap<FbcodeBuckRule, (Arc<TargetsPath>, Arc<RawBuckManifest>)>> {
@@ -272,16 +344,17 @@ async fn compute_all_raw_manifests(
     let dependency_rules = extract_dependencies(manifest_builders.values());
     let missing_rules = dependency_rules.difference(&loaded_rules).cloned(); // && -> & with cloned
 
-    let raw_manifests_of_missing_rules = BuckManifestLoader::from_rust_buck_rules(
+    let mut raw_manifests_of_missing_rules = BuckManifestLoader::from_rust_buck_rules(
         logger,
         fbcode_root,
-        use_isolation_dir,
+        buck,
         missing_rules,
         cmd_runner,
     )
     .await?
-    .load()
+    .load(concurrency)
     .await?;
+    assign_companion_proc_macro_dirs(logger, &mut raw_manifests_of_missing_rules)?;
 
     Ok(manifest_builders
         .iter()
@@ -296,23 +369,104 @@ async fn compute_all_raw_manifests(
         .collect())
 }
 
+/// For each library with autocargo.companion_proc_macro_dir find the
+/// proc-macro rule from the same TARGETS file that maps into the same
+/// Cargo.toml file as the library and move it into the companion directory.
+fn assign_companion_proc_macro_dirs(
+    logger: &Logger,
+    raw_manifests: &mut HashMap<FbcodeBuckRule, RawBuckManifest>,
+) -> Result<()> {
+    let is_proc_macro = |raw: &RawBuckManifest| {
+        raw.fbconfig_rule_type == RawFbconfigRuleType::RustLibrary
+            && raw
+                .autocargo
+                .cargo_target_config
+                .proc_macro
+                .unwrap_or(raw.rust_config.proc_macro)
+    };
+
+    let parents: Vec<_> = raw_manifests
+        .iter()
+        .filter_map(|(rule, raw)| {
+            let dir = raw.autocargo.companion_proc_macro_dir.as_ref()?;
+            Some((rule.clone(), raw.autocargo.cargo_toml_dir.join(dir)))
+        })
+        .sorted()
+        .collect();
+
+    for (parent, companion_dir) in parents {
+        let parent_raw = &raw_manifests[&parent];
+        ensure!(
+            parent_raw.fbconfig_rule_type == RawFbconfigRuleType::RustLibrary
+                && !is_proc_macro(parent_raw),
+            "Rule {} defines autocargo.companion_proc_macro_dir, but only non \
+            proc-macro rust_library rules might define it",
+            parent,
+        );
+
+        let parent_dir = &parent_raw.autocargo.cargo_toml_dir;
+        let companions: Vec<_> = raw_manifests
+            .iter()
+            .filter(|(rule, raw)| {
+                rule.path == parent.path
+                    && is_proc_macro(raw)
+                    && &raw.autocargo.cargo_toml_dir == parent_dir
+            })
+            .map(|(rule, _)| rule.clone())
+            .sorted()
+            .collect();
+
+        match companions.as_slice() {
+            [] => warn!(
+                logger,
+                "Rule {} defines autocargo.companion_proc_macro_dir, but there is no \
+                proc-macro rule next to it generated into the same Cargo.toml",
+                parent,
+            ),
+            [companion] => {
+                raw_manifests
+                    .get_mut(companion)
+                    .expect("Logic error: companion rule was just found")
+                    .autocargo
+                    .cargo_toml_dir = companion_dir;
+            }
+            _ => bail!(
+                "Rule {} defines autocargo.companion_proc_macro_dir, but multiple \
+                proc-macro rules next to it are generated into the same Cargo.toml: {}",
+                parent,
+                companions.iter().join(", "),
+            ),
+        }
+    }
+
+    Ok(())
+}
+
 async fn read_all_thrift_cratemaps(
     logger: &'_ Logger,
     fbcode_root: &'_ FbcodeRoot,
-    use_isolation_dir: bool,
+    concurrency: &'_ ConcurrencyLimit,
+    buck: BuckOptions<'_>,
     manifest_builders: &HashMap<FbcodeBuckRule, BuckManifestBuilder>,
+    all_raw_manifests: &HashMap<FbcodeBuckRule, (Arc<TargetsPath>, Arc<RawBuckManifest>)>,
     cmd_runner: MockableCommandRunner,
 ) -> Result<HashMap<FbcodeBuckRule, String>> {
+    let all_raw_manifests = all_raw_manifests
+        .iter()
+        .map(|(rule, (_, raw))| (rule, &**raw))
+        .collect();
     ThriftCratemapLoader::from_rules_and_raw(
         logger,
         fbcode_root,
-        use_isolation_dir,
+        buck,
         manifest_builders
             .iter()
             .map(|(rule, builder)| (rule, &*builder.raw)),
         cmd_runner,
     )
-    .load()
+    .with_cache_keys(&all_raw_manifests)
+    .await
+    .load(concurrency)
     .await
 }
 
@@ -334,6 +488,9 @@ fn extract_dependencies<'a>(
                  test_deps,
                  test_named_deps,
                  test_os_deps,
+                 select_deps,
+                 build_script_deps,
+                 os_features: _,
                  extra_buck_dependencies,
              }| {
                 deps.iter()
@@ -366,6 +523,15 @@ fn extract_dependencies<'a>(
                         deps.iter()
                             .filter_map(UnprocessedBuckDependency::fbcode_crate)
                     }))
+                    .chain(select_deps.values().flat_map(|deps| {
+                        deps.iter()
+                            .filter_map(UnprocessedBuckDependency::fbcode_crate)
+                    }))
+                    .chain(
+                        build_script_deps
+                            .iter()
+                            .filter_map(UnprocessedBuckDependency::fbcode_crate),
+                    )
                     .chain(if raw.autocargo.thrift.is_some() {
                         vec![&*THRIFT_COMPILER_RULE, &*CODEGEN_INCLUDER_PROC_MACRO_RULE]
                     } else {
@@ -381,14 +547,16 @@ fn extract_dependencies<'a>(
 /// builders (intermediate result of parsing) and pre-fetched all raw manifests
 /// it stiches all together via builder's build() call and return ProcessOutput
 /// that contains the unprocessed_paths computed based on the difference between
-/// all_raw_manifests and provided manifest_builders.
+/// all_raw_manifests and provided manifest_builders, together with the
+/// diagnostics collected while creating the builders.
 fn process_manifest_builders(
     logger: &'_ Logger,
     manifest_builders: HashMap<FbcodeBuckRule, BuckManifestBuilder>,
     all_raw_manifests: HashMap<FbcodeBuckRule, (Arc<TargetsPath>, Arc<RawBuckManifest>)>,
     all_thrift_cratemaps: HashMap<FbcodeBuckRule, String>,
+    diagnostics: Diagnostics,
 ) -> ProcessOutput {
-    let processed_manifests = manifest_builders
+    let mut processed_manifests = manifest_builders
         .into_iter()
         .map(|(rule, builder)| {
             let maybe_cratemap = builder
@@ -409,6 +577,12 @@ fn process_manifest_builders(
             )
         })
         .into_group_map();
+    // The builders come from a HashMap, sort the manifests of each TARGETS
+    // file so that e.g. the products of a Cargo.toml are generated in a stable
+    // order.
+    for manifests in processed_manifests.values_mut() {
+        manifests.sort_by(|a, b| a.raw.name.cmp(&b.raw.name));
+    }
 
     let unprocessed_paths = all_raw_manifests
         .into_iter()
@@ -424,6 +598,7 @@ fn process_manifest_builders(
     ProcessOutput {
         processed_manifests,
         unprocessed_paths,
+        diagnostics,
     }
 }
 
@@ -442,23 +617,44 @@ struct BuckManifestBuilder {
     test_named_deps: HashMap<String, UnprocessedBuckDependency>,
     extra_buck_dependencies: UnprocessedExtraBuckDependencies,
     test_os_deps: HashMap<OsDepsPlatform, Vec<UnprocessedBuckDependency>>,
+    select_deps: BTreeMap<String, Vec<UnprocessedBuckDependency>>,
+    build_script_deps: Vec<UnprocessedBuckDependency>,
+    os_features: HashMap<OsDepsPlatform, Vec<String>>,
 }
 
 impl BuckManifestBuilder {
     /// Given raw manifest process its dependencies into
-    /// [UnprocessedBuckDependency].
+    /// [UnprocessedBuckDependency]. Rules of unsupported types and attributes
+    /// for unsupported platforms are skipped and recorded in diagnostics.
     fn from_raw_manifest(
-        logger: &'_ Logger,
+        diagnostics: &'_ mut Diagnostics,
         targets_path: &'_ TargetsPath,
         raw: RawBuckManifest,
     ) -> Option<Self> {
+        let rule = FbcodeBuckRule {
+            path: targets_path.clone(),
+            name: raw.name.clone(),
+        };
         let fbconfig_rule_type =
-            FbconfigRuleType::try_from_raw(logger, targets_path, &raw.fbconfig_rule_type)?;
+            FbconfigRuleType::try_from_raw(diagnostics, &rule, &raw.fbconfig_rule_type)?;
+        let is_protobuf_rule = raw.fbconfig_rule_type == RawFbconfigRuleType::RustProtobufLibrary;
+        if is_protobuf_rule != raw.autocargo.protobuf.is_some() {
+            diagnostics.push(
+                &rule,
+                Severity::Warning,
+                if is_protobuf_rule {
+                    "rust_protobuf_library rule has no autocargo.protobuf config"
+                } else {
+                    "autocargo.protobuf config is supported only on rust_protobuf_library rules"
+                },
+            );
+            return None;
+        }
 
         let raw = Arc::new(raw);
 
         let mut rule_parse =
-            |rule: &_| UnprocessedBuckDependency::try_from_rule(logger, targets_path, rule);
+            |rule: &_| UnprocessedBuckDependency::try_from_rule(targets_path, rule);
 
         let RawBuckManifestDependencies {
             deps,
@@ -468,6 +664,7 @@ impl BuckManifestBuilder {
             test_deps,
             test_named_deps,
             test_os_deps,
+            select_deps,
         } = &raw.dependencies;
 
         let deps = deps.iter().filter_map(rule_parse).collect();
@@ -478,7 +675,7 @@ impl BuckManifestBuilder {
         let os_deps = os_deps
             .iter()
             .filter_map(|(k, vs)| {
-                let k = OsDepsPlatform::try_from_raw(logger, targets_path, k)?;
+                let k = OsDepsPlatform::try_from_raw(diagnostics, &rule, "os_deps", k)?;
                 let vs = vs.iter().filter_map(rule_parse);
                 // The raw OsDeps are of type Vec<(OsDepsPlatform, Vec<T>>), so
                 // the OsDeps might be not unique. That is why here we are
@@ -499,7 +696,7 @@ impl BuckManifestBuilder {
         let test_os_deps = test_os_deps
             .iter()
             .filter_map(|(k, vs)| {
-                let k = OsDepsPlatform::try_from_raw(logger, targets_path, k)?;
+                let k = OsDepsPlatform::try_from_raw(diagnostics, &rule, "test_os_deps", k)?;
                 let vs = vs.iter().filter_map(rule_parse);
                 // See `os_deps` above for discussion about what is going on
                 // here.
@@ -507,6 +704,43 @@ impl BuckManifestBuilder {
             })
             .flatten()
             .into_group_map();
+        let select_deps = select_deps
+            .iter()
+            .flat_map(|(k, vs)| {
+                // See `os_deps` above for discussion about what is going on
+                // here.
+                vs.iter()
+                    .filter_map(rule_parse)
+                    .map(move |v| (k.clone(), v))
+            })
+            .into_group_map()
+            .into_iter()
+            .collect();
+        let build_script_deps = raw
+            .autocargo
+            .build_script
+            .iter()
+            .flat_map(|build_script| build_script.deps.iter())
+            .chain(
+                raw.autocargo
+                    .protobuf
+                    .iter()
+                    .flat_map(|protobuf| protobuf.build_deps.iter()),
+            )
+            .filter_map(&mut rule_parse)
+            .collect();
+        let os_features = raw
+            .rust_config
+            .os_features
+            .iter()
+            .filter_map(|(k, features)| {
+                let k = OsDepsPlatform::try_from_raw(diagnostics, &rule, "os_features", k)?;
+                // See `os_deps` above for discussion about what is going on
+                // here.
+                Some(features.iter().map(move |f| (k, f.clone())))
+            })
+            .flatten()
+            .into_group_map();
 
         let extra_buck_dependencies =
             if let Some(cargo_toml_config) = &raw.autocargo.cargo_toml_config {
@@ -528,6 +762,9 @@ impl BuckManifestBuilder {
             test_deps,
             test_named_deps,
             test_os_deps,
+            select_deps,
+            build_script_deps,
+            os_features,
             extra_buck_dependencies,
         })
     }
@@ -550,6 +787,9 @@ impl BuckManifestBuilder {
             test_deps,
             test_named_deps,
             test_os_deps,
+            select_deps,
+            build_script_deps,
+            os_features,
             extra_buck_dependencies,
         } = self;
 
@@ -596,6 +836,21 @@ impl BuckManifestBuilder {
                     if v.is_empty() { None } else { Some((k, v)) }
                 })
                 .collect(),
+            select_deps: select_deps
+                .into_iter()
+                .map(|(k, v)| {
+                    let v = v
+                        .into_iter()
+                        .filter_map(|d| d.process(logger, all_raw_manifests))
+                        .collect();
+                    (k, v)
+                })
+                .collect(),
+            build_script_deps: build_script_deps
+                .into_iter()
+                .filter_map(|d| d.process(logger, all_raw_manifests))
+                .collect(),
+            os_features,
             extra_buck_dependencies: extra_buck_dependencies.process(logger, all_raw_manifests),
             thrift_config: thrift_cratemap_content.map(|cratemap_content| ThriftConfig {
                 cratemap_content,
@@ -620,6 +875,7 @@ impl BuckManifestBuilder {
 struct UnprocessedExtraBuckDependencies {
     pub deps: UnprocessedBuckTargetDependencies,
     pub target: BTreeMap<TargetKey, UnprocessedBuckTargetDependencies>,
+    pub artifacts: UnprocessedArtifactDependencies,
 }
 
 impl UnprocessedExtraBuckDependencies {
@@ -629,10 +885,30 @@ impl UnprocessedExtraBuckDependencies {
             &'a BuckRuleParseOutput,
         ) -> Option<UnprocessedBuckDependency>,
     ) -> Self {
-        let RawExtraBuckDependencies { deps, target } = raw;
+        let RawExtraBuckDependencies {
+            deps,
+            target,
+            artifact_dependencies,
+        } = raw;
+
+        let mut deps = UnprocessedBuckTargetDependencies::from_raw(deps, process);
+        let artifacts = UnprocessedArtifactDependencies::from_raw(artifact_dependencies, process);
+        // Artifact dependencies are regular dependencies with extra keys, so
+        // they are generated like the other extra dependencies.
+        for (section, artifacts) in [
+            (&mut deps.dependencies, &artifacts.dependencies),
+            (&mut deps.dev_dependencies, &artifacts.dev_dependencies),
+            (&mut deps.build_dependencies, &artifacts.build_dependencies),
+        ] {
+            section.extend(
+                artifacts
+                    .iter()
+                    .map(|(dep, _)| UnprocessedBuckDependencyOverride::Dep(dep.clone())),
+            );
+        }
 
         Self {
-            deps: UnprocessedBuckTargetDependencies::from_raw(deps, process),
+            deps,
             target: target
                 .iter()
                 .map(|(k, deps)| {
@@ -642,11 +918,17 @@ impl UnprocessedExtraBuckDependencies {
                     )
                 })
                 .collect(),
+            artifacts,
         }
     }
 
     fn fbcode_crates(&self) -> impl Iterator<Item = &'_ FbcodeBuckRule> {
-        let Self { deps, target } = self;
+        // The artifact dependencies are among deps too.
+        let Self {
+            deps,
+            target,
+            artifacts: _,
+        } = self;
 
         deps.fbcode_crates().chain(
             target
@@ -660,7 +942,11 @@ impl UnprocessedExtraBuckDependencies {
         logger: &'_ Logger,
         all_raw_manifests: &HashMap<FbcodeBuckRule, (Arc<TargetsPath>, Arc<RawBuckManifest>)>,
     ) -> ExtraBuckDependencies {
-        let Self { deps, target } = self;
+        let Self {
+            deps,
+            target,
+            artifacts,
+        } = self;
 
         ExtraBuckDependencies {
             deps: deps.process(logger, all_raw_manifests),
@@ -668,6 +954,67 @@ impl UnprocessedExtraBuckDependencies {
                 .into_iter()
                 .map(|(k, deps)| (k, deps.process(logger, all_raw_manifests)))
                 .collect(),
+            artifacts: artifacts.process(logger, all_raw_manifests),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct UnprocessedArtifactDependencies {
+    pub dependencies: Vec<(UnprocessedBuckDependency, RawArtifactDependency)>,
+    pub dev_dependencies: Vec<(UnprocessedBuckDependency, RawArtifactDependency)>,
+    pub build_dependencies: Vec<(UnprocessedBuckDependency, RawArtifactDependency)>,
+}
+
+impl UnprocessedArtifactDependencies {
+    fn from_raw(
+        raw: &RawArtifactDependencies,
+        process: &mut dyn for<'a> FnMut(
+            &'a BuckRuleParseOutput,
+        ) -> Option<UnprocessedBuckDependency>,
+    ) -> Self {
+        let RawArtifactDependencies {
+            dependencies,
+            dev_dependencies,
+            build_dependencies,
+        } = raw;
+        let mut from_raw = |section: &BTreeMap<BuckRuleParseOutput, RawArtifactDependency>| {
+            section
+                .iter()
+                .filter_map(|(rule, artifact)| Some((process(rule)?, artifact.clone())))
+                .collect()
+        };
+
+        Self {
+            dependencies: from_raw(dependencies),
+            dev_dependencies: from_raw(dev_dependencies),
+            build_dependencies: from_raw(build_dependencies),
+        }
+    }
+
+    fn process(
+        self,
+        logger: &'_ Logger,
+        all_raw_manifests: &HashMap<FbcodeBuckRule, (Arc<TargetsPath>, Arc<RawBuckManifest>)>,
+    ) -> ArtifactDependencies {
+        let Self {
+            dependencies,
+            dev_dependencies,
+            build_dependencies,
+        } = self;
+        let process = |section: Vec<(UnprocessedBuckDependency, RawArtifactDependency)>| {
+            section
+                .into_iter()
+                .filter_map(|(dep, artifact)| {
+                    Some((dep.process(logger, all_raw_manifests)?, artifact))
+                })
+                .collect()
+        };
+
+        ArtifactDependencies {
+            dependencies: process(dependencies),
+            dev_dependencies: process(dev_dependencies),
+            build_dependencies: process(build_dependencies),
         }
     }
 }
@@ -815,31 +1162,37 @@ impl UnprocessedBuckDependencyOverride {
 }
 
 /// Intermediate result of parsing dependencies of a manifest.
-#[derive(Debug, Eq, PartialEq)]
-enum UnprocessedBuckDependency {
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(super) enum UnprocessedBuckDependency {
     /// Name of a crate from registry.
     ThirdPartyCrate(String),
     /// Rule in fbcode which might be a rust rule, but isn't necessary. It has to
     /// be processed further by querying buck.
     FbcodeCrate(FbcodeBuckRule),
+    /// Fully qualified rule of another cell.
+    CellRule(String),
 }
 
 impl UnprocessedBuckDependency {
     /// Return fbcode rule if this is the right enum variant.
-    fn fbcode_crate(&self) -> Option<&FbcodeBuckRule> {
+    pub(super) fn fbcode_crate(&self) -> Option<&FbcodeBuckRule> {
         use UnprocessedBuckDependency::*;
         match self {
             FbcodeCrate(rule) => Some(rule),
-            ThirdPartyCrate(_) => None,
+            ThirdPartyCrate(_) | CellRule(_) => None,
         }
     }
 
     /// Given a BuckRuleParseOutput dependency turn it into Self if possible.
     /// `fbsource//third-party/rust:<crate>` is turned into ThirdPartyCrate.
     /// `[fbcode]//foo:bar` is turned into FbcodeCrate.
-    /// Other rules are ignored as they are not supported by this library.
-    fn try_from_rule(
-        logger: &'_ Logger,
+    /// Other fully qualified rules, e.g. `xplat//foo:bar`, are turned into
+    /// CellRule, they are ignored during generation unless mapped in the
+    /// cell_dependencies of the global config.
+    /// `[fbcode]//foo:bar[subtarget]` and `:foo[subtarget]` are turned into
+    /// FbcodeCrate of the rule generating the crate of the subtarget, see
+    /// [subtarget_rule_name].
+    pub(super) fn try_from_rule(
         targets_path: &'_ TargetsPath,
         rule: &'_ BuckRuleParseOutput,
     ) -> Option<Self> {
@@ -847,27 +1200,37 @@ impl UnprocessedBuckDependency {
         match rule {
             BuckRuleParseOutput::FullyQualified(rule)
                 if rule.repo() == "fbsource"
-                    && rule.path().as_path() == Path::new("third-party/rust") =>
+                    && rule.path().as_path() == Path::new("third-party/rust")
+                    && rule.subtarget().is_none() =>
             {
                 Some(ThirdPartyCrate(rule.name().clone()))
             }
-            BuckRuleParseOutput::FullyQualified(rule) => {
-                trace!(
-                    logger,
-                    "Build file at {}: This type of dependency is not supported: {:#?}",
-                    targets_path.as_dir().as_ref().display(),
-                    rule
-                );
-                None
-            }
+            BuckRuleParseOutput::FullyQualified(rule) => Some(CellRule(format!(
+                "{}//{}:{}{}",
+                rule.repo(),
+                rule.path().display(),
+                rule.name(),
+                rule.subtarget()
+                    .as_ref()
+                    .map_or_else(String::new, |subtarget| format!("[{subtarget}]")),
+            ))),
             BuckRuleParseOutput::FullyQualifiedInFbcode(rule) => Some(FbcodeCrate(rule.clone())),
-            BuckRuleParseOutput::RuleName(rule) => match &rule.subtarget {
-                Some(_subtarget) => None,
-                None => Some(FbcodeCrate(FbcodeBuckRule {
+            BuckRuleParseOutput::FullyQualifiedInFbcodeSubtarget(rule, subtarget) => {
+                Some(FbcodeCrate(FbcodeBuckRule {
+                    path: rule.path.clone(),
+                    name: subtarget_rule_name(&rule.name, subtarget)?,
+                }))
+            }
+            BuckRuleParseOutput::RuleName(rule) => {
+                let name = match &rule.subtarget {
+                    Some(subtarget) => subtarget_rule_name(&rule.name, subtarget)?,
+                    None => rule.name.clone(),
+                };
+                Some(FbcodeCrate(FbcodeBuckRule {
                     path: targets_path.clone(),
-                    name: rule.name.clone(),
-                })),
-            },
+                    name,
+                }))
+            }
         }
     }
 
@@ -883,6 +1246,7 @@ impl UnprocessedBuckDependency {
             UnprocessedBuckDependency::ThirdPartyCrate(name) => {
                 Some(BuckDependency::ThirdPartyCrate(name))
             }
+            UnprocessedBuckDependency::CellRule(rule) => Some(BuckDependency::CellRule(rule)),
             UnprocessedBuckDependency::FbcodeCrate(rule) => match all_raw_manifests.get(&rule) {
                 Some((path, raw_manifest)) => Some(BuckDependency::FbcodeCrate(
                     path.clone(),
@@ -900,12 +1264,31 @@ impl UnprocessedBuckDependency {
     }
 }
 
+/// Name of the rule generating the crate that the subtarget refers to. The
+/// rust library of thrift_library `foo` is `foo[rust]` or `foo-rust`, which is
+/// also the types crate, and its other crates are generated by the rules with
+/// `-clients`, `-services` and `-mocks` suffixes. Other subtargets, e.g.
+/// `[doc]`, don't refer to a crate, so None is returned for them.
+fn subtarget_rule_name(name: &str, subtarget: &str) -> Option<String> {
+    let library = if name.ends_with("-rust") {
+        name.to_owned()
+    } else {
+        format!("{name}-rust")
+    };
+    match subtarget {
+        "rust" | "types" => Some(library),
+        "clients" | "services" | "mocks" => Some(format!("{library}-{subtarget}")),
+        _ => None,
+    }
+}
+
 #[cfg(test)]
 mod test {
     #[cfg(unix)]
     use std::os::unix::process::ExitStatusExt;
     #[cfg(windows)]
     use std::os::windows::process::ExitStatusExt;
+    use std::path::PathBuf;
     use std::process::ExitStatus;
     use std::process::Output;
 
@@ -915,14 +1298,19 @@ mod test {
     use maplit::hashset;
     use mockall::Sequence;
     use serde_json::from_str;
+    use serde_json::from_value;
     use serde_json::json;
     use serde_json::to_vec;
     use slog::o;
 
     use super::*;
+    use crate::buck_processing::AutocargoField;
+    use crate::buck_processing::AutocargoProtobuf;
+    use crate::buck_processing::Diagnostic;
     use crate::buck_processing::rules::BuckRule;
     use crate::buck_processing::rules::RuleName;
     use crate::buck_processing::test_utils::TmpManifests;
+    use crate::config::BuckConfig;
     use crate::paths::PathInFbcode;
 
     fn tk(s: &str) -> TargetKey {
@@ -1021,6 +1409,19 @@ mod test {
                         UnprocessedBuckDependency::ThirdPartyCrate("fiz_windows".to_owned()),
                     ],
                 },
+                select_deps: btreemap! {
+                    "ovr_config//third-party/tls:openssl".to_owned() => vec![
+                        UnprocessedBuckDependency::ThirdPartyCrate("openssl".to_owned()),
+                        UnprocessedBuckDependency::FbcodeCrate(make_rule("cpp_openssl")),
+                    ],
+                },
+                build_script_deps: vec![
+                    UnprocessedBuckDependency::ThirdPartyCrate("build_foo".to_owned()),
+                    UnprocessedBuckDependency::FbcodeCrate(make_rule("cpp_build")),
+                ],
+                os_features: hashmap! {
+                    OsDepsPlatform::Linux => vec!["fiz/linux".to_owned()],
+                },
                 extra_buck_dependencies: UnprocessedExtraBuckDependencies {
                     deps: UnprocessedBuckTargetDependencies {
                         dependencies: vec![
@@ -1047,6 +1448,7 @@ mod test {
                             ..UnprocessedBuckTargetDependencies::default()
                         }
                     },
+                    artifacts: UnprocessedArtifactDependencies::default(),
                 },
             };
 
@@ -1061,6 +1463,9 @@ mod test {
                 test_named_deps: HashMap::new(),
                 extra_buck_dependencies: UnprocessedExtraBuckDependencies::default(),
                 test_os_deps: HashMap::new(),
+                select_deps: BTreeMap::new(),
+                build_script_deps: Vec::new(),
+                os_features: HashMap::new(),
             };
 
             Self {
@@ -1157,7 +1562,15 @@ mod test {
             compute_all_raw_manifests(
                 &logger,
                 &fbcode_root,
-                false, // use_isolation_dir
+                &ConcurrencyLimit::default(),
+                BuckOptions {
+                    config: &BuckConfig::default(),
+                    use_isolation_dir: false,
+                    show_output: false,
+                    fixtures: None,
+                    offline: false,
+                    cratemap_cache: None,
+                },
                 &hashmap! {
                     FbcodeBuckRule {
                         path: targets_path.clone(),
@@ -1232,6 +1645,57 @@ mod test {
         );
     }
 
+    #[test]
+    fn assign_companion_proc_macro_dirs_test() {
+        let logger = Logger::root(slog::Discard, o!());
+        let rule = |path: &str, name: &str| FbcodeBuckRule {
+            path: TargetsPath::new(PathInFbcode::new_mock(path)).unwrap(),
+            name: name.to_owned(),
+        };
+        let lib = |name: &str, proc_macro: bool, companion_dir: Option<&str>| {
+            let mut raw = RawBuckManifest::empty_test();
+            raw.name = name.to_owned();
+            raw.fbconfig_rule_type = RawFbconfigRuleType::RustLibrary;
+            raw.rust_config.proc_macro = proc_macro;
+            raw.autocargo.companion_proc_macro_dir = companion_dir.map(PathBuf::from);
+            raw
+        };
+
+        let mut raw_manifests = hashmap! {
+            rule("foo/TARGETS", "foo") => lib("foo", false, Some("derive")),
+            rule("foo/TARGETS", "foo_derive") => lib("foo_derive", true, None),
+            rule("bar/TARGETS", "bar_derive") => lib("bar_derive", true, None),
+        };
+        assign_companion_proc_macro_dirs(&logger, &mut raw_manifests).unwrap();
+        let dir = |raw_manifests: &HashMap<_, RawBuckManifest>, path, name| {
+            raw_manifests[&rule(path, name)]
+                .autocargo
+                .cargo_toml_dir
+                .clone()
+        };
+        assert_eq!(
+            dir(&raw_manifests, "foo/TARGETS", "foo_derive"),
+            PathBuf::from("derive")
+        );
+        assert_eq!(dir(&raw_manifests, "foo/TARGETS", "foo"), PathBuf::new());
+        assert_eq!(
+            dir(&raw_manifests, "bar/TARGETS", "bar_derive"),
+            PathBuf::new()
+        );
+
+        let mut raw_manifests = hashmap! {
+            rule("foo/TARGETS", "foo") => lib("foo", false, Some("derive")),
+            rule("foo/TARGETS", "foo_derive") => lib("foo_derive", true, None),
+            rule("foo/TARGETS", "foo_derive2") => lib("foo_derive2", true, None),
+        };
+        assert!(assign_companion_proc_macro_dirs(&logger, &mut raw_manifests).is_err());
+
+        let mut raw_manifests = hashmap! {
+            rule("foo/TARGETS", "foo") => lib("foo", true, Some("derive")),
+        };
+        assert!(assign_companion_proc_macro_dirs(&logger, &mut raw_manifests).is_err());
+    }
+
     #[test]
     fn extract_dependencies_test() {
         if cfg!(windows) {
@@ -1257,6 +1721,7 @@ mod test {
             make_rule("cpp_fiz"),
             make_rule("cpp_mac"),
             make_rule("cpp_biz"),
+            make_rule("cpp_build"),
             make_rule("extra_foo"),
             make_rule("extra_fiz"),
         ];
@@ -1305,6 +1770,7 @@ mod test {
         let ProcessOutput {
             processed_manifests,
             unprocessed_paths,
+            diagnostics: _,
         } = process_manifest_builders(
             &logger,
             hashmap! {
@@ -1320,6 +1786,7 @@ mod test {
             hashmap! {
                 processed_rule => "foocratemap".to_owned(),
             },
+            Diagnostics::default(),
         );
 
         assert_matches!(
@@ -1393,10 +1860,17 @@ mod test {
                 test_deps,
                 test_named_deps,
                 test_os_deps,
+                select_deps,
+                build_script_deps,
+                os_features,
                 thrift_config,
                 extra_buck_dependencies,
             } => {
                 assert_eq!(fbconfig_rule_type, FbconfigRuleType::RustBinary);
+                assert_eq!(
+                    os_features,
+                    hashmap! { OsDepsPlatform::Linux => vec!["fiz/linux".to_owned()] }
+                );
                 assert_matches!(
                     deps.into_iter().exactly_one(),
                     Ok(BuckDependency::ThirdPartyCrate(name)) => {
@@ -1453,6 +1927,24 @@ mod test {
                         );
                     }
                 );
+                assert_matches!(
+                    select_deps.into_iter().exactly_one(),
+                    Ok((constraint, deps)) => {
+                        assert_eq!(&constraint, "ovr_config//third-party/tls:openssl");
+                        assert_matches!(
+                            deps.into_iter().exactly_one(),
+                            Ok(BuckDependency::ThirdPartyCrate(name)) => {
+                                assert_eq!(&name, "openssl")
+                            }
+                        );
+                    }
+                );
+                assert_matches!(
+                    build_script_deps.into_iter().exactly_one(),
+                    Ok(BuckDependency::ThirdPartyCrate(name)) => {
+                        assert_eq!(&name, "build_foo")
+                    }
+                );
                 let ThriftConfig {
                     cratemap_content,
                     thrift_compiler,
@@ -1469,7 +1961,8 @@ mod test {
                             dev_dependencies,
                             build_dependencies,
                         },
-                        target
+                        target,
+                        artifacts: _,
                     } => {
                         assert_matches!(
                             dependencies.into_iter().exactly_one(),
@@ -1504,9 +1997,78 @@ mod test {
         );
     }
 
+    #[test]
+    fn buck_manifest_builder_test_protobuf() {
+        let mut diagnostics = Diagnostics::default();
+        let targets_path = TargetsPath::new(PathInFbcode::new_mock("foo/TARGETS")).unwrap();
+        let manifest = |rule_type, protobuf| RawBuckManifest {
+            name: "foo".to_owned(),
+            fbconfig_rule_type: rule_type,
+            autocargo: AutocargoField {
+                protobuf,
+                ..AutocargoField::default()
+            },
+            ..RawBuckManifest::empty_test()
+        };
+        let protobuf = || {
+            from_value::<AutocargoProtobuf>(json!({
+                "proto_srcs": ["foo.proto"],
+                "build_deps": ["fbsource//third-party/rust:prost-build"],
+            }))
+            .unwrap()
+        };
+
+        assert_matches!(
+            BuckManifestBuilder::from_raw_manifest(
+                &mut diagnostics,
+                &targets_path,
+                manifest(RawFbconfigRuleType::RustProtobufLibrary, Some(protobuf())),
+            ),
+            Some(BuckManifestBuilder {
+                fbconfig_rule_type: FbconfigRuleType::RustLibrary,
+                build_script_deps,
+                ..
+            }) => {
+                assert_eq!(
+                    build_script_deps,
+                    vec![UnprocessedBuckDependency::ThirdPartyCrate("prost-build".to_owned())]
+                );
+            }
+        );
+        assert!(diagnostics.sorted().is_empty());
+
+        assert!(
+            BuckManifestBuilder::from_raw_manifest(
+                &mut diagnostics,
+                &targets_path,
+                manifest(RawFbconfigRuleType::RustProtobufLibrary, None),
+            )
+            .is_none()
+        );
+        assert!(
+            BuckManifestBuilder::from_raw_manifest(
+                &mut diagnostics,
+                &targets_path,
+                manifest(RawFbconfigRuleType::RustLibrary, Some(protobuf())),
+            )
+            .is_none()
+        );
+        assert_eq!(
+            diagnostics
+                .sorted()
+                .into_iter()
+                .map(|diagnostic| diagnostic.reason.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "autocargo.protobuf config is supported only on rust_protobuf_library rules",
+                "rust_protobuf_library rule has no autocargo.protobuf config",
+            ]
+        );
+    }
+
     #[test]
     fn buck_manifest_builder_test_from_raw_manifest() {
-        let logger = Logger::root(slog::Discard, o!());
+        let mut diagnostics = Diagnostics::default();
         let targets_path = TargetsPath::new(PathInFbcode::new_mock(
             "common/rust/cargo_from_buck/autocargo/TARGETS",
         ))
@@ -1519,7 +2081,7 @@ mod test {
             .unwrap();
 
             assert_matches!(
-                BuckManifestBuilder::from_raw_manifest(&logger, &targets_path, manifest),
+                BuckManifestBuilder::from_raw_manifest(&mut diagnostics, &targets_path, manifest),
                 Some(BuckManifestBuilder {
                     raw,
                     deps,
@@ -1550,7 +2112,16 @@ mod test {
             manifest.fbconfig_rule_type = RawFbconfigRuleType::RustBindgenLibrary;
 
             assert!(
-                BuckManifestBuilder::from_raw_manifest(&logger, &targets_path, manifest).is_none()
+                BuckManifestBuilder::from_raw_manifest(&mut diagnostics, &targets_path, manifest)
+                    .is_none()
+            );
+            assert_eq!(
+                diagnostics.sorted(),
+                vec![&Diagnostic {
+                    rule: "fbcode//common/rust/cargo_from_buck/autocargo:autocargo".to_owned(),
+                    reason: "Rule type RustBindgenLibrary is not supported".to_owned(),
+                    severity: Severity::Warning,
+                }]
             );
         }
 
@@ -1591,16 +2162,28 @@ mod test {
                     })],
                 ),
             ];
+            manifest.rust_config.os_features = vec![
+                (RawOsDepsPlatform::Other, vec!["not_supported".to_owned()]),
+                (RawOsDepsPlatform::Macos, vec!["foo".to_owned()]),
+                (RawOsDepsPlatform::Macos, vec!["bar/baz".to_owned()]),
+            ];
 
             assert_matches!(
-                BuckManifestBuilder::from_raw_manifest(&logger, &targets_path, manifest),
+                BuckManifestBuilder::from_raw_manifest(&mut diagnostics, &targets_path, manifest),
                 Some(BuckManifestBuilder {
                     raw,
                     deps,
                     test_deps,
                     os_deps,
+                    os_features,
                     ..
                 }) => {
+                    assert_eq!(
+                        os_features,
+                        hashmap! {
+                            OsDepsPlatform::Macos => vec!["foo".to_owned(), "bar/baz".to_owned()],
+                        }
+                    );
                     assert_eq!(&raw.name, "autocargo_lib");
                     {
                         let expected = UnprocessedBuckDependency::ThirdPartyCrate(
@@ -1640,9 +2223,8 @@ mod test {
 
     #[test]
     fn unprocessed_buck_dependency_test_try_from_rule() {
-        let logger = Logger::root(slog::Discard, o!());
         let targets_path = TargetsPath::new(PathInFbcode::new_mock("foo/bar/TARGETS")).unwrap();
-        let test = |rule| UnprocessedBuckDependency::try_from_rule(&logger, &targets_path, &rule);
+        let test = |rule| UnprocessedBuckDependency::try_from_rule(&targets_path, &rule);
 
         {
             let test = |(repo, path, name)| {
@@ -1662,8 +2244,12 @@ mod test {
                 .collect::<Vec<_>>(),
                 vec![
                     Some(UnprocessedBuckDependency::ThirdPartyCrate("biz".to_owned())),
-                    None,
-                    None,
+                    Some(UnprocessedBuckDependency::CellRule(
+                        "fbsource//foo/bar:biz".to_owned()
+                    )),
+                    Some(UnprocessedBuckDependency::CellRule(
+                        "xplat//third-party/rust:biz".to_owned()
+                    )),
                 ],
             );
         }
@@ -1677,20 +2263,70 @@ mod test {
                 test(BuckRuleParseOutput::FullyQualifiedInFbcode(
                     fbcode_rule.clone()
                 )),
-                Some(UnprocessedBuckDependency::FbcodeCrate(fbcode_rule)),
-            )
+                Some(UnprocessedBuckDependency::FbcodeCrate(fbcode_rule.clone())),
+            );
+
+            let test = |rule: &str| test(rule.parse().unwrap());
+            let fbcode_crate = |name: &str| {
+                Some(UnprocessedBuckDependency::FbcodeCrate(FbcodeBuckRule {
+                    name: name.to_owned(),
+                    ..fbcode_rule.clone()
+                }))
+            };
+            assert_eq!(test("//fiz/biz:foobar[rust]"), fbcode_crate("foobar-rust"));
+            assert_eq!(
+                test("fbcode//fiz/biz:foobar[clients]"),
+                fbcode_crate("foobar-rust-clients"),
+            );
+            assert_eq!(test("//fiz/biz:foobar[doc]"), None);
+            assert_eq!(
+                test("fbsource//third-party/rust:biz[doc]"),
+                Some(UnprocessedBuckDependency::CellRule(
+                    "fbsource//third-party/rust:biz[doc]".to_owned()
+                )),
+            );
         }
 
-        assert_eq!(
-            test(BuckRuleParseOutput::RuleName(RuleName {
-                name: "biz".to_owned(),
-                subtarget: None,
-            })),
-            Some(UnprocessedBuckDependency::FbcodeCrate(FbcodeBuckRule {
-                path: targets_path.clone(),
-                name: "biz".to_owned(),
-            })),
-        )
+        {
+            let test = |(name, subtarget): (&str, Option<&str>)| {
+                test(BuckRuleParseOutput::RuleName(RuleName {
+                    name: name.to_owned(),
+                    subtarget: subtarget.map(str::to_owned),
+                }))
+            };
+            let fbcode_crate = |name: &str| {
+                Some(UnprocessedBuckDependency::FbcodeCrate(FbcodeBuckRule {
+                    path: targets_path.clone(),
+                    name: name.to_owned(),
+                }))
+            };
+
+            assert_eq!(
+                vec![
+                    ("biz", None),
+                    ("if", Some("rust")),
+                    ("if", Some("types")),
+                    ("if", Some("clients")),
+                    ("if-rust", Some("types")),
+                    ("if-rust", Some("services")),
+                    ("if-rust", Some("mocks")),
+                    ("biz", Some("doc")),
+                ]
+                .into_iter()
+                .map(test)
+                .collect::<Vec<_>>(),
+                vec![
+                    fbcode_crate("biz"),
+                    fbcode_crate("if-rust"),
+                    fbcode_crate("if-rust"),
+                    fbcode_crate("if-rust-clients"),
+                    fbcode_crate("if-rust"),
+                    fbcode_crate("if-rust-services"),
+                    fbcode_crate("if-rust-mocks"),
+                    None,
+                ],
+            );
+        }
     }
 
     #[test]
@@ -1747,6 +2383,77 @@ mod test {
         );
     }
 
+    #[test]
+    fn unprocessed_extra_buck_dependencies_test_artifacts() {
+        let logger = Logger::root(slog::Discard, o!());
+        let targets_path = TargetsPath::new(PathInFbcode::new_mock("foo/bar/TARGETS")).unwrap();
+        let raw: RawExtraBuckDependencies = serde_json::from_value(json!({
+            "build-dependencies": ["fbsource//third-party/rust:cc"],
+            "artifact_dependencies": {
+                "build-dependencies": {
+                    ":autocargo": {"target": "target"},
+                    ":some_cpp_rule": {},
+                },
+                "dev-dependencies": {
+                    "fbsource//third-party/rust:tool": {"artifact": ["bin:a", "bin:b"], "lib": true},
+                },
+            },
+        }))
+        .unwrap();
+        let extra = UnprocessedExtraBuckDependencies::from_raw(&raw, &mut |rule| {
+            UnprocessedBuckDependency::try_from_rule(&targets_path, rule)
+        });
+        assert_eq!(
+            extra.fbcode_crates().collect::<Vec<_>>(),
+            vec![
+                &FbcodeBuckRule {
+                    path: targets_path.clone(),
+                    name: "autocargo".to_owned(),
+                },
+                &FbcodeBuckRule {
+                    path: targets_path.clone(),
+                    name: "some_cpp_rule".to_owned(),
+                },
+            ]
+        );
+
+        let mut manifest = RawBuckManifest::empty_test();
+        manifest.name = "autocargo".to_owned();
+        let all_test_manifests = hashmap! {
+            FbcodeBuckRule {
+                path: targets_path.clone(),
+                name: "autocargo".to_owned(),
+            } => (Arc::new(targets_path.clone()), Arc::new(manifest)),
+        };
+        let ExtraBuckDependencies {
+            deps,
+            target: _,
+            artifacts,
+        } = extra.process(&logger, &all_test_manifests);
+        assert_eq!(deps.build_dependencies.len(), 2);
+        assert_eq!(deps.dev_dependencies.len(), 1);
+        assert_matches!(
+            artifacts.build_dependencies.into_iter().exactly_one(),
+            Ok((BuckDependency::FbcodeCrate(_, manifest), artifact)) => {
+                assert_eq!(&manifest.name, "autocargo");
+                assert_eq!(artifact, RawArtifactDependency {
+                    artifact: Vec::new(),
+                    target: Some("target".to_owned()),
+                    lib: false,
+                });
+            }
+        );
+        assert_matches!(
+            artifacts.dev_dependencies.into_iter().exactly_one(),
+            Ok((BuckDependency::ThirdPartyCrate(name), artifact)) => {
+                assert_eq!(&name, "tool");
+                assert_eq!(artifact.artifact, vec!["bin:a".to_owned(), "bin:b".to_owned()]);
+                assert!(artifact.lib);
+            }
+        );
+        assert!(artifacts.dependencies.is_empty());
+    }
+
     #[test]
     fn os_deps_platform_test_to_cargo_target_valid_returns() {
         for platform in enum_iterator::all::<OsDepsPlatform>() {
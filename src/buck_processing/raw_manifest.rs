@@ -27,6 +27,7 @@ use thrift_compiler::GenContext;
 use super::rules::BuckRuleParseOutput;
 use crate::cargo_manifest::Product;
 use crate::cargo_manifest::TargetKey;
+use crate::config::FeaturePropagation;
 use crate::config::PatchGeneration;
 use crate::config::PatchGenerationInput;
 
@@ -42,6 +43,10 @@ pub enum RawFbconfigRuleType {
     RustUnittest,
     /// Bindgen generated library
     RustBindgenLibrary,
+    /// Library with cxx bridges to C++ code
+    RustCxxLibrary,
+    /// Library with code generated from proto files by prost
+    RustProtobufLibrary,
     /// Unknown rule type
     #[serde(other)]
     Other,
@@ -100,6 +105,13 @@ pub struct RawBuckManifestRustConfig {
     /// Features that are always enabled for this crate.
     #[serde(deserialize_with = "default_on_null::deserialize")]
     pub features: Vec<String>,
+    /// Features that are enabled only when building for the given platform,
+    /// i.e. the platform specific branches of a select() on features. Features
+    /// of dependencies ("dep/feature") are activated in the corresponding
+    /// [target.'cfg(...)'.dependencies] section, while features of the crate
+    /// itself are only declared, because Cargo can't enable them per platform.
+    #[serde(default, deserialize_with = "default_on_null::deserialize")]
+    pub os_features: Vec<(RawOsDepsPlatform, Vec<String>)>,
     /// Usually name of the crate is the same as name of rule, but this field
     /// lets you change it.
     #[serde(rename = "crate")]
@@ -118,6 +130,10 @@ pub struct RawBuckManifestRustConfig {
     pub test_features: Vec<String>,
     /// Edition of Rust that this crate uses.
     pub edition: Option<Edition>,
+    /// Extra flags passed to rustc. Only `--cfg` flags are supported by
+    /// Cargo, via a generated build script.
+    #[serde(default, deserialize_with = "default_on_null::deserialize")]
+    pub rustc_flags: Vec<String>,
 }
 
 /// Group of attributes configuring sources of build.
@@ -162,6 +178,12 @@ pub struct RawBuckManifestDependencies {
     /// Extra platform deps for unittests.
     #[serde(deserialize_with = "default_on_null::deserialize")]
     pub test_os_deps: Vec<(RawOsDepsPlatform, Vec<BuckRuleParseOutput>)>,
+    /// Dependencies from branches of select() keyed on custom constraints, as
+    /// pairs of the constraint value and the dependencies of its branch. See
+    /// [crate::config::ProjectConf::select_features] for how they are mapped
+    /// into Cargo features.
+    #[serde(default, deserialize_with = "default_on_null::deserialize")]
+    pub select_deps: Vec<(String, Vec<BuckRuleParseOutput>)>,
 }
 
 /// Autocargo field used for fine-tuning autocargo generation per buck rule.
@@ -172,6 +194,12 @@ pub struct AutocargoField {
     /// current TARGETS file.
     #[serde(default)]
     pub cargo_toml_dir: PathBuf,
+    /// Only for rust_library rules. Folder relative to cargo_toml_dir of this
+    /// rule where the proc-macro rule from the same TARGETS file that would
+    /// otherwise map into the same Cargo.toml file is generated. This way a
+    /// library re-exporting its proc-macro sibling doesn't require configuring
+    /// cargo_toml_dir on the proc-macro rule.
+    pub companion_proc_macro_dir: Option<PathBuf>,
     /// If true do not generate Cargo.toml for this rule and treat it as
     /// non-existing as a dependency.
     pub ignore_rule: bool,
@@ -185,6 +213,15 @@ pub struct AutocargoField {
     pub cargo_target_config: AutocargoTargetConfig,
     /// Present only for thrift_library rules, contains thrift-specific configs.
     pub thrift: Option<AutocargoThrift>,
+    /// Present only for rust_protobuf_library rules, contains
+    /// protobuf-specific configs.
+    pub protobuf: Option<AutocargoProtobuf>,
+    /// Present only for rust_cxx_library rules, contains configs of the cxx
+    /// bridges.
+    pub cxx: Option<AutocargoCxx>,
+    /// Checked-in build script of the crate generated from this rule together
+    /// with its dependencies, which are generated into [build-dependencies].
+    pub build_script: Option<AutocargoBuildScript>,
 }
 
 /// Configuration for the whole Cargo.toml file generated. Based on
@@ -208,7 +245,9 @@ pub struct AutocargoCargoTomlConfig {
     /// extra dependencies to your generated Cargo.toml file that are not
     /// included in Buck or even delete some of the dependencies that Buck has,
     /// but Cargo shouldn't. Note that this enables you to add build-dependencies
-    /// which don't exist in Buck.
+    /// which don't exist in Buck. Removing a dependency that Buck doesn't have
+    /// is reported, or fails the generation if the project sets
+    /// strict_removed_dependencies.
     ///
     /// Check examples in dependencies_override documentation.
     pub extra_buck_dependencies: RawExtraBuckDependencies,
@@ -240,7 +279,10 @@ pub struct AutocargoCargoTomlConfig {
     ///             },
     ///             "dependencies_override": {
     ///                 "dependencies": {
-    ///                      "biz": { "version": "0.4.2" },
+    ///                      "biz": {
+    ///                          "version": "0.4.2",
+    ///                          "comment": "Newer versions need a newer rustc.",
+    ///                      },
     ///                      "foobar": { "features": ["foo2"] },
     ///                 },
     ///             },
@@ -259,6 +301,7 @@ pub struct AutocargoCargoTomlConfig {
     /// edition = "2021"
     ///
     /// [dependencies]
+    /// # Newer versions need a newer rustc.
     /// biz = "0.4.2"
     /// foobar = { package = "fiz", "path" = "../bar/fiz", "features": ["foo2"] }
     /// ```
@@ -276,6 +319,11 @@ pub struct AutocargoCargoTomlConfig {
     pub dependencies_override: DependenciesOverride,
     /// Features for the crate.
     pub features: Option<FeatureSet>,
+    /// Documentation of features, keyed by feature name. Each entry is emitted
+    /// as `## ` comment lines above the feature in the generated [features]
+    /// table, which is the format understood by e.g. the document-features
+    /// crate.
+    pub feature_docs: BTreeMap<String, String>,
     /// This field is to allow defining a lib section in Cargo.toml file when it
     /// is not generated from Buck already. If you are looking for a way to
     /// modify fields of an existing generated library section then use
@@ -287,13 +335,13 @@ pub struct AutocargoCargoTomlConfig {
     /// This field is to allow defining extra tests in Cargo.toml file that are
     /// not generated from any buck rule.
     pub test: Vec<Product>,
-    /// Currently there aren't any Buck rules that would be generated into a
-    /// benchmark rule, since Buck doesn't run benchmarks. This field is to allow
-    /// defining benchmarks in Cargo.toml file.
+    /// Benchmarks are generated from rust_unittest rules marked with
+    /// autocargo.cargo_target_config.bench = true. This field is to allow
+    /// defining extra benchmarks in Cargo.toml file.
     pub bench: Vec<Product>,
-    /// Currently there aren't any Buck rules that would be generated into an
-    /// example rule. This field is to allow defining examples in Cargo.toml
-    /// file.
+    /// Examples are generated from rust_binary rules in the examples/ dir or
+    /// with autocargo.cargo_target_config.product_type = "example". This field
+    /// is to allow defining extra examples in Cargo.toml file.
     pub example: Vec<Product>,
     /// How to generate the [patch] section for the crate.
     pub patch_generation: Option<PatchGeneration>,
@@ -327,6 +375,27 @@ pub struct AutocargoCargoTomlConfig {
     /// ```
     #[serde(default)]
     pub lints: BTreeMap<String, Value>,
+    /// The [badges] section, each badge is written as an inline table.
+    ///
+    /// ```text
+    /// "badges": {
+    ///     "maintenance": {"status": "actively-developed"},
+    /// }
+    /// ```
+    #[serde(default)]
+    pub badges: BTreeMap<String, Value>,
+    /// Configuration of Cargo tools like cargo-deny, cargo-udeps or cargo-vet,
+    /// keyed by the name of the tool. Each value is emitted as
+    /// `[package.metadata.<tool>]`, the tool must not be configured in
+    /// package.metadata as well.
+    ///
+    /// ```text
+    /// "package_metadata_tools": {
+    ///     "cargo-udeps": {"ignore": {"normal": ["log"]}},
+    /// }
+    /// ```
+    #[serde(default)]
+    pub package_metadata_tools: BTreeMap<String, Value>,
 }
 
 /// Cargo package configuration, based on [::cargo_toml::Package] and extended by
@@ -338,7 +407,7 @@ pub struct AutocargoCargoTomlConfig {
 ///   left undefined ignoring the defaults, e.g. authors: Some(vec![]) leaves
 ///   authors unspecified
 /// - Some(Some(T)) or Some(T) - sets field to T
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
 #[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
 #[allow(missing_docs)]
 pub struct AutocargoPackageConfig {
@@ -376,43 +445,13 @@ pub struct AutocargoPackageConfig {
     #[serde(with = "double_option")]
     pub metadata: Option<Option<Value>>,
     pub default_run: Option<String>,
-    pub autobins: bool,
-    pub autoexamples: bool,
-    pub autotests: bool,
-    pub autobenches: bool,
-}
-
-impl Default for AutocargoPackageConfig {
-    fn default() -> Self {
-        Self {
-            name: None,
-            version: None,
-            authors: None,
-            edition: None,
-            rust_version: None,
-            description: None,
-            documentation: None,
-            readme: None,
-            homepage: None,
-            repository: None,
-            license: None,
-            license_file: None,
-            keywords: None,
-            categories: None,
-            workspace: None,
-            build: None,
-            links: None,
-            exclude: None,
-            include: None,
-            publish: None,
-            metadata: None,
-            default_run: None,
-            autobins: true,
-            autoexamples: true,
-            autotests: true,
-            autobenches: true,
-        }
-    }
+    /// If None then autodiscovery is enabled unless the project sets
+    /// disable_autodiscovery and the package has explicit bins, same for the
+    /// other products below.
+    pub autobins: Option<bool>,
+    pub autoexamples: Option<bool>,
+    pub autotests: Option<bool>,
+    pub autobenches: Option<bool>,
 }
 
 /// Those are some extra dependencies structured like Cargo dependencies.
@@ -426,6 +465,36 @@ pub struct RawExtraBuckDependencies {
     /// For overriding target dependencies. Since the key is an arbitrary string
     /// you can both override RawOsDepsPlatform targets and create new ones.
     pub target: BTreeMap<TargetKey, RawBuckTargetDependencies>,
+    /// Buck rules of binaries to add as Cargo artifact dependencies, i.e.
+    /// `{ artifact = "bin", ... }`, so that e.g. build scripts or tests can
+    /// run the binaries built by Cargo. Projects using them get
+    /// `unstable.bindeps = true` added to their generated cargo config.
+    pub artifact_dependencies: RawArtifactDependencies,
+}
+
+/// Artifact dependencies per section of the Cargo.toml file.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+#[allow(missing_docs)]
+pub struct RawArtifactDependencies {
+    pub dependencies: BTreeMap<BuckRuleParseOutput, RawArtifactDependency>,
+    pub dev_dependencies: BTreeMap<BuckRuleParseOutput, RawArtifactDependency>,
+    pub build_dependencies: BTreeMap<BuckRuleParseOutput, RawArtifactDependency>,
+}
+
+/// The keys of a Cargo artifact dependency, on top of the ones generated for
+/// the Buck rule.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct RawArtifactDependency {
+    /// Kinds of artifacts to depend on, e.g. "bin", "bin:<name>", "cdylib"
+    /// or "staticlib". Empty means "bin".
+    pub artifact: Vec<String>,
+    /// Target triple to build the artifacts for, "target" for the one the
+    /// crate is built for. By default it is the host for build-dependencies.
+    pub target: Option<String>,
+    /// Whether the crate can use the library of the dependency too.
+    pub lib: bool,
 }
 
 /// Structure for overriding dependencies, dev-dependencies and
@@ -505,6 +574,11 @@ pub struct CargoDependencyOverride {
     pub default_features: Option<bool>,
     #[serde(with = "double_option")]
     pub package: Option<Option<String>>,
+    /// Justification of the override, e.g. why the dependency is pinned or
+    /// re-added, emitted as a comment above the dependency in the generated
+    /// Cargo.toml. To justify an entry of extra_buck_dependencies add an
+    /// override with only the comment for the dependency it adds.
+    pub comment: Option<String>,
 }
 
 /// Configuration for the library/binary/test/bench that is generated directly
@@ -520,6 +594,8 @@ pub struct AutocargoTargetConfig {
     pub test: Option<Option<bool>>,
     #[serde(with = "double_option")]
     pub doctest: Option<Option<bool>>,
+    /// For rust_unittest rules true generates a [[bench]] instead of a [[test]],
+    /// with harness = false if the rule depends on criterion.
     pub bench: Option<bool>,
     pub doc: Option<bool>,
     pub plugin: bool,
@@ -528,7 +604,28 @@ pub struct AutocargoTargetConfig {
     #[serde(with = "double_option")]
     pub edition: Option<Option<Edition>>,
     pub crate_type: Vec<String>,
+    /// Crate types built by Buck only for some platforms, e.g. a cdylib only
+    /// on linux. Cargo has no per-platform crate-type, so they are added to
+    /// crate_type of the [lib] for all platforms with a comment listing the
+    /// platforms they are meant for.
+    pub os_crate_type: Vec<(RawOsDepsPlatform, Vec<String>)>,
     pub required_features: Vec<String>,
+    /// Overrides feature_propagation of the project for bin and test rules.
+    pub feature_propagation: Option<FeaturePropagation>,
+    /// Kind of product generated from a rust_binary rule. If None then binaries
+    /// whose crate root is in the examples/ dir next to Cargo.toml are
+    /// generated as [[example]], others as [[bin]].
+    pub product_type: Option<BinaryProductType>,
+}
+
+/// Kind of product that a rust_binary rule is generated into.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum BinaryProductType {
+    /// A [[bin]] section.
+    Bin,
+    /// An [[example]] section.
+    Example,
 }
 
 impl Default for AutocargoTargetConfig {
@@ -545,7 +642,10 @@ impl Default for AutocargoTargetConfig {
             harness: true,
             edition: None,
             crate_type: Vec::new(),
+            os_crate_type: Vec::new(),
             required_features: Vec::new(),
+            feature_propagation: None,
+            product_type: None,
         }
     }
 }
@@ -601,6 +701,59 @@ pub struct AutocargoThriftOptions {
     pub more_options: BTreeMap<String, Option<String>>,
 }
 
+/// Protobuf-specific configs that should be passed to prost-build.
+#[derive(Debug, Deserialize, Eq, PartialEq)]
+pub struct AutocargoProtobuf {
+    /// List of proto files to compile, relative to the TARGETS file.
+    pub proto_srcs: Vec<String>,
+    /// Dependencies of the generated build script compiling the proto files,
+    /// in the same format as deps of the rule. They have to include the
+    /// prost-build compiler, e.g. fbsource//third-party/rust:prost-build,
+    /// while the prost runtime is taken from the deps of the rule.
+    pub build_deps: Vec<BuckRuleParseOutput>,
+    /// Directories searched for imported proto files, relative to the TARGETS
+    /// file. If empty the directory of the TARGETS file is used.
+    #[serde(default)]
+    pub includes: Vec<String>,
+    /// Map of fully qualified protobuf paths to Rust paths for messages that
+    /// are generated by other crates, e.g. ".foo.bar" => "::foo_proto::bar".
+    /// See prost_build::Config::extern_path.
+    #[serde(default)]
+    pub extern_paths: BTreeMap<String, String>,
+}
+
+/// Cxx-specific configs that should be passed to cxx-build.
+#[derive(Debug, Deserialize, Eq, PartialEq)]
+pub struct AutocargoCxx {
+    /// Rust files containing #[cxx::bridge] modules, relative to the TARGETS
+    /// file.
+    pub bridges: Vec<String>,
+    /// C++ files compiled together with the generated bridge code, relative to
+    /// the TARGETS file.
+    #[serde(default)]
+    pub srcs: Vec<String>,
+    /// Include directories for the C++ compilation, relative to the TARGETS
+    /// file.
+    #[serde(default)]
+    pub includes: Vec<String>,
+    /// C++ standard to compile with, e.g. "c++17".
+    pub std: Option<String>,
+}
+
+/// Build script that is checked in next to the sources of a rule, like build.rs.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AutocargoBuildScript {
+    /// Source files of the build script, relative to the TARGETS file. The
+    /// first of them is the entry point that is put in package.build unless
+    /// cargo_toml_config.package.build is set.
+    pub srcs: Vec<PathBuf>,
+    /// Dependencies of the build script, in the same format as deps of the
+    /// rule.
+    #[serde(default)]
+    pub deps: Vec<BuckRuleParseOutput>,
+}
+
 #[cfg(test)]
 impl RawBuckManifest {
     pub fn empty_test() -> RawBuckManifest {
@@ -609,12 +762,14 @@ impl RawBuckManifest {
             fbconfig_rule_type: RawFbconfigRuleType::RustBinary,
             rust_config: RawBuckManifestRustConfig {
                 features: Vec::new(),
+                os_features: Vec::new(),
                 crate_: None,
                 crate_root: None,
                 unittests: true,
                 proc_macro: false,
                 test_features: Vec::new(),
                 edition: None,
+                rustc_flags: Vec::new(),
             },
             sources: RawBuckManifestSources {
                 srcs: Vec::new(),
@@ -629,6 +784,7 @@ impl RawBuckManifest {
                 test_deps: Vec::new(),
                 test_named_deps: HashMap::new(),
                 test_os_deps: Vec::new(),
+                select_deps: Vec::new(),
             },
             autocargo: AutocargoField::default(),
         }
@@ -665,6 +821,10 @@ mod test {
             parse("rust_bindgen_library"),
             Ok(RawFbconfigRuleType::RustBindgenLibrary)
         );
+        assert_matches!(
+            parse("rust_cxx_library"),
+            Ok(RawFbconfigRuleType::RustCxxLibrary)
+        );
         assert_matches!(parse("rust_unknown"), Ok(RawFbconfigRuleType::Other));
     }
 
@@ -788,6 +948,56 @@ mod test {
         );
     }
 
+    #[test]
+    fn autocargo_field_test_protobuf() {
+        assert_matches!(
+            from_value::<AutocargoField>(json!({
+                "protobuf": {
+                    "proto_srcs": ["foo.proto", "bar/bar.proto"],
+                    "build_deps": ["fbsource//third-party/rust:prost-build"],
+                    "extern_paths": {".common": "::common_proto"},
+                }
+            })),
+            Ok(field) => {
+                assert_eq!(field.thrift, None);
+                assert_eq!(field.protobuf, Some(AutocargoProtobuf {
+                    proto_srcs: vec!["foo.proto".to_owned(), "bar/bar.proto".to_owned()],
+                    build_deps: vec![BuckRuleParseOutput::FullyQualified(BuckRule::new_mock(
+                        "fbsource",
+                        "third-party/rust",
+                        "prost-build",
+                    ))],
+                    includes: Vec::new(),
+                    extern_paths: btreemap! {
+                        ".common".to_owned() => "::common_proto".to_owned(),
+                    },
+                }));
+            }
+        );
+    }
+
+    #[test]
+    fn autocargo_field_test_cxx() {
+        assert_matches!(
+            from_value::<AutocargoField>(json!({
+                "cxx": {
+                    "bridges": ["src/ffi.rs"],
+                    "srcs": ["src/foo.cpp"],
+                    "std": "c++17",
+                }
+            })),
+            Ok(field) => {
+                assert_eq!(field.protobuf, None);
+                assert_eq!(field.cxx, Some(AutocargoCxx {
+                    bridges: vec!["src/ffi.rs".to_owned()],
+                    srcs: vec!["src/foo.cpp".to_owned()],
+                    includes: Vec::new(),
+                    std: Some("c++17".to_owned()),
+                }));
+            }
+        );
+    }
+
     #[test]
     fn autocargo_field_test_types_include_srcs() {
         assert_matches!(
@@ -42,6 +42,7 @@ pub struct BuckRule {
     repo: String,
     path: PathBuf,
     name: String,
+    subtarget: Option<String>,
 }
 
 impl BuckRule {
@@ -55,6 +56,7 @@ impl BuckRule {
             repo: repo.into(),
             path: path.into(),
             name: name.into(),
+            subtarget: None,
         }
     }
 }
@@ -93,6 +95,8 @@ pub enum BuckRuleParseOutput {
     FullyQualified(BuckRule),
     /// Example rule: //common/rust/foo:bar
     FullyQualifiedInFbcode(FbcodeBuckRule),
+    /// Example rule: //common/rust/foo:bar[rust]
+    FullyQualifiedInFbcodeSubtarget(FbcodeBuckRule, String),
     /// Example rule: :foobar
     RuleName(RuleName),
 }
@@ -144,7 +148,7 @@ impl TryFrom<BuckManifestRule> for FbcodeBuckRule {
 /// This type is used to distinguish between buck rules of unknown type (like
 /// rust rules, but also c++, python or thrift etc.) and rules that point to
 /// thrift cratemap rules.
-#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+#[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct ThriftCratemapRule {
     path: TargetsPath,
     name_of_library: String,
@@ -200,10 +204,14 @@ mod parsing {
 
     // Based on https://buck.build/concept/build_target.html
     static BUCK_FULLY_QUALIFIED_REGEX: LazyLock<Regex> = LazyLock::new(|| {
-        Regex::new(r"^([A-Za-z0-9._-]+)//([A-Za-z0-9/._-]*):([A-Za-z0-9_/.=,@~+-]+)$").unwrap()
+        Regex::new(
+            r"^([A-Za-z0-9._-]+)//([A-Za-z0-9/._-]*):([A-Za-z0-9_/.=,@~+-]+)(?:\[([a-z_]+)\])?$",
+        )
+        .unwrap()
     });
     static BUCK_FULLY_QUALIFIED_IN_FBCODE_REGEX: LazyLock<Regex> = LazyLock::new(|| {
-        Regex::new(r"^(?:fbcode)?//([A-Za-z0-9/._-]*):([A-Za-z0-9_/.=,@~+-]+)$").unwrap()
+        Regex::new(r"^(?:fbcode)?//([A-Za-z0-9/._-]*):([A-Za-z0-9_/.=,@~+-]+)(?:\[([a-z_]+)\])?$")
+            .unwrap()
     });
     static BUCK_RULE_NAME_REGEX: LazyLock<Regex> =
         LazyLock::new(|| Regex::new(r"^:([A-Za-z0-9_/.=,@~+-]+)(?:\[([a-z_]+)\])?$").unwrap());
@@ -213,17 +221,23 @@ mod parsing {
 
         fn from_str(s: &str) -> Result<Self, Self::Err> {
             if let Some(captures) = BUCK_FULLY_QUALIFIED_IN_FBCODE_REGEX.captures(s) {
-                Ok(BuckRuleParseOutput::FullyQualifiedInFbcode(
-                    FbcodeBuckRule {
-                        path: TargetsPath::from_buck_rule(&captures[1]),
-                        name: captures[2].to_owned(),
-                    },
-                ))
+                let rule = FbcodeBuckRule {
+                    path: TargetsPath::from_buck_rule(&captures[1]),
+                    name: captures[2].to_owned(),
+                };
+                Ok(match captures.get(3) {
+                    Some(subtarget) => BuckRuleParseOutput::FullyQualifiedInFbcodeSubtarget(
+                        rule,
+                        subtarget.as_str().to_owned(),
+                    ),
+                    None => BuckRuleParseOutput::FullyQualifiedInFbcode(rule),
+                })
             } else if let Some(captures) = BUCK_FULLY_QUALIFIED_REGEX.captures(s) {
                 Ok(BuckRuleParseOutput::FullyQualified(BuckRule {
                     repo: captures[1].to_owned(),
                     path: Path::new(&captures[2]).to_owned(),
                     name: captures[3].to_owned(),
+                    subtarget: captures.get(4).map(|capture| capture.as_str().to_owned()),
                 }))
             } else if let Some(captures) = BUCK_RULE_NAME_REGEX.captures(s) {
                 Ok(BuckRuleParseOutput::RuleName(RuleName {
@@ -306,6 +320,9 @@ mod test {
                 "fbsource//third-party/rust:foo",
                 "fbcode//common/rust/foo:bar",
                 "//common/rust/biz:baz",
+                "//common/rust/biz:baz[rust]",
+                "fbcode//common/rust/biz:baz[clients]",
+                "xplat//foo:bar[doc]",
                 ":foobar",
                 ":foobar[doc]",
             ])),
@@ -317,6 +334,7 @@ mod test {
                             repo: "fbsource".to_owned(),
                             path: Path::new("third-party/rust").to_owned(),
                             name: "foo".to_owned(),
+                            subtarget: None,
                         }),
                         BuckRuleParseOutput::FullyQualifiedInFbcode(FbcodeBuckRule {
                             path: TargetsPath::new(PathInFbcode::new_mock("common/rust/foo/TARGETS")).unwrap(),
@@ -326,6 +344,26 @@ mod test {
                             path: TargetsPath::new(PathInFbcode::new_mock("common/rust/biz/TARGETS")).unwrap(),
                             name: "baz".to_owned(),
                         }),
+                        BuckRuleParseOutput::FullyQualifiedInFbcodeSubtarget(
+                            FbcodeBuckRule {
+                                path: TargetsPath::new(PathInFbcode::new_mock("common/rust/biz/TARGETS")).unwrap(),
+                                name: "baz".to_owned(),
+                            },
+                            "rust".to_owned(),
+                        ),
+                        BuckRuleParseOutput::FullyQualifiedInFbcodeSubtarget(
+                            FbcodeBuckRule {
+                                path: TargetsPath::new(PathInFbcode::new_mock("common/rust/biz/TARGETS")).unwrap(),
+                                name: "baz".to_owned(),
+                            },
+                            "clients".to_owned(),
+                        ),
+                        BuckRuleParseOutput::FullyQualified(BuckRule {
+                            repo: "xplat".to_owned(),
+                            path: Path::new("foo").to_owned(),
+                            name: "bar".to_owned(),
+                            subtarget: Some("doc".to_owned()),
+                        }),
                         BuckRuleParseOutput::RuleName(RuleName {
                             name: "foobar".to_owned(),
                             subtarget: None,
@@ -0,0 +1,182 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::anyhow;
+use anyhow::ensure;
+use futures::FutureExt;
+use itertools::Itertools;
+use serde_json::from_slice;
+use slog::Logger;
+
+use super::BuckOptions;
+use super::commands::buck_query_buildfiles_cmd;
+use crate::paths::FbcodeRoot;
+use crate::paths::PathInFbcode;
+use crate::util::command_runner::MockableCommandRunner;
+
+/// True if the input is a Buck target pattern, e.g. `//foo:bar` or
+/// `fbcode//foo/...`, rather than a path.
+pub fn is_target_pattern(input: &str) -> bool {
+    input.contains("//")
+}
+
+/// Resolve Buck target patterns to the build files defining the targets that
+/// match them, so that they can be used as input paths.
+pub async fn resolve_target_patterns(
+    logger: &Logger,
+    fbcode_root: &FbcodeRoot,
+    buck: BuckOptions<'_>,
+    patterns: &[&str],
+) -> Result<Vec<PathInFbcode>> {
+    resolve_target_patterns_impl(
+        logger,
+        fbcode_root,
+        buck,
+        patterns,
+        MockableCommandRunner::default(),
+    )
+    .await
+}
+
+async fn resolve_target_patterns_impl(
+    logger: &Logger,
+    fbcode_root: &FbcodeRoot,
+    buck: BuckOptions<'_>,
+    patterns: &[&str],
+    cmd_runner: MockableCommandRunner,
+) -> Result<Vec<PathInFbcode>> {
+    if patterns.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let dbg_name = "buck query buildfiles";
+    let output = cmd_runner
+        .run(
+            logger,
+            dbg_name,
+            Duration::from_secs(5),
+            buck_query_buildfiles_cmd(fbcode_root, buck, logger, patterns.iter().copied())
+                .boxed_local(),
+        )
+        .await?;
+
+    ensure!(
+        output.status.success(),
+        "Failed to run '{}' for target patterns {}",
+        dbg_name,
+        patterns.join(" "),
+    );
+
+    from_slice::<Vec<String>>(&output.stdout)
+        .with_context(|| format!("Failed to parse output of '{dbg_name}'"))?
+        .into_iter()
+        .map(|buildfile| {
+            let path = buildfile
+                .strip_prefix("fbcode//")
+                .or_else(|| buildfile.strip_prefix("fbcode/"))
+                .ok_or_else(|| anyhow!("Build file {} is outside of fbcode", buildfile))?;
+            PathInFbcode::from_absolute(fbcode_root, Path::join(fbcode_root.as_ref(), path))
+        })
+        .process_results(|paths| paths.sorted().dedup().collect())
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(unix)]
+    use std::os::unix::process::ExitStatusExt;
+    #[cfg(windows)]
+    use std::os::windows::process::ExitStatusExt;
+    use std::process::ExitStatus;
+    use std::process::Output;
+
+    use serde_json::json;
+    use serde_json::to_vec;
+    use slog::o;
+
+    use super::*;
+    use crate::config::BuckConfig;
+
+    #[test]
+    fn is_target_pattern_test() {
+        assert!(is_target_pattern("//foo:bar"));
+        assert!(is_target_pattern("fbcode//foo/..."));
+        assert!(!is_target_pattern("foo/bar/BUCK"));
+    }
+
+    #[tokio::test]
+    async fn resolve_target_patterns_test() {
+        let logger = Logger::root(slog::Discard, o!());
+        let fbcode_root = FbcodeRoot::new_mock("/foo/bar");
+        let buck_config = BuckConfig::default();
+        let buck = BuckOptions {
+            config: &buck_config,
+            use_isolation_dir: false,
+            show_output: false,
+            fixtures: None,
+            offline: false,
+            cratemap_cache: None,
+        };
+
+        assert_eq!(
+            resolve_target_patterns_impl(
+                &logger,
+                &fbcode_root,
+                buck,
+                &[],
+                MockableCommandRunner::default(),
+            )
+            .await
+            .unwrap(),
+            vec![]
+        );
+
+        let cmd_runner = {
+            let mut cmd_runner = MockableCommandRunner::default();
+            cmd_runner.expect_run().return_once(|_, _, _, _| {
+                Ok(Output {
+                    status: ExitStatus::from_raw(0),
+                    stderr: vec![],
+                    stdout: to_vec(&json!(["fbcode//fiz/BUCK", "fbcode/biz/TARGETS"])).unwrap(),
+                })
+            });
+            cmd_runner
+        };
+        assert_eq!(
+            resolve_target_patterns_impl(&logger, &fbcode_root, buck, &["//fiz/..."], cmd_runner)
+                .await
+                .unwrap(),
+            vec![
+                PathInFbcode::new_mock("biz/TARGETS"),
+                PathInFbcode::new_mock("fiz/BUCK"),
+            ]
+        );
+
+        let cmd_runner = {
+            let mut cmd_runner = MockableCommandRunner::default();
+            cmd_runner.expect_run().return_once(|_, _, _, _| {
+                Ok(Output {
+                    status: ExitStatus::from_raw(0),
+                    stderr: vec![],
+                    stdout: to_vec(&json!(["xplat//fiz/BUCK"])).unwrap(),
+                })
+            });
+            cmd_runner
+        };
+        assert!(
+            resolve_target_patterns_impl(&logger, &fbcode_root, buck, &["xplat//..."], cmd_runner)
+                .await
+                .is_err()
+        );
+    }
+}
@@ -9,11 +9,86 @@
 
 //! Cargo.toml generation logic.
 
+mod crate_index;
+mod dependency_cycles;
+mod error;
+mod explain;
+mod file_manifest;
 mod generation;
 mod generator;
+mod manual_sections;
+mod oss_validation;
+mod stamp;
+mod toolchain;
+mod workspace_dependencies;
+mod workspace_package;
 
+pub use dependency_cycles::DependencyCycle;
+pub use dependency_cycles::find_dependency_cycles;
+pub use error::ErrorLocation;
+pub use error::GenerationError;
+pub use error::OutputConflict;
+pub use error::OutputConflicts;
+pub use explain::DependencyDeclaration;
+pub use explain::DependencyExplanation;
+pub use explain::explain_dependency;
+pub use file_manifest::render_file_manifests;
 pub use generator::CargoGenerator;
+pub use generator::GeneratedCrate;
 pub use generator::GenerationOutput;
+pub(crate) use generator::THIRD_PARTY_CARGO_TOML;
+pub(crate) use generator::path_dependencies;
+pub use manual_sections::KEEP_REGION_BEGIN;
+pub use manual_sections::KEEP_REGION_END;
+pub use manual_sections::MANUAL_SECTION_BEGIN;
+pub use manual_sections::MANUAL_SECTION_END;
+pub use manual_sections::preserve_manual_sections;
+pub use oss_validation::OssProblem;
+pub use oss_validation::validate_oss_packages;
+use regex::Regex;
+pub use stamp::STAMP_MARKER;
+pub use stamp::Stamp;
+pub use stamp::StampInputs;
 
 /// Preamble that can be found on the first line of an autocargo generated file
 pub static GENERATED_PREAMBLE: &str = "\x40generated by autocargo";
+
+/// True if the first line of the content or any comment line following it has
+/// [GENERATED_PREAMBLE], so that preambles customized per project are
+/// recognized too, or if the first line matches any of the legacy_preambles
+/// from the global config, i.e. the file was generated by autocargo, possibly
+/// by an older version of it.
+pub fn is_generated_content(content: &str, legacy_preambles: &[Regex]) -> bool {
+    let mut lines = content.lines();
+    let Some(first) = lines.next() else {
+        return false;
+    };
+    first.contains(GENERATED_PREAMBLE)
+        || legacy_preambles.iter().any(|re| re.is_match(first))
+        || lines
+            .take_while(|line| line.starts_with('#') || line.starts_with("//"))
+            .any(|line| line.contains(GENERATED_PREAMBLE))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn is_generated_content_test() {
+        let legacy = [Regex::new("^# Generated by cargo_from_buck").unwrap()];
+        let current = format!("# {GENERATED_PREAMBLE} from //foo:bar\n[package]\n");
+        let old = "# Generated by cargo_from_buck\n[package]\n";
+
+        assert!(is_generated_content(&current, &[]));
+        assert!(is_generated_content(old, &legacy));
+        assert!(!is_generated_content(old, &[]));
+        assert!(!is_generated_content("[package]\n", &legacy));
+        assert!(!is_generated_content("", &legacy));
+
+        let custom = format!("# Owned by proj_oncall\n#\n# {GENERATED_PREAMBLE} from //foo:bar\n");
+        assert!(is_generated_content(&custom, &[]));
+        let late = format!("# Owned by proj_oncall\n[package]\n# {GENERATED_PREAMBLE}\n");
+        assert!(!is_generated_content(&late, &[]));
+    }
+}
@@ -0,0 +1,118 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use anyhow::Result;
+use anyhow::anyhow;
+use itertools::Itertools;
+
+use super::GENERATED_PREAMBLE;
+use super::generator::GeneratedCrate;
+use crate::paths::PathInFbcode;
+use crate::paths::relative_path;
+
+/// Render a markdown table listing the given crates of the project, with paths
+/// relative to the directory of index_path.
+pub(super) fn render_crate_index<'a>(
+    project: &str,
+    index_path: &PathInFbcode,
+    crates: impl IntoIterator<Item = &'a GeneratedCrate>,
+) -> Result<String> {
+    let index_dir = index_path
+        .as_ref()
+        .parent()
+        .ok_or_else(|| anyhow!("Crate index path {} has no parent", index_path))?;
+
+    let rows = crates
+        .into_iter()
+        .sorted_by(|a, b| (&a.name, &a.cargo_toml_path).cmp(&(&b.name, &b.cargo_toml_path)))
+        .map(|krate| {
+            let path = relative_path(krate.cargo_toml_path.as_dir().as_ref(), index_dir)
+                .and_then(|path| path.to_str().map(|s| s.to_owned()))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Couldn't construct a relative path between {} and {}",
+                        index_path,
+                        krate.cargo_toml_path.as_dir(),
+                    )
+                })?;
+            let path = if path.is_empty() {
+                ".".to_owned()
+            } else {
+                path
+            };
+            Ok(format!(
+                "| {} | [{}]({}) | {} | `{}` |\n",
+                escape(&krate.name),
+                escape(&path),
+                path,
+                escape(krate.description.as_deref().unwrap_or_default()),
+                krate.rule,
+            ))
+        })
+        .collect::<Result<String>>()?;
+
+    Ok(format!(
+        "<!-- {GENERATED_PREAMBLE} -->\n\
+        \n\
+        # Crates of {project}\n\
+        \n\
+        | Crate | Path | Description | Buck rule |\n\
+        | --- | --- | --- | --- |\n\
+        {rows}"
+    ))
+}
+
+/// Make the text safe to put in a markdown table cell.
+fn escape(text: &str) -> String {
+    text.split_whitespace().join(" ").replace('|', "\\|")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::paths::CargoTomlPath;
+
+    fn krate(name: &str, dir: &str, description: Option<&str>) -> GeneratedCrate {
+        GeneratedCrate {
+            project: "proj".to_owned(),
+            name: name.to_owned(),
+            cargo_toml_path: CargoTomlPath::new(PathInFbcode::new_mock(format!(
+                "{dir}/Cargo.toml"
+            )))
+            .unwrap(),
+            description: description.map(|d| d.to_owned()),
+            rule: format!("//{dir}:{name}"),
+            rules: vec![format!("//{dir}:{name}")],
+            oss_cargo_toml_path: None,
+        }
+    }
+
+    #[test]
+    fn render_crate_index_test() {
+        assert_eq!(
+            render_crate_index(
+                "proj",
+                &PathInFbcode::new_mock("proj/CRATES.md"),
+                &[
+                    krate("foo", "proj/foo", Some("Does | things\nwell")),
+                    krate("bar", "proj", None),
+                ],
+            )
+            .unwrap(),
+            "<!-- \x40generated by autocargo -->\n\
+            \n\
+            # Crates of proj\n\
+            \n\
+            | Crate | Path | Description | Buck rule |\n\
+            | --- | --- | --- | --- |\n\
+            | bar | [.](.) |  | `//proj:bar` |\n\
+            | foo | [foo](foo) | Does \\| things well | `//proj/foo:foo` |\n"
+        );
+    }
+}
@@ -0,0 +1,308 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Detection of cycles among the path dependencies of the generated crates,
+//! which Cargo would otherwise only reject when building them.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+
+use anyhow::Result;
+use anyhow::bail;
+use cargo_toml::DepsSet;
+use itertools::Itertools;
+use slog::Logger;
+use slog::warn;
+
+use super::GenerationOutput;
+use crate::cargo_manifest::Manifest;
+use crate::paths::CargoTomlPath;
+
+/// Cycle of path dependencies between generated crates.
+#[derive(Debug, PartialEq, Eq)]
+pub struct DependencyCycle<'a> {
+    /// Cargo.toml files of the crates in the cycle, each depends on the next
+    /// one and the last one on the first.
+    pub cargo_toml_paths: Vec<&'a CargoTomlPath>,
+    /// True if the cycle goes through dev-dependencies. Cargo accepts such
+    /// cycles, but the crate is built twice for its tests then, so its types
+    /// differ from the ones its dependencies see.
+    pub through_dev_dependencies: bool,
+}
+
+/// Fail if the path dependencies of the generated crates form a cycle, warn
+/// about cycles that go through dev-dependencies.
+pub(super) fn check_dependency_cycles(logger: &Logger, output: &GenerationOutput) -> Result<()> {
+    let cycles = find_dependency_cycles(output);
+    if cycles.is_empty() {
+        return Ok(());
+    }
+
+    let rules: HashMap<&CargoTomlPath, &str> = output
+        .crates
+        .iter()
+        .map(|krate| (&krate.cargo_toml_path, krate.rule.as_str()))
+        .collect();
+    let describe = |cycle: &DependencyCycle<'_>| {
+        cycle
+            .cargo_toml_paths
+            .iter()
+            .chain(cycle.cargo_toml_paths.first())
+            .map(|path| match rules.get(path) {
+                Some(rule) => format!("{rule} ({})", path.as_file()),
+                None => path.as_file().to_string(),
+            })
+            .join(" -> ")
+    };
+
+    let (dev_cycles, cycles): (Vec<_>, Vec<_>) = cycles
+        .iter()
+        .partition(|cycle| cycle.through_dev_dependencies);
+    for cycle in dev_cycles {
+        warn!(
+            logger,
+            "Generated crates depend on each other through dev-dependencies: {}",
+            describe(cycle),
+        );
+    }
+    if !cycles.is_empty() {
+        bail!(
+            "Generated crates have cyclic path dependencies, which Cargo rejects:\n{}",
+            cycles
+                .into_iter()
+                .map(|cycle| format!("  {}", describe(cycle)))
+                .join("\n"),
+        );
+    }
+    Ok(())
+}
+
+/// Find cycles among the path dependencies of the generated crates, first the
+/// ones made of regular and build dependencies, then the ones that need
+/// dev-dependencies to close. Not every cycle is listed if several cycles
+/// share crates, but each group of crates depending on each other is covered
+/// by at least one.
+pub fn find_dependency_cycles(output: &GenerationOutput) -> Vec<DependencyCycle<'_>> {
+    let crates: BTreeMap<&CargoTomlPath, &Manifest> = output
+        .crates
+        .iter()
+        .filter_map(|krate| {
+            let manifest = output.cargo_manifests.get(&krate.cargo_toml_path)?;
+            Some((&krate.cargo_toml_path, manifest))
+        })
+        .collect();
+    let by_dir: HashMap<_, &CargoTomlPath> =
+        crates.keys().map(|path| (path.as_dir(), *path)).collect();
+    let edges = |with_dev_dependencies: bool| -> Graph<'_> {
+        crates
+            .iter()
+            .map(|(path, manifest)| {
+                let targets = dependency_sets(manifest, with_dev_dependencies)
+                    .flat_map(|deps| deps.values())
+                    .filter_map(|dep| dep.detail()?.path.as_ref())
+                    .filter_map(|dep_path| {
+                        by_dir
+                            .get(&path.as_dir().join_to_path_in_fbcode(dep_path))
+                            .copied()
+                    })
+                    // Packages may dev-depend on themselves to enable features
+                    // in their tests, which Cargo accepts.
+                    .filter(|target| target != path)
+                    .collect();
+                (*path, targets)
+            })
+            .collect()
+    };
+
+    let regular = edges(false);
+    let mut cycles: Vec<_> = find_cycles(&regular)
+        .into_iter()
+        .map(|cargo_toml_paths| DependencyCycle {
+            cargo_toml_paths,
+            through_dev_dependencies: false,
+        })
+        .collect();
+    cycles.extend(
+        find_cycles(&edges(true))
+            .into_iter()
+            .filter(|cycle| {
+                // Cycles of regular dependencies are already reported.
+                !cycle
+                    .iter()
+                    .zip(cycle.iter().cycle().skip(1))
+                    .all(|(from, to)| regular[from].contains(to))
+            })
+            .map(|cargo_toml_paths| DependencyCycle {
+                cargo_toml_paths,
+                through_dev_dependencies: true,
+            }),
+    );
+    cycles
+}
+
+type Graph<'a> = BTreeMap<&'a CargoTomlPath, BTreeSet<&'a CargoTomlPath>>;
+
+/// The dependency sets of the manifest, including the dev-dependencies if
+/// asked to.
+fn dependency_sets(
+    manifest: &Manifest,
+    with_dev_dependencies: bool,
+) -> impl Iterator<Item = &DepsSet> {
+    [&manifest.dependencies, &manifest.build_dependencies]
+        .into_iter()
+        .chain(with_dev_dependencies.then_some(&manifest.dev_dependencies))
+        .chain(manifest.target.values().flat_map(move |target| {
+            [&target.dependencies, &target.build_dependencies]
+                .into_iter()
+                .chain(with_dev_dependencies.then_some(&target.dev_dependencies))
+        }))
+}
+
+/// A cycle for each back edge found by a depth-first search of the graph,
+/// each rotated to start at its smallest node.
+fn find_cycles<'a>(graph: &Graph<'a>) -> Vec<Vec<&'a CargoTomlPath>> {
+    fn visit<'a>(
+        graph: &Graph<'a>,
+        node: &'a CargoTomlPath,
+        stack: &mut Vec<&'a CargoTomlPath>,
+        visited: &mut BTreeSet<&'a CargoTomlPath>,
+        cycles: &mut BTreeSet<Vec<&'a CargoTomlPath>>,
+    ) {
+        visited.insert(node);
+        stack.push(node);
+        for next in &graph[node] {
+            if let Some(start) = stack.iter().position(|on_stack| on_stack == next) {
+                let mut cycle = stack[start..].to_vec();
+                let smallest = cycle.iter().position_min().unwrap_or_default();
+                cycle.rotate_left(smallest);
+                cycles.insert(cycle);
+            } else if !visited.contains(next) {
+                visit(graph, next, stack, visited, cycles);
+            }
+        }
+        stack.pop();
+    }
+
+    let mut visited = BTreeSet::new();
+    let mut cycles = BTreeSet::new();
+    for node in graph.keys() {
+        if !visited.contains(node) {
+            visit(graph, node, &mut Vec::new(), &mut visited, &mut cycles);
+        }
+    }
+    cycles.into_iter().collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use cargo_toml::Dependency;
+    use cargo_toml::DependencyDetail;
+    use slog::o;
+
+    use super::*;
+    use crate::cargo_generator::GeneratedCrate;
+    use crate::paths::PathInFbcode;
+
+    fn cargo_path(dir: &str) -> CargoTomlPath {
+        CargoTomlPath::new(PathInFbcode::new_mock(dir).join_to_path_in_fbcode("Cargo.toml"))
+            .unwrap()
+    }
+
+    fn path_deps(deps: &[&str]) -> DepsSet {
+        deps.iter()
+            .map(|dep| {
+                (
+                    (*dep).to_owned(),
+                    Dependency::Detailed(Box::new(DependencyDetail {
+                        path: Some(format!("../{dep}")),
+                        ..DependencyDetail::default()
+                    })),
+                )
+            })
+            .collect()
+    }
+
+    fn output(crates: &[(&str, &[&str], &[&str])]) -> GenerationOutput {
+        GenerationOutput {
+            cargo_manifests: crates
+                .iter()
+                .map(|(name, deps, dev_deps)| {
+                    (
+                        cargo_path(name),
+                        Manifest {
+                            dependencies: path_deps(deps),
+                            dev_dependencies: path_deps(dev_deps),
+                            ..Manifest::default()
+                        },
+                    )
+                })
+                .collect(),
+            additional_files: HashMap::new(),
+            crates: crates
+                .iter()
+                .map(|(name, _, _)| GeneratedCrate {
+                    project: "proj".to_owned(),
+                    name: (*name).to_owned(),
+                    cargo_toml_path: cargo_path(name),
+                    description: None,
+                    rule: format!("//{name}:{name}"),
+                    rules: vec![format!("//{name}:{name}")],
+                    oss_cargo_toml_path: None,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn find_dependency_cycles_test() {
+        let logger = Logger::root(slog::Discard, o!());
+
+        let acyclic = output(&[("a", &["b", "c"], &[]), ("b", &["c"], &[]), ("c", &[], &[])]);
+        assert!(find_dependency_cycles(&acyclic).is_empty());
+        check_dependency_cycles(&logger, &acyclic).unwrap();
+
+        let self_dev = output(&[("a", &[], &["a"])]);
+        assert!(find_dependency_cycles(&self_dev).is_empty());
+
+        let dev_cycle = output(&[("a", &["b"], &[]), ("b", &[], &["a"])]);
+        assert_eq!(
+            find_dependency_cycles(&dev_cycle),
+            vec![DependencyCycle {
+                cargo_toml_paths: vec![&cargo_path("a"), &cargo_path("b")],
+                through_dev_dependencies: true,
+            }]
+        );
+        check_dependency_cycles(&logger, &dev_cycle).unwrap();
+
+        let cycle = output(&[
+            ("a", &["b"], &[]),
+            ("b", &["c"], &[]),
+            ("c", &["a"], &[]),
+            ("d", &[], &[]),
+        ]);
+        assert_eq!(
+            find_dependency_cycles(&cycle),
+            vec![DependencyCycle {
+                cargo_toml_paths: vec![&cargo_path("a"), &cargo_path("b"), &cargo_path("c")],
+                through_dev_dependencies: false,
+            }]
+        );
+        assert_eq!(
+            check_dependency_cycles(&logger, &cycle)
+                .unwrap_err()
+                .to_string(),
+            "Generated crates have cyclic path dependencies, which Cargo rejects:\n  \
+            //a:a (a/Cargo.toml) -> //b:b (b/Cargo.toml) -> //c:c (c/Cargo.toml) -> \
+            //a:a (a/Cargo.toml)"
+        );
+    }
+}
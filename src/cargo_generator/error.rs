@@ -0,0 +1,207 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::fmt::Display;
+
+use anyhow::Error;
+use anyhow::Result;
+
+use crate::paths::PathInFbcode;
+use crate::paths::TargetsPath;
+
+/// Where in the Buck files or configs a generation failure happened.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ErrorLocation {
+    /// Build file the failing crate is generated from.
+    pub targets_path: Option<TargetsPath>,
+    /// Name of the Buck rule involved.
+    pub rule: Option<String>,
+    /// Config key involved, e.g. `autocargo.cargo_toml_config.patch`.
+    pub config_key: Option<String>,
+}
+
+impl ErrorLocation {
+    pub(crate) fn targets_path(targets_path: &TargetsPath) -> Self {
+        Self {
+            targets_path: Some(targets_path.clone()),
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn rule(rule: &str) -> Self {
+        Self {
+            rule: Some(rule.to_owned()),
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn config_key(config_key: &str) -> Self {
+        Self {
+            config_key: Some(config_key.to_owned()),
+            ..Self::default()
+        }
+    }
+}
+
+/// Error of the Cargo.toml generation that, like an anyhow context, wraps the
+/// underlying error with a message, but additionally records the location of
+/// the failure. Each layer of the generation records what it knows about, use
+/// [GenerationError::locate] to gather it from a whole error chain.
+#[derive(Debug)]
+pub struct GenerationError {
+    message: String,
+    location: ErrorLocation,
+    source: Error,
+}
+
+impl GenerationError {
+    /// Location of the failure merged from all GenerationErrors in the chain
+    /// of the error, the innermost ones taking precedence.
+    pub fn locate(error: &Error) -> ErrorLocation {
+        let mut location = ErrorLocation::default();
+        for generation_error in error
+            .chain()
+            .filter_map(|cause| cause.downcast_ref::<Self>())
+        {
+            let ErrorLocation {
+                targets_path,
+                rule,
+                config_key,
+            } = &generation_error.location;
+            location.targets_path = targets_path.clone().or(location.targets_path);
+            location.rule = rule.clone().or(location.rule);
+            location.config_key = config_key.clone().or(location.config_key);
+        }
+        location
+    }
+}
+
+impl Display for GenerationError {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        self.message.fmt(formatter)
+    }
+}
+
+impl StdError for GenerationError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+/// Counterpart of [anyhow::Context] that wraps errors in [GenerationError].
+pub(crate) trait WithLocation<T> {
+    /// Wrap the error with the message and location returned by f.
+    fn with_location(self, f: impl FnOnce() -> (ErrorLocation, String)) -> Result<T>;
+}
+
+impl<T> WithLocation<T> for Result<T> {
+    fn with_location(self, f: impl FnOnce() -> (ErrorLocation, String)) -> Result<T> {
+        self.map_err(|source| {
+            let (location, message) = f();
+            GenerationError {
+                message,
+                location,
+                source,
+            }
+            .into()
+        })
+    }
+}
+
+/// Paths that were generated from more than one build file, e.g. because two
+/// projects cover TARGETS files that generate into the same directory.
+#[derive(Debug)]
+pub struct OutputConflicts(pub Vec<OutputConflict>);
+
+/// Path generated from more than one build file.
+#[derive(Debug)]
+pub struct OutputConflict {
+    /// The generated path.
+    pub path: PathInFbcode,
+    /// Build files the path was generated from together with the names of
+    /// the projects covering them.
+    pub sources: Vec<(TargetsPath, Option<String>)>,
+}
+
+impl Display for OutputConflicts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} paths were generated from more than one build file:",
+            self.0.len()
+        )?;
+        for OutputConflict { path, sources } in &self.0 {
+            write!(f, "\n  {path}")?;
+            for (targets_path, project) in sources {
+                write!(
+                    f,
+                    "\n    build file in {} (project {})",
+                    targets_path.as_dir(),
+                    project.as_deref().unwrap_or("<none>"),
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl StdError for OutputConflicts {}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Context;
+    use anyhow::anyhow;
+
+    use super::*;
+    use crate::paths::PathInFbcode;
+
+    #[test]
+    fn locate_test() {
+        let targets_path = TargetsPath::new(PathInFbcode::new_mock("foo/TARGETS")).unwrap();
+        let error = Err::<(), _>(anyhow!("bad patch"))
+            .with_location(|| {
+                (
+                    ErrorLocation::config_key("autocargo.cargo_toml_config.patch"),
+                    "In patch generation".to_owned(),
+                )
+            })
+            .with_location(|| (ErrorLocation::rule("inner"), "In lib".to_owned()))
+            .context("Plain context")
+            .with_location(|| {
+                (
+                    ErrorLocation {
+                        targets_path: Some(targets_path.clone()),
+                        rule: Some("outer".to_owned()),
+                        config_key: None,
+                    },
+                    "While generating".to_owned(),
+                )
+            })
+            .unwrap_err();
+
+        assert_eq!(
+            format!("{error:#}"),
+            "While generating: Plain context: In lib: In patch generation: bad patch"
+        );
+        assert_eq!(
+            GenerationError::locate(&error),
+            ErrorLocation {
+                targets_path: Some(targets_path),
+                rule: Some("inner".to_owned()),
+                config_key: Some("autocargo.cargo_toml_config.patch".to_owned()),
+            }
+        );
+        assert_eq!(
+            GenerationError::locate(&anyhow!("unlocated")),
+            ErrorLocation::default()
+        );
+    }
+}
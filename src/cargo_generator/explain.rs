@@ -0,0 +1,326 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Explanation of where a dependency of a generated Cargo.toml file comes
+//! from, i.e. which attributes of which Buck rules declare it, which
+//! dependencies_override entries change it and what it looks like in the end.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::anyhow;
+use cargo_toml::DepsSet;
+use serde::Serialize;
+
+use super::CargoGenerator;
+use super::GenerationOutput;
+use super::generation::generate_dependency_package_name;
+use crate::buck_processing::BuckDependency;
+use crate::buck_processing::BuckDependencyOverride;
+use crate::buck_processing::BuckManifest;
+use crate::buck_processing::BuckTargetDependencies;
+use crate::buck_processing::DependenciesOverride;
+use crate::paths::CargoTomlPath;
+use crate::paths::TargetsPath;
+
+/// Declaration of a Buck dependency that resolves to the explained dependency.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct DependencyDeclaration {
+    /// The rule declaring the dependency.
+    pub rule: String,
+    /// Attribute of the rule declaring the dependency, e.g. `named_deps` or
+    /// `os_deps (Linux)`.
+    pub attribute: String,
+    /// The declared Buck dependency.
+    pub dependency: String,
+}
+
+/// Where a dependency of a generated Cargo.toml file comes from.
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct DependencyExplanation {
+    /// Path of the generated Cargo.toml file, relative to fbcode.
+    pub cargo_toml_path: String,
+    /// Name of the dependency in the Cargo.toml file.
+    pub name: String,
+    /// Buck dependencies of the rules of the Cargo.toml file that resolve to
+    /// the dependency, including the ones added or removed via
+    /// extra_buck_dependencies.
+    pub declarations: Vec<DependencyDeclaration>,
+    /// The dependencies_override entries of the rules that change the
+    /// dependency, e.g. `//foo:bar dependencies_override.dev-dependencies`.
+    pub overrides: Vec<String>,
+    /// The dependency as generated, in TOML, keyed by the section of the
+    /// Cargo.toml file it is in.
+    pub resolved: BTreeMap<String, String>,
+}
+
+/// Explain where the dependency of the given name in the generated Cargo.toml
+/// file comes from.
+pub fn explain_dependency(
+    cargo_generator: &CargoGenerator<'_>,
+    processed_manifests: &HashMap<TargetsPath, Vec<BuckManifest>>,
+    output: &GenerationOutput,
+    cargo_toml_path: &CargoTomlPath,
+    name: &str,
+) -> Result<DependencyExplanation> {
+    let manifest = output.cargo_manifests.get(cargo_toml_path).ok_or_else(|| {
+        anyhow!(
+            "No Cargo.toml is generated at {} by the selected projects",
+            cargo_toml_path.as_file()
+        )
+    })?;
+
+    let mut resolved = BTreeMap::new();
+    let sections = [
+        ("dependencies".to_owned(), &manifest.dependencies),
+        ("dev-dependencies".to_owned(), &manifest.dev_dependencies),
+        (
+            "build-dependencies".to_owned(),
+            &manifest.build_dependencies,
+        ),
+    ]
+    .into_iter()
+    .chain(manifest.target.iter().flat_map(|(key, target)| {
+        [
+            (
+                format!("target.{}.dependencies", **key),
+                &target.dependencies,
+            ),
+            (
+                format!("target.{}.dev-dependencies", **key),
+                &target.dev_dependencies,
+            ),
+            (
+                format!("target.{}.build-dependencies", **key),
+                &target.build_dependencies,
+            ),
+        ]
+    }));
+    for (section, deps) in sections {
+        if let Some(dep) = deps.get(name) {
+            let value = toml::Value::try_from(dep)
+                .with_context(|| format!("While rendering dependency {name} in {section}"))?;
+            resolved.insert(section, value.to_string());
+        }
+    }
+
+    let rules: Vec<_> = output
+        .crates
+        .iter()
+        .filter(|krate| &krate.cargo_toml_path == cargo_toml_path)
+        .flat_map(|krate| krate.rules.iter())
+        .collect();
+    let mut declarations = Vec::new();
+    let mut overrides = Vec::new();
+    for (targets_path, manifests) in processed_manifests {
+        for buck_manifest in manifests {
+            let rule = format!(
+                "//{}:{}",
+                targets_path.as_dir().as_ref().display(),
+                buck_manifest.raw().name
+            );
+            if !rules.contains(&&rule) {
+                continue;
+            }
+            for (attribute, alias, dep) in rule_dependencies(buck_manifest) {
+                let key = match alias {
+                    Some(alias) => alias.to_owned(),
+                    None => dependency_key(cargo_generator, dep),
+                };
+                if key == name {
+                    declarations.push(DependencyDeclaration {
+                        rule: rule.clone(),
+                        attribute,
+                        dependency: describe(dep),
+                    });
+                }
+            }
+            if let Some(cargo_toml_config) = &buck_manifest.raw().autocargo.cargo_toml_config {
+                overrides.extend(
+                    override_sections(&cargo_toml_config.dependencies_override, name)
+                        .into_iter()
+                        .map(|section| format!("{rule} dependencies_override.{section}")),
+                );
+            }
+        }
+    }
+    declarations.sort();
+    overrides.sort();
+
+    Ok(DependencyExplanation {
+        cargo_toml_path: cargo_toml_path.as_file().to_string(),
+        name: name.to_owned(),
+        declarations,
+        overrides,
+        resolved,
+    })
+}
+
+/// All Buck dependencies of the rule with the attribute declaring them and
+/// their alias, if they are renamed.
+fn rule_dependencies<'a>(
+    manifest: &'a BuckManifest,
+) -> Vec<(String, Option<&'a str>, &'a BuckDependency)> {
+    let mut result = Vec::new();
+    let mut add_deps = |attribute: String, deps: &'a [BuckDependency]| {
+        result.extend(deps.iter().map(|dep| (attribute.clone(), None, dep)));
+    };
+    add_deps("deps".to_owned(), manifest.deps());
+    add_deps("test_deps".to_owned(), manifest.test_deps());
+    add_deps(
+        "autocargo.build_script.deps".to_owned(),
+        manifest.build_script_deps(),
+    );
+    for (platform, deps) in manifest.os_deps() {
+        add_deps(format!("os_deps ({platform:?})"), deps);
+    }
+    for (platform, deps) in manifest.test_os_deps() {
+        add_deps(format!("test_os_deps ({platform:?})"), deps);
+    }
+    for (constraint, deps) in manifest.select_deps() {
+        add_deps(format!("select_deps ({constraint})"), deps);
+    }
+
+    for (attribute, named_deps) in [
+        ("named_deps", manifest.named_deps()),
+        ("test_named_deps", manifest.test_named_deps()),
+    ] {
+        result.extend(
+            named_deps
+                .iter()
+                .map(|(alias, dep)| (attribute.to_owned(), Some(alias.as_str()), dep)),
+        );
+    }
+
+    let extra = manifest.extra_buck_dependencies();
+    let sections = std::iter::once((String::new(), &extra.deps)).chain(
+        extra
+            .target
+            .iter()
+            .map(|(key, deps)| (format!("target.{}.", **key), deps)),
+    );
+    for (prefix, deps) in sections {
+        let BuckTargetDependencies {
+            dependencies,
+            dev_dependencies,
+            build_dependencies,
+        } = deps;
+        for (section, overrides) in [
+            ("dependencies", dependencies),
+            ("dev-dependencies", dev_dependencies),
+            ("build-dependencies", build_dependencies),
+        ] {
+            let attribute = format!("extra_buck_dependencies.{prefix}{section}");
+            result.extend(overrides.iter().map(|dep_override| match dep_override {
+                BuckDependencyOverride::Dep(dep) => (attribute.clone(), None, dep),
+                BuckDependencyOverride::NamedDep(alias, dep) => {
+                    (attribute.clone(), Some(alias.as_str()), dep)
+                }
+                BuckDependencyOverride::RemovedDep(dep) => {
+                    (format!("{attribute} (removed)"), None, dep)
+                }
+            }));
+        }
+    }
+
+    result
+}
+
+/// Name of the dependency in generated Cargo.toml files if it is not renamed.
+pub(super) fn dependency_key(cargo_generator: &CargoGenerator<'_>, dep: &BuckDependency) -> String {
+    let package_of = |deps: &DepsSet, key: &str| {
+        deps.get(key)
+            .and_then(|dep| dep.package())
+            .map(|package| package.to_owned())
+    };
+    match dep {
+        BuckDependency::ThirdPartyCrate(name) => {
+            package_of(cargo_generator.third_party_crates(), name).unwrap_or_else(|| name.clone())
+        }
+        BuckDependency::FbcodeCrate(targets_path, raw) => {
+            generate_dependency_package_name(targets_path, raw)
+        }
+        BuckDependency::CellRule(rule) => package_of(cargo_generator.cell_dependencies(), rule)
+            .unwrap_or_else(|| {
+                rule.rsplit_once(':')
+                    .map_or(rule.as_str(), |(_, name)| name)
+                    .to_owned()
+            }),
+    }
+}
+
+/// The Buck dependency as it could be written in a build file.
+fn describe(dep: &BuckDependency) -> String {
+    match dep {
+        BuckDependency::ThirdPartyCrate(name) => format!("fbsource//third-party/rust:{name}"),
+        BuckDependency::FbcodeCrate(targets_path, raw) => format!(
+            "//{}:{}",
+            targets_path.as_dir().as_ref().display(),
+            raw.name
+        ),
+        BuckDependency::CellRule(rule) => rule.clone(),
+    }
+}
+
+/// Sections of the dependencies_override that have an entry for the name.
+fn override_sections(dependencies_override: &DependenciesOverride, name: &str) -> Vec<String> {
+    let DependenciesOverride { deps, target } = dependencies_override;
+    std::iter::once((String::new(), deps))
+        .chain(
+            target
+                .iter()
+                .map(|(key, deps)| (format!("target.{}.", **key), deps)),
+        )
+        .flat_map(|(prefix, deps)| {
+            [
+                ("dependencies", &deps.dependencies),
+                ("dev-dependencies", &deps.dev_dependencies),
+                ("build-dependencies", &deps.build_dependencies),
+            ]
+            .into_iter()
+            .filter(|(_, overrides)| overrides.contains_key(name))
+            .map(move |(section, _)| format!("{prefix}{section}"))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn override_sections_test() {
+        let dependencies_override: DependenciesOverride = serde_json::from_value(json!({
+            "dev-dependencies": {"foo": {"features": ["bar"]}},
+            "target": {
+                "'cfg(unix)'": {
+                    "dependencies": {"foo": {"optional": true}, "biz": {}},
+                },
+            },
+        }))
+        .unwrap();
+
+        assert_eq!(
+            override_sections(&dependencies_override, "foo"),
+            vec![
+                "dev-dependencies".to_owned(),
+                "target.'cfg(unix)'.dependencies".to_owned(),
+            ]
+        );
+        assert_eq!(
+            override_sections(&dependencies_override, "biz"),
+            vec!["target.'cfg(unix)'.dependencies".to_owned()]
+        );
+        assert!(override_sections(&dependencies_override, "bar").is_empty());
+    }
+}
@@ -0,0 +1,133 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::anyhow;
+use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+
+use super::GENERATED_PREAMBLE;
+use crate::config::ProjectConf;
+use crate::paths::PathInFbcode;
+use crate::paths::relative_path;
+
+/// Content of a .autocargo_manifest.json file.
+#[derive(Serialize)]
+struct FileManifest {
+    /// Marks the manifest itself as generated.
+    generated: &'static str,
+    /// Map from path of a generated file to the sha256 hash of its content.
+    files: BTreeMap<String, String>,
+}
+
+/// Render the file manifests of the projects that have file_manifest_dir
+/// configured, each listing the given files covered by the project, other than
+/// the file manifests, with the hashes of their content. The content must be
+/// the one written to disk, i.e. with the manual sections of the existing
+/// files preserved, for the hashes to match the files.
+pub fn render_file_manifests<'a>(
+    confs: impl IntoIterator<Item = &'a ProjectConf>,
+    files: &[(&PathInFbcode, &str)],
+) -> Result<Vec<(&'a ProjectConf, PathInFbcode, String)>> {
+    let confs: Vec<_> = confs
+        .into_iter()
+        .filter_map(|conf| Some((conf, conf.file_manifest_path()?)))
+        .collect();
+    let manifest_paths: HashSet<_> = confs.iter().map(|(_, path)| path).collect();
+
+    confs
+        .iter()
+        .map(|(conf, manifest_path)| {
+            let files = files
+                .iter()
+                .copied()
+                .filter(|(path, _)| !manifest_paths.contains(path) && conf.covers_path(path));
+            let content = render_file_manifest(manifest_path, files).with_context(|| {
+                format!("While generating file manifest of project {}", conf.name())
+            })?;
+            Ok((*conf, manifest_path.clone(), content))
+        })
+        .collect()
+}
+
+/// Render a JSON manifest listing the given generated files with the sha256
+/// hashes of their content, with paths relative to the directory of
+/// manifest_path.
+fn render_file_manifest<'a>(
+    manifest_path: &PathInFbcode,
+    files: impl IntoIterator<Item = (&'a PathInFbcode, &'a str)>,
+) -> Result<String> {
+    let manifest_dir = manifest_path
+        .as_ref()
+        .parent()
+        .ok_or_else(|| anyhow!("File manifest path {} has no parent", manifest_path))?;
+
+    let files = files
+        .into_iter()
+        .map(|(path, content)| {
+            let relative_path = relative_path(path.as_ref(), manifest_dir)
+                .and_then(|path| path.to_str().map(|s| s.to_owned()))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Couldn't construct a relative path between {} and {}",
+                        manifest_path,
+                        path,
+                    )
+                })?;
+            Ok((relative_path, format!("{:x}", Sha256::digest(content))))
+        })
+        .collect::<Result<_>>()?;
+
+    let mut content = serde_json::to_string_pretty(&FileManifest {
+        generated: GENERATED_PREAMBLE,
+        files,
+    })
+    .with_context(|| format!("While serializing file manifest {manifest_path}"))?;
+    content.push('\n');
+    Ok(content)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn render_file_manifest_test() {
+        let p = PathInFbcode::new_mock;
+        let (a, b, c) = (
+            p("proj/a/Cargo.toml"),
+            p("proj/Cargo.toml"),
+            p("other/BUCK"),
+        );
+        let content = render_file_manifest(
+            &p("proj/.autocargo_manifest.json"),
+            [(&a, "abc"), (&b, ""), (&c, "abc")],
+        )
+        .unwrap();
+        assert_eq!(
+            content,
+            format!(
+                r#"{{
+  "generated": "{GENERATED_PREAMBLE}",
+  "files": {{
+    "../other/BUCK": "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+    "Cargo.toml": "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+    "a/Cargo.toml": "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+  }}
+}}
+"#
+            )
+        );
+    }
+}
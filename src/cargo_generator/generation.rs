@@ -8,44 +8,91 @@
  */
 
 mod consolidated_dependencies;
+mod cxx_additional;
 mod dependencies;
+mod deprecation_additional;
+mod editions;
+mod feature_aliases;
+mod mapped_srcs_additional;
 mod package;
 mod product;
+mod protobuf_additional;
+mod readme_additional;
+mod rustc_flags_additional;
 mod thrift_additional;
 
 use std::borrow::Borrow;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::path::Path;
 
 use anyhow::Context;
 use anyhow::Result;
 use anyhow::anyhow;
+use anyhow::bail;
 use anyhow::ensure;
+use cargo_toml::Dependency;
+use cargo_toml::DependencyDetail;
+use cargo_toml::DepsSet;
 use cargo_toml::FeatureSet;
 use itertools::Itertools;
-use pathdiff::diff_paths;
 use slog::Logger;
-use thrift_additional::generate_additional_thrift_files;
+use slog::warn;
 
 use self::consolidated_dependencies::ConsolidatedDependencies;
+use self::cxx_additional::generate_additional_cxx_files;
 use self::dependencies::Dependencies;
 use self::dependencies::DependenciesGenerator;
+use self::dependencies::add_target_feature_activations;
+use self::deprecation_additional::add_deprecation_metadata;
+use self::deprecation_additional::generate_additional_deprecation_files;
+use self::editions::validate_product_editions;
+use self::editions::validate_toolchain_editions;
+use self::feature_aliases::resolve_feature_aliases;
 use self::r#impl::BoxConfig;
 use self::r#impl::BoxExtraBuckDeps;
+use self::mapped_srcs_additional::add_mapped_srcs_includes;
+use self::mapped_srcs_additional::generate_additional_mapped_srcs_files;
+use self::mapped_srcs_additional::mapped_srcs_copies;
+use self::package::add_tool_metadata;
+use self::package::disable_autodiscovery;
+pub(super) use self::package::generate_dependency_package_name;
 use self::package::generate_package;
+use self::package::validate_links;
 use self::product::generate_product;
+use self::product::is_example_product;
+use self::product::relative_crate_root;
+use self::product::unused_target_config_keys;
+use self::protobuf_additional::generate_additional_protobuf_files;
+use self::readme_additional::generate_additional_readme_files;
+use self::rustc_flags_additional::RustcFlags;
+use self::rustc_flags_additional::generate_additional_rustc_cfg_files;
+use self::rustc_flags_additional::rustc_flags;
+use self::thrift_additional::generate_additional_thrift_files;
 use super::CargoGenerator;
+use super::error::ErrorLocation;
+use super::error::WithLocation;
 use crate::buck_processing::AutocargoCargoTomlConfig;
 use crate::buck_processing::BuckManifest;
+use crate::buck_processing::CRITERION_CRATE;
 use crate::buck_processing::ExtraBuckDependencies;
 use crate::buck_processing::FbconfigRuleType;
 use crate::cargo_generator::GENERATED_PREAMBLE;
 use crate::cargo_manifest::Manifest;
+use crate::cargo_manifest::Product;
+use crate::cargo_manifest::TargetKey;
+use crate::config::BenchDefaults;
+use crate::config::CrateDeprecation;
+use crate::config::FeaturePropagation;
 use crate::config::OssGitConfig;
 use crate::config::ProjectConf;
 use crate::config::ProjectConfDefaults;
 use crate::paths::CargoTomlPath;
+use crate::paths::FbcodeRoot;
 use crate::paths::PathInFbcode;
 use crate::paths::TargetsPath;
+use crate::paths::relative_path;
+use crate::paths::to_public_cargo_dir;
 
 // The cargo key for default features
 const DEFAULT: &str = "default";
@@ -54,6 +101,10 @@ fn compute_cargo_toml_path(cargo_toml_dir: &PathInFbcode) -> CargoTomlPath {
     CargoTomlPath::new(cargo_toml_dir.join_to_path_in_fbcode(CargoTomlPath::filename())).unwrap()
 }
 
+/// Manifest generated for public_cargo_dir together with the package files
+/// copied next to it.
+pub type OssManifest = (CargoTomlPath, Manifest, HashMap<PathInFbcode, String>);
+
 #[derive(Debug)]
 pub struct GenerationInput<'geninp> {
     cargo_toml_config: BoxConfig<'geninp>,
@@ -189,14 +240,7 @@ impl<'geninp> GenerationInput<'geninp> {
     /// Identifier that might be put in the Cargo.toml file to know what rules
     /// were it generated from.
     pub fn generation_identifier(&self, targets_path: &TargetsPath) -> String {
-        let targets: Vec<_> = self
-            .lib
-            .iter()
-            .chain(self.bins.iter())
-            .chain(self.tests.iter())
-            .map(|manifest| manifest.raw().name.as_str())
-            .sorted()
-            .collect();
+        let targets = self.rule_names();
         let targets = if targets.len() > 1 {
             format!("[{}]", targets.join(","))
         } else {
@@ -205,6 +249,45 @@ impl<'geninp> GenerationInput<'geninp> {
         format!("//{}:{}", targets_path.as_dir().as_ref().display(), targets)
     }
 
+    /// The rule that defines autocargo.cargo_toml_config as a full Buck rule,
+    /// or the generation identifier if none of the rules defines it.
+    fn cargo_toml_config_rule(&self, targets_path: &TargetsPath) -> String {
+        self.lib
+            .iter()
+            .chain(self.bins.iter())
+            .chain(self.tests.iter())
+            .find(|manifest| manifest.raw().autocargo.cargo_toml_config.is_some())
+            .map_or_else(
+                || self.generation_identifier(targets_path),
+                |manifest| {
+                    format!(
+                        "//{}:{}",
+                        targets_path.as_dir().as_ref().display(),
+                        manifest.raw().name
+                    )
+                },
+            )
+    }
+
+    /// The rules that the Cargo.toml file is generated from, each as a full
+    /// Buck rule.
+    pub fn rules(&self, targets_path: &TargetsPath) -> Vec<String> {
+        self.rule_names()
+            .into_iter()
+            .map(|name| format!("//{}:{}", targets_path.as_dir().as_ref().display(), name))
+            .collect()
+    }
+
+    fn rule_names(&self) -> Vec<&str> {
+        self.lib
+            .iter()
+            .chain(self.bins.iter())
+            .chain(self.tests.iter())
+            .map(|manifest| manifest.raw().name.as_str())
+            .sorted()
+            .collect()
+    }
+
     /// Generate a Cargo.toml manifest.
     pub fn generate_manifest(
         &self,
@@ -225,7 +308,9 @@ impl<'geninp> GenerationInput<'geninp> {
     }
 
     /// Generate a oss version of Cargo.toml manifest if the project configures
-    /// a oss_git_config.public_cargo_dir.
+    /// a oss_git_config.public_cargo_dir. Also returns copies of files
+    /// referenced by the package if oss_git_config.copy_package_files is set
+    /// and a README stub if oss_git_config.generate_readme is set.
     pub fn generate_oss_manifest(
         &self,
         logger: &Logger,
@@ -233,7 +318,7 @@ impl<'geninp> GenerationInput<'geninp> {
         conf: &ProjectConf,
         targets_path: &TargetsPath,
         cargo_toml_dir: &PathInFbcode,
-    ) -> Result<Option<(CargoTomlPath, Manifest)>> {
+    ) -> Result<Option<OssManifest>> {
         conf.oss_git_config()
             .as_ref()
             .and_then(|oss_git_config| {
@@ -243,7 +328,7 @@ impl<'geninp> GenerationInput<'geninp> {
                     .map(|public_cargo_dir| (oss_git_config, public_cargo_dir))
             })
             .map(|(oss_git_config, public_cargo_dir)| -> Result<_> {
-                let (cargo_toml_path, manifest) = self.generate_manifest_impl(
+                let (original_cargo_toml_path, mut manifest) = self.generate_manifest_impl(
                     logger,
                     cargo_generator,
                     conf,
@@ -252,34 +337,35 @@ impl<'geninp> GenerationInput<'geninp> {
                     Some(oss_git_config),
                 )?;
 
-                let cargo_toml_path = {
-                    // We have to put the cargo_toml_path under public_cargo_dir
-                    let public_cargo_dir_parent =
-                        public_cargo_dir.as_ref().parent().ok_or_else(|| {
-                            anyhow!(
-                                "Failed to get parent of public_cargo_dir: {:?}",
-                                public_cargo_dir
-                            )
-                        })?;
-                    let cargo_toml_relative_path = cargo_toml_path
-                        .as_file()
-                        .as_ref()
-                        .strip_prefix(public_cargo_dir_parent)
-                        .with_context(|| {
-                            anyhow!(
-                                "Failed to strip prefix {} from {:?}, make \
-                                sure project's generated Cargo.toml files are \
-                                all inside of public_cargo_dir parent directory",
-                                public_cargo_dir_parent.display(),
-                                cargo_toml_path
-                            )
-                        })?;
-                    CargoTomlPath::new(
-                        public_cargo_dir.join_to_path_in_fbcode(cargo_toml_relative_path),
-                    )?
+                // We have to put the cargo_toml_path under public_cargo_dir
+                let cargo_toml_path = CargoTomlPath::new(to_public_cargo_dir(
+                    oss_git_config,
+                    original_cargo_toml_path.as_file(),
+                )?)?;
+
+                let mut copied_files = if oss_git_config.copy_package_files {
+                    copy_package_files(
+                        cargo_generator.fbcode_root(),
+                        &original_cargo_toml_path,
+                        &cargo_toml_path,
+                        &mut manifest,
+                    )
+                    .with_context(|| {
+                        format!("While copying package files to {}", public_cargo_dir)
+                    })?
+                } else {
+                    HashMap::new()
                 };
 
-                Ok((cargo_toml_path, manifest))
+                if oss_git_config.generate_readme {
+                    copied_files.extend(generate_additional_readme_files(
+                        cargo_generator.fbcode_root(),
+                        &cargo_toml_path,
+                        &mut manifest,
+                    ));
+                }
+
+                Ok((cargo_toml_path, manifest, copied_files))
             })
             .transpose()
             .with_context(|| format!("While generating oss manifest for project {}", conf.name()))
@@ -304,6 +390,7 @@ impl<'geninp> GenerationInput<'geninp> {
                 extra_buck_dependencies: _,
                 dependencies_override,
                 features: _,
+                feature_docs,
                 lib,
                 bin,
                 test,
@@ -313,6 +400,8 @@ impl<'geninp> GenerationInput<'geninp> {
                 patch,
                 profile,
                 lints,
+                badges,
+                package_metadata_tools,
             } = self.cargo_toml_config();
 
             let ProjectConfDefaults {
@@ -321,11 +410,15 @@ impl<'geninp> GenerationInput<'geninp> {
                 patch_generation: default_patch_generation,
                 patch: default_patch,
                 profile: default_profile,
+                dependency_versions: _,
             } = conf.defaults();
 
-            let features = self.generate_features();
+            let features = self.generate_features(
+                *conf.feature_propagation(),
+                conf.test_features_feature().as_deref(),
+            );
 
-            let features = match (oss_git_config, features.get(DEFAULT)) {
+            let mut features = match (oss_git_config, features.get(DEFAULT)) {
                 (Some(oss_git_config), Some(default_features)) => {
                     let mut default_features = default_features.clone();
                     default_features.retain(|f| {
@@ -353,10 +446,14 @@ impl<'geninp> GenerationInput<'geninp> {
             );
 
             let Dependencies {
-                dependencies,
-                dev_dependencies,
+                mut dependencies,
+                mut dev_dependencies,
                 build_dependencies,
-                target,
+                mut target,
+                unmatched_removals,
+                unused_overrides,
+                comments: dependency_comments,
+                select_features,
             } = DependenciesGenerator {
                 cargo_generator,
                 features: &features,
@@ -365,32 +462,283 @@ impl<'geninp> GenerationInput<'geninp> {
                 extra_buck_dependencies: self.extra_buck_dependencies(),
                 dependencies_override,
                 oss_git_config,
+                version_lockstep: conf.version_lockstep(),
+                dependency_versions: &conf.defaults().dependency_versions,
+                dedupe_dev_dependencies: *conf.dedupe_dev_dependencies(),
+                select_features: conf.select_features(),
+                dual_source_policy: *conf.dual_source_dependencies(),
             }
             .generate()
             .context("In dependencies generation")?;
 
+            // The OSS manifest is generated from the same rules, so report only
+            // once for the regular one.
+            if !unmatched_removals.is_empty() && oss_git_config.is_none() {
+                let rule = self.generation_identifier(targets_path);
+                if *conf.strict_removed_dependencies() {
+                    Err::<(), _>(anyhow!(
+                        "Removed dependencies that don't match any dependency of the \
+                        rule: {}",
+                        unmatched_removals.join(", "),
+                    ))
+                    .with_location(|| {
+                        (
+                            ErrorLocation::config_key(
+                                "autocargo.cargo_toml_config.extra_buck_dependencies",
+                            ),
+                            format!("In removed dependencies of {rule}"),
+                        )
+                    })?;
+                }
+                warn!(
+                    logger,
+                    "Rule {} removes dependencies in extra_buck_dependencies that don't match \
+                    any dependency generated from Buck: {}",
+                    rule,
+                    unmatched_removals.join(", "),
+                );
+            }
+            if !unused_overrides.is_empty() {
+                warn!(
+                    logger,
+                    "Rule {} has dependencies_override entries in cargo_toml_config that have no \
+                    effect on {}: {}",
+                    self.cargo_toml_config_rule(targets_path),
+                    self.generation_identifier(targets_path),
+                    unused_overrides.join(", "),
+                );
+            }
+            if oss_git_config.is_none() {
+                for manifest in self
+                    .lib
+                    .iter()
+                    .chain(self.bins.iter())
+                    .chain(self.tests.iter())
+                {
+                    let unused_keys =
+                        unused_target_config_keys(*manifest.fbconfig_rule_type(), manifest.raw());
+                    if !unused_keys.is_empty() {
+                        warn!(
+                            logger,
+                            "Rule //{}:{} has cargo_target_config keys that have no effect: {}",
+                            targets_path.as_dir().as_ref().display(),
+                            manifest.raw().name,
+                            unused_keys.join(", "),
+                        );
+                    }
+                }
+            }
+
+            let self_dev_dependency_feature = conf
+                .test_features_feature()
+                .as_ref()
+                .filter(|test_feature| self.lib.is_some() && features.contains_key(*test_feature));
+            if let Some(test_feature) = self_dev_dependency_feature {
+                dev_dependencies.insert(
+                    self.generate_package_name(targets_path),
+                    Dependency::Detailed(Box::new(DependencyDetail {
+                        path: Some(".".to_owned()),
+                        features: vec![test_feature.clone()],
+                        ..DependencyDetail::default()
+                    })),
+                );
+            }
+
+            resolve_feature_aliases(
+                logger,
+                &mut features,
+                std::iter::once(&mut dependencies)
+                    .chain(target.values_mut().map(|target| &mut target.dependencies)),
+            )
+            .with_location(|| {
+                (
+                    ErrorLocation::config_key("autocargo.cargo_toml_config.features"),
+                    "While resolving dependencies referenced by features".to_owned(),
+                )
+            })?;
+
+            for (feature, select_dependencies) in select_features {
+                let activated = features.entry(feature).or_default();
+                for name in select_dependencies {
+                    if !activated.contains(&name) {
+                        activated.push(name);
+                    }
+                }
+            }
+
+            for (target_key, os_features) in self.generate_os_features() {
+                let (dep_features, crate_features): (Vec<_>, Vec<_>) =
+                    os_features.into_iter().partition(|f| f.contains('/'));
+                add_target_feature_activations(
+                    &dependencies,
+                    &dev_dependencies,
+                    &mut target,
+                    target_key,
+                    dep_features,
+                )
+                .context("In platform specific features generation")?;
+                if !crate_features.is_empty() {
+                    warn!(
+                        logger,
+                        "Build file at {}: features {:?} are enabled only for {} and Cargo \
+                        can't enable them per platform, they will only be declared",
+                        targets_path.as_dir().as_ref().display(),
+                        crate_features,
+                        target_key.get(),
+                    );
+                    for feature in crate_features {
+                        features.entry(feature).or_default();
+                    }
+                }
+            }
+
+            for feature in feature_docs.keys() {
+                ensure!(
+                    features.contains_key(feature),
+                    "Documentation provided for feature {:?} which is not declared",
+                    feature,
+                );
+            }
+
+            let unsupported_rustc_flags = self.rustc_flags().unsupported;
+            if !unsupported_rustc_flags.is_empty() {
+                warn!(
+                    logger,
+                    "Build file at {}: rustc_flags {:?} have no Cargo equivalent, only --cfg \
+                    flags are supported, they will be skipped",
+                    targets_path.as_dir().as_ref().display(),
+                    unsupported_rustc_flags,
+                );
+            }
+
             let prefix_comment = format!(
-                "# {GENERATED_PREAMBLE} from {}\n\n",
-                self.generation_identifier(targets_path),
+                "{}{}\n",
+                preamble_comment(conf, &self.generation_identifier(targets_path)),
+                cargo_generator
+                    .stamp()
+                    .as_ref()
+                    .map(|inputs| inputs.stamp(conf))
+                    .transpose()?
+                    .map(|stamp| stamp.comment())
+                    .unwrap_or_default(),
             );
 
-            let manifest = Manifest {
+            let (examples, bins): (Vec<_>, Vec<_>) = self
+                .bins
+                .iter()
+                .map(|manifest| {
+                    generate_product(
+                        *manifest.fbconfig_rule_type(),
+                        manifest.raw(),
+                        targets_path,
+                        &cargo_toml_path,
+                    )
+                    .map(|product| (is_example_product(manifest.raw(), &product), product))
+                    .with_location(|| {
+                        (
+                            ErrorLocation::rule(&manifest.raw().name),
+                            format!("In bin '{}' product generation", manifest.raw().name),
+                        )
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?
+                .into_iter()
+                .partition(|(is_example, _)| *is_example);
+            let (benches, tests): (Vec<_>, Vec<_>) = self
+                .tests
+                .iter()
+                .map(|manifest| {
+                    generate_product(
+                        *manifest.fbconfig_rule_type(),
+                        manifest.raw(),
+                        targets_path,
+                        &cargo_toml_path,
+                    )
+                    .map(|product| (manifest, product))
+                    .with_location(|| {
+                        (
+                            ErrorLocation::rule(&manifest.raw().name),
+                            format!("In test '{}' product generation", manifest.raw().name),
+                        )
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?
+                .into_iter()
+                .partition(|(manifest, _)| manifest.is_bench());
+            let benches: Vec<_> = benches
+                .into_iter()
+                .map(|(manifest, product)| generate_bench(manifest, product))
+                .chain(bench.iter().cloned())
+                .collect();
+
+            let mut manifest = Manifest {
                 prefix_comment: Some(prefix_comment),
 
                 cargo_features: generate_field(cargo_features, default_cargo_features),
-                package: Some(
-                    generate_package(
+                package: Some({
+                    let deprecation = self.deprecation(conf, targets_path);
+                    let build_script = match (
+                        self.generated_build_script(deprecation)?,
+                        self.declared_build_script(targets_path, &cargo_toml_path)?,
+                    ) {
+                        (Some(generated), Some(declared)) => bail!(
+                            "The package needs the generated build script {} and the one \
+                            declared via autocargo.build_script {}, only one per package is \
+                            supported",
+                            generated,
+                            declared,
+                        ),
+                        (generated, declared) => declared.or_else(|| generated.map(str::to_owned)),
+                    };
+                    let mut package = generate_package(
                         self.generate_package_name(targets_path),
                         package,
                         default_package,
+                        cargo_generator.toolchain_rust_version().as_deref(),
                         &cargo_toml_path,
-                        self.lib
-                            .as_ref()
-                            .map(|lib| lib.thrift_config().is_some())
-                            .unwrap_or_default(),
+                        build_script.as_deref(),
                     )
-                    .context("In package generation")?,
-                ),
+                    .with_location(|| {
+                        (
+                            ErrorLocation::config_key("autocargo.cargo_toml_config.package"),
+                            "In package generation".to_owned(),
+                        )
+                    })?;
+                    validate_links(&package, || {
+                        Path::join(
+                            cargo_generator.fbcode_root().as_ref(),
+                            cargo_toml_path.as_dir().as_ref(),
+                        )
+                        .join("build.rs")
+                        .is_file()
+                    })
+                    .with_location(|| {
+                        (
+                            ErrorLocation::config_key("autocargo.cargo_toml_config.package.links"),
+                            "In package generation".to_owned(),
+                        )
+                    })?;
+                    if let Some(deprecation) = deprecation {
+                        add_deprecation_metadata(&mut package, deprecation)?;
+                    }
+                    add_tool_metadata(&mut package, package_metadata_tools).with_location(
+                        || {
+                            (
+                                ErrorLocation::config_key(
+                                    "autocargo.cargo_toml_config.package_metadata_tools",
+                                ),
+                                "In package generation".to_owned(),
+                            )
+                        },
+                    )?;
+                    add_mapped_srcs_includes(
+                        &mut package,
+                        targets_path,
+                        &cargo_toml_path,
+                        &self.mapped_srcs_copies(),
+                    )?;
+                    package
+                }),
 
                 lib: self
                     .lib
@@ -401,53 +749,41 @@ impl<'geninp> GenerationInput<'geninp> {
                             targets_path,
                             &cargo_toml_path,
                         )
-                        .with_context(|| {
-                            format!("In lib '{}' product generation", manifest.raw().name)
+                        .with_location(|| {
+                            (
+                                ErrorLocation::rule(&manifest.raw().name),
+                                format!("In lib '{}' product generation", manifest.raw().name),
+                            )
                         })
                     })
                     .transpose()?
                     .or_else(|| lib.clone()),
-                bin: self
-                    .bins
-                    .iter()
-                    .map(|manifest| {
-                        generate_product(
-                            *manifest.fbconfig_rule_type(),
-                            manifest.raw(),
-                            targets_path,
-                            &cargo_toml_path,
-                        )
-                        .with_context(|| {
-                            format!("In bin '{}' product generation", manifest.raw().name)
-                        })
-                    })
-                    .chain(bin.iter().cloned().map(Ok))
-                    .collect::<Result<_>>()?,
-                example: example.clone(),
-                test: self
-                    .tests
-                    .iter()
-                    .map(|manifest| {
-                        generate_product(
-                            *manifest.fbconfig_rule_type(),
-                            manifest.raw(),
-                            targets_path,
-                            &cargo_toml_path,
-                        )
-                        .with_context(|| {
-                            format!("In test '{}' product generation", manifest.raw().name)
-                        })
-                    })
-                    .chain(test.iter().cloned().map(Ok))
-                    .collect::<Result<_>>()?,
-                bench: bench.clone(),
+                bin: bins
+                    .into_iter()
+                    .map(|(_, product)| product)
+                    .chain(bin.iter().cloned())
+                    .collect(),
+                example: examples
+                    .into_iter()
+                    .map(|(_, product)| product)
+                    .chain(example.iter().cloned())
+                    .collect(),
+                test: tests
+                    .into_iter()
+                    .map(|(_, product)| product)
+                    .chain(test.iter().cloned())
+                    .collect(),
+                bench: apply_bench_defaults(&benches, conf.bench_defaults(), &mut dev_dependencies),
+                badges: badges.clone(),
 
                 dependencies,
                 dev_dependencies,
                 build_dependencies,
                 target,
+                dependency_comments,
 
                 features,
+                feature_docs: feature_docs.clone(),
                 patch: cargo_generator
                     .generate_patch(
                         patch_generation
@@ -455,11 +791,22 @@ impl<'geninp> GenerationInput<'geninp> {
                             .unwrap_or(default_patch_generation),
                         default_patch.iter().chain(patch.iter()),
                     )
-                    .context("In patch generation")?,
+                    .with_location(|| {
+                        (
+                            ErrorLocation::config_key("autocargo.cargo_toml_config.patch"),
+                            "In patch generation".to_owned(),
+                        )
+                    })?,
                 profile: generate_field(profile, default_profile),
                 workspace: workspace.clone(),
                 lints: lints.clone(),
             };
+            if *conf.disable_autodiscovery() {
+                disable_autodiscovery(&mut manifest, package);
+            }
+            validate_package_files(cargo_generator.fbcode_root(), &cargo_toml_path, &manifest)?;
+            validate_product_editions(&manifest)?;
+            validate_toolchain_editions(&manifest, *conf.max_edition())?;
             (cargo_toml_path, manifest)
         };
 
@@ -471,43 +818,216 @@ impl<'geninp> GenerationInput<'geninp> {
     }
 
     /// If not provided via cargo_toml_config the features will be taken from
-    /// combined rules' default_features attributes.
-    fn generate_features(&self) -> FeatureSet {
+    /// combined rules' default_features attributes. Features of bin and test
+    /// rules are handled according to feature_propagation of the rule or else
+    /// of the project. Test features of all rules are put in test_feature if
+    /// given.
+    fn generate_features(
+        &self,
+        feature_propagation: FeaturePropagation,
+        test_feature: Option<&str>,
+    ) -> FeatureSet {
         if let Some(features) = self.cargo_toml_config().features.clone() {
             features
         } else {
-            let default_features: Vec<_> = self
-                .lib
+            let rule_features = |manifest: &BuckManifest| {
+                let rust_config = &manifest.raw().rust_config;
+                rust_config
+                    .features
+                    .iter()
+                    .chain(
+                        test_feature
+                            .is_none()
+                            .then_some(&rust_config.test_features)
+                            .into_iter()
+                            .flatten(),
+                    )
+                    .cloned()
+                    .collect::<Vec<_>>()
+            };
+            let test_features = test_feature.map(|test_feature| {
+                let features = self
+                    .lib
+                    .iter()
+                    .chain(self.bins.iter())
+                    .chain(self.tests.iter())
+                    .flat_map(|manifest| manifest.raw().rust_config.test_features.iter())
+                    .unique()
+                    .cloned()
+                    .collect();
+                (test_feature.to_owned(), features, FeaturePropagation::Named)
+            });
+            combine_features(
+                self.lib.iter().flat_map(|lib| rule_features(lib)).collect(),
+                self.bins
+                    .iter()
+                    .map(|bin| ("bin", bin))
+                    .chain(self.tests.iter().map(|test| ("test", test)))
+                    .map(|(kind, manifest)| {
+                        (
+                            format!("{kind}-{}", manifest.raw().name),
+                            rule_features(manifest),
+                            manifest
+                                .raw()
+                                .autocargo
+                                .cargo_target_config
+                                .feature_propagation
+                                .unwrap_or(feature_propagation),
+                        )
+                    })
+                    .chain(test_features),
+            )
+        }
+    }
+
+    /// Platform specific features of the combined rules, keyed by the Cargo
+    /// target they apply to. Ignored if features are provided via
+    /// cargo_toml_config, same as in [Self::generate_features].
+    fn generate_os_features(&self) -> BTreeMap<&'static TargetKey, Vec<String>> {
+        if self.cargo_toml_config().features.is_some() {
+            return BTreeMap::new();
+        }
+        self.lib
+            .iter()
+            .chain(self.bins.iter())
+            .chain(self.tests.iter())
+            .flat_map(|manifest| manifest.os_features())
+            .flat_map(|(os, features)| {
+                features
+                    .iter()
+                    .map(move |feature| (os.to_cargo_target(), feature.clone()))
+            })
+            .sorted()
+            .dedup()
+            .into_group_map()
+            .into_iter()
+            .collect()
+    }
+
+    /// Deprecation notice of the generated package, if the project schedules
+    /// it for removal.
+    fn deprecation<'conf>(
+        &self,
+        conf: &'conf ProjectConf,
+        targets_path: &TargetsPath,
+    ) -> Option<&'conf CrateDeprecation> {
+        conf.deprecated_crates()
+            .get(&self.generate_package_name(targets_path))
+    }
+
+    /// Name of the build script that autocargo generates next to Cargo.toml
+    /// for codegen libraries, for copying mapped_srcs or for printing a
+    /// deprecation warning, if any.
+    fn generated_build_script(
+        &self,
+        deprecation: Option<&CrateDeprecation>,
+    ) -> Result<Option<&'static str>> {
+        let codegen_build_script = match &self.lib {
+            None => None,
+            Some(lib) => match (
+                lib.thrift_config().is_some(),
+                lib.raw().autocargo.protobuf.is_some(),
+                lib.raw().autocargo.cxx.is_some(),
+            ) {
+                (true, false, false) => Some(PathInFbcode::thrift_build_filename()),
+                (false, true, false) => Some(PathInFbcode::protobuf_build_filename()),
+                (false, false, true) => Some(PathInFbcode::cxx_build_filename()),
+                (false, false, false) => None,
+                _ => bail!(
+                    "Rule {} has more than one of thrift, protobuf and cxx configs, only \
+                    one kind of codegen per library is supported",
+                    lib.raw().name,
+                ),
+            },
+        };
+        let build_scripts: Vec<_> = codegen_build_script
+            .into_iter()
+            .chain(
+                (!self.mapped_srcs_copies().is_empty())
+                    .then(PathInFbcode::mapped_srcs_build_filename),
+            )
+            .chain(
+                deprecation
+                    .filter(|deprecation| deprecation.build_warning)
+                    .map(|_| PathInFbcode::deprecation_build_filename()),
+            )
+            .chain(
+                (!self.rustc_flags().cfgs.is_empty()).then(PathInFbcode::rustc_cfg_build_filename),
+            )
+            .collect();
+        match build_scripts.as_slice() {
+            [] => Ok(None),
+            [build_script] => Ok(Some(build_script)),
+            _ => bail!(
+                "The package needs multiple generated build scripts {:?}, only one \
+                per package is supported",
+                build_scripts,
+            ),
+        }
+    }
+
+    /// Entry point of the checked-in build script declared via
+    /// autocargo.build_script by one of the combined rules, relative to the
+    /// Cargo.toml file.
+    fn declared_build_script(
+        &self,
+        targets_path: &TargetsPath,
+        cargo_toml_path: &CargoTomlPath,
+    ) -> Result<Option<String>> {
+        let declared: Vec<_> = self
+            .lib
+            .iter()
+            .chain(self.bins.iter())
+            .filter_map(|manifest| {
+                manifest
+                    .raw()
+                    .autocargo
+                    .build_script
+                    .as_ref()
+                    .map(|build_script| (&manifest.raw().name, build_script))
+            })
+            .collect();
+        match declared.as_slice() {
+            [] => Ok(None),
+            [(name, build_script)] => {
+                let entry_point = build_script.srcs.first().ok_or_else(|| {
+                    anyhow!("autocargo.build_script of rule {} has no srcs", name)
+                })?;
+                relative_crate_root(entry_point, targets_path, cargo_toml_path).map(Some)
+            }
+            _ => bail!(
+                "Rules {:?} declare autocargo.build_script, only one per package is supported",
+                declared.iter().map(|(name, _)| name).collect::<Vec<_>>(),
+            ),
+        }
+    }
+
+    /// Plain file mapped_srcs of all the combined rules that have to be copied
+    /// into place by the generated build script.
+    fn mapped_srcs_copies(&self) -> Vec<(&Path, &str)> {
+        mapped_srcs_copies(
+            self.lib
                 .iter()
                 .chain(self.bins.iter())
                 .chain(self.tests.iter())
-                .flat_map(|manifest| {
-                    let rust_config = &manifest.raw().rust_config;
-                    rust_config
-                        .features
-                        .iter()
-                        .chain(rust_config.test_features.iter())
-                })
-                .cloned()
-                .collect();
+                .map(|manifest| &**manifest.raw()),
+        )
+    }
 
-            let mut features = FeatureSet::default();
-            if !default_features.is_empty() {
-                features.extend(default_features.iter().filter_map(|f| {
-                    if f.contains('/') {
-                        None
-                    } else {
-                        Some((f.clone(), Vec::new()))
-                    }
-                }));
-                features.insert(DEFAULT.to_owned(), default_features);
-            }
-            features
-        }
+    /// rustc_flags of all the combined rules.
+    fn rustc_flags(&self) -> RustcFlags<'_> {
+        rustc_flags(
+            self.lib
+                .iter()
+                .chain(self.bins.iter())
+                .chain(self.tests.iter())
+                .map(|manifest| &**manifest.raw()),
+        )
     }
 
     pub fn generate_additional_files(
         &self,
+        conf: &ProjectConf,
         targets_path: &TargetsPath,
         cargo_toml_dir: &PathInFbcode,
     ) -> Result<HashMap<PathInFbcode, String>> {
@@ -524,17 +1044,220 @@ impl<'geninp> GenerationInput<'geninp> {
                     autocargo_thrift,
                 );
             }
+            if let Some(autocargo_protobuf) = &lib.raw().autocargo.protobuf {
+                return generate_additional_protobuf_files(
+                    targets_path,
+                    &cargo_toml_path,
+                    autocargo_protobuf,
+                );
+            }
+            if let Some(autocargo_cxx) = &lib.raw().autocargo.cxx {
+                return generate_additional_cxx_files(
+                    targets_path,
+                    &cargo_toml_path,
+                    &lib.raw().name,
+                    autocargo_cxx,
+                );
+            }
+        }
+        if let Some(deprecation) = self
+            .deprecation(conf, targets_path)
+            .filter(|deprecation| deprecation.build_warning)
+        {
+            return Ok(generate_additional_deprecation_files(
+                &cargo_toml_path,
+                &self.generate_package_name(targets_path),
+                deprecation,
+            ));
+        }
+        let copies = self.mapped_srcs_copies();
+        if !copies.is_empty() {
+            return generate_additional_mapped_srcs_files(targets_path, &cargo_toml_path, &copies);
+        }
+        let RustcFlags { cfgs, .. } = self.rustc_flags();
+        if !cfgs.is_empty() {
+            return Ok(generate_additional_rustc_cfg_files(&cargo_toml_path, &cfgs));
         }
         Ok(HashMap::new())
     }
 }
 
+/// Render the preamble of the project for a Cargo.toml file generated from
+/// the rule as TOML comment lines.
+fn preamble_comment(conf: &ProjectConf, rule: &str) -> String {
+    conf.preamble()
+        .as_deref()
+        .unwrap_or("{marker} from {rule}")
+        .trim_end()
+        .replace("{marker}", GENERATED_PREAMBLE)
+        .replace("{rule}", rule)
+        .replace("{project}", conf.name())
+        .replace("{oncall}", conf.oncall())
+        .lines()
+        .map(|line| match line.trim_end() {
+            "" => "#\n".to_owned(),
+            line => format!("# {line}\n"),
+        })
+        .collect()
+}
+
 fn generate_field<T: Clone>(first_choice: &Option<T>, second_choice: &T) -> T {
     first_choice
         .clone()
         .unwrap_or_else(|| second_choice.clone())
 }
 
+/// Copy files referenced by `readme` and `license-file` of the manifest
+/// generated from original_cargo_toml_path next to cargo_toml_path and point
+/// the fields to the copies. Copies of markdown readmes get the generated
+/// preamble, so that they are told apart from hand-written ones and deleted
+/// once they are no longer copied, like license files named as in
+/// [PathInFbcode::license_filenames].
+fn copy_package_files(
+    fbcode_root: &FbcodeRoot,
+    original_cargo_toml_path: &CargoTomlPath,
+    cargo_toml_path: &CargoTomlPath,
+    manifest: &mut Manifest,
+) -> Result<HashMap<PathInFbcode, String>> {
+    let mut copied_files = HashMap::new();
+    let Some(package) = &mut manifest.package else {
+        return Ok(copied_files);
+    };
+    for (field, value) in [
+        ("readme", &mut package.readme),
+        ("license-file", &mut package.license_file),
+    ] {
+        let Some(relative) = value.as_mut() else {
+            continue;
+        };
+        let source = original_cargo_toml_path
+            .as_dir()
+            .join_to_path_in_fbcode(&*relative);
+        let filename = source
+            .as_ref()
+            .file_name()
+            .and_then(|name| name.to_str())
+            .ok_or_else(|| anyhow!("Field {} points to {} without filename", field, source))?
+            .to_owned();
+        let mut content =
+            std::fs::read_to_string(Path::join(fbcode_root.as_ref(), source.as_ref()))
+                .with_context(|| format!("While reading {} file {}", field, source))?;
+        if field == "readme" && filename.ends_with(".md") {
+            content = format!("<!-- {GENERATED_PREAMBLE} from {source} -->\n\n{content}");
+        }
+        copied_files.insert(
+            cargo_toml_path.as_dir().join_to_path_in_fbcode(&filename),
+            content,
+        );
+        *relative = filename;
+    }
+    Ok(copied_files)
+}
+
+/// Build the [features] section from the features of the library, which are
+/// default ones, and the features of other rules under the names of their
+/// named features together with how they are propagated.
+fn combine_features(
+    mut default_features: Vec<String>,
+    rule_features: impl IntoIterator<Item = (String, Vec<String>, FeaturePropagation)>,
+) -> FeatureSet {
+    let mut named_features = FeatureSet::default();
+    for (name, features, feature_propagation) in rule_features {
+        match feature_propagation {
+            FeaturePropagation::Default => default_features.extend(features),
+            FeaturePropagation::Named if !features.is_empty() => {
+                named_features.insert(name, features);
+            }
+            FeaturePropagation::Named | FeaturePropagation::Drop => {}
+        }
+    }
+
+    let mut features = FeatureSet::default();
+    features.extend(
+        default_features
+            .iter()
+            .chain(named_features.values().flatten())
+            .filter_map(|f| {
+                if f.contains('/') {
+                    None
+                } else {
+                    Some((f.clone(), Vec::new()))
+                }
+            }),
+    );
+    if !default_features.is_empty() {
+        features.insert(DEFAULT.to_owned(), default_features);
+    }
+    features.extend(named_features);
+    features
+}
+
+/// Turn the product generated from a rust_unittest rule marked as bench into a
+/// [[bench]]. The bench marker is not a setting of the product, and the harness
+/// is turned off if the rule depends on criterion, which provides its own main,
+/// otherwise it is left to the bench_defaults of the project unless the rule
+/// turned it off.
+fn generate_bench(manifest: &BuckManifest, product: Product) -> Product {
+    Product {
+        bench: None,
+        harness: if manifest.depends_on_third_party(CRITERION_CRATE) {
+            Some(false)
+        } else {
+            product.harness.filter(|harness| !harness)
+        },
+        ..product
+    }
+}
+
+/// Fill in the harness of the benches from bench_defaults and add its
+/// dev-dependencies if there are any benches.
+fn apply_bench_defaults(
+    benches: &[Product],
+    bench_defaults: &BenchDefaults,
+    dev_dependencies: &mut DepsSet,
+) -> Vec<Product> {
+    if !benches.is_empty() {
+        for (name, dep) in &bench_defaults.dev_dependencies {
+            dev_dependencies
+                .entry(name.clone())
+                .or_insert_with(|| dep.clone());
+        }
+    }
+    benches
+        .iter()
+        .map(|bench| Product {
+            harness: bench.harness.or(bench_defaults.harness),
+            ..bench.clone()
+        })
+        .collect()
+}
+
+/// Make sure that files referenced by `readme` and `license-file` exist.
+fn validate_package_files(
+    fbcode_root: &FbcodeRoot,
+    cargo_toml_path: &CargoTomlPath,
+    manifest: &Manifest,
+) -> Result<()> {
+    let Some(package) = &manifest.package else {
+        return Ok(());
+    };
+    for (field, value) in [
+        ("readme", &package.readme),
+        ("license-file", &package.license_file),
+    ] {
+        if let Some(relative) = value {
+            let path = cargo_toml_path.as_dir().join_to_path_in_fbcode(relative);
+            ensure!(
+                Path::join(fbcode_root.as_ref(), path.as_ref()).is_file(),
+                "Field {} points to {}, but there is no such file",
+                field,
+                path,
+            );
+        }
+    }
+    Ok(())
+}
+
 fn generate_path_field(
     first_choice: &Option<Option<String>>,
     second_choice: &Option<PathInFbcode>,
@@ -544,7 +1267,7 @@ fn generate_path_field(
         val
     } else if let Some(path) = second_choice.clone() {
         Some(
-            diff_paths(path.as_ref(), cargo_toml_path.as_dir().as_ref())
+            relative_path(path.as_ref(), cargo_toml_path.as_dir().as_ref())
                 .and_then(|path| path.to_str().map(|s| s.to_owned()))
                 .ok_or_else(|| {
                     anyhow!(
@@ -585,6 +1308,8 @@ mod r#impl {
 
 #[cfg(test)]
 mod test {
+    use cargo_toml::Dependency;
+
     use super::*;
     use crate::paths::PathInFbcode;
 
@@ -607,4 +1332,159 @@ mod test {
             cp("../biz/Cargo.toml"),
         );
     }
+
+    #[test]
+    fn copy_package_files_test() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("foo/docs")).unwrap();
+        std::fs::write(dir.path().join("foo/docs/README.md"), "# Foo\n").unwrap();
+        std::fs::write(dir.path().join("foo/LICENSE-MIT"), "MIT License\n").unwrap();
+        let cp = |s: &str| CargoTomlPath::new(PathInFbcode::new_mock(s)).unwrap();
+        let mut manifest = Manifest {
+            package: Some({
+                let mut package = crate::cargo_manifest::empty_package();
+                package.readme = Some("docs/README.md".to_owned());
+                package.license_file = Some("LICENSE-MIT".to_owned());
+                package
+            }),
+            ..Manifest::default()
+        };
+
+        let copied = copy_package_files(
+            &FbcodeRoot::new_mock(dir.path()),
+            &cp("foo/Cargo.toml"),
+            &cp("public/foo/Cargo.toml"),
+            &mut manifest,
+        )
+        .unwrap();
+        // The copied readme is marked as generated, so that it is deleted
+        // once it is no longer copied.
+        assert_eq!(
+            copied,
+            HashMap::from([
+                (
+                    PathInFbcode::new_mock("public/foo/README.md"),
+                    format!("<!-- {GENERATED_PREAMBLE} from foo/docs/README.md -->\n\n# Foo\n"),
+                ),
+                (
+                    PathInFbcode::new_mock("public/foo/LICENSE-MIT"),
+                    "MIT License\n".to_owned(),
+                ),
+            ]),
+        );
+        let package = manifest.package.unwrap();
+        assert_eq!(package.readme.as_deref(), Some("README.md"));
+        assert_eq!(package.license_file.as_deref(), Some("LICENSE-MIT"));
+        assert!(copied.keys().all(|path| {
+            PathInFbcode::all_additional_filenames()
+                .into_iter()
+                .any(|filename| path.as_ref().ends_with(filename))
+        }));
+    }
+
+    #[test]
+    fn combine_features_test() {
+        let s = |s: &str| s.to_owned();
+        let v = |features: &[&str]| -> Vec<String> {
+            features.iter().map(|f| (*f).to_owned()).collect()
+        };
+        let rules = |propagation| {
+            vec![
+                (s("bin-foo"), v(&["fast", "dep/std"]), propagation),
+                (s("test-foo-unittest"), v(&["mocks"]), propagation),
+                (s("bin-empty"), Vec::new(), propagation),
+            ]
+        };
+
+        assert_eq!(
+            combine_features(v(&["lib"]), rules(FeaturePropagation::Default)),
+            FeatureSet::from([
+                (s("default"), v(&["lib", "fast", "dep/std", "mocks"])),
+                (s("fast"), Vec::new()),
+                (s("lib"), Vec::new()),
+                (s("mocks"), Vec::new()),
+            ])
+        );
+        assert_eq!(
+            combine_features(v(&["lib"]), rules(FeaturePropagation::Named)),
+            FeatureSet::from([
+                (s("bin-foo"), v(&["fast", "dep/std"])),
+                (s("default"), v(&["lib"])),
+                (s("fast"), Vec::new()),
+                (s("lib"), Vec::new()),
+                (s("mocks"), Vec::new()),
+                (s("test-foo-unittest"), v(&["mocks"])),
+            ])
+        );
+        assert_eq!(
+            combine_features(Vec::new(), rules(FeaturePropagation::Drop)),
+            FeatureSet::new()
+        );
+    }
+
+    #[test]
+    fn apply_bench_defaults_test() {
+        let bench = |name: &str, harness: Option<bool>| Product {
+            name: Some(name.to_owned()),
+            harness,
+            ..Product::default()
+        };
+        let bench_defaults = BenchDefaults {
+            harness: Some(false),
+            dev_dependencies: DepsSet::from([(
+                "criterion".to_owned(),
+                Dependency::Simple("0.5".to_owned()),
+            )]),
+        };
+
+        let mut dev_dependencies = DepsSet::new();
+        assert!(apply_bench_defaults(&[], &bench_defaults, &mut dev_dependencies).is_empty());
+        assert!(dev_dependencies.is_empty());
+
+        dev_dependencies.insert("criterion".to_owned(), Dependency::Simple("0.4".to_owned()));
+        let benches = apply_bench_defaults(
+            &[bench("foo", None), bench("bar", Some(true))],
+            &bench_defaults,
+            &mut dev_dependencies,
+        );
+        assert_eq!(
+            benches
+                .iter()
+                .map(|bench| bench.harness)
+                .collect::<Vec<_>>(),
+            vec![Some(false), Some(true)]
+        );
+        assert_eq!(
+            dev_dependencies,
+            DepsSet::from([("criterion".to_owned(), Dependency::Simple("0.4".to_owned()),)])
+        );
+    }
+
+    #[test]
+    fn preamble_comment_test() {
+        let conf = |preamble: Option<&str>| -> ProjectConf {
+            serde_json::from_value(serde_json::json!({
+                "name": "proj",
+                "oncall": "proj_oncall",
+                "preamble": preamble,
+            }))
+            .unwrap()
+        };
+
+        assert_eq!(
+            preamble_comment(&conf(None), "//foo:bar"),
+            format!("# {GENERATED_PREAMBLE} from //foo:bar\n")
+        );
+        assert_eq!(
+            preamble_comment(
+                &conf(Some(
+                    "Owned by {oncall}\n\n{marker} from {rule}, run autocargo -p {project}\n"
+                )),
+                "//foo:bar"
+            ),
+            format!(
+                "# Owned by proj_oncall\n#\n# {GENERATED_PREAMBLE} from //foo:bar, run autocargo -p proj\n"
+            )
+        );
+    }
 }
@@ -7,16 +7,18 @@
  * of this source tree.
  */
 
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
 
-use maplit::hashmap;
 use slog::Logger;
 use slog::trace;
 
 use crate::buck_processing::BuckDependency;
 use crate::buck_processing::BuckManifest;
 use crate::buck_processing::CODEGEN_INCLUDER_PROC_MACRO_RULE;
+use crate::buck_processing::CXX_BUILD_CRATE;
+use crate::buck_processing::CXX_CRATE;
 use crate::buck_processing::OsDepsPlatform;
 use crate::buck_processing::RawBuckManifest;
 use crate::buck_processing::RawFbconfigRuleType;
@@ -37,8 +39,11 @@ pub struct ConsolidatedDependencies<'a> {
     pub test_deps: Deps<'a>,
     pub test_named_deps: NamedDeps<'a>,
     pub test_os_deps: HashMap<OsDepsPlatform, Deps<'a>>,
-    /// Build deps don't currently exist on Buck rules, but we want to store e.g.
-    /// thrift build deps here.
+    /// Deps of lib and bins from select() branches per constraint value.
+    pub select_deps: BTreeMap<&'a str, Deps<'a>>,
+    /// Build deps don't exist on Buck rules, they come from the checked-in
+    /// build scripts declared via autocargo.build_script and from codegen,
+    /// e.g. thrift build deps.
     pub build_deps: Deps<'a>,
 }
 
@@ -63,6 +68,7 @@ impl<'a> ConsolidatedDependencies<'a> {
             .collect();
 
         let thrift_config = lib.and_then(|lib| lib.thrift_config().as_ref());
+        let is_cxx = lib.is_some_and(|lib| lib.raw().autocargo.cxx.is_some());
 
         // The [dependency] section is for lib and bins
         let lib_and_bins = lib.iter().chain(bins.iter());
@@ -85,6 +91,9 @@ impl<'a> ConsolidatedDependencies<'a> {
                     &*thrift_config.codegen_includer_proc_macro,
                 );
             }
+            if is_cxx {
+                deps.third_party.insert(CXX_CRATE);
+            }
             deps
         };
         let named_deps = NamedDeps::from_named_deps(
@@ -153,18 +162,46 @@ impl<'a> ConsolidatedDependencies<'a> {
             })
             .unzip();
 
-        let build_deps = Deps {
-            third_party: HashSet::new(),
-            fbcode: if let Some(thrift_config) = thrift_config {
-                hashmap! {
+        let select_deps = lib_and_bins
+            .clone()
+            .flat_map(|manifest| manifest.select_deps().keys())
+            .map(|constraint| {
+                let deps = Deps::from_deps(
+                    logger,
+                    cargo_generator,
+                    targets_path,
+                    &local_rules,
+                    lib_and_bins.clone().flat_map(|manifest| {
+                        manifest.select_deps().get(constraint).into_iter().flatten()
+                    }),
+                );
+                (constraint.as_str(), deps)
+            })
+            .collect();
+
+        let build_deps = {
+            let mut build_deps = Deps::from_deps(
+                logger,
+                cargo_generator,
+                targets_path,
+                &local_rules,
+                lib_and_bins
+                    .clone()
+                    .flat_map(|manifest| manifest.build_script_deps().iter()),
+            );
+            if is_cxx {
+                build_deps.third_party.insert(CXX_BUILD_CRATE);
+            }
+            if let Some(thrift_config) = thrift_config {
+                build_deps.fbcode.insert(
                     FbcodeRule::unsafe_from_buck_rule(
                         &THRIFT_COMPILER_RULE.path,
                         &THRIFT_COMPILER_RULE.name,
-                    ) => &*thrift_config.thrift_compiler
-                }
-            } else {
-                HashMap::new()
-            },
+                    ),
+                    &*thrift_config.thrift_compiler,
+                );
+            }
+            build_deps
         };
 
         ConsolidatedDependencies {
@@ -174,6 +211,7 @@ impl<'a> ConsolidatedDependencies<'a> {
             test_deps,
             test_named_deps,
             test_os_deps,
+            select_deps,
             build_deps,
         }
     }
@@ -244,6 +282,7 @@ pub use r#impl::FbcodeRule;
 pub struct Deps<'a> {
     pub third_party: HashSet<&'a str>,
     pub fbcode: HashMap<FbcodeRule<'a>, &'a RawBuckManifest>,
+    pub cell: HashSet<&'a str>,
 }
 
 impl<'a> Deps<'a> {
@@ -259,12 +298,21 @@ impl<'a> Deps<'a> {
                 .clone()
                 .filter_map(|dep| match dep {
                     BuckDependency::ThirdPartyCrate(name) => Some(name.as_str()),
-                    BuckDependency::FbcodeCrate(_, _) => None,
+                    BuckDependency::FbcodeCrate(_, _) | BuckDependency::CellRule(_) => None,
+                })
+                .collect(),
+            cell: deps
+                .clone()
+                .filter_map(|dep| match dep {
+                    BuckDependency::CellRule(rule) => {
+                        mapped_cell_rule(logger, cargo_generator, local_targets_path, rule)
+                    }
+                    BuckDependency::ThirdPartyCrate(_) | BuckDependency::FbcodeCrate(_, _) => None,
                 })
                 .collect(),
             fbcode: deps
                 .filter_map(|dep| match dep {
-                    BuckDependency::ThirdPartyCrate(_) => None,
+                    BuckDependency::ThirdPartyCrate(_) | BuckDependency::CellRule(_) => None,
                     BuckDependency::FbcodeCrate(targets_path, raw) => {
                         if &**targets_path == local_targets_path
                             && local_rules.contains(raw.name.as_str())
@@ -285,9 +333,17 @@ impl<'a> Deps<'a> {
 pub struct NamedDeps<'a> {
     pub third_party: HashSet<(&'a str, &'a str)>,
     pub fbcode: HashMap<(&'a str, FbcodeRule<'a>), &'a RawBuckManifest>,
+    pub cell: HashSet<(&'a str, &'a str)>,
 }
 
 impl<'a> NamedDeps<'a> {
+    /// True if any of the dependencies uses the alias.
+    pub fn contains_alias(&self, alias: &str) -> bool {
+        self.third_party.iter().any(|(a, _)| *a == alias)
+            || self.fbcode.keys().any(|(a, _)| *a == alias)
+            || self.cell.iter().any(|(a, _)| *a == alias)
+    }
+
     fn from_named_deps(
         logger: &Logger,
         cargo_generator: &CargoGenerator<'_>,
@@ -300,12 +356,22 @@ impl<'a> NamedDeps<'a> {
                 .clone()
                 .filter_map(|(alias, dep)| match dep {
                     BuckDependency::ThirdPartyCrate(name) => Some((alias.as_str(), name.as_str())),
-                    BuckDependency::FbcodeCrate(_, _) => None,
+                    BuckDependency::FbcodeCrate(_, _) | BuckDependency::CellRule(_) => None,
+                })
+                .collect(),
+            cell: named_deps
+                .clone()
+                .filter_map(|(alias, dep)| match dep {
+                    BuckDependency::CellRule(rule) => {
+                        mapped_cell_rule(logger, cargo_generator, local_targets_path, rule)
+                            .map(|rule| (alias.as_str(), rule))
+                    }
+                    BuckDependency::ThirdPartyCrate(_) | BuckDependency::FbcodeCrate(_, _) => None,
                 })
                 .collect(),
             fbcode: named_deps
                 .filter_map(|(alias, dep)| match dep {
-                    BuckDependency::ThirdPartyCrate(_) => None,
+                    BuckDependency::ThirdPartyCrate(_) | BuckDependency::CellRule(_) => None,
                     BuckDependency::FbcodeCrate(targets_path, raw) => {
                         if &**targets_path == local_targets_path
                             && local_rules.contains(raw.name.as_str())
@@ -321,3 +387,24 @@ impl<'a> NamedDeps<'a> {
         }
     }
 }
+
+/// Return the rule of another cell if it is mapped in cell_dependencies,
+/// otherwise the dependency is not supported and ignored.
+fn mapped_cell_rule<'a>(
+    logger: &Logger,
+    cargo_generator: &CargoGenerator<'_>,
+    local_targets_path: &TargetsPath,
+    rule: &'a str,
+) -> Option<&'a str> {
+    if cargo_generator.cell_dependencies().contains_key(rule) {
+        Some(rule)
+    } else {
+        trace!(
+            logger,
+            "Build file at {}: Dependency on {} is not supported, it is not in cell_dependencies",
+            local_targets_path.as_dir().as_ref().display(),
+            rule
+        );
+        None
+    }
+}
@@ -0,0 +1,136 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use anyhow::ensure;
+use itertools::Itertools;
+use maplit::hashmap;
+use quote::quote;
+
+use super::thrift_additional::relative_src_path;
+use super::thrift_additional::render;
+use crate::buck_processing::AutocargoCxx;
+use crate::paths::CargoTomlPath;
+use crate::paths::PathInFbcode;
+use crate::paths::TargetsPath;
+
+pub fn generate_additional_cxx_files(
+    targets_path: &TargetsPath,
+    cargo_toml_path: &CargoTomlPath,
+    library_name: &str,
+    autocargo_cxx: &AutocargoCxx,
+) -> Result<HashMap<PathInFbcode, String>> {
+    ensure!(
+        !autocargo_cxx.bridges.is_empty(),
+        "Cxx library in {} has no bridges",
+        targets_path.as_dir(),
+    );
+
+    let relative_paths = |paths: &[String]| {
+        paths
+            .iter()
+            .sorted()
+            .map(|path| relative_src_path(targets_path, cargo_toml_path, path))
+            .collect::<Result<Vec<_>>>()
+    };
+    let bridges = relative_paths(&autocargo_cxx.bridges)?;
+    let srcs = relative_paths(&autocargo_cxx.srcs)?;
+    let includes = autocargo_cxx
+        .includes
+        .iter()
+        .map(|include| relative_src_path(targets_path, cargo_toml_path, include))
+        .map_ok(|include| {
+            if include.is_empty() {
+                ".".to_owned()
+            } else {
+                include
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let std = autocargo_cxx.std.iter();
+
+    let cxx_build_filename = PathInFbcode::cxx_build_filename();
+    let rerun_if_changed = std::iter::once(cxx_build_filename.to_owned())
+        .chain(bridges.iter().cloned())
+        .chain(srcs.iter().cloned())
+        .map(|path| format!("cargo:rerun-if-changed={path}"));
+
+    let compiled_name = format!("{library_name}_cxxbridge");
+
+    Ok(hashmap! {
+        cargo_toml_path.as_dir().join_to_path_in_fbcode(cxx_build_filename) => render(quote! {
+            #[rustfmt::skip]
+            fn main() {
+                // Rerun if cxx_build.rs or any of the bridges or C++ files change.
+                #(
+                    println!(#rerun_if_changed);
+                )*
+
+                cxx_build::bridges([#(#bridges),*])
+                    #(
+                        .file(#srcs)
+                    )*
+                    #(
+                        .include(#includes)
+                    )*
+                    #(
+                        .std(#std)
+                    )*
+                    .compile(#compiled_name);
+            }
+        }),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn generate_additional_cxx_files_test() {
+        let targets_path = TargetsPath::new(PathInFbcode::new_mock("foo/TARGETS")).unwrap();
+        let cargo_toml_path =
+            CargoTomlPath::new(PathInFbcode::new_mock("foo/cargo/Cargo.toml")).unwrap();
+        let autocargo_cxx = AutocargoCxx {
+            bridges: vec!["src/ffi.rs".to_owned()],
+            srcs: vec!["src/foo.cpp".to_owned()],
+            includes: vec!["".to_owned()],
+            std: Some("c++17".to_owned()),
+        };
+
+        let files =
+            generate_additional_cxx_files(&targets_path, &cargo_toml_path, "foo", &autocargo_cxx)
+                .unwrap();
+
+        let build = &files[&PathInFbcode::new_mock("foo/cargo/cxx_build.rs")];
+        assert!(build.contains(r#"println!("cargo:rerun-if-changed=../src/ffi.rs");"#));
+        assert!(build.contains(r#"cxx_build::bridges(["../src/ffi.rs"])"#));
+        assert!(build.contains(r#".file("../src/foo.cpp")"#));
+        assert!(build.contains(r#".include("..")"#));
+        assert!(build.contains(r#".std("c++17")"#));
+        assert!(build.contains(r#".compile("foo_cxxbridge");"#));
+
+        assert!(
+            generate_additional_cxx_files(
+                &targets_path,
+                &cargo_toml_path,
+                "foo",
+                &AutocargoCxx {
+                    bridges: Vec::new(),
+                    srcs: Vec::new(),
+                    includes: Vec::new(),
+                    std: None,
+                },
+            )
+            .is_err()
+        );
+    }
+}
@@ -8,25 +8,29 @@
  */
 
 use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::collections::HashSet;
+use std::path::Path;
 
 use anyhow::Context;
 use anyhow::Result;
 use anyhow::anyhow;
-use anyhow::ensure;
+use anyhow::bail;
 use cargo_toml::Dependency;
 use cargo_toml::DependencyDetail;
 use cargo_toml::DepsSet;
 use cargo_toml::FeatureSet;
 use cargo_toml::Target;
-use pathdiff::diff_paths;
+use cargo_toml::Value;
 
 use super::compute_cargo_toml_path;
 use super::consolidated_dependencies::ConsolidatedDependencies;
 use super::consolidated_dependencies::Deps;
+use super::consolidated_dependencies::FbcodeRule;
 use super::consolidated_dependencies::NamedDeps;
 use super::package::generate_dependency_package_name;
 use super::package::generate_dependency_package_version;
+use crate::buck_processing::ArtifactDependencies;
 use crate::buck_processing::BuckDependency;
 use crate::buck_processing::BuckDependencyOverride;
 use crate::buck_processing::BuckTargetDependencies;
@@ -34,20 +38,43 @@ use crate::buck_processing::CargoDependencyOverride;
 use crate::buck_processing::DependenciesOverride;
 use crate::buck_processing::ExtraBuckDependencies;
 use crate::buck_processing::OsDepsPlatform;
+use crate::buck_processing::RawArtifactDependency;
 use crate::buck_processing::RawBuckManifest;
 use crate::buck_processing::TargetDependenciesOverride;
 use crate::cargo_generator::CargoGenerator;
+use crate::cargo_generator::explain::dependency_key;
+use crate::cargo_manifest::DependencyComments;
 use crate::cargo_manifest::KeyedTargetDepsSet;
+use crate::cargo_manifest::TargetKey;
+use crate::config::DualSourcePolicy;
+use crate::config::OssDependencyOverride;
 use crate::config::OssGitConfig;
 use crate::config::ProjectConf;
 use crate::paths::CargoTomlPath;
+use crate::paths::FbcodeRoot;
 use crate::paths::TargetsPath;
+use crate::paths::relative_path;
 
 pub struct Dependencies {
     pub dependencies: DepsSet,
     pub dev_dependencies: DepsSet,
     pub build_dependencies: DepsSet,
     pub target: KeyedTargetDepsSet,
+    /// Descriptions of the dependencies removed via extra_buck_dependencies
+    /// that don't match any dependency generated from Buck, e.g. because of a
+    /// typo in the removal.
+    pub unmatched_removals: Vec<String>,
+    /// Descriptions of the dependencies_override entries that have no effect,
+    /// either because they leave the dependency generated from Buck unchanged
+    /// or because there is no such dependency and they don't give a version,
+    /// path or git to add it with. Only computed for the regular manifest, not
+    /// the OSS one.
+    pub unused_overrides: Vec<String>,
+    /// Comments of the dependencies_override entries.
+    pub comments: DependencyComments,
+    /// Features activating the optional dependencies generated from select()
+    /// branches of the rules.
+    pub select_features: FeatureSet,
 }
 
 /// Struct to hold inputs for dependency generation.
@@ -59,6 +86,11 @@ pub struct DependenciesGenerator<'a> {
     pub extra_buck_dependencies: &'a ExtraBuckDependencies,
     pub dependencies_override: &'a DependenciesOverride,
     pub oss_git_config: Option<&'a OssGitConfig>,
+    pub version_lockstep: &'a BTreeMap<String, String>,
+    pub dependency_versions: &'a BTreeMap<String, String>,
+    pub dedupe_dev_dependencies: bool,
+    pub select_features: &'a BTreeMap<String, String>,
+    pub dual_source_policy: DualSourcePolicy,
 }
 
 impl DependenciesGenerator<'_> {
@@ -76,6 +108,8 @@ impl DependenciesGenerator<'_> {
     ///   section if extra_buck_dependencies includes one
     /// - lastly apply any transformations that the dependencies_override defines
     /// - now do the above for each target dependency set
+    /// - the dependencies of select() branches whose constraint is mapped to a
+    ///   feature are added to [dependencies] as optional and activated by it
     pub fn generate(self) -> Result<Dependencies> {
         let ConsolidatedDependencies {
             deps,
@@ -84,6 +118,7 @@ impl DependenciesGenerator<'_> {
             test_deps,
             test_named_deps,
             test_os_deps,
+            select_deps,
             build_deps,
         } = &self.consolidated_dependencies;
 
@@ -95,6 +130,7 @@ impl DependenciesGenerator<'_> {
                     build_dependencies: extra_build_dependencies,
                 },
             target: extra_target,
+            artifacts,
         } = &self.extra_buck_dependencies;
 
         let DependenciesOverride {
@@ -107,6 +143,56 @@ impl DependenciesGenerator<'_> {
             target: target_override,
         } = &self.dependencies_override;
 
+        let mut unmatched_removals = Vec::new();
+        let mut check_removals =
+            |section: &str,
+             deps: &Deps<'_>,
+             named_deps: &NamedDeps<'_>,
+             extra: &[BuckDependencyOverride]| {
+                unmatched_removals.extend(
+                    unmatched_removals_in(deps, named_deps, extra)
+                        .map(|dep| format!("{} in {section}", describe_dependency(dep))),
+                );
+            };
+        let default_deps = Deps::default();
+        let default_named_deps = NamedDeps::default();
+        check_removals("dependencies", deps, named_deps, extra_dependencies);
+        check_removals(
+            "dev_dependencies",
+            test_deps,
+            test_named_deps,
+            extra_dev_dependencies,
+        );
+        check_removals(
+            "build_dependencies",
+            build_deps,
+            &default_named_deps,
+            extra_build_dependencies,
+        );
+        for (target_name, extra) in extra_target {
+            let os = enum_iterator::all::<OsDepsPlatform>()
+                .find(|os| os.to_cargo_target() == target_name);
+            check_removals(
+                &format!("target.{target_name:?}.dependencies"),
+                os.and_then(|os| os_deps.get(&os)).unwrap_or(&default_deps),
+                &default_named_deps,
+                &extra.dependencies,
+            );
+            check_removals(
+                &format!("target.{target_name:?}.dev_dependencies"),
+                os.and_then(|os| test_os_deps.get(&os))
+                    .unwrap_or(&default_deps),
+                &default_named_deps,
+                &extra.dev_dependencies,
+            );
+            check_removals(
+                &format!("target.{target_name:?}.build_dependencies"),
+                &default_deps,
+                &default_named_deps,
+                &extra.build_dependencies,
+            );
+        }
+
         let optional_deps: HashSet<_> = self
             .features
             .values()
@@ -114,35 +200,78 @@ impl DependenciesGenerator<'_> {
             .map(|s| s.as_str())
             .collect();
 
-        let dependencies = self
+        // Effects of the dependencies_override entries keyed by the section
+        // they are in.
+        let mut override_effects: BTreeMap<String, OverrideEffects> = BTreeMap::new();
+        let mut dependencies = self
             .gen_regular_dependencies(
                 &optional_deps,
                 deps,
                 named_deps,
                 extra_dependencies,
                 dependencies_override,
+                override_effects
+                    .entry("dependencies".to_owned())
+                    .or_default(),
             )
             .context("In dependencies")?;
 
-        let dev_dependencies = self
+        let mut select_features = FeatureSet::new();
+        for (constraint, select_deps) in select_deps {
+            let Some(feature) = self.select_features.get(*constraint) else {
+                continue;
+            };
+            let select_dependencies = self
+                .gen_regular_dependencies(
+                    &HashSet::new(),
+                    select_deps,
+                    &default_named_deps,
+                    &[],
+                    dependencies_override,
+                    override_effects
+                        .entry("dependencies".to_owned())
+                        .or_default(),
+                )
+                .with_context(|| format!("In dependencies selected on {constraint:?}"))?;
+            add_select_dependencies(
+                &mut dependencies,
+                &mut select_features,
+                feature,
+                select_dependencies,
+            )?;
+        }
+
+        let mut dev_dependencies = self
             .gen_dev_dependencies(
                 &dependencies,
                 test_deps,
                 test_named_deps,
                 extra_dev_dependencies,
                 dev_dependencies_override,
+                override_effects
+                    .entry("dev-dependencies".to_owned())
+                    .or_default(),
             )
             .context("In dev_dependencies")?;
+        check_alias_collisions(
+            &dependencies,
+            named_deps,
+            &dev_dependencies,
+            test_named_deps,
+        )?;
 
-        let build_dependencies = self
+        let mut build_dependencies = self
             .gen_build_dependencies(
                 build_deps,
                 extra_build_dependencies,
                 build_dependencies_override,
+                override_effects
+                    .entry("build-dependencies".to_owned())
+                    .or_default(),
             )
             .context("In build_dependencies")?;
 
-        let target = enum_iterator::all::<OsDepsPlatform>()
+        let mut target: KeyedTargetDepsSet = enum_iterator::all::<OsDepsPlatform>()
             .map(|os| {
                 (
                     os_deps.get(&os),
@@ -187,6 +316,9 @@ impl DependenciesGenerator<'_> {
                                 target_override
                                     .get(target_name)
                                     .map_or(&default_overrides, |dep| &dep.dependencies),
+                                override_effects
+                                    .entry(format!("target.{}.dependencies", **target_name))
+                                    .or_default(),
                             )
                             .context("In dependencies")?;
 
@@ -201,6 +333,9 @@ impl DependenciesGenerator<'_> {
                                 target_override
                                     .get(target_name)
                                     .map_or(&default_overrides, |dep| &dep.dev_dependencies),
+                                override_effects
+                                    .entry(format!("target.{}.dev-dependencies", **target_name))
+                                    .or_default(),
                             )
                             .context("In dev_dependencies")?;
 
@@ -213,6 +348,9 @@ impl DependenciesGenerator<'_> {
                                 target_override
                                     .get(target_name)
                                     .map_or(&default_overrides, |dep| &dep.build_dependencies),
+                                override_effects
+                                    .entry(format!("target.{}.build-dependencies", **target_name))
+                                    .or_default(),
                             )
                             .context("In build_dependencies")?;
 
@@ -227,11 +365,61 @@ impl DependenciesGenerator<'_> {
             })
             .collect::<Result<_>>()?;
 
+        let ArtifactDependencies {
+            dependencies: artifact_dependencies,
+            dev_dependencies: artifact_dev_dependencies,
+            build_dependencies: artifact_build_dependencies,
+        } = artifacts;
+        for (deps, artifacts) in [
+            (&mut dependencies, artifact_dependencies),
+            (&mut dev_dependencies, artifact_dev_dependencies),
+            (&mut build_dependencies, artifact_build_dependencies),
+        ] {
+            apply_artifact_dependencies(self.cargo_generator, deps, artifacts)?;
+        }
+
+        let unused_overrides = if self.oss_git_config.is_none() {
+            override_effects
+                .iter()
+                .flat_map(|(section, effects)| {
+                    effects.iter().filter_map(move |(key, effect)| {
+                        effect
+                            .unused_reason()
+                            .map(|reason| format!("{key} in {section} ({reason})"))
+                    })
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        lock_versions(
+            self.version_lockstep,
+            [
+                &mut dependencies,
+                &mut build_dependencies,
+                &mut dev_dependencies,
+            ]
+            .into_iter()
+            .chain(target.values_mut().flat_map(|target| {
+                [
+                    &mut target.dependencies,
+                    &mut target.build_dependencies,
+                    &mut target.dev_dependencies,
+                ]
+            }))
+            .collect(),
+        )?;
+
         Ok(Dependencies {
             dependencies,
             dev_dependencies,
             build_dependencies,
             target,
+            unmatched_removals,
+            unused_overrides,
+            comments: override_comments(self.dependencies_override),
+            select_features,
         })
     }
 
@@ -243,6 +431,7 @@ impl DependenciesGenerator<'_> {
         named_deps: &NamedDeps<'_>,
         extra_buck_dependencies: &[BuckDependencyOverride],
         dependencies_override: &BTreeMap<String, CargoDependencyOverride>,
+        override_effects: &mut OverrideEffects,
     ) -> Result<DepsSet> {
         ComputeDependencies {
             cargo_generator: self.cargo_generator,
@@ -253,15 +442,19 @@ impl DependenciesGenerator<'_> {
             extra_buck_dependencies,
             dependencies_override,
             oss_git_config: self.oss_git_config,
+            version_lockstep: self.version_lockstep,
+            dependency_versions: self.dependency_versions,
+            dual_source_policy: self.dual_source_policy,
         }
-        .compute()
+        .compute(override_effects)
     }
 
     /// Dev dependencies cannot be optional, so no optional_deps here,
     /// but if a dependency is already present in regular dependencies then there
     /// is no need to repeate it for the dev section, so we are passing
     /// regular_dependencies and using deps_difference on it and the generation
-    /// result.
+    /// result, unless the project asked to keep them via
+    /// dedupe_dev_dependencies.
     fn gen_dev_dependencies(
         &self,
         regular_dependencies: &DepsSet,
@@ -269,21 +462,28 @@ impl DependenciesGenerator<'_> {
         named_deps: &NamedDeps<'_>,
         extra_buck_dependencies: &[BuckDependencyOverride],
         dependencies_override: &BTreeMap<String, CargoDependencyOverride>,
+        override_effects: &mut OverrideEffects,
     ) -> Result<DepsSet> {
-        Ok(deps_difference(
-            regular_dependencies,
-            ComputeDependencies {
-                cargo_generator: self.cargo_generator,
-                optional_deps: &HashSet::new(),
-                cargo_toml_path: self.cargo_toml_path,
-                deps,
-                named_deps,
-                extra_buck_dependencies,
-                dependencies_override,
-                oss_git_config: self.oss_git_config,
-            }
-            .compute()?,
-        ))
+        let dev_dependencies = ComputeDependencies {
+            cargo_generator: self.cargo_generator,
+            optional_deps: &HashSet::new(),
+            cargo_toml_path: self.cargo_toml_path,
+            deps,
+            named_deps,
+            extra_buck_dependencies,
+            dependencies_override,
+            oss_git_config: self.oss_git_config,
+            version_lockstep: self.version_lockstep,
+            dependency_versions: self.dependency_versions,
+            dual_source_policy: self.dual_source_policy,
+        }
+        .compute(override_effects)?;
+
+        Ok(if self.dedupe_dev_dependencies {
+            deps_difference(regular_dependencies, dev_dependencies)
+        } else {
+            dev_dependencies
+        })
     }
 
     /// Build dependencies are completely indendent from regular and
@@ -293,6 +493,7 @@ impl DependenciesGenerator<'_> {
         deps: &Deps<'_>,
         extra_buck_dependencies: &[BuckDependencyOverride],
         dependencies_override: &BTreeMap<String, CargoDependencyOverride>,
+        override_effects: &mut OverrideEffects,
     ) -> Result<DepsSet> {
         ComputeDependencies {
             cargo_generator: self.cargo_generator,
@@ -303,8 +504,11 @@ impl DependenciesGenerator<'_> {
             extra_buck_dependencies,
             dependencies_override,
             oss_git_config: self.oss_git_config,
+            version_lockstep: self.version_lockstep,
+            dependency_versions: self.dependency_versions,
+            dual_source_policy: self.dual_source_policy,
         }
-        .compute()
+        .compute(override_effects)
     }
 }
 
@@ -318,11 +522,16 @@ struct ComputeDependencies<'a> {
     extra_buck_dependencies: &'a [BuckDependencyOverride],
     dependencies_override: &'a BTreeMap<String, CargoDependencyOverride>,
     oss_git_config: Option<&'a OssGitConfig>,
+    version_lockstep: &'a BTreeMap<String, String>,
+    dependency_versions: &'a BTreeMap<String, String>,
+    dual_source_policy: DualSourcePolicy,
 }
 
 impl ComputeDependencies<'_> {
-    /// Take all the regular and named deps to produce a dependency set.
-    fn compute(self) -> Result<DepsSet> {
+    /// Take all the regular and named deps to produce a dependency set. The
+    /// effect of each dependencies_override entry is merged into
+    /// override_effects.
+    fn compute(self, override_effects: &mut OverrideEffects) -> Result<DepsSet> {
         let ComputeDependencies {
             cargo_generator,
             optional_deps,
@@ -332,22 +541,37 @@ impl ComputeDependencies<'_> {
             extra_buck_dependencies,
             dependencies_override,
             oss_git_config,
+            version_lockstep,
+            dependency_versions,
+            dual_source_policy,
         } = self;
 
         let mut deps_set = DepsSet::new();
-        let mut add_to_deps = |key: String, value: Dependency| {
-            if let Some(old_value) = deps_set.get(&key) {
-                ensure!(
-                    value.eq(old_value),
-                    "Found duplicate key {} with one value {:?} and other {:?}",
-                    key,
-                    value,
-                    old_value
-                )
-            }
-            deps_set.insert(key, value);
-            Ok(())
-        };
+        let mut sources = HashMap::new();
+        let mut add_to_deps =
+            |source: DependencySource, key: String, value: Dependency| -> Result<()> {
+                let Some(value) = apply_oss_dependency_override(oss_git_config, &key, value)?
+                else {
+                    return Ok(());
+                };
+                let replace = match deps_set.get(&key) {
+                    Some(old_value) => {
+                        !value.eq(old_value)
+                            && resolve_dual_source(
+                                dual_source_policy,
+                                &key,
+                                (sources[&key], old_value),
+                                (source, &value),
+                            )?
+                    }
+                    None => true,
+                };
+                if replace {
+                    sources.insert(key.clone(), source);
+                    deps_set.insert(key, value);
+                }
+                Ok(())
+            };
 
         let removed_third_party: HashSet<_> = extra_buck_dependencies
             .iter()
@@ -367,16 +591,26 @@ impl ComputeDependencies<'_> {
                 _ => None,
             })
             .collect();
+        let removed_cell: HashSet<_> = extra_buck_dependencies
+            .iter()
+            .filter_map(|dep_override| match dep_override {
+                BuckDependencyOverride::RemovedDep(BuckDependency::CellRule(rule)) => {
+                    Some(rule.as_str())
+                }
+                _ => None,
+            })
+            .collect();
 
         for tp_name in &deps.third_party {
             if !removed_third_party.contains(tp_name) {
                 let (name, dep) = get_third_party_dependency(
-                    cargo_generator,
+                    cargo_generator.third_party_crates(),
+                    dependency_versions,
                     optional_deps,
                     Alias(None),
                     tp_name,
                 )?;
-                add_to_deps(name, dep)?;
+                add_to_deps(DependencySource::ThirdParty, name, dep)?;
             }
         }
         for (rule, raw) in &deps.fbcode {
@@ -390,17 +624,31 @@ impl ComputeDependencies<'_> {
                     rule.targets_path(),
                     raw,
                 )? {
-                    add_to_deps(name, dep)?;
+                    add_to_deps(DependencySource::Fbcode, name, dep)?;
                 }
             }
         }
 
+        for rule in deps.cell.difference(&removed_cell) {
+            if let Some((name, dep)) = get_cell_dependency(
+                cargo_generator.cell_dependencies(),
+                optional_deps,
+                Alias(None),
+                cargo_toml_path,
+                rule,
+            )? {
+                add_to_deps(DependencySource::Cell, name, dep)?;
+            }
+        }
+
         for (alias, tp_name) in &named_deps.third_party {
             if !removed_third_party.contains(tp_name) {
                 add_to_deps(
+                    DependencySource::ThirdParty,
                     (*alias).to_owned(),
                     get_third_party_dependency(
-                        cargo_generator,
+                        cargo_generator.third_party_crates(),
+                        dependency_versions,
                         optional_deps,
                         Alias(Some(alias)),
                         tp_name,
@@ -420,21 +668,38 @@ impl ComputeDependencies<'_> {
                     rule.targets_path(),
                     raw,
                 )? {
-                    add_to_deps((*alias).to_owned(), dep)?;
+                    add_to_deps(DependencySource::Fbcode, (*alias).to_owned(), dep)?;
                 }
             }
         }
 
+        for (alias, rule) in named_deps
+            .cell
+            .iter()
+            .filter(|(_, rule)| !removed_cell.contains(rule))
+        {
+            if let Some((_, dep)) = get_cell_dependency(
+                cargo_generator.cell_dependencies(),
+                optional_deps,
+                Alias(Some(alias)),
+                cargo_toml_path,
+                rule,
+            )? {
+                add_to_deps(DependencySource::Cell, (*alias).to_owned(), dep)?;
+            }
+        }
+
         for dep_override in extra_buck_dependencies {
             match dep_override {
                 BuckDependencyOverride::Dep(BuckDependency::ThirdPartyCrate(tp_name)) => {
                     let (name, dep) = get_third_party_dependency(
-                        cargo_generator,
+                        cargo_generator.third_party_crates(),
+                        dependency_versions,
                         optional_deps,
                         Alias(None),
                         tp_name,
                     )?;
-                    add_to_deps(name, dep)?;
+                    add_to_deps(DependencySource::ThirdParty, name, dep)?;
                 }
                 BuckDependencyOverride::Dep(BuckDependency::FbcodeCrate(path, raw)) => {
                     if let Some((name, dep)) = get_fbcode_dependency(
@@ -446,7 +711,7 @@ impl ComputeDependencies<'_> {
                         path,
                         raw,
                     )? {
-                        add_to_deps(name, dep)?;
+                        add_to_deps(DependencySource::Fbcode, name, dep)?;
                     }
                 }
                 BuckDependencyOverride::NamedDep(
@@ -454,9 +719,11 @@ impl ComputeDependencies<'_> {
                     BuckDependency::ThirdPartyCrate(tp_name),
                 ) => {
                     add_to_deps(
+                        DependencySource::ThirdParty,
                         (*alias).to_owned(),
                         get_third_party_dependency(
-                            cargo_generator,
+                            cargo_generator.third_party_crates(),
+                            dependency_versions,
                             optional_deps,
                             Alias(Some(alias)),
                             tp_name,
@@ -474,15 +741,63 @@ impl ComputeDependencies<'_> {
                         path,
                         raw,
                     )? {
-                        add_to_deps((*alias).to_owned(), dep)?;
+                        add_to_deps(DependencySource::Fbcode, (*alias).to_owned(), dep)?;
+                    }
+                }
+                BuckDependencyOverride::Dep(BuckDependency::CellRule(rule)) => {
+                    if let Some((name, dep)) = get_cell_dependency(
+                        cargo_generator.cell_dependencies(),
+                        optional_deps,
+                        Alias(None),
+                        cargo_toml_path,
+                        rule,
+                    )? {
+                        add_to_deps(DependencySource::Cell, name, dep)?;
+                    }
+                }
+                BuckDependencyOverride::NamedDep(alias, BuckDependency::CellRule(rule)) => {
+                    if let Some((_, dep)) = get_cell_dependency(
+                        cargo_generator.cell_dependencies(),
+                        optional_deps,
+                        Alias(Some(alias)),
+                        cargo_toml_path,
+                        rule,
+                    )? {
+                        add_to_deps(DependencySource::Cell, (*alias).to_owned(), dep)?;
                     }
                 }
                 BuckDependencyOverride::RemovedDep(_) => {}
             }
         }
 
+        let apply = |key: &str, dep: Dependency, dep_override: &CargoDependencyOverride| {
+            apply_override(
+                cargo_generator,
+                optional_deps,
+                version_lockstep,
+                dependency_versions,
+                key,
+                dep,
+                dep_override,
+            )
+        };
+        for (key, dep_override) in dependencies_override {
+            let generated = deps_set.get(key);
+            let effect = override_effect(generated, dep_override, |dep_override| {
+                apply(
+                    key,
+                    generated
+                        .cloned()
+                        .unwrap_or_else(|| Dependency::Detailed(Box::default())),
+                    dep_override,
+                )
+            })?;
+            let merged = override_effects.entry(key.clone()).or_insert(effect);
+            *merged = (*merged).max(effect);
+        }
+
         let default_override = CargoDependencyOverride::default();
-        Ok(dependencies_override
+        dependencies_override
             .iter()
             .filter_map(|(key, dep_override)| {
                 if deps_set.contains_key(key) {
@@ -501,14 +816,121 @@ impl ComputeDependencies<'_> {
                 let dep_override = dependencies_override.get(&key).unwrap_or(&default_override);
                 (key, dep, dep_override)
             }))
-            .map(|(key, dep, dep_override)| {
-                (
-                    key.clone(),
-                    apply_override(cargo_generator, optional_deps, &key, dep, dep_override),
-                )
-            })
-            .collect())
+            .map(|(key, dep, dep_override)| Ok((key.clone(), apply(&key, dep, dep_override)?)))
+            .collect()
+    }
+}
+
+/// Kind of Buck dependency that a generated dependency was resolved from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum DependencySource {
+    ThirdParty,
+    Fbcode,
+    Cell,
+}
+
+/// Decide between two different dependencies generated for the same key.
+/// If one of them is a third-party crate and the other a crate generated from
+/// fbcode then the policy picks one of them, any other duplicates are errors.
+/// Returns true if the new dependency should replace the old one.
+fn resolve_dual_source(
+    policy: DualSourcePolicy,
+    key: &str,
+    (old_source, old_value): (DependencySource, &Dependency),
+    (new_source, new_value): (DependencySource, &Dependency),
+) -> Result<bool> {
+    match (old_source, new_source) {
+        (DependencySource::ThirdParty, DependencySource::Fbcode)
+        | (DependencySource::Fbcode, DependencySource::ThirdParty) => match policy {
+            DualSourcePolicy::Error => bail!(
+                "Dependency {} resolves both to a third-party crate and to a crate generated \
+                from fbcode, rename one of them or set dual_source_dependencies of the \
+                project to prefer-fbcode or prefer-third-party",
+                key,
+            ),
+            DualSourcePolicy::PreferFbcode => Ok(new_source == DependencySource::Fbcode),
+            DualSourcePolicy::PreferThirdParty => Ok(new_source == DependencySource::ThirdParty),
+        },
+        _ => bail!(
+            "Found duplicate key {} with one value {:?} and other {:?}",
+            key,
+            new_value,
+            old_value
+        ),
+    }
+}
+
+/// Fail if a dev-dependency has the name of a regular dependency, but points to
+/// a different crate, e.g. because an alias in test_named_deps equals the name
+/// of a regular dependency. Cargo would make only one of them available to the
+/// tests.
+fn check_alias_collisions(
+    dependencies: &DepsSet,
+    named_deps: &NamedDeps<'_>,
+    dev_dependencies: &DepsSet,
+    test_named_deps: &NamedDeps<'_>,
+) -> Result<()> {
+    for (name, dev_dep) in dev_dependencies {
+        let Some(dep) = dependencies.get(name) else {
+            continue;
+        };
+        if dependency_target(name, dep) != dependency_target(name, dev_dep) {
+            bail!(
+                "Dependency {:?} from {} and dev-dependency {:?} from {} point to different \
+                crates, rename one of them",
+                name,
+                if named_deps.contains_alias(name) {
+                    "named_deps"
+                } else {
+                    "deps"
+                },
+                name,
+                if test_named_deps.contains_alias(name) {
+                    "test_named_deps"
+                } else {
+                    "test_deps"
+                },
+            );
+        }
     }
+    Ok(())
+}
+
+/// Package name and source of the dependency, which tell what crate it points
+/// to regardless of its features.
+fn dependency_target(
+    name: &str,
+    dep: &Dependency,
+) -> Option<(String, Option<String>, Option<String>)> {
+    let detail = dependency_to_dependency_detail(name, dep.clone()).ok()?;
+    Some((
+        detail.package.unwrap_or_else(|| name.to_owned()),
+        detail.path,
+        detail.git,
+    ))
+}
+
+/// Collect the comments of dependencies_override entries, keyed like the
+/// dependencies they justify in the generated manifest.
+fn override_comments(dependencies_override: &DependenciesOverride) -> DependencyComments {
+    let DependenciesOverride { deps, target } = dependencies_override;
+    std::iter::once((None, deps))
+        .chain(target.iter().map(|(key, deps)| (Some(key), deps)))
+        .flat_map(|(target_key, deps)| {
+            [
+                ("dependencies", &deps.dependencies),
+                ("dev-dependencies", &deps.dev_dependencies),
+                ("build-dependencies", &deps.build_dependencies),
+            ]
+            .into_iter()
+            .flat_map(move |(section, overrides)| {
+                overrides.iter().filter_map(move |(name, dep_override)| {
+                    let comment = dep_override.comment.clone()?;
+                    Some(((target_key.cloned(), section, name.clone()), comment))
+                })
+            })
+        })
+        .collect()
 }
 
 struct Alias<'a>(Option<&'a str>);
@@ -548,42 +970,70 @@ fn detail_to_dep(
 /// be "foo" and this should be used by Cargo as an alias for dependency, unless
 /// it is overwritten via named_deps.
 fn get_third_party_dependency(
-    cargo_generator: &CargoGenerator<'_>,
+    third_party_crates: &DepsSet,
+    dependency_versions: &BTreeMap<String, String>,
     optional_deps: &HashSet<&str>,
     alias: Alias<'_>,
     tp_name: &str,
 ) -> Result<(String, Dependency)> {
-    cargo_generator
-        .third_party_crates()
-        .get(tp_name)
-        .cloned()
-        .map(|dep| {
-            let package_name = match &dep {
-                Dependency::Inherited(_) => unimplemented!(
-                    "third-party dependency `{tp_name}` uses inherited dependency syntax which is not supported"
-                ),
-                _ => {
-                    dep.package().map_or(tp_name.to_owned(), |p| p.to_owned())
-                }
-            };
+    let dep = third_party_crates.get(tp_name).cloned().ok_or_else(|| {
+        anyhow!(
+            "Missing third-party dependency {}. List of known third-party crates: {:?}",
+            tp_name,
+            third_party_crates.keys().collect::<Vec<_>>(),
+        )
+    })?;
 
-            let dep = {
-                let detail = dependency_to_dependency_detail(tp_name, dep);
-                detail_to_dep(&package_name, detail, optional_deps, alias)
-            };
+    let package_name = dep.package().map_or(tp_name.to_owned(), |p| p.to_owned());
+    let dep = {
+        let mut detail = dependency_to_dependency_detail(tp_name, dep)
+            .context("While resolving a third-party dependency")?;
+        if let Some(version) = dependency_versions.get(&package_name) {
+            detail.version = Some(version.clone());
+        }
+        detail_to_dep(&package_name, detail, optional_deps, alias)
+    };
 
-            (package_name, dep)
-        })
-        .ok_or_else(|| {
-            anyhow!(
-                "Missing third-party dependency {}. List of known third-party crates: {:?}",
-                tp_name,
-                cargo_generator
-                    .third_party_crates()
-                    .keys()
-                    .collect::<Vec<_>>(),
-            )
-        })
+    Ok((package_name, dep))
+}
+
+/// Resolve a dependency on a rule of another cell using the cell_dependencies
+/// of the global config, with its path made relative to the generated
+/// Cargo.toml. Returns None if the rule is not mapped.
+fn get_cell_dependency(
+    cell_dependencies: &DepsSet,
+    optional_deps: &HashSet<&str>,
+    alias: Alias<'_>,
+    from_cargo_toml_path: &CargoTomlPath,
+    rule: &str,
+) -> Result<Option<(String, Dependency)>> {
+    let Some(dep) = cell_dependencies.get(rule).cloned() else {
+        return Ok(None);
+    };
+    let rule_name = rule.rsplit_once(':').map_or(rule, |(_, name)| name);
+    let package_name = dep.package().unwrap_or(rule_name).to_owned();
+    let mut detail = dependency_to_dependency_detail(rule, dep)
+        .with_context(|| format!("While resolving cell dependency {rule}"))?;
+    if let Some(path) = &detail.path {
+        let from_dir =
+            Path::new(FbcodeRoot::dirname()).join(from_cargo_toml_path.as_dir().as_ref());
+        detail.path = Some(
+            relative_path(path, &from_dir)
+                .and_then(|p| p.to_str().map(|s| s.to_owned()))
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Failed to make a relative path from {:?} to {:?} while creating a \
+                        cell dependency",
+                        from_dir,
+                        path,
+                    )
+                })?,
+        );
+    }
+    Ok(Some((
+        package_name.clone(),
+        detail_to_dep(&package_name, detail, optional_deps, alias),
+    )))
 }
 
 #[derive(Clone, Copy)]
@@ -626,24 +1076,51 @@ fn get_fbcode_dependency(
     };
 
     let package_name = generate_dependency_package_name(to_targets_path, to_raw);
+    let to_cargo_toml_path = compute_cargo_toml_path(
+        &to_targets_path
+            .as_dir()
+            .join_to_path_in_fbcode(&to_raw.autocargo.cargo_toml_dir),
+    );
+
+    // Cargo.toml files in manual_dirs are not generated, so the dependency has
+    // to match what they actually declare.
+    let manual_package = match maybe_to_project_conf {
+        Some(project) if project.in_manual_dirs(to_cargo_toml_path.as_dir()) => Some(
+            read_manual_package(cargo_generator.fbcode_root(), &to_cargo_toml_path).with_context(
+                || {
+                    format!(
+                        "While reading manually maintained {} of dependency {}",
+                        to_cargo_toml_path.as_file(),
+                        to_raw.name,
+                    )
+                },
+            )?,
+        ),
+        _ => None,
+    };
 
-    let features = match maybe_to_project_conf {
+    let rule_default_features = || {
+        if let Some(features) = to_raw
+            .autocargo
+            .cargo_toml_config
+            .as_ref()
+            .and_then(|conf| conf.features.as_ref())
+        {
+            features.get("default").cloned().unwrap_or_default()
+        } else {
+            to_raw.rust_config.features.clone()
+        }
+    };
+    let features = match (maybe_to_project_conf, &manual_package) {
         // For autocargo maintained Cargo.toml files the features defined on
         // buck rules should be included as default features. With manually
         // maintained Cargo.toml files it might not be the case, so add the
-        // features to the dependency.
-        Some(project) if *project.manual_cargo_toml() => {
-            if let Some(features) = to_raw
-                .autocargo
-                .cargo_toml_config
-                .as_ref()
-                .and_then(|conf| conf.features.as_ref())
-            {
-                features.get("default").cloned().unwrap_or_default()
-            } else {
-                to_raw.rust_config.features.clone()
-            }
-        }
+        // features to the dependency, as far as they are declared there.
+        (_, Some((_, manual_features))) => rule_default_features()
+            .into_iter()
+            .filter(|feature| manual_features.contains_key(feature))
+            .collect(),
+        (Some(project), None) if *project.manual_cargo_toml() => rule_default_features(),
         _ => Vec::new(),
     };
 
@@ -674,6 +1151,9 @@ fn get_fbcode_dependency(
                     tag,
                     rev,
                     default_features_to_strip: _,
+                    copy_package_files: _,
+                    generate_readme: _,
+                    oss_dependency_overrides: _,
                 } = to_oss_git_config;
                 DependencyDetail {
                     version,
@@ -688,16 +1168,10 @@ fn get_fbcode_dependency(
             _ => {
                 // Either dependency inside the same git repository or not a
                 // oss-generation and all dependencies are path dependencies
-                let to_cargo_toml_path = compute_cargo_toml_path(
-                    &to_targets_path
-                        .as_dir()
-                        .join_to_path_in_fbcode(&to_raw.autocargo.cargo_toml_dir),
-                );
-
                 DependencyDetail {
                     version,
                     path: Some(
-                        diff_paths(
+                        relative_path(
                             to_cargo_toml_path.as_dir().as_ref(),
                             from_cargo_toml_path.as_dir().as_ref(),
                         )
@@ -717,26 +1191,268 @@ fn get_fbcode_dependency(
             }
         };
 
-        detail_to_dep(&package_name, detail, optional_deps, alias)
+        // The package is referred to by the name generated from the rule, as
+        // the code expects, even if the manual Cargo.toml names it differently.
+        let real_name = manual_package
+            .as_ref()
+            .map_or(package_name.as_str(), |(name, _)| name.as_str());
+        detail_to_dep(
+            real_name,
+            detail,
+            optional_deps,
+            Alias(alias.0.or(Some(&package_name))),
+        )
     };
 
     Ok(Some((package_name, dep)))
 }
 
+/// Package name and features declared in a manually maintained Cargo.toml file.
+fn read_manual_package(
+    fbcode_root: &FbcodeRoot,
+    cargo_toml_path: &CargoTomlPath,
+) -> Result<(String, FeatureSet)> {
+    let path = Path::join(fbcode_root.as_ref(), cargo_toml_path.as_file().as_ref());
+    let manifest = cargo_toml::Manifest::from_slice(&std::fs::read(&path)?)?;
+    let package = manifest
+        .package
+        .ok_or_else(|| anyhow!("It has no [package] section"))?;
+    Ok((package.name, manifest.features))
+}
+
+/// Effects of the dependencies_override entries of a dependency section,
+/// keyed by the overridden dependency.
+type OverrideEffects = BTreeMap<String, OverrideEffect>;
+
+/// Effect of a dependencies_override entry. The variants are ordered so that
+/// the effect of an entry applied to several dependency sets, e.g. to the
+/// dependencies of select() branches too, is the greatest of them.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum OverrideEffect {
+    NoSuchDependency,
+    Unchanged,
+    Applied,
+}
+
+impl OverrideEffect {
+    /// Why the override has no effect, None if it has one.
+    fn unused_reason(self) -> Option<&'static str> {
+        match self {
+            Self::NoSuchDependency => Some("no such dependency is generated"),
+            Self::Unchanged => Some("leaves the dependency unchanged"),
+            Self::Applied => None,
+        }
+    }
+}
+
+/// Effect of the override of a dependency. generated is the dependency as
+/// generated without overrides, apply applies an override to it.
+fn override_effect(
+    generated: Option<&Dependency>,
+    dep_override: &CargoDependencyOverride,
+    apply: impl Fn(&CargoDependencyOverride) -> Result<Dependency>,
+) -> Result<OverrideEffect> {
+    Ok(match generated {
+        Some(_) => {
+            if dep_override.comment.is_none()
+                && apply(dep_override)? == apply(&CargoDependencyOverride::default())?
+            {
+                OverrideEffect::Unchanged
+            } else {
+                OverrideEffect::Applied
+            }
+        }
+        None => {
+            if [&dep_override.version, &dep_override.path, &dep_override.git]
+                .iter()
+                .all(|source| !matches!(source, Some(Some(_))))
+            {
+                OverrideEffect::NoSuchDependency
+            } else {
+                OverrideEffect::Applied
+            }
+        }
+    })
+}
+
+/// Dependencies removed in extra_buck_dependencies that are neither among the
+/// deps nor the named_deps generated from Buck.
+fn unmatched_removals_in<'a>(
+    deps: &'a Deps<'_>,
+    named_deps: &'a NamedDeps<'_>,
+    extra_buck_dependencies: &'a [BuckDependencyOverride],
+) -> impl Iterator<Item = &'a BuckDependency> {
+    extra_buck_dependencies
+        .iter()
+        .filter_map(move |dep_override| match dep_override {
+            BuckDependencyOverride::RemovedDep(dep) => {
+                let matched = match dep {
+                    BuckDependency::ThirdPartyCrate(name) => {
+                        deps.third_party.contains(name.as_str())
+                            || named_deps
+                                .third_party
+                                .iter()
+                                .any(|(_, tp_name)| tp_name == name)
+                    }
+                    BuckDependency::FbcodeCrate(path, raw) => {
+                        let matches = |rule: &FbcodeRule<'_>, dep_raw: &RawBuckManifest| {
+                            **rule.targets_path() == **path && dep_raw.name == raw.name
+                        };
+                        deps.fbcode
+                            .iter()
+                            .any(|(rule, dep_raw)| matches(rule, dep_raw))
+                            || named_deps
+                                .fbcode
+                                .iter()
+                                .any(|((_, rule), dep_raw)| matches(rule, dep_raw))
+                    }
+                    BuckDependency::CellRule(rule) => {
+                        deps.cell.contains(rule.as_str())
+                            || named_deps
+                                .cell
+                                .iter()
+                                .any(|(_, cell_rule)| cell_rule == rule)
+                    }
+                };
+                (!matched).then_some(dep)
+            }
+            BuckDependencyOverride::Dep(_) | BuckDependencyOverride::NamedDep(..) => None,
+        })
+}
+
+/// Human readable identifier of the dependency for error messages.
+fn describe_dependency(dep: &BuckDependency) -> String {
+    match dep {
+        BuckDependency::ThirdPartyCrate(name) => format!("third-party crate {name}"),
+        BuckDependency::FbcodeCrate(path, raw) => {
+            format!("//{}:{}", path.as_dir(), raw.name)
+        }
+        BuckDependency::CellRule(rule) => rule.clone(),
+    }
+}
+
+/// Remove from other_dependencies the entries that are the same as any entry
+/// of base_dependencies. Entries are compared by the package they resolve to,
+/// i.e. the `package` rename or else their name, and the rest of their spec,
+/// so e.g. `foo = "1"` is the same as `foo = { version = "1" }` or
+/// `bar = { version = "1", package = "foo" }`.
 fn deps_difference(base_dependencies: &DepsSet, other_dependencies: DepsSet) -> DepsSet {
+    let base: Vec<_> = base_dependencies
+        .iter()
+        .map(|(k, v)| normalize_dependency(k, v))
+        .collect();
     other_dependencies
         .into_iter()
-        .filter(|(k, v)| base_dependencies.get(k) != Some(v))
+        .filter(|(k, v)| !base.contains(&normalize_dependency(k, v)))
         .collect()
 }
 
+/// Representation of the dependency independent of how it is spelled and of
+/// the name it is used under: the package it resolves to together with the
+/// rest of its spec.
+fn normalize_dependency(name: &str, dep: &Dependency) -> (String, Dependency) {
+    match dependency_to_dependency_detail(name, dep.clone()) {
+        Ok(mut detail) => {
+            let package = detail.package.take().unwrap_or_else(|| name.to_owned());
+            detail.features.sort();
+            detail.features.dedup();
+            (package, Dependency::Detailed(Box::new(detail)))
+        }
+        Err(_) => (name.to_owned(), dep.clone()),
+    }
+}
+
+/// Change the dependency according to the oss_dependency_overrides when
+/// generating an oss-ready Cargo.toml file, returns None if it is removed.
+fn apply_oss_dependency_override(
+    oss_git_config: Option<&OssGitConfig>,
+    key: &str,
+    dep: Dependency,
+) -> Result<Option<Dependency>> {
+    let Some(dep_override) =
+        oss_git_config.and_then(|oss_git_config| oss_git_config.oss_dependency_override(key))
+    else {
+        return Ok(Some(dep));
+    };
+    let detail = dependency_to_dependency_detail(key, dep)?;
+    let detail = match dep_override {
+        OssDependencyOverride::Remove { .. } => return Ok(None),
+        OssDependencyOverride::ReplaceWithGit {
+            pattern: _,
+            git,
+            branch,
+            tag,
+            rev,
+        } => DependencyDetail {
+            path: None,
+            registry: None,
+            registry_index: None,
+            git: Some(git.clone()),
+            branch: branch.clone(),
+            tag: tag.clone(),
+            rev: rev.clone(),
+            ..detail
+        },
+        OssDependencyOverride::ReplaceWithVersion {
+            pattern: _,
+            version,
+        } => DependencyDetail {
+            version: Some(version.clone()),
+            path: None,
+            git: None,
+            branch: None,
+            tag: None,
+            rev: None,
+            ..detail
+        },
+    };
+    Ok(Some(dependency_detail_to_dependency(detail)))
+}
+
+/// Set the version of each follower of version_lockstep to the version of its
+/// leader if both are dependencies of the crate, so that they can't drift apart
+/// even if the leader's version was changed via dependencies_override. The
+/// version of the leader is taken from the first of the deps sets that has it.
+fn lock_versions(
+    version_lockstep: &BTreeMap<String, String>,
+    mut deps_sets: Vec<&mut DepsSet>,
+) -> Result<()> {
+    for (follower, leader) in version_lockstep {
+        let Some(leader_version) = deps_sets
+            .iter()
+            .filter_map(|deps| deps.get(leader))
+            .find_map(|dep| {
+                dependency_to_dependency_detail(leader, dep.clone())
+                    .ok()
+                    .and_then(|detail| detail.version)
+            })
+        else {
+            continue;
+        };
+        for deps in deps_sets.iter_mut() {
+            let Some(dep) = deps.get_mut(follower) else {
+                continue;
+            };
+            let mut detail = dependency_to_dependency_detail(follower, dep.clone())?;
+            if detail.version.is_none() || detail.version.as_ref() == Some(&leader_version) {
+                continue;
+            }
+            detail.version = Some(leader_version.clone());
+            *dep = dependency_detail_to_dependency(detail);
+        }
+    }
+    Ok(())
+}
+
 fn apply_override(
     cargo_generator: &CargoGenerator<'_>,
     optional_deps: &HashSet<&str>,
+    version_lockstep: &BTreeMap<String, String>,
+    dependency_versions: &BTreeMap<String, String>,
     key: &str,
     dep: Dependency,
     dep_override: &CargoDependencyOverride,
-) -> Dependency {
+) -> Result<Dependency> {
     let CargoDependencyOverride {
         version: version_override,
         registry: registry_override,
@@ -750,6 +1466,7 @@ fn apply_override(
         optional: optional_override,
         default_features: default_features_override,
         package: package_override,
+        comment: _,
     } = dep_override;
 
     let DependencyDetail {
@@ -767,16 +1484,25 @@ fn apply_override(
         default_features,
         package,
         unstable: _,
-    } = dependency_to_dependency_detail(key, dep);
-    let fixed_up_version = if key == "cxx-build" {
-        match get_third_party_dependency(cargo_generator, optional_deps, Alias(None), "cxx") {
-            Ok((_, cxx_dep)) => dependency_to_dependency_detail("cxx", cxx_dep).version,
-            Err(_) => version_override.clone().unwrap_or(version),
+    } = dependency_to_dependency_detail(key, dep)?;
+    let fixed_up_version = match version_lockstep.get(key).map(|leader| {
+        (
+            leader,
+            get_third_party_dependency(
+                cargo_generator.third_party_crates(),
+                dependency_versions,
+                optional_deps,
+                Alias(None),
+                leader,
+            ),
+        )
+    }) {
+        Some((leader, Ok((_, leader_dep)))) => {
+            dependency_to_dependency_detail(leader, leader_dep)?.version
         }
-    } else {
-        version_override.clone().unwrap_or(version)
+        _ => version_override.clone().unwrap_or(version),
     };
-    dependency_detail_to_dependency(DependencyDetail {
+    Ok(dependency_detail_to_dependency(DependencyDetail {
         version: fixed_up_version,
         registry: registry_override.clone().unwrap_or(registry),
         registry_index: registry_index_override.clone().unwrap_or(registry_index),
@@ -791,19 +1517,149 @@ fn apply_override(
         default_features: (*default_features_override).unwrap_or(default_features),
         package: package_override.clone().unwrap_or(package),
         unstable: BTreeMap::new(),
-    })
+    }))
+}
+
+/// Activate features of dependencies ("dep/feature" or "dep?/feature") only
+/// for the given target by adding the dependency, with just those features, to
+/// the target's dependencies. Cargo unifies features of the same dependency
+/// declared in multiple sections, so this enables them only on that platform.
+/// Features of dev-dependencies, which test rules may refer to, are activated
+/// in the target's dev-dependencies instead.
+pub fn add_target_feature_activations(
+    dependencies: &DepsSet,
+    dev_dependencies: &DepsSet,
+    target: &mut KeyedTargetDepsSet,
+    target_key: &TargetKey,
+    features: impl IntoIterator<Item = String>,
+) -> Result<()> {
+    let target = target.entry(target_key.clone()).or_insert_with(|| Target {
+        dependencies: DepsSet::new(),
+        dev_dependencies: DepsSet::new(),
+        build_dependencies: DepsSet::new(),
+    });
+
+    for feature in features {
+        let (dep_name, dep_feature) = feature
+            .split_once('/')
+            .ok_or_else(|| anyhow!("Feature {feature:?} is not a dependency feature"))?;
+        let dep_name = dep_name.trim_end_matches('?');
+
+        let (target_deps, crate_deps) =
+            if target.dependencies.contains_key(dep_name) || dependencies.contains_key(dep_name) {
+                (&mut target.dependencies, dependencies)
+            } else {
+                (&mut target.dev_dependencies, dev_dependencies)
+            };
+        let mut detail = match target_deps.get(dep_name) {
+            Some(dep) => dependency_to_dependency_detail(dep_name, dep.clone())?,
+            None => {
+                let dep = crate_deps.get(dep_name).ok_or_else(|| {
+                    anyhow!(
+                        "Feature {:?} enabled for {} refers to {:?} which is neither a \
+                        dependency nor a dev-dependency of this crate",
+                        feature,
+                        target_key.get(),
+                        dep_name,
+                    )
+                })?;
+                DependencyDetail {
+                    features: Vec::new(),
+                    ..dependency_to_dependency_detail(dep_name, dep.clone())?
+                }
+            }
+        };
+        if !detail.features.iter().any(|f| f == dep_feature) {
+            detail.features.push(dep_feature.to_owned());
+        }
+        target_deps.insert(dep_name.to_owned(), dependency_detail_to_dependency(detail));
+    }
+    Ok(())
+}
+
+/// Add the dependencies generated from a select() branch to dependencies as
+/// optional ones activated by the feature. Dependencies that are already
+/// unconditional are left as they are. The feature refers to them via "dep:",
+/// since it is often named after the dependency it enables.
+fn add_select_dependencies(
+    dependencies: &mut DepsSet,
+    select_features: &mut FeatureSet,
+    feature: &str,
+    select_dependencies: DepsSet,
+) -> Result<()> {
+    let activated = select_features.entry(feature.to_owned()).or_default();
+    for (name, dep) in select_dependencies {
+        match dependencies.get(&name) {
+            Some(existing) if !existing.optional() => continue,
+            Some(_) => {}
+            None => {
+                let detail = DependencyDetail {
+                    optional: true,
+                    ..dependency_to_dependency_detail(&name, dep)?
+                };
+                dependencies.insert(name.clone(), dependency_detail_to_dependency(detail));
+            }
+        }
+        let value = format!("dep:{name}");
+        if !activated.contains(&value) {
+            activated.push(value);
+        }
+    }
+    Ok(())
+}
+
+/// Turn the dependencies generated for the artifact dependencies into ones on
+/// their artifacts. Dependencies that are not generated, e.g. fbcode ones
+/// without oss config in oss manifests or ones deduplicated from
+/// dev-dependencies, are skipped.
+fn apply_artifact_dependencies(
+    cargo_generator: &CargoGenerator<'_>,
+    deps: &mut DepsSet,
+    artifacts: &[(BuckDependency, RawArtifactDependency)],
+) -> Result<()> {
+    for (buck_dep, artifact) in artifacts {
+        let key = dependency_key(cargo_generator, buck_dep);
+        let Some(dep) = deps.remove(&key) else {
+            continue;
+        };
+        let mut detail = dependency_to_dependency_detail(&key, dep)?;
+        let kinds = match artifact.artifact.as_slice() {
+            [] => Value::String("bin".to_owned()),
+            [kind] => Value::String(kind.clone()),
+            kinds => Value::Array(kinds.iter().cloned().map(Value::String).collect()),
+        };
+        detail.unstable.insert("artifact".to_owned(), kinds);
+        if let Some(target) = &artifact.target {
+            detail
+                .unstable
+                .insert("target".to_owned(), Value::String(target.clone()));
+        }
+        if artifact.lib {
+            detail
+                .unstable
+                .insert("lib".to_owned(), Value::Boolean(true));
+        }
+        deps.insert(key, dependency_detail_to_dependency(detail));
+    }
+    Ok(())
 }
 
-fn dependency_to_dependency_detail(name: &str, dep: Dependency) -> DependencyDetail {
+/// Inherited dependencies only make sense inside of a workspace, they are
+/// generated by autocargo at the workspace level, see
+/// [crate::config::WorkspaceConfig::inherit_dependencies], so they are not
+/// expected as input to the dependency computation.
+fn dependency_to_dependency_detail(name: &str, dep: Dependency) -> Result<DependencyDetail> {
     match dep {
-        Dependency::Simple(version) => DependencyDetail {
+        Dependency::Simple(version) => Ok(DependencyDetail {
             version: Some(version),
             ..DependencyDetail::default()
-        },
-        Dependency::Detailed(detail) => *detail,
-        Dependency::Inherited(_) => unimplemented!(
-            "dependency `{name}` uses inherited dependency syntax which is not supported"
-        ),
+        }),
+        Dependency::Detailed(detail) => Ok(*detail),
+        Dependency::Inherited(_) => Err(anyhow!(
+            "Dependency `{}` uses inherited dependency syntax, which cannot be resolved \
+            outside of its workspace",
+            name
+        )),
     }
 }
 
@@ -828,3 +1684,584 @@ fn dependency_detail_to_dependency(detail: DependencyDetail) -> Dependency {
         detail => Dependency::Detailed(Box::new(detail)),
     }
 }
+
+#[cfg(test)]
+mod test {
+    use maplit::btreemap;
+
+    use super::*;
+    use crate::paths::PathInFbcode;
+
+    fn s(s: &str) -> String {
+        s.to_owned()
+    }
+
+    #[test]
+    fn check_alias_collisions_test() {
+        let path_dep = |path: &str, package: Option<&str>| {
+            Dependency::Detailed(Box::new(DependencyDetail {
+                path: Some(s(path)),
+                package: package.map(s),
+                ..DependencyDetail::default()
+            }))
+        };
+        let dependencies = btreemap! {
+            s("foo") => path_dep("../foo", None),
+            s("bar") => Dependency::Simple(s("1")),
+        };
+        let test_named_deps = NamedDeps {
+            third_party: [("foo", "foo_mock")].into(),
+            ..NamedDeps::default()
+        };
+
+        // Same crate with other features or under other names is fine.
+        check_alias_collisions(
+            &dependencies,
+            &NamedDeps::default(),
+            &btreemap! {
+                s("foo") => path_dep("../foo", Some("foo")),
+                s("bar") => Dependency::Detailed(Box::new(DependencyDetail {
+                    version: Some(s("1")),
+                    features: vec![s("test")],
+                    ..DependencyDetail::default()
+                })),
+                s("foo_mock") => Dependency::Simple(s("1")),
+            },
+            &test_named_deps,
+        )
+        .unwrap();
+
+        assert_eq!(
+            check_alias_collisions(
+                &dependencies,
+                &NamedDeps::default(),
+                &btreemap! { s("foo") => path_dep("../foo_mock", Some("foo_mock")) },
+                &test_named_deps,
+            )
+            .unwrap_err()
+            .to_string(),
+            "Dependency \"foo\" from deps and dev-dependency \"foo\" from test_named_deps \
+            point to different crates, rename one of them",
+        );
+    }
+
+    #[test]
+    fn get_third_party_dependency_test() {
+        let bar = Dependency::Detailed(Box::new(DependencyDetail {
+            version: Some(s("2")),
+            features: vec![s("std")],
+            ..DependencyDetail::default()
+        }));
+        let third_party_crates = btreemap! {
+            s("foo") => Dependency::Simple(s("1.0")),
+            s("foo-0") => Dependency::Detailed(Box::new(DependencyDetail {
+                version: Some(s("0.9")),
+                package: Some(s("foo")),
+                ..DependencyDetail::default()
+            })),
+            s("bar") => bar.clone(),
+        };
+        let dependency_versions = btreemap! { s("foo") => s("=1.0.5") };
+        let get = |alias, tp_name| {
+            get_third_party_dependency(
+                &third_party_crates,
+                &dependency_versions,
+                &HashSet::new(),
+                Alias(alias),
+                tp_name,
+            )
+        };
+
+        // The version from defaults.dependency_versions wins over the one of
+        // the third-party crate, also for its other versions.
+        assert_eq!(
+            get(None, "foo").unwrap(),
+            (s("foo"), Dependency::Simple(s("=1.0.5"))),
+        );
+        assert_eq!(
+            get(None, "foo-0").unwrap(),
+            (s("foo"), Dependency::Simple(s("=1.0.5"))),
+        );
+        assert_eq!(
+            get(Some("foo_renamed"), "foo").unwrap(),
+            (
+                s("foo"),
+                Dependency::Detailed(Box::new(DependencyDetail {
+                    version: Some(s("=1.0.5")),
+                    package: Some(s("foo")),
+                    ..DependencyDetail::default()
+                })),
+            ),
+        );
+        // Crates without a default keep the version of the third-party crate.
+        assert_eq!(get(None, "bar").unwrap(), (s("bar"), bar));
+        assert!(get(None, "missing").is_err());
+    }
+
+    #[test]
+    fn get_cell_dependency_test() {
+        let cell_dependencies = btreemap! {
+            s("xplat//rust/foo:foo") => Dependency::Detailed(Box::new(DependencyDetail {
+                path: Some(s("xplat/rust/foo")),
+                ..DependencyDetail::default()
+            })),
+            s("xplat//rust/bar:bar-lib") => Dependency::Detailed(Box::new(DependencyDetail {
+                git: Some(s("https://github.com/org/bar")),
+                package: Some(s("bar")),
+                ..DependencyDetail::default()
+            })),
+        };
+        let cargo_toml_path = CargoTomlPath::new(PathInFbcode::new_mock("a/b/Cargo.toml")).unwrap();
+        let test = |alias, rule| {
+            get_cell_dependency(
+                &cell_dependencies,
+                &HashSet::new(),
+                Alias(alias),
+                &cargo_toml_path,
+                rule,
+            )
+            .unwrap()
+        };
+
+        assert_eq!(
+            test(None, "xplat//rust/foo:foo"),
+            Some((
+                s("foo"),
+                Dependency::Detailed(Box::new(DependencyDetail {
+                    path: Some(s("../../../xplat/rust/foo")),
+                    ..DependencyDetail::default()
+                }))
+            ))
+        );
+        assert_eq!(
+            test(Some("bar_alias"), "xplat//rust/bar:bar-lib"),
+            Some((
+                s("bar"),
+                Dependency::Detailed(Box::new(DependencyDetail {
+                    git: Some(s("https://github.com/org/bar")),
+                    package: Some(s("bar")),
+                    ..DependencyDetail::default()
+                }))
+            ))
+        );
+        assert_eq!(test(None, "xplat//rust/baz:baz"), None);
+    }
+
+    #[test]
+    fn unmatched_removals_in_test() {
+        let deps = Deps {
+            third_party: ["anyhow"].into(),
+            ..Deps::default()
+        };
+        let named_deps = NamedDeps {
+            third_party: [("serde_alias", "serde")].into(),
+            ..NamedDeps::default()
+        };
+        let third_party = |name: &str| BuckDependency::ThirdPartyCrate(s(name));
+        let extra = [
+            BuckDependencyOverride::RemovedDep(third_party("anyhow")),
+            BuckDependencyOverride::RemovedDep(third_party("serde")),
+            BuckDependencyOverride::RemovedDep(third_party("anyhwo")),
+            BuckDependencyOverride::Dep(third_party("tokio")),
+        ];
+
+        assert_eq!(
+            unmatched_removals_in(&deps, &named_deps, &extra)
+                .map(describe_dependency)
+                .collect::<Vec<_>>(),
+            vec![s("third-party crate anyhwo")]
+        );
+    }
+
+    #[test]
+    fn override_effect_test() {
+        let generated = Dependency::Detailed(Box::new(DependencyDetail {
+            version: Some(s("1")),
+            features: vec![s("derive")],
+            ..DependencyDetail::default()
+        }));
+        // Stands in for apply_override, which only handles features here.
+        let apply = |dep_override: &CargoDependencyOverride| -> Result<Dependency> {
+            let mut detail = dependency_to_dependency_detail("serde", generated.clone())?;
+            if let Some(features) = &dep_override.features {
+                detail.features = features.clone();
+            }
+            Ok(Dependency::Detailed(Box::new(detail)))
+        };
+        let features = |features: &[&str]| CargoDependencyOverride {
+            features: Some(features.iter().map(|feature| s(feature)).collect()),
+            ..CargoDependencyOverride::default()
+        };
+
+        assert_eq!(
+            override_effect(Some(&generated), &features(&["derive"]), apply).unwrap(),
+            OverrideEffect::Unchanged
+        );
+        assert_eq!(
+            override_effect(Some(&generated), &features(&["rc"]), apply).unwrap(),
+            OverrideEffect::Applied
+        );
+        let comment = CargoDependencyOverride {
+            comment: Some(s("Pinned until the next release")),
+            ..CargoDependencyOverride::default()
+        };
+        assert_eq!(
+            override_effect(Some(&generated), &comment, apply).unwrap(),
+            OverrideEffect::Applied
+        );
+        assert_eq!(
+            override_effect(None, &comment, apply).unwrap(),
+            OverrideEffect::NoSuchDependency
+        );
+        let version = CargoDependencyOverride {
+            version: Some(Some(s("1"))),
+            ..CargoDependencyOverride::default()
+        };
+        assert_eq!(
+            override_effect(None, &version, apply).unwrap(),
+            OverrideEffect::Applied
+        );
+        assert_eq!(
+            OverrideEffect::NoSuchDependency.max(OverrideEffect::Unchanged),
+            OverrideEffect::Unchanged
+        );
+    }
+
+    #[test]
+    fn resolve_dual_source_test() {
+        let third_party = Dependency::Simple(s("1"));
+        let fbcode = Dependency::Detailed(Box::new(DependencyDetail {
+            path: Some(s("../foo")),
+            ..DependencyDetail::default()
+        }));
+        let resolve = |policy, old, new| {
+            resolve_dual_source(policy, "foo", (old, &third_party), (new, &fbcode))
+        };
+
+        assert!(
+            resolve(
+                DualSourcePolicy::Error,
+                DependencySource::ThirdParty,
+                DependencySource::Fbcode
+            )
+            .is_err()
+        );
+        assert!(
+            resolve(
+                DualSourcePolicy::PreferFbcode,
+                DependencySource::ThirdParty,
+                DependencySource::Fbcode
+            )
+            .unwrap()
+        );
+        assert!(
+            !resolve(
+                DualSourcePolicy::PreferFbcode,
+                DependencySource::Fbcode,
+                DependencySource::ThirdParty
+            )
+            .unwrap()
+        );
+        assert!(
+            resolve(
+                DualSourcePolicy::PreferThirdParty,
+                DependencySource::Fbcode,
+                DependencySource::ThirdParty
+            )
+            .unwrap()
+        );
+        assert!(
+            resolve(
+                DualSourcePolicy::PreferThirdParty,
+                DependencySource::Fbcode,
+                DependencySource::Cell
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn deps_difference_test() {
+        let detailed = |version: &str, package: Option<&str>, features: &[&str]| {
+            Dependency::Detailed(Box::new(DependencyDetail {
+                version: Some(s(version)),
+                package: package.map(s),
+                features: features.iter().copied().map(s).collect(),
+                ..DependencyDetail::default()
+            }))
+        };
+        let base = btreemap! {
+            s("foo") => Dependency::Simple(s("1")),
+            s("bar") => detailed("2", Some("bar"), &["b", "a"]),
+            s("biz") => detailed("3", Some("biz_rs"), &[]),
+        };
+
+        assert_eq!(
+            deps_difference(
+                &base,
+                btreemap! {
+                    s("foo") => detailed("1", Some("foo"), &[]),
+                    s("bar") => detailed("2", None, &["a", "b"]),
+                    s("biz") => detailed("3", None, &[]),
+                    s("biz_rs") => detailed("3", None, &[]),
+                    s("fiz") => Dependency::Simple(s("4")),
+                }
+            ),
+            btreemap! {
+                s("biz") => detailed("3", None, &[]),
+                s("fiz") => Dependency::Simple(s("4")),
+            }
+        );
+
+        // Renamed entries are compared by the package they resolve to.
+        assert_eq!(
+            deps_difference(
+                &base,
+                btreemap! {
+                    s("foo_renamed") => detailed("1", Some("foo"), &[]),
+                    s("bar_renamed") => detailed("2", Some("bar"), &["a"]),
+                    s("biz_renamed") => detailed("3", Some("biz_rs"), &[]),
+                    s("fiz_renamed") => detailed("3", Some("fiz"), &[]),
+                }
+            ),
+            btreemap! {
+                s("bar_renamed") => detailed("2", Some("bar"), &["a"]),
+                s("fiz_renamed") => detailed("3", Some("fiz"), &[]),
+            }
+        );
+    }
+
+    #[test]
+    fn lock_versions_test() {
+        let lockstep = btreemap! {
+            s("cxx-build") => s("cxx"),
+            s("serde_derive") => s("serde"),
+            s("thiserror-impl") => s("thiserror"),
+        };
+        let mut dependencies = btreemap! {
+            s("cxx") => Dependency::Simple(s("1.0.100")),
+            s("serde_derive") => Dependency::Detailed(Box::new(DependencyDetail {
+                version: Some(s("1.0.180")),
+                features: vec![s("deserialize_in_place")],
+                ..DependencyDetail::default()
+            })),
+            s("thiserror-impl") => Dependency::Simple(s("2")),
+        };
+        let mut dev_dependencies = btreemap! {
+            s("serde") => Dependency::Simple(s("1.0.185")),
+        };
+        let mut build_dependencies = btreemap! {
+            s("cxx-build") => Dependency::Simple(s("1.0.90")),
+        };
+
+        lock_versions(
+            &lockstep,
+            vec![
+                &mut dependencies,
+                &mut build_dependencies,
+                &mut dev_dependencies,
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            build_dependencies["cxx-build"],
+            Dependency::Simple(s("1.0.100"))
+        );
+        assert_eq!(
+            dependencies["serde_derive"],
+            Dependency::Detailed(Box::new(DependencyDetail {
+                version: Some(s("1.0.185")),
+                features: vec![s("deserialize_in_place")],
+                ..DependencyDetail::default()
+            }))
+        );
+        // Without the leader the follower is left alone.
+        assert_eq!(dependencies["thiserror-impl"], Dependency::Simple(s("2")));
+    }
+
+    #[test]
+    fn apply_oss_dependency_override_test() {
+        let oss_git_config: OssGitConfig = serde_json::from_value(serde_json::json!({
+            "public_cargo_dir": "public_autocargo",
+            "git": "https://github.com/facebook/foo.git",
+            "oss_dependency_overrides": [
+                { "action": "remove", "pattern": "fbinit*" },
+                {
+                    "action": "replace-with-git",
+                    "pattern": "fb303_core",
+                    "git": "https://github.com/facebook/fb303.git",
+                    "branch": "main",
+                },
+                { "action": "replace-with-version", "pattern": "shim*", "version": "0.3" },
+                { "action": "remove", "pattern": "shim_internal" },
+            ],
+        }))
+        .unwrap();
+        let path_dep = |features: Vec<String>| {
+            Dependency::Detailed(Box::new(DependencyDetail {
+                path: Some(s("../fb303")),
+                version: Some(s("0.1.0")),
+                features,
+                ..DependencyDetail::default()
+            }))
+        };
+
+        assert_eq!(
+            apply_oss_dependency_override(Some(&oss_git_config), "fbinit-tokio", path_dep(vec![]))
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            apply_oss_dependency_override(
+                Some(&oss_git_config),
+                "fb303_core",
+                path_dep(vec![s("foo")])
+            )
+            .unwrap(),
+            Some(Dependency::Detailed(Box::new(DependencyDetail {
+                version: Some(s("0.1.0")),
+                git: Some(s("https://github.com/facebook/fb303.git")),
+                branch: Some(s("main")),
+                features: vec![s("foo")],
+                ..DependencyDetail::default()
+            })))
+        );
+        // The first matching override wins.
+        assert_eq!(
+            apply_oss_dependency_override(Some(&oss_git_config), "shim_internal", path_dep(vec![]))
+                .unwrap(),
+            Some(Dependency::Simple(s("0.3")))
+        );
+        assert_eq!(
+            apply_oss_dependency_override(Some(&oss_git_config), "anyhow", path_dep(vec![]))
+                .unwrap(),
+            Some(path_dep(vec![]))
+        );
+        // Outside of the oss generation nothing is changed.
+        assert_eq!(
+            apply_oss_dependency_override(None, "fbinit", path_dep(vec![])).unwrap(),
+            Some(path_dep(vec![]))
+        );
+    }
+
+    #[test]
+    fn add_select_dependencies_test() {
+        let optional = |version: &str| {
+            Dependency::Detailed(Box::new(DependencyDetail {
+                version: Some(s(version)),
+                optional: true,
+                ..DependencyDetail::default()
+            }))
+        };
+        let mut dependencies = btreemap! {
+            s("foo") => Dependency::Simple(s("1")),
+        };
+        let mut select_features = FeatureSet::new();
+
+        add_select_dependencies(
+            &mut dependencies,
+            &mut select_features,
+            "tls",
+            btreemap! {
+                s("foo") => Dependency::Simple(s("1")),
+                s("openssl") => Dependency::Simple(s("0.10")),
+            },
+        )
+        .unwrap();
+        add_select_dependencies(
+            &mut dependencies,
+            &mut select_features,
+            "vendored",
+            btreemap! {
+                s("openssl") => Dependency::Simple(s("0.10")),
+            },
+        )
+        .unwrap();
+
+        assert_eq!(
+            dependencies,
+            btreemap! {
+                s("foo") => Dependency::Simple(s("1")),
+                s("openssl") => optional("0.10"),
+            }
+        );
+        assert_eq!(
+            select_features,
+            btreemap! {
+                s("tls") => vec![s("dep:openssl")],
+                s("vendored") => vec![s("dep:openssl")],
+            }
+        );
+    }
+
+    #[test]
+    fn add_target_feature_activations_test() {
+        let linux = OsDepsPlatform::Linux.to_cargo_target();
+        let dependencies = btreemap! {
+            s("foo") => Dependency::Simple(s("1.0")),
+            s("bar") => Dependency::Detailed(Box::new(DependencyDetail {
+                version: Some(s("2.0")),
+                features: vec![s("std")],
+                default_features: false,
+                ..DependencyDetail::default()
+            })),
+        };
+        let dev_dependencies = btreemap! {
+            s("foo") => Dependency::Simple(s("0.1")),
+            s("baz") => Dependency::Simple(s("3.0")),
+        };
+        let mut target = KeyedTargetDepsSet::new();
+
+        add_target_feature_activations(
+            &dependencies,
+            &dev_dependencies,
+            &mut target,
+            linux,
+            vec![
+                s("foo/epoll"),
+                s("bar?/os"),
+                s("foo/io_uring"),
+                s("baz/mock"),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            target[linux].dependencies,
+            btreemap! {
+                s("foo") => Dependency::Detailed(Box::new(DependencyDetail {
+                    version: Some(s("1.0")),
+                    features: vec![s("epoll"), s("io_uring")],
+                    ..DependencyDetail::default()
+                })),
+                s("bar") => Dependency::Detailed(Box::new(DependencyDetail {
+                    version: Some(s("2.0")),
+                    features: vec![s("os")],
+                    default_features: false,
+                    ..DependencyDetail::default()
+                })),
+            }
+        );
+        assert_eq!(
+            target[linux].dev_dependencies,
+            btreemap! {
+                s("baz") => Dependency::Detailed(Box::new(DependencyDetail {
+                    version: Some(s("3.0")),
+                    features: vec![s("mock")],
+                    ..DependencyDetail::default()
+                })),
+            }
+        );
+
+        assert!(
+            add_target_feature_activations(
+                &dependencies,
+                &dev_dependencies,
+                &mut target,
+                linux,
+                vec![s("biz/os")],
+            )
+            .is_err()
+        );
+    }
+}
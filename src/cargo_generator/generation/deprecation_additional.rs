@@ -0,0 +1,155 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use anyhow::bail;
+use cargo_toml::Value;
+use itertools::Itertools;
+use maplit::hashmap;
+use quote::quote;
+use toml::map::Map;
+
+use super::thrift_additional::render;
+use crate::cargo_manifest::Package;
+use crate::config::CrateDeprecation;
+use crate::paths::CargoTomlPath;
+use crate::paths::PathInFbcode;
+
+/// Put the deprecation notice in [package.metadata.deprecation].
+pub fn add_deprecation_metadata(
+    package: &mut Package,
+    deprecation: &CrateDeprecation,
+) -> Result<()> {
+    let CrateDeprecation {
+        message,
+        replacement,
+        removal_date,
+        build_warning: _,
+    } = deprecation;
+
+    let mut table = Map::new();
+    table.insert("message".to_owned(), Value::String(message.clone()));
+    if let Some(replacement) = replacement {
+        table.insert("replacement".to_owned(), Value::String(replacement.clone()));
+    }
+    if let Some(removal_date) = removal_date {
+        table.insert(
+            "removal-date".to_owned(),
+            Value::String(removal_date.clone()),
+        );
+    }
+
+    match package
+        .metadata
+        .get_or_insert_with(|| Value::Table(Map::new()))
+    {
+        Value::Table(metadata) => {
+            if metadata
+                .insert("deprecation".to_owned(), Value::Table(table))
+                .is_some()
+            {
+                bail!(
+                    "Package {} already has package.metadata.deprecation",
+                    package.name
+                );
+            }
+        }
+        _ => bail!(
+            "Package {} has package.metadata that is not a table",
+            package.name
+        ),
+    }
+    Ok(())
+}
+
+pub fn generate_additional_deprecation_files(
+    cargo_toml_path: &CargoTomlPath,
+    package_name: &str,
+    deprecation: &CrateDeprecation,
+) -> HashMap<PathInFbcode, String> {
+    let deprecation_build_filename = PathInFbcode::deprecation_build_filename();
+    let rerun_if_changed = format!("cargo:rerun-if-changed={deprecation_build_filename}");
+
+    let removal = deprecation
+        .removal_date
+        .as_ref()
+        .map(|date| format!(" and will be removed after {date}"))
+        .unwrap_or_default();
+    let replacement = deprecation
+        .replacement
+        .as_ref()
+        .map(|replacement| format!(", use {replacement} instead"))
+        .unwrap_or_default();
+    // Cargo reads the warnings line by line.
+    let message = deprecation.message.split_whitespace().join(" ");
+    let warning = format!(
+        "cargo:warning=Crate {package_name} is deprecated{removal}: {message}{replacement}"
+    );
+
+    hashmap! {
+        cargo_toml_path.as_dir().join_to_path_in_fbcode(deprecation_build_filename) => render(quote! {
+            #[rustfmt::skip]
+            fn main() {
+                // Rerun if deprecation_build.rs gets rewritten.
+                println!(#rerun_if_changed);
+
+                println!("{}", #warning);
+            }
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cargo_manifest::empty_package;
+
+    fn deprecation() -> CrateDeprecation {
+        CrateDeprecation {
+            message: "Superseded by the\nnew API.".to_owned(),
+            replacement: Some("bar".to_owned()),
+            removal_date: Some("2026-12-31".to_owned()),
+            build_warning: true,
+        }
+    }
+
+    #[test]
+    fn add_deprecation_metadata_test() {
+        let mut package = empty_package();
+        package.name = "foo".to_owned();
+        add_deprecation_metadata(&mut package, &deprecation()).unwrap();
+
+        let metadata = package.metadata.as_ref().unwrap();
+        assert_eq!(
+            metadata["deprecation"]["message"].as_str(),
+            Some("Superseded by the\nnew API.")
+        );
+        assert_eq!(metadata["deprecation"]["replacement"].as_str(), Some("bar"));
+        assert_eq!(
+            metadata["deprecation"]["removal-date"].as_str(),
+            Some("2026-12-31")
+        );
+
+        assert!(add_deprecation_metadata(&mut package, &deprecation()).is_err());
+    }
+
+    #[test]
+    fn generate_additional_deprecation_files_test() {
+        let cargo_toml_path = CargoTomlPath::new(PathInFbcode::new_mock("foo/Cargo.toml")).unwrap();
+        let files = generate_additional_deprecation_files(&cargo_toml_path, "foo", &deprecation());
+
+        let build = &files[&PathInFbcode::new_mock("foo/deprecation_build.rs")];
+        assert!(build.contains(
+            "\"cargo:warning=Crate foo is deprecated and will be removed after 2026-12-31: \
+            Superseded by the new API., use bar instead\""
+        ));
+    }
+}
@@ -0,0 +1,204 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::iter::once;
+
+use anyhow::Result;
+use anyhow::bail;
+use anyhow::ensure;
+use cargo_toml::Edition;
+
+use crate::cargo_manifest::Manifest;
+use crate::cargo_manifest::Product;
+
+/// Tests that share the crate root with the library compile the same sources,
+/// so they must use the same edition. Products with different crate roots may
+/// use different editions, they are emitted per product.
+pub(super) fn validate_product_editions(manifest: &Manifest) -> Result<()> {
+    let (Some(package), Some(lib)) = (&manifest.package, &manifest.lib) else {
+        return Ok(());
+    };
+    let edition = |product: &Product| product.edition.unwrap_or(package.edition);
+    for test in &manifest.test {
+        if test.path.is_some() && test.path == lib.path && edition(test) != edition(lib) {
+            bail!(
+                "Test {:?} shares the crate root {:?} with lib {:?}, but uses edition \
+                {:?} instead of {:?}. Set the same edition on both rules or override it \
+                with autocargo.cargo_target_config.edition",
+                test.name.as_deref().unwrap_or_default(),
+                lib.path.as_deref().unwrap_or_default(),
+                lib.name.as_deref().unwrap_or_default(),
+                edition(test),
+                edition(lib),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Fail if the package or any of its products uses an edition newer than
+/// max_edition of the project or than the package's rust-version supports, so
+/// that the error is reported during generation instead of by Cargo.
+pub(super) fn validate_toolchain_editions(
+    manifest: &Manifest,
+    max_edition: Option<Edition>,
+) -> Result<()> {
+    let Some(package) = &manifest.package else {
+        return Ok(());
+    };
+    let rust_version = package
+        .rust_version
+        .as_deref()
+        .map(|version| Ok::<_, anyhow::Error>((version, rust_version_minor(version)?)))
+        .transpose()?;
+
+    let products = manifest
+        .lib
+        .iter()
+        .chain(&manifest.bin)
+        .chain(&manifest.example)
+        .chain(&manifest.test)
+        .chain(&manifest.bench)
+        .filter_map(|product| {
+            Some((
+                format!("product {:?}", product.name.as_deref().unwrap_or_default()),
+                product.edition?,
+            ))
+        });
+    for (owner, edition) in once(("the package".to_owned(), package.edition)).chain(products) {
+        if let Some(max_edition) = max_edition {
+            ensure!(
+                edition <= max_edition,
+                "Edition {} of {} is newer than {}, the newest edition supported by the \
+                toolchain of the project. Use an older edition for the rule or raise \
+                max_edition of the project",
+                edition,
+                owner,
+                max_edition,
+            );
+        }
+        if let Some((version, minor)) = rust_version {
+            ensure!(
+                minor >= edition.min_rust_version_minor(),
+                "Edition {} of {} requires rust-version 1.{} or newer, but the package \
+                sets rust-version {:?}",
+                edition,
+                owner,
+                edition.min_rust_version_minor(),
+                version,
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Minor version of a rust-version like "1.70" or "1.70.1".
+fn rust_version_minor(rust_version: &str) -> Result<u16> {
+    let mut parts = rust_version.split('.');
+    match (parts.next(), parts.next().map(str::parse)) {
+        (Some("1"), Some(Ok(minor))) => Ok(minor),
+        _ => bail!("Invalid rust-version {rust_version:?}, expected e.g. \"1.70\""),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cargo_manifest::Package;
+    use crate::cargo_manifest::empty_package;
+
+    #[test]
+    fn validate_product_editions_test() {
+        let product = |name: &str, path: &str, edition: Option<Edition>| Product {
+            name: Some(name.to_owned()),
+            path: Some(path.to_owned()),
+            edition,
+            ..Product::default()
+        };
+        let manifest = |tests: Vec<Product>| Manifest {
+            package: Some(empty_package()),
+            lib: Some(product("foo", "src/lib.rs", None)),
+            test: tests,
+            ..Manifest::default()
+        };
+
+        // Different crate roots may use different editions.
+        validate_product_editions(&manifest(vec![product(
+            "foo_test",
+            "tests/main.rs",
+            Some(Edition::E2024),
+        )]))
+        .unwrap();
+        // Same crate root with the same, possibly implicit, edition.
+        validate_product_editions(&manifest(vec![product(
+            "foo_unittest",
+            "src/lib.rs",
+            Some(Edition::E2021),
+        )]))
+        .unwrap();
+
+        assert!(
+            validate_product_editions(&manifest(vec![product(
+                "foo_unittest",
+                "src/lib.rs",
+                Some(Edition::E2024),
+            )]))
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn validate_toolchain_editions_test() {
+        let manifest =
+            |edition: Edition, rust_version: Option<&str>, lib: Option<Edition>| Manifest {
+                package: Some(Package {
+                    edition,
+                    rust_version: rust_version.map(|v| v.to_owned()),
+                    ..empty_package()
+                }),
+                lib: Some(Product {
+                    name: Some("foo".to_owned()),
+                    edition: lib,
+                    ..Product::default()
+                }),
+                ..Manifest::default()
+            };
+
+        validate_toolchain_editions(&manifest(Edition::E2024, None, None), None).unwrap();
+        validate_toolchain_editions(
+            &manifest(Edition::E2021, Some("1.70"), None),
+            Some(Edition::E2021),
+        )
+        .unwrap();
+        validate_toolchain_editions(&manifest(Edition::E2024, Some("1.85.1"), None), None).unwrap();
+
+        assert_eq!(
+            validate_toolchain_editions(
+                &manifest(Edition::E2021, None, Some(Edition::E2024)),
+                Some(Edition::E2021),
+            )
+            .unwrap_err()
+            .to_string(),
+            "Edition 2024 of product \"foo\" is newer than 2021, the newest edition supported \
+            by the toolchain of the project. Use an older edition for the rule or raise \
+            max_edition of the project",
+        );
+        assert_eq!(
+            validate_toolchain_editions(&manifest(Edition::E2024, Some("1.70"), None), None)
+                .unwrap_err()
+                .to_string(),
+            "Edition 2024 of the package requires rust-version 1.85 or newer, but the package \
+            sets rust-version \"1.70\"",
+        );
+        assert!(
+            validate_toolchain_editions(&manifest(Edition::E2021, Some("stable"), None), None)
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,204 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use anyhow::bail;
+use cargo_toml::Dependency;
+use cargo_toml::DepsSet;
+use cargo_toml::FeatureSet;
+use slog::Logger;
+use slog::warn;
+
+/// Reference to a dependency from a feature list, see
+/// https://doc.rust-lang.org/cargo/reference/features.html#dependency-features
+enum FeatureRef<'a> {
+    /// "foo" - either a feature or an optional dependency
+    Plain(&'a str),
+    /// "dep:foo"
+    Dep(&'a str),
+    /// "foo/bar" or "foo?/bar"
+    DepFeature {
+        dep: &'a str,
+        weak: bool,
+        feature: &'a str,
+    },
+}
+
+impl<'a> FeatureRef<'a> {
+    fn parse(value: &'a str) -> Self {
+        if let Some(dep) = value.strip_prefix("dep:") {
+            Self::Dep(dep)
+        } else if let Some((dep, feature)) = value.split_once('/') {
+            match dep.strip_suffix('?') {
+                Some(dep) => Self::DepFeature {
+                    dep,
+                    weak: true,
+                    feature,
+                },
+                None => Self::DepFeature {
+                    dep,
+                    weak: false,
+                    feature,
+                },
+            }
+        } else {
+            Self::Plain(value)
+        }
+    }
+
+    fn dep(&self) -> &'a str {
+        match self {
+            Self::Plain(dep) | Self::Dep(dep) | Self::DepFeature { dep, .. } => dep,
+        }
+    }
+
+    fn with_dep(&self, dep: &str) -> String {
+        match self {
+            Self::Plain(_) => dep.to_owned(),
+            Self::Dep(_) => format!("dep:{dep}"),
+            Self::DepFeature {
+                weak: true,
+                feature,
+                ..
+            } => format!("{dep}?/{feature}"),
+            Self::DepFeature {
+                weak: false,
+                feature,
+                ..
+            } => format!("{dep}/{feature}"),
+        }
+    }
+}
+
+/// Features might refer to a dependency by its package name while the
+/// generated manifest exposes it under an alias, e.g. via named_deps, in which
+/// case Cargo would not recognize the reference and the dependency wouldn't be
+/// made optional. Rewrite such references to use the alias and mark the
+/// dependency as optional, same as it would be if the alias was used. If the
+/// package is available under multiple aliases return an error, since it is
+/// not known which one the feature meant.
+pub fn resolve_feature_aliases<'a>(
+    logger: &Logger,
+    features: &mut FeatureSet,
+    deps_sets: impl IntoIterator<Item = &'a mut DepsSet>,
+) -> Result<()> {
+    let mut deps_sets: Vec<_> = deps_sets.into_iter().collect();
+    let feature_names: BTreeSet<_> = features.keys().cloned().collect();
+
+    for (feature_name, values) in features.iter_mut() {
+        for value in values.iter_mut() {
+            let feature_ref = FeatureRef::parse(value);
+            let dep = feature_ref.dep();
+            if matches!(feature_ref, FeatureRef::Plain(_)) && feature_names.contains(dep) {
+                continue;
+            }
+            if deps_sets.iter().any(|deps| deps.contains_key(dep)) {
+                continue;
+            }
+
+            let aliases: BTreeSet<_> = deps_sets
+                .iter()
+                .flat_map(|deps| deps.iter())
+                .filter(|(_, d)| d.package() == Some(dep))
+                .map(|(alias, _)| alias.clone())
+                .collect();
+            let alias = match aliases.len() {
+                0 => continue,
+                1 => aliases.into_iter().next().unwrap(),
+                _ => bail!(
+                    "Feature {:?} refers to {:?} in {:?}, but the package is available \
+                    under multiple aliases {:?}, use one of them instead",
+                    feature_name,
+                    dep,
+                    value,
+                    aliases,
+                ),
+            };
+
+            let fixed = feature_ref.with_dep(&alias);
+            warn!(
+                logger,
+                "Feature {:?} refers to package {:?} that is renamed to {:?}, \
+                replacing {:?} with {:?}",
+                feature_name,
+                dep,
+                alias,
+                value,
+                fixed,
+            );
+            if matches!(feature_ref, FeatureRef::Plain(_) | FeatureRef::Dep(_)) {
+                for deps in deps_sets.iter_mut() {
+                    if let Some(Dependency::Detailed(detail)) = deps.get_mut(&alias) {
+                        detail.optional = true;
+                    }
+                }
+            }
+            *value = fixed;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use cargo_toml::DependencyDetail;
+    use maplit::btreemap;
+    use slog::o;
+
+    use super::*;
+
+    fn s(s: &str) -> String {
+        s.to_owned()
+    }
+
+    fn aliased(package: &str, optional: bool) -> Dependency {
+        Dependency::Detailed(Box::new(DependencyDetail {
+            version: Some(s("1")),
+            package: Some(s(package)),
+            optional,
+            ..DependencyDetail::default()
+        }))
+    }
+
+    #[test]
+    fn resolve_feature_aliases_test() {
+        let logger = Logger::root(slog::Discard, o!());
+
+        let mut features = btreemap! {
+            s("default") => vec![s("foo_rs"), s("bar"), s("std")],
+            s("std") => vec![s("foo_rs/std"), s("dep:baz"), s("unknown/x")],
+        };
+        let mut deps = btreemap! {
+            s("foo") => aliased("foo_rs", false),
+            s("bar") => Dependency::Simple(s("1")),
+            s("baz") => Dependency::Simple(s("1")),
+        };
+
+        resolve_feature_aliases(&logger, &mut features, [&mut deps]).unwrap();
+
+        assert_eq!(
+            features,
+            btreemap! {
+                s("default") => vec![s("foo"), s("bar"), s("std")],
+                s("std") => vec![s("foo/std"), s("dep:baz"), s("unknown/x")],
+            }
+        );
+        assert_eq!(deps[&s("foo")], aliased("foo_rs", true));
+
+        let mut features = btreemap! { s("default") => vec![s("foo_rs?/std")] };
+        let mut deps = btreemap! { s("foo") => aliased("foo_rs", false) };
+        let mut target_deps = btreemap! { s("foo2") => aliased("foo_rs", false) };
+        assert!(
+            resolve_feature_aliases(&logger, &mut features, [&mut deps, &mut target_deps]).is_err()
+        );
+    }
+}
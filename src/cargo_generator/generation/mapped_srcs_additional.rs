@@ -0,0 +1,159 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::Result;
+use itertools::Itertools;
+use maplit::hashmap;
+use quote::quote;
+
+use super::thrift_additional::relative_src_path;
+use super::thrift_additional::render;
+use crate::buck_processing::RawBuckManifest;
+use crate::cargo_manifest::Package;
+use crate::paths::CargoTomlPath;
+use crate::paths::PathInFbcode;
+use crate::paths::TargetsPath;
+
+/// True if the mapped_srcs key is a plain file rather than an output of a Buck
+/// rule.
+pub fn is_plain_mapped_src(src: &Path) -> bool {
+    src.to_str().is_some_and(|src| !src.contains(':'))
+}
+
+/// Pairs of (source, destination) of mapped_srcs that are plain files, both
+/// relative to the TARGETS file. Sources produced by Buck rules can't be built
+/// by Cargo, so they are skipped, same as mapped_srcs that are already in
+/// place.
+pub fn mapped_srcs_copies<'a>(
+    raws: impl IntoIterator<Item = &'a RawBuckManifest>,
+) -> Vec<(&'a Path, &'a str)> {
+    raws.into_iter()
+        .flat_map(|raw| raw.sources.mapped_srcs.iter())
+        .filter(|(src, dst)| is_plain_mapped_src(src) && src.as_path() != Path::new(dst))
+        .map(|(src, dst)| (src.as_path(), dst.as_str()))
+        .sorted()
+        .dedup()
+        .collect()
+}
+
+/// If the package lists the files to include then add the sources of the
+/// copied mapped_srcs to it, so that the build script finds them in the
+/// packaged crate as well. Cargo only packages files inside the package
+/// directory, so sources outside of it are not added and packaging a crate
+/// that copies them is not supported, the build script of the packaged crate
+/// fails to find them.
+pub fn add_mapped_srcs_includes(
+    package: &mut Package,
+    targets_path: &TargetsPath,
+    cargo_toml_path: &CargoTomlPath,
+    copies: &[(&Path, &str)],
+) -> Result<()> {
+    if package.include.is_empty() {
+        return Ok(());
+    }
+    for (src, _) in copies {
+        let src = relative_src_path(targets_path, cargo_toml_path, src)?;
+        if !Path::new(&src).starts_with("..") && !package.include.contains(&src) {
+            package.include.push(src);
+        }
+    }
+    Ok(())
+}
+
+pub fn generate_additional_mapped_srcs_files(
+    targets_path: &TargetsPath,
+    cargo_toml_path: &CargoTomlPath,
+    copies: &[(&Path, &str)],
+) -> Result<HashMap<PathInFbcode, String>> {
+    let srcs = copies
+        .iter()
+        .map(|(src, _)| relative_src_path(targets_path, cargo_toml_path, src))
+        .collect::<Result<Vec<_>>>()?;
+    let dsts = copies
+        .iter()
+        .map(|(_, dst)| relative_src_path(targets_path, cargo_toml_path, dst))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mapped_srcs_build_filename = PathInFbcode::mapped_srcs_build_filename();
+    let rerun_if_changed = std::iter::once(mapped_srcs_build_filename.to_owned())
+        .chain(srcs.iter().cloned())
+        .map(|path| format!("cargo:rerun-if-changed={path}"));
+
+    Ok(hashmap! {
+        cargo_toml_path.as_dir().join_to_path_in_fbcode(mapped_srcs_build_filename) => render(quote! {
+            use std::fs;
+            use std::path::Path;
+
+            #[rustfmt::skip]
+            fn main() {
+                // Rerun if mapped_srcs_build.rs or any of the mapped sources change.
+                #(
+                    println!(#rerun_if_changed);
+                )*
+
+                #(
+                    copy_mapped_src(#srcs, #dsts);
+                )*
+            }
+
+            fn copy_mapped_src(src: &str, dst: &str) {
+                if let Some(parent) = Path::new(dst).parent() {
+                    fs::create_dir_all(parent)
+                        .unwrap_or_else(|e| panic!("Failed to create directory for {dst}: {e}"));
+                }
+                fs::copy(src, dst).unwrap_or_else(|e| panic!("Failed to copy {src} to {dst}: {e}"));
+            }
+        }),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+
+    use super::*;
+    use crate::cargo_manifest::empty_package;
+
+    #[test]
+    fn generate_additional_mapped_srcs_files_test() {
+        let mut raw = RawBuckManifest::empty_test();
+        raw.sources.mapped_srcs = HashMap::from([
+            (PathBuf::from("gen/lib.rs"), "src/lib.rs".to_owned()),
+            (PathBuf::from(":gen_rule"), "src/gen.rs".to_owned()),
+            (PathBuf::from("src/same.rs"), "src/same.rs".to_owned()),
+        ]);
+        let copies = mapped_srcs_copies([&raw]);
+        assert_eq!(copies, vec![(Path::new("gen/lib.rs"), "src/lib.rs")]);
+
+        let targets_path = TargetsPath::new(PathInFbcode::new_mock("foo/TARGETS")).unwrap();
+        let cargo_toml_path =
+            CargoTomlPath::new(PathInFbcode::new_mock("foo/cargo/Cargo.toml")).unwrap();
+        let files = generate_additional_mapped_srcs_files(&targets_path, &cargo_toml_path, &copies)
+            .unwrap();
+
+        let build = &files[&PathInFbcode::new_mock("foo/cargo/mapped_srcs_build.rs")];
+        assert!(build.contains(r#"println!("cargo:rerun-if-changed=../gen/lib.rs");"#));
+        assert!(build.contains(r#"copy_mapped_src("../gen/lib.rs", "../src/lib.rs");"#));
+
+        let mut package = empty_package();
+        add_mapped_srcs_includes(&mut package, &targets_path, &cargo_toml_path, &copies).unwrap();
+        assert!(package.include.is_empty());
+        package.include = vec!["src/**".to_owned()];
+        add_mapped_srcs_includes(&mut package, &targets_path, &cargo_toml_path, &copies).unwrap();
+        // ../gen/lib.rs is outside of the package, Cargo can't include it.
+        assert_eq!(package.include, vec!["src/**"]);
+
+        let cargo_toml_path = CargoTomlPath::new(PathInFbcode::new_mock("foo/Cargo.toml")).unwrap();
+        add_mapped_srcs_includes(&mut package, &targets_path, &cargo_toml_path, &copies).unwrap();
+        assert_eq!(package.include, vec!["src/**", "gen/lib.rs"]);
+    }
+}
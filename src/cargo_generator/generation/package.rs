@@ -7,8 +7,13 @@
  * of this source tree.
  */
 
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
 use anyhow::Context;
 use anyhow::Result;
+use anyhow::bail;
+use cargo_toml::Value;
 use cargo_util_schemas::manifest::StringOrBool;
 use itertools::Itertools;
 
@@ -19,6 +24,7 @@ use super::product::generate_product_name;
 use crate::buck_processing::AutocargoCargoTomlConfig;
 use crate::buck_processing::AutocargoPackageConfig;
 use crate::buck_processing::RawBuckManifest;
+use crate::cargo_manifest::Manifest;
 use crate::cargo_manifest::Package;
 use crate::config::PackageDefaults;
 use crate::paths::CargoTomlPath;
@@ -98,13 +104,15 @@ fn generate_package_version(
 }
 
 /// Generate package based on provided input. Not-None Autocargo fields take
-/// precedence over PackageDefaults fields.
+/// precedence over PackageDefaults fields, which take precedence over the
+/// rust-version of the toolchain.
 pub fn generate_package(
     name: String,
     package_config: &AutocargoPackageConfig,
     package_defaults: &PackageDefaults,
+    toolchain_rust_version: Option<&str>,
     cargo_toml_path: &CargoTomlPath,
-    is_thrift: bool,
+    generated_build_script: Option<&str>,
 ) -> Result<Package> {
     let AutocargoPackageConfig {
         name: _,
@@ -155,6 +163,7 @@ pub fn generate_package(
         include: default_include,
         publish: default_publish,
         metadata: default_metadata,
+        docs_rs: default_docs_rs,
     } = package_defaults;
 
     Ok(Package {
@@ -162,7 +171,12 @@ pub fn generate_package(
         version: generate_package_version(version, package_defaults),
         authors: generate_field(authors, default_authors),
         edition: generate_field(edition, default_edition),
-        rust_version: generate_field(rust_version, default_rust_version),
+        rust_version: generate_field(
+            rust_version,
+            &default_rust_version
+                .clone()
+                .or_else(|| toolchain_rust_version.map(str::to_owned)),
+        ),
         description: generate_field(description, default_description),
         documentation: generate_field(documentation, default_documentation),
         readme: generate_path_field(readme, default_readme, cargo_toml_path)
@@ -177,21 +191,234 @@ pub fn generate_package(
         workspace: generate_path_field(workspace, default_workspace, cargo_toml_path)
             .context("For field workspace")?,
         build: build.clone().or_else(|| {
-            if is_thrift {
-                Some(StringOrBool::String("thrift_build.rs".to_owned()))
-            } else {
-                None
-            }
+            generated_build_script.map(|script| StringOrBool::String(script.to_owned()))
         }),
         links: generate_field(links, default_links),
         exclude: generate_field(exclude, default_exclude),
         include: generate_field(include, default_include),
         publish: generate_field(publish, default_publish),
-        metadata: generate_field(metadata, default_metadata),
+        metadata: add_docs_rs_metadata(
+            generate_field(metadata, default_metadata),
+            default_docs_rs.as_ref(),
+        )
+        .context("For field metadata")?,
         default_run: default_run.clone(),
-        autobins: *autobins,
-        autoexamples: *autoexamples,
-        autotests: *autotests,
-        autobenches: *autobenches,
+        autobins: autobins.unwrap_or(true),
+        autoexamples: autoexamples.unwrap_or(true),
+        autotests: autotests.unwrap_or(true),
+        autobenches: autobenches.unwrap_or(true),
+        inherited_from_workspace: BTreeSet::new(),
     })
 }
+
+/// Put the docs.rs table into [package.metadata.docs.rs] unless the metadata
+/// already has one.
+fn add_docs_rs_metadata(metadata: Option<Value>, docs_rs: Option<&Value>) -> Result<Option<Value>> {
+    let Some(docs_rs) = docs_rs else {
+        return Ok(metadata);
+    };
+    let mut metadata = metadata.unwrap_or_else(|| Value::Table(Default::default()));
+    let Value::Table(table) = &mut metadata else {
+        bail!("Package metadata is not a table, can't add docs.rs metadata to it");
+    };
+    match table
+        .entry("docs")
+        .or_insert_with(|| Value::Table(Default::default()))
+    {
+        Value::Table(docs) => {
+            docs.entry("rs").or_insert_with(|| docs_rs.clone());
+        }
+        _ => bail!("Package metadata.docs is not a table, can't add docs.rs metadata to it"),
+    }
+    Ok(Some(metadata))
+}
+
+/// Put the configuration of each tool into [package.metadata.<tool>], failing
+/// if the metadata of the package already has an entry for the tool.
+pub(super) fn add_tool_metadata(
+    package: &mut Package,
+    tools: &BTreeMap<String, Value>,
+) -> Result<()> {
+    if tools.is_empty() {
+        return Ok(());
+    }
+    let Value::Table(metadata) = package
+        .metadata
+        .get_or_insert_with(|| Value::Table(Default::default()))
+    else {
+        bail!("Package metadata is not a table, can't add tool metadata to it");
+    };
+    for (tool, value) in tools {
+        if metadata.insert(tool.clone(), value.clone()).is_some() {
+            bail!(
+                "Metadata of tool {} is set both in package.metadata and in \
+                package_metadata_tools",
+                tool
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Turn off autodiscovery of the kinds of products that the manifest defines
+/// explicitly, so that Cargo doesn't pick up unrelated files e.g. from src/bin
+/// or tests/ next to them. Autodiscovery set explicitly in package_config is
+/// left untouched.
+pub(super) fn disable_autodiscovery(
+    manifest: &mut Manifest,
+    package_config: &AutocargoPackageConfig,
+) {
+    let Some(package) = manifest.package.as_mut() else {
+        return;
+    };
+    for (auto, config, products) in [
+        (
+            &mut package.autobins,
+            package_config.autobins,
+            &manifest.bin,
+        ),
+        (
+            &mut package.autoexamples,
+            package_config.autoexamples,
+            &manifest.example,
+        ),
+        (
+            &mut package.autotests,
+            package_config.autotests,
+            &manifest.test,
+        ),
+        (
+            &mut package.autobenches,
+            package_config.autobenches,
+            &manifest.bench,
+        ),
+    ] {
+        if config.is_none() && !products.is_empty() {
+            *auto = false;
+        }
+    }
+}
+
+/// Cargo only exposes the native library of a package with links to the
+/// packages depending on it through a build script, so make sure that the
+/// package has one, either set in package.build or as the default build.rs
+/// next to Cargo.toml, which is checked with build_rs_exists.
+pub fn validate_links(package: &Package, build_rs_exists: impl FnOnce() -> bool) -> Result<()> {
+    let Some(links) = &package.links else {
+        return Ok(());
+    };
+    let has_build_script = match &package.build {
+        Some(StringOrBool::String(_)) => true,
+        Some(StringOrBool::Bool(false)) => false,
+        Some(StringOrBool::Bool(true)) | None => build_rs_exists(),
+    };
+    if !has_build_script {
+        bail!(
+            "Package {} sets links = {:?} but has no build script, declare one via \
+            autocargo.build_script or package.build",
+            package.name,
+            links,
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cargo_manifest::Product;
+    use crate::cargo_manifest::empty_package;
+
+    #[test]
+    fn disable_autodiscovery_test() {
+        let mut manifest = Manifest {
+            package: Some(empty_package()),
+            bin: vec![Product::default()],
+            test: vec![Product::default()],
+            ..Default::default()
+        };
+        disable_autodiscovery(
+            &mut manifest,
+            &AutocargoPackageConfig {
+                autotests: Some(true),
+                ..Default::default()
+            },
+        );
+        let package = manifest.package.unwrap();
+        assert!(!package.autobins);
+        assert!(package.autoexamples);
+        assert!(package.autotests);
+        assert!(package.autobenches);
+    }
+
+    #[test]
+    fn add_docs_rs_metadata_test() {
+        let value = |s: &str| toml::from_str::<Value>(s).unwrap();
+        let docs_rs = value("all-features = true");
+
+        assert_eq!(add_docs_rs_metadata(None, None).unwrap(), None);
+        assert_eq!(
+            add_docs_rs_metadata(None, Some(&docs_rs)).unwrap(),
+            Some(value("docs.rs.all-features = true"))
+        );
+        assert_eq!(
+            add_docs_rs_metadata(Some(value("foo = 1")), Some(&docs_rs)).unwrap(),
+            Some(value("foo = 1\ndocs.rs.all-features = true"))
+        );
+        assert_eq!(
+            add_docs_rs_metadata(Some(value("docs.rs.features = []")), Some(&docs_rs)).unwrap(),
+            Some(value("docs.rs.features = []"))
+        );
+        assert!(add_docs_rs_metadata(Some(Value::Integer(1)), Some(&docs_rs)).is_err());
+    }
+
+    #[test]
+    fn add_tool_metadata_test() {
+        let value = |s: &str| toml::from_str::<Value>(s).unwrap();
+        let tools = BTreeMap::from([
+            ("cargo-udeps".to_owned(), value("ignore.normal = [\"log\"]")),
+            ("cargo-vet".to_owned(), value("audit-as-crates-io = false")),
+        ]);
+
+        let mut package = empty_package();
+        add_tool_metadata(&mut package, &BTreeMap::new()).unwrap();
+        assert_eq!(package.metadata, None);
+
+        package.metadata = Some(value("docs.rs.all-features = true"));
+        add_tool_metadata(&mut package, &tools).unwrap();
+        assert_eq!(
+            package.metadata,
+            Some(value(
+                "docs.rs.all-features = true\n\
+                cargo-udeps.ignore.normal = [\"log\"]\n\
+                cargo-vet.audit-as-crates-io = false"
+            ))
+        );
+
+        assert!(add_tool_metadata(&mut package, &tools).is_err());
+    }
+
+    #[test]
+    fn validate_links_test() {
+        let package = |links: Option<&str>, build: Option<StringOrBool>| Package {
+            name: "foo-sys".to_owned(),
+            links: links.map(|links| links.to_owned()),
+            build,
+            ..empty_package()
+        };
+
+        validate_links(&package(None, None), || false).unwrap();
+        validate_links(
+            &package(Some("foo"), Some(StringOrBool::String("gen.rs".to_owned()))),
+            || false,
+        )
+        .unwrap();
+        validate_links(&package(Some("foo"), None), || true).unwrap();
+        validate_links(&package(Some("foo"), None), || false).unwrap_err();
+        validate_links(
+            &package(Some("foo"), Some(StringOrBool::Bool(false))),
+            || true,
+        )
+        .unwrap_err();
+    }
+}
@@ -7,6 +7,7 @@
  * of this source tree.
  */
 
+use std::collections::BTreeMap;
 use std::collections::HashSet;
 use std::path::Path;
 use std::path::PathBuf;
@@ -14,16 +15,21 @@ use std::sync::LazyLock;
 
 use anyhow::Result;
 use anyhow::anyhow;
+use anyhow::bail;
+use anyhow::ensure;
 use itertools::Itertools;
 use maplit::hashset;
-use pathdiff::diff_paths;
 
+use super::mapped_srcs_additional::is_plain_mapped_src;
 use crate::buck_processing::AutocargoTargetConfig;
+use crate::buck_processing::BinaryProductType;
 use crate::buck_processing::FbconfigRuleType;
 use crate::buck_processing::RawBuckManifest;
+use crate::buck_processing::RawOsDepsPlatform;
 use crate::cargo_manifest::Product;
 use crate::paths::CargoTomlPath;
 use crate::paths::TargetsPath;
+use crate::paths::relative_path;
 
 static RUST_KEYWORDS: LazyLock<HashSet<&'static str>> = LazyLock::new(|| {
     hashset! {
@@ -50,22 +56,91 @@ static RUST_KEYWORDS: LazyLock<HashSet<&'static str>> = LazyLock::new(|| {
 /// package name or when escaped (replace "-" with "_") can be used as a
 /// target/product name.
 pub fn generate_product_name(raw: &RawBuckManifest) -> String {
-    if let Some(name) = raw.autocargo.cargo_target_config.name.clone() {
+    raw.autocargo
+        .cargo_target_config
+        .name
+        .clone()
+        .unwrap_or_else(|| default_product_name(raw))
+}
+
+/// Name of the product if it isn't set via autocargo.cargo_target_config.
+fn default_product_name(raw: &RawBuckManifest) -> String {
+    let name = raw
+        .rust_config
+        .crate_
+        .clone()
+        .unwrap_or_else(|| raw.name.clone());
+    if RUST_KEYWORDS.contains(name.as_str()) {
+        name + "_"
+    } else {
         name
+    }
+}
+
+/// Value of test and doctest of the product if they aren't set via
+/// autocargo.cargo_target_config.
+fn default_test(raw: &RawBuckManifest) -> Option<bool> {
+    if !raw.rust_config.unittests || raw.rust_config.proc_macro {
+        Some(false)
     } else {
-        let name = raw
-            .rust_config
-            .crate_
-            .clone()
-            .unwrap_or_else(|| raw.name.clone());
-        if RUST_KEYWORDS.contains(name.as_str()) {
-            name + "_"
-        } else {
-            name
-        }
+        None
     }
 }
 
+/// Describe the keys of autocargo.cargo_target_config of the rule that have no
+/// effect, either because rules of its type ignore them or because they set
+/// the value that is generated without them.
+pub fn unused_target_config_keys(
+    fbconfig_rule_type: FbconfigRuleType,
+    raw: &RawBuckManifest,
+) -> Vec<String> {
+    let AutocargoTargetConfig {
+        name,
+        test,
+        doctest,
+        bench,
+        proc_macro,
+        edition,
+        feature_propagation,
+        ..
+    } = &raw.autocargo.cargo_target_config;
+    let unchanged = "same as the generated value";
+    [
+        (
+            "name",
+            name.as_ref()
+                .is_some_and(|name| *name == default_product_name(raw)),
+            unchanged,
+        ),
+        ("test", *test == Some(default_test(raw)), unchanged),
+        ("doctest", *doctest == Some(default_test(raw)), unchanged),
+        (
+            "proc_macro",
+            *proc_macro == Some(raw.rust_config.proc_macro),
+            unchanged,
+        ),
+        (
+            "edition",
+            *edition == Some(raw.rust_config.edition),
+            unchanged,
+        ),
+        (
+            "bench",
+            bench.is_some() && fbconfig_rule_type != FbconfigRuleType::RustUnittest,
+            "only rust_unittest rules are generated as benchmarks",
+        ),
+        (
+            "feature_propagation",
+            feature_propagation.is_some() && fbconfig_rule_type == FbconfigRuleType::RustLibrary,
+            "features are propagated only from bin and test rules",
+        ),
+    ]
+    .into_iter()
+    .filter(|(_, unused, _)| *unused)
+    .map(|(key, _, reason)| format!("{key} ({reason})"))
+    .collect()
+}
+
 pub fn generate_product(
     fbconfig_rule_type: FbconfigRuleType,
     raw: &RawBuckManifest,
@@ -84,8 +159,34 @@ pub fn generate_product(
         harness,
         edition,
         crate_type,
+        os_crate_type,
         required_features,
+        feature_propagation: _,
+        product_type,
     } = &raw.autocargo.cargo_target_config;
+    ensure!(
+        product_type.is_none() || fbconfig_rule_type == FbconfigRuleType::RustBinary,
+        "autocargo.cargo_target_config.product_type is supported only for rust_binary rules",
+    );
+    ensure!(
+        os_crate_type.is_empty() || fbconfig_rule_type == FbconfigRuleType::RustLibrary,
+        "autocargo.cargo_target_config.os_crate_type is supported only for rust_library rules",
+    );
+    let proc_macro = proc_macro.unwrap_or(raw.rust_config.proc_macro);
+    let default_crate_type = if proc_macro { "proc-macro" } else { "lib" };
+    let (crate_type, crate_type_comment) =
+        merge_os_crate_types(crate_type, os_crate_type, default_crate_type)?;
+    if proc_macro {
+        let other_types = crate_type
+            .iter()
+            .filter(|crate_type| *crate_type != "proc-macro")
+            .join(", ");
+        ensure!(
+            other_types.is_empty(),
+            "Proc-macro crates can't have crate-type {}, Cargo only builds them as proc-macro",
+            other_types,
+        );
+    }
 
     let name = generate_product_name(raw).replace('-', "_");
 
@@ -98,6 +199,10 @@ pub fn generate_product(
                         // This is possible only for [lib]. We will put a generated
                         // thrift_lib.rs file next to the Cargo.toml file.
                         Some(Ok("thrift_lib.rs".to_owned()))
+                    } else if raw.autocargo.protobuf.is_some() {
+                        // Same as for thrift, protobuf_lib.rs includes the code
+                        // generated by protobuf_build.rs.
+                        Some(Ok("protobuf_lib.rs".to_owned()))
                     } else {
                         raw.rust_config
                             .crate_root
@@ -116,31 +221,90 @@ pub fn generate_product(
                 })?,
         ),
         name: Some(name),
-        test: test.unwrap_or(
-            if !raw.rust_config.unittests || raw.rust_config.proc_macro {
-                Some(false)
-            } else {
-                None
-            },
-        ),
-        doctest: doctest.unwrap_or(
-            if !raw.rust_config.unittests || raw.rust_config.proc_macro {
-                Some(false)
-            } else {
-                None
-            },
-        ),
+        test: test.unwrap_or(default_test(raw)),
+        doctest: doctest.unwrap_or(default_test(raw)),
         bench: *bench,
         doc: *doc,
         plugin: *plugin,
-        proc_macro: proc_macro.unwrap_or(raw.rust_config.proc_macro),
-        harness: *harness,
+        proc_macro,
+        harness: Some(*harness),
         edition: edition.unwrap_or(raw.rust_config.edition),
-        crate_type: crate_type.clone(),
+        crate_type,
+        crate_type_comment,
         required_features: required_features.clone(),
     })
 }
 
+/// The crate types of all platforms, since Cargo has no per-platform
+/// crate-type, together with a comment listing the platforms that the crate
+/// types from os_crate_type are meant for. An empty crate_type stands for the
+/// default crate type, so it is kept when adding the ones from os_crate_type.
+fn merge_os_crate_types(
+    crate_type: &[String],
+    os_crate_type: &[(RawOsDepsPlatform, Vec<String>)],
+    default_crate_type: &str,
+) -> Result<(Vec<String>, Option<String>)> {
+    let default_crate_type = [default_crate_type.to_owned()];
+    let base_crate_type = if crate_type.is_empty() {
+        &default_crate_type[..]
+    } else {
+        crate_type
+    };
+    let mut platforms: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    for (platform, crate_types) in os_crate_type {
+        let platform = match platform {
+            RawOsDepsPlatform::Linux => "linux",
+            RawOsDepsPlatform::Macos => "macos",
+            RawOsDepsPlatform::Windows => "windows",
+            RawOsDepsPlatform::Other => {
+                bail!("autocargo.cargo_target_config.os_crate_type has an unsupported platform")
+            }
+        };
+        for os_type in crate_types
+            .iter()
+            .filter(|os_type| !base_crate_type.contains(os_type))
+        {
+            platforms.entry(os_type).or_default().push(platform);
+        }
+    }
+
+    let comment = (!platforms.is_empty()).then(|| {
+        "Cargo builds every crate-type on all platforms, Buck builds only:\n".to_owned()
+            + &platforms
+                .iter()
+                .map(|(os_type, platforms)| {
+                    format!(
+                        "  {os_type} on {}",
+                        platforms.iter().sorted().dedup().join(", ")
+                    )
+                })
+                .join("\n")
+    });
+    let crate_type = if platforms.is_empty() {
+        crate_type.to_vec()
+    } else {
+        base_crate_type
+            .iter()
+            .cloned()
+            .chain(platforms.into_keys().map(str::to_owned))
+            .collect()
+    };
+    Ok((crate_type, comment))
+}
+
+/// True if the product generated from the rust_binary rule should be an
+/// [[example]] rather than a [[bin]].
+pub fn is_example_product(raw: &RawBuckManifest, product: &Product) -> bool {
+    match raw.autocargo.cargo_target_config.product_type {
+        Some(BinaryProductType::Bin) => false,
+        Some(BinaryProductType::Example) => true,
+        None => product
+            .path
+            .as_ref()
+            .is_some_and(|path| Path::new(path).starts_with("examples")),
+    }
+}
+
 /// Looks for the root path of crate following the logic from rust_common.bzl.
 fn generate_crate_root(
     fbconfig_rule_type: FbconfigRuleType,
@@ -151,12 +315,18 @@ fn generate_crate_root(
 ) -> Result<String> {
     let mut srcs: Vec<PathBuf> = vec![];
 
-    // mapped_srcs can only be used for Cargo if destination .rs is already present for cargo usage
+    // Plain file mapped_srcs are copied to their destination by the generated
+    // mapped_srcs_build.rs, other mapped_srcs can only be used for Cargo if
+    // destination .rs is already present for cargo usage
     if raw.sources.srcs.is_empty() && !raw.sources.mapped_srcs.is_empty() {
-        for path in raw.sources.mapped_srcs.values() {
-            let mut p = PathBuf::from("src");
-            p.push(PathBuf::from(path));
-            srcs.push(p);
+        for (src, path) in &raw.sources.mapped_srcs {
+            if is_plain_mapped_src(src) {
+                srcs.push(PathBuf::from(path));
+            } else {
+                let mut p = PathBuf::from("src");
+                p.push(PathBuf::from(path));
+                srcs.push(p);
+            }
         }
     } else {
         srcs.clone_from(&raw.sources.srcs);
@@ -202,14 +372,14 @@ fn generate_crate_root(
     relative_crate_root(crate_root, targets_path, cargo_toml_path)
 }
 
-fn relative_crate_root(
+pub(super) fn relative_crate_root(
     crate_root: impl AsRef<Path>,
     targets_path: &TargetsPath,
     cargo_toml_path: &CargoTomlPath,
 ) -> Result<String> {
     let crate_root = targets_path.as_dir().join_to_path_in_fbcode(crate_root);
 
-    diff_paths(crate_root.as_ref(), cargo_toml_path.as_dir().as_ref())
+    relative_path(crate_root.as_ref(), cargo_toml_path.as_dir().as_ref())
         .and_then(|path| path.to_str().map(|s| s.to_owned()))
         .ok_or_else(|| {
             anyhow!(
@@ -264,6 +434,159 @@ mod test {
         );
         let raw = r(&["foo/foo.rs", "foo/bar/main2.rs"]);
         assert_eq!(r#gen(FbconfigRuleType::RustUnittest, &raw), "foo/foo.rs");
+
+        let mapped = |src: &str, dst: &str| {
+            let mut raw = r(&[]);
+            raw.sources.mapped_srcs = [(PathBuf::from(src), dst.to_owned())].into();
+            raw
+        };
+        let raw = mapped("gen/lib_impl.rs", "lib.rs");
+        assert_eq!(r#gen(FbconfigRuleType::RustLibrary, &raw), "lib.rs");
+        let raw = mapped(":gen[lib.rs]", "lib.rs");
+        assert_eq!(r#gen(FbconfigRuleType::RustLibrary, &raw), "src/lib.rs");
+    }
+
+    #[test]
+    fn is_example_product_test() {
+        let product = |path: &str| Product {
+            path: Some(path.to_owned()),
+            ..Product::default()
+        };
+        let raw = |product_type| {
+            let mut raw = RawBuckManifest::empty_test();
+            raw.autocargo.cargo_target_config.product_type = product_type;
+            raw
+        };
+
+        assert!(is_example_product(&raw(None), &product("examples/foo.rs")));
+        assert!(is_example_product(
+            &raw(None),
+            &product("examples/foo/main.rs")
+        ));
+        assert!(!is_example_product(&raw(None), &product("src/main.rs")));
+        assert!(!is_example_product(
+            &raw(None),
+            &product("foo/examples/main.rs")
+        ));
+        assert!(is_example_product(
+            &raw(Some(BinaryProductType::Example)),
+            &product("src/main.rs")
+        ));
+        assert!(!is_example_product(
+            &raw(Some(BinaryProductType::Bin)),
+            &product("examples/foo.rs")
+        ));
+    }
+
+    #[test]
+    fn unused_target_config_keys_test() {
+        let mut raw = RawBuckManifest::empty_test();
+        raw.name = "foo".to_owned();
+        raw.rust_config.unittests = true;
+        assert!(unused_target_config_keys(FbconfigRuleType::RustLibrary, &raw).is_empty());
+
+        let config = &mut raw.autocargo.cargo_target_config;
+        config.name = Some("foo".to_owned());
+        config.test = Some(Some(false));
+        config.doctest = Some(None);
+        config.bench = Some(true);
+        assert_eq!(
+            unused_target_config_keys(FbconfigRuleType::RustLibrary, &raw),
+            vec![
+                "name (same as the generated value)".to_owned(),
+                "doctest (same as the generated value)".to_owned(),
+                "bench (only rust_unittest rules are generated as benchmarks)".to_owned(),
+            ]
+        );
+        assert_eq!(
+            unused_target_config_keys(FbconfigRuleType::RustUnittest, &raw),
+            vec![
+                "name (same as the generated value)".to_owned(),
+                "doctest (same as the generated value)".to_owned(),
+            ]
+        );
+    }
+
+    #[test]
+    fn generate_product_test_os_crate_type() {
+        let targets_path = TargetsPath::new(PathInFbcode::new_mock("foo/TARGETS")).unwrap();
+        let cargo_path = CargoTomlPath::new(PathInFbcode::new_mock("foo/Cargo.toml")).unwrap();
+        let raw = |crate_type: &[&str], os_crate_type: &[(RawOsDepsPlatform, &[&str])]| {
+            let mut raw = RawBuckManifest::empty_test();
+            raw.name = "foo".to_owned();
+            raw.sources.srcs = vec![PathBuf::from("foo/lib.rs")];
+            let config = &mut raw.autocargo.cargo_target_config;
+            config.crate_type = crate_type.iter().map(|t| (*t).to_owned()).collect();
+            config.os_crate_type = os_crate_type
+                .iter()
+                .map(|(platform, types)| {
+                    (*platform, types.iter().map(|t| (*t).to_owned()).collect())
+                })
+                .collect();
+            raw
+        };
+        let r#gen = |ty, raw: &RawBuckManifest| {
+            generate_product(ty, raw, &targets_path, &cargo_path)
+                .map(|product| (product.crate_type, product.crate_type_comment))
+        };
+
+        let lib = raw(
+            &["rlib"],
+            &[
+                (RawOsDepsPlatform::Windows, &["cdylib"]),
+                (RawOsDepsPlatform::Linux, &["cdylib", "staticlib", "rlib"]),
+            ],
+        );
+        assert_eq!(
+            r#gen(FbconfigRuleType::RustLibrary, &lib).unwrap(),
+            (
+                vec![
+                    "rlib".to_owned(),
+                    "cdylib".to_owned(),
+                    "staticlib".to_owned()
+                ],
+                Some(
+                    "Cargo builds every crate-type on all platforms, Buck builds only:\n  \
+                    cdylib on linux, windows\n  \
+                    staticlib on linux"
+                        .to_owned()
+                ),
+            )
+        );
+        assert!(r#gen(FbconfigRuleType::RustBinary, &lib).is_err());
+
+        // The default crate type of an empty crate_type is kept.
+        let lib = raw(&[], &[(RawOsDepsPlatform::Linux, &["cdylib"])]);
+        assert_eq!(
+            r#gen(FbconfigRuleType::RustLibrary, &lib).unwrap(),
+            (
+                vec!["lib".to_owned(), "cdylib".to_owned()],
+                Some(
+                    "Cargo builds every crate-type on all platforms, Buck builds only:\n  \
+                    cdylib on linux"
+                        .to_owned()
+                ),
+            )
+        );
+        let lib = raw(&[], &[(RawOsDepsPlatform::Linux, &["lib"])]);
+        assert_eq!(
+            r#gen(FbconfigRuleType::RustLibrary, &lib).unwrap(),
+            (Vec::new(), None)
+        );
+
+        let mut proc_macro = raw(&[], &[(RawOsDepsPlatform::Linux, &["cdylib"])]);
+        proc_macro.rust_config.proc_macro = true;
+        assert_eq!(
+            r#gen(FbconfigRuleType::RustLibrary, &proc_macro)
+                .unwrap_err()
+                .to_string(),
+            "Proc-macro crates can't have crate-type cdylib, Cargo only builds them as proc-macro"
+        );
+        proc_macro.autocargo.cargo_target_config.os_crate_type = Vec::new();
+        assert_eq!(
+            r#gen(FbconfigRuleType::RustLibrary, &proc_macro).unwrap(),
+            (Vec::new(), None)
+        );
     }
 
     #[test]
@@ -0,0 +1,164 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::collections::HashMap;
+
+use anyhow::Result;
+use anyhow::ensure;
+use itertools::Itertools;
+use maplit::hashmap;
+use quote::quote;
+
+use super::thrift_additional::relative_src_path;
+use super::thrift_additional::render;
+use crate::buck_processing::AutocargoProtobuf;
+use crate::paths::CargoTomlPath;
+use crate::paths::PathInFbcode;
+use crate::paths::TargetsPath;
+
+/// Name of the file in OUT_DIR that prost-build generates with modules of all
+/// compiled protobuf packages.
+const PROST_INCLUDE_FILE: &str = "protobuf_lib.rs";
+
+pub fn generate_additional_protobuf_files(
+    targets_path: &TargetsPath,
+    cargo_toml_path: &CargoTomlPath,
+    autocargo_protobuf: &AutocargoProtobuf,
+) -> Result<HashMap<PathInFbcode, String>> {
+    ensure!(
+        !autocargo_protobuf.proto_srcs.is_empty(),
+        "Protobuf library in {} has no proto_srcs",
+        targets_path.as_dir(),
+    );
+    ensure!(
+        !autocargo_protobuf.build_deps.is_empty(),
+        "Protobuf library in {} has no build_deps, they have to include the prost-build \
+        compiler",
+        targets_path.as_dir(),
+    );
+
+    let input = autocargo_protobuf
+        .proto_srcs
+        .iter()
+        .sorted()
+        .map(|src| relative_src_path(targets_path, cargo_toml_path, src))
+        .collect::<Result<Vec<_>>>()?;
+
+    let includes = if autocargo_protobuf.includes.is_empty() {
+        vec![relative_src_path(targets_path, cargo_toml_path, "")?]
+    } else {
+        autocargo_protobuf
+            .includes
+            .iter()
+            .map(|include| relative_src_path(targets_path, cargo_toml_path, include))
+            .collect::<Result<Vec<_>>>()?
+    };
+    let includes = includes.into_iter().map(|include| {
+        if include.is_empty() {
+            ".".to_owned()
+        } else {
+            include
+        }
+    });
+
+    let extern_proto_paths = autocargo_protobuf.extern_paths.keys();
+    let extern_rust_paths = autocargo_protobuf.extern_paths.values();
+
+    let protobuf_build_filename = PathInFbcode::protobuf_build_filename();
+    let rerun_if_changed = std::iter::once(protobuf_build_filename.to_owned())
+        .chain(input.iter().cloned())
+        .map(|path| format!("cargo:rerun-if-changed={path}"));
+
+    let include_file = format!("/{PROST_INCLUDE_FILE}");
+
+    Ok(hashmap! {
+        cargo_toml_path.as_dir().join_to_path_in_fbcode(PathInFbcode::protobuf_lib_filename()) => render(quote! {
+            include!(concat!(env!("OUT_DIR"), #include_file));
+        }),
+        cargo_toml_path.as_dir().join_to_path_in_fbcode(protobuf_build_filename) => render(quote! {
+            #[rustfmt::skip]
+            fn main() {
+                // Rerun if protobuf_build.rs or any of the proto files change.
+                #(
+                    println!(#rerun_if_changed);
+                )*
+
+                prost_build::Config::new()
+                    #(
+                        .extern_path(#extern_proto_paths, #extern_rust_paths)
+                    )*
+                    .include_file(#PROST_INCLUDE_FILE)
+                    .compile_protos(&[#(#input),*], &[#(#includes),*])
+                    .expect("Failed while running protobuf compilation");
+            }
+        }),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use maplit::btreemap;
+    use serde_json::from_value;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn generate_additional_protobuf_files_test() {
+        let targets_path = TargetsPath::new(PathInFbcode::new_mock("foo/TARGETS")).unwrap();
+        let cargo_toml_path =
+            CargoTomlPath::new(PathInFbcode::new_mock("foo/cargo/Cargo.toml")).unwrap();
+        let autocargo_protobuf: AutocargoProtobuf = from_value(json!({
+            "proto_srcs": ["if/foo.proto"],
+            "build_deps": ["fbsource//third-party/rust:prost-build"],
+            "extern_paths": {".common": "::common_proto"},
+        }))
+        .unwrap();
+
+        let files = generate_additional_protobuf_files(
+            &targets_path,
+            &cargo_toml_path,
+            &autocargo_protobuf,
+        )
+        .unwrap();
+
+        let lib = &files[&PathInFbcode::new_mock("foo/cargo/protobuf_lib.rs")];
+        assert!(lib.contains(r#"include!(concat!(env!("OUT_DIR"), "/protobuf_lib.rs"));"#));
+
+        let build = &files[&PathInFbcode::new_mock("foo/cargo/protobuf_build.rs")];
+        assert!(build.contains(r#"println!("cargo:rerun-if-changed=../if/foo.proto");"#));
+        assert!(build.contains(r#".extern_path(".common", "::common_proto")"#));
+        assert!(build.contains(r#".compile_protos(&["../if/foo.proto"], &[".."])"#));
+
+        assert!(
+            generate_additional_protobuf_files(
+                &targets_path,
+                &cargo_toml_path,
+                &AutocargoProtobuf {
+                    proto_srcs: Vec::new(),
+                    build_deps: Vec::new(),
+                    includes: Vec::new(),
+                    extern_paths: btreemap! {},
+                },
+            )
+            .is_err()
+        );
+        assert!(
+            generate_additional_protobuf_files(
+                &targets_path,
+                &cargo_toml_path,
+                &AutocargoProtobuf {
+                    build_deps: Vec::new(),
+                    ..autocargo_protobuf
+                },
+            )
+            .is_err()
+        );
+    }
+}
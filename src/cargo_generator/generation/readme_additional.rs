@@ -0,0 +1,123 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use maplit::hashmap;
+
+use crate::cargo_generator::GENERATED_PREAMBLE;
+use crate::cargo_generator::is_generated_content;
+use crate::cargo_manifest::Manifest;
+use crate::paths::CargoTomlPath;
+use crate::paths::FbcodeRoot;
+use crate::paths::PathInFbcode;
+
+/// Generate a minimal README.md next to cargo_toml_path out of the package
+/// description and repository and point the `readme` field to it. Nothing is
+/// generated if the package already has a readme or if there is a README.md
+/// that was not generated by autocargo, which Cargo picks up by itself.
+pub fn generate_additional_readme_files(
+    fbcode_root: &FbcodeRoot,
+    cargo_toml_path: &CargoTomlPath,
+    manifest: &mut Manifest,
+) -> HashMap<PathInFbcode, String> {
+    let Some(package) = &mut manifest.package else {
+        return HashMap::new();
+    };
+    if package.readme.is_some() {
+        return HashMap::new();
+    }
+    let path = cargo_toml_path
+        .as_dir()
+        .join_to_path_in_fbcode(PathInFbcode::readme_filename());
+    let existing = std::fs::read_to_string(Path::join(fbcode_root.as_ref(), path.as_ref()));
+    if existing.is_ok_and(|existing| !is_generated_content(&existing, &[])) {
+        return HashMap::new();
+    }
+
+    let mut content = format!("<!-- {GENERATED_PREAMBLE} -->\n\n# {}\n", package.name);
+    if let Some(description) = &package.description {
+        content += &format!("\n{}\n", description.trim());
+    }
+    if let Some(repository) = &package.repository {
+        content += &format!("\nThe source code is available at <{repository}>.\n");
+    }
+
+    package.readme = Some(PathInFbcode::readme_filename().to_owned());
+    hashmap! { path => content }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::cargo_manifest::empty_package;
+
+    #[test]
+    fn generate_additional_readme_files_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let fbcode_root = FbcodeRoot::new_mock(dir.path());
+        let cargo_toml_path =
+            CargoTomlPath::new(PathInFbcode::new_mock("foo/public/Cargo.toml")).unwrap();
+        let new_manifest = || Manifest {
+            package: Some({
+                let mut package = empty_package();
+                package.name = "foo".to_owned();
+                package.description = Some("Does foo things".to_owned());
+                package.repository = Some("https://github.com/facebook/foo".to_owned());
+                package
+            }),
+            ..Manifest::default()
+        };
+        let mut manifest = new_manifest();
+
+        let files = generate_additional_readme_files(&fbcode_root, &cargo_toml_path, &mut manifest);
+        assert_eq!(
+            files[&PathInFbcode::new_mock("foo/public/README.md")],
+            "<!-- \x40generated by autocargo -->\n\
+            \n\
+            # foo\n\
+            \n\
+            Does foo things\n\
+            \n\
+            The source code is available at <https://github.com/facebook/foo>.\n"
+        );
+        assert_eq!(
+            manifest.package.as_ref().unwrap().readme.as_deref(),
+            Some("README.md")
+        );
+
+        assert!(
+            generate_additional_readme_files(&fbcode_root, &cargo_toml_path, &mut manifest)
+                .is_empty()
+        );
+
+        // A previously generated README.md is generated again, a hand-written
+        // one is kept.
+        let readme = dir.path().join("foo/public/README.md");
+        std::fs::create_dir_all(readme.parent().unwrap()).unwrap();
+        std::fs::write(
+            &readme,
+            &files[&PathInFbcode::new_mock("foo/public/README.md")],
+        )
+        .unwrap();
+        let mut manifest = new_manifest();
+        assert_eq!(
+            generate_additional_readme_files(&fbcode_root, &cargo_toml_path, &mut manifest),
+            files
+        );
+        std::fs::write(&readme, "# Foo\n\nHand-written.\n").unwrap();
+        let mut manifest = new_manifest();
+        assert!(
+            generate_additional_readme_files(&fbcode_root, &cargo_toml_path, &mut manifest)
+                .is_empty()
+        );
+        assert_eq!(manifest.package.unwrap().readme, None);
+    }
+}
@@ -0,0 +1,121 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::collections::HashMap;
+
+use itertools::Itertools;
+use maplit::hashmap;
+use quote::quote;
+
+use super::thrift_additional::render;
+use crate::buck_processing::RawBuckManifest;
+use crate::paths::CargoTomlPath;
+use crate::paths::PathInFbcode;
+
+/// rustc_flags of the rules mapped to what Cargo supports.
+#[derive(Debug, Default, PartialEq)]
+pub struct RustcFlags<'a> {
+    /// Values of `--cfg` flags, e.g. `foo` or `foo="bar"`.
+    pub cfgs: Vec<&'a str>,
+    /// Flags that have no Cargo equivalent and are skipped.
+    pub unsupported: Vec<&'a str>,
+}
+
+/// Collect the rustc_flags of the rules, `--cfg` flags are set by a generated
+/// build script and all the others are unsupported.
+pub fn rustc_flags<'a>(raws: impl IntoIterator<Item = &'a RawBuckManifest>) -> RustcFlags<'a> {
+    let mut flags = RustcFlags::default();
+    for raw in raws {
+        let mut iter = raw.rust_config.rustc_flags.iter().map(String::as_str);
+        while let Some(flag) = iter.next() {
+            match flag.strip_prefix("--cfg") {
+                Some("") => flags.cfgs.extend(iter.next()),
+                Some(cfg) if cfg.starts_with('=') => flags.cfgs.push(&cfg[1..]),
+                _ => flags.unsupported.push(flag),
+            }
+        }
+    }
+    flags.cfgs = flags.cfgs.into_iter().sorted().dedup().collect();
+    flags
+}
+
+/// The `--check-cfg` spec that declares the cfg as expected.
+fn check_cfg(cfg: &str) -> String {
+    match cfg.split_once('=') {
+        Some((name, value)) => format!("cfg({}, values({}))", name.trim(), value.trim()),
+        None => format!("cfg({})", cfg.trim()),
+    }
+}
+
+pub fn generate_additional_rustc_cfg_files(
+    cargo_toml_path: &CargoTomlPath,
+    cfgs: &[&str],
+) -> HashMap<PathInFbcode, String> {
+    let rustc_cfg_build_filename = PathInFbcode::rustc_cfg_build_filename();
+    let rerun_if_changed = format!("cargo:rerun-if-changed={rustc_cfg_build_filename}");
+    let check_cfgs = cfgs
+        .iter()
+        .map(|cfg| format!("cargo:rustc-check-cfg={}", check_cfg(cfg)));
+    let cfgs = cfgs.iter().map(|cfg| format!("cargo:rustc-cfg={cfg}"));
+
+    hashmap! {
+        cargo_toml_path.as_dir().join_to_path_in_fbcode(rustc_cfg_build_filename) => render(quote! {
+            #[rustfmt::skip]
+            fn main() {
+                // Rerun if rustc_cfg_build.rs gets rewritten.
+                println!(#rerun_if_changed);
+
+                #(
+                    println!("{}", #check_cfgs);
+                )*
+                #(
+                    println!("{}", #cfgs);
+                )*
+            }
+        }),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rustc_flags_test() {
+        let mut lib = RawBuckManifest::empty_test();
+        lib.rust_config.rustc_flags = vec![
+            "--cfg".to_owned(),
+            "foo".to_owned(),
+            "--cfg=bar=\"baz\"".to_owned(),
+            "-Copt-level=3".to_owned(),
+        ];
+        let mut bin = RawBuckManifest::empty_test();
+        bin.rust_config.rustc_flags = vec!["--cfg=foo".to_owned()];
+
+        assert_eq!(
+            rustc_flags([&lib, &bin]),
+            RustcFlags {
+                cfgs: vec!["bar=\"baz\"", "foo"],
+                unsupported: vec!["-Copt-level=3"],
+            }
+        );
+    }
+
+    #[test]
+    fn generate_additional_rustc_cfg_files_test() {
+        let cargo_toml_path = CargoTomlPath::new(PathInFbcode::new_mock("foo/Cargo.toml")).unwrap();
+        let files = generate_additional_rustc_cfg_files(&cargo_toml_path, &["bar=\"baz\"", "foo"]);
+
+        let build = &files[&PathInFbcode::new_mock("foo/rustc_cfg_build.rs")];
+        assert!(build.contains(r#"cargo:rustc-check-cfg=cfg(bar, values(\"baz\"))"#));
+        assert!(build.contains(r#"cargo:rustc-check-cfg=cfg(foo)"#));
+        assert!(build.contains(r#"cargo:rustc-cfg=bar=\"baz\""#));
+        assert!(build.contains(r#"cargo:rustc-cfg=foo"#));
+    }
+}
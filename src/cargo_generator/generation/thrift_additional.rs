@@ -14,7 +14,6 @@ use anyhow::Result;
 use anyhow::anyhow;
 use itertools::Itertools;
 use maplit::hashmap;
-use pathdiff::diff_paths;
 use proc_macro2::Ident;
 use proc_macro2::Literal;
 use proc_macro2::Span;
@@ -28,6 +27,7 @@ use crate::cargo_generator::GENERATED_PREAMBLE;
 use crate::paths::CargoTomlPath;
 use crate::paths::PathInFbcode;
 use crate::paths::TargetsPath;
+use crate::paths::relative_path;
 
 pub fn generate_additional_thrift_files(
     targets_path: &TargetsPath,
@@ -35,7 +35,7 @@ pub fn generate_additional_thrift_files(
     thrift_config: &ThriftConfig,
     autocargo_thrift: &AutocargoThrift,
 ) -> Result<HashMap<PathInFbcode, String>> {
-    let path_to_base = diff_paths("", cargo_toml_path.as_dir().as_ref())
+    let path_to_base = relative_path("", cargo_toml_path.as_dir().as_ref())
         .and_then(|path| path.to_str().map(|s| s.to_owned()))
         .ok_or_else(|| {
             anyhow!(
@@ -49,7 +49,7 @@ pub fn generate_additional_thrift_files(
         .thrift_srcs
         .keys()
         .sorted()
-        .map(|src| relative_path(targets_path, cargo_toml_path, src))
+        .map(|src| relative_src_path(targets_path, cargo_toml_path, src))
         .collect::<Result<Vec<_>>>()?;
     let input_type_hint = input.is_empty().then_some(quote!(as [&Path; 0]));
 
@@ -82,7 +82,7 @@ pub fn generate_additional_thrift_files(
         .as_deref()
         .unwrap_or_default()
         .split_terminator(':')
-        .map(|src| relative_path(targets_path, cargo_toml_path, src))
+        .map(|src| relative_src_path(targets_path, cargo_toml_path, src))
         .collect::<Result<Vec<_>>>()?;
     let include_srcs = (!include_srcs.is_empty())
         .then_some(include_srcs)
@@ -98,7 +98,7 @@ pub fn generate_additional_thrift_files(
         .as_deref()
         .unwrap_or_default()
         .split_terminator(':')
-        .map(|src| relative_path(targets_path, cargo_toml_path, src))
+        .map(|src| relative_src_path(targets_path, cargo_toml_path, src))
         .collect::<Result<Vec<_>>>()?;
     let extra_srcs = (!extra_srcs.is_empty()).then_some(extra_srcs).into_iter();
 
@@ -162,26 +162,26 @@ pub fn generate_additional_thrift_files(
     })
 }
 
-fn relative_path(
+pub(super) fn relative_src_path(
     targets_path: &TargetsPath,
     cargo_toml_path: &CargoTomlPath,
     src: impl AsRef<Path>,
 ) -> Result<String> {
     let absolute_src = targets_path.as_dir().join_to_path_in_fbcode(src);
 
-    diff_paths(absolute_src.as_ref(), cargo_toml_path.as_dir().as_ref())
+    relative_path(absolute_src.as_ref(), cargo_toml_path.as_dir().as_ref())
         .and_then(|path| path.to_str().map(|s| s.to_owned()))
         .ok_or_else(|| {
             anyhow!(
                 "Failed to make a relative path from {:?} to {:?} \
-                        while constructing thrift compiler input",
+                        while constructing codegen compiler input",
                 absolute_src,
                 cargo_toml_path.as_dir()
             )
         })
 }
 
-fn render(content: TokenStream) -> String {
+pub(super) fn render(content: TokenStream) -> String {
     let file: syn::File = syn::parse2(content).unwrap();
     let code = prettyplease::unparse(&file);
     format!("// {GENERATED_PREAMBLE}\n\n{code}")
@@ -10,28 +10,49 @@
 use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::collections::hash_map::Entry;
 use std::path::Path;
+use std::path::PathBuf;
 
 use anyhow::Context;
 use anyhow::Error;
 use anyhow::Result;
 use anyhow::anyhow;
+use anyhow::bail;
 use cargo_toml::Dependency;
 use cargo_toml::DependencyDetail;
 use cargo_toml::DepsSet;
 use cargo_toml::PatchSet;
+use cargo_toml::Profiles;
 use cargo_toml::Resolver;
+use cargo_toml::Value;
 use cargo_toml::Workspace;
 use futures::FutureExt;
 use futures::future::LocalBoxFuture;
 use getset::Getters;
+use glob::Pattern;
 use itertools::Itertools;
 use maplit::hashmap;
+use rayon::iter::IntoParallelIterator;
+use rayon::iter::ParallelIterator;
 use slog::Logger;
 use slog::o;
+use slog::warn;
 use tokio::fs::read;
 
+use super::GENERATED_PREAMBLE;
+use super::crate_index::render_crate_index;
+use super::dependency_cycles::check_dependency_cycles;
+use super::error::ErrorLocation;
+use super::error::OutputConflict;
+use super::error::OutputConflicts;
+use super::error::WithLocation;
+use super::file_manifest::render_file_manifests;
 use super::generation::GenerationInput;
+use super::stamp::StampInputs;
+use super::toolchain::read_toolchain_rust_version;
+use super::workspace_dependencies::inherit_workspace_dependencies;
+use super::workspace_package::inherit_workspace_package;
 use crate::buck_processing::BuckManifest;
 use crate::cargo_manifest::Manifest;
 use crate::config::AllProjects;
@@ -42,13 +63,19 @@ use crate::config::PatchGenerationMode;
 use crate::config::ProjectConf;
 use crate::config::SelectedProjects;
 use crate::config::WorkspaceConfig;
+use crate::error::AutocargoError;
+use crate::events::AutocargoEvent;
+use crate::events::EventSender;
+use crate::events::Phase;
 use crate::paths::CargoTomlPath;
+use crate::paths::FbcodeRoot;
 use crate::paths::FbsourceRoot;
 use crate::paths::PathInFbcode;
 use crate::paths::TargetsPath;
+use crate::paths::relative_path;
 use crate::project_loader::ProjectFiles;
 
-static THIRD_PARTY_CARGO_TOML: &str = "third-party/rust/Cargo.toml";
+pub(crate) static THIRD_PARTY_CARGO_TOML: &str = "third-party/rust/Cargo.toml";
 
 /// Struct holding result of successful generation.
 #[derive(Default)]
@@ -57,6 +84,28 @@ pub struct GenerationOutput {
     pub cargo_manifests: HashMap<CargoTomlPath, Manifest>,
     /// Additional files generated, e.g. thrift build files
     pub additional_files: HashMap<PathInFbcode, String>,
+    /// Crates generated for the projects, not including the oss-ready ones.
+    pub crates: Vec<GeneratedCrate>,
+}
+
+/// Information about a crate generated from Buck rules.
+#[derive(Clone, Debug)]
+pub struct GeneratedCrate {
+    /// Name of the project covering the crate.
+    pub project: String,
+    /// Name of the package.
+    pub name: String,
+    /// Path of the generated Cargo.toml file.
+    pub cargo_toml_path: CargoTomlPath,
+    /// Description of the package.
+    pub description: Option<String>,
+    /// Buck rules the crate is generated from.
+    pub rule: String,
+    /// Each of the Buck rules the crate is generated from.
+    pub rules: Vec<String>,
+    /// Path of the oss-ready Cargo.toml file generated for the crate, if the
+    /// project is shipped to a git repository.
+    pub oss_cargo_toml_path: Option<CargoTomlPath>,
 }
 
 /// This is the main Cargo generator of autocargo.
@@ -67,8 +116,17 @@ pub struct CargoGenerator<'r#gen> {
     third_party_crates: DepsSet,
     /// Third party patches defined in fbsource.
     third_party_patches: PatchSet,
+    /// Dependencies that rules of other cells are mapped to.
+    cell_dependencies: &'r#gen DepsSet,
     /// Map from targets paths to projects that cover them.
     targets_to_projects: HashMap<&'r#gen TargetsPath, &'r#gen ProjectConf>,
+    /// Root of fbcode, used for reading files referenced by the manifests.
+    fbcode_root: FbcodeRoot,
+    /// Inputs of the reproducibility stamps put in the generated Cargo.toml
+    /// files, if any.
+    stamp: Option<StampInputs>,
+    /// Default rust-version derived from the toolchain file of the repository.
+    toolchain_rust_version: Option<String>,
 }
 
 impl<'r#gen> CargoGenerator<'r#gen> {
@@ -81,7 +139,7 @@ impl<'r#gen> CargoGenerator<'r#gen> {
         all_configs: &'r#gen AllProjects,
         project_files: impl IntoIterator<Item = &'r#gen ProjectFiles<'r#gen>>,
         unprocessed_paths: impl IntoIterator<Item = &'r#gen TargetsPath>,
-    ) -> LocalBoxFuture<'fut, Result<Self>>
+    ) -> LocalBoxFuture<'fut, Result<Self, AutocargoError>>
     where
         'r#gen: 'fut,
     {
@@ -172,41 +230,153 @@ impl<'r#gen> CargoGenerator<'r#gen> {
                 }
             }
 
+            let toolchain_rust_version = match all_configs.global().rust_toolchain() {
+                Some(path) => Some(read_toolchain_rust_version(fbsource_root, path).await?),
+                None => None,
+            };
+
+            let mut third_party_patches = manifest.patch;
+            resolve_registries(
+                all_configs.global().registries(),
+                &mut third_party_crates,
+                &mut third_party_patches,
+            );
+
             Ok(Self {
                 third_party_crates,
-                third_party_patches: manifest.patch,
+                third_party_patches,
+                cell_dependencies: all_configs.global().cell_dependencies(),
                 targets_to_projects,
+                fbcode_root: FbcodeRoot::from(fbsource_root.clone()),
+                stamp: None,
+                toolchain_rust_version,
             })
         }
+        .map(|generator| generator.map_err(AutocargoError::io))
         .boxed_local()
     }
 
+    /// Put the reproducibility stamps in the generated Cargo.toml files.
+    pub fn with_stamp(mut self, stamp: Option<StampInputs>) -> Self {
+        self.stamp = stamp;
+        self
+    }
+
     /// Generate Cargo files for the given TARGETS files and additional workspace
     /// manifest for selected projects.
-    pub fn generate_for_projects<'input, Manifests: IntoIterator<Item = &'input BuckManifest>>(
+    pub fn generate_for_projects<
+        'input,
+        Manifests: IntoIterator<Item = &'input BuckManifest> + Send,
+    >(
         &self,
         logger: &Logger,
+        events: &EventSender,
+        selected_projects: &SelectedProjects<'_>,
+        many_targets: impl IntoIterator<Item = (&'input TargetsPath, Manifests)>,
+    ) -> Result<GenerationOutput, AutocargoError> {
+        self.generate_for_projects_impl(logger, events, selected_projects, many_targets)
+            .map_err(AutocargoError::generation)
+    }
+
+    fn generate_for_projects_impl<
+        'input,
+        Manifests: IntoIterator<Item = &'input BuckManifest> + Send,
+    >(
+        &self,
+        logger: &Logger,
+        events: &EventSender,
         selected_projects: &SelectedProjects<'_>,
         many_targets: impl IntoIterator<Item = (&'input TargetsPath, Manifests)>,
     ) -> Result<GenerationOutput> {
-        let mut output = generate_and_combine(
-            many_targets,
-            |targets_path, manifests| self.generate_for_targets(logger, targets_path, manifests),
-            |path, tp, other_tp| {
-                anyhow!(
-                    "Path '{:?}' has been generated by both TARGETS '{:?}' and '{:?}'",
-                    path,
-                    tp,
-                    other_tp,
+        let mut output = self.generate_for_many_targets_impl(logger, events, many_targets)?;
+        check_dependency_cycles(logger, &output)?;
+
+        self.generate_workspaces(
+            logger,
+            selected_projects,
+            &output.crates,
+            &mut output.cargo_manifests,
+        )?;
+        validate_cargo_locks(
+            selected_projects.projects().iter().flat_map(|conf| {
+                conf.cargo_locks()
+                    .iter()
+                    .map(move |lock_path| (conf.name().as_str(), lock_path))
+            }),
+            &output.cargo_manifests,
+            |cargo_toml_path| {
+                Path::join(
+                    self.fbcode_root.as_ref(),
+                    cargo_toml_path.as_file().as_ref(),
                 )
+                .is_file()
             },
         )?;
-
-        self.generate_workspaces(selected_projects, &mut output.cargo_manifests)?;
+        self.generate_crate_indexes(selected_projects, &mut output)?;
+        for conf in selected_projects.projects() {
+            generate_tool_configs(conf, &mut output.additional_files)?;
+            let uses_bindeps = uses_artifact_dependencies(conf, &output);
+            generate_cargo_config(conf, uses_bindeps, &mut output.additional_files)?;
+        }
+        report_deprecated_dependents(logger, selected_projects, &output);
+        report_dual_source_dependencies(logger, &output);
+        generate_file_manifests(selected_projects, &mut output)?;
 
         Ok(output)
     }
 
+    /// Generate Cargo files for the given TARGETS files only, without the
+    /// workspace manifests and crate indexes that depend on all crates of the
+    /// projects.
+    pub fn generate_for_many_targets<
+        'input,
+        Manifests: IntoIterator<Item = &'input BuckManifest> + Send,
+    >(
+        &self,
+        logger: &Logger,
+        events: &EventSender,
+        many_targets: impl IntoIterator<Item = (&'input TargetsPath, Manifests)>,
+    ) -> Result<GenerationOutput, AutocargoError> {
+        self.generate_for_many_targets_impl(logger, events, many_targets)
+            .map_err(AutocargoError::generation)
+    }
+
+    fn generate_for_many_targets_impl<
+        'input,
+        Manifests: IntoIterator<Item = &'input BuckManifest> + Send,
+    >(
+        &self,
+        logger: &Logger,
+        events: &EventSender,
+        many_targets: impl IntoIterator<Item = (&'input TargetsPath, Manifests)>,
+    ) -> Result<GenerationOutput> {
+        events.emit(AutocargoEvent::PhaseStarted(Phase::Generate));
+        generate_and_combine(
+            many_targets,
+            |targets_path, manifests| self.generate_for_targets(logger, targets_path, manifests),
+            |conflicts| {
+                Error::new(OutputConflicts(
+                    conflicts
+                        .into_iter()
+                        .map(|(path, targets_paths)| OutputConflict {
+                            path,
+                            sources: targets_paths
+                                .into_iter()
+                                .map(|tp| {
+                                    let project = self
+                                        .targets_to_projects
+                                        .get(tp)
+                                        .map(|conf| conf.name().clone());
+                                    (tp.clone(), project)
+                                })
+                                .collect(),
+                        })
+                        .collect(),
+                ))
+            },
+        )
+    }
+
     /// Generate Cargo files for single TARGETS file. Multiple Cargo.toml files
     /// might be computed from a single TARGETS file, but only one TARGETS file
     /// might be the source of a Cargo.toml file.
@@ -216,12 +386,8 @@ impl<'r#gen> CargoGenerator<'r#gen> {
         targets_path: &TargetsPath,
         manifests: impl IntoIterator<Item = &'input BuckManifest>,
     ) -> Result<GenerationOutput> {
-        if self
-            .targets_to_projects
-            .get(targets_path)
-            .map(|proj| *proj.manual_cargo_toml())
-            .unwrap_or_default()
-        {
+        let maybe_conf = self.targets_to_projects.get(targets_path);
+        if maybe_conf.is_some_and(|proj| *proj.manual_cargo_toml()) {
             return Ok(GenerationOutput::default());
         }
 
@@ -235,6 +401,9 @@ impl<'r#gen> CargoGenerator<'r#gen> {
                     manifest,
                 )
             })
+            .filter(|(cargo_toml_dir, _)| {
+                !maybe_conf.is_some_and(|proj| proj.in_manual_dirs(cargo_toml_dir))
+            })
             .into_group_map();
 
         generate_and_combine(
@@ -242,20 +411,24 @@ impl<'r#gen> CargoGenerator<'r#gen> {
             |cargo_toml_dir, manifests| {
                 self.generate_for_cargo_toml(logger, targets_path, cargo_toml_dir, manifests)
             },
-            |path, ctd, other_ctd| {
+            |conflicts| {
                 anyhow!(
-                    "Path '{:?}' has been generated by both manifests generating \
-                cargo in dirs '{:?}' and '{:?}'",
-                    path,
-                    ctd,
-                    other_ctd,
+                    "Paths have been generated by multiple manifests generating cargo \
+                    in different dirs:\n{}",
+                    conflicts
+                        .iter()
+                        .map(|(path, dirs)| format!("  {path}: {}", dirs.iter().join(", ")))
+                        .join("\n"),
                 )
             },
         )
-        .with_context(|| {
-            format!(
-                "While generating cargo files for build file at {}",
-                targets_path.as_dir().as_ref().display(),
+        .with_location(|| {
+            (
+                ErrorLocation::targets_path(targets_path),
+                format!(
+                    "While generating cargo files for build file at {}",
+                    targets_path.as_dir().as_ref().display(),
+                ),
             )
         })
     }
@@ -296,7 +469,7 @@ impl<'r#gen> CargoGenerator<'r#gen> {
             )
         })?;
 
-        let cargo_manifests = {
+        let (cargo_manifests, copied_files, crates) = {
             let logger = &logger.new(o!(
                 "targets_path" => format!("{targets_path:?}"),
                 "cargo_toml_dir" => format!("{cargo_toml_dir:?}")
@@ -310,34 +483,98 @@ impl<'r#gen> CargoGenerator<'r#gen> {
                 cargo_toml_dir,
             )?;
 
+            let oss_manifest = generation_input.generate_oss_manifest(
+                logger,
+                self,
+                conf,
+                targets_path,
+                cargo_toml_dir,
+            )?;
+
+            let crates = cargo_manifest
+                .package
+                .iter()
+                .map(|package| GeneratedCrate {
+                    project: conf.name().clone(),
+                    name: package.name.clone(),
+                    cargo_toml_path: cargo_toml_path.clone(),
+                    description: package.description.clone(),
+                    rule: generation_input.generation_identifier(targets_path),
+                    rules: generation_input.rules(targets_path),
+                    oss_cargo_toml_path: oss_manifest.as_ref().map(|(path, _, _)| path.clone()),
+                })
+                .collect();
+
             let mut cargo_manifests = hashmap! { cargo_toml_path => cargo_manifest };
+            let mut copied_files = HashMap::new();
 
-            if let Some((cargo_toml_path, cargo_manifest)) = generation_input
-                .generate_oss_manifest(logger, self, conf, targets_path, cargo_toml_dir)?
-            {
+            if let Some((cargo_toml_path, cargo_manifest, files)) = oss_manifest {
                 cargo_manifests.insert(cargo_toml_path, cargo_manifest);
+                copied_files = files;
             }
 
-            cargo_manifests
+            (cargo_manifests, copied_files, crates)
         };
 
-        let additional_files =
-            generation_input.generate_additional_files(targets_path, cargo_toml_dir)?;
+        let mut additional_files =
+            generation_input.generate_additional_files(conf, targets_path, cargo_toml_dir)?;
+        additional_files.extend(copied_files);
 
         Ok(GenerationOutput {
             cargo_manifests,
             additional_files,
+            crates,
         })
     }
 
+    /// For each selected project that has crate_index configured render the
+    /// list of its generated crates into additional_files of the output.
+    fn generate_crate_indexes(
+        &self,
+        selected_projects: &SelectedProjects<'_>,
+        output: &mut GenerationOutput,
+    ) -> Result<()> {
+        for conf in selected_projects.projects() {
+            if let Some(index_path) = conf.crate_index() {
+                let content = render_crate_index(
+                    conf.name(),
+                    index_path,
+                    output
+                        .crates
+                        .iter()
+                        .filter(|krate| &krate.project == conf.name()),
+                )
+                .with_context(|| {
+                    format!("While generating crate index of project {}", conf.name())
+                })?;
+                if output
+                    .additional_files
+                    .insert(index_path.clone(), content)
+                    .is_some()
+                {
+                    bail!(
+                        "Crate index {} of project {} conflicts with another generated file",
+                        index_path,
+                        conf.name(),
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
     /// For each selected project that has workspace_config configured create a
     /// workspace section with a third-party patch section and put it in a new or
     /// already generated Cargo.toml file inside of cargo_manifest.
     fn generate_workspaces(
         &self,
+        logger: &Logger,
         selected_projects: &SelectedProjects<'_>,
+        crates: &[GeneratedCrate],
         cargo_manifests: &mut HashMap<CargoTomlPath, Manifest>,
     ) -> Result<()> {
+        let rule_workspaces = validate_rule_workspaces(cargo_manifests)?;
+
         let workspaces = selected_projects
             .projects()
             .iter()
@@ -349,6 +586,14 @@ impl<'r#gen> CargoGenerator<'r#gen> {
                          save_to_dir,
                          patch_generation,
                          patch,
+                         inherit_dependencies,
+                         inherit_package_fields,
+                         extra_members,
+                         rustfmt: _,
+                         clippy: _,
+                         nextest: _,
+                         default_members_globs,
+                         exclude_globs,
                      }| {
                         let manifests = cargo_manifests
                             .iter()
@@ -358,47 +603,119 @@ impl<'r#gen> CargoGenerator<'r#gen> {
                                     .as_ref()
                                     .strip_prefix(scrape_dir.as_ref())
                                     .ok()
-                                    .map(|member| (member, manifest))
+                                    .map(|member| (member, cargo_toml_path, manifest))
                             })
                             .collect::<Vec<_>>();
 
-                        check_packages_are_unique(manifests.iter().map(|(_, manifest)| *manifest))
+                        check_packages_are_unique(
+                            manifests.iter().map(|(_, _, manifest)| *manifest),
+                        )
+                        .with_context(|| {
+                            format!("Cannot generate Workspace including {scrape_dir:?}")
+                        })?;
+                        check_links_are_unique(
+                            manifests.iter().map(|(_, cargo_toml_path, manifest)| {
+                                (*cargo_toml_path, *manifest)
+                            }),
+                            crates,
+                        )
+                        .with_context(|| {
+                            format!("Cannot generate Workspace including {scrape_dir:?}")
+                        })?;
+
+                        let workspace_path = CargoTomlPath::new(
+                            save_to_dir
+                                .as_ref()
+                                .unwrap_or(scrape_dir)
+                                .join_to_path_in_fbcode(CargoTomlPath::filename()),
+                        )
+                        .expect(
+                            "Failed to create a CargoTomlPath for \
+                            workspace even though a proper filename was \
+                            joined to path",
+                        );
+
+                        if let Some(rule_workspace) = rule_workspaces
+                            .iter()
+                            .find(|path| workspaces_overlap(path, &workspace_path, scrape_dir))
+                        {
+                            bail!(
+                                "Workspace defined via autocargo.cargo_toml_config.workspace \
+                                in {:?} conflicts with workspace_config of project {}, \
+                                which is saved to {:?} and covers {:?}",
+                                rule_workspace,
+                                conf.name(),
+                                workspace_path,
+                                scrape_dir,
+                            );
+                        }
+
+                        let extra_members = extra_members
+                            .iter()
+                            .map(|extra_member| {
+                                self.validate_extra_member(
+                                    scrape_dir,
+                                    extra_member,
+                                    manifests.iter().map(|(_, path, _)| *path),
+                                )
+                            })
+                            .collect::<Result<Vec<_>>>()
                             .with_context(|| {
-                                format!("Cannot generate Workspace including {scrape_dir:?}")
+                                format!(
+                                    "In workspace_config.extra_members of project {}",
+                                    conf.name()
+                                )
                             })?;
 
+                        let MemberGlobs {
+                            members,
+                            default_members,
+                            excluded,
+                        } = apply_member_globs(
+                            manifests
+                                .iter()
+                                .map(|(member, _, _)| member.to_path_buf())
+                                .chain(extra_members)
+                                .collect(),
+                            default_members_globs,
+                            exclude_globs,
+                        )
+                        .with_context(|| {
+                            format!("In workspace_config of project {}", conf.name())
+                        })?;
+
+                        let member_paths: HashSet<CargoTomlPath> = manifests
+                            .iter()
+                            .filter(|(member, _, _)| !excluded.iter().any(|ex| ex == member))
+                            .map(|(_, cargo_toml_path, _)| (*cargo_toml_path).clone())
+                            .collect();
+
+                        let to_members = |members: Vec<PathBuf>| {
+                            members
+                                .into_iter()
+                                .map(|member| {
+                                    let member = prefix_for_dir.as_ref().map_or_else(
+                                        || member.to_string_lossy().into_owned(),
+                                        |prefix| {
+                                            prefix.join(&member).to_string_lossy().into_owned()
+                                        },
+                                    );
+                                    if member.is_empty() {
+                                        ".".to_owned()
+                                    } else {
+                                        member
+                                    }
+                                })
+                                .collect()
+                        };
+
                         Ok((
-                            CargoTomlPath::new(
-                                save_to_dir
-                                    .as_ref()
-                                    .unwrap_or(scrape_dir)
-                                    .join_to_path_in_fbcode(CargoTomlPath::filename()),
-                            )
-                            .expect(
-                                "Failed to create a CargoTomlPath for \
-                                workspace even though a proper filename was \
-                                joined to path",
-                            ),
+                            workspace_path,
                             Workspace {
-                                members: manifests
-                                    .into_iter()
-                                    .map(|(member, _)| {
-                                        let member = prefix_for_dir.as_ref().map_or_else(
-                                            || member.to_string_lossy().into_owned(),
-                                            |prefix| {
-                                                prefix.join(member).to_string_lossy().into_owned()
-                                            },
-                                        );
-                                        if member.is_empty() {
-                                            ".".to_owned()
-                                        } else {
-                                            member
-                                        }
-                                    })
-                                    .collect(),
-                                default_members: Vec::new(),
+                                members: to_members(members),
+                                default_members: to_members(default_members),
                                 package: None,
-                                exclude: Vec::new(),
+                                exclude: to_members(excluded),
                                 metadata: None,
                                 resolver: Some(Resolver::V2),
                                 dependencies: DepsSet::new(),
@@ -406,13 +723,45 @@ impl<'r#gen> CargoGenerator<'r#gen> {
                             },
                             self.generate_patch(patch_generation, patch.iter())
                                 .context("While generating patch for workspace")?,
+                            member_paths,
+                            *inherit_dependencies,
+                            inherit_package_fields,
+                            conf.defaults(),
                         ))
                     },
                 )
             })
             .collect::<Result<Vec<_>>>()?;
 
-        for (workspace_path, workspace, patch) in workspaces {
+        for (
+            workspace_path,
+            mut workspace,
+            patch,
+            member_paths,
+            inherit_dependencies,
+            inherit_package_fields,
+            defaults,
+        ) in workspaces
+        {
+            hoist_profiles(
+                logger,
+                &workspace_path,
+                &defaults.profile,
+                cargo_manifests,
+                &member_paths,
+            );
+            if inherit_dependencies {
+                workspace.dependencies = inherit_workspace_dependencies(workspace_members(
+                    cargo_manifests,
+                    &member_paths,
+                ));
+            }
+            workspace.package = inherit_workspace_package(
+                inherit_package_fields,
+                &defaults.package,
+                self.toolchain_rust_version.as_deref(),
+                workspace_members(cargo_manifests, &member_paths),
+            );
             let manifest = cargo_manifests.entry(workspace_path).or_default();
             manifest.workspace = Some(workspace);
             manifest.patch = patch;
@@ -421,6 +770,36 @@ impl<'r#gen> CargoGenerator<'r#gen> {
         Ok(())
     }
 
+    /// Check that the manually maintained extra member of a workspace exists
+    /// and isn't generated, return its path relative to scrape_dir.
+    fn validate_extra_member<'a>(
+        &self,
+        scrape_dir: &PathInFbcode,
+        extra_member: &PathInFbcode,
+        generated: impl IntoIterator<Item = &'a CargoTomlPath>,
+    ) -> Result<PathBuf> {
+        let cargo_toml_path = extra_member.join_to_path_in_fbcode(CargoTomlPath::filename());
+        if generated
+            .into_iter()
+            .any(|path| path.as_file() == &cargo_toml_path)
+        {
+            bail!(
+                "{} is generated by autocargo and already is a member",
+                cargo_toml_path
+            );
+        }
+        if !Path::join(self.fbcode_root.as_ref(), cargo_toml_path.as_ref()).is_file() {
+            bail!("{} doesn't exist", cargo_toml_path);
+        }
+        relative_path(extra_member.as_ref(), scrape_dir.as_ref()).ok_or_else(|| {
+            anyhow!(
+                "Couldn't construct a relative path between {} and {}",
+                scrape_dir,
+                extra_member,
+            )
+        })
+    }
+
     /// Resolve the PatchGenerationInputOrThirdParty using third_party_patches.
     pub(super) fn generate_patch<'input>(
         &self,
@@ -431,6 +810,7 @@ impl<'r#gen> CargoGenerator<'r#gen> {
             PatchGenerationMode::Empty => PatchSet::new(),
             PatchGenerationMode::ThirdPartyFull => self.third_party_patches().clone(),
         };
+        filter_patches(&mut patch_set, &patch_generation.only, true);
 
         let empty_third_party_patches = DepsSet::default();
         for (source, patches) in additional_patches {
@@ -462,56 +842,500 @@ impl<'r#gen> CargoGenerator<'r#gen> {
             }
         }
 
-        for (source, exclusions) in patch_generation.exclude.iter() {
-            if let Some(deps_set) = patch_set.get_mut(source) {
-                for name in exclusions {
-                    deps_set.remove(name);
-                }
+        filter_patches(&mut patch_set, &patch_generation.exclude, false);
+
+        Ok(patch_set)
+    }
+}
+
+/// Members of a workspace relative to its scrape_dir, split by
+/// default_members_globs and exclude_globs of the workspace_config.
+#[derive(Debug, PartialEq)]
+struct MemberGlobs {
+    members: Vec<PathBuf>,
+    default_members: Vec<PathBuf>,
+    excluded: Vec<PathBuf>,
+}
+
+/// Move the members matching exclude_globs out of the members and collect the
+/// ones matching default_members_globs, failing if any glob doesn't match a
+/// member or if an excluded member should be a default one.
+fn apply_member_globs(
+    members: Vec<PathBuf>,
+    default_members_globs: &HashSet<Pattern>,
+    exclude_globs: &HashSet<Pattern>,
+) -> Result<MemberGlobs> {
+    fn matches<'a>(globs: &'a HashSet<Pattern>, member: &Path) -> Vec<&'a Pattern> {
+        globs
+            .iter()
+            .filter(|glob| glob.matches_path(member))
+            .collect()
+    }
+    let mut unmatched: HashSet<_> = default_members_globs.iter().chain(exclude_globs).collect();
+    let mut result = MemberGlobs {
+        members: Vec::new(),
+        default_members: Vec::new(),
+        excluded: Vec::new(),
+    };
+    for member in members {
+        let default_by = matches(default_members_globs, &member);
+        let excluded_by = matches(exclude_globs, &member);
+        if !default_by.is_empty() && !excluded_by.is_empty() {
+            bail!(
+                "Member {:?} matches both default_members_globs {:?} and exclude_globs {:?}",
+                member,
+                default_by[0].as_str(),
+                excluded_by[0].as_str(),
+            );
+        }
+        for glob in default_by.iter().chain(&excluded_by) {
+            unmatched.remove(glob);
+        }
+        if !excluded_by.is_empty() {
+            result.excluded.push(member);
+        } else {
+            if !default_by.is_empty() {
+                result.default_members.push(member.clone());
             }
+            result.members.push(member);
         }
+    }
+    if !unmatched.is_empty() {
+        bail!(
+            "Globs don't match any workspace member: {}",
+            unmatched
+                .iter()
+                .map(|glob| format!("{:?}", glob.as_str()))
+                .sorted()
+                .join(", "),
+        );
+    }
+    Ok(result)
+}
 
-        Ok(patch_set)
+/// Replace names of the known registries by their index URLs in the
+/// dependencies and as sources of the patches.
+fn resolve_registries(
+    registries: &BTreeMap<String, String>,
+    deps: &mut DepsSet,
+    patches: &mut PatchSet,
+) {
+    let details = deps
+        .values_mut()
+        .chain(patches.values_mut().flat_map(|deps| deps.values_mut()))
+        .filter_map(|dep| match dep {
+            Dependency::Detailed(detail) => Some(detail),
+            Dependency::Simple(_) | Dependency::Inherited(_) => None,
+        });
+    for detail in details {
+        if let Some(index) = detail
+            .registry
+            .as_ref()
+            .and_then(|name| registries.get(name))
+        {
+            detail.registry = None;
+            detail.registry_index = Some(index.clone());
+        }
+    }
+    for (name, index) in registries {
+        if let Some(deps) = patches.remove(name) {
+            patches.entry(index.clone()).or_default().extend(deps);
+        }
+    }
+}
+
+/// Keep only the patches whose names match any of the patterns given for
+/// their source if `keep_matching` is true, otherwise remove them. Sources
+/// without patterns are left as they are.
+fn filter_patches(
+    patch_set: &mut PatchSet,
+    patterns: &HashMap<String, HashSet<Pattern>>,
+    keep_matching: bool,
+) {
+    for (source, patterns) in patterns {
+        if let Some(deps_set) = patch_set.get_mut(source) {
+            deps_set.retain(|name, _| {
+                patterns.iter().any(|pattern| pattern.matches(name)) == keep_matching
+            });
+        }
+    }
+}
+
+/// Cargo only respects [profile] of the workspace root, so remove profiles from
+/// the members and put the default profile of the project into the root,
+/// unless the root is a crate with its own profile. Members with a profile
+/// other than the default one are reported, since it was ignored anyway.
+fn hoist_profiles(
+    logger: &Logger,
+    workspace_path: &CargoTomlPath,
+    default_profile: &Profiles,
+    cargo_manifests: &mut HashMap<CargoTomlPath, Manifest>,
+    member_paths: &HashSet<CargoTomlPath>,
+) {
+    for (path, manifest) in cargo_manifests
+        .iter_mut()
+        .filter(|(path, _)| *path != workspace_path && member_paths.contains(*path))
+    {
+        let profile = std::mem::take(&mut manifest.profile);
+        if profile != Profiles::default() && &profile != default_profile {
+            warn!(
+                logger,
+                "[profile] of {} is ignored by Cargo since it is a member of workspace {}, \
+                set the profiles in the project defaults instead",
+                path.as_file(),
+                workspace_path.as_file(),
+            );
+        }
+    }
+
+    let root = cargo_manifests.entry(workspace_path.clone()).or_default();
+    if root.profile == Profiles::default() {
+        root.profile = default_profile.clone();
+    }
+}
+
+/// For each selected project that has file_manifest_dir configured list the
+/// generated files it covers with hashes of their content into
+/// additional_files of the output. Must run after all other files are
+/// generated. The hashes are of the generated content, they are computed again
+/// from the content with the manual sections preserved when writing the files.
+fn generate_file_manifests(
+    selected_projects: &SelectedProjects<'_>,
+    output: &mut GenerationOutput,
+) -> Result<()> {
+    let cargo_manifests: Vec<_> = output
+        .cargo_manifests
+        .iter()
+        .map(|(path, manifest)| (path.as_file(), manifest.to_toml_string()))
+        .collect();
+    let files: Vec<_> = cargo_manifests
+        .iter()
+        .map(|(path, content)| (*path, content.as_str()))
+        .chain(
+            output
+                .additional_files
+                .iter()
+                .map(|(path, content)| (path, content.as_str())),
+        )
+        .collect();
+    let file_manifests =
+        render_file_manifests(selected_projects.projects().iter().copied(), &files)?;
+
+    for (conf, manifest_path, content) in file_manifests {
+        if output
+            .additional_files
+            .insert(manifest_path.clone(), content)
+            .is_some()
+        {
+            bail!(
+                "File manifest {} of project {} conflicts with another generated file",
+                manifest_path,
+                conf.name(),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// If workspace_config of the project sets rustfmt, clippy or nextest then
+/// render the respective file next to the workspace Cargo.toml into additional_files.
+fn generate_tool_configs(
+    conf: &ProjectConf,
+    additional_files: &mut HashMap<PathInFbcode, String>,
+) -> Result<()> {
+    let Some(workspace) = conf.workspace_config() else {
+        return Ok(());
+    };
+    let dir = workspace
+        .save_to_dir
+        .as_ref()
+        .unwrap_or(&workspace.scrape_dir);
+    for (filename, table) in [
+        (PathInFbcode::rustfmt_filename(), &workspace.rustfmt),
+        (PathInFbcode::clippy_filename(), &workspace.clippy),
+        (PathInFbcode::nextest_filename(), &workspace.nextest),
+    ] {
+        let Some(table) = table else {
+            continue;
+        };
+        insert_generated_toml(
+            conf,
+            dir.join_to_path_in_fbcode(filename),
+            table,
+            additional_files,
+        )?;
+    }
+    Ok(())
+}
+
+/// If the project sets cargo_config then render its .cargo/config.toml into
+/// additional_files. If the crates of the project use artifact dependencies
+/// then `unstable.bindeps = true` is added to it, unless the project sets it.
+fn generate_cargo_config(
+    conf: &ProjectConf,
+    uses_bindeps: bool,
+    additional_files: &mut HashMap<PathInFbcode, String>,
+) -> Result<()> {
+    let (Some(cargo_config), Some(path)) = (conf.cargo_config(), conf.cargo_config_path()) else {
+        return Ok(());
+    };
+    let mut content = cargo_config.content.clone();
+    if uses_bindeps {
+        let Value::Table(unstable) = content
+            .entry("unstable".to_owned())
+            .or_insert_with(|| Value::Table(Default::default()))
+        else {
+            bail!(
+                "cargo_config.content.unstable of project {} must be a table",
+                conf.name()
+            );
+        };
+        unstable
+            .entry("bindeps".to_owned())
+            .or_insert(Value::Boolean(true));
+    }
+    insert_generated_toml(conf, path, &content, additional_files)
+}
+
+/// True if any crate generated for the project has an artifact dependency.
+fn uses_artifact_dependencies(conf: &ProjectConf, output: &GenerationOutput) -> bool {
+    output
+        .crates
+        .iter()
+        .filter(|krate| &krate.project == conf.name())
+        .filter_map(|krate| output.cargo_manifests.get(&krate.cargo_toml_path))
+        .flat_map(|manifest| {
+            [
+                &manifest.dependencies,
+                &manifest.dev_dependencies,
+                &manifest.build_dependencies,
+            ]
+            .into_iter()
+            .chain(manifest.target.values().flat_map(|target| {
+                [
+                    &target.dependencies,
+                    &target.dev_dependencies,
+                    &target.build_dependencies,
+                ]
+            }))
+        })
+        .flat_map(|deps| deps.values())
+        .any(|dep| {
+            dep.detail()
+                .is_some_and(|detail| detail.unstable.contains_key("artifact"))
+        })
+}
+
+/// Render the table with the generated preamble into additional_files, failing
+/// if another file is already generated at the path.
+fn insert_generated_toml(
+    conf: &ProjectConf,
+    path: PathInFbcode,
+    table: &BTreeMap<String, Value>,
+    additional_files: &mut HashMap<PathInFbcode, String>,
+) -> Result<()> {
+    let content = format!(
+        "# {GENERATED_PREAMBLE}\n\n{}",
+        toml::to_string(table).with_context(|| format!(
+            "While rendering {} of project {}",
+            path,
+            conf.name()
+        ))?
+    );
+    if additional_files.insert(path.clone(), content).is_some() {
+        bail!(
+            "{} of project {} conflicts with another generated file",
+            path,
+            conf.name(),
+        );
+    }
+    Ok(())
+}
+
+fn workspace_members<'a>(
+    cargo_manifests: &'a mut HashMap<CargoTomlPath, Manifest>,
+    member_paths: &'a HashSet<CargoTomlPath>,
+) -> impl Iterator<Item = &'a mut Manifest> {
+    cargo_manifests
+        .iter_mut()
+        .filter(|(path, _)| member_paths.contains(*path))
+        .map(|(_, manifest)| manifest)
+}
+
+/// Validate workspaces defined by rules via autocargo.cargo_toml_config.workspace,
+/// making sure that all of their members were generated. Returns paths of
+/// Cargo.toml files that define those workspaces.
+fn validate_rule_workspaces(
+    cargo_manifests: &HashMap<CargoTomlPath, Manifest>,
+) -> Result<Vec<&CargoTomlPath>> {
+    let mut rule_workspaces = Vec::new();
+    for (cargo_toml_path, manifest) in cargo_manifests {
+        let Some(workspace) = &manifest.workspace else {
+            continue;
+        };
+        for member in &workspace.members {
+            let member_dir = cargo_toml_path.as_dir().join_to_path_in_fbcode(member);
+            let is_generated = Pattern::new(&member_dir.as_ref().to_string_lossy())
+                .map(|pattern| {
+                    cargo_manifests
+                        .keys()
+                        .any(|path| pattern.matches_path(path.as_dir().as_ref()))
+                })
+                .with_context(|| format!("Invalid workspace member {member:?}"));
+            if !is_generated? {
+                bail!(
+                    "While validating autocargo.cargo_toml_config.workspace of {:?}: \
+                    no generated Cargo.toml matches member {:?}",
+                    cargo_toml_path,
+                    member
+                );
+            }
+        }
+        rule_workspaces.push(cargo_toml_path);
+    }
+    rule_workspaces.sort_unstable();
+    Ok(rule_workspaces)
+}
+
+/// True if the workspace defined by a rule in rule_workspace overlaps with the
+/// workspace of a project saved to workspace_path and covering scrape_dir,
+/// i.e. they are the same or one of them is nested in the other.
+fn workspaces_overlap(
+    rule_workspace: &CargoTomlPath,
+    workspace_path: &CargoTomlPath,
+    scrape_dir: &PathInFbcode,
+) -> bool {
+    let rule_dir = rule_workspace.as_dir().as_ref();
+    rule_workspace == workspace_path
+        || rule_dir.starts_with(scrape_dir.as_ref())
+        || scrape_dir.as_ref().starts_with(rule_dir)
+        || workspace_path.as_dir().as_ref().starts_with(rule_dir)
+}
+
+/// Validate the cargo_locks paths of projects, making sure that each of them
+/// has a generated or manually maintained Cargo.toml and that no two of them
+/// end up in the same generated workspace, as cargo would use a single
+/// Cargo.lock for the whole workspace anyway.
+fn validate_cargo_locks<'a>(
+    lock_paths: impl IntoIterator<Item = (&'a str, &'a PathInFbcode)>,
+    cargo_manifests: &HashMap<CargoTomlPath, Manifest>,
+    manual_exists: impl Fn(&CargoTomlPath) -> bool,
+) -> Result<()> {
+    let mut workspace_locks: BTreeMap<&CargoTomlPath, (&str, &PathInFbcode)> = BTreeMap::new();
+    for (project, lock_path) in lock_paths {
+        let cargo_toml_path =
+            CargoTomlPath::new(lock_path.join_to_path_in_fbcode(CargoTomlPath::filename()))?;
+        if !cargo_manifests.contains_key(&cargo_toml_path) && !manual_exists(&cargo_toml_path) {
+            bail!(
+                "cargo_lock path '{}' of project '{}' has neither a generated nor a manual {}",
+                lock_path,
+                project,
+                CargoTomlPath::filename(),
+            );
+        }
+
+        let mut workspaces = cargo_manifests
+            .iter()
+            .filter_map(|(path, manifest)| Some((path, manifest.workspace.as_ref()?)))
+            .filter(|(path, workspace)| {
+                *path == &cargo_toml_path
+                    || workspace.members.iter().any(|member| {
+                        let member_dir = path.as_dir().join_to_path_in_fbcode(member);
+                        Pattern::new(&member_dir.as_ref().to_string_lossy())
+                            .is_ok_and(|pattern| pattern.matches_path(lock_path.as_ref()))
+                    })
+            })
+            .map(|(path, _)| path);
+        let Some(workspace_path) = workspaces.next() else {
+            continue;
+        };
+        let Some((other_project, other_lock_path)) =
+            workspace_locks.insert(workspace_path, (project, lock_path))
+        else {
+            continue;
+        };
+        if other_lock_path != lock_path {
+            bail!(
+                "cargo_lock paths '{}' of project '{}' and '{}' of project '{}' are both \
+                within the workspace of {}, only one Cargo.lock is used per workspace",
+                other_lock_path,
+                other_project,
+                lock_path,
+                project,
+                workspace_path.as_file(),
+            );
+        }
     }
+    Ok(())
 }
 
 /// Given input and generation function produce GenerationOutput, check the
-/// generated paths for uniqueness, reporting with bail function if not unique,
-/// and finally combine all GenerationOutput into a single struct.
-fn generate_and_combine<TKey: Clone, TValue>(
+/// generated paths for uniqueness, reporting all paths generated for more than
+/// one key with conflict function, and finally combine all GenerationOutput
+/// into a single struct. The generation function runs in parallel for all keys,
+/// but the outputs are combined in order of the keys, so that the reported
+/// conflicts and errors don't depend on the order of the input or on timing.
+fn generate_and_combine<TKey: Clone + Ord + Send, TValue: Send>(
     input: impl IntoIterator<Item = (TKey, TValue)>,
-    mut gen_fun: impl FnMut(&TKey, TValue) -> Result<GenerationOutput>,
-    bail_fun: impl FnOnce(&Path, &TKey, &TKey) -> Error,
+    gen_fun: impl Fn(&TKey, TValue) -> Result<GenerationOutput> + Sync,
+    conflict_fun: impl FnOnce(BTreeMap<PathInFbcode, Vec<TKey>>) -> Error,
 ) -> Result<GenerationOutput> {
+    let mut input: Vec<_> = input.into_iter().collect();
+    input.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let outputs: Vec<_> = input
+        .into_par_iter()
+        .map(|(key, value)| {
+            let output = gen_fun(&key, value);
+            (key, output)
+        })
+        .collect();
+
     let mut all_cargo_manifests = HashMap::new();
     let mut all_additional_files = HashMap::new();
-    for (key, value) in input {
+    let mut all_crates = Vec::new();
+    let mut conflicts = BTreeMap::new();
+    for (key, output) in outputs {
         let GenerationOutput {
             cargo_manifests,
             additional_files,
-        } = gen_fun(&key, value)?;
+            crates,
+        } = output?;
 
-        for path in cargo_manifests.keys() {
-            if let Some((_, other_key)) = all_cargo_manifests.get(path) {
-                return Err(bail_fun(path.as_file().as_ref(), &key, other_key));
+        for (path, manifest) in cargo_manifests {
+            match all_cargo_manifests.entry(path) {
+                Entry::Occupied(entry) => {
+                    let (_, other_key): &(_, TKey) = entry.get();
+                    conflicts
+                        .entry(entry.key().as_file().clone())
+                        .or_insert_with(|| vec![other_key.clone()])
+                        .push(key.clone());
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert((manifest, key.clone()));
+                }
             }
         }
 
-        for path in additional_files.keys() {
-            if let Some((_, other_key)) = all_additional_files.get(path) {
-                return Err(bail_fun(path.as_ref(), &key, other_key));
+        for (path, content) in additional_files {
+            match all_additional_files.entry(path) {
+                Entry::Occupied(entry) => {
+                    let (_, other_key): &(_, TKey) = entry.get();
+                    conflicts
+                        .entry(entry.key().clone())
+                        .or_insert_with(|| vec![other_key.clone()])
+                        .push(key.clone());
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert((content, key.clone()));
+                }
             }
         }
 
-        all_cargo_manifests.extend(
-            cargo_manifests
-                .into_iter()
-                .map(|(path, manifest)| (path, (manifest, key.clone()))),
-        );
-        all_additional_files.extend(
-            additional_files
-                .into_iter()
-                .map(|(path, content)| (path, (content, key.clone()))),
-        );
+        all_crates.extend(crates);
+    }
+
+    if !conflicts.is_empty() {
+        return Err(conflict_fun(conflicts));
     }
 
     Ok(GenerationOutput {
@@ -523,9 +1347,159 @@ fn generate_and_combine<TKey: Clone, TValue>(
             .into_iter()
             .map(|(path, (content, _))| (path, content))
             .collect(),
+        crates: all_crates,
     })
 }
 
+/// Warn about generated crates that still depend on crates deprecated by the
+/// selected projects.
+fn report_deprecated_dependents(
+    logger: &Logger,
+    selected_projects: &SelectedProjects<'_>,
+    output: &GenerationOutput,
+) {
+    for conf in selected_projects.projects() {
+        for (name, deprecation) in conf.deprecated_crates() {
+            let dependents: Vec<_> = output
+                .crates
+                .iter()
+                .filter(|krate| {
+                    output
+                        .cargo_manifests
+                        .get(&krate.cargo_toml_path)
+                        .is_some_and(|manifest| depends_on(manifest, name))
+                })
+                .map(|krate| format!("{} ({})", krate.name, krate.cargo_toml_path.as_dir()))
+                .sorted()
+                .collect();
+            if !dependents.is_empty() {
+                warn!(
+                    logger,
+                    "Crate {} of project {} is deprecated{}, but it is still used by: {}",
+                    name,
+                    conf.name(),
+                    deprecation
+                        .removal_date
+                        .as_ref()
+                        .map(|date| format!(" and will be removed after {date}"))
+                        .unwrap_or_default(),
+                    dependents.join(", "),
+                );
+            }
+        }
+    }
+}
+
+/// Warn about generated crates whose package name is also used by other
+/// generated crates to depend on a third-party crate, as the crates of the run
+/// then disagree about what the name refers to.
+fn report_dual_source_dependencies(logger: &Logger, output: &GenerationOutput) {
+    let crates = output.crates.iter().filter_map(|krate| {
+        output
+            .cargo_manifests
+            .get(&krate.cargo_toml_path)
+            .map(|manifest| (krate, manifest))
+    });
+    for (name, dual) in dual_source_dependents(crates) {
+        let describe = |crates: Vec<&GeneratedCrate>| {
+            crates
+                .into_iter()
+                .map(|krate| format!("{} ({})", krate.name, krate.cargo_toml_path.as_dir()))
+                .join(", ")
+        };
+        warn!(
+            logger,
+            "Dependency {} resolves to a crate generated from fbcode for: {}, but to a \
+            third-party crate for: {}",
+            name,
+            describe(dual.fbcode),
+            describe(dual.third_party),
+        );
+    }
+}
+
+/// Dependents of a package name that resolves to different sources.
+struct DualSourceDependents<'a> {
+    fbcode: Vec<&'a GeneratedCrate>,
+    third_party: Vec<&'a GeneratedCrate>,
+}
+
+/// For each package name of the given crates that other crates depend on as a
+/// third-party crate, the crates depending on it via path and via the
+/// third-party crate, in the input order.
+fn dual_source_dependents<'a>(
+    crates: impl IntoIterator<Item = (&'a GeneratedCrate, &'a Manifest)>,
+) -> BTreeMap<&'a str, DualSourceDependents<'a>> {
+    let crates: Vec<_> = crates.into_iter().collect();
+    let mut result = BTreeMap::new();
+    for (krate, _) in &crates {
+        let name = krate.name.as_str();
+        let third_party: Vec<_> = crates
+            .iter()
+            .filter(|(_, manifest)| third_party_dependencies(manifest).any(|dep| dep == name))
+            .map(|(krate, _)| *krate)
+            .collect();
+        if third_party.is_empty() {
+            continue;
+        }
+        let fbcode = crates
+            .iter()
+            .filter(|(_, manifest)| depends_on(manifest, name))
+            .map(|(krate, _)| *krate)
+            .collect();
+        result.insert(
+            name,
+            DualSourceDependents {
+                fbcode,
+                third_party,
+            },
+        );
+    }
+    result
+}
+
+/// True if the manifest has a path dependency on the given package.
+fn depends_on(manifest: &Manifest, package: &str) -> bool {
+    path_dependencies(manifest).any(|dep| dep == package)
+}
+
+/// Package names of all path dependencies of the manifest, in all sections.
+pub(crate) fn path_dependencies(manifest: &Manifest) -> impl Iterator<Item = &str> {
+    all_dependencies(manifest)
+        .filter(|(_, dep)| dep.detail().is_some_and(|detail| detail.path.is_some()))
+        .map(|(name, dep)| dep.package().unwrap_or(name))
+}
+
+/// Package names of all registry dependencies of the manifest, in all
+/// sections.
+fn third_party_dependencies(manifest: &Manifest) -> impl Iterator<Item = &str> {
+    all_dependencies(manifest)
+        .filter(|(_, dep)| match dep {
+            Dependency::Simple(_) => true,
+            Dependency::Detailed(detail) => detail.path.is_none() && detail.git.is_none(),
+            Dependency::Inherited(_) => false,
+        })
+        .map(|(name, dep)| dep.package().unwrap_or(name))
+}
+
+/// All dependencies of the manifest, in all sections.
+fn all_dependencies(manifest: &Manifest) -> impl Iterator<Item = (&String, &Dependency)> {
+    [
+        &manifest.dependencies,
+        &manifest.dev_dependencies,
+        &manifest.build_dependencies,
+    ]
+    .into_iter()
+    .chain(manifest.target.values().flat_map(|target| {
+        [
+            &target.dependencies,
+            &target.dev_dependencies,
+            &target.build_dependencies,
+        ]
+    }))
+    .flat_map(|deps| deps.iter())
+}
+
 fn check_packages_are_unique<'a>(
     manifests: impl IntoIterator<Item = &'a Manifest>,
 ) -> Result<(), Error> {
@@ -543,3 +1517,630 @@ fn check_packages_are_unique<'a>(
 
     Ok(())
 }
+
+/// Check that no two packages of a workspace have the same links value, which
+/// Cargo rejects. The error lists the Buck rules, and so the build files, of
+/// the offending packages.
+fn check_links_are_unique<'a>(
+    manifests: impl IntoIterator<Item = (&'a CargoTomlPath, &'a Manifest)>,
+    crates: &[GeneratedCrate],
+) -> Result<()> {
+    let mut by_links: BTreeMap<&str, Vec<&CargoTomlPath>> = BTreeMap::new();
+    for (cargo_toml_path, manifest) in manifests {
+        if let Some(links) = manifest
+            .package
+            .as_ref()
+            .and_then(|package| package.links.as_deref())
+        {
+            by_links.entry(links).or_default().push(cargo_toml_path);
+        }
+    }
+
+    let conflicts: Vec<_> = by_links
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .map(|(links, paths)| {
+            let sources = paths
+                .into_iter()
+                .map(|path| {
+                    let rules = crates
+                        .iter()
+                        .filter(|krate| &krate.cargo_toml_path == path)
+                        .flat_map(|krate| krate.rules.iter())
+                        .join(", ");
+                    if rules.is_empty() {
+                        path.as_file().to_string()
+                    } else {
+                        rules
+                    }
+                })
+                .sorted()
+                .join("; ");
+            format!("links = {links:?} is set by packages generated from {sources}")
+        })
+        .collect();
+    if !conflicts.is_empty() {
+        bail!(
+            "Packages of a workspace must not link the same native library:\n  {}",
+            conflicts.join("\n  ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use assert_matches::assert_matches;
+    use maplit::hashset;
+
+    use super::*;
+    use crate::cargo_manifest::Package;
+    use crate::cargo_manifest::empty_package;
+
+    fn cargo_path(dir: &str) -> CargoTomlPath {
+        CargoTomlPath::new(PathInFbcode::new_mock(dir).join_to_path_in_fbcode("Cargo.toml"))
+            .unwrap()
+    }
+
+    fn workspace_manifest(members: &[&str]) -> Manifest {
+        Manifest {
+            workspace: Some(Workspace {
+                members: members.iter().map(|m| (*m).to_owned()).collect(),
+                default_members: Vec::new(),
+                package: None,
+                exclude: Vec::new(),
+                metadata: None,
+                resolver: None,
+                dependencies: DepsSet::new(),
+                lints: BTreeMap::new(),
+            }),
+            ..Manifest::default()
+        }
+    }
+
+    #[test]
+    fn validate_rule_workspaces_test() {
+        let manifests = hashmap! {
+            cargo_path("a") => workspace_manifest(&[".", "b", "crates/*"]),
+            cargo_path("a/b") => Manifest::default(),
+            cargo_path("a/crates/c") => Manifest::default(),
+        };
+        assert_eq!(
+            validate_rule_workspaces(&manifests).unwrap(),
+            vec![&cargo_path("a")]
+        );
+
+        let manifests = hashmap! {
+            cargo_path("a") => workspace_manifest(&["b", "../d"]),
+            cargo_path("a/b") => Manifest::default(),
+        };
+        assert_matches!(validate_rule_workspaces(&manifests), Err(err) => {
+            assert_eq!(
+                format!("{:#}", err),
+                "While validating autocargo.cargo_toml_config.workspace of \
+                CargoTomlPath { file: PathInFbcode(\"a/Cargo.toml\"), dir: PathInFbcode(\"a\") }: \
+                no generated Cargo.toml matches member \"../d\""
+            );
+        });
+    }
+
+    #[test]
+    fn workspaces_overlap_test() {
+        let overlap = |rule: &str, workspace: &str, scrape_dir: &str| {
+            workspaces_overlap(
+                &cargo_path(rule),
+                &cargo_path(workspace),
+                &PathInFbcode::new_mock(scrape_dir),
+            )
+        };
+        assert!(overlap("a", "a", "a"));
+        assert!(overlap("a/b", "a", "a"));
+        assert!(overlap("a", "a/b", "a/b"));
+        assert!(overlap("a", "c", "a/b"));
+        assert!(overlap("a", "a/b", "c"));
+        assert!(!overlap("a", "c", "c"));
+        assert!(!overlap("a/b", "a/c", "a/c"));
+        assert!(!overlap("ab", "a", "a"));
+    }
+
+    #[test]
+    fn validate_cargo_locks_test() {
+        let manifests = hashmap! {
+            cargo_path("a") => workspace_manifest(&["b", "crates/*"]),
+            cargo_path("a/b") => Manifest::default(),
+            cargo_path("a/crates/c") => Manifest::default(),
+            cargo_path("d") => Manifest::default(),
+        };
+        let manual = |path: &CargoTomlPath| path == &cargo_path("manual");
+        let lock = |dir: &str| PathInFbcode::new_mock(dir);
+        let (a, b, c, d, e, m) = (
+            lock("a"),
+            lock("a/b"),
+            lock("a/crates/c"),
+            lock("d"),
+            lock("e"),
+            lock("manual"),
+        );
+
+        validate_cargo_locks(
+            [("p", &a), ("q", &a), ("p", &d), ("p", &m)],
+            &manifests,
+            manual,
+        )
+        .unwrap();
+
+        assert_matches!(
+            validate_cargo_locks([("p", &e)], &manifests, manual),
+            Err(err) => assert!(err.to_string().contains("'e' of project 'p' has neither"))
+        );
+        assert_matches!(
+            validate_cargo_locks([("p", &a), ("q", &c)], &manifests, manual),
+            Err(err) => assert!(err.to_string().contains("are both within the workspace"))
+        );
+        assert_matches!(
+            validate_cargo_locks([("p", &b), ("p", &c)], &manifests, manual),
+            Err(err) => assert!(err.to_string().contains("are both within the workspace"))
+        );
+    }
+
+    #[test]
+    fn depends_on_test() {
+        let path_dep = |path: &str, package: Option<&str>| {
+            Dependency::Detailed(Box::new(DependencyDetail {
+                path: Some(path.to_owned()),
+                package: package.map(|p| p.to_owned()),
+                ..DependencyDetail::default()
+            }))
+        };
+        let manifest = Manifest {
+            dependencies: DepsSet::from([
+                ("foo".to_owned(), path_dep("../foo", None)),
+                ("renamed".to_owned(), path_dep("../bar", Some("bar"))),
+                ("baz".to_owned(), Dependency::Simple("1.0".to_owned())),
+            ]),
+            ..Manifest::default()
+        };
+        assert!(depends_on(&manifest, "foo"));
+        assert!(depends_on(&manifest, "bar"));
+        assert!(!depends_on(&manifest, "renamed"));
+        assert!(!depends_on(&manifest, "baz"));
+    }
+
+    #[test]
+    fn filter_patches_test() {
+        let patch_set = || {
+            PatchSet::from([
+                (
+                    "crates-io".to_owned(),
+                    ["foo", "bar-a", "bar-b"]
+                        .map(|name| (name.to_owned(), Dependency::Simple("1".to_owned())))
+                        .into(),
+                ),
+                (
+                    "git".to_owned(),
+                    DepsSet::from([("bar-c".to_owned(), Dependency::Simple("1".to_owned()))]),
+                ),
+            ])
+        };
+        let patterns = HashMap::from([(
+            "crates-io".to_owned(),
+            HashSet::from([Pattern::new("bar-*").unwrap()]),
+        )]);
+        let names = |patch_set: &PatchSet| {
+            patch_set
+                .iter()
+                .flat_map(|(source, deps)| deps.keys().map(move |name| format!("{source}.{name}")))
+                .collect::<Vec<_>>()
+        };
+
+        let mut only = patch_set();
+        filter_patches(&mut only, &patterns, true);
+        assert_eq!(
+            names(&only),
+            ["crates-io.bar-a", "crates-io.bar-b", "git.bar-c"]
+        );
+
+        let mut excluded = patch_set();
+        filter_patches(&mut excluded, &patterns, false);
+        assert_eq!(names(&excluded), ["crates-io.foo", "git.bar-c"]);
+    }
+
+    #[test]
+    fn resolve_registries_test() {
+        let registry_dep = |registry: &str| {
+            Dependency::Detailed(Box::new(DependencyDetail {
+                version: Some("1".to_owned()),
+                registry: Some(registry.to_owned()),
+                ..DependencyDetail::default()
+            }))
+        };
+        let registries = BTreeMap::from([(
+            "mirror".to_owned(),
+            "sparse+https://mirror.example.com/".to_owned(),
+        )]);
+        let mut deps = DepsSet::from([
+            ("foo".to_owned(), registry_dep("mirror")),
+            ("bar".to_owned(), registry_dep("other")),
+        ]);
+        let mut patches = PatchSet::from([
+            (
+                "mirror".to_owned(),
+                DepsSet::from([("baz".to_owned(), registry_dep("mirror"))]),
+            ),
+            ("crates-io".to_owned(), DepsSet::new()),
+        ]);
+
+        resolve_registries(&registries, &mut deps, &mut patches);
+
+        let registry_of = |dep: &Dependency| {
+            let detail = dep.detail().unwrap();
+            (detail.registry.clone(), detail.registry_index.clone())
+        };
+        let index = Some("sparse+https://mirror.example.com/".to_owned());
+        assert_eq!(registry_of(&deps["foo"]), (None, index.clone()));
+        assert_eq!(registry_of(&deps["bar"]), (Some("other".to_owned()), None));
+        assert_eq!(
+            patches.keys().collect::<Vec<_>>(),
+            ["crates-io", "sparse+https://mirror.example.com/"]
+        );
+        assert_eq!(
+            registry_of(&patches["sparse+https://mirror.example.com/"]["baz"]),
+            (None, index)
+        );
+    }
+
+    #[test]
+    fn validate_extra_member_test() {
+        let dir = tempfile::tempdir().unwrap();
+        for member in ["proj/manual", "other/manual"] {
+            std::fs::create_dir_all(dir.path().join(member)).unwrap();
+            std::fs::write(dir.path().join(member).join("Cargo.toml"), "").unwrap();
+        }
+        let cell_dependencies = DepsSet::new();
+        let generator = CargoGenerator {
+            third_party_crates: DepsSet::new(),
+            third_party_patches: PatchSet::new(),
+            cell_dependencies: &cell_dependencies,
+            targets_to_projects: HashMap::new(),
+            fbcode_root: FbcodeRoot::new_mock(dir.path()),
+            stamp: None,
+            toolchain_rust_version: None,
+        };
+        let scrape_dir = PathInFbcode::new_mock("proj");
+        let generated = [cargo_path("proj/lib")];
+        let validate = |member: &str| {
+            generator.validate_extra_member(
+                &scrape_dir,
+                &PathInFbcode::new_mock(member),
+                &generated,
+            )
+        };
+
+        assert_eq!(validate("proj/manual").unwrap(), PathBuf::from("manual"));
+        assert_eq!(
+            validate("other/manual").unwrap(),
+            PathBuf::from("../other/manual")
+        );
+        assert_eq!(
+            validate("proj/lib").unwrap_err().to_string(),
+            "proj/lib/Cargo.toml is generated by autocargo and already is a member"
+        );
+        assert_eq!(
+            validate("proj/missing").unwrap_err().to_string(),
+            "proj/missing/Cargo.toml doesn't exist"
+        );
+    }
+
+    #[test]
+    fn apply_member_globs_test() {
+        let globs = |globs: &[&str]| -> HashSet<Pattern> {
+            globs.iter().map(|g| Pattern::new(g).unwrap()).collect()
+        };
+        let paths = |paths: &[&str]| -> Vec<PathBuf> { paths.iter().map(PathBuf::from).collect() };
+        let members = || paths(&["", "app", "lib/a", "lib/b", "fixtures/x"]);
+
+        assert_eq!(
+            apply_member_globs(
+                members(),
+                &globs(&["app", "lib/*"]),
+                &globs(&["fixtures/*"])
+            )
+            .unwrap(),
+            MemberGlobs {
+                members: paths(&["", "app", "lib/a", "lib/b"]),
+                default_members: paths(&["app", "lib/a", "lib/b"]),
+                excluded: paths(&["fixtures/x"]),
+            }
+        );
+        assert_eq!(
+            apply_member_globs(members(), &HashSet::new(), &HashSet::new()).unwrap(),
+            MemberGlobs {
+                members: members(),
+                default_members: Vec::new(),
+                excluded: Vec::new(),
+            }
+        );
+        assert!(
+            apply_member_globs(members(), &globs(&["bin/*"]), &HashSet::new())
+                .unwrap_err()
+                .to_string()
+                .contains("\"bin/*\"")
+        );
+        assert!(
+            apply_member_globs(members(), &globs(&["lib/*"]), &globs(&["lib/b"]))
+                .unwrap_err()
+                .to_string()
+                .contains("matches both")
+        );
+    }
+
+    #[test]
+    fn generate_and_combine_conflicts_test() {
+        let output = |paths: &[&str]| GenerationOutput {
+            additional_files: paths
+                .iter()
+                .map(|path| (PathInFbcode::new_mock(path), String::new()))
+                .collect(),
+            ..GenerationOutput::default()
+        };
+
+        let combine = |input: Vec<(&'static str, GenerationOutput)>| {
+            generate_and_combine(
+                input,
+                |_, output| Ok(output),
+                |conflicts| {
+                    anyhow!(
+                        "{}",
+                        conflicts
+                            .iter()
+                            .map(|(path, keys)| format!("{path}: {}", keys.join(",")))
+                            .join("; ")
+                    )
+                },
+            )
+            .err()
+            .unwrap()
+            .to_string()
+        };
+
+        assert_eq!(
+            combine(vec![
+                ("a", output(&["x/BUILD", "y/BUILD"])),
+                ("b", output(&["x/BUILD"])),
+                ("c", output(&["y/BUILD", "z/BUILD"])),
+            ]),
+            "x/BUILD: a,b; y/BUILD: a,c"
+        );
+        // The conflicts are reported in order of the keys, whatever the order of
+        // the input is.
+        assert_eq!(
+            combine(vec![
+                ("c", output(&["y/BUILD", "z/BUILD"])),
+                ("b", output(&["x/BUILD"])),
+                ("a", output(&["x/BUILD", "y/BUILD"])),
+            ]),
+            "x/BUILD: a,b; y/BUILD: a,c"
+        );
+    }
+
+    #[test]
+    fn hoist_profiles_test() {
+        let logger = Logger::root(slog::Discard, o!());
+        let profiles = |opt_level: &str| Profiles {
+            release: Some(toml::from_str(&format!("opt-level = {opt_level:?}")).unwrap()),
+            ..Profiles::default()
+        };
+        let with_profile = |profile: Profiles| Manifest {
+            profile,
+            ..Manifest::default()
+        };
+        let mut manifests = hashmap! {
+            cargo_path("a/b") => with_profile(profiles("3")),
+            cargo_path("a/c") => with_profile(profiles("s")),
+            cargo_path("d") => with_profile(profiles("s")),
+        };
+        let members = hashset! { cargo_path("a/b"), cargo_path("a/c") };
+
+        hoist_profiles(
+            &logger,
+            &cargo_path("a"),
+            &profiles("3"),
+            &mut manifests,
+            &members,
+        );
+        assert_eq!(manifests[&cargo_path("a")].profile, profiles("3"));
+        assert_eq!(manifests[&cargo_path("a/b")].profile, Profiles::default());
+        assert_eq!(manifests[&cargo_path("a/c")].profile, Profiles::default());
+        assert_eq!(manifests[&cargo_path("d")].profile, profiles("s"));
+
+        manifests.insert(cargo_path("e"), with_profile(profiles("1")));
+        hoist_profiles(
+            &logger,
+            &cargo_path("e"),
+            &profiles("3"),
+            &mut manifests,
+            &hashset! { cargo_path("e"), cargo_path("d") },
+        );
+        assert_eq!(manifests[&cargo_path("e")].profile, profiles("1"));
+        assert_eq!(manifests[&cargo_path("d")].profile, Profiles::default());
+    }
+
+    #[test]
+    fn generate_tool_configs_test() {
+        let conf: ProjectConf = toml::from_str(
+            r#"
+            name = "proj"
+            oncall = "oncall"
+            roots = ["proj"]
+
+            [workspace_config]
+            scrape_dir = "proj"
+            save_to_dir = "proj/public"
+
+            [workspace_config.rustfmt]
+            edition = "2021"
+            imports_granularity = "Item"
+
+            [workspace_config.nextest.profile.default]
+            slow-timeout = "60s"
+            "#,
+        )
+        .unwrap();
+        let mut additional_files = HashMap::new();
+        generate_tool_configs(&conf, &mut additional_files).unwrap();
+        assert_eq!(
+            additional_files,
+            hashmap! {
+                PathInFbcode::new_mock("proj/public/rustfmt.toml") =>
+                    "# \x40generated by autocargo\n\n\
+                    edition = \"2021\"\n\
+                    imports_granularity = \"Item\"\n"
+                        .to_owned(),
+                PathInFbcode::new_mock("proj/public/.config/nextest.toml") =>
+                    "# \x40generated by autocargo\n\n\
+                    [profile.default]\n\
+                    slow-timeout = \"60s\"\n"
+                        .to_owned(),
+            }
+        );
+        assert!(generate_tool_configs(&conf, &mut additional_files).is_err());
+    }
+
+    #[test]
+    fn generate_cargo_config_test() {
+        let conf: ProjectConf = toml::from_str(
+            r#"
+            name = "proj"
+            oncall = "oncall"
+            roots = ["proj"]
+
+            [workspace_config]
+            scrape_dir = "proj"
+
+            [cargo_config.content.net]
+            git-fetch-with-cli = true
+            "#,
+        )
+        .unwrap();
+        let mut additional_files = HashMap::new();
+        generate_cargo_config(&conf, false, &mut additional_files).unwrap();
+        assert_eq!(
+            additional_files,
+            hashmap! {
+                PathInFbcode::new_mock("proj/.cargo/config.toml") =>
+                    "# \x40generated by autocargo\n\n\
+                    [net]\n\
+                    git-fetch-with-cli = true\n"
+                        .to_owned(),
+            }
+        );
+
+        let mut additional_files = HashMap::new();
+        generate_cargo_config(&conf, true, &mut additional_files).unwrap();
+        assert_eq!(
+            additional_files[&PathInFbcode::new_mock("proj/.cargo/config.toml")],
+            "# \x40generated by autocargo\n\n\
+            [net]\n\
+            git-fetch-with-cli = true\n\n\
+            [unstable]\n\
+            bindeps = true\n"
+        );
+    }
+
+    #[test]
+    fn dual_source_dependents_test() {
+        let krate = |name: &str| GeneratedCrate {
+            project: "proj".to_owned(),
+            name: name.to_owned(),
+            cargo_toml_path: cargo_path(name),
+            description: None,
+            rule: format!("//{name}:{name}"),
+            rules: vec![format!("//{name}:{name}")],
+            oss_cargo_toml_path: None,
+        };
+        let manifest = |deps: &[(&str, Dependency)]| Manifest {
+            dependencies: deps
+                .iter()
+                .map(|(name, dep)| ((*name).to_owned(), dep.clone()))
+                .collect(),
+            ..Manifest::default()
+        };
+        let path = |path: &str| {
+            Dependency::Detailed(Box::new(DependencyDetail {
+                path: Some(path.to_owned()),
+                ..DependencyDetail::default()
+            }))
+        };
+        let (foo, bar, biz) = (krate("foo"), krate("bar"), krate("biz"));
+        let foo_manifest = manifest(&[]);
+        let bar_manifest = manifest(&[("foo", path("../foo"))]);
+        let biz_manifest = manifest(&[
+            ("foo", Dependency::Simple("1".to_owned())),
+            ("bar", path("../bar")),
+        ]);
+
+        let dual = dual_source_dependents([
+            (&foo, &foo_manifest),
+            (&bar, &bar_manifest),
+            (&biz, &biz_manifest),
+        ]);
+        assert_eq!(dual.keys().collect::<Vec<_>>(), vec![&"foo"]);
+        let names = |crates: &[&GeneratedCrate]| {
+            crates
+                .iter()
+                .map(|krate| krate.name.clone())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&dual["foo"].fbcode), vec!["bar"]);
+        assert_eq!(names(&dual["foo"].third_party), vec!["biz"]);
+    }
+
+    #[test]
+    fn check_links_are_unique_test() {
+        let krate = |name: &str| GeneratedCrate {
+            project: "proj".to_owned(),
+            name: name.to_owned(),
+            cargo_toml_path: cargo_path(name),
+            description: None,
+            rule: format!("//{name}:{name}"),
+            rules: vec![format!("//{name}:{name}")],
+            oss_cargo_toml_path: None,
+        };
+        let manifest = |links: Option<&str>| Manifest {
+            package: Some(Package {
+                links: links.map(|links| links.to_owned()),
+                ..empty_package()
+            }),
+            ..Manifest::default()
+        };
+        let crates = vec![krate("foo"), krate("bar"), krate("biz")];
+        let (foo, bar, biz, fiz) = (
+            manifest(Some("z")),
+            manifest(Some("z")),
+            manifest(Some("ssl")),
+            manifest(None),
+        );
+        let (foo_path, bar_path, biz_path, fiz_path) = (
+            cargo_path("foo"),
+            cargo_path("bar"),
+            cargo_path("biz"),
+            cargo_path("fiz"),
+        );
+
+        check_links_are_unique(
+            [(&foo_path, &foo), (&biz_path, &biz), (&fiz_path, &fiz)],
+            &crates,
+        )
+        .unwrap();
+        let err = check_links_are_unique(
+            [(&foo_path, &foo), (&bar_path, &bar), (&biz_path, &biz)],
+            &crates,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Packages of a workspace must not link the same native library:\n  \
+            links = \"z\" is set by packages generated from //bar:bar; //foo:foo"
+        );
+    }
+}
@@ -0,0 +1,344 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use anyhow::bail;
+
+/// Line that starts a section of a generated TOML file that is maintained by
+/// hand and kept verbatim across regenerations.
+pub const MANUAL_SECTION_BEGIN: &str = "# BEGIN MANUAL SECTION";
+
+/// Line that ends a section started by [MANUAL_SECTION_BEGIN].
+pub const MANUAL_SECTION_END: &str = "# END MANUAL SECTION";
+
+/// Line that starts a region of a generated TOML file kept verbatim across
+/// regenerations, e.g. for a temporary hand tweak during a migration. Such a
+/// region is a manual section like the one started by [MANUAL_SECTION_BEGIN].
+pub const KEEP_REGION_BEGIN: &str = "# autocargo:keep begin";
+
+/// Line that ends a region started by [KEEP_REGION_BEGIN].
+pub const KEEP_REGION_END: &str = "# autocargo:keep end";
+
+/// Pairs of lines that start and end a manual section.
+const FENCES: [(&str, &str); 2] = [
+    (MANUAL_SECTION_BEGIN, MANUAL_SECTION_END),
+    (KEEP_REGION_BEGIN, KEEP_REGION_END),
+];
+
+/// Manual section of an existing file together with the table it was in.
+struct ManualSection<'a> {
+    /// Header of the table the section was in and how many tables with the
+    /// same header preceded it, None if it was before the first table.
+    anchor: Option<(&'a str, usize)>,
+    lines: Vec<&'a str>,
+}
+
+/// Copy the manual sections of the existing content of a generated TOML file
+/// into its newly generated content. Each section is put at the end of the
+/// same table it was in, or at the end of the file under the header of that
+/// table if the table is no longer generated. Returns the generated content
+/// unchanged if the existing one has no manual sections.
+pub fn preserve_manual_sections(existing: &str, generated: &str) -> Result<String> {
+    let sections = parse_manual_sections(existing)?;
+    if sections.is_empty() {
+        return Ok(generated.to_owned());
+    }
+
+    let generated_lines: Vec<&str> = generated.lines().collect();
+    // Insertion points are computed on the generated lines only, so sections
+    // sharing a table keep their order.
+    let mut inserted: BTreeMap<usize, Vec<&str>> = BTreeMap::new();
+    let mut appended = Vec::new();
+    for section in &sections {
+        match insertion_point(&generated_lines, section.anchor) {
+            Some(index) => inserted
+                .entry(index)
+                .or_default()
+                .extend(section.lines.iter().copied()),
+            None => appended.push(section),
+        }
+    }
+
+    let mut lines = Vec::new();
+    for index in 0..=generated_lines.len() {
+        if let Some(section_lines) = inserted.remove(&index) {
+            lines.extend(section_lines);
+        }
+        lines.extend(generated_lines.get(index).copied());
+    }
+
+    let mut current_anchor = None;
+    for section in appended {
+        if section.anchor != current_anchor {
+            if let Some((header, _)) = section.anchor {
+                lines.extend(["", header]);
+            }
+            current_anchor = section.anchor;
+        }
+        lines.extend(section.lines.iter().copied());
+    }
+
+    let mut content = lines.join("\n");
+    content.push('\n');
+    Ok(content)
+}
+
+fn parse_manual_sections(content: &str) -> Result<Vec<ManualSection<'_>>> {
+    let mut sections = Vec::new();
+    // The section being parsed together with the line that must end it.
+    let mut current: Option<(ManualSection<'_>, &str)> = None;
+    let mut anchor = None;
+    let mut seen_headers = Vec::new();
+
+    for (index, line) in content.lines().enumerate() {
+        let trimmed = line.trim();
+        let begin = FENCES.iter().find(|(begin, _)| *begin == trimmed);
+        let is_end = FENCES.iter().any(|(_, end)| *end == trimmed);
+        match &mut current {
+            Some((section, end)) => {
+                section.lines.push(line);
+                if begin.is_some() {
+                    bail!(
+                        "Line {}: manual section started inside another manual section",
+                        index + 1
+                    );
+                }
+                if trimmed == *end {
+                    sections.extend(current.take().map(|(section, _)| section));
+                } else if is_end {
+                    bail!(
+                        "Line {}: manual section must be ended with '{}'",
+                        index + 1,
+                        end
+                    );
+                }
+            }
+            None => {
+                if let Some((_, end)) = begin {
+                    current = Some((
+                        ManualSection {
+                            anchor,
+                            lines: vec![line],
+                        },
+                        end,
+                    ));
+                } else if is_end {
+                    bail!(
+                        "Line {}: manual section ended without being started",
+                        index + 1
+                    );
+                } else if is_table_header(trimmed) {
+                    let count = seen_headers.iter().filter(|h| **h == trimmed).count();
+                    seen_headers.push(trimmed);
+                    anchor = Some((trimmed, count));
+                }
+            }
+        }
+    }
+
+    if let Some((_, end)) = current {
+        bail!("Manual section is not ended with '{end}'");
+    }
+    Ok(sections)
+}
+
+/// Index of the line before which the section with the given anchor should
+/// be inserted, None if the table of the anchor is not in the lines.
+fn insertion_point(lines: &[&str], anchor: Option<(&str, usize)>) -> Option<usize> {
+    let start = match anchor {
+        None => 0,
+        Some((header, count)) => {
+            lines
+                .iter()
+                .enumerate()
+                .filter(|(_, line)| line.trim() == header)
+                .nth(count)?
+                .0
+                + 1
+        }
+    };
+    let mut end = lines[start..]
+        .iter()
+        .position(|line| is_table_header(line.trim()))
+        .map_or(lines.len(), |position| start + position);
+    // Keep the blank lines that separate the table from the next one.
+    while end > start && lines[end - 1].trim().is_empty() {
+        end -= 1;
+    }
+    Some(end)
+}
+
+fn is_table_header(line: &str) -> bool {
+    line.starts_with('[') && line.ends_with(']') && !line.contains('=')
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn preserve_manual_sections_test() {
+        let generated = "# generated\n\
+            \n\
+            [package]\n\
+            name = \"foo\"\n\
+            \n\
+            [[bin]]\n\
+            name = \"a\"\n\
+            \n\
+            [[bin]]\n\
+            name = \"b\"\n\
+            \n\
+            [dependencies]\n\
+            anyhow = \"1\"\n";
+
+        assert_eq!(
+            preserve_manual_sections("[package]\nname = \"old\"\n", generated).unwrap(),
+            generated
+        );
+
+        let existing = "# generated\n\
+            # BEGIN MANUAL SECTION\n\
+            cargo-features = [\"foo\"]\n\
+            # END MANUAL SECTION\n\
+            \n\
+            [package]\n\
+            name = \"foo\"\n\
+            \n\
+            [[bin]]\n\
+            name = \"a\"\n\
+            \n\
+            [[bin]]\n\
+            name = \"b\"\n\
+            # BEGIN MANUAL SECTION\n\
+            test = false\n\
+            # END MANUAL SECTION\n\
+            \n\
+            [package.metadata.tool]\n\
+            # BEGIN MANUAL SECTION\n\
+            key = 1\n\
+            # END MANUAL SECTION\n";
+        assert_eq!(
+            preserve_manual_sections(existing, generated).unwrap(),
+            "# generated\n\
+            # BEGIN MANUAL SECTION\n\
+            cargo-features = [\"foo\"]\n\
+            # END MANUAL SECTION\n\
+            \n\
+            [package]\n\
+            name = \"foo\"\n\
+            \n\
+            [[bin]]\n\
+            name = \"a\"\n\
+            \n\
+            [[bin]]\n\
+            name = \"b\"\n\
+            # BEGIN MANUAL SECTION\n\
+            test = false\n\
+            # END MANUAL SECTION\n\
+            \n\
+            [dependencies]\n\
+            anyhow = \"1\"\n\
+            \n\
+            [package.metadata.tool]\n\
+            # BEGIN MANUAL SECTION\n\
+            key = 1\n\
+            # END MANUAL SECTION\n"
+        );
+
+        assert!(preserve_manual_sections("# BEGIN MANUAL SECTION\n", generated).is_err());
+        assert!(preserve_manual_sections("# END MANUAL SECTION\n", generated).is_err());
+    }
+
+    #[test]
+    fn preserve_sections_of_same_table_test() {
+        let generated = "[package]\n\
+            name = \"foo\"\n\
+            \n\
+            [dependencies]\n\
+            anyhow = \"1\"\n\
+            serde = \"1\"\n";
+        let existing = "[package]\n\
+            name = \"foo\"\n\
+            \n\
+            [dependencies]\n\
+            # BEGIN MANUAL SECTION\n\
+            first = \"1\"\n\
+            # END MANUAL SECTION\n\
+            anyhow = \"1\"\n\
+            # BEGIN MANUAL SECTION\n\
+            second = \"1\"\n\
+            # END MANUAL SECTION\n\
+            serde = \"1\"\n";
+        let expected = "[package]\n\
+            name = \"foo\"\n\
+            \n\
+            [dependencies]\n\
+            anyhow = \"1\"\n\
+            serde = \"1\"\n\
+            # BEGIN MANUAL SECTION\n\
+            first = \"1\"\n\
+            # END MANUAL SECTION\n\
+            # BEGIN MANUAL SECTION\n\
+            second = \"1\"\n\
+            # END MANUAL SECTION\n";
+
+        let regenerated = preserve_manual_sections(existing, generated).unwrap();
+        assert_eq!(regenerated, expected);
+        // Regenerating again doesn't change the file.
+        assert_eq!(
+            preserve_manual_sections(&regenerated, generated).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn preserve_keep_regions_test() {
+        let generated = "[package]\n\
+            name = \"foo\"\n\
+            \n\
+            [dependencies]\n\
+            anyhow = \"1\"\n";
+        let existing = "[package]\n\
+            name = \"foo\"\n\
+            \n\
+            [dependencies]\n\
+            anyhow = \"1\"\n\
+            # autocargo:keep begin\n\
+            # BEGIN MANUAL SECTION is just a comment here\n\
+            old-api = { path = \"../old-api\" }\n\
+            # autocargo:keep end\n";
+        assert_eq!(
+            preserve_manual_sections(existing, generated).unwrap(),
+            "[package]\n\
+            name = \"foo\"\n\
+            \n\
+            [dependencies]\n\
+            anyhow = \"1\"\n\
+            # autocargo:keep begin\n\
+            # BEGIN MANUAL SECTION is just a comment here\n\
+            old-api = { path = \"../old-api\" }\n\
+            # autocargo:keep end\n"
+        );
+
+        let err = preserve_manual_sections(
+            "# autocargo:keep begin\nfoo = 1\n# END MANUAL SECTION\n",
+            generated,
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Line 3: manual section must be ended with '# autocargo:keep end'"
+        );
+        assert!(preserve_manual_sections("# autocargo:keep begin\n", generated).is_err());
+        assert!(preserve_manual_sections("# autocargo:keep end\n", generated).is_err());
+    }
+}
@@ -0,0 +1,275 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Validation of the license and publish metadata of the oss-ready Cargo.toml
+//! files, so that problems of the crates shipped to git repositories are
+//! found before anyone tries to publish them.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use cargo_toml::Dependency;
+use cargo_toml::DepsSet;
+use cargo_toml::Publish;
+use serde::Serialize;
+
+use super::GenerationOutput;
+use crate::cargo_manifest::Manifest;
+use crate::cargo_manifest::Package;
+use crate::paths::CargoTomlPath;
+use crate::paths::FbcodeRoot;
+
+/// Problem found in an oss-ready Cargo.toml file.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct OssProblem {
+    /// Path of the oss-ready Cargo.toml file, relative to fbcode.
+    pub cargo_toml_path: String,
+    /// What is wrong with the file.
+    pub problem: String,
+}
+
+/// Check the packages of the oss-ready Cargo.toml files:
+/// - each has license or license-file set,
+/// - license is a valid SPDX expression, with `/` accepted as `OR` like
+///   crates.io does,
+/// - license-file exists, either among the generated files or on disk, next to
+///   the oss-ready Cargo.toml file,
+/// - packages that can be published don't have path dependencies on packages
+///   with `publish = false`.
+///
+/// Returns the problems sorted by path of the file.
+pub fn validate_oss_packages(
+    fbcode_root: &FbcodeRoot,
+    output: &GenerationOutput,
+) -> Vec<OssProblem> {
+    let mut paths: Vec<&CargoTomlPath> = output
+        .crates
+        .iter()
+        .filter_map(|krate| krate.oss_cargo_toml_path.as_ref())
+        .collect();
+    paths.sort_by_key(|path| path.as_file().to_string());
+    paths.dedup();
+
+    let packages: Vec<(&CargoTomlPath, &Manifest, &Package)> = paths
+        .into_iter()
+        .filter_map(|path| {
+            let manifest = output.cargo_manifests.get(path)?;
+            Some((path, manifest, manifest.package.as_ref()?))
+        })
+        .collect();
+    let unpublished: HashSet<&str> = packages
+        .iter()
+        .filter(|(_, _, package)| !is_publishable(&package.publish))
+        .map(|(_, _, package)| package.name.as_str())
+        .collect();
+
+    let mut problems = Vec::new();
+    for (path, manifest, package) in packages {
+        let mut report = |problem: String| {
+            problems.push(OssProblem {
+                cargo_toml_path: path.as_file().to_string(),
+                problem,
+            })
+        };
+
+        match (&package.license, &package.license_file) {
+            (None, None) => report(format!(
+                "Package {} has neither license nor license-file set",
+                package.name
+            )),
+            (Some(license), _) => {
+                if let Err(err) = spdx::Expression::parse_mode(license, spdx::ParseMode::LAX) {
+                    report(format!(
+                        "License {license:?} of package {} is not a valid SPDX expression: {err}",
+                        package.name
+                    ));
+                }
+            }
+            (None, Some(_)) => {}
+        }
+
+        if let Some(license_file) = &package.license_file {
+            let file = path.as_dir().join_to_path_in_fbcode(license_file);
+            if !output.additional_files.contains_key(&file)
+                && !Path::join(fbcode_root.as_ref(), file.as_ref()).exists()
+            {
+                report(format!(
+                    "License file {} of package {} doesn't exist",
+                    file, package.name
+                ));
+            }
+        }
+
+        if is_publishable(&package.publish) {
+            let deps_sets = [&manifest.dependencies, &manifest.build_dependencies]
+                .into_iter()
+                .chain(
+                    manifest
+                        .target
+                        .values()
+                        .flat_map(|target| [&target.dependencies, &target.build_dependencies]),
+                );
+            for name in deps_sets.flat_map(unpublishable_path_dependencies(&unpublished)) {
+                report(format!(
+                    "Package {} can be published, but depends on {} which has publish = false",
+                    package.name, name
+                ));
+            }
+        }
+    }
+    problems.sort();
+    problems.dedup();
+    problems
+}
+
+/// False if the package can't be published to any registry.
+fn is_publishable(publish: &Publish) -> bool {
+    match publish {
+        Publish::Flag(flag) => *flag,
+        Publish::Registry(registries) => !registries.is_empty(),
+    }
+}
+
+/// Names of packages from unpublished that the dependencies refer to by path.
+fn unpublishable_path_dependencies<'a>(
+    unpublished: &'a HashSet<&str>,
+) -> impl Fn(&'a DepsSet) -> Vec<&'a str> {
+    move |deps| {
+        deps.iter()
+            .filter(|(_, dep)| matches!(dep, Dependency::Detailed(detail) if detail.path.is_some()))
+            .map(|(key, dep)| dep.package().unwrap_or(key))
+            .filter(|name| unpublished.contains(name))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use cargo_toml::DependencyDetail;
+    use maplit::btreemap;
+    use maplit::hashmap;
+
+    use super::*;
+    use crate::cargo_generator::GeneratedCrate;
+    use crate::cargo_manifest::empty_package;
+    use crate::paths::PathInFbcode;
+
+    fn cargo_path(dir: &str) -> CargoTomlPath {
+        CargoTomlPath::new(PathInFbcode::new_mock(dir).join_to_path_in_fbcode("Cargo.toml"))
+            .unwrap()
+    }
+
+    fn manifest(
+        name: &str,
+        license: Option<&str>,
+        license_file: Option<&str>,
+        publish: bool,
+    ) -> Manifest {
+        let mut package = empty_package();
+        package.name = name.to_owned();
+        package.license = license.map(|license| license.to_owned());
+        package.license_file = license_file.map(|file| file.to_owned());
+        package.publish = Publish::Flag(publish);
+        Manifest {
+            package: Some(package),
+            ..Manifest::default()
+        }
+    }
+
+    #[test]
+    fn validate_oss_packages_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let fbcode_root = FbcodeRoot::new_mock(dir.path());
+        std::fs::create_dir_all(dir.path().join("public/bar")).unwrap();
+        std::fs::write(dir.path().join("public/bar/LICENSE"), "").unwrap();
+
+        let krate = |name: &str| GeneratedCrate {
+            project: "proj".to_owned(),
+            name: name.to_owned(),
+            cargo_toml_path: cargo_path(name),
+            description: None,
+            rule: format!("//{name}:{name}"),
+            rules: vec![format!("//{name}:{name}")],
+            oss_cargo_toml_path: Some(cargo_path(&format!("public/{name}"))),
+        };
+        let mut foo = manifest("foo", Some("MIT/Apache-2.0"), None, true);
+        foo.dependencies = btreemap! {
+            "bar".to_owned() => Dependency::Detailed(Box::new(DependencyDetail {
+                path: Some("../bar".to_owned()),
+                ..DependencyDetail::default()
+            })),
+            "biz".to_owned() => Dependency::Detailed(Box::new(DependencyDetail {
+                path: Some("../biz".to_owned()),
+                ..DependencyDetail::default()
+            })),
+        };
+        let output = GenerationOutput {
+            cargo_manifests: hashmap! {
+                cargo_path("public/foo") => foo,
+                cargo_path("public/bar") => manifest("bar", None, Some("LICENSE"), false),
+                cargo_path("public/biz") => manifest("biz", Some("MIT"), Some("COPYING"), false),
+                cargo_path("public/fiz") => manifest("fiz", Some("Proprietary License"), None, true),
+                cargo_path("public/buz") => manifest("buz", None, None, true),
+                cargo_path("public/ok") => manifest("ok", Some("MIT"), Some("LICENSE"), true),
+            },
+            additional_files: hashmap! {
+                PathInFbcode::new_mock("public/ok/LICENSE") => String::new(),
+            },
+            crates: vec![
+                krate("foo"),
+                krate("bar"),
+                krate("biz"),
+                krate("fiz"),
+                krate("buz"),
+                krate("ok"),
+            ],
+        };
+
+        let problems = validate_oss_packages(&fbcode_root, &output);
+        assert_eq!(
+            problems
+                .iter()
+                .map(|problem| problem.cargo_toml_path.as_str())
+                .collect::<Vec<_>>(),
+            vec![
+                "public/biz/Cargo.toml",
+                "public/buz/Cargo.toml",
+                "public/fiz/Cargo.toml",
+                "public/foo/Cargo.toml",
+                "public/foo/Cargo.toml",
+            ]
+        );
+        assert_eq!(
+            problems[0].problem,
+            "License file public/biz/COPYING of package biz doesn't exist"
+        );
+        assert_eq!(
+            problems[1].problem,
+            "Package buz has neither license nor license-file set"
+        );
+        assert!(
+            problems[2]
+                .problem
+                .starts_with("License \"Proprietary License\" of package fiz is not a valid SPDX")
+        );
+        assert_eq!(
+            problems[3].problem,
+            "Package foo can be published, but depends on bar which has publish = false"
+        );
+
+        let without_oss = GenerationOutput {
+            cargo_manifests: output.cargo_manifests,
+            additional_files: HashMap::new(),
+            crates: Vec::new(),
+        };
+        assert!(validate_oss_packages(&fbcode_root, &without_oss).is_empty());
+    }
+}
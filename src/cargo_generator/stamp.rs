@@ -0,0 +1,228 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::env::current_exe;
+use std::fmt;
+use std::fmt::Display;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::str::FromStr;
+
+use anyhow::Context;
+use anyhow::Error;
+use anyhow::Result;
+use anyhow::anyhow;
+use sha2::Digest;
+use sha2::Sha256;
+use tokio::fs::read;
+
+use super::generator::THIRD_PARTY_CARGO_TOML;
+use crate::config::GLOBAL_CONFIG_FILENAME;
+use crate::config::ProjectConf;
+use crate::paths::FbsourceRoot;
+
+/// Marker of the comment line with the [Stamp] in generated Cargo.toml files.
+pub static STAMP_MARKER: &str = "autocargo-stamp:";
+
+/// Number of leading hex digits of the hashes kept in the stamp.
+const HASH_LEN: usize = 16;
+
+/// Source control revision that autocargo was built from, set by the build,
+/// e.g. in the env of the Buck rule of the binary. Builds without it are
+/// identified by the hash of their executable instead.
+const REVISION: Option<&str> = option_env!("AUTOCARGO_REVISION");
+
+/// Reproducibility stamp of the files generated for a project, capturing the
+/// inputs besides the Buck build files that they depend on. Files whose stamp
+/// differs from the current one were generated by another build of autocargo
+/// or with older configs and need to be regenerated.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Stamp {
+    /// Build of autocargo, either `rev-` followed by the revision it was built
+    /// from or `bin-` followed by the hash of its executable.
+    pub version: String,
+    /// Hash of the global config and of the config of the project.
+    pub config: String,
+    /// Hash of fbsource/third-party/rust/Cargo.toml.
+    pub third_party: String,
+}
+
+/// Inputs of the [Stamp]s that are shared by all projects of a run.
+#[derive(Clone, Debug)]
+pub struct StampInputs {
+    version: String,
+    global_config: Vec<u8>,
+    third_party: String,
+}
+
+impl StampInputs {
+    /// Capture the build of the current autocargo, the global config in
+    /// config_dir and the third-party crates of fbsource.
+    pub async fn compute(fbsource_root: &FbsourceRoot, config_dir: &Path) -> Result<Self> {
+        let third_party_path = Path::join(fbsource_root.as_ref(), THIRD_PARTY_CARGO_TOML);
+        let third_party = read(&third_party_path)
+            .await
+            .with_context(|| format!("While reading {}", third_party_path.display()))?;
+        let global_config_path = config_dir.join(GLOBAL_CONFIG_FILENAME);
+        let global_config = match read(&global_config_path).await {
+            Ok(content) => content,
+            Err(err) if err.kind() == ErrorKind::NotFound => Vec::new(),
+            Err(err) => {
+                return Err(err)
+                    .with_context(|| format!("While reading {}", global_config_path.display()));
+            }
+        };
+        Ok(Self {
+            version: build_identity().await?,
+            global_config,
+            third_party: short_hash(third_party),
+        })
+    }
+
+    /// The stamp of the files generated for the project.
+    pub fn stamp(&self, conf: &ProjectConf) -> Result<Stamp> {
+        let mut config = self.global_config.clone();
+        config.push(0);
+        serde_json::to_writer(&mut config, conf)
+            .with_context(|| format!("While serializing config of project {}", conf.name()))?;
+        Ok(Stamp {
+            version: self.version.clone(),
+            config: short_hash(config),
+            third_party: self.third_party.clone(),
+        })
+    }
+}
+
+/// Identity of the running autocargo build, see [Stamp::version].
+async fn build_identity() -> Result<String> {
+    if let Some(revision) = REVISION {
+        return Ok(format!("rev-{revision}"));
+    }
+    let exe = current_exe().context("While finding the autocargo executable")?;
+    let content = read(&exe)
+        .await
+        .with_context(|| format!("While reading {}", exe.display()))?;
+    Ok(format!("bin-{}", short_hash(content)))
+}
+
+fn short_hash(content: impl AsRef<[u8]>) -> String {
+    format!("{:x}", Sha256::digest(content))[..HASH_LEN].to_owned()
+}
+
+impl Stamp {
+    /// Comment line with this stamp to put in generated Cargo.toml files.
+    pub fn comment(&self) -> String {
+        format!("# {STAMP_MARKER} {self}\n")
+    }
+
+    /// Find the stamp among the leading comment lines of a generated file, the
+    /// result is None if there is none.
+    pub fn from_content(content: &str) -> Option<Result<Self>> {
+        content
+            .lines()
+            .take_while(|line| line.starts_with('#'))
+            .find_map(|line| line.strip_prefix("# ")?.strip_prefix(STAMP_MARKER))
+            .map(str::parse)
+    }
+}
+
+impl Display for Stamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "version={} config={} third-party={}",
+            self.version, self.config, self.third_party
+        )
+    }
+}
+
+impl FromStr for Stamp {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut version = None;
+        let mut config = None;
+        let mut third_party = None;
+        for field in s.split_whitespace() {
+            let (key, value) = field
+                .split_once('=')
+                .ok_or_else(|| anyhow!("Invalid field {field:?} of stamp {s:?}"))?;
+            let slot = match key {
+                "version" => &mut version,
+                "config" => &mut config,
+                "third-party" => &mut third_party,
+                _ => return Err(anyhow!("Unknown field {key:?} of stamp {s:?}")),
+            };
+            *slot = Some(value.to_owned());
+        }
+        let missing = |name| anyhow!("Missing field {name:?} of stamp {s:?}");
+        Ok(Self {
+            version: version.ok_or_else(|| missing("version"))?,
+            config: config.ok_or_else(|| missing("config"))?,
+            third_party: third_party.ok_or_else(|| missing("third-party"))?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::from_value;
+    use serde_json::json;
+
+    use super::*;
+    use crate::cargo_generator::GENERATED_PREAMBLE;
+
+    #[test]
+    fn stamp_of_project_test() {
+        let inputs = StampInputs {
+            version: "rev-0123".to_owned(),
+            global_config: b"[buck]\n".to_vec(),
+            third_party: "fedcba9876543210".to_owned(),
+        };
+        let conf = |name: &str| -> ProjectConf {
+            from_value(json!({"name": name, "oncall": "oncall_name", "roots": ["foo", "bar"]}))
+                .unwrap()
+        };
+
+        let stamp = inputs.stamp(&conf("a")).unwrap();
+        assert_eq!(stamp.version, "rev-0123");
+        assert_eq!(stamp.third_party, "fedcba9876543210");
+        assert_eq!(stamp.config.len(), HASH_LEN);
+        assert_eq!(inputs.stamp(&conf("a")).unwrap(), stamp);
+        assert_ne!(inputs.stamp(&conf("b")).unwrap(), stamp);
+
+        let inputs = StampInputs {
+            global_config: Vec::new(),
+            ..inputs
+        };
+        assert_ne!(inputs.stamp(&conf("a")).unwrap(), stamp);
+    }
+
+    #[test]
+    fn stamp_roundtrip_test() {
+        let stamp = Stamp {
+            version: "0.1.0".to_owned(),
+            config: "0123456789abcdef".to_owned(),
+            third_party: "fedcba9876543210".to_owned(),
+        };
+        let content = format!(
+            "# {GENERATED_PREAMBLE} from //foo:bar\n{}\n[package]\n",
+            stamp.comment()
+        );
+
+        assert_eq!(Stamp::from_content(&content).unwrap().unwrap(), stamp);
+        assert!(Stamp::from_content("# foo\n[package]\n").is_none());
+        assert!(Stamp::from_content("[package]\n# autocargo-stamp: version=0.1.0\n").is_none());
+        assert!(
+            Stamp::from_content("# autocargo-stamp: version=0.1.0\n")
+                .unwrap()
+                .is_err()
+        );
+    }
+}
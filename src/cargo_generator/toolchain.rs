@@ -0,0 +1,83 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::path::Path;
+
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::bail;
+use serde::Deserialize;
+use tokio::fs::read_to_string;
+
+use crate::paths::FbsourceRoot;
+
+#[derive(Deserialize)]
+struct ToolchainFile {
+    toolchain: Toolchain,
+}
+
+#[derive(Deserialize)]
+struct Toolchain {
+    channel: String,
+}
+
+/// Read the rust-toolchain or rust-toolchain.toml file at path relative to
+/// fbsource and return the rust-version matching its channel.
+pub(super) async fn read_toolchain_rust_version(
+    fbsource_root: &FbsourceRoot,
+    path: &Path,
+) -> Result<String> {
+    let path = Path::join(fbsource_root.as_ref(), path);
+    let try_version: Result<_> = try { toolchain_rust_version(&read_to_string(&path).await?)? };
+    try_version.with_context(|| format!("While processing toolchain file {}", path.display()))
+}
+
+/// The rust-version, i.e. "major.minor", of the channel in the content of a
+/// toolchain file, either in the toml format or in the legacy one that only
+/// has the channel. Only stable releases like "1.83.0" have a rust-version.
+fn toolchain_rust_version(content: &str) -> Result<String> {
+    let channel = match toml::from_str::<ToolchainFile>(content) {
+        Ok(file) => file.toolchain.channel,
+        Err(_) if !content.trim().contains('\n') => content.trim().to_owned(),
+        Err(err) => return Err(err).context("While parsing the toolchain file"),
+    };
+
+    let parts: Vec<_> = channel.split('.').collect();
+    match parts.as_slice() {
+        [major, minor] | [major, minor, _]
+            if parts.iter().all(|part| part.parse::<u32>().is_ok()) =>
+        {
+            Ok(format!("{major}.{minor}"))
+        }
+        _ => bail!(
+            "Channel {:?} is not a stable release, rust-version can't be derived from it",
+            channel
+        ),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn toolchain_rust_version_test() {
+        assert_eq!(
+            toolchain_rust_version(
+                "[toolchain]\nchannel = \"1.83.0\"\ncomponents = [\"rustfmt\"]\n"
+            )
+            .unwrap(),
+            "1.83"
+        );
+        assert_eq!(toolchain_rust_version("1.80\n").unwrap(), "1.80");
+        assert!(toolchain_rust_version("nightly-2024-11-01\n").is_err());
+        assert!(toolchain_rust_version("[toolchain]\nchannel = \"stable\"\n").is_err());
+        assert!(toolchain_rust_version("[toolchain]\nprofile = \"minimal\"\n").is_err());
+    }
+}
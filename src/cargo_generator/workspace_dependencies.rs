@@ -0,0 +1,261 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::collections::BTreeMap;
+
+use cargo_toml::Dependency;
+use cargo_toml::DependencyDetail;
+use cargo_toml::DepsSet;
+use cargo_toml::InheritedDependencyDetail;
+
+use crate::cargo_manifest::Manifest;
+
+/// Compute [workspace.dependencies] for the provided workspace members and
+/// replace their dependencies with `foo.workspace = true` entries. Only
+/// dependencies that are declared the same way (ignoring features and
+/// optionality) in every member are inherited, path dependencies are left
+/// untouched as their paths are relative to the member. Features common to all
+/// members are moved to the workspace definition, the remaining ones are kept
+/// on the member.
+pub(super) fn inherit_workspace_dependencies<'a>(
+    members: impl IntoIterator<Item = &'a mut Manifest>,
+) -> DepsSet {
+    let mut members: Vec<_> = members.into_iter().collect();
+
+    let mut candidates: BTreeMap<&str, Option<(DependencyDetail, Vec<String>)>> = BTreeMap::new();
+    for manifest in &members {
+        for (key, dep) in deps_sets(manifest).flat_map(|deps| deps.iter()) {
+            let candidate = workspace_candidate(dep);
+            candidates
+                .entry(key.as_str())
+                .and_modify(|existing| {
+                    *existing = match (existing.take(), &candidate) {
+                        (Some((source, features)), Some((other_source, other_features)))
+                            if source == *other_source =>
+                        {
+                            Some((
+                                source,
+                                features
+                                    .into_iter()
+                                    .filter(|f| other_features.contains(f))
+                                    .collect(),
+                            ))
+                        }
+                        _ => None,
+                    }
+                })
+                .or_insert(candidate);
+        }
+    }
+
+    let workspace_deps: DepsSet = candidates
+        .into_iter()
+        .filter_map(|(key, candidate)| {
+            candidate.map(|(source, features)| {
+                let dep = if features.is_empty()
+                    && source
+                        == (DependencyDetail {
+                            version: source.version.clone(),
+                            ..DependencyDetail::default()
+                        }) {
+                    match source.version {
+                        Some(version) => Dependency::Simple(version),
+                        None => Dependency::Detailed(Box::default()),
+                    }
+                } else {
+                    Dependency::Detailed(Box::new(DependencyDetail { features, ..source }))
+                };
+                (key.to_owned(), dep)
+            })
+        })
+        .collect();
+
+    for manifest in &mut members {
+        for deps in deps_sets_mut(manifest) {
+            for (key, dep) in deps.iter_mut() {
+                if let Some(workspace_dep) = workspace_deps.get(key) {
+                    let common_features = workspace_dep
+                        .detail()
+                        .map_or(&[][..], |detail| &detail.features);
+                    let (features, optional) = match dep {
+                        Dependency::Detailed(detail) => (
+                            detail
+                                .features
+                                .iter()
+                                .filter(|f| !common_features.contains(f))
+                                .cloned()
+                                .collect(),
+                            detail.optional,
+                        ),
+                        _ => (Vec::new(), false),
+                    };
+                    *dep = Dependency::Inherited(InheritedDependencyDetail {
+                        features,
+                        optional,
+                        workspace: true,
+                    });
+                }
+            }
+        }
+    }
+
+    workspace_deps
+}
+
+/// Split a dependency into the part that can be defined in the workspace and
+/// its features, if it is eligible for inheritance.
+fn workspace_candidate(dep: &Dependency) -> Option<(DependencyDetail, Vec<String>)> {
+    match dep {
+        Dependency::Simple(version) => Some((
+            DependencyDetail {
+                version: Some(version.clone()),
+                ..DependencyDetail::default()
+            },
+            Vec::new(),
+        )),
+        Dependency::Detailed(detail) if detail.path.is_none() && detail.unstable.is_empty() => {
+            Some((
+                DependencyDetail {
+                    features: Vec::new(),
+                    optional: false,
+                    ..(**detail).clone()
+                },
+                detail.features.clone(),
+            ))
+        }
+        Dependency::Detailed(_) | Dependency::Inherited(_) => None,
+    }
+}
+
+fn deps_sets(manifest: &Manifest) -> impl Iterator<Item = &DepsSet> {
+    [
+        &manifest.dependencies,
+        &manifest.dev_dependencies,
+        &manifest.build_dependencies,
+    ]
+    .into_iter()
+    .chain(manifest.target.values().flat_map(|target| {
+        [
+            &target.dependencies,
+            &target.dev_dependencies,
+            &target.build_dependencies,
+        ]
+    }))
+}
+
+fn deps_sets_mut(manifest: &mut Manifest) -> impl Iterator<Item = &mut DepsSet> {
+    [
+        &mut manifest.dependencies,
+        &mut manifest.dev_dependencies,
+        &mut manifest.build_dependencies,
+    ]
+    .into_iter()
+    .chain(manifest.target.values_mut().flat_map(|target| {
+        [
+            &mut target.dependencies,
+            &mut target.dev_dependencies,
+            &mut target.build_dependencies,
+        ]
+    }))
+}
+
+#[cfg(test)]
+mod test {
+    use cargo_toml::Target;
+    use maplit::btreemap;
+
+    use super::*;
+    use crate::cargo_manifest::TargetKey;
+
+    fn s(s: &str) -> String {
+        s.to_owned()
+    }
+
+    fn detailed(version: &str, features: &[&str], optional: bool) -> Dependency {
+        Dependency::Detailed(Box::new(DependencyDetail {
+            version: Some(s(version)),
+            features: features.iter().map(|f| s(f)).collect(),
+            optional,
+            ..DependencyDetail::default()
+        }))
+    }
+
+    fn inherited(features: &[&str], optional: bool) -> Dependency {
+        Dependency::Inherited(InheritedDependencyDetail {
+            features: features.iter().map(|f| s(f)).collect(),
+            optional,
+            workspace: true,
+        })
+    }
+
+    #[test]
+    fn inherit_workspace_dependencies_test() {
+        let path_dep = Dependency::Detailed(Box::new(DependencyDetail {
+            path: Some(s("../foo")),
+            ..DependencyDetail::default()
+        }));
+
+        let mut first = Manifest {
+            dependencies: btreemap! {
+                s("anyhow") => Dependency::Simple(s("1.0")),
+                s("serde") => detailed("1.0", &["derive", "rc"], false),
+                s("foo") => path_dep.clone(),
+            },
+            ..Manifest::default()
+        };
+        let mut second = Manifest {
+            dependencies: btreemap! {
+                s("serde") => detailed("1.0", &["derive"], true),
+                s("tokio") => Dependency::Simple(s("1")),
+            },
+            target: btreemap! {
+                TargetKey::try_from("unix").unwrap() => Target {
+                    dependencies: btreemap! { s("anyhow") => Dependency::Simple(s("1.0")) },
+                    dev_dependencies: DepsSet::new(),
+                    build_dependencies: DepsSet::new(),
+                }
+            },
+            dev_dependencies: btreemap! { s("tokio") => Dependency::Simple(s("2")) },
+            ..Manifest::default()
+        };
+
+        let workspace_deps = inherit_workspace_dependencies([&mut first, &mut second]);
+
+        assert_eq!(
+            workspace_deps,
+            btreemap! {
+                s("anyhow") => Dependency::Simple(s("1.0")),
+                s("serde") => detailed("1.0", &["derive"], false),
+            }
+        );
+        assert_eq!(
+            first.dependencies,
+            btreemap! {
+                s("anyhow") => inherited(&[], false),
+                s("serde") => inherited(&["rc"], false),
+                s("foo") => path_dep,
+            }
+        );
+        assert_eq!(
+            second.dependencies,
+            btreemap! {
+                s("serde") => inherited(&[], true),
+                s("tokio") => Dependency::Simple(s("1")),
+            }
+        );
+        assert_eq!(
+            second.dev_dependencies,
+            btreemap! { s("tokio") => Dependency::Simple(s("2")) }
+        );
+        assert_eq!(
+            second.target.values().next().unwrap().dependencies,
+            btreemap! { s("anyhow") => inherited(&[], false) }
+        );
+    }
+}
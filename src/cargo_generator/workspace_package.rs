@@ -0,0 +1,244 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use cargo_toml::PackageTemplate;
+
+use crate::cargo_manifest::Manifest;
+use crate::cargo_manifest::Package;
+use crate::config::InheritablePackageField;
+use crate::config::PackageDefaults;
+
+/// Compute [workspace.package] out of the project's package defaults for the
+/// requested fields and mark them as inherited in every workspace member whose
+/// value is the same as the default one. Fields that have no value in the
+/// defaults are skipped, except for rust-version which defaults to the one of
+/// the toolchain. Returns None if no field ended up in the template.
+pub(super) fn inherit_workspace_package<'a>(
+    fields: &[InheritablePackageField],
+    defaults: &PackageDefaults,
+    toolchain_rust_version: Option<&str>,
+    members: impl IntoIterator<Item = &'a mut Manifest>,
+) -> Option<PackageTemplate> {
+    let rust_version = defaults
+        .rust_version
+        .clone()
+        .or_else(|| toolchain_rust_version.map(str::to_owned));
+    let mut template = PackageTemplate::default();
+    let fields: Vec<_> = fields
+        .iter()
+        .copied()
+        .filter(|field| set_template_field(&mut template, *field, defaults, &rust_version))
+        .collect();
+
+    if fields.is_empty() {
+        return None;
+    }
+
+    for package in members
+        .into_iter()
+        .filter_map(|manifest| manifest.package.as_mut())
+    {
+        for field in &fields {
+            if member_uses_default(package, *field, defaults, &rust_version) {
+                package.inherited_from_workspace.insert(field_key(*field));
+            }
+        }
+    }
+
+    Some(template)
+}
+
+/// Name of the field in Cargo.toml.
+fn field_key(field: InheritablePackageField) -> &'static str {
+    use InheritablePackageField::*;
+    match field {
+        Version => "version",
+        Authors => "authors",
+        Edition => "edition",
+        RustVersion => "rust-version",
+        Description => "description",
+        Documentation => "documentation",
+        Homepage => "homepage",
+        Repository => "repository",
+        License => "license",
+        Keywords => "keywords",
+        Categories => "categories",
+        Exclude => "exclude",
+        Include => "include",
+        Publish => "publish",
+    }
+}
+
+/// Copy the default value of the field into the template, returns false if
+/// there is no value to copy.
+fn set_template_field(
+    template: &mut PackageTemplate,
+    field: InheritablePackageField,
+    defaults: &PackageDefaults,
+    rust_version: &Option<String>,
+) -> bool {
+    fn non_empty(values: &[String]) -> Option<Vec<String>> {
+        (!values.is_empty()).then(|| values.to_vec())
+    }
+
+    use InheritablePackageField::*;
+    match field {
+        Version => template.version = Some(defaults.version.clone()),
+        Authors => template.authors = non_empty(&defaults.authors),
+        Edition => template.edition = Some(defaults.edition),
+        RustVersion => template.rust_version = rust_version.clone(),
+        Description => template.description = defaults.description.clone(),
+        Documentation => template.documentation = defaults.documentation.clone(),
+        Homepage => template.homepage = defaults.homepage.clone(),
+        Repository => template.repository = defaults.repository.clone(),
+        License => template.license = defaults.license.clone(),
+        Keywords => template.keywords = non_empty(&defaults.keywords),
+        Categories => template.categories = non_empty(&defaults.categories),
+        Exclude => template.exclude = non_empty(&defaults.exclude),
+        Include => template.include = non_empty(&defaults.include),
+        Publish => template.publish = defaults.publish.clone(),
+    }
+
+    match field {
+        Version | Edition => true,
+        Authors => template.authors.is_some(),
+        RustVersion => template.rust_version.is_some(),
+        Description => template.description.is_some(),
+        Documentation => template.documentation.is_some(),
+        Homepage => template.homepage.is_some(),
+        Repository => template.repository.is_some(),
+        License => template.license.is_some(),
+        Keywords => template.keywords.is_some(),
+        Categories => template.categories.is_some(),
+        Exclude => template.exclude.is_some(),
+        Include => template.include.is_some(),
+        Publish => template.publish != cargo_toml::Publish::Flag(true),
+    }
+}
+
+fn member_uses_default(
+    package: &Package,
+    field: InheritablePackageField,
+    defaults: &PackageDefaults,
+    rust_version: &Option<String>,
+) -> bool {
+    use InheritablePackageField::*;
+    match field {
+        Version => package.version == defaults.version,
+        Authors => package.authors == defaults.authors,
+        Edition => package.edition == defaults.edition,
+        RustVersion => package.rust_version == *rust_version,
+        Description => package.description == defaults.description,
+        Documentation => package.documentation == defaults.documentation,
+        Homepage => package.homepage == defaults.homepage,
+        Repository => package.repository == defaults.repository,
+        License => package.license == defaults.license,
+        Keywords => package.keywords == defaults.keywords,
+        Categories => package.categories == defaults.categories,
+        Exclude => package.exclude == defaults.exclude,
+        Include => package.include == defaults.include,
+        Publish => package.publish == defaults.publish,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use cargo_toml::Edition;
+
+    use super::*;
+    use crate::cargo_manifest::empty_package;
+
+    fn s(s: &str) -> String {
+        s.to_owned()
+    }
+
+    fn member(version: &str, license: Option<&str>) -> Manifest {
+        Manifest {
+            package: Some(Package {
+                version: s(version),
+                license: license.map(s),
+                ..empty_package()
+            }),
+            ..Manifest::default()
+        }
+    }
+
+    #[test]
+    fn inherit_workspace_package_test() {
+        let defaults = PackageDefaults {
+            version: s("0.1.0"),
+            license: Some(s("MIT")),
+            ..PackageDefaults::default()
+        };
+        let mut first = member("0.1.0", Some("MIT"));
+        let mut second = member("0.2.0", Some("MIT"));
+        let mut virtual_manifest = Manifest::default();
+
+        let template = inherit_workspace_package(
+            &[
+                InheritablePackageField::Version,
+                InheritablePackageField::License,
+                InheritablePackageField::Repository,
+            ],
+            &defaults,
+            None,
+            [&mut first, &mut second, &mut virtual_manifest],
+        )
+        .unwrap();
+
+        assert_eq!(template.version, Some(s("0.1.0")));
+        assert_eq!(template.license, Some(s("MIT")));
+        assert_eq!(template.repository, None);
+        assert_eq!(template.edition, None);
+        assert_eq!(
+            first.package.unwrap().inherited_from_workspace,
+            BTreeSet::from(["license", "version"])
+        );
+        assert_eq!(
+            second.package.unwrap().inherited_from_workspace,
+            BTreeSet::from(["license"])
+        );
+        assert!(virtual_manifest.package.is_none());
+
+        assert!(
+            inherit_workspace_package(
+                &[
+                    InheritablePackageField::Repository,
+                    InheritablePackageField::Keywords,
+                ],
+                &defaults,
+                None,
+                [&mut member("0.1.0", None)]
+            )
+            .is_none()
+        );
+
+        let template =
+            inherit_workspace_package(&[InheritablePackageField::Edition], &defaults, None, [])
+                .unwrap();
+        assert_eq!(template.edition, Some(Edition::E2024));
+
+        let mut toolchain_member = member("0.1.0", None);
+        toolchain_member.package.as_mut().unwrap().rust_version = Some(s("1.83"));
+        let template = inherit_workspace_package(
+            &[InheritablePackageField::RustVersion],
+            &defaults,
+            Some("1.83"),
+            [&mut toolchain_member],
+        )
+        .unwrap();
+        assert_eq!(template.rust_version, Some(s("1.83")));
+        assert_eq!(
+            toolchain_member.package.unwrap().inherited_from_workspace,
+            BTreeSet::from(["rust-version"])
+        );
+    }
+}
@@ -18,8 +18,11 @@ mod profiles;
 mod target_key;
 mod toml_util;
 
+pub use manifest::DependencyComments;
 pub use manifest::Manifest;
 pub use package::Package;
+#[cfg(test)]
+pub(crate) use package::empty_package;
 pub use product::Product;
 pub use target_key::KeyedTargetDepsSet;
 pub use target_key::TargetKey;
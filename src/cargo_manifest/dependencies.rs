@@ -10,6 +10,7 @@
 use cargo_toml::Dependency;
 use cargo_toml::DependencyDetail;
 use cargo_toml::DepsSet;
+use cargo_toml::InheritedDependencyDetail;
 use cargo_toml::Target;
 use toml_edit::InlineTable;
 use toml_edit::Item;
@@ -86,9 +87,29 @@ pub fn deps_set_to_toml(deps: &DepsSet) -> Table {
                     continue;
                 }
             }
-            Dependency::Inherited(_) => unimplemented!(
-                "dependency `{alias}` uses inherited dependency syntax whic his not supported"
-            ),
+            Dependency::Inherited(InheritedDependencyDetail {
+                features,
+                optional,
+                workspace,
+            }) => {
+                let mut dep_table = InlineTable::default();
+                {
+                    let dep_table = &mut dep_table;
+                    maybe_add_to_inline_table(
+                        dep_table,
+                        "workspace",
+                        if *workspace { Some(true) } else { None },
+                    );
+                    maybe_add_to_inline_table(dep_table, "features", sorted_array(features));
+                    maybe_add_to_inline_table(
+                        dep_table,
+                        "optional",
+                        if *optional { Some(true) } else { None },
+                    );
+                }
+                dep_table.fmt();
+                decorated_value(dep_table)
+            }
         };
 
         table[alias] = item;
@@ -193,6 +214,28 @@ foo = "1"
         );
     }
 
+    #[test]
+    fn deps_set_to_toml_test_inherited() {
+        assert_eq!(
+            deps_set_to_toml(&btreemap! {
+                s("foo") => Dependency::Inherited(InheritedDependencyDetail {
+                    features: Vec::new(),
+                    optional: false,
+                    workspace: true,
+                }),
+                s("bar") => Dependency::Inherited(InheritedDependencyDetail {
+                    features: vec_s(&["foo", "bar"]),
+                    optional: true,
+                    workspace: true,
+                }),
+            })
+            .to_string(),
+            r#"bar = { workspace = true, features = ["bar", "foo"], optional = true }
+foo = { workspace = true }
+"#
+        );
+    }
+
     #[test]
     fn target_deps_set_to_toml_test_empty() {
         assert!(target_deps_set_to_toml(&KeyedTargetDepsSet::new()).is_empty());
@@ -25,17 +25,25 @@ use toml_edit::Item;
 use super::KeyedTargetDepsSet;
 use super::Package;
 use super::Product;
+use super::TargetKey;
 use super::dependencies::deps_set_to_toml;
 use super::dependencies::target_deps_set_to_toml;
+use super::package::package_template_to_toml;
 use super::product::ProductType;
 use super::profiles::profiles_to_toml;
 use super::toml_util::cargo_toml_to_toml_edit_value;
+use super::toml_util::comment_lines;
 use super::toml_util::decorated_value;
 use super::toml_util::maybe_add_to_table;
 use super::toml_util::new_implicit_table;
 use super::toml_util::sorted_array;
 use super::toml_util::sorted_array_maybe_multiline;
 
+/// Comments to write above entries of the dependency tables, keyed by the
+/// target of the table (None for the top-level ones), name of the table, e.g.
+/// "dev-dependencies", and name of the dependency.
+pub type DependencyComments = BTreeMap<(Option<TargetKey>, &'static str, String), String>;
+
 /// Formatted with accordance to
 /// https://doc.rust-lang.org/cargo/reference/manifest.html
 #[derive(Debug, Default)]
@@ -52,12 +60,19 @@ pub struct Manifest {
     pub test: Vec<Product>,
     pub bench: Vec<Product>,
 
+    /// Written as the [badges] table, each badge as an inline table
+    pub badges: BTreeMap<String, Value>,
+
     pub dependencies: DepsSet,
     pub dev_dependencies: DepsSet,
     pub build_dependencies: DepsSet,
     pub target: KeyedTargetDepsSet,
+    /// Written as `# ` comments above entries of the dependency tables
+    pub dependency_comments: DependencyComments,
 
     pub features: FeatureSet,
+    /// Written as `## ` comments above entries of the [features] table
+    pub feature_docs: BTreeMap<String, String>,
     pub patch: PatchSet,
     pub profile: Profiles,
     pub workspace: Option<Workspace>,
@@ -79,11 +94,14 @@ impl Manifest {
             example,
             test,
             bench,
+            badges,
             dependencies,
             dev_dependencies,
             build_dependencies,
             target,
+            dependency_comments,
             features,
+            feature_docs,
             patch,
             profile,
             workspace,
@@ -134,6 +152,15 @@ impl Manifest {
             }
         }
 
+        if !badges.is_empty() {
+            table["badges"] = Item::Table(
+                badges
+                    .iter()
+                    .map(|(k, v)| (k, cargo_toml_to_toml_edit_value(v)))
+                    .collect(),
+            );
+        }
+
         let dependencies = deps_set_to_toml(dependencies);
         if !dependencies.is_empty() {
             table["dependencies"] = Item::Table(dependencies);
@@ -150,6 +177,21 @@ impl Manifest {
         if !target.is_empty() {
             table["target"] = Item::Table(target);
         }
+        for ((target_key, section, name), comment) in dependency_comments {
+            let deps_table = match target_key {
+                None => table.get_mut(section),
+                Some(target_key) => table
+                    .get_mut("target")
+                    .and_then(|target| target.get_mut(target_key.get()))
+                    .and_then(|target| target.get_mut(section)),
+            };
+            if let Some(mut key) = deps_table
+                .and_then(Item::as_table_mut)
+                .and_then(|deps_table| deps_table.key_mut(name))
+            {
+                key.leaf_decor_mut().set_prefix(comment_lines("#", comment));
+            }
+        }
 
         let mut features_table = new_implicit_table();
         {
@@ -160,6 +202,10 @@ impl Manifest {
                     k,
                     Some(sorted_array(vs).unwrap_or_default()),
                 );
+                if let (Some(doc), Some(mut key)) = (feature_docs.get(k), features_table.key_mut(k))
+                {
+                    key.leaf_decor_mut().set_prefix(comment_lines("##", doc));
+                }
             }
         }
         if !features_table.is_empty() {
@@ -185,11 +231,11 @@ impl Manifest {
         if let Some(Workspace {
             members,
             default_members,
-            package: _,
+            package: workspace_package,
             exclude,
             metadata: _,
             resolver,
-            dependencies: _,
+            dependencies: workspace_dependencies,
             lints: _,
         }) = workspace
         {
@@ -230,6 +276,16 @@ impl Manifest {
                         });
                     }
                 }
+                if let Some(workspace_package) = workspace_package {
+                    let workspace_package = package_template_to_toml(workspace_package);
+                    if !workspace_package.is_empty() {
+                        workspace_table["package"] = Item::Table(workspace_package);
+                    }
+                }
+                let workspace_dependencies = deps_set_to_toml(workspace_dependencies);
+                if !workspace_dependencies.is_empty() {
+                    workspace_table["dependencies"] = Item::Table(workspace_dependencies);
+                }
             }
             table["workspace"] = Item::Table(workspace_table);
         }
@@ -250,6 +306,7 @@ impl Manifest {
 #[cfg(test)]
 mod test {
     use cargo_toml::Dependency;
+    use cargo_toml::PackageTemplate;
     use cargo_toml::Profile;
     use cargo_toml::Target;
     use maplit::btreemap;
@@ -427,6 +484,146 @@ incremental = true
 
 [workspace]
 members = ["bar", "foo"]
+"#
+        );
+    }
+
+    #[test]
+    fn manifest_toml_test_feature_docs() {
+        assert_eq!(
+            &Manifest {
+                features: btreemap! { s("default") => vec_s(&["foo"]), s("foo") => vec![] },
+                feature_docs: btreemap! {
+                    s("foo") => s("Enables foo.\n\nRequires bar to be installed."),
+                },
+                ..Manifest::default()
+            }
+            .to_toml_string(),
+            r#"[features]
+default = ["foo"]
+## Enables foo.
+##
+## Requires bar to be installed.
+foo = []
+"#
+        );
+    }
+
+    #[test]
+    fn manifest_toml_test_dependency_comments() {
+        let unix = TargetKey::try_from("unix").unwrap();
+        assert_eq!(
+            &Manifest {
+                dependencies: btreemap! {
+                    s("bar") => Dependency::Simple(s("1")),
+                    s("foo") => Dependency::Simple(s("=1.2.3")),
+                },
+                target: btreemap! {
+                    unix.clone() => Target {
+                        dependencies: DepsSet::new(),
+                        dev_dependencies: btreemap! { s("biz") => Dependency::Simple(s("2")) },
+                        build_dependencies: DepsSet::new(),
+                    },
+                },
+                dependency_comments: btreemap! {
+                    (None, "dependencies", s("foo")) => s("Pinned until T123 is fixed.\n\nSee #42."),
+                    (None, "dev-dependencies", s("foo")) => s("Not generated, so ignored."),
+                    (Some(unix), "dev-dependencies", s("biz")) => s("Only tested on unix."),
+                },
+                ..Manifest::default()
+            }
+            .to_toml_string(),
+            r#"[dependencies]
+bar = "1"
+# Pinned until T123 is fixed.
+#
+# See #42.
+foo = "=1.2.3"
+
+[target.unix.dev-dependencies]
+# Only tested on unix.
+biz = "2"
+"#
+        );
+    }
+
+    #[test]
+    fn manifest_toml_test_workspace_dependencies() {
+        assert_eq!(
+            &Manifest {
+                workspace: Some(Workspace {
+                    members: vec_s(&["foo"]),
+                    default_members: Vec::new(),
+                    package: None,
+                    exclude: Vec::new(),
+                    metadata: None,
+                    resolver: None,
+                    dependencies: btreemap! { s("bar") => Dependency::Simple(s("1")) },
+                    lints: BTreeMap::new(),
+                }),
+                ..Manifest::default()
+            }
+            .to_toml_string(),
+            r#"[workspace]
+members = ["foo"]
+
+[workspace.dependencies]
+bar = "1"
+"#
+        );
+    }
+
+    #[test]
+    fn manifest_toml_test_workspace_package() {
+        let mut package = PackageTemplate::default();
+        package.version = Some(s("0.1.0"));
+        package.edition = Some(Edition::E2021);
+        package.license = Some(s("MIT"));
+
+        assert_eq!(
+            &Manifest {
+                workspace: Some(Workspace {
+                    members: vec_s(&["foo"]),
+                    default_members: Vec::new(),
+                    package: Some(package),
+                    exclude: Vec::new(),
+                    metadata: None,
+                    resolver: None,
+                    dependencies: DepsSet::new(),
+                    lints: BTreeMap::new(),
+                }),
+                ..Manifest::default()
+            }
+            .to_toml_string(),
+            r#"[workspace]
+members = ["foo"]
+
+[workspace.package]
+version = "0.1.0"
+edition = "2021"
+license = "MIT"
+"#
+        );
+    }
+
+    #[test]
+    fn manifest_toml_test_badges() {
+        assert_eq!(
+            &Manifest {
+                badges: btreemap! {
+                    s("maintenance") => toml::from_str::<Value>(
+                        r#"status = "actively-developed""#
+                    ).unwrap(),
+                },
+                dependencies: btreemap! { s("foo") => Dependency::Simple(s("1")) },
+                ..Manifest::default()
+            }
+            .to_toml_string(),
+            r#"[badges]
+maintenance = { status = "actively-developed" }
+
+[dependencies]
+foo = "1"
 "#
         );
     }
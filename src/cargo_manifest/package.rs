@@ -7,10 +7,14 @@
  * of this source tree.
  */
 
+use std::collections::BTreeSet;
+
 use cargo_toml::Edition;
+use cargo_toml::PackageTemplate;
 use cargo_toml::Publish;
 use cargo_toml::Value as CValue;
 use cargo_util_schemas::manifest::StringOrBool;
+use toml_edit::InlineTable;
 use toml_edit::Table;
 
 use super::toml_util::cargo_toml_to_toml_edit_item;
@@ -51,6 +55,9 @@ pub struct Package {
     pub autoexamples: bool,
     pub autotests: bool,
     pub autobenches: bool,
+    /// Keys of fields that are inherited from [workspace.package], they are
+    /// formatted as `field.workspace = true` instead of their values.
+    pub inherited_from_workspace: BTreeSet<&'static str>,
 }
 
 impl Package {
@@ -82,6 +89,7 @@ impl Package {
             autoexamples,
             autotests,
             autobenches,
+            inherited_from_workspace,
         } = self;
 
         let mut table = new_implicit_table();
@@ -139,11 +147,71 @@ impl Package {
             if let Some(value) = metadata {
                 table["metadata"] = cargo_toml_to_toml_edit_item(value);
             }
+            for key in inherited_from_workspace {
+                let mut inherited = InlineTable::default();
+                inherited.insert("workspace", true.into());
+                table[*key] = decorated_value(inherited);
+            }
         }
         table
     }
 }
 
+/// Format [workspace.package] according to
+/// https://doc.rust-lang.org/cargo/reference/workspaces.html#the-package-table
+/// Path fields like readme or license-file are not supported, autocargo
+/// computes them relative to each member instead.
+pub(super) fn package_template_to_toml(template: &PackageTemplate) -> Table {
+    let PackageTemplate {
+        authors,
+        categories,
+        description,
+        documentation,
+        edition,
+        exclude,
+        homepage,
+        include,
+        keywords,
+        license,
+        publish,
+        repository,
+        rust_version,
+        version,
+        ..
+    } = template;
+
+    let mut table = new_implicit_table();
+    {
+        let table = &mut table;
+
+        maybe_add_to_table(table, "version", version.as_deref());
+        maybe_add_to_table(table, "authors", authors.as_ref().and_then(ordered_array));
+        maybe_add_to_table(table, "edition", edition.as_ref().map(edition_to_str));
+        maybe_add_to_table(table, "rust-version", rust_version.as_deref());
+        maybe_add_to_table(table, "description", description.as_deref());
+        maybe_add_to_table(table, "documentation", documentation.as_deref());
+        maybe_add_to_table(table, "homepage", homepage.as_deref());
+        maybe_add_to_table(table, "repository", repository.as_deref());
+        maybe_add_to_table(table, "license", license.as_deref());
+        maybe_add_to_table(table, "keywords", keywords.as_ref().and_then(sorted_array));
+        maybe_add_to_table(
+            table,
+            "categories",
+            categories.as_ref().and_then(sorted_array),
+        );
+        maybe_add_to_table(table, "exclude", exclude.as_ref().and_then(sorted_array));
+        maybe_add_to_table(table, "include", include.as_ref().and_then(sorted_array));
+        if let Some(value) = match publish {
+            Publish::Flag(true) => None,
+            Publish::Flag(false) => Some(decorated_value(false)),
+            Publish::Registry(regs) => sorted_array(regs).map(decorated_value),
+        } {
+            table["publish"] = value;
+        }
+    }
+    table
+}
+
 #[cfg(test)]
 pub fn empty_package() -> Package {
     let s = |s: &str| s.to_owned();
@@ -174,6 +242,7 @@ pub fn empty_package() -> Package {
         autoexamples: true,
         autotests: true,
         autobenches: true,
+        inherited_from_workspace: BTreeSet::new(),
     }
 }
 
@@ -293,6 +362,7 @@ proident, sunt in culpa qui officia deserunt mollit anim id est laborum.
             autoexamples: false,
             autotests: false,
             autobenches: false,
+            inherited_from_workspace: BTreeSet::new(),
         };
         let table = package.to_toml();
         assert_eq!(
@@ -330,8 +400,6 @@ autoexamples = false
 autotests = false
 autobenches = false
 
-[metadata]
-
 [[metadata.stuff]]
 bar = 2021-02-16T12:12:12.12Z
 
@@ -14,6 +14,7 @@ use serde::Deserialize;
 use toml_edit::Table;
 
 use super::Package;
+use super::toml_util::comment_lines;
 use super::toml_util::decorated_value;
 use super::toml_util::edition_to_str;
 use super::toml_util::maybe_add_to_table;
@@ -31,7 +32,7 @@ pub(super) enum ProductType {
 
 /// Based on [cargo_toml::Product] and
 /// https://doc.rust-lang.org/cargo/reference/cargo-targets.html
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize)]
 #[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
 pub struct Product {
     pub name: Option<String>,
@@ -42,31 +43,17 @@ pub struct Product {
     pub doc: Option<bool>,
     pub plugin: bool,
     pub proc_macro: bool,
-    pub harness: bool,
+    /// None means the default of Cargo, except for benches which follow the
+    /// bench_defaults of the project.
+    pub harness: Option<bool>,
     pub edition: Option<Edition>,
     pub crate_type: Vec<String>,
+    /// Written as `# ` comments above crate-type.
+    #[serde(skip)]
+    pub crate_type_comment: Option<String>,
     pub required_features: Vec<String>,
 }
 
-impl Default for Product {
-    fn default() -> Self {
-        Self {
-            name: None,
-            path: None,
-            test: None,
-            doctest: None,
-            bench: None,
-            doc: None,
-            plugin: false,
-            proc_macro: false,
-            harness: true,
-            edition: None,
-            crate_type: Vec::new(),
-            required_features: Vec::new(),
-        }
-    }
-}
-
 impl Product {
     pub(super) fn to_toml(
         &self,
@@ -85,6 +72,7 @@ impl Product {
             harness,
             edition,
             crate_type,
+            crate_type_comment,
             required_features,
         } = self;
 
@@ -118,8 +106,8 @@ impl Product {
             if *proc_macro && is_lib {
                 table_entries.push(("proc-macro", decorated_value(*proc_macro)));
             }
-            if !harness {
-                table_entries.push(("harness", decorated_value(*harness)));
+            if *harness == Some(false) {
+                table_entries.push(("harness", decorated_value(false)));
             }
             if let Some(edition) = edition {
                 if maybe_package.map(|package| package.edition) != Some(*edition) {
@@ -162,6 +150,11 @@ impl Product {
             for (k, v) in table_entries {
                 table[k] = v
             }
+            if let (Some(comment), Some(mut key)) =
+                (crate_type_comment, table.key_mut("crate-type"))
+            {
+                key.leaf_decor_mut().set_prefix(comment_lines("#", comment));
+            }
         }
         table
     }
@@ -288,9 +281,10 @@ mod test {
             doc: Some(false),
             plugin: true,
             proc_macro: true,
-            harness: false,
+            harness: Some(false),
             edition: Some(Edition::E2015),
             crate_type: vec_s(&["staticlib"]),
+            crate_type_comment: None,
             required_features: vec_s(&["bar/biz"]),
         }
     }
@@ -365,6 +359,20 @@ proc-macro = true
 harness = false
 crate-type = ["staticlib"]
 required-features = ["bar/biz"]
+"#,
+        );
+        assert_eq!(
+            Product {
+                name: Some(s("foo")),
+                crate_type: vec_s(&["rlib", "cdylib"]),
+                crate_type_comment: Some(s("Buck builds cdylib only on linux")),
+                ..Product::default()
+            }
+            .to_toml(None, ProductType::Lib)
+            .to_string(),
+            r#"name = "foo"
+# Buck builds cdylib only on linux
+crate-type = ["cdylib", "rlib"]
 "#,
         );
     }
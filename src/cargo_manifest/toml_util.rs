@@ -26,6 +26,14 @@ fn decorate(value: Value) -> Value {
     value.decorated(" ", "")
 }
 
+/// Turn the text into lines of comments starting with the marker, to be used
+/// as a decor prefix of a key.
+pub fn comment_lines(marker: &str, text: &str) -> String {
+    text.lines()
+        .map(|line| format!("{marker} {line}").trim_end().to_owned() + "\n")
+        .collect()
+}
+
 pub fn new_implicit_table() -> Table {
     let mut table = Table::new();
     table.set_implicit(true);
@@ -113,6 +121,10 @@ pub fn edition_to_str(edition: &Edition) -> &'static str {
     }
 }
 
+/// Convert the value into an item, writing tables as [table] sections with keys
+/// in sorted order. Tables that contain only other tables are implicit, so that
+/// e.g. `[package.metadata.docs.rs]` is not preceded by empty
+/// `[package.metadata]` and `[package.metadata.docs]` headers.
 pub fn cargo_toml_to_toml_edit_item(value: &CValue) -> Item {
     match value {
         CValue::Array(vs) if vs.iter().all(CValue::is_table) => {
@@ -123,10 +135,13 @@ pub fn cargo_toml_to_toml_edit_item(value: &CValue) -> Item {
                 array
             }))
         }
-        CValue::Table(vs) => Item::Table(vs.iter().fold(Table::new(), |mut table, (k, v)| {
-            table[k] = cargo_toml_to_toml_edit_item(v);
-            table
-        })),
+        CValue::Table(vs) => Item::Table(vs.iter().sorted_by_key(|(k, _)| *k).fold(
+            new_implicit_table(),
+            |mut table, (k, v)| {
+                table[k] = cargo_toml_to_toml_edit_item(v);
+                table
+            },
+        )),
         other => Item::Value(cargo_toml_to_toml_edit_value(other)),
     }
 }
@@ -150,10 +165,13 @@ pub fn cargo_toml_to_toml_edit_value(value: &CValue) -> Value {
             value.into()
         }
         CValue::Table(vs) => {
-            let mut value = vs.iter().fold(InlineTable::default(), |mut table, (k, v)| {
-                table.get_or_insert(k.as_str(), cargo_toml_to_toml_edit_value(v));
-                table
-            });
+            let mut value = vs.iter().sorted_by_key(|(k, _)| *k).fold(
+                InlineTable::default(),
+                |mut table, (k, v)| {
+                    table.get_or_insert(k.as_str(), cargo_toml_to_toml_edit_value(v));
+                    table
+                },
+            );
             value.fmt();
             value.into()
         }
@@ -251,6 +269,34 @@ foo = 42
         );
     }
 
+    #[test]
+    fn cargo_toml_to_toml_edit_item_nested_tables_test() {
+        let metadata: CValue = toml::from_str(
+            r#"
+            [docs.rs]
+            rustdoc-args = ["--cfg", "docsrs"]
+            all-features = true
+
+            [foo]
+            bar = { z = 1, a = 2 }
+            "#,
+        )
+        .unwrap();
+        let mut table = new_implicit_table();
+        table["metadata"] = cargo_toml_to_toml_edit_item(&metadata);
+        assert_eq!(
+            toml_edit::DocumentMut::from(table).to_string(),
+            r#"[metadata.docs.rs]
+all-features = true
+rustdoc-args = ["--cfg", "docsrs"]
+
+[metadata.foo.bar]
+a = 2
+z = 1
+"#
+        );
+    }
+
     #[test]
     fn cargo_toml_to_toml_edit_value_test() {
         let mut table = new_implicit_table();
@@ -11,8 +11,10 @@
 //! materialized Configerator files and directly from Configerator
 
 use std::collections::BTreeMap;
+use std::collections::BTreeSet;
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::io::ErrorKind;
 use std::path::Path;
 use std::path::PathBuf;
 
@@ -22,6 +24,7 @@ use anyhow::Result;
 use anyhow::bail;
 use anyhow::ensure;
 use cargo_toml::Dependency;
+use cargo_toml::DepsSet;
 use cargo_toml::Edition;
 use cargo_toml::Profiles;
 use cargo_toml::Publish;
@@ -29,20 +32,37 @@ use cargo_toml::Value;
 use futures::StreamExt;
 use futures::TryFutureExt;
 use futures::TryStreamExt;
+use futures::future;
 use futures::stream;
 use futures::stream::BoxStream;
 use getset::Getters;
 use glob::Pattern;
 use glob::PatternError;
+use itertools::Itertools;
+use regex::Regex;
 use serde::Deserialize;
+use serde::Serialize;
 use tokio::fs::read_dir;
 use tokio::fs::read_to_string;
 use tokio_stream::wrappers::ReadDirStream;
 use toml::from_str;
 
+use crate::error::AutocargoError;
+use crate::paths::BuildFilenames;
 use crate::paths::PathInFbcode;
 use crate::paths::TargetsPath;
+use crate::util::deserialize::deserialize_glob;
 use crate::util::deserialize::deserialize_globs;
+use crate::util::deserialize::deserialize_globs_map;
+use crate::util::deserialize::deserialize_regexes;
+use crate::util::serialize::serialize_glob;
+use crate::util::serialize::serialize_globs;
+use crate::util::serialize::serialize_globs_map;
+use crate::util::serialize::serialize_sorted;
+
+/// Name of the file in the config dir that holds [GlobalConf] instead of a
+/// ProjectConf.
+pub const GLOBAL_CONFIG_FILENAME: &str = "_global.toml";
 
 /// A newtype for better tracking list of all projects.
 #[derive(Debug, Getters)]
@@ -50,6 +70,11 @@ use crate::util::deserialize::deserialize_globs;
 pub struct AllProjects {
     /// Map from name of the project to its config.
     projects: HashMap<String, ProjectConf>,
+    /// Configuration that is not specific to any project.
+    global: GlobalConf,
+    /// Names of the projects whose configs were not loaded, since they were
+    /// not needed by a run with lazily loaded configs.
+    unloaded_projects: BTreeSet<String>,
 }
 
 impl AllProjects {
@@ -65,58 +90,13 @@ impl AllProjects {
         paths: &[PathInFbcode],
         names: &[String],
     ) -> Result<SelectedProjects> {
-        let mut selected_by_path: HashSet<_> = self
-            .projects()
-            .iter()
-            .filter_map(|(name, c)| {
-                if paths.iter().any(|p| c.covers_path(p)) {
-                    Some(name)
-                } else {
-                    None
-                }
-            })
-            .collect();
-
-        // Making BFS on reverse graph of deps to gather all dependent projects
-        let mut to_process: HashSet<_> = selected_by_path.clone();
-        while !to_process.is_empty() {
-            to_process = self
-                .projects()
-                .iter()
-                .filter_map(|(name, c)| {
-                    if to_process.iter().any(|p| c.dependencies().contains(*p)) {
-                        Some(name)
-                    } else {
-                        None
-                    }
-                })
-                .collect();
-            // .copied() changes && -> &
-            to_process = to_process.difference(&selected_by_path).copied().collect();
-            selected_by_path.extend(to_process.iter().copied());
-        }
-
-        // Now process projects specified, including their dependencies (this
-        // time in the forward direction).
-        for name in names {
-            ensure!(
-                self.projects().contains_key(name),
-                "Project '{}' not recognised",
-                name
-            );
-        }
-        let mut selected_by_name: HashSet<_> = names.iter().collect();
-        let mut to_process = selected_by_name.clone();
-        while !to_process.is_empty() {
-            to_process = to_process
-                .iter()
-                .flat_map(|p| self.projects().get(*p).unwrap().dependencies().iter())
-                .collect();
-            to_process = to_process.difference(&selected_by_name).copied().collect();
-            selected_by_name.extend(to_process.iter().copied());
-        }
-
-        let selected = &selected_by_path | &selected_by_name;
+        let selected = select_names(
+            self.projects(),
+            ProjectConf::covers_path,
+            |conf| &conf.dependencies,
+            paths,
+            names,
+        )?;
 
         Ok(SelectedProjects::new(
             selected
@@ -126,6 +106,17 @@ impl AllProjects {
         ))
     }
 
+    /// Return projects that cover the provided path, sorted by name. Unlike
+    /// [Self::select_based_on_paths_and_names] the dependent projects are not
+    /// included.
+    pub fn projects_covering_path(&self, path: &PathInFbcode) -> Vec<&ProjectConf> {
+        self.projects()
+            .values()
+            .filter(|conf| conf.covers_path(path))
+            .sorted_by_key(|conf| conf.name())
+            .collect()
+    }
+
     /// Build up a map from path to project that covers that path. Uncovered
     /// paths are ignored.
     pub fn resolve_projects_for_paths<'a>(
@@ -144,6 +135,86 @@ impl AllProjects {
     }
 }
 
+/// Names of the projects that cover the provided paths or that depend on
+/// projects that cover them, together with the named projects and their
+/// dependencies.
+fn select_names<'a, P>(
+    projects: &'a HashMap<String, P>,
+    covers_path: impl Fn(&P, &PathInFbcode) -> bool,
+    dependencies: impl Fn(&P) -> &HashSet<String>,
+    paths: &[PathInFbcode],
+    names: &[String],
+) -> Result<HashSet<&'a String>> {
+    let mut selected_by_path: HashSet<_> = projects
+        .iter()
+        .filter_map(|(name, c)| {
+            if paths.iter().any(|p| covers_path(c, p)) {
+                Some(name)
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    // Making BFS on reverse graph of deps to gather all dependent projects
+    let mut to_process: HashSet<_> = selected_by_path.clone();
+    while !to_process.is_empty() {
+        to_process = projects
+            .iter()
+            .filter_map(|(name, c)| {
+                if to_process.iter().any(|p| dependencies(c).contains(*p)) {
+                    Some(name)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        // .copied() changes && -> &
+        to_process = to_process.difference(&selected_by_path).copied().collect();
+        selected_by_path.extend(to_process.iter().copied());
+    }
+
+    // Now process projects specified, including their dependencies (this
+    // time in the forward direction).
+    for name in names {
+        ensure!(
+            projects.contains_key(name),
+            "Project '{}' not recognised",
+            name
+        );
+    }
+    let selected_by_name = with_dependencies(
+        projects,
+        dependencies,
+        names
+            .iter()
+            .filter_map(|name| projects.get_key_value(name).map(|(name, _)| name))
+            .collect(),
+    );
+
+    Ok(&selected_by_path | &selected_by_name)
+}
+
+/// The projects together with all their direct and indirect dependencies.
+fn with_dependencies<'a, P>(
+    projects: &'a HashMap<String, P>,
+    dependencies: impl Fn(&P) -> &HashSet<String>,
+    mut selected: HashSet<&'a String>,
+) -> HashSet<&'a String> {
+    let mut to_process = selected.clone();
+    while !to_process.is_empty() {
+        to_process = to_process
+            .iter()
+            .filter_map(|p| projects.get(*p))
+            .flat_map(|conf| dependencies(conf).iter())
+            .filter_map(|dep| projects.get_key_value(dep).map(|(name, _)| name))
+            .collect();
+        to_process = to_process.difference(&selected).copied().collect();
+        selected.extend(to_process.iter().copied());
+    }
+    selected
+}
+
 /// Wrappping SelectedProjects in a module will prevent from using its struct
 /// constructor, forcing usage of SelectedProjects::new that sorts the input.
 mod selected_projects {
@@ -162,26 +233,39 @@ mod selected_projects {
             projects.sort_unstable_by_key(|c| c.name());
             Self { projects }
         }
+
+        /// Keep only the projects for which the predicate returns true.
+        pub fn retain(&mut self, f: impl FnMut(&&'a ProjectConf) -> bool) {
+            self.projects.retain(f);
+        }
     }
 }
 pub use selected_projects::SelectedProjects;
 
 /// Configuration of a project
-#[derive(Debug, Deserialize, Getters)]
+#[derive(Debug, Deserialize, Serialize, Getters)]
 #[getset(get = "pub")]
 #[serde(deny_unknown_fields)]
 pub struct ProjectConf {
     /// Name of the project, used mostly as ID and for printing.
     name: String,
     /// Project roots which contain the files.
-    #[serde(default)]
+    #[serde(default, serialize_with = "serialize_sorted")]
     roots: HashSet<String>,
     /// Set of globs that point to folders containing TARGETS and Cargo.toml
     /// files.
-    #[serde(default, deserialize_with = "deserialize_globs")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_globs",
+        serialize_with = "serialize_globs"
+    )]
     include_globs: HashSet<Pattern>,
     /// Set of globs that exclude folders or files added by include_globs.
-    #[serde(default, deserialize_with = "deserialize_globs")]
+    #[serde(
+        default,
+        deserialize_with = "deserialize_globs",
+        serialize_with = "serialize_globs"
+    )]
     exclude_globs: HashSet<Pattern>,
     /// Oncall that is responsible for this project.
     oncall: String,
@@ -190,10 +274,17 @@ pub struct ProjectConf {
     /// manually maintained project.
     #[serde(default)]
     manual_cargo_toml: bool,
+    /// Directories whose subtrees keep manually maintained Cargo.toml files
+    /// while the rest of the project is generated, e.g. while migrating a
+    /// large project to autocargo piece by piece. Nothing is generated for
+    /// Cargo.toml files in them, dependencies on their rules use the package
+    /// names and features declared in the manual Cargo.toml files.
+    #[serde(default)]
+    manual_dirs: Vec<PathInFbcode>,
     /// Set of direct dependencies of this project. If one of the dependencies
     /// will change then all projects that depend on it (directly or indirectly)
     /// will be regenerated.
-    #[serde(default)]
+    #[serde(default, serialize_with = "serialize_sorted")]
     dependencies: HashSet<String>,
     /// Configuration for project if it is being shipped to an external git
     /// repository
@@ -207,11 +298,337 @@ pub struct ProjectConf {
     /// Paths to generate a Cargo.lock
     #[serde(default)]
     cargo_locks: Vec<PathInFbcode>,
+    /// Path to a markdown file that will list all crates generated for this
+    /// project together with their paths, descriptions and Buck rules, e.g.
+    /// to be referenced from the README of an OSS repository.
+    crate_index: Option<PathInFbcode>,
+    /// Map from name of a dependency to name of a third-party crate whose
+    /// version it must follow, also accepted as `version_locked_pairs`. The
+    /// version of such dependency is set to the version of the leader in the
+    /// same Cargo.toml or, if the crate doesn't depend on the leader and the
+    /// dependency is modified via dependencies_override, to the version of the
+    /// leader from third-party/rust/Cargo.toml, so that e.g. cxx-build stays in
+    /// lockstep with cxx. Defaults to cxx-build following cxx, serde_derive
+    /// following serde and thiserror-impl following thiserror, provide an
+    /// empty map to turn it off.
+    #[serde(default = "default_version_lockstep", alias = "version_locked_pairs")]
+    version_lockstep: BTreeMap<String, String>,
+    /// If true, which is the default, then dev-dependencies that are the same
+    /// as regular dependencies of the same name are not repeated in the
+    /// [dev-dependencies] section. Set to false to keep them explicit.
+    #[serde(default = "default_dedupe_dev_dependencies")]
+    dedupe_dev_dependencies: bool,
+    /// Map from package name of a crate of this project that is scheduled for
+    /// removal to its deprecation notice. The notice is put in
+    /// [package.metadata.deprecation] of the crate and autocargo reports the
+    /// generated crates that still depend on it.
+    #[serde(default)]
+    deprecated_crates: BTreeMap<String, CrateDeprecation>,
+    /// Settings applied to all benches declared in cargo_toml_config.bench of
+    /// the crates of this project.
+    #[serde(default)]
+    bench_defaults: BenchDefaults,
+    /// Maximum number of files of this project that autocargo may delete in a
+    /// single run because they are no longer generated, overriding the value
+    /// of --max-deletions. Above it the run aborts unless --force is passed.
+    max_deletions: Option<usize>,
+    /// Content of a .cargo/config.toml file generated for this project, e.g.
+    /// with registry, net or target-specific rustflags settings needed to
+    /// build it in OSS.
+    cargo_config: Option<CargoConfig>,
+    /// If true then a dependency removed via extra_buck_dependencies that
+    /// doesn't match any dependency generated from Buck fails the generation,
+    /// otherwise it is only reported as a warning.
+    #[serde(default)]
+    strict_removed_dependencies: bool,
+    /// If true then autobins, autoexamples, autotests and autobenches of the
+    /// generated packages are set to false for the kinds of products that are
+    /// emitted explicitly, so that Cargo doesn't define them twice. Crates can
+    /// override it via cargo_toml_config.package.
+    #[serde(default)]
+    disable_autodiscovery: bool,
+    /// Directory in which a .autocargo_manifest.json file is generated that
+    /// lists every file generated for this project together with the sha256
+    /// hash of its content, so that tooling can tell generated files from
+    /// hand-edited ones without re-running the generation. Files with manual
+    /// sections are hashed without them.
+    file_manifest_dir: Option<PathInFbcode>,
+    /// Newest edition supported by the toolchain the project is built with.
+    /// Generation fails if a crate of the project uses a newer edition, be it
+    /// from the rule, cargo_toml_config or the project defaults.
+    max_edition: Option<Edition>,
+    /// How the features of bin and test rules are put in the generated
+    /// packages, unless overridden via cargo_target_config of the rule.
+    #[serde(default)]
+    feature_propagation: FeaturePropagation,
+    /// Name of a feature, e.g. "test-utils", that the test_features of the
+    /// rules are put in instead of the default features, so that test-only
+    /// features don't leak to the dependents. Packages with a library
+    /// dev-depend on themselves with the feature enabled, so that it is
+    /// active in their tests like in Buck.
+    test_features_feature: Option<String>,
+    /// Map from constraint values that Buck rules of this project select()
+    /// dependencies on to the Cargo features enabling them. The dependencies
+    /// of a mapped select() branch are generated as optional and activated by
+    /// the feature, the ones of unmapped branches are skipped. E.g.:
+    ///
+    /// ```toml
+    /// [select_features]
+    /// "ovr_config//third-party/tls:openssl" = "openssl"
+    /// ```
+    #[serde(default)]
+    select_features: BTreeMap<String, String>,
+    /// What to do when a dependency set of a crate has the same name resolved
+    /// both to a third-party crate and to a crate generated from fbcode.
+    /// Crates of the whole run that use both kinds of such a name are
+    /// reported regardless of it.
+    #[serde(default)]
+    dual_source_dependencies: DualSourcePolicy,
+    /// Template of the comment lines put at the top of the Cargo.toml files
+    /// generated for this project, e.g. to mention the oncall or the command
+    /// regenerating them. `{marker}` is replaced by the marker that autocargo
+    /// recognizes its files by and must be present, `{rule}`, `{project}` and
+    /// `{oncall}` by the Buck rules of the file, the name of the project and
+    /// its oncall. Each line of the template becomes a comment line. Defaults
+    /// to `{marker} from {rule}`, e.g.:
+    ///
+    /// ```toml
+    /// preamble = """
+    /// {marker} from {rule}
+    /// To regenerate run `buck run //common/rust/cargo_from_buck:autocargo -- -p {project}`
+    /// """
+    /// ```
+    preamble: Option<String>,
+}
+
+fn default_version_lockstep() -> BTreeMap<String, String> {
+    BTreeMap::from([
+        ("cxx-build".to_owned(), "cxx".to_owned()),
+        ("serde_derive".to_owned(), "serde".to_owned()),
+        ("thiserror-impl".to_owned(), "thiserror".to_owned()),
+    ])
+}
+
+fn default_dedupe_dev_dependencies() -> bool {
+    true
+}
+
+/// Deprecation notice of a crate that is scheduled for removal.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CrateDeprecation {
+    /// Why the crate is deprecated and what to do about it.
+    pub message: String,
+    /// Name of the crate that should be used instead.
+    pub replacement: Option<String>,
+    /// Date after which the crate may be removed, e.g. "2026-12-31".
+    pub removal_date: Option<String>,
+    /// If true then a build script printing the message as a Cargo warning is
+    /// generated for the crate, so that its users notice the deprecation when
+    /// building it. Not supported for crates that already have a generated
+    /// build script, e.g. thrift libraries.
+    #[serde(default)]
+    pub build_warning: bool,
+}
+
+/// Settings applied to benches declared in cargo_toml_config.bench, so that
+/// e.g. criterion doesn't have to be wired by hand in every crate.
+///
+/// Example:
+/// ```text
+/// [bench_defaults]
+/// harness = false
+/// dev_dependencies = { criterion = "0.5" }
+/// ```
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct BenchDefaults {
+    /// Value of `harness` for benches that don't set it themselves.
+    pub harness: Option<bool>,
+    /// Dev-dependencies added to every crate with at least one bench, unless
+    /// the crate already has a dev-dependency of the same name.
+    pub dev_dependencies: DepsSet,
+}
+
+/// Configuration shared by all projects, read from [GLOBAL_CONFIG_FILENAME].
+#[derive(Debug, Default, Deserialize, Getters)]
+#[serde(default, deny_unknown_fields)]
+#[getset(get = "pub")]
+pub struct GlobalConf {
+    /// How to invoke Buck.
+    buck: BuckConfig,
+    /// Map from name of an alternative registry used by crates in
+    /// fbsource/third-party/rust/Cargo.toml to its index URL. Dependencies
+    /// and [patch] sections of the generated crates refer to these registries
+    /// by their index, so that the crates build without the registries being
+    /// configured for Cargo. Registries that are not listed here are kept by
+    /// name.
+    ///
+    /// Example:
+    /// ```text
+    /// [registries]
+    /// mirror = "sparse+https://crates-mirror.example.com/index/"
+    /// ```
+    registries: BTreeMap<String, String>,
+    /// Regexes matching first lines of files generated by older versions of
+    /// autocargo, before the current preamble was introduced. Such files are
+    /// treated as generated, e.g. deleted once they are no longer generated,
+    /// instead of as manually maintained ones.
+    ///
+    /// Example:
+    /// ```text
+    /// legacy_preambles = ["^# Generated by cargo_from_buck"]
+    /// ```
+    #[serde(deserialize_with = "deserialize_regexes")]
+    legacy_preambles: Vec<Regex>,
+    /// Map from fully qualified Buck rules outside of fbcode and
+    /// fbsource//third-party/rust, e.g. in the xplat cell, to the dependency
+    /// they are turned into. Paths of the dependencies are relative to the
+    /// root of fbsource and are rewritten to be relative to each generated
+    /// Cargo.toml. The name of the rule is used as the package name unless
+    /// the package is set. Dependencies on rules of other cells that are not
+    /// listed here are ignored.
+    ///
+    /// Example:
+    /// ```text
+    /// [cell_dependencies]
+    /// "xplat//rust/foo:foo" = { path = "xplat/rust/foo" }
+    /// "xplat//rust/bar:bar-lib" = { git = "https://github.com/org/bar", package = "bar" }
+    /// ```
+    cell_dependencies: DepsSet,
+    /// Path to the rust-toolchain or rust-toolchain.toml file of the
+    /// repository relative to the root of fbsource. If set, the channel of the
+    /// toolchain, which has to be a stable release, is the default
+    /// package.rust_version of the generated Cargo.toml files, e.g. "1.83" for
+    /// channel "1.83.0". Projects override it with
+    /// defaults.package.rust_version and rules with their rust_version.
+    ///
+    /// Example:
+    /// ```text
+    /// rust_toolchain = "fbcode/rust-toolchain.toml"
+    /// ```
+    rust_toolchain: Option<PathBuf>,
+    /// Command listing the files of the checkout that match glob patterns.
+    /// If set, the TARGETS, Cargo.toml and other files of the projects are
+    /// discovered with it instead of walking their roots, which is slow on
+    /// EdenFS checkouts and faults in every visited file.
+    ///
+    /// Example:
+    /// ```text
+    /// [file_discovery]
+    /// binary = "hg"
+    /// args = ["files"]
+    /// pattern_prefix = "glob:"
+    /// ```
+    file_discovery: Option<FileDiscoveryConfig>,
+    /// Filenames recognized as Buck build files in the repository, e.g. in
+    /// repos that mix Buck and Bazel conventions. Defaults to TARGETS, BUCK,
+    /// TARGETS.v2 and BUCK.v2, --build-filename overrides it for a single run.
+    ///
+    /// Example:
+    /// ```text
+    /// build_filenames = ["BUILD", "BUILD.bazel"]
+    /// ```
+    build_filenames: BuildFilenames,
+}
+
+impl GlobalConf {
+    /// Read the [GLOBAL_CONFIG_FILENAME] file directly in the config dir, the
+    /// default config is used if there is no such file.
+    pub async fn from_dir(dir: impl AsRef<Path>) -> Result<Self, AutocargoError> {
+        Self::from_dir_impl(dir.as_ref())
+            .await
+            .map_err(AutocargoError::config)
+    }
+
+    async fn from_dir_impl(dir: &Path) -> Result<Self> {
+        let global_path = dir.join(GLOBAL_CONFIG_FILENAME);
+        match read_to_string(&global_path).await {
+            Ok(content) => from_str(&content)
+                .with_context(|| format!("While processing config file {}", global_path.display())),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(Error::from(err).context(format!(
+                "While reading config file {}",
+                global_path.display()
+            ))),
+        }
+    }
+}
+
+/// How to invoke Buck, e.g. to run in a sandboxed CI environment or through
+/// a wrapper of Buck.
+///
+/// Example:
+/// ```text
+/// [buck]
+/// binary = "/opt/ci/bin/buck2-wrapper"
+/// args = ["--isolation-dir=ci"]
+/// command_args = ["--config", "client.id=ci"]
+/// ```
+#[derive(Debug, Deserialize, Eq, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct BuckConfig {
+    /// Buck binary to run, either a path or a name looked up in PATH.
+    pub binary: PathBuf,
+    /// Args passed before the subcommand, e.g. `--isolation-dir`.
+    pub args: Vec<String>,
+    /// Args passed after the subcommand, e.g. `--config` flags.
+    pub command_args: Vec<String>,
+}
+
+impl Default for BuckConfig {
+    fn default() -> Self {
+        Self {
+            binary: PathBuf::from("buck2"),
+            args: Vec::new(),
+            command_args: Vec::new(),
+        }
+    }
+}
+
+/// How to list the files of the checkout instead of walking the filesystem,
+/// see [GlobalConf::file_discovery]. The command is run in the root of
+/// fbsource with the args followed by glob patterns relative to it, e.g.
+/// `fbcode/foo/**/TARGETS`, and must print the matching files one per line,
+/// relative to the root of fbsource too.
+#[derive(Debug, Deserialize, Eq, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct FileDiscoveryConfig {
+    /// Binary to run, either a path or a name looked up in PATH, e.g. "hg"
+    /// or "eden".
+    pub binary: PathBuf,
+    /// Args passed before the patterns, e.g. `["files"]` or `["glob"]`.
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Prefix of each pattern, e.g. "glob:" for `hg files`.
+    #[serde(default)]
+    pub pattern_prefix: String,
+}
+
+/// Configuration of the .cargo/config.toml file generated for a project.
+///
+/// Example:
+/// ```text
+/// [cargo_config.content.net]
+/// git-fetch-with-cli = true
+///
+/// [cargo_config.content.target.x86_64-unknown-linux-gnu]
+/// rustflags = ["-C", "target-cpu=native"]
+/// ```
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct CargoConfig {
+    /// Directory in which the .cargo directory is generated, it must be
+    /// covered by the project. Defaults to the directory of the workspace
+    /// Cargo.toml if workspace_config is set.
+    pub dir: Option<PathInFbcode>,
+    /// Tables of the generated file.
+    pub content: BTreeMap<String, Value>,
 }
 
 /// Holds configuration for projects that are being shipped to external git
 /// repository.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct OssGitConfig {
     /// If set, this is the place where oss-ready Cargo.toml files will be stored
@@ -222,6 +639,8 @@ pub struct OssGitConfig {
     ///   use git-dependencies as per their OssGitConfig setup
     /// - fbcode dependencies on crates from projects with no OssGitConfig will
     ///   be stripped
+    /// - dependencies matching oss_dependency_overrides will be removed or
+    ///   replaced
     ///
     /// The layout of oss-ready Cargo.toml files inside public_cargo_dir will
     /// match the layout of non-oss-ready Cargo.toml files realtive to parent of
@@ -272,6 +691,109 @@ pub struct OssGitConfig {
     /// Cargo features are path structured, so if you specify foo, it will also strip bar/foo
     #[serde(default)]
     pub default_features_to_strip: Vec<String>,
+    /// If true then files referenced by `readme` and `license-file` are copied
+    /// next to the Cargo.toml files generated in public_cargo_dir and the
+    /// fields point to the copies, so the published crates are self-contained
+    /// even if the original files are not shipped. Copies that are no longer
+    /// made are deleted if they are named README.md or like a license file,
+    /// e.g. LICENSE-MIT.
+    #[serde(default)]
+    pub copy_package_files: bool,
+    /// If true then a minimal README.md, made of the package description and
+    /// repository, is generated next to each Cargo.toml file generated in
+    /// public_cargo_dir whose package has no readme, since crates.io expects
+    /// published crates to have one.
+    #[serde(default)]
+    pub generate_readme: bool,
+    /// Changes of dependencies in the oss-ready Cargo.toml files, e.g. to
+    /// remove internal-only crates or swap them for their public counterparts.
+    /// The first entry whose pattern matches the name of a dependency is
+    /// applied.
+    ///
+    /// Example:
+    /// ```text
+    /// [[oss_git_config.oss_dependency_overrides]]
+    /// action = "remove"
+    /// pattern = "fbinit*"
+    ///
+    /// [[oss_git_config.oss_dependency_overrides]]
+    /// action = "replace-with-git"
+    /// pattern = "fb303_core"
+    /// git = "https://github.com/facebook/fb303.git"
+    /// branch = "main"
+    ///
+    /// [[oss_git_config.oss_dependency_overrides]]
+    /// action = "replace-with-version"
+    /// pattern = "internal_shim"
+    /// version = "0.3"
+    /// ```
+    #[serde(default)]
+    pub oss_dependency_overrides: Vec<OssDependencyOverride>,
+}
+
+impl OssGitConfig {
+    /// The first of oss_dependency_overrides matching the name of the
+    /// dependency.
+    pub fn oss_dependency_override(&self, name: &str) -> Option<&OssDependencyOverride> {
+        self.oss_dependency_overrides
+            .iter()
+            .find(|dep_override| dep_override.pattern().matches(name))
+    }
+}
+
+/// Change of the dependencies matching the pattern in oss-ready Cargo.toml
+/// files.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "action", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum OssDependencyOverride {
+    /// Leave the dependency out.
+    Remove {
+        /// Glob matching names of the dependencies.
+        #[serde(
+            deserialize_with = "deserialize_glob",
+            serialize_with = "serialize_glob"
+        )]
+        pattern: Pattern,
+    },
+    /// Make it a git dependency.
+    ReplaceWithGit {
+        /// Glob matching names of the dependencies.
+        #[serde(
+            deserialize_with = "deserialize_glob",
+            serialize_with = "serialize_glob"
+        )]
+        pattern: Pattern,
+        /// Url of the git repo.
+        git: String,
+        /// Optional branch of the git repo.
+        branch: Option<String>,
+        /// Optional tag of the git repo.
+        tag: Option<String>,
+        /// Optional rev of the git repo.
+        rev: Option<String>,
+    },
+    /// Make it a registry dependency of the version.
+    ReplaceWithVersion {
+        /// Glob matching names of the dependencies.
+        #[serde(
+            deserialize_with = "deserialize_glob",
+            serialize_with = "serialize_glob"
+        )]
+        pattern: Pattern,
+        /// Version requirement of the dependency.
+        version: String,
+    },
+}
+
+impl OssDependencyOverride {
+    /// Glob matching names of the dependencies the override applies to.
+    pub fn pattern(&self) -> &Pattern {
+        match self {
+            Self::Remove { pattern }
+            | Self::ReplaceWithGit { pattern, .. }
+            | Self::ReplaceWithVersion { pattern, .. } => pattern,
+        }
+    }
 }
 
 /// Configuration for generating root Cargo.toml with autodiscovered [workspace]
@@ -279,7 +801,7 @@ pub struct OssGitConfig {
 /// autocargo that are under the configured `scrape_dir`. Additionally this root
 /// Cargo.toml will contain a [patch] section based on
 /// fbsource/third-party/rust/Cargo.toml.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct WorkspaceConfig {
     /// All Cargo.toml files generated by autocargo under the `scrape_dir`
@@ -314,6 +836,99 @@ pub struct WorkspaceConfig {
     /// and introduces a custom patch for `bytecount`.
     #[serde(default)]
     pub patch: PatchGenerationInput,
+    /// If true then dependencies declared the same way by all workspace
+    /// members are written once into [workspace.dependencies] and the
+    /// members refer to them with `foo.workspace = true`.
+    #[serde(default)]
+    pub inherit_dependencies: bool,
+    /// Fields of [package] that should be written once into
+    /// [workspace.package] based on the project's `defaults.package` and
+    /// inherited by members with `field.workspace = true`. Members that
+    /// override a field via cargo_toml_config keep their own value.
+    ///
+    /// Example:
+    /// ```text
+    /// inherit_package_fields = ["version", "edition", "license"]
+    /// ```
+    #[serde(default)]
+    pub inherit_package_fields: Vec<InheritablePackageField>,
+    /// Directories of manually maintained Cargo.toml files, i.e. not generated
+    /// by autocargo, that should be members of this workspace as well. Each
+    /// of them must contain a Cargo.toml file. Paths of the members are
+    /// computed the same way as for generated ones, relative to scrape_dir and
+    /// prefixed with prefix_for_dir.
+    #[serde(default)]
+    pub extra_members: Vec<PathInFbcode>,
+    /// If set then a rustfmt.toml file with this content is generated next to
+    /// the workspace Cargo.toml, so that OSS builds are formatted the same way
+    /// as internal ones.
+    ///
+    /// Example:
+    /// ```text
+    /// [workspace_config.rustfmt]
+    /// edition = "2021"
+    /// imports_granularity = "Item"
+    /// ```
+    #[serde(default)]
+    pub rustfmt: Option<BTreeMap<String, Value>>,
+    /// If set then a clippy.toml file with this content is generated next to
+    /// the workspace Cargo.toml, see `rustfmt`.
+    #[serde(default)]
+    pub clippy: Option<BTreeMap<String, Value>>,
+    /// If set then a .config/nextest.toml file with this content is generated
+    /// next to the workspace Cargo.toml, so that cargo-nextest runs the tests
+    /// with the same profiles and test groups everywhere.
+    ///
+    /// Example:
+    /// ```text
+    /// [workspace_config.nextest.profile.default]
+    /// slow-timeout = "60s"
+    ///
+    /// [workspace_config.nextest.test-groups]
+    /// serial = { max-threads = 1 }
+    /// ```
+    #[serde(default)]
+    pub nextest: Option<BTreeMap<String, Value>>,
+    /// Globs matching paths of members relative to scrape_dir that are put in
+    /// [workspace.default-members], e.g. to keep heavy crates out of a plain
+    /// `cargo build`. Each glob must match at least one member.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_globs",
+        serialize_with = "serialize_globs"
+    )]
+    pub default_members_globs: HashSet<Pattern>,
+    /// Globs matching paths of generated crates relative to scrape_dir that
+    /// are moved from the members to [workspace.exclude], e.g. test fixtures.
+    /// Each glob must match at least one member.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_globs",
+        serialize_with = "serialize_globs"
+    )]
+    pub exclude_globs: HashSet<Pattern>,
+}
+
+/// Fields of [package] that can be inherited from [workspace.package], see
+/// https://doc.rust-lang.org/cargo/reference/workspaces.html#the-package-table
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "kebab-case")]
+#[allow(missing_docs)]
+pub enum InheritablePackageField {
+    Version,
+    Authors,
+    Edition,
+    RustVersion,
+    Description,
+    Documentation,
+    Homepage,
+    Repository,
+    License,
+    Keywords,
+    Categories,
+    Exclude,
+    Include,
+    Publish,
 }
 
 /// Decide how to generate the [patch] section.
@@ -321,27 +936,46 @@ pub struct WorkspaceConfig {
 /// The patch section can be generated based on the `mode`.  See
 /// `PatchGenerationMode` for a description of each mode.
 ///
-/// Once generated, entries can be excluded by adding them to
-/// the `exclude` entry.
+/// Once generated, entries can be excluded by adding them or glob patterns
+/// matching their names to the `exclude` entry. The entries copied from
+/// third-party can also be limited to the ones matching the `only` entry.
 ///
 /// Example:
 /// ```text
 /// exclude = {
-///     "crates-io": ["foo", "bar"]
+///     "crates-io": ["foo", "bar-*"]
+/// }
+/// only = {
+///     "https://github.com/facebook/fbthrift.git": ["fbthrift*"]
 /// }
 /// ```
 ///
-/// This example will exclude the `foo` and `bar` crates from the `crates-io`
-/// registry patches.
-#[derive(Debug, Deserialize, Default)]
+/// This example will exclude the `foo` crate and crates starting with `bar-`
+/// from the `crates-io` registry patches and keep only the fbthrift crates
+/// patched from its git repository.
+#[derive(Debug, Deserialize, Serialize, Default)]
 #[serde(deny_unknown_fields)]
 pub struct PatchGeneration {
     /// Mode of patch generation to use.
     pub mode: PatchGenerationMode,
 
-    /// Names of packages to exclude for each source.
-    #[serde(default)]
-    pub exclude: HashMap<String, Vec<String>>,
+    /// Globs matching names of packages to exclude for each source.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_globs_map",
+        serialize_with = "serialize_globs_map"
+    )]
+    pub exclude: HashMap<String, HashSet<Pattern>>,
+
+    /// Globs matching names of packages copied from third-party to keep for
+    /// each source, the other ones are left out. Sources that are not listed
+    /// here keep all of their packages.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_globs_map",
+        serialize_with = "serialize_globs_map"
+    )]
+    pub only: HashMap<String, HashSet<Pattern>>,
 }
 
 impl PatchGeneration {
@@ -362,8 +996,36 @@ impl PatchGeneration {
     }
 }
 
+/// How features of bin and test rules combined into a package end up in its
+/// [features] section. Features of the library are always default ones.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FeaturePropagation {
+    /// Make them default features of the package.
+    #[default]
+    Default,
+    /// Group them in a separate `bin-<rule>` or `test-<rule>` feature.
+    Named,
+    /// Leave them out of the package.
+    Drop,
+}
+
+/// Resolution of dependency names that resolve both to a third-party crate
+/// and to a crate generated from fbcode within one dependency set.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DualSourcePolicy {
+    /// Fail the generation.
+    #[default]
+    Error,
+    /// Keep the crate generated from fbcode.
+    PreferFbcode,
+    /// Keep the third-party crate.
+    PreferThirdParty,
+}
+
 /// Modes of patch generation.
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Deserialize, Serialize, Default)]
 #[serde(rename_all = "kebab-case")]
 pub enum PatchGenerationMode {
     /// Generate no entries.
@@ -375,7 +1037,7 @@ pub enum PatchGenerationMode {
 
 /// A structure for describing a custom [patch] section that might mix values
 /// copied from fbsource/third-party/rust/Cargo.toml and custom ones.
-#[derive(Debug, Default, Deserialize)]
+#[derive(Debug, Default, Deserialize, Serialize)]
 pub struct PatchGenerationInput(pub BTreeMap<String, Vec<PatchGenerationInputDep>>);
 
 /// Iterator of patch generation input items.
@@ -403,7 +1065,7 @@ impl PatchGenerationInput {
 ///   "foo" from registry "crates-io" using the entry from third-party
 /// - `PatchGenerationInputDep::Dependency("bar", <Dep with git = "bar.com">)`,
 ///   which will patch "bar" from registry "crates-io" with `{ git = "bar.com" }`
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(untagged)]
 #[allow(clippy::large_enum_variant)]
 pub enum PatchGenerationInputDep {
@@ -417,7 +1079,7 @@ pub enum PatchGenerationInputDep {
 /// The attributes here are based on [::cargo_toml::Manifest] amd
 /// [::cargo_toml::Package] plus some fields from
 /// https://doc.rust-lang.org/cargo/reference/manifest.html.
-#[derive(Debug, Deserialize, Default)]
+#[derive(Debug, Deserialize, Serialize, Default)]
 #[serde(default, deny_unknown_fields)]
 pub struct ProjectConfDefaults {
     /// Default values for "cargo-features" value of Cargo.toml.
@@ -442,12 +1104,26 @@ pub struct ProjectConfDefaults {
     /// and introduces a custom patch for `bytecount`.
     #[serde(default)]
     pub patch: PatchGenerationInput,
-    /// Default value for [profile] section of Cargo.toml.
+    /// Default value for [profile] section of Cargo.toml. For projects with
+    /// workspace_config it is put into the workspace root instead, since
+    /// Cargo ignores profiles of workspace members.
     pub profile: Profiles,
+    /// Map from package name of a third-party crate to the version that every
+    /// generated dependency on it should require instead of the one from
+    /// fbsource/third-party/rust/Cargo.toml, e.g. when the OSS build has to
+    /// stay on an older major version. dependencies_override still takes
+    /// precedence.
+    ///
+    /// Example:
+    /// ```text
+    /// [defaults.dependency_versions]
+    /// tokio = "0.2"
+    /// ```
+    pub dependency_versions: BTreeMap<String, String>,
 }
 
 /// Default values for [package] section of Cargo.toml.
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize)]
 #[serde(default, deny_unknown_fields)]
 #[allow(missing_docs)]
 pub struct PackageDefaults {
@@ -476,6 +1152,9 @@ pub struct PackageDefaults {
     pub include: Vec<String>,
     pub publish: Publish,
     pub metadata: Option<Value>,
+    /// Table added as [package.metadata.docs.rs] to packages whose metadata
+    /// doesn't have it, see https://docs.rs/about/metadata
+    pub docs_rs: Option<Value>,
 }
 
 impl Default for PackageDefaults {
@@ -500,10 +1179,25 @@ impl Default for PackageDefaults {
             include: Vec::new(),
             publish: Publish::default(),
             metadata: None,
+            docs_rs: None,
         }
     }
 }
 
+/// Paths and contents of the project config files in the dir.
+fn read_config_files(dir: &Path) -> BoxStream<'static, Result<(PathBuf, String)>> {
+    let global_path = dir.join(GLOBAL_CONFIG_FILENAME);
+    process_dir(dir.to_owned())
+        .try_filter(move |path| future::ready(*path != global_path))
+        .and_then(|path| async move {
+            let content = read_to_string(&path)
+                .await
+                .with_context(|| format!("While reading config file {}", path.display()))?;
+            Ok((path, content))
+        })
+        .boxed()
+}
+
 fn process_dir(dir: PathBuf) -> BoxStream<'static, Result<PathBuf>> {
     async move {
         Ok(ReadDirStream::new(read_dir(dir).await?)
@@ -532,19 +1226,136 @@ fn process_dir(dir: PathBuf) -> BoxStream<'static, Result<PathBuf>> {
 impl ProjectConf {
     /// Read the provided folder and deserialize each .toml file in it as
     /// TOML-encoded ProjectConf, then validate it and return AllProjects struct.
-    pub async fn from_dir(dir: impl AsRef<Path>) -> Result<AllProjects> {
-        let dir = dir.as_ref();
-        let configs = process_dir(dir.to_owned())
-            .and_then(|path| async move {
-                let result: Result<Self> = try { from_str(&read_to_string(&path).await?)? };
-                result.with_context(|| format!("While processing config file {}", path.display()))
+    /// The [GLOBAL_CONFIG_FILENAME] file directly in the folder is deserialized
+    /// as GlobalConf instead.
+    pub async fn from_dir(dir: impl AsRef<Path>) -> Result<AllProjects, AutocargoError> {
+        Self::from_dir_impl(dir.as_ref())
+            .await
+            .map_err(AutocargoError::config)
+    }
+
+    async fn from_dir_impl(dir: &Path) -> Result<AllProjects> {
+        let configs = read_config_files(dir)
+            .and_then(|(path, content)| async move {
+                from_str(&content)
+                    .with_context(|| format!("While processing config file {}", path.display()))
+            })
+            .try_collect()
+            .await
+            .with_context(|| format!("While processing config dir {}", dir.display()))?;
+
+        Ok(AllProjects {
+            projects: validate_projects(configs)?,
+            global: GlobalConf::from_dir_impl(dir).await?,
+            unloaded_projects: BTreeSet::new(),
+        })
+    }
+
+    /// Like [Self::from_dir], but only the projects that the run with the
+    /// provided paths and project names needs are fully deserialized and
+    /// validated: the selected ones together with their dependencies. Other
+    /// config files are only checked for the name, coverage and dependencies
+    /// of the project, which keeps the startup fast with many projects. If
+    /// both paths and names are empty then all projects are loaded.
+    pub async fn from_dir_lazy(
+        dir: impl AsRef<Path>,
+        paths: &[PathInFbcode],
+        names: &[String],
+    ) -> Result<AllProjects, AutocargoError> {
+        Self::from_dir_lazy_impl(dir.as_ref(), paths, names)
+            .await
+            .map_err(AutocargoError::config)
+    }
+
+    async fn from_dir_lazy_impl(
+        dir: &Path,
+        paths: &[PathInFbcode],
+        names: &[String],
+    ) -> Result<AllProjects> {
+        if paths.is_empty() && names.is_empty() {
+            return Self::from_dir_impl(dir).await;
+        }
+        let entries: Vec<(PathBuf, String, ProjectIndexEntry)> = read_config_files(dir)
+            .and_then(|(path, content)| async move {
+                let entry = from_str(&content)
+                    .with_context(|| format!("While processing config file {}", path.display()))?;
+                Ok((path, content, entry))
             })
             .try_collect()
             .await
             .with_context(|| format!("While processing config dir {}", dir.display()))?;
 
+        let mut index = HashMap::new();
+        for (path, content, entry) in entries {
+            let name = entry.name.clone();
+            if index.insert(name.clone(), (path, content, entry)).is_some() {
+                bail!(
+                    "The names of projects are not unique, one of the offenders is: {}",
+                    name
+                );
+            }
+        }
+        let selected = select_names(
+            &index,
+            |(_, _, entry), path| entry.covers_path(path),
+            |(_, _, entry)| &entry.dependencies,
+            paths,
+            names,
+        )?;
+        let needed = with_dependencies(&index, |(_, _, entry)| &entry.dependencies, selected);
+
+        let configs = needed
+            .iter()
+            .map(|name| {
+                let (path, content, _) = &index[*name];
+                from_str(content)
+                    .with_context(|| format!("While processing config file {}", path.display()))
+            })
+            .collect::<Result<_>>()?;
+        let unloaded_projects = index
+            .keys()
+            .filter(|name| !needed.contains(name))
+            .cloned()
+            .collect();
+
         Ok(AllProjects {
             projects: validate_projects(configs)?,
+            global: GlobalConf::from_dir_impl(dir).await?,
+            unloaded_projects,
+        })
+    }
+
+    /// Read the project configs from a single bundle file instead of a dir,
+    /// e.g. one materialized by configuration management for hermetic CI
+    /// runs, and validate them like [Self::from_dir] does. A bundle with the
+    /// `.json` extension is a JSON array of project configs, otherwise it is
+    /// TOML whose top-level tables are project configs keyed by an arbitrary
+    /// name, e.g. the name of the config file they come from. The
+    /// [GLOBAL_CONFIG_FILENAME] file is still read from dir.
+    pub async fn from_bundle(
+        bundle: impl AsRef<Path>,
+        dir: impl AsRef<Path>,
+    ) -> Result<AllProjects, AutocargoError> {
+        Self::from_bundle_impl(bundle.as_ref(), dir.as_ref())
+            .await
+            .map_err(AutocargoError::config)
+    }
+
+    async fn from_bundle_impl(bundle: &Path, dir: &Path) -> Result<AllProjects> {
+        let configs = async {
+            let content = read_to_string(bundle).await.context("While reading it")?;
+            parse_bundle(
+                &content,
+                bundle.extension().is_some_and(|ext| ext == "json"),
+            )
+        }
+        .await
+        .with_context(|| format!("While processing config bundle {}", bundle.display()))?;
+
+        Ok(AllProjects {
+            projects: validate_projects(configs)?,
+            global: GlobalConf::from_dir_impl(dir).await?,
+            unloaded_projects: BTreeSet::new(),
         })
     }
 
@@ -556,37 +1367,144 @@ impl ProjectConf {
             .collect()
     }
 
+    /// Path of the .cargo/config.toml file generated for this project, if any.
+    pub fn cargo_config_path(&self) -> Option<PathInFbcode> {
+        let cargo_config = self.cargo_config.as_ref()?;
+        let dir = cargo_config.dir.as_ref().or_else(|| {
+            self.workspace_config.as_ref().map(|workspace| {
+                workspace
+                    .save_to_dir
+                    .as_ref()
+                    .unwrap_or(&workspace.scrape_dir)
+            })
+        })?;
+        Some(dir.join_to_path_in_fbcode(PathInFbcode::cargo_config_filename()))
+    }
+
+    /// Path of the .autocargo_manifest.json file generated for this project,
+    /// if any.
+    pub fn file_manifest_path(&self) -> Option<PathInFbcode> {
+        self.file_manifest_dir
+            .as_ref()
+            .map(|dir| dir.join_to_path_in_fbcode(PathInFbcode::file_manifest_filename()))
+    }
+
+    /// True if the path is within one of the manual_dirs of this project.
+    pub fn in_manual_dirs(&self, path: &PathInFbcode) -> bool {
+        let path: &PathBuf = path.as_ref();
+        self.manual_dirs
+            .iter()
+            .any(|dir| path.starts_with::<&PathBuf>(dir.as_ref()))
+    }
+
+    /// True if the path is within the roots and globs of this project.
+    pub fn covers_path(&self, path: &PathInFbcode) -> bool {
+        covers_path(
+            path,
+            &self.roots,
+            &self.include_globs,
+            &self.exclude_globs,
+            self.oss_git_config
+                .as_ref()
+                .and_then(|c| c.public_cargo_dir.as_ref()),
+        )
+    }
+}
+
+/// Fields of [ProjectConf] that tell which paths a project covers and which
+/// projects it depends on. In lazy mode only they are parsed from all the
+/// config files to find the projects that a run needs.
+#[derive(Debug, Deserialize)]
+struct ProjectIndexEntry {
+    name: String,
+    #[serde(default)]
+    roots: HashSet<String>,
+    #[serde(default, deserialize_with = "deserialize_globs")]
+    include_globs: HashSet<Pattern>,
+    #[serde(default, deserialize_with = "deserialize_globs")]
+    exclude_globs: HashSet<Pattern>,
+    #[serde(default)]
+    dependencies: HashSet<String>,
+    oss_git_config: Option<OssGitConfigIndexEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OssGitConfigIndexEntry {
+    public_cargo_dir: Option<PathInFbcode>,
+}
+
+impl ProjectIndexEntry {
     fn covers_path(&self, path: &PathInFbcode) -> bool {
-        let path: &Path = path.as_ref();
-        for pattern in &self.exclude_globs {
-            if pattern.matches_path(path) {
-                return false;
-            }
+        covers_path(
+            path,
+            &self.roots,
+            &self.include_globs,
+            &self.exclude_globs,
+            self.oss_git_config
+                .as_ref()
+                .and_then(|c| c.public_cargo_dir.as_ref()),
+        )
+    }
+}
+
+fn covers_path(
+    path: &PathInFbcode,
+    roots: &HashSet<String>,
+    include_globs: &HashSet<Pattern>,
+    exclude_globs: &HashSet<Pattern>,
+    public_cargo_dir: Option<&PathInFbcode>,
+) -> bool {
+    let path: &Path = path.as_ref();
+    for pattern in exclude_globs {
+        if pattern.matches_path(path) {
+            return false;
         }
+    }
 
-        for pattern in &self.include_globs {
-            if pattern.matches_path(path) {
-                return true;
-            }
+    for pattern in include_globs {
+        if pattern.matches_path(path) {
+            return true;
         }
+    }
 
-        for root in &self.roots {
-            if path.starts_with(root) {
-                return true;
-            }
+    for root in roots {
+        if path.starts_with(root) {
+            return true;
         }
+    }
 
-        if let Some(public_dir) = self
-            .oss_git_config
-            .as_ref()
-            .and_then(|c| c.public_cargo_dir.as_ref())
-        {
-            if path.starts_with(public_dir.as_ref()) {
-                return true;
-            }
+    if let Some(public_dir) = public_cargo_dir {
+        if path.starts_with(public_dir.as_ref()) {
+            return true;
         }
+    }
+
+    false
+}
 
-        false
+/// Deserialize the project configs of a bundle, either a JSON array or a TOML
+/// document of tables.
+fn parse_bundle(content: &str, is_json: bool) -> Result<Vec<ProjectConf>> {
+    if is_json {
+        let entries: Vec<serde_json::Value> = serde_json::from_str(content)?;
+        entries
+            .into_iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                serde_json::from_value(entry)
+                    .with_context(|| format!("While processing entry {index} of the bundle"))
+            })
+            .collect()
+    } else {
+        let entries: BTreeMap<String, toml::Value> = from_str(content)?;
+        entries
+            .into_iter()
+            .map(|(key, entry)| {
+                entry
+                    .try_into()
+                    .with_context(|| format!("While processing entry {key} of the bundle"))
+            })
+            .collect()
     }
 }
 
@@ -612,6 +1530,15 @@ fn validate_projects(configs: Vec<ProjectConf>) -> Result<HashMap<String, Projec
             );
         }
 
+        for (follower, leader) in &conf.version_lockstep {
+            ensure!(
+                follower != leader,
+                "version_lockstep of project {} maps {} to itself",
+                conf.name(),
+                follower
+            );
+        }
+
         for lock_path in &conf.cargo_locks {
             let lock_file = lock_path.join_to_path_in_fbcode("Cargo.lock");
             if !conf.covers_path(&lock_file) {
@@ -622,6 +1549,40 @@ fn validate_projects(configs: Vec<ProjectConf>) -> Result<HashMap<String, Projec
                 );
             }
         }
+
+        if conf.cargo_config.is_some() {
+            match conf.cargo_config_path() {
+                None => bail!(
+                    "cargo_config of project {} needs either cargo_config.dir or \
+                    workspace_config to be set",
+                    conf.name()
+                ),
+                Some(path) => ensure!(
+                    conf.covers_path(&path),
+                    "cargo_config path '{}' is not contained in project '{}'",
+                    path,
+                    conf.name()
+                ),
+            }
+        }
+
+        if let Some(path) = conf.file_manifest_path() {
+            ensure!(
+                conf.covers_path(&path),
+                "file_manifest_dir path '{}' is not contained in project '{}'",
+                path,
+                conf.name()
+            );
+        }
+
+        if let Some(preamble) = &conf.preamble {
+            ensure!(
+                preamble.contains("{marker}"),
+                "preamble of project {} must contain {{marker}}, otherwise the generated files \
+                are not recognized as such",
+                conf.name()
+            );
+        }
     }
 
     Ok(all)
@@ -666,6 +1627,8 @@ mod test {
 
         assert_selected(
             &AllProjects {
+                global: GlobalConf::default(),
+                unloaded_projects: BTreeSet::new(),
                 projects: validate_projects(vec![pc("proj1"), pc("proj3"), pc("proj2")]).unwrap(),
             }
             .select_all(),
@@ -673,6 +1636,41 @@ mod test {
         )
     }
 
+    #[test]
+    fn projects_covering_path_test() {
+        let pc = |name: &str, inc: &[&str], deps: &[&str]| {
+            pc(json!({
+                "name": name,
+                "include_globs": inc,
+                "oncall": "oncall_name",
+                "dependencies": deps,
+            }))
+        };
+        let all_proj = AllProjects {
+            global: GlobalConf::default(),
+            unloaded_projects: BTreeSet::new(),
+            projects: validate_projects(vec![
+                pc("proj2", &["a/**"], &[]),
+                pc("proj1", &["a/b/**"], &["proj2"]),
+                pc("proj3", &["c/**"], &["proj1"]),
+            ])
+            .unwrap(),
+        };
+
+        assert_equal(
+            all_proj
+                .projects_covering_path(&PathInFbcode::new_mock("a/b/TARGETS"))
+                .into_iter()
+                .map(|p| p.name().as_str()),
+            ["proj1", "proj2"],
+        );
+        assert!(
+            all_proj
+                .projects_covering_path(&PathInFbcode::new_mock("d/TARGETS"))
+                .is_empty()
+        );
+    }
+
     #[test]
     fn select_based_on_paths_and_names_test() {
         let pc = |name: &str, inc: &[&str], deps: &[&str]| {
@@ -687,6 +1685,8 @@ mod test {
         let s = String::from;
 
         let all_proj = AllProjects {
+            global: GlobalConf::default(),
+            unloaded_projects: BTreeSet::new(),
             projects: validate_projects(vec![
                 pc("proj1", &["a"], &[]),
                 pc("proj2", &["b"], &["proj1"]),
@@ -759,6 +1759,8 @@ mod test {
         let p = |s: &str| TargetsPath::new(PathInFbcode::new_mock(s)).unwrap();
 
         let all_proj = AllProjects {
+            global: GlobalConf::default(),
+            unloaded_projects: BTreeSet::new(),
             projects: validate_projects(vec![
                 pc("proj1", &[], &["a/**"]),
                 pc("proj2", &[], &["b/**"]),
@@ -859,5 +1861,198 @@ mod test {
                 assert_equal(map.keys().sorted(), &["proj1", "proj2"]);
             }
         );
+
+        assert_matches!(
+            validate_projects(vec![
+                from_value(json!({
+                    "name": "proj1",
+                    "oncall": "oncall_name",
+                    "version_lockstep": { "serde": "serde" },
+                }))
+                .unwrap()
+            ]),
+            Err(err) => {
+                assert_eq!(
+                    err.to_string(),
+                    "version_lockstep of project proj1 maps serde to itself"
+                )
+            }
+        );
+
+        assert_matches!(
+            validate_projects(vec![
+                from_value(json!({
+                    "name": "proj1",
+                    "oncall": "oncall_name",
+                    "preamble": "Generated from {rule}",
+                }))
+                .unwrap()
+            ]),
+            Err(err) => {
+                assert_eq!(
+                    err.to_string(),
+                    "preamble of project proj1 must contain {marker}, otherwise the generated \
+                    files are not recognized as such"
+                )
+            }
+        );
+    }
+
+    #[test]
+    fn version_lockstep_test() {
+        let conf = pc(json!({ "name": "proj", "oncall": "oncall_name" }));
+        assert_eq!(
+            conf.version_lockstep(),
+            &BTreeMap::from([
+                ("cxx-build".to_owned(), "cxx".to_owned()),
+                ("serde_derive".to_owned(), "serde".to_owned()),
+                ("thiserror-impl".to_owned(), "thiserror".to_owned()),
+            ])
+        );
+
+        let conf = pc(json!({
+            "name": "proj",
+            "oncall": "oncall_name",
+            "version_lockstep": { "serde_derive": "serde" },
+        }));
+        assert_eq!(
+            conf.version_lockstep(),
+            &BTreeMap::from([("serde_derive".to_owned(), "serde".to_owned())])
+        );
+
+        let conf = pc(json!({
+            "name": "proj",
+            "oncall": "oncall_name",
+            "version_locked_pairs": { "thiserror-impl": "thiserror" },
+        }));
+        assert_eq!(
+            conf.version_lockstep(),
+            &BTreeMap::from([("thiserror-impl".to_owned(), "thiserror".to_owned())])
+        );
+    }
+
+    #[tokio::test]
+    async fn from_dir_global_conf_test() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("nested")).unwrap();
+        std::fs::write(
+            dir.path().join("nested/proj.toml"),
+            "name = \"proj\"\noncall = \"oncall_name\"\n",
+        )
+        .unwrap();
+
+        let all = ProjectConf::from_dir(dir.path()).await.unwrap();
+        assert_equal(all.projects().keys(), ["proj"]);
+        assert_eq!(all.global().buck(), &BuckConfig::default());
+
+        std::fs::write(
+            dir.path().join(GLOBAL_CONFIG_FILENAME),
+            "[buck]\nbinary = \"buck2-wrapper\"\nargs = [\"--isolation-dir=ci\"]\n",
+        )
+        .unwrap();
+        let all = ProjectConf::from_dir(dir.path()).await.unwrap();
+        assert_equal(all.projects().keys(), ["proj"]);
+        assert_eq!(
+            all.global().buck(),
+            &BuckConfig {
+                binary: PathBuf::from("buck2-wrapper"),
+                args: vec!["--isolation-dir=ci".to_owned()],
+                command_args: Vec::new(),
+            }
+        );
+        assert_eq!(all.global().build_filenames(), &BuildFilenames::default());
+
+        std::fs::write(
+            dir.path().join(GLOBAL_CONFIG_FILENAME),
+            "build_filenames = [\"BUILD\", \"BUILD.bazel\"]\n",
+        )
+        .unwrap();
+        let all = ProjectConf::from_dir(dir.path()).await.unwrap();
+        assert_eq!(
+            all.global().build_filenames(),
+            &BuildFilenames::new(["BUILD", "BUILD.bazel"]).unwrap()
+        );
+
+        std::fs::write(
+            dir.path().join(GLOBAL_CONFIG_FILENAME),
+            "build_filenames = [\"foo/BUILD\"]\n",
+        )
+        .unwrap();
+        assert!(ProjectConf::from_dir(dir.path()).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn from_dir_lazy_test() {
+        let dir = tempfile::tempdir().unwrap();
+        for (name, content) in [
+            ("a", "roots = [\"a\"]\n"),
+            ("b", "roots = [\"b\"]\ndependencies = [\"a\"]\n"),
+            ("c", "roots = [\"c\"]\ndependencies = [\"b\"]\n"),
+            ("d", "roots = [\"d\"]\nunknown_field = true\n"),
+        ] {
+            std::fs::write(
+                dir.path().join(format!("{name}.toml")),
+                format!("name = \"{name}\"\noncall = \"oncall_name\"\n{content}"),
+            )
+            .unwrap();
+        }
+
+        assert!(ProjectConf::from_dir(dir.path()).await.is_err());
+        let all =
+            ProjectConf::from_dir_lazy(dir.path(), &[PathInFbcode::new_mock("b/TARGETS")], &[])
+                .await
+                .unwrap();
+        assert_equal(all.projects().keys().sorted(), ["a", "b", "c"]);
+        assert_equal(all.unloaded_projects(), ["d"]);
+        assert!(
+            ProjectConf::from_dir_lazy(dir.path(), &[], &["d".to_owned()])
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn from_bundle_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let toml_bundle = dir.path().join("bundle.toml");
+        std::fs::write(
+            &toml_bundle,
+            "[a]\nname = \"a\"\noncall = \"oncall_name\"\n\n\
+            [b]\nname = \"b\"\noncall = \"oncall_name\"\ndependencies = [\"a\"]\n",
+        )
+        .unwrap();
+        let all = ProjectConf::from_bundle(&toml_bundle, dir.path())
+            .await
+            .unwrap();
+        assert_equal(all.projects().keys().sorted(), ["a", "b"]);
+
+        let json_bundle = dir.path().join("bundle.json");
+        std::fs::write(
+            &json_bundle,
+            json!([
+                {"name": "a", "oncall": "oncall_name"},
+                {"name": "b", "oncall": "oncall_name", "dependencies": ["c"]},
+            ])
+            .to_string(),
+        )
+        .unwrap();
+        assert_matches!(
+            ProjectConf::from_bundle(&json_bundle, dir.path()).await,
+            Err(AutocargoError::Config(err)) => {
+                assert_eq!(err.to_string(), "Dependency c of project b does not exists")
+            }
+        );
+
+        std::fs::write(
+            &json_bundle,
+            json!([{"name": "a", "oncall": "oncall_name", "unknown_field": true}]).to_string(),
+        )
+        .unwrap();
+        assert_matches!(
+            ProjectConf::from_bundle(&json_bundle, dir.path()).await,
+            Err(err) => {
+                assert!(format!("{err:#}").contains("While processing entry 0 of the bundle"))
+            }
+        );
     }
 }
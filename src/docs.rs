@@ -0,0 +1,149 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Documentation of the `autocargo` attribute of Buck rules and of the project
+//! configs. The registry is generated out of the doc comments of the structs
+//! that deserialize them and checked in, a test makes sure that the help
+//! printed to users doesn't diverge from the code.
+
+#[cfg(test)]
+mod registry_test;
+
+use std::fmt::Write;
+
+use anyhow::Result;
+use anyhow::bail;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// Every documented field, as generated by the tests of this module.
+static REGISTRY: &str = include_str!("docs/registry.json");
+
+/// Documentation of a single field, addressed by its dot-separated path, e.g.
+/// `autocargo.cargo_toml_config.extra_buck_dependencies.target`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FieldDoc {
+    /// Full path of the field starting from one of the roots.
+    pub path: String,
+    /// Type of the field as written in the source.
+    pub ty: String,
+    /// Doc comment of the field, or of its type if the field has none.
+    pub doc: String,
+    /// Names of nested fields if the type of this field is a documented struct.
+    pub fields: Vec<String>,
+}
+
+impl FieldDoc {
+    /// Render the documentation for printing in the terminal.
+    pub fn render(&self) -> String {
+        let mut out = format!("{}: {}\n", self.path, self.ty);
+        if !self.doc.is_empty() {
+            let _ = write!(out, "\n{}\n", self.doc);
+        }
+        if !self.fields.is_empty() {
+            let _ = write!(out, "\nFields: {}\n", self.fields.join(", "));
+        }
+        out
+    }
+}
+
+/// All documented fields reachable from the `autocargo` rule attribute and the
+/// project config.
+#[derive(Debug)]
+pub struct DocRegistry {
+    fields: Vec<FieldDoc>,
+}
+
+impl Default for DocRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DocRegistry {
+    /// Load the checked-in registry.
+    pub fn new() -> Self {
+        Self {
+            fields: serde_json::from_str(REGISTRY).expect("The docs registry is valid JSON"),
+        }
+    }
+
+    /// All known fields.
+    pub fn fields(&self) -> &[FieldDoc] {
+        &self.fields
+    }
+
+    /// Find fields matching the dot-separated query. The query might be a full
+    /// path or any suffix of it, so both `extra_buck_dependencies.target` and
+    /// `autocargo.cargo_toml_config.extra_buck_dependencies.target` match.
+    pub fn lookup(&self, query: &str) -> Result<Vec<&FieldDoc>> {
+        let query = query.trim_matches('.');
+        let suffix = format!(".{query}");
+        let found: Vec<_> = self
+            .fields
+            .iter()
+            .filter(|field| field.path == query || field.path.ends_with(&suffix))
+            .collect();
+        if found.is_empty() {
+            bail!(
+                "No autocargo rule attribute or project config field matches {query:?}, \
+                use `autocargo doc` without arguments to list all of them"
+            );
+        }
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lookup_test() {
+        let registry = DocRegistry::new();
+
+        let found = registry.lookup("extra_buck_dependencies.target").unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(
+            found[0].path,
+            "autocargo.cargo_toml_config.extra_buck_dependencies.target"
+        );
+        assert_eq!(
+            found[0].ty,
+            "BTreeMap<TargetKey, RawBuckTargetDependencies>"
+        );
+        assert!(
+            found[0]
+                .doc
+                .starts_with("For overriding target dependencies.")
+        );
+        assert_eq!(
+            found[0].fields,
+            vec!["dependencies", "dev-dependencies", "build-dependencies"]
+        );
+
+        // Flattened fields are reachable directly from their parent.
+        let found = registry
+            .lookup("autocargo.cargo_toml_config.extra_buck_dependencies.dev-dependencies")
+            .unwrap();
+        assert_eq!(found.len(), 1);
+
+        let found = registry
+            .lookup("project.workspace_config.scrape_dir")
+            .unwrap();
+        assert!(found[0].doc.contains("will be included as members"));
+
+        assert!(
+            registry
+                .lookup("extra_buck_dependencies.no_such_field")
+                .is_err()
+        );
+    }
+}
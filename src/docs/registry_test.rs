@@ -0,0 +1,418 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Builds the registry of [super::DocRegistry] out of the doc comments of the
+//! structs that deserialize the `autocargo` attribute of Buck rules and the
+//! project configs, and checks that the checked-in [REGISTRY_PATH] is up to
+//! date with them.
+//!
+//! Run the tests with the UPDATE_GOLDEN env variable set to regenerate the
+//! registry after changing the docs of the config structs.
+
+use std::collections::HashMap;
+use std::env::var_os;
+use std::fs;
+
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::bail;
+use pretty_assertions::assert_eq;
+use quote::ToTokens;
+use syn::Attribute;
+use syn::Expr;
+use syn::ExprLit;
+use syn::Fields;
+use syn::GenericArgument;
+use syn::Item;
+use syn::Lit;
+use syn::LitStr;
+use syn::Meta;
+use syn::PathArguments;
+use syn::Type;
+
+use super::FieldDoc;
+use super::REGISTRY;
+
+static REGISTRY_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/docs/registry.json");
+static UPDATE_GOLDEN: &str = "UPDATE_GOLDEN";
+
+/// Sources of the structs that are deserialized from user provided configs.
+const SOURCES: &[(&str, &str)] = &[
+    ("src/config.rs", include_str!("../config.rs")),
+    (
+        "src/buck_processing/raw_manifest.rs",
+        include_str!("../buck_processing/raw_manifest.rs"),
+    ),
+];
+
+/// Roots of the documented configs: the name under which they are looked up
+/// and the struct deserializing them.
+const ROOTS: &[(&str, &str)] = &[("autocargo", "AutocargoField"), ("project", "ProjectConf")];
+
+/// Documentation of a struct or enum as parsed from the source.
+#[derive(Debug)]
+struct TypeDoc {
+    doc: String,
+    fields: Vec<RawField>,
+}
+
+#[derive(Debug)]
+struct RawField {
+    name: String,
+    doc: String,
+    ty: Type,
+    flatten: bool,
+}
+
+#[derive(Default)]
+struct SerdeAttrs {
+    rename: Option<String>,
+    rename_all: Option<String>,
+    flatten: bool,
+    skip: bool,
+}
+
+fn build_registry() -> Result<Vec<FieldDoc>> {
+    let mut types = HashMap::new();
+    for (path, source) in SOURCES {
+        let file = syn::parse_file(source).with_context(|| format!("While parsing {path}"))?;
+        collect_types(&file.items, &mut types)
+            .with_context(|| format!("While collecting docs from {path}"))?;
+    }
+
+    let mut fields = Vec::new();
+    for (root, type_name) in ROOTS {
+        let ty = types
+            .get(*type_name)
+            .with_context(|| format!("Missing definition of {type_name}"))?;
+        collect_fields(root, type_name, ty, &types, &mut Vec::new(), &mut fields);
+    }
+    Ok(fields)
+}
+
+fn collect_types(items: &[Item], types: &mut HashMap<String, TypeDoc>) -> Result<()> {
+    for item in items {
+        match item {
+            Item::Struct(item) => {
+                let rename_all = serde_attrs(&item.attrs)?.rename_all;
+                let mut fields = Vec::new();
+                if let Fields::Named(named) = &item.fields {
+                    for field in &named.named {
+                        let attrs = serde_attrs(&field.attrs)?;
+                        if attrs.skip {
+                            continue;
+                        }
+                        let ident = field.ident.as_ref().expect("named field").to_string();
+                        let name = match (attrs.rename, &rename_all) {
+                            (Some(rename), _) => rename,
+                            (None, Some(rename_all)) => apply_rename_all(&ident, rename_all)
+                                .with_context(|| format!("In struct {}", item.ident))?,
+                            (None, None) => ident,
+                        };
+                        fields.push(RawField {
+                            name,
+                            doc: doc_comment(&field.attrs),
+                            ty: field.ty.clone(),
+                            flatten: attrs.flatten,
+                        });
+                    }
+                }
+                types.insert(
+                    item.ident.to_string(),
+                    TypeDoc {
+                        doc: doc_comment(&item.attrs),
+                        fields,
+                    },
+                );
+            }
+            Item::Enum(item) => {
+                types.insert(
+                    item.ident.to_string(),
+                    TypeDoc {
+                        doc: doc_comment(&item.attrs),
+                        fields: Vec::new(),
+                    },
+                );
+            }
+            Item::Mod(item) => {
+                if let Some((_, items)) = &item.content {
+                    collect_types(items, types)?;
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Collect docs of the fields of the type. Types that contain themselves,
+/// directly or through other types, are listed but not descended into again,
+/// since their fields are already reachable from the outer occurrence.
+fn collect_fields<'a>(
+    prefix: &str,
+    type_name: &'a str,
+    ty: &'a TypeDoc,
+    types: &'a HashMap<String, TypeDoc>,
+    ancestors: &mut Vec<&'a str>,
+    out: &mut Vec<FieldDoc>,
+) {
+    ancestors.push(type_name);
+    for field in &ty.fields {
+        let nested = nested_type(&field.ty, types);
+        let recurse = nested.filter(|(name, _)| !ancestors.contains(name));
+        if field.flatten {
+            if let Some((name, nested)) = recurse {
+                collect_fields(prefix, name, nested, types, ancestors, out);
+            }
+            continue;
+        }
+
+        let path = format!("{prefix}.{}", field.name);
+        let doc = match nested {
+            Some((_, nested)) if field.doc.is_empty() => nested.doc.clone(),
+            _ => field.doc.clone(),
+        };
+        out.push(FieldDoc {
+            path: path.clone(),
+            ty: type_to_string(&field.ty),
+            doc,
+            fields: nested.map_or_else(Vec::new, |(_, nested)| visible_field_names(nested, types)),
+        });
+        if let Some((name, nested)) = recurse {
+            collect_fields(&path, name, nested, types, ancestors, out);
+        }
+    }
+    ancestors.pop();
+}
+
+/// Names of fields as they appear in the config, with flattened fields inlined.
+fn visible_field_names(ty: &TypeDoc, types: &HashMap<String, TypeDoc>) -> Vec<String> {
+    ty.fields
+        .iter()
+        .flat_map(|field| {
+            if field.flatten {
+                nested_type(&field.ty, types)
+                    .map_or_else(Vec::new, |(_, nested)| visible_field_names(nested, types))
+            } else {
+                vec![field.name.clone()]
+            }
+        })
+        .collect()
+}
+
+/// Find the documented struct wrapped in the type, looking through generics
+/// like Option, Vec or the value of BTreeMap.
+fn nested_type<'a>(
+    ty: &Type,
+    types: &'a HashMap<String, TypeDoc>,
+) -> Option<(&'a str, &'a TypeDoc)> {
+    let Type::Path(type_path) = ty else {
+        return None;
+    };
+    let segment = type_path.path.segments.last()?;
+    if let Some(found) = types
+        .get_key_value(&segment.ident.to_string())
+        .filter(|(_, found)| !found.fields.is_empty())
+    {
+        return Some((found.0.as_str(), found.1));
+    }
+    let PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
+    };
+    args.args.iter().rev().find_map(|arg| match arg {
+        GenericArgument::Type(ty) => nested_type(ty, types),
+        _ => None,
+    })
+}
+
+fn type_to_string(ty: &Type) -> String {
+    ty.to_token_stream()
+        .to_string()
+        .replace(" < ", "<")
+        .replace(" <", "<")
+        .replace("< ", "<")
+        .replace(" >", ">")
+        .replace(" ,", ",")
+        .replace(" :: ", "::")
+}
+
+fn doc_comment(attrs: &[Attribute]) -> String {
+    attrs
+        .iter()
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(meta) if meta.path.is_ident("doc") => match &meta.value {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(doc), ..
+                }) => Some(doc.value()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .map(|line| {
+            line.strip_prefix(' ')
+                .unwrap_or(&line)
+                .trim_end()
+                .to_owned()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+        .trim()
+        .to_owned()
+}
+
+fn serde_attrs(attrs: &[Attribute]) -> Result<SerdeAttrs> {
+    let mut result = SerdeAttrs::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("serde")) {
+        attr.parse_nested_meta(|meta| {
+            let is_rename = meta.path.is_ident("rename");
+            if is_rename || meta.path.is_ident("rename_all") {
+                // Only the name used for deserialization is relevant, which is
+                // either the only value or the `deserialize` one.
+                let mut name = None;
+                if meta.input.peek(syn::Token![=]) {
+                    name = Some(meta.value()?.parse::<LitStr>()?.value());
+                } else {
+                    meta.parse_nested_meta(|nested| {
+                        let value = nested.value()?.parse::<LitStr>()?.value();
+                        if nested.path.is_ident("deserialize") {
+                            name = Some(value);
+                        }
+                        Ok(())
+                    })?;
+                }
+                if is_rename {
+                    result.rename = name;
+                } else {
+                    result.rename_all = name;
+                }
+                return Ok(());
+            }
+
+            if meta.path.is_ident("flatten") {
+                result.flatten = true;
+            } else if meta.path.is_ident("skip") || meta.path.is_ident("skip_deserializing") {
+                result.skip = true;
+            }
+            // Consume values of attributes that are not relevant for docs.
+            if meta.input.peek(syn::Token![=]) {
+                meta.value()?.parse::<Expr>()?;
+            } else if meta.input.peek(syn::token::Paren) {
+                meta.parse_nested_meta(|nested| {
+                    if nested.input.peek(syn::Token![=]) {
+                        nested.value()?.parse::<Expr>()?;
+                    }
+                    Ok(())
+                })?;
+            }
+            Ok(())
+        })
+        .context("While parsing serde attribute")?;
+    }
+    Ok(result)
+}
+
+/// Rename the snake_case field the same way `#[serde(rename_all = "...")]`
+/// does.
+fn apply_rename_all(ident: &str, rename_all: &str) -> Result<String> {
+    let ident = ident.strip_prefix("r#").unwrap_or(ident);
+    let capitalize = |word: &str| {
+        let mut chars = word.chars();
+        chars.next().map_or_else(String::new, |first| {
+            first.to_uppercase().chain(chars).collect()
+        })
+    };
+    Ok(match rename_all {
+        "lowercase" | "snake_case" => ident.to_owned(),
+        "UPPERCASE" | "SCREAMING_SNAKE_CASE" => ident.to_uppercase(),
+        "kebab-case" => ident.replace('_', "-"),
+        "SCREAMING-KEBAB-CASE" => ident.to_uppercase().replace('_', "-"),
+        "PascalCase" => ident.split('_').map(capitalize).collect(),
+        "camelCase" => {
+            let mut words = ident.split('_');
+            words
+                .next()
+                .into_iter()
+                .map(str::to_owned)
+                .chain(words.map(capitalize))
+                .collect()
+        }
+        _ => bail!("Unknown rename_all rule {rename_all:?}"),
+    })
+}
+
+fn render_registry(fields: &[FieldDoc]) -> Result<String> {
+    Ok(serde_json::to_string_pretty(fields)? + "\n")
+}
+
+#[test]
+fn registry_up_to_date_test() {
+    let fields = build_registry().unwrap();
+    assert!(!fields.is_empty());
+    if var_os(UPDATE_GOLDEN).is_some() {
+        fs::write(REGISTRY_PATH, render_registry(&fields).unwrap()).unwrap();
+    } else {
+        assert_eq!(
+            REGISTRY,
+            render_registry(&fields).unwrap(),
+            "{REGISTRY_PATH} is out of date with the docs of the config structs, rerun with \
+            {UPDATE_GOLDEN}=1 to regenerate it",
+        );
+    }
+}
+
+#[test]
+fn apply_rename_all_test() {
+    let cases = [
+        ("lowercase", "dev_dependencies"),
+        ("snake_case", "dev_dependencies"),
+        ("UPPERCASE", "DEV_DEPENDENCIES"),
+        ("SCREAMING_SNAKE_CASE", "DEV_DEPENDENCIES"),
+        ("kebab-case", "dev-dependencies"),
+        ("SCREAMING-KEBAB-CASE", "DEV-DEPENDENCIES"),
+        ("PascalCase", "DevDependencies"),
+        ("camelCase", "devDependencies"),
+    ];
+    for (rule, expected) in cases {
+        assert_eq!(
+            apply_rename_all("dev_dependencies", rule).unwrap(),
+            expected,
+            "{rule}"
+        );
+    }
+    assert!(apply_rename_all("dev_dependencies", "Title Case").is_err());
+}
+
+#[test]
+fn self_referencing_type_test() {
+    let file: syn::File = syn::parse_quote! {
+        /// A node.
+        struct Node {
+            /// Name of the node.
+            name: String,
+            /// Children of the node.
+            children: Vec<Node>,
+        }
+    };
+    let mut types = HashMap::new();
+    collect_types(&file.items, &mut types).unwrap();
+    let mut fields = Vec::new();
+    collect_fields(
+        "node",
+        "Node",
+        &types["Node"],
+        &types,
+        &mut Vec::new(),
+        &mut fields,
+    );
+    assert_eq!(
+        fields.iter().map(|f| f.path.as_str()).collect::<Vec<_>>(),
+        vec!["node.name", "node.children"]
+    );
+    assert_eq!(fields[1].fields, vec!["name", "children"]);
+}
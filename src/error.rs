@@ -0,0 +1,259 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Errors returned by the pipeline entrypoints of the library, classified by
+//! the step that failed so that callers can e.g. retry Buck failures and
+//! report config errors to the user.
+//!
+//! [AutocargoError] is returned by the config loaders
+//! ([crate::config::GlobalConf::from_dir], [crate::config::ProjectConf::from_dir]
+//! and friends), [crate::project_loader::ProjectLoader::load],
+//! [crate::buck_processing::process_targets], [crate::cargo_generator::CargoGenerator]
+//! (`new`, `generate_for_projects` and `generate_for_many_targets`) and the
+//! steps of [crate::run::GenerationSession]. The lower level helpers of the
+//! library still return [anyhow::Result].
+
+use std::error::Error as StdError;
+use std::fmt;
+use std::fmt::Display;
+use std::io;
+
+use crate::cargo_generator::OutputConflicts;
+
+/// Error of an autocargo run, see module docs for details.
+#[derive(Debug)]
+pub enum AutocargoError {
+    /// The project configs or the inputs of the run are invalid or can't be
+    /// read, e.g. unknown project names or roots missing from the checkout.
+    Config(ErrorDetails),
+    /// Buck commands failed or their output couldn't be processed.
+    Buck(ErrorDetails),
+    /// Generation failed, e.g. because of conflicting packages or
+    /// dependencies.
+    Generation(ErrorDetails),
+    /// Paths were generated from more than one build file.
+    OutputConflicts(OutputConflicts),
+    /// Reading or listing files needed for the run, e.g. the third-party
+    /// Cargo.toml or the files covered by the projects, failed.
+    Io(ErrorDetails),
+}
+
+/// Message of a failed step together with the errors that caused it.
+#[derive(Debug)]
+pub struct ErrorDetails {
+    message: String,
+    source: Option<Box<dyn StdError + Send + Sync + 'static>>,
+}
+
+impl ErrorDetails {
+    /// Create details from the message and the optional cause.
+    pub fn new(
+        message: impl Into<String>,
+        source: Option<Box<dyn StdError + Send + Sync + 'static>>,
+    ) -> Self {
+        Self {
+            message: message.into(),
+            source,
+        }
+    }
+
+    /// Describes what failed, without the causes.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    fn from_anyhow(error: anyhow::Error) -> Self {
+        let message = error.to_string();
+        let source = if error.chain().nth(1).is_some() {
+            Some(Box::new(Causes(error.into())) as Box<dyn StdError + Send + Sync>)
+        } else {
+            None
+        };
+        Self { message, source }
+    }
+}
+
+impl Display for ErrorDetails {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if f.alternate() {
+            let mut source = self.source();
+            while let Some(cause) = source {
+                write!(f, ": {cause}")?;
+                source = cause.source();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl StdError for ErrorDetails {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.source.as_deref().map(|source| source as _)
+    }
+}
+
+/// Causes of an [anyhow::Error], i.e. its chain without the outermost
+/// context which becomes the message of [ErrorDetails].
+#[derive(Debug)]
+struct Causes(Box<dyn StdError + Send + Sync + 'static>);
+
+impl Display for Causes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0.source() {
+            Some(cause) => Display::fmt(cause, f),
+            None => Ok(()),
+        }
+    }
+}
+
+impl StdError for Causes {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.0.source()?.source()
+    }
+}
+
+impl AutocargoError {
+    pub(crate) fn config(error: anyhow::Error) -> Self {
+        Self::Config(ErrorDetails::from_anyhow(error))
+    }
+
+    /// Like [Self::config], but errors caused by failing IO, e.g. while
+    /// listing the files of the projects, are [Self::Io].
+    pub(crate) fn project_files(error: anyhow::Error) -> Self {
+        if is_io(&error) {
+            Self::io(error)
+        } else {
+            Self::config(error)
+        }
+    }
+
+    pub(crate) fn buck(error: anyhow::Error) -> Self {
+        Self::Buck(ErrorDetails::from_anyhow(error))
+    }
+
+    pub(crate) fn generation(error: anyhow::Error) -> Self {
+        match error.downcast::<OutputConflicts>() {
+            Ok(conflicts) => Self::OutputConflicts(conflicts),
+            Err(error) if is_io(&error) => Self::io(error),
+            Err(error) => Self::Generation(ErrorDetails::from_anyhow(error)),
+        }
+    }
+
+    pub(crate) fn io(error: anyhow::Error) -> Self {
+        Self::Io(ErrorDetails::from_anyhow(error))
+    }
+}
+
+/// True if the error was caused by failing IO.
+fn is_io(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| cause.is::<io::Error>())
+}
+
+impl Display for AutocargoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Config(details)
+            | Self::Buck(details)
+            | Self::Generation(details)
+            | Self::Io(details) => Display::fmt(details, f),
+            Self::OutputConflicts(conflicts) => Display::fmt(conflicts, f),
+        }
+    }
+}
+
+impl StdError for AutocargoError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::Config(details)
+            | Self::Buck(details)
+            | Self::Generation(details)
+            | Self::Io(details) => details.source(),
+            Self::OutputConflicts(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use anyhow::Context;
+    use anyhow::anyhow;
+    use assert_matches::assert_matches;
+
+    use super::*;
+
+    #[test]
+    fn autocargo_error_test() {
+        let error = AutocargoError::buck(
+            Err::<(), _>(anyhow!("exit status 1"))
+                .context("Failed to run 'buck build'")
+                .context("Failed to build manifests")
+                .unwrap_err(),
+        );
+        assert_matches!(&error, AutocargoError::Buck(details) if details.message() == "Failed to build manifests");
+        assert_eq!(error.to_string(), "Failed to build manifests");
+        assert_eq!(
+            format!("{error:#}"),
+            "Failed to build manifests: Failed to run 'buck build': exit status 1"
+        );
+        assert_eq!(
+            error.source().unwrap().to_string(),
+            "Failed to run 'buck build'"
+        );
+
+        let error = anyhow::Error::from(error);
+        assert_eq!(
+            error.chain().map(ToString::to_string).collect::<Vec<_>>(),
+            vec![
+                "Failed to build manifests",
+                "Failed to run 'buck build'",
+                "exit status 1"
+            ]
+        );
+
+        let error = AutocargoError::config(anyhow!("Unknown project foo"));
+        assert_eq!(format!("{error:#}"), "Unknown project foo");
+        assert!(error.source().is_none());
+    }
+
+    #[test]
+    fn output_conflicts_error_test() {
+        let error = AutocargoError::generation(
+            Err::<(), _>(OutputConflicts(Vec::new()))
+                .context("Failed to generate")
+                .unwrap_err(),
+        );
+        assert_matches!(error, AutocargoError::OutputConflicts(OutputConflicts(conflicts)) if conflicts.is_empty());
+    }
+
+    #[test]
+    fn io_error_test() {
+        let io_error = || {
+            Err::<(), _>(io::Error::from(io::ErrorKind::NotFound))
+                .context("While reading third-party/rust/Cargo.toml")
+                .unwrap_err()
+        };
+        assert_matches!(
+            AutocargoError::generation(io_error()),
+            AutocargoError::Io(_)
+        );
+        assert_matches!(
+            AutocargoError::generation(anyhow!("Conflicting packages")),
+            AutocargoError::Generation(_)
+        );
+        assert_matches!(
+            AutocargoError::project_files(io_error()),
+            AutocargoError::Io(_)
+        );
+        assert_matches!(
+            AutocargoError::project_files(anyhow!("Files are covered by two projects")),
+            AutocargoError::Config(_)
+        );
+    }
+}
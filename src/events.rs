@@ -0,0 +1,156 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Typed progress events emitted throughout the pipeline, so that tools
+//! embedding this library can display live progress without parsing the logs.
+//!
+//! Create a channel with [EventSender::channel], pass the sender to the
+//! pipeline steps and consume the events from the receiver. Wrap the drain of
+//! the logger with [EventDrain] to also receive warnings as events.
+
+use slog::Drain;
+use slog::Level;
+use slog::OwnedKVList;
+use slog::Record;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::mpsc::unbounded_channel;
+
+use crate::paths::PathInFbcode;
+
+/// Steps of the autocargo pipeline.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Phase {
+    /// Finding files covered by the selected projects.
+    LoadProjects,
+    /// Querying Buck for manifests of the found TARGETS files.
+    ProcessTargets,
+    /// Resolving dependencies of the queried manifests, which queries Buck
+    /// for manifests of dependencies outside of the found TARGETS files.
+    ProcessManifests,
+    /// Generating Cargo.toml and additional files.
+    Generate,
+    /// Writing or checking the generated files on disk.
+    WriteFiles,
+    /// Generating Cargo.lock files.
+    GenerateCargoLocks,
+    /// Generating the UTD map.
+    GenerateUtdMap,
+}
+
+/// Event emitted by the pipeline.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AutocargoEvent {
+    /// The given phase has started, the previous one has finished.
+    PhaseStarted(Phase),
+    /// Files covered by a project were found.
+    ProjectLoaded {
+        /// Name of the project.
+        name: String,
+        /// Number of found Cargo.toml files.
+        cargo: usize,
+        /// Number of found TARGETS files.
+        targets: usize,
+        /// Number of found additional generated files.
+        additional: usize,
+    },
+    /// Buck manifests of the TARGETS files were processed.
+    TargetsProcessed {
+        /// Number of processed manifests.
+        manifests: usize,
+        /// Number of TARGETS files that are out of scope of selected projects.
+        unprocessed_paths: usize,
+    },
+    /// A generated file was written to disk.
+    FileWritten(PathInFbcode),
+    /// A warning was logged.
+    Warning(String),
+}
+
+/// Sending half of the event channel. The default value discards all events.
+#[derive(Clone, Debug, Default)]
+pub struct EventSender(Option<UnboundedSender<AutocargoEvent>>);
+
+impl EventSender {
+    /// Create a sender together with the receiver of its events.
+    pub fn channel() -> (Self, UnboundedReceiver<AutocargoEvent>) {
+        let (sender, receiver) = unbounded_channel();
+        (Self(Some(sender)), receiver)
+    }
+
+    /// Send the event, it is dropped if nobody listens.
+    pub fn emit(&self, event: AutocargoEvent) {
+        if let Some(sender) = &self.0 {
+            // The receiver might be dropped if the embedding tool is no longer
+            // interested in the events, it is not an error.
+            let _ = sender.send(event);
+        }
+    }
+}
+
+/// Drain that passes records to the wrapped drain and additionally emits
+/// records of warning level or above as [AutocargoEvent::Warning].
+pub struct EventDrain<D> {
+    drain: D,
+    events: EventSender,
+}
+
+impl<D> EventDrain<D> {
+    /// Wrap the drain.
+    pub fn new(drain: D, events: EventSender) -> Self {
+        Self { drain, events }
+    }
+}
+
+impl<D: Drain> Drain for EventDrain<D> {
+    type Ok = D::Ok;
+    type Err = D::Err;
+
+    fn log(&self, record: &Record<'_>, values: &OwnedKVList) -> Result<Self::Ok, Self::Err> {
+        if record.level().is_at_least(Level::Warning) {
+            self.events
+                .emit(AutocargoEvent::Warning(record.msg().to_string()));
+        }
+        self.drain.log(record, values)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use slog::Logger;
+    use slog::info;
+    use slog::o;
+    use slog::warn;
+
+    use super::*;
+
+    #[test]
+    fn event_drain_test() {
+        let (events, mut receiver) = EventSender::channel();
+        let logger = Logger::root(EventDrain::new(slog::Discard, events.clone()), o!());
+
+        events.emit(AutocargoEvent::PhaseStarted(Phase::LoadProjects));
+        info!(logger, "not an event");
+        warn!(logger, "Something {} happened", "bad");
+        drop(logger);
+        drop(events);
+
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            AutocargoEvent::PhaseStarted(Phase::LoadProjects)
+        );
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            AutocargoEvent::Warning("Something bad happened".to_owned())
+        );
+        assert!(receiver.try_recv().is_err());
+
+        EventSender::default().emit(AutocargoEvent::Warning("dropped".to_owned()));
+    }
+}
@@ -0,0 +1,134 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Dependency graphs between projects and between the generated crates, which
+//! can be rendered in DOT or JSON format e.g. to audit the boundaries of OSS
+//! projects.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use crate::cargo_generator::GenerationOutput;
+use crate::cargo_generator::path_dependencies;
+use crate::config::SelectedProjects;
+
+/// Directed graph with edges pointing from dependents to their dependencies.
+#[derive(Debug, Default, Eq, PartialEq, Serialize)]
+pub struct DependencyGraph {
+    /// Names of the nodes together with the group they belong to, e.g. the
+    /// project of a crate.
+    pub nodes: BTreeMap<String, Option<String>>,
+    /// Pairs of names of the dependent and the dependency.
+    pub edges: BTreeSet<(String, String)>,
+}
+
+impl DependencyGraph {
+    /// Graph of the dependencies between the projects, as declared in their
+    /// configs.
+    pub fn projects(projects: &SelectedProjects<'_>) -> Self {
+        let mut graph = Self::default();
+        for conf in projects.projects() {
+            graph.nodes.insert(conf.name().clone(), None);
+            for dep in conf.dependencies() {
+                graph.nodes.entry(dep.clone()).or_default();
+                graph.edges.insert((conf.name().clone(), dep.clone()));
+            }
+        }
+        graph
+    }
+
+    /// Graph of the path dependencies between the generated crates, grouped by
+    /// their projects. Crates that are not generated, but are depended on, are
+    /// included without a group.
+    pub fn crates(output: &GenerationOutput) -> Self {
+        let mut graph = Self::default();
+        for krate in &output.crates {
+            graph
+                .nodes
+                .insert(krate.name.clone(), Some(krate.project.clone()));
+        }
+        for krate in &output.crates {
+            let Some(manifest) = output.cargo_manifests.get(&krate.cargo_toml_path) else {
+                continue;
+            };
+            for dep in path_dependencies(manifest) {
+                graph.nodes.entry(dep.to_owned()).or_default();
+                graph.edges.insert((krate.name.clone(), dep.to_owned()));
+            }
+        }
+        graph
+    }
+
+    /// Render the graph in the DOT language of Graphviz, nodes of the same
+    /// group are put in a cluster.
+    pub fn to_dot(&self, name: &str) -> String {
+        let mut groups: BTreeMap<Option<&str>, Vec<&str>> = BTreeMap::new();
+        for (node, group) in &self.nodes {
+            groups.entry(group.as_deref()).or_default().push(node);
+        }
+
+        let mut dot = format!("digraph {name:?} {{\n");
+        for (group, nodes) in groups {
+            let indent = if let Some(group) = group {
+                writeln!(dot, "  subgraph {:?} {{", format!("cluster_{group}")).unwrap();
+                writeln!(dot, "    label = {group:?};").unwrap();
+                "    "
+            } else {
+                "  "
+            };
+            for node in nodes {
+                writeln!(dot, "{indent}{node:?};").unwrap();
+            }
+            if group.is_some() {
+                dot.push_str("  }\n");
+            }
+        }
+        for (from, to) in &self.edges {
+            writeln!(dot, "  {from:?} -> {to:?};").unwrap();
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn to_dot_test() {
+        let graph = DependencyGraph {
+            nodes: BTreeMap::from([
+                ("foo".to_owned(), Some("proj".to_owned())),
+                ("bar".to_owned(), Some("proj".to_owned())),
+                ("manual".to_owned(), None),
+            ]),
+            edges: BTreeSet::from([
+                ("foo".to_owned(), "bar".to_owned()),
+                ("foo".to_owned(), "manual".to_owned()),
+            ]),
+        };
+        assert_eq!(
+            graph.to_dot("crates"),
+            "digraph \"crates\" {\n\
+            \x20 \"manual\";\n\
+            \x20 subgraph \"cluster_proj\" {\n\
+            \x20   label = \"proj\";\n\
+            \x20   \"bar\";\n\
+            \x20   \"foo\";\n\
+            \x20 }\n\
+            \x20 \"foo\" -> \"bar\";\n\
+            \x20 \"foo\" -> \"manual\";\n\
+            }\n"
+        );
+    }
+}
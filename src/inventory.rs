@@ -0,0 +1,137 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Inventory of the crates generated for the projects, so that e.g. release
+//! tooling and dashboards can learn what the projects consist of without
+//! writing any files.
+
+use serde::Serialize;
+
+use crate::cargo_generator::GenerationOutput;
+
+/// Entry of the inventory describing a single generated crate.
+#[derive(Debug, Eq, PartialEq, Serialize)]
+pub struct CrateInventoryEntry {
+    /// Name of the project covering the crate.
+    pub project: String,
+    /// Name of the package.
+    pub name: String,
+    /// Version of the package, None if it is inherited from the workspace.
+    pub version: Option<String>,
+    /// Path of the generated Cargo.toml file, relative to fbcode.
+    pub cargo_toml_path: String,
+    /// Buck rules the crate is generated from.
+    pub rules: Vec<String>,
+    /// Path of the oss-ready Cargo.toml file of the crate, relative to fbcode,
+    /// if the crate is published to the git repository of its project.
+    pub oss_cargo_toml_path: Option<String>,
+}
+
+impl CrateInventoryEntry {
+    /// True if the crate is published to the git repository of its project.
+    pub fn is_oss_published(&self) -> bool {
+        self.oss_cargo_toml_path.is_some()
+    }
+}
+
+/// List the crates of the generation output sorted by project and name.
+pub fn crate_inventory(output: &GenerationOutput) -> Vec<CrateInventoryEntry> {
+    let mut inventory: Vec<_> = output
+        .crates
+        .iter()
+        .map(|krate| CrateInventoryEntry {
+            project: krate.project.clone(),
+            name: krate.name.clone(),
+            version: output
+                .cargo_manifests
+                .get(&krate.cargo_toml_path)
+                .and_then(|manifest| manifest.package.as_ref())
+                .filter(|package| !package.inherited_from_workspace.contains("version"))
+                .map(|package| package.version.clone()),
+            cargo_toml_path: krate.cargo_toml_path.as_file().to_string(),
+            rules: krate.rules.clone(),
+            oss_cargo_toml_path: krate
+                .oss_cargo_toml_path
+                .as_ref()
+                .map(|path| path.as_file().to_string()),
+        })
+        .collect();
+    inventory.sort_by(|a, b| (&a.project, &a.name).cmp(&(&b.project, &b.name)));
+    inventory
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use maplit::hashmap;
+
+    use super::*;
+    use crate::cargo_generator::GeneratedCrate;
+    use crate::cargo_manifest::Manifest;
+    use crate::cargo_manifest::empty_package;
+    use crate::paths::CargoTomlPath;
+    use crate::paths::PathInFbcode;
+
+    fn cargo_path(dir: &str) -> CargoTomlPath {
+        CargoTomlPath::new(PathInFbcode::new_mock(dir).join_to_path_in_fbcode("Cargo.toml"))
+            .unwrap()
+    }
+
+    #[test]
+    fn crate_inventory_test() {
+        let krate = |project: &str, name: &str, oss: bool| GeneratedCrate {
+            project: project.to_owned(),
+            name: name.to_owned(),
+            cargo_toml_path: cargo_path(name),
+            description: None,
+            rule: format!("//{name}:{name}"),
+            rules: vec![format!("//{name}:{name}")],
+            oss_cargo_toml_path: oss.then(|| cargo_path(&format!("public/{name}"))),
+        };
+        let mut package = empty_package();
+        package.version = "0.1.0".to_owned();
+        let output = GenerationOutput {
+            cargo_manifests: hashmap! {
+                cargo_path("foo") => Manifest {
+                    package: Some(package),
+                    ..Manifest::default()
+                },
+            },
+            additional_files: HashMap::new(),
+            crates: vec![
+                krate("proj", "foo", true),
+                krate("other", "biz", false),
+                krate("proj", "bar", false),
+            ],
+        };
+
+        let inventory = crate_inventory(&output);
+        assert_eq!(
+            inventory
+                .iter()
+                .map(|entry| (entry.project.as_str(), entry.name.as_str()))
+                .collect::<Vec<_>>(),
+            vec![("other", "biz"), ("proj", "bar"), ("proj", "foo")],
+        );
+        assert_eq!(
+            inventory[2],
+            CrateInventoryEntry {
+                project: "proj".to_owned(),
+                name: "foo".to_owned(),
+                version: Some("0.1.0".to_owned()),
+                cargo_toml_path: "foo/Cargo.toml".to_owned(),
+                rules: vec!["//foo:foo".to_owned()],
+                oss_cargo_toml_path: Some("public/foo/Cargo.toml".to_owned()),
+            }
+        );
+        assert!(inventory[2].is_oss_published());
+        assert_eq!(inventory[1].version, None);
+    }
+}
@@ -24,7 +24,14 @@ pub mod buck_processing;
 pub mod cargo_generator;
 mod cargo_manifest;
 pub mod config;
+pub mod docs;
+pub mod error;
+pub mod events;
+pub mod graph;
+pub mod inventory;
 pub mod paths;
 pub mod project_loader;
+pub mod run;
 mod util;
+pub use crate::util::concurrency::ConcurrencyLimit;
 pub use crate::util::future_timeout::future_soft_timeout;
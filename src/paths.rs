@@ -22,14 +22,18 @@ use std::path::PathBuf;
 use anyhow::Context;
 use anyhow::Result;
 use anyhow::anyhow;
+use anyhow::bail;
 use anyhow::ensure;
 use derive_more::AsRef;
 use futures::TryStreamExt;
 use futures::stream::FuturesUnordered;
 use serde::Deserialize;
+use serde::Serialize;
 use tokio::fs::canonicalize;
 use tokio::fs::read_to_string;
 
+use crate::config::OssGitConfig;
+
 /// Parses provided paths, and makes them relative to root of fbcode.
 pub async fn process_input_paths<'a>(
     paths: impl IntoIterator<Item = &'a str>,
@@ -120,7 +124,7 @@ pub const RUST_VENDOR_STR: &str = "third-party/rust/vendor";
 
 /// Wrapper for PathBuf that holds path relative to root of fbcode which also
 /// is inside of fbcode.
-#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, AsRef, Deserialize)]
+#[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, AsRef, Deserialize, Serialize)]
 #[serde(transparent)]
 pub struct PathInFbcode(PathBuf);
 
@@ -135,10 +139,118 @@ impl PathInFbcode {
         "thrift_lib.rs"
     }
 
+    /// Filename of the build file used by generated from protobuf Cargo.toml.
+    pub const fn protobuf_build_filename() -> &'static str {
+        "protobuf_build.rs"
+    }
+
+    /// Filename of the lib file used by generated from protobuf Cargo.toml.
+    pub const fn protobuf_lib_filename() -> &'static str {
+        "protobuf_lib.rs"
+    }
+
+    /// Filename of the build file used by generated from cxx bridges
+    /// Cargo.toml.
+    pub const fn cxx_build_filename() -> &'static str {
+        "cxx_build.rs"
+    }
+
+    /// Filename of the build file copying plain file mapped_srcs in generated
+    /// Cargo.toml.
+    pub const fn mapped_srcs_build_filename() -> &'static str {
+        "mapped_srcs_build.rs"
+    }
+
+    /// Filename of the build file printing a deprecation warning in generated
+    /// Cargo.toml.
+    pub const fn deprecation_build_filename() -> &'static str {
+        "deprecation_build.rs"
+    }
+
+    /// Filename of the build file setting the `--cfg` rustc_flags of Buck
+    /// rules in generated Cargo.toml.
+    pub const fn rustc_cfg_build_filename() -> &'static str {
+        "rustc_cfg_build.rs"
+    }
+
+    /// Filename of rustfmt config generated next to workspace roots.
+    pub const fn rustfmt_filename() -> &'static str {
+        "rustfmt.toml"
+    }
+
+    /// Filename of clippy config generated next to workspace roots.
+    pub const fn clippy_filename() -> &'static str {
+        "clippy.toml"
+    }
+
+    /// Path of the cargo-nextest config generated next to workspace roots.
+    pub const fn nextest_filename() -> &'static str {
+        ".config/nextest.toml"
+    }
+
+    /// Name of the file listing the generated files of a project together with
+    /// hashes of their content.
+    pub const fn file_manifest_filename() -> &'static str {
+        ".autocargo_manifest.json"
+    }
+
+    /// Path of the Cargo config file relative to the directory it configures.
+    pub const fn cargo_config_filename() -> &'static str {
+        ".cargo/config.toml"
+    }
+
+    /// Filename of the README stub generated next to public Cargo.toml files.
+    pub const fn readme_filename() -> &'static str {
+        "README.md"
+    }
+
+    /// Filenames of license files that may be copied next to public Cargo.toml
+    /// files, so that the copies are deleted once they are no longer made.
+    pub const fn license_filenames() -> [&'static str; 3] {
+        ["LICENSE", "LICENSE-APACHE", "LICENSE-MIT"]
+    }
+
     /// List of all additional filenames that autocargo generates (excluding
     /// Cargo.toml).
     pub fn all_additional_filenames() -> Vec<&'static str> {
-        vec![Self::thrift_build_filename(), Self::thrift_lib_filename()]
+        vec![
+            Self::thrift_build_filename(),
+            Self::thrift_lib_filename(),
+            Self::protobuf_build_filename(),
+            Self::protobuf_lib_filename(),
+            Self::cxx_build_filename(),
+            Self::mapped_srcs_build_filename(),
+            Self::deprecation_build_filename(),
+            Self::rustc_cfg_build_filename(),
+            Self::rustfmt_filename(),
+            Self::clippy_filename(),
+            Self::cargo_config_filename(),
+            Self::nextest_filename(),
+            Self::readme_filename(),
+        ]
+        .into_iter()
+        .chain(Self::license_filenames())
+        .collect()
+    }
+
+    /// Filenames among [Self::all_additional_filenames] that are commonly
+    /// written by hand too, so such files are only treated as generated by
+    /// autocargo if their content says so.
+    pub fn hand_writable_filenames() -> Vec<&'static str> {
+        vec![
+            Self::rustfmt_filename(),
+            Self::clippy_filename(),
+            Self::cargo_config_filename(),
+            Self::nextest_filename(),
+            Self::readme_filename(),
+        ]
+    }
+
+    /// True if the path ends with one of [Self::hand_writable_filenames].
+    pub fn is_hand_writable(&self) -> bool {
+        Self::hand_writable_filenames()
+            .into_iter()
+            .any(|filename| self.0.ends_with(filename))
     }
 
     /// Given root of fbcode and an absolute path in fbcode computes path
@@ -198,6 +310,155 @@ impl Display for PathInFbcode {
     }
 }
 
+/// Return oss_git_config.public_cargo_dir together with its parent, which is
+/// the directory whose layout is mirrored inside public_cargo_dir.
+fn public_cargo_dir_and_parent(oss_git_config: &OssGitConfig) -> Result<(&PathInFbcode, &Path)> {
+    let Some(public_cargo_dir) = &oss_git_config.public_cargo_dir else {
+        bail!(
+            "The oss_git_config for {} has no public_cargo_dir",
+            oss_git_config.git
+        );
+    };
+    let parent = public_cargo_dir
+        .as_ref()
+        .parent()
+        .ok_or_else(|| anyhow!("Failed to get parent of public_cargo_dir: {public_cargo_dir:?}"))?;
+    Ok((public_cargo_dir, parent))
+}
+
+/// Translate a path of a generated file to the path of its oss-ready
+/// counterpart inside oss_git_config.public_cargo_dir, e.g. with
+/// public_cargo_dir = "proj/public_autocargo" the path "proj/foo/Cargo.toml"
+/// is translated to "proj/public_autocargo/foo/Cargo.toml".
+pub fn to_public_cargo_dir(
+    oss_git_config: &OssGitConfig,
+    path: &PathInFbcode,
+) -> Result<PathInFbcode> {
+    let (public_cargo_dir, parent) = public_cargo_dir_and_parent(oss_git_config)?;
+    let relative_path = path.as_ref().strip_prefix(parent).with_context(|| {
+        format!(
+            "Failed to strip prefix {} from {:?}, make sure project's generated \
+            Cargo.toml files are all inside of public_cargo_dir parent directory",
+            parent.display(),
+            path,
+        )
+    })?;
+    Ok(public_cargo_dir.join_to_path_in_fbcode(relative_path))
+}
+
+/// Reverse of [to_public_cargo_dir], translate a path inside
+/// oss_git_config.public_cargo_dir to the path it mirrors.
+pub fn from_public_cargo_dir(
+    oss_git_config: &OssGitConfig,
+    path: &PathInFbcode,
+) -> Result<PathInFbcode> {
+    let (public_cargo_dir, parent) = public_cargo_dir_and_parent(oss_git_config)?;
+    let relative_path = path
+        .as_ref()
+        .strip_prefix(public_cargo_dir.as_ref())
+        .with_context(|| {
+            format!("{path:?} is not inside of public_cargo_dir {public_cargo_dir}")
+        })?;
+    Ok(PathInFbcode(parent.join(relative_path)))
+}
+
+/// Translate each of the paths with [to_public_cargo_dir], yielding pairs of
+/// the original and the translated path. Paths that are already inside of
+/// public_cargo_dir are skipped, since they have no oss-ready counterpart.
+pub fn translate_tree<'a>(
+    oss_git_config: &'a OssGitConfig,
+    paths: impl IntoIterator<Item = &'a PathInFbcode> + 'a,
+) -> impl Iterator<Item = Result<(&'a PathInFbcode, PathInFbcode)>> + 'a {
+    paths
+        .into_iter()
+        .filter(|path| {
+            oss_git_config
+                .public_cargo_dir
+                .as_ref()
+                .is_none_or(|public_cargo_dir| {
+                    !path.as_ref().starts_with(public_cargo_dir.as_ref())
+                })
+        })
+        .map(|path| Ok((path, to_public_cargo_dir(oss_git_config, path)?)))
+}
+
+/// Relative path leading from base_dir to path, computed purely lexically so
+/// that symlinked checkouts give the same result as the real ones. Both paths
+/// are normalized first, `.` is dropped and `..` cancels the component before
+/// it, so paths escaping their dirs, e.g. public_cargo_dir layouts with `..`,
+/// are handled. On Windows and macOS, whose filesystems are case-insensitive
+/// by default, components are compared ignoring ASCII case.
+///
+/// Returns None if only one of the paths is absolute or if base_dir goes
+/// above its start with `..`, since the names of such dirs are unknown.
+pub fn relative_path(path: impl AsRef<Path>, base_dir: impl AsRef<Path>) -> Option<PathBuf> {
+    relative_path_impl(
+        path.as_ref(),
+        base_dir.as_ref(),
+        cfg!(any(windows, target_os = "macos")),
+    )
+}
+
+fn relative_path_impl(path: &Path, base_dir: &Path, case_insensitive: bool) -> Option<PathBuf> {
+    let path = normalize_lexically(path);
+    let base_dir = normalize_lexically(base_dir);
+    let is_rooted = |components: &[Component<'_>]| {
+        matches!(
+            components.first(),
+            Some(Component::Prefix(_) | Component::RootDir)
+        )
+    };
+    if is_rooted(&path) != is_rooted(&base_dir) {
+        return None;
+    }
+    let same = |a: &Component<'_>, b: &Component<'_>| {
+        if case_insensitive {
+            a.as_os_str()
+                .to_string_lossy()
+                .eq_ignore_ascii_case(&b.as_os_str().to_string_lossy())
+        } else {
+            a == b
+        }
+    };
+
+    let common = path
+        .iter()
+        .zip(&base_dir)
+        .take_while(|(a, b)| same(a, b))
+        .count();
+    if base_dir[common..].contains(&Component::ParentDir) {
+        return None;
+    }
+    Some(
+        base_dir[common..]
+            .iter()
+            .map(|_| Component::ParentDir)
+            .chain(path[common..].iter().copied())
+            .collect(),
+    )
+}
+
+/// Components of the path with `.` dropped and `..` resolved against the
+/// preceding components where possible.
+fn normalize_lexically(path: &Path) -> Vec<Component<'_>> {
+    let mut components = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match components.last() {
+                Some(Component::Normal(_)) => {
+                    components.pop();
+                }
+                // Nothing is above the root.
+                Some(Component::Prefix(_) | Component::RootDir) => {}
+                Some(Component::ParentDir | Component::CurDir) | None => components.push(component),
+            },
+            _ => components.push(component),
+        }
+    }
+    components
+}
+
 /// Wrapper for PathBuf that holds path to Cargo.toml file relative to fbcode.
 #[derive(Debug, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, AsRef)]
 pub struct CargoTomlPath {
@@ -237,7 +498,75 @@ impl CargoTomlPath {
     }
 }
 
+/// List of filenames that are recognized as Buck build files in the repo. By
+/// default these are the [TargetsPath::filenames], but repositories that use
+/// other conventions (e.g. BUILD or BUILD.bazel in mixed repos) can override
+/// them.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "Vec<String>")]
+pub struct BuildFilenames(Vec<String>);
+
+impl Default for BuildFilenames {
+    fn default() -> Self {
+        Self(
+            TargetsPath::filenames()
+                .iter()
+                .map(|name| (*name).to_owned())
+                .collect(),
+        )
+    }
+}
+
+impl TryFrom<Vec<String>> for BuildFilenames {
+    type Error = anyhow::Error;
+
+    fn try_from(filenames: Vec<String>) -> Result<Self> {
+        Self::new(filenames)
+    }
+}
+
+impl BuildFilenames {
+    /// Validates that the provided filenames are non-empty, unique and do not
+    /// contain path separators.
+    pub fn new(filenames: impl IntoIterator<Item = impl Into<String>>) -> Result<Self> {
+        let mut result = Vec::new();
+        for name in filenames {
+            let name = name.into();
+            ensure!(
+                !name.is_empty() && Path::new(&name).file_name() == Some(name.as_ref()),
+                "Build filename '{}' is not a plain filename",
+                name
+            );
+            ensure!(
+                !result.contains(&name),
+                "Build filename '{}' was provided more than once",
+                name
+            );
+            result.push(name);
+        }
+        ensure!(
+            !result.is_empty(),
+            "At least one build filename is required"
+        );
+        Ok(Self(result))
+    }
+
+    /// Iterate over the recognized filenames.
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.0.iter().map(String::as_str)
+    }
+
+    /// Check if the path provided ends with one of the recognized filenames.
+    pub fn matches_path(&self, path: &Path) -> bool {
+        self.iter().any(|name| path.ends_with(name))
+    }
+}
+
 /// Wrapper for PathBuf that holds path to TARGETS file relative to fbcode.
+///
+/// Build files are always queried as part of the fbcode cell, i.e. as
+/// `fbcode//<dir>:`, so build files of cells nested in fbcode are not
+/// supported yet.
 #[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash)]
 pub struct TargetsPath {
     dir: PathInFbcode,
@@ -257,9 +586,15 @@ impl TargetsPath {
 
     /// Given a path relative in fbcode wrap it.
     pub fn new(path: PathInFbcode) -> Result<Self> {
+        Self::new_with_filenames(path, &BuildFilenames::default())
+    }
+
+    /// Given a path relative in fbcode wrap it, accepting any of the provided
+    /// build filenames.
+    pub fn new_with_filenames(path: PathInFbcode, filenames: &BuildFilenames) -> Result<Self> {
         let dir = if !path.0.is_dir() {
             ensure!(
-                Self::filenames().iter().any(|name| path.0.ends_with(name)),
+                filenames.matches_path(&path.0),
                 "Provided path {} does not point to a valid BUCK file",
                 path.0.display(),
             );
@@ -373,4 +708,95 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn public_cargo_dir_translation_test() {
+        let oss_git_config: OssGitConfig = toml::from_str(
+            r#"
+            git = "https://github.com/foo/bar.git"
+            public_cargo_dir = "proj/public_autocargo"
+            "#,
+        )
+        .unwrap();
+        let path = PathInFbcode::new_mock("proj/foo/Cargo.toml");
+        let public_path = PathInFbcode::new_mock("proj/public_autocargo/foo/Cargo.toml");
+
+        assert_eq!(
+            to_public_cargo_dir(&oss_git_config, &path).unwrap(),
+            public_path
+        );
+        assert_eq!(
+            from_public_cargo_dir(&oss_git_config, &public_path).unwrap(),
+            path
+        );
+        to_public_cargo_dir(&oss_git_config, &PathInFbcode::new_mock("other/Cargo.toml"))
+            .unwrap_err();
+        from_public_cargo_dir(&oss_git_config, &path).unwrap_err();
+
+        assert_eq!(
+            translate_tree(&oss_git_config, [&path, &public_path])
+                .collect::<Result<Vec<_>>>()
+                .unwrap(),
+            vec![(&path, public_path.clone())]
+        );
+    }
+
+    #[test]
+    fn relative_path_test() {
+        let rel =
+            |path: &str, base: &str| relative_path_impl(Path::new(path), Path::new(base), false);
+        assert_eq!(rel("foo/bar", "foo/biz"), Some(PathBuf::from("../bar")));
+        assert_eq!(rel("foo/bar", "foo/bar"), Some(PathBuf::new()));
+        assert_eq!(rel("", "foo/bar"), Some(PathBuf::from("../..")));
+        assert_eq!(rel("foo/bar", ""), Some(PathBuf::from("foo/bar")));
+        assert_eq!(
+            rel("proj/public_autocargo/../foo/./bar", "proj/biz"),
+            Some(PathBuf::from("../foo/bar"))
+        );
+        assert_eq!(
+            rel("../other/foo", "proj"),
+            Some(PathBuf::from("../../other/foo"))
+        );
+        assert_eq!(rel("../other/foo", "../other"), Some(PathBuf::from("foo")));
+        assert_eq!(rel("/a/b/c", "/a/d"), Some(PathBuf::from("../b/c")));
+        assert_eq!(rel("/../a", "/a/b"), Some(PathBuf::from("..")));
+        assert_eq!(rel("foo", "../bar"), None);
+        assert_eq!(rel("/foo", "foo"), None);
+        assert_eq!(
+            rel("Foo/Bar", "foo/biz"),
+            Some(PathBuf::from("../../Foo/Bar"))
+        );
+
+        let rel_insensitive =
+            |path: &str, base: &str| relative_path_impl(Path::new(path), Path::new(base), true);
+        assert_eq!(
+            rel_insensitive("Foo/Bar", "foo/biz"),
+            Some(PathBuf::from("../Bar"))
+        );
+        assert_eq!(
+            relative_path("proj/foo", "proj/bar"),
+            Some(PathBuf::from("../foo"))
+        );
+    }
+
+    #[test]
+    fn build_filenames_test() {
+        let filenames = BuildFilenames::new(["BUILD", "BUILD.bazel"]).unwrap();
+        assert!(filenames.matches_path(Path::new("foo/bar/BUILD.bazel")));
+        assert!(!filenames.matches_path(Path::new("foo/bar/BUCK")));
+
+        assert_eq!(
+            TargetsPath::new_with_filenames(PathInFbcode::new_mock("foo/bar/BUILD"), &filenames)
+                .unwrap()
+                .as_dir(),
+            &PathInFbcode::new_mock("foo/bar")
+        );
+        TargetsPath::new_with_filenames(PathInFbcode::new_mock("foo/bar/BUCK"), &filenames)
+            .unwrap_err();
+
+        BuildFilenames::new(Vec::<String>::new()).unwrap_err();
+        BuildFilenames::new(["BUILD", "BUILD"]).unwrap_err();
+        BuildFilenames::new(["foo/BUILD"]).unwrap_err();
+        BuildFilenames::new([""]).unwrap_err();
+    }
 }
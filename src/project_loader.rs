@@ -11,6 +11,7 @@
 //! paths that those project cover.
 
 mod eden_prefetch;
+mod file_discovery;
 mod files_uniqueness_check;
 mod project_files_load;
 mod projectless_files;
@@ -21,10 +22,17 @@ use slog::Logger;
 use self::files_uniqueness_check::files_uniqueness_check;
 pub use self::r#impl::ProjectFiles;
 pub use self::r#impl::ProjectlessFiles;
+use crate::config::FileDiscoveryConfig;
 use crate::config::SelectedProjects;
+use crate::error::AutocargoError;
+use crate::events::AutocargoEvent;
+use crate::events::EventSender;
+use crate::events::Phase;
+use crate::paths::BuildFilenames;
 use crate::paths::FbcodeRoot;
 use crate::paths::FbsourceRoot;
 use crate::paths::PathInFbcode;
+use crate::util::concurrency::ConcurrencyLimit;
 
 /// Main structure of this module which performs the loading of relevant paths.
 pub struct ProjectLoader<'proj, 'a> {
@@ -38,19 +46,54 @@ pub struct ProjectLoader<'proj, 'a> {
     pub configs: &'a SelectedProjects<'proj>,
     /// Input paths provided by user.
     pub input_paths: Vec<PathInFbcode>,
+    /// Filenames that are recognized as Buck build files.
+    pub build_filenames: &'a BuildFilenames,
+    /// Receives progress events.
+    pub events: &'a EventSender,
+    /// Limits concurrent filesystem operations.
+    pub concurrency: &'a ConcurrencyLimit,
+    /// Don't access the network, i.e. don't ask EdenFS to prefetch files.
+    pub offline: bool,
+    /// If set, the files of the projects are listed with this command rather
+    /// than found by walking their roots.
+    pub file_discovery: Option<&'a FileDiscoveryConfig>,
 }
 
 impl<'proj, 'a> ProjectLoader<'proj, 'a> {
     /// Load files for selected projects. It does so by:
-    /// - prefetching files using eden to speed up operations on EdenFS
+    /// - prefetching files using eden to speed up operations on EdenFS, unless
+    ///   the files are listed with the file_discovery command
     /// - using include/exclude globs from projects gather the relevant paths
     /// - check those paths and validate them for uniqueness, so two projects
     ///   don't try to cover the same paths
     /// - compute which files provided by user were not covered by any project
-    pub async fn load(self) -> Result<(Vec<ProjectFiles<'proj>>, ProjectlessFiles)> {
-        // Prefetching files with eden should speed up further operations
-        self.eden_prefetch().await?;
-        let project_files_list = self.project_files_load().await?;
+    pub async fn load(
+        self,
+    ) -> Result<(Vec<ProjectFiles<'proj>>, ProjectlessFiles), AutocargoError> {
+        self.load_impl()
+            .await
+            .map_err(AutocargoError::project_files)
+    }
+
+    async fn load_impl(self) -> Result<(Vec<ProjectFiles<'proj>>, ProjectlessFiles)> {
+        self.events
+            .emit(AutocargoEvent::PhaseStarted(Phase::LoadProjects));
+        let project_files_list = match self.file_discovery {
+            Some(file_discovery) => self.project_files_discover(file_discovery).await?,
+            None => {
+                // Prefetching files with eden should speed up further operations
+                self.eden_prefetch().await?;
+                self.project_files_load().await?
+            }
+        };
+        for project_files in &project_files_list {
+            self.events.emit(AutocargoEvent::ProjectLoaded {
+                name: project_files.conf().name().clone(),
+                cargo: project_files.cargo().len(),
+                targets: project_files.targets().len(),
+                additional: project_files.additional().len(),
+            });
+        }
         let (all_cargo, all_targets, all_additional) = files_uniqueness_check(&project_files_list)?;
         let projectless_files = self.projectless_files(all_cargo, all_targets, all_additional);
         Ok((project_files_list, projectless_files))
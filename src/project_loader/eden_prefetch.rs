@@ -18,6 +18,7 @@ use anyhow::Context as _;
 use anyhow::Result;
 use futures::future;
 use itertools::Itertools;
+use slog::info;
 use tokio::fs::metadata;
 use tokio::io::AsyncWriteExt as _;
 use tokio::io::BufWriter;
@@ -25,12 +26,12 @@ use tokio::process::Command;
 
 use super::ProjectLoader;
 use crate::config::ProjectConf;
+use crate::paths::BuildFilenames;
 use crate::paths::CargoTomlPath;
 use crate::paths::FbcodeRoot;
 use crate::paths::FbsourceRoot;
 use crate::paths::PathInFbcode;
 use crate::paths::RUST_VENDOR_STR;
-use crate::paths::TargetsPath;
 use crate::util::command_runner::run_command;
 
 impl<'proj, 'a> ProjectLoader<'proj, 'a> {
@@ -43,9 +44,18 @@ impl<'proj, 'a> ProjectLoader<'proj, 'a> {
             logger,
             fbsource_root,
             configs,
+            build_filenames,
+            offline,
             ..
         } = self;
 
+        if offline {
+            // Prefetching fetches the files from the servers up front, it is
+            // just an optimisation, so skip it.
+            info!(logger, "Skipping eden prefetch in offline mode");
+            return Ok(());
+        }
+
         if metadata(Path::join(fbsource_root.as_ref(), ".eden"))
             .await
             .is_err()
@@ -61,7 +71,7 @@ impl<'proj, 'a> ProjectLoader<'proj, 'a> {
             logger,
             "eden prefetch",
             Duration::from_secs(5),
-            eden_prefetch_cmd(fbsource_root, configs.projects()),
+            eden_prefetch_cmd(fbsource_root, configs.projects(), build_filenames),
         )
         .await?;
 
@@ -72,6 +82,7 @@ impl<'proj, 'a> ProjectLoader<'proj, 'a> {
 async fn eden_prefetch_cmd(
     fbsource_root: &FbsourceRoot,
     configs: &[&ProjectConf],
+    build_filenames: &BuildFilenames,
 ) -> Result<(Command, Output)> {
     let mut command = Command::new("eden");
     command
@@ -90,7 +101,7 @@ async fn eden_prefetch_cmd(
 
     let (_, output) = future::try_join(
         async move {
-            let patterns = eden_prefetch_args(configs);
+            let patterns = eden_prefetch_args(configs, build_filenames);
             for pat in patterns {
                 let line = format!("{}\n", pat.display());
                 stdin.write_all(line.as_bytes()).await?;
@@ -109,6 +120,7 @@ async fn eden_prefetch_cmd(
 /// of fbsource to be passed to 'eden prefetch'
 fn eden_prefetch_args<'a>(
     configs: &[&'a ProjectConf],
+    build_filenames: &'a BuildFilenames,
 ) -> impl Iterator<Item = PathBuf> + 'a + use<'a> {
     let fbcode_root = Path::new(FbcodeRoot::dirname());
     let patterns: BTreeSet<PathBuf> = configs
@@ -140,7 +152,7 @@ fn eden_prefetch_args<'a>(
                 .iter()
                 .map(|filename| pat.join(filename))
                 .chain(Some(pat.join(CargoTomlPath::filename())))
-                .chain(TargetsPath::filenames().iter().map(|name| pat.join(name)))
+                .chain(build_filenames.iter().map(|name| pat.join(name)))
                 .collect_vec()
         }),
         vendor_paths,
@@ -182,15 +194,32 @@ mod test {
                 .collect::<Vec<_>>()
         };
         assert_eq!(
-            eden_prefetch_args(&[
-                &pc(&[], &["a/b/**", "c"]),
-                &pc(&[], &["d/**/e"]),
-                &pc(&["f"], &[])
-            ])
+            eden_prefetch_args(
+                &[
+                    &pc(&[], &["a/b/**", "c"]),
+                    &pc(&[], &["d/**/e"]),
+                    &pc(&["f"], &[])
+                ],
+                &BuildFilenames::default()
+            )
             .collect::<Vec<_>>(),
             vec_p(&[
                 "fbcode/a/b/**/thrift_build.rs",
                 "fbcode/a/b/**/thrift_lib.rs",
+                "fbcode/a/b/**/protobuf_build.rs",
+                "fbcode/a/b/**/protobuf_lib.rs",
+                "fbcode/a/b/**/cxx_build.rs",
+                "fbcode/a/b/**/mapped_srcs_build.rs",
+                "fbcode/a/b/**/deprecation_build.rs",
+                "fbcode/a/b/**/rustc_cfg_build.rs",
+                "fbcode/a/b/**/rustfmt.toml",
+                "fbcode/a/b/**/clippy.toml",
+                "fbcode/a/b/**/.cargo/config.toml",
+                "fbcode/a/b/**/.config/nextest.toml",
+                "fbcode/a/b/**/README.md",
+                "fbcode/a/b/**/LICENSE",
+                "fbcode/a/b/**/LICENSE-APACHE",
+                "fbcode/a/b/**/LICENSE-MIT",
                 "fbcode/a/b/**/Cargo.toml",
                 "fbcode/a/b/**/TARGETS",
                 "fbcode/a/b/**/BUCK",
@@ -198,6 +227,20 @@ mod test {
                 "fbcode/a/b/**/BUCK.v2",
                 "fbcode/c/thrift_build.rs",
                 "fbcode/c/thrift_lib.rs",
+                "fbcode/c/protobuf_build.rs",
+                "fbcode/c/protobuf_lib.rs",
+                "fbcode/c/cxx_build.rs",
+                "fbcode/c/mapped_srcs_build.rs",
+                "fbcode/c/deprecation_build.rs",
+                "fbcode/c/rustc_cfg_build.rs",
+                "fbcode/c/rustfmt.toml",
+                "fbcode/c/clippy.toml",
+                "fbcode/c/.cargo/config.toml",
+                "fbcode/c/.config/nextest.toml",
+                "fbcode/c/README.md",
+                "fbcode/c/LICENSE",
+                "fbcode/c/LICENSE-APACHE",
+                "fbcode/c/LICENSE-MIT",
                 "fbcode/c/Cargo.toml",
                 "fbcode/c/TARGETS",
                 "fbcode/c/BUCK",
@@ -205,6 +248,20 @@ mod test {
                 "fbcode/c/BUCK.v2",
                 "fbcode/d/**/e/thrift_build.rs",
                 "fbcode/d/**/e/thrift_lib.rs",
+                "fbcode/d/**/e/protobuf_build.rs",
+                "fbcode/d/**/e/protobuf_lib.rs",
+                "fbcode/d/**/e/cxx_build.rs",
+                "fbcode/d/**/e/mapped_srcs_build.rs",
+                "fbcode/d/**/e/deprecation_build.rs",
+                "fbcode/d/**/e/rustc_cfg_build.rs",
+                "fbcode/d/**/e/rustfmt.toml",
+                "fbcode/d/**/e/clippy.toml",
+                "fbcode/d/**/e/.cargo/config.toml",
+                "fbcode/d/**/e/.config/nextest.toml",
+                "fbcode/d/**/e/README.md",
+                "fbcode/d/**/e/LICENSE",
+                "fbcode/d/**/e/LICENSE-APACHE",
+                "fbcode/d/**/e/LICENSE-MIT",
                 "fbcode/d/**/e/Cargo.toml",
                 "fbcode/d/**/e/TARGETS",
                 "fbcode/d/**/e/BUCK",
@@ -212,6 +269,20 @@ mod test {
                 "fbcode/d/**/e/BUCK.v2",
                 "fbcode/f/**/thrift_build.rs",
                 "fbcode/f/**/thrift_lib.rs",
+                "fbcode/f/**/protobuf_build.rs",
+                "fbcode/f/**/protobuf_lib.rs",
+                "fbcode/f/**/cxx_build.rs",
+                "fbcode/f/**/mapped_srcs_build.rs",
+                "fbcode/f/**/deprecation_build.rs",
+                "fbcode/f/**/rustc_cfg_build.rs",
+                "fbcode/f/**/rustfmt.toml",
+                "fbcode/f/**/clippy.toml",
+                "fbcode/f/**/.cargo/config.toml",
+                "fbcode/f/**/.config/nextest.toml",
+                "fbcode/f/**/README.md",
+                "fbcode/f/**/LICENSE",
+                "fbcode/f/**/LICENSE-APACHE",
+                "fbcode/f/**/LICENSE-MIT",
                 "fbcode/f/**/Cargo.toml",
                 "fbcode/f/**/TARGETS",
                 "fbcode/f/**/BUCK",
@@ -220,11 +291,28 @@ mod test {
             ])
         );
         assert_eq!(
-            eden_prefetch_args(&[&pc_with_lock(&["a/**/b"], "a/some/random/path")])
-                .collect::<Vec<_>>(),
+            eden_prefetch_args(
+                &[&pc_with_lock(&["a/**/b"], "a/some/random/path")],
+                &BuildFilenames::default()
+            )
+            .collect::<Vec<_>>(),
             vec_p(&[
                 "fbcode/a/**/b/thrift_build.rs",
                 "fbcode/a/**/b/thrift_lib.rs",
+                "fbcode/a/**/b/protobuf_build.rs",
+                "fbcode/a/**/b/protobuf_lib.rs",
+                "fbcode/a/**/b/cxx_build.rs",
+                "fbcode/a/**/b/mapped_srcs_build.rs",
+                "fbcode/a/**/b/deprecation_build.rs",
+                "fbcode/a/**/b/rustc_cfg_build.rs",
+                "fbcode/a/**/b/rustfmt.toml",
+                "fbcode/a/**/b/clippy.toml",
+                "fbcode/a/**/b/.cargo/config.toml",
+                "fbcode/a/**/b/.config/nextest.toml",
+                "fbcode/a/**/b/README.md",
+                "fbcode/a/**/b/LICENSE",
+                "fbcode/a/**/b/LICENSE-APACHE",
+                "fbcode/a/**/b/LICENSE-MIT",
                 "fbcode/a/**/b/Cargo.toml",
                 "fbcode/a/**/b/TARGETS",
                 "fbcode/a/**/b/BUCK",
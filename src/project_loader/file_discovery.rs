@@ -0,0 +1,290 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Discovery of the files covered by the projects with a command listing the
+//! files of the checkout, e.g. `hg files` or `eden glob`, which unlike walking
+//! the project roots doesn't fault in every visited file on EdenFS.
+
+use std::collections::BTreeSet;
+use std::collections::HashSet;
+use std::path::Path;
+use std::process::Output;
+use std::process::Stdio;
+use std::time::Duration;
+
+use ::glob::MatchOptions;
+use ::glob::Pattern;
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::bail;
+use itertools::Itertools;
+use tokio::process::Command;
+
+use super::ProjectFiles;
+use super::ProjectLoader;
+use super::project_files_load::maybe_public_cargo_dir_pattern;
+use crate::config::FileDiscoveryConfig;
+use crate::config::ProjectConf;
+use crate::paths::BuildFilenames;
+use crate::paths::CargoTomlPath;
+use crate::paths::FbcodeRoot;
+use crate::paths::FbsourceRoot;
+use crate::paths::PathInFbcode;
+use crate::paths::TargetsPath;
+use crate::util::command_runner::run_command;
+
+/// The options [::glob::glob] matches the walked paths with, so that the
+/// listed files are covered by the same patterns.
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// Kind of the files a pattern matches.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum FileKind {
+    Cargo,
+    Targets,
+    Additional,
+}
+
+impl<'proj, 'a> ProjectLoader<'proj, 'a> {
+    /// List the files matching the include globs of all selected projects
+    /// with a single run of the file_discovery command, then pick the paths
+    /// covered by each project out of them.
+    pub(super) async fn project_files_discover(
+        &self,
+        file_discovery: &FileDiscoveryConfig,
+    ) -> Result<Vec<ProjectFiles<'proj>>> {
+        let &Self {
+            logger,
+            fbsource_root,
+            fbcode_root,
+            configs,
+            build_filenames,
+            ..
+        } = self;
+
+        let project_patterns = configs
+            .projects()
+            .iter()
+            .map(|conf| -> Result<_> { Ok((*conf, project_patterns(conf, build_filenames)?)) })
+            .collect::<Result<Vec<_>>>()?;
+        let patterns: BTreeSet<String> = project_patterns
+            .iter()
+            .flat_map(|(_, patterns)| patterns)
+            .map(|(pattern, _)| format!("{}/{}", FbcodeRoot::dirname(), pattern.as_str()))
+            .collect();
+
+        let output = run_command(
+            logger,
+            "file discovery",
+            Duration::from_secs(5),
+            file_discovery_cmd(fbsource_root, file_discovery, &patterns),
+        )
+        .await?;
+        if !output.status.success() {
+            bail!(
+                "File discovery command {} failed with {}",
+                file_discovery.binary.display(),
+                output.status,
+            );
+        }
+        let files = parse_discovered_files(fbsource_root, fbcode_root, &output.stdout)?;
+
+        let mut result = project_patterns
+            .into_iter()
+            .map(|(conf, patterns)| files_for_project(conf, build_filenames, &patterns, &files))
+            .collect::<Result<Vec<_>>>()?;
+        result.sort_unstable_by_key(|proj_files| proj_files.conf().name());
+        Ok(result)
+    }
+}
+
+async fn file_discovery_cmd(
+    fbsource_root: &FbsourceRoot,
+    file_discovery: &FileDiscoveryConfig,
+    patterns: &BTreeSet<String>,
+) -> Result<(Command, Output)> {
+    let mut command = Command::new(&file_discovery.binary);
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .current_dir(fbsource_root)
+        .args(&file_discovery.args)
+        .args(
+            patterns
+                .iter()
+                .map(|pattern| format!("{}{pattern}", file_discovery.pattern_prefix)),
+        );
+
+    let output = command
+        .output()
+        .await
+        .with_context(|| format!("Executing command: {:?}", command.as_std()))?;
+    Ok((command, output))
+}
+
+/// Patterns of the files the project covers, i.e. its include globs, roots
+/// and public_cargo_dir joined with the names of the files autocargo looks
+/// for, same as the ones walked by [ProjectLoader::project_files_load].
+fn project_patterns(
+    conf: &ProjectConf,
+    build_filenames: &BuildFilenames,
+) -> Result<Vec<(Pattern, FileKind)>> {
+    let filenames: Vec<_> = std::iter::once((CargoTomlPath::filename(), FileKind::Cargo))
+        .chain(build_filenames.iter().map(|name| (name, FileKind::Targets)))
+        .chain(
+            PathInFbcode::all_additional_filenames()
+                .into_iter()
+                .map(|name| (name, FileKind::Additional)),
+        )
+        .collect();
+
+    let result: Result<_> = try {
+        conf.include_globs()
+            .iter()
+            .cloned()
+            .chain(conf.root_patterns()?)
+            .chain(maybe_public_cargo_dir_pattern(conf)?)
+            .cartesian_product(filenames)
+            .map(|(dir, (filename, kind))| {
+                Ok((Pattern::new(&format!("{}/{filename}", dir.as_str()))?, kind))
+            })
+            .collect::<Result<_>>()?
+    };
+    result.with_context(|| format!("While creating file patterns for project {}", conf.name()))
+}
+
+/// Paths in fbcode of the files printed by the file discovery command, the
+/// ones outside of fbcode are skipped.
+fn parse_discovered_files(
+    fbsource_root: &FbsourceRoot,
+    fbcode_root: &FbcodeRoot,
+    stdout: &[u8],
+) -> Result<Vec<PathInFbcode>> {
+    let stdout = std::str::from_utf8(stdout)
+        .context("Output of the file discovery command is not valid UTF-8")?;
+    stdout
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| Path::join(fbsource_root.as_ref(), line))
+        .filter(|path| path.starts_with(fbcode_root))
+        .map(|path| PathInFbcode::from_absolute(fbcode_root, path))
+        .collect()
+}
+
+/// The files covered by the project out of all the discovered ones.
+fn files_for_project<'proj>(
+    conf: &'proj ProjectConf,
+    build_filenames: &BuildFilenames,
+    patterns: &[(Pattern, FileKind)],
+    files: &[PathInFbcode],
+) -> Result<ProjectFiles<'proj>> {
+    let mut cargo = HashSet::new();
+    let mut targets = HashSet::new();
+    let mut additional = HashSet::new();
+
+    for path in files {
+        if conf
+            .exclude_globs()
+            .iter()
+            .any(|pattern| pattern.matches_path(path.as_ref()))
+        {
+            continue;
+        }
+        let kinds = patterns
+            .iter()
+            .filter(|(pattern, _)| pattern.matches_path_with(path.as_ref(), MATCH_OPTIONS))
+            .map(|(_, kind)| *kind)
+            .unique();
+        for kind in kinds {
+            match kind {
+                FileKind::Cargo => {
+                    cargo.insert(CargoTomlPath::new(path.clone())?);
+                }
+                FileKind::Targets => {
+                    targets.insert(TargetsPath::new_with_filenames(
+                        path.clone(),
+                        build_filenames,
+                    )?);
+                }
+                FileKind::Additional => {
+                    additional.insert(path.clone());
+                }
+            }
+        }
+    }
+
+    Ok(ProjectFiles::new(
+        conf,
+        cargo.into_iter().collect(),
+        targets.into_iter().collect(),
+        additional.into_iter().collect(),
+    ))
+}
+
+#[cfg(test)]
+mod test {
+    use serde_json::from_value;
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn files_for_project_test() {
+        let fbcode_root = FbcodeRoot::new_mock("/fbsource/fbcode");
+        let fbsource_root = FbsourceRoot::from(fbcode_root.clone());
+        let files = parse_discovered_files(
+            &fbsource_root,
+            &fbcode_root,
+            b"fbcode/a/BUCK\n\
+            fbcode/a/b/Cargo.toml\n\
+            fbcode/a/b/TARGETS\n\
+            fbcode/a/excluded/Cargo.toml\n\
+            fbcode/c/d/.cargo/config.toml\n\
+            fbcode/c/d/e/BUCK\n\
+            fbcode/c/Cargo.toml\n\
+            xplat/a/BUCK\n\n",
+        )
+        .unwrap();
+        assert_eq!(files.len(), 7);
+
+        let conf: ProjectConf = from_value(json!({
+            "name": "proj",
+            "oncall": "oncall_name",
+            "roots": ["a"],
+            "include_globs": ["c/*"],
+            "exclude_globs": ["a/excluded/**"],
+        }))
+        .unwrap();
+        let build_filenames = BuildFilenames::default();
+        let patterns = project_patterns(&conf, &build_filenames).unwrap();
+        let project_files = files_for_project(&conf, &build_filenames, &patterns, &files).unwrap();
+
+        assert_eq!(
+            project_files.cargo(),
+            &vec![CargoTomlPath::new(PathInFbcode::new_mock("a/b/Cargo.toml")).unwrap()]
+        );
+        assert_eq!(
+            project_files.targets(),
+            &vec![
+                TargetsPath::new(PathInFbcode::new_mock("a/BUCK")).unwrap(),
+                TargetsPath::new(PathInFbcode::new_mock("a/b/TARGETS")).unwrap(),
+            ]
+        );
+        assert_eq!(
+            project_files.additional(),
+            &vec![PathInFbcode::new_mock("c/d/.cargo/config.toml")]
+        );
+    }
+}
@@ -26,10 +26,12 @@ use tokio::task::spawn_blocking;
 use super::ProjectFiles;
 use super::ProjectLoader;
 use crate::config::ProjectConf;
+use crate::paths::BuildFilenames;
 use crate::paths::CargoTomlPath;
 use crate::paths::FbcodeRoot;
 use crate::paths::PathInFbcode;
 use crate::paths::TargetsPath;
+use crate::util::concurrency::ConcurrencyLimit;
 
 // This is to help with mocking calls to [::glob::glob], as described here:
 // https://docs.rs/mockall/0.8.3/mockall/index.html#mocking-structs
@@ -48,6 +50,8 @@ impl<'proj, 'a> ProjectLoader<'proj, 'a> {
         get_files_for_multiple_projects(
             Arc::new(Glob::default()),
             self.fbcode_root,
+            self.concurrency,
+            self.build_filenames,
             self.configs.projects().iter().cloned(), // && -> & with cloned
         )
         .await
@@ -57,16 +61,23 @@ impl<'proj, 'a> ProjectLoader<'proj, 'a> {
 async fn get_files_for_multiple_projects<'proj>(
     glob: Arc<Glob>,
     fbcode_root: &FbcodeRoot,
+    concurrency: &ConcurrencyLimit,
+    build_filenames: &BuildFilenames,
     configs: impl IntoIterator<Item = &'proj ProjectConf>,
 ) -> Result<Vec<ProjectFiles<'proj>>> {
     let mut result: Vec<_> = configs
         .into_iter()
         .map(|conf| {
-            get_files_for_project(glob.clone(), fbcode_root, conf).and_then(
-                move |(cargo, targets, additional)| async move {
-                    Ok(ProjectFiles::new(conf, cargo, targets, additional))
-                },
+            get_files_for_project(
+                glob.clone(),
+                fbcode_root,
+                concurrency,
+                build_filenames,
+                conf,
             )
+            .and_then(move |(cargo, targets, additional)| async move {
+                Ok(ProjectFiles::new(conf, cargo, targets, additional))
+            })
         })
         .collect::<FuturesUnordered<_>>()
         .try_collect()
@@ -79,6 +90,8 @@ async fn get_files_for_multiple_projects<'proj>(
 async fn get_files_for_project(
     glob: Arc<Glob>,
     fbcode_root: &FbcodeRoot,
+    concurrency: &ConcurrencyLimit,
+    build_filenames: &BuildFilenames,
     conf: &ProjectConf,
 ) -> Result<(Vec<CargoTomlPath>, Vec<TargetsPath>, Vec<PathInFbcode>)> {
     let maybe_public_cargo_dir_pattern = maybe_public_cargo_dir_pattern(conf)?;
@@ -92,7 +105,7 @@ async fn get_files_for_project(
         .chain(root_patterns.iter())
         .chain(maybe_public_cargo_dir_pattern.as_ref())
         .map(|include_pat| {
-            let cargo_fut = get_files_helper(
+            let cargo_fut = concurrency.run(get_files_helper(
                 glob.clone(),
                 fbcode_root.clone(),
                 conf.name(),
@@ -100,21 +113,23 @@ async fn get_files_for_project(
                 CargoTomlPath::filename(),
                 CargoTomlPath::new,
                 exclude_globs.clone(),
-            );
+            ));
 
-            let targets_fut = TargetsPath::filenames()
+            let targets_fut = build_filenames
                 .iter()
                 .map(|filename| {
-                    get_files_helper(
-                        glob.clone(),
-                        fbcode_root.clone(),
-                        conf.name(),
-                        include_pat.clone(),
-                        filename,
-                        TargetsPath::new,
-                        exclude_globs.clone(),
-                    )
-                    .map_ok(|vec| stream::iter(vec.into_iter().map(Result::<_>::Ok)))
+                    let build_filenames = build_filenames.clone();
+                    concurrency
+                        .run(get_files_helper(
+                            glob.clone(),
+                            fbcode_root.clone(),
+                            conf.name(),
+                            include_pat.clone(),
+                            filename,
+                            move |path| TargetsPath::new_with_filenames(path, &build_filenames),
+                            exclude_globs.clone(),
+                        ))
+                        .map_ok(|vec| stream::iter(vec.into_iter().map(Result::<_>::Ok)))
                 })
                 .collect::<FuturesUnordered<_>>()
                 .try_flatten()
@@ -123,16 +138,17 @@ async fn get_files_for_project(
             let additional_fut = PathInFbcode::all_additional_filenames()
                 .iter()
                 .map(|filename| {
-                    get_files_helper(
-                        glob.clone(),
-                        fbcode_root.clone(),
-                        conf.name(),
-                        include_pat.clone(),
-                        filename,
-                        Ok,
-                        exclude_globs.clone(),
-                    )
-                    .map_ok(|vec| stream::iter(vec.into_iter().map(Result::<_>::Ok)))
+                    concurrency
+                        .run(get_files_helper(
+                            glob.clone(),
+                            fbcode_root.clone(),
+                            conf.name(),
+                            include_pat.clone(),
+                            filename,
+                            Ok,
+                            exclude_globs.clone(),
+                        ))
+                        .map_ok(|vec| stream::iter(vec.into_iter().map(Result::<_>::Ok)))
                 })
                 .collect::<FuturesUnordered<_>>()
                 .try_flatten()
@@ -162,7 +178,7 @@ async fn get_files_for_project(
 }
 
 /// Create a pattern from public_cargo_dir if it is present in the project.
-fn maybe_public_cargo_dir_pattern(conf: &ProjectConf) -> Result<Option<Pattern>> {
+pub(super) fn maybe_public_cargo_dir_pattern(conf: &ProjectConf) -> Result<Option<Pattern>> {
     conf.oss_git_config()
         .as_ref()
         .and_then(|oss_git_config| oss_git_config.public_cargo_dir.as_ref())
@@ -359,6 +375,20 @@ mod test {
                 "/a/b/c/**/TARGETS.v2" => Ok(vec![]),
                 "/a/b/c/**/thrift_lib.rs" => Ok(vec![]),
                 "/a/b/c/**/thrift_build.rs" => Ok(vec![]),
+                "/a/b/c/**/protobuf_build.rs" => Ok(vec![]),
+                "/a/b/c/**/protobuf_lib.rs" => Ok(vec![]),
+                "/a/b/c/**/cxx_build.rs" => Ok(vec![]),
+                "/a/b/c/**/mapped_srcs_build.rs" => Ok(vec![]),
+                "/a/b/c/**/deprecation_build.rs" => Ok(vec![]),
+                "/a/b/c/**/rustc_cfg_build.rs" => Ok(vec![]),
+                "/a/b/c/**/rustfmt.toml" => Ok(vec![]),
+                "/a/b/c/**/clippy.toml" => Ok(vec![]),
+                "/a/b/c/**/.cargo/config.toml" => Ok(vec![]),
+                "/a/b/c/**/.config/nextest.toml" => Ok(vec![]),
+                "/a/b/c/**/README.md" => Ok(vec![]),
+                "/a/b/c/**/LICENSE" => Ok(vec![]),
+                "/a/b/c/**/LICENSE-APACHE" => Ok(vec![]),
+                "/a/b/c/**/LICENSE-MIT" => Ok(vec![]),
                 "/a/b/c/d/**/Cargo.toml" => Ok(vec![
                     Ok("/a/b/c/d/e/Cargo.toml"),
                     Ok("/a/b/c/d/f/Cargo.toml"),
@@ -370,8 +400,24 @@ mod test {
                 "/a/b/c/d/**/TARGETS.v2" => Ok(vec![Ok("/a/b/c/d/TARGETS.v2")]),
                 "/a/b/c/d/**/thrift_lib.rs" => Ok(vec![Ok("/a/b/c/d/thrift_lib.rs")]),
                 "/a/b/c/d/**/thrift_build.rs" => Ok(vec![Ok("/a/b/c/d/thrift_build.rs")]),
+                "/a/b/c/d/**/protobuf_build.rs" => Ok(vec![]),
+                "/a/b/c/d/**/protobuf_lib.rs" => Ok(vec![]),
+                "/a/b/c/d/**/cxx_build.rs" => Ok(vec![]),
+                "/a/b/c/d/**/mapped_srcs_build.rs" => Ok(vec![]),
+                "/a/b/c/d/**/deprecation_build.rs" => Ok(vec![]),
+                "/a/b/c/d/**/rustc_cfg_build.rs" => Ok(vec![]),
+                "/a/b/c/d/**/rustfmt.toml" => Ok(vec![]),
+                "/a/b/c/d/**/clippy.toml" => Ok(vec![]),
+                "/a/b/c/d/**/.cargo/config.toml" => Ok(vec![]),
+                "/a/b/c/d/**/.config/nextest.toml" => Ok(vec![]),
+                "/a/b/c/d/**/README.md" => Ok(vec![]),
+                "/a/b/c/d/**/LICENSE" => Ok(vec![]),
+                "/a/b/c/d/**/LICENSE-APACHE" => Ok(vec![]),
+                "/a/b/c/d/**/LICENSE-MIT" => Ok(vec![]),
             })),
             &FbcodeRoot::new_mock("/a"),
+            &ConcurrencyLimit::default(),
+            &BuildFilenames::default(),
             &configs,
         )
         .await
@@ -443,6 +489,20 @@ mod test {
             "/a/b/c/d/**/TARGETS.v2" => Ok(vec![Ok("/a/b/c/d/TARGETS.v2")]),
             "/a/b/c/d/**/thrift_lib.rs" => Ok(vec![Ok("/a/b/c/d/thrift_lib.rs")]),
             "/a/b/c/d/**/thrift_build.rs" => Ok(vec![Ok("/a/b/c/d/f/thrift_build.rs")]),
+            "/a/b/c/d/**/protobuf_build.rs" => Ok(vec![]),
+            "/a/b/c/d/**/protobuf_lib.rs" => Ok(vec![]),
+            "/a/b/c/d/**/cxx_build.rs" => Ok(vec![]),
+            "/a/b/c/d/**/mapped_srcs_build.rs" => Ok(vec![]),
+            "/a/b/c/d/**/deprecation_build.rs" => Ok(vec![]),
+            "/a/b/c/d/**/rustc_cfg_build.rs" => Ok(vec![]),
+            "/a/b/c/d/**/rustfmt.toml" => Ok(vec![]),
+            "/a/b/c/d/**/clippy.toml" => Ok(vec![]),
+            "/a/b/c/d/**/.cargo/config.toml" => Ok(vec![]),
+            "/a/b/c/d/**/.config/nextest.toml" => Ok(vec![]),
+            "/a/b/c/d/**/README.md" => Ok(vec![]),
+            "/a/b/c/d/**/LICENSE" => Ok(vec![]),
+            "/a/b/c/d/**/LICENSE-APACHE" => Ok(vec![]),
+            "/a/b/c/d/**/LICENSE-MIT" => Ok(vec![]),
         };
         let fbcode_root = FbcodeRoot::new_mock("/a/b");
 
@@ -451,6 +511,8 @@ mod test {
                 get_files_for_project(
                     Arc::new(glob_mock(glob_values.clone())),
                     &fbcode_root,
+                    &ConcurrencyLimit::default(),
+                    &BuildFilenames::default(),
                     &pc(&["c/d/**"], &[])
                 )
                 .await
@@ -468,6 +530,8 @@ mod test {
                 get_files_for_project(
                     Arc::new(glob_mock(glob_values.clone())),
                     &fbcode_root,
+                    &ConcurrencyLimit::default(),
+                    &BuildFilenames::default(),
                     &pc(&["c/d/**"], &["c/d/f/**"])
                 )
                 .await
@@ -485,6 +549,8 @@ mod test {
                 get_files_for_project(
                     Arc::new(glob_mock(glob_values)),
                     &fbcode_root,
+                    &ConcurrencyLimit::default(),
+                    &BuildFilenames::default(),
                     &pc(&["c/d/**"], &["c/d/**"])
                 )
                 .await
@@ -503,8 +569,24 @@ mod test {
                     "/a/b/c/d/**/TARGETS.v2" => Ok(vec![]),
                     "/a/b/c/d/**/thrift_lib.rs" => Ok(vec![]),
                     "/a/b/c/d/**/thrift_build.rs" => Ok(vec![]),
+                    "/a/b/c/d/**/protobuf_build.rs" => Ok(vec![]),
+                    "/a/b/c/d/**/protobuf_lib.rs" => Ok(vec![]),
+                    "/a/b/c/d/**/cxx_build.rs" => Ok(vec![]),
+                    "/a/b/c/d/**/mapped_srcs_build.rs" => Ok(vec![]),
+                    "/a/b/c/d/**/deprecation_build.rs" => Ok(vec![]),
+                    "/a/b/c/d/**/rustc_cfg_build.rs" => Ok(vec![]),
+                    "/a/b/c/d/**/rustfmt.toml" => Ok(vec![]),
+                    "/a/b/c/d/**/clippy.toml" => Ok(vec![]),
+                    "/a/b/c/d/**/.cargo/config.toml" => Ok(vec![]),
+                    "/a/b/c/d/**/.config/nextest.toml" => Ok(vec![]),
+                    "/a/b/c/d/**/README.md" => Ok(vec![]),
+                    "/a/b/c/d/**/LICENSE" => Ok(vec![]),
+                    "/a/b/c/d/**/LICENSE-APACHE" => Ok(vec![]),
+                    "/a/b/c/d/**/LICENSE-MIT" => Ok(vec![]),
                 })),
                 &fbcode_root,
+                &ConcurrencyLimit::default(),
+                &BuildFilenames::default(),
                 &pc(&["c/d/**"], &["c/d/**"])
             ).await,
             Err(err) => {
@@ -519,6 +601,59 @@ mod test {
         );
     }
 
+    #[tokio::test]
+    async fn get_files_for_project_custom_build_filenames_test() {
+        if cfg!(windows) {
+            return; // Broken on Windows
+        }
+
+        let conf: ProjectConf = from_value(json!({
+            "name": "proj",
+            "include_globs": ["c/**"],
+            "oncall": "oncall_name",
+        }))
+        .unwrap();
+
+        let (_, mut targets, _) = get_files_for_project(
+            Arc::new(glob_mock(hashmap! {
+                "/a/c/**/Cargo.toml" => Ok(vec![]),
+                "/a/c/**/BUILD" => Ok(vec![Ok("/a/c/d/BUILD")]),
+                "/a/c/**/BUILD.bazel" => Ok(vec![Ok("/a/c/e/BUILD.bazel")]),
+                "/a/c/**/thrift_lib.rs" => Ok(vec![]),
+                "/a/c/**/thrift_build.rs" => Ok(vec![]),
+                "/a/c/**/protobuf_build.rs" => Ok(vec![]),
+                "/a/c/**/protobuf_lib.rs" => Ok(vec![]),
+                "/a/c/**/cxx_build.rs" => Ok(vec![]),
+                "/a/c/**/mapped_srcs_build.rs" => Ok(vec![]),
+                "/a/c/**/deprecation_build.rs" => Ok(vec![]),
+                "/a/c/**/rustc_cfg_build.rs" => Ok(vec![]),
+                "/a/c/**/rustfmt.toml" => Ok(vec![]),
+                "/a/c/**/clippy.toml" => Ok(vec![]),
+                "/a/c/**/.cargo/config.toml" => Ok(vec![]),
+                "/a/c/**/.config/nextest.toml" => Ok(vec![]),
+                "/a/c/**/README.md" => Ok(vec![]),
+                "/a/c/**/LICENSE" => Ok(vec![]),
+                "/a/c/**/LICENSE-APACHE" => Ok(vec![]),
+                "/a/c/**/LICENSE-MIT" => Ok(vec![]),
+            })),
+            &FbcodeRoot::new_mock("/a"),
+            &ConcurrencyLimit::default(),
+            &BuildFilenames::new(["BUILD", "BUILD.bazel"]).unwrap(),
+            &conf,
+        )
+        .await
+        .unwrap();
+        targets.sort();
+
+        assert_eq!(
+            targets,
+            vec![
+                TargetsPath::from_buck_rule("c/d"),
+                TargetsPath::from_buck_rule("c/e"),
+            ]
+        );
+    }
+
     #[derive(Clone, Debug)]
     struct TestGetFilesHelper {
         test_run: u64,
@@ -11,6 +11,7 @@ use std::collections::HashSet;
 
 use super::ProjectLoader;
 use super::ProjectlessFiles;
+use crate::paths::BuildFilenames;
 use crate::paths::CargoTomlPath;
 use crate::paths::PathInFbcode;
 use crate::paths::TargetsPath;
@@ -25,12 +26,19 @@ impl<'proj, 'a> ProjectLoader<'proj, 'a> {
         all_targets: impl IntoIterator<Item = &'input TargetsPath>,
         all_additional: impl IntoIterator<Item = &'input PathInFbcode>,
     ) -> ProjectlessFiles {
-        projectless_files(self.input_paths, all_cargo, all_targets, all_additional)
+        projectless_files(
+            self.input_paths,
+            self.build_filenames,
+            all_cargo,
+            all_targets,
+            all_additional,
+        )
     }
 }
 
 fn projectless_files<'input>(
     input_paths: Vec<PathInFbcode>,
+    build_filenames: &BuildFilenames,
     all_cargo: impl IntoIterator<Item = &'input CargoTomlPath>,
     all_targets: impl IntoIterator<Item = &'input TargetsPath>,
     all_additional: impl IntoIterator<Item = &'input PathInFbcode>,
@@ -41,8 +49,8 @@ fn projectless_files<'input>(
     for path in input_paths {
         if path.as_ref().ends_with(CargoTomlPath::filename()) {
             input_cargo.insert(CargoTomlPath::new(path).unwrap());
-        } else if TargetsPath::matches_path(path.as_ref()) {
-            input_targets.insert(TargetsPath::new(path).unwrap());
+        } else if build_filenames.matches_path(path.as_ref()) {
+            input_targets.insert(TargetsPath::new_with_filenames(path, build_filenames).unwrap());
         } else if PathInFbcode::all_additional_filenames()
             .iter()
             .any(|filename| path.as_ref().ends_with(filename))
@@ -112,6 +120,7 @@ mod test {
                     "g/thrift_lib.rs",
                     "g/thrift_build.rs",
                 ]),
+                &BuildFilenames::default(),
                 &vec_cargo(&["b/c/Cargo.toml", "g/Cargo.toml"]),
                 &vec_targets(&["e/TARGETS", "c/BUCK"]),
                 &vec_fb(&["g/thrift_build.rs", "g/thrift_lib.rs"])
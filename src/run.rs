@@ -0,0 +1,506 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! High-level entrypoint of the generation pipeline for tools that embed
+//! autocargo instead of running its binary. [GenerationSession::run] performs
+//! all steps at once, the individual steps are exposed for callers that want
+//! to handle failures of each of them differently.
+//!
+//! Nothing is written to disk, it is up to the caller to persist the
+//! [GenerationOutput]. Each step fails with the variant of [AutocargoError]
+//! matching it.
+
+#[cfg(test)]
+mod golden_test;
+
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+use std::path::Path;
+
+use anyhow::Context;
+use anyhow::Result;
+use anyhow::anyhow;
+use anyhow::ensure;
+use itertools::Itertools;
+use slog::Logger;
+use slog::info;
+use slog::warn;
+use tokio::fs::try_exists;
+
+use crate::buck_processing::BuckFixtures;
+use crate::buck_processing::BuckOptions;
+use crate::buck_processing::ProcessOutput;
+use crate::buck_processing::ThriftCratemapCache;
+use crate::buck_processing::process_targets;
+use crate::cargo_generator::CargoGenerator;
+use crate::cargo_generator::GenerationOutput;
+use crate::cargo_generator::StampInputs;
+use crate::cargo_generator::THIRD_PARTY_CARGO_TOML;
+use crate::config::AllProjects;
+use crate::config::SelectedProjects;
+use crate::error::AutocargoError;
+use crate::events::EventSender;
+use crate::paths::BuildFilenames;
+use crate::paths::CargoTomlPath;
+use crate::paths::FbcodeRoot;
+use crate::paths::FbsourceRoot;
+use crate::paths::PathInFbcode;
+use crate::paths::TargetsPath;
+use crate::project_loader::ProjectFiles;
+use crate::project_loader::ProjectLoader;
+use crate::project_loader::ProjectlessFiles;
+use crate::util::concurrency::ConcurrencyLimit;
+
+/// Inputs of a single generation run.
+pub struct GenerationSession<'a> {
+    /// Used for logging.
+    pub logger: &'a Logger,
+    /// Receives progress events.
+    pub events: &'a EventSender,
+    /// Root of fbsource.
+    pub fbsource_root: &'a FbsourceRoot,
+    /// Configs of all projects.
+    pub all_configs: &'a AllProjects,
+    /// Paths for which to regenerate the covering projects.
+    pub input_paths: Vec<PathInFbcode>,
+    /// Names of projects to regenerate. If both this and input_paths are empty
+    /// then all projects are regenerated.
+    pub project_names: Vec<String>,
+    /// Run buck commands in an isolation dir.
+    pub use_isolation_dir: bool,
+    /// Log the progress that Buck prints to stderr instead of letting it print
+    /// directly.
+    pub show_buck_output: bool,
+    /// Record the Buck commands into or replay them from the fixtures.
+    pub buck_fixtures: Option<&'a BuckFixtures>,
+    /// Limits concurrent filesystem operations.
+    pub concurrency: &'a ConcurrencyLimit,
+    /// Filenames that are recognized as Buck build files.
+    pub build_filenames: &'a BuildFilenames,
+    /// Don't access the network, e.g. skip prefetching files with EdenFS and
+    /// never run Buck, only replay its commands from buck_fixtures.
+    pub offline: bool,
+    /// Cache of the thrift cratemaps built by Buck, shared between runs.
+    pub cratemap_cache: Option<&'a ThriftCratemapCache>,
+    /// Skip selected projects whose roots are missing from the checkout, e.g.
+    /// a sparse one, instead of failing.
+    pub allow_missing_roots: bool,
+    /// Inputs of the reproducibility stamps to put in the generated Cargo.toml
+    /// files.
+    pub stamp: Option<StampInputs>,
+}
+
+/// Projects selected for the run together with the files they cover.
+pub struct LoadedProjects<'a> {
+    /// Projects selected based on input paths and project names.
+    pub selected_configs: SelectedProjects<'a>,
+    /// Files covered by each of the selected projects.
+    pub project_files: Vec<ProjectFiles<'a>>,
+    /// Input files that are not covered by any project.
+    pub projectless_files: ProjectlessFiles,
+    /// Names of the selected projects that were skipped since their roots are
+    /// missing from the checkout.
+    pub skipped_projects: Vec<String>,
+}
+
+/// Result of [GenerationSession::run].
+pub struct SessionOutput<'a> {
+    /// Projects processed in the run.
+    pub loaded: LoadedProjects<'a>,
+    /// Generated files.
+    pub generated: GenerationOutput,
+}
+
+impl<'a> GenerationSession<'a> {
+    /// Run the whole pipeline and return the generated files.
+    pub async fn run(&self) -> Result<SessionOutput<'a>, AutocargoError> {
+        let loaded = self.load_projects(self.select_projects()?).await?;
+        let processed = self.process_targets(&loaded).await?;
+        let generator = self.cargo_generator(&loaded, &processed).await?;
+        let generated = self.generate(&generator, &loaded, &processed)?;
+        Ok(SessionOutput { loaded, generated })
+    }
+
+    /// True if the run covers only some of the projects.
+    pub fn is_partial(&self) -> bool {
+        !self.input_paths.is_empty() || !self.project_names.is_empty()
+    }
+
+    /// Select projects based on input paths and project names.
+    pub fn select_projects(&self) -> Result<SelectedProjects<'a>, AutocargoError> {
+        if self.is_partial() {
+            info!(
+                self.logger,
+                "Processing projects selected based on input paths and project names provided"
+            );
+            self.all_configs
+                .select_based_on_paths_and_names(&self.input_paths, &self.project_names)
+                .map_err(AutocargoError::config)
+        } else {
+            info!(
+                self.logger,
+                "Processing all projects since no input paths or project names were provided"
+            );
+            Ok(self.all_configs.select_all())
+        }
+    }
+
+    /// Find files covered by the selected projects. Fails if roots of any of
+    /// them are missing from the checkout, unless allow_missing_roots is set
+    /// in which case such projects are skipped.
+    pub async fn load_projects(
+        &self,
+        selected_configs: SelectedProjects<'a>,
+    ) -> Result<LoadedProjects<'a>, AutocargoError> {
+        let fbcode_root = FbcodeRoot::from(self.fbsource_root.clone());
+        let (selected_configs, missing) = self
+            .skip_missing_roots(&fbcode_root, selected_configs)
+            .await
+            .map_err(AutocargoError::project_files)?;
+
+        let (project_files, projectless_files) = ProjectLoader {
+            logger: self.logger,
+            fbsource_root: self.fbsource_root,
+            fbcode_root: &fbcode_root,
+            configs: &selected_configs,
+            input_paths: self.input_paths.clone(),
+            build_filenames: self.build_filenames,
+            events: self.events,
+            concurrency: self.concurrency,
+            offline: self.offline,
+            file_discovery: self.all_configs.global().file_discovery().as_ref(),
+        }
+        .load()
+        .await?;
+
+        let max_proj_name_len = selected_configs
+            .projects()
+            .iter()
+            .map(|conf| conf.name().len())
+            .chain(Some(12)) // The minimal will be 12
+            .max()
+            .unwrap_or(0);
+        for proj in &project_files {
+            info!(
+                self.logger,
+                "{:>4$} found Cargo.toml {:>3} TARGETS {:>3} additional {:>3}",
+                proj.conf().name(),
+                proj.cargo().len(),
+                proj.targets().len(),
+                proj.additional().len(),
+                max_proj_name_len,
+            );
+        }
+        if !self.input_paths.is_empty() {
+            info!(
+                self.logger,
+                "Files from input for which no project was found - \
+                Cargo.toml {:>3} TARGETS {:>3} additional {:>3}",
+                projectless_files.cargo().len(),
+                projectless_files.targets().len(),
+                projectless_files.additional().len()
+            );
+        }
+
+        Ok(LoadedProjects {
+            selected_configs,
+            project_files,
+            projectless_files,
+            skipped_projects: missing.into_keys().collect(),
+        })
+    }
+
+    async fn skip_missing_roots(
+        &self,
+        fbcode_root: &FbcodeRoot,
+        mut selected_configs: SelectedProjects<'a>,
+    ) -> Result<(SelectedProjects<'a>, BTreeMap<String, Vec<String>>)> {
+        let missing = missing_roots(fbcode_root, &selected_configs).await?;
+        ensure!(
+            missing.is_empty() || self.allow_missing_roots,
+            "Roots of the selected projects are missing from the checkout, check them out or \
+            pass --allow-missing-roots to skip these projects:\n{}",
+            missing
+                .iter()
+                .map(|(name, roots)| format!("  {name}: {}", roots.join(", ")))
+                .join("\n"),
+        );
+        for (name, roots) in &missing {
+            warn!(
+                self.logger,
+                "Skipping project {} since its roots are missing from the checkout: {}",
+                name,
+                roots.join(", "),
+            );
+        }
+        selected_configs.retain(|conf| !missing.contains_key(conf.name()));
+        Ok((selected_configs, missing))
+    }
+
+    /// Query Buck for manifests of the TARGETS files covered by the projects.
+    pub async fn process_targets(
+        &self,
+        loaded: &LoadedProjects<'a>,
+    ) -> Result<ProcessOutput, AutocargoError> {
+        self.process_targets_impl(
+            loaded
+                .project_files
+                .iter()
+                .flat_map(|p| p.targets().iter())
+                .collect(),
+        )
+        .await
+    }
+
+    /// Like [Self::process_targets], but only for the TARGETS files of the
+    /// selected projects that are among the input paths. Meant to be followed
+    /// by [Self::generate_changed].
+    pub async fn process_changed_targets(
+        &self,
+        loaded: &LoadedProjects<'a>,
+    ) -> Result<ProcessOutput, AutocargoError> {
+        let changed: HashSet<_> = self
+            .input_paths
+            .iter()
+            .filter_map(|path| {
+                TargetsPath::new_with_filenames(path.clone(), self.build_filenames).ok()
+            })
+            .collect();
+        self.process_targets_impl(
+            loaded
+                .project_files
+                .iter()
+                .flat_map(|p| p.targets().iter())
+                .filter(|targets_path| changed.contains(*targets_path))
+                .collect(),
+        )
+        .await
+    }
+
+    async fn process_targets_impl(
+        &self,
+        targets: Vec<&TargetsPath>,
+    ) -> Result<ProcessOutput, AutocargoError> {
+        let fbcode_root = FbcodeRoot::from(self.fbsource_root.clone());
+        let output = process_targets(
+            self.logger,
+            self.events,
+            &fbcode_root,
+            self.concurrency,
+            BuckOptions {
+                config: self.all_configs.global().buck(),
+                use_isolation_dir: self.use_isolation_dir,
+                show_output: self.show_buck_output,
+                fixtures: self.buck_fixtures,
+                offline: self.offline,
+                cratemap_cache: self.cratemap_cache,
+            },
+            targets,
+        )
+        .await?;
+
+        info!(
+            self.logger,
+            "Processed {} manifests; {} paths were out of scope of selected \
+            projects",
+            output.processed_manifests.values().flatten().count(),
+            output.unprocessed_paths.len(),
+        );
+
+        Ok(output)
+    }
+
+    /// Prepare the generator for the processed manifests.
+    pub async fn cargo_generator<'b>(
+        &self,
+        loaded: &'b LoadedProjects<'a>,
+        processed: &'b ProcessOutput,
+    ) -> Result<CargoGenerator<'b>, AutocargoError>
+    where
+        'a: 'b,
+    {
+        let third_party_path = Path::join(self.fbsource_root.as_ref(), THIRD_PARTY_CARGO_TOML);
+        if self.offline && !try_exists(&third_party_path).await.unwrap_or(false) {
+            return Err(AutocargoError::io(anyhow!(
+                "{} is missing from the checkout, it has to be fetched before running in \
+                offline mode",
+                third_party_path.display()
+            )));
+        }
+        CargoGenerator::new(
+            self.logger,
+            self.fbsource_root,
+            self.all_configs,
+            &loaded.project_files,
+            &processed.unprocessed_paths,
+        )
+        .await
+        .map(|generator| generator.with_stamp(self.stamp.clone()))
+    }
+
+    /// Generate files for the selected projects.
+    pub fn generate(
+        &self,
+        generator: &CargoGenerator<'_>,
+        loaded: &LoadedProjects<'a>,
+        processed: &ProcessOutput,
+    ) -> Result<GenerationOutput, AutocargoError> {
+        generator.generate_for_projects(
+            self.logger,
+            self.events,
+            &loaded.selected_configs,
+            &processed.processed_manifests,
+        )
+    }
+
+    /// Generate only the crates of the processed TARGETS files, without the
+    /// workspace manifests and crate indexes that need all crates of the
+    /// selected projects. Cargo.toml files that the workspaces would be saved
+    /// to are left out, since they would be incomplete.
+    pub fn generate_changed(
+        &self,
+        generator: &CargoGenerator<'_>,
+        loaded: &LoadedProjects<'a>,
+        processed: &ProcessOutput,
+    ) -> Result<GenerationOutput, AutocargoError> {
+        let mut output = generator.generate_for_many_targets(
+            self.logger,
+            self.events,
+            &processed.processed_manifests,
+        )?;
+        let workspace_paths: HashSet<_> = loaded
+            .selected_configs
+            .projects()
+            .iter()
+            .filter_map(|conf| conf.workspace_config().as_ref())
+            .map(|workspace| {
+                workspace
+                    .save_to_dir
+                    .as_ref()
+                    .unwrap_or(&workspace.scrape_dir)
+                    .join_to_path_in_fbcode(CargoTomlPath::filename())
+            })
+            .collect();
+        output
+            .cargo_manifests
+            .retain(|path, _| !workspace_paths.contains(path.as_file()));
+        Ok(output)
+    }
+}
+
+/// Roots of the selected projects that don't exist, keyed by the name of the
+/// project.
+async fn missing_roots(
+    fbcode_root: &FbcodeRoot,
+    selected_configs: &SelectedProjects<'_>,
+) -> Result<BTreeMap<String, Vec<String>>> {
+    let mut missing = BTreeMap::new();
+    for conf in selected_configs.projects() {
+        for root in conf.roots().iter().sorted() {
+            let path = Path::join(fbcode_root.as_ref(), root);
+            let exists = try_exists(&path)
+                .await
+                .with_context(|| format!("While checking if {} exists", path.display()))?;
+            if !exists {
+                missing
+                    .entry(conf.name().clone())
+                    .or_insert_with(Vec::new)
+                    .push(root.clone());
+            }
+        }
+    }
+    Ok(missing)
+}
+
+#[cfg(test)]
+mod test {
+    use assert_matches::assert_matches;
+    use slog::o;
+
+    use super::*;
+    use crate::config::GLOBAL_CONFIG_FILENAME;
+    use crate::config::ProjectConf;
+
+    #[tokio::test]
+    async fn load_projects_missing_roots_test() {
+        let dir = tempfile::tempdir().unwrap();
+        let config_dir = dir.path().join("config");
+        std::fs::create_dir_all(&config_dir).unwrap();
+        for name in ["present", "missing"] {
+            std::fs::write(
+                config_dir.join(format!("{name}.toml")),
+                format!("name = \"{name}\"\noncall = \"oncall_name\"\nroots = [\"{name}\"]\n"),
+            )
+            .unwrap();
+        }
+        // Globbing is mocked in tests, so the files are listed by a command.
+        std::fs::write(
+            config_dir.join(GLOBAL_CONFIG_FILENAME),
+            "[file_discovery]\nbinary = \"sh\"\nargs = [\"-c\", \"echo fbcode/present/Cargo.toml\"]\n",
+        )
+        .unwrap();
+        let fbcode_root = FbcodeRoot::new_mock(dir.path().join(FbcodeRoot::dirname()));
+        std::fs::create_dir_all(Path::join(fbcode_root.as_ref(), "present")).unwrap();
+
+        let logger = Logger::root(slog::Discard, o!());
+        let events = EventSender::default();
+        let fbsource_root = FbsourceRoot::from(fbcode_root.clone());
+        let all_configs = ProjectConf::from_dir(&config_dir).await.unwrap();
+        let concurrency = ConcurrencyLimit::new(None);
+        let build_filenames = BuildFilenames::default();
+        let session = |allow_missing_roots| GenerationSession {
+            logger: &logger,
+            events: &events,
+            fbsource_root: &fbsource_root,
+            all_configs: &all_configs,
+            input_paths: Vec::new(),
+            project_names: Vec::new(),
+            use_isolation_dir: false,
+            show_buck_output: false,
+            buck_fixtures: None,
+            concurrency: &concurrency,
+            build_filenames: &build_filenames,
+            offline: true,
+            cratemap_cache: None,
+            allow_missing_roots,
+            stamp: None,
+        };
+
+        let err = session(false)
+            .load_projects(all_configs.select_all())
+            .await
+            .err()
+            .unwrap();
+        assert_matches!(err, AutocargoError::Config(_));
+        assert!(
+            err.to_string().ends_with(
+                "pass --allow-missing-roots to skip these projects:\n  missing: missing"
+            ),
+            "{err}"
+        );
+
+        let loaded = session(true)
+            .load_projects(all_configs.select_all())
+            .await
+            .unwrap();
+        assert_eq!(loaded.skipped_projects, ["missing"]);
+        assert_eq!(
+            loaded
+                .selected_configs
+                .projects()
+                .iter()
+                .map(|conf| conf.name().as_str())
+                .collect::<Vec<_>>(),
+            ["present"]
+        );
+        assert_eq!(loaded.project_files.len(), 1);
+        assert_eq!(loaded.project_files[0].conf().name(), "present");
+        assert_eq!(loaded.project_files[0].cargo().len(), 1);
+    }
+}
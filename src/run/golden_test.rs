@@ -0,0 +1,225 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+//! Golden tests of the whole generation pipeline. Each directory in
+//! [GOLDEN_DIR] is a case with:
+//! - `config/` - the autocargo config dir,
+//! - `fbsource/` - root of fbsource with third-party/rust/Cargo.toml and any
+//!   other files read during the generation,
+//! - `manifests.json` - raw rust manifests of all rules of the case, including
+//!   the dependencies, keyed by path of their build file relative to fbcode.
+//!   Buck is never run and the build files covered by the projects are taken
+//!   from here instead of globbing the filesystem,
+//! - `expected/` - the generated files, relative to fbcode.
+//!
+//! Run the tests with the UPDATE_GOLDEN env variable set to regenerate the
+//! `expected/` dirs after an intended change of the output.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::env::var_os;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+use anyhow::Context;
+use anyhow::Result;
+use itertools::Itertools;
+use pretty_assertions::assert_eq;
+use slog::Logger;
+use slog::o;
+use tokio::fs::read;
+
+use super::GenerationSession;
+use super::LoadedProjects;
+use crate::buck_processing::BuckOptions;
+use crate::buck_processing::FbcodeBuckRule;
+use crate::buck_processing::RawBuckManifest;
+use crate::buck_processing::process_raw_manifests;
+use crate::config::ProjectConf;
+use crate::events::EventSender;
+use crate::paths::BuildFilenames;
+use crate::paths::FbcodeRoot;
+use crate::paths::FbsourceRoot;
+use crate::paths::PathInFbcode;
+use crate::paths::TargetsPath;
+use crate::project_loader::ProjectFiles;
+use crate::project_loader::ProjectlessFiles;
+use crate::util::concurrency::ConcurrencyLimit;
+
+static GOLDEN_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/golden");
+static UPDATE_GOLDEN: &str = "UPDATE_GOLDEN";
+
+/// Run the generation for the case and return the content of the generated
+/// files keyed by their paths relative to fbcode.
+async fn generate_case(case_dir: &Path) -> Result<BTreeMap<PathBuf, String>> {
+    let logger = Logger::root(slog::Discard, o!());
+    let events = EventSender::default();
+    let fbcode_root = FbcodeRoot::new_mock(case_dir.join("fbsource").join(FbcodeRoot::dirname()));
+    let fbsource_root = FbsourceRoot::from(fbcode_root.clone());
+    let all_configs = ProjectConf::from_dir(case_dir.join("config")).await?;
+    let concurrency = ConcurrencyLimit::new(None);
+    let build_filenames = BuildFilenames::default();
+    let session = GenerationSession {
+        logger: &logger,
+        events: &events,
+        fbsource_root: &fbsource_root,
+        all_configs: &all_configs,
+        input_paths: Vec::new(),
+        project_names: Vec::new(),
+        use_isolation_dir: false,
+        show_buck_output: false,
+        buck_fixtures: None,
+        concurrency: &concurrency,
+        build_filenames: &build_filenames,
+        offline: true,
+        cratemap_cache: None,
+        allow_missing_roots: false,
+        stamp: None,
+    };
+
+    let manifests_path = case_dir.join("manifests.json");
+    let manifests: BTreeMap<String, Vec<RawBuckManifest>> =
+        serde_json::from_slice(&read(&manifests_path).await?)
+            .with_context(|| format!("While parsing {}", manifests_path.display()))?;
+    let mut raw_manifests = HashMap::new();
+    for (targets_path, manifests) in manifests {
+        let path = TargetsPath::new(PathInFbcode::new_mock(targets_path))?;
+        for raw in manifests {
+            let rule = FbcodeBuckRule {
+                path: path.clone(),
+                name: raw.name.clone(),
+            };
+            raw_manifests.insert(rule, raw);
+        }
+    }
+
+    let selected_configs = all_configs.select_all();
+    let project_files = selected_configs
+        .projects()
+        .iter()
+        .map(|conf| {
+            let targets = raw_manifests
+                .keys()
+                .map(|rule| rule.path.clone())
+                .filter(|path| conf.covers_path(&path.as_buck_path()))
+                .unique()
+                .collect();
+            ProjectFiles::new(conf, Vec::new(), targets, Vec::new())
+        })
+        .collect();
+    let loaded = LoadedProjects {
+        selected_configs,
+        project_files,
+        projectless_files: ProjectlessFiles::new(Vec::new(), Vec::new(), Vec::new()),
+        skipped_projects: Vec::new(),
+    };
+    let processed = process_raw_manifests(
+        &logger,
+        &fbcode_root,
+        &concurrency,
+        BuckOptions {
+            config: all_configs.global().buck(),
+            use_isolation_dir: false,
+            show_output: false,
+            fixtures: None,
+            offline: true,
+            cratemap_cache: None,
+        },
+        raw_manifests,
+    )
+    .await?;
+    let generator = session.cargo_generator(&loaded, &processed).await?;
+    let generated = session.generate(&generator, &loaded, &processed)?;
+
+    Ok(generated
+        .cargo_manifests
+        .iter()
+        .map(|(path, manifest)| {
+            let path: &Path = path.as_file().as_ref();
+            (path.to_owned(), manifest.to_toml_string())
+        })
+        .chain(generated.additional_files.iter().map(|(path, content)| {
+            let path: &Path = path.as_ref();
+            (path.to_owned(), content.clone())
+        }))
+        .collect())
+}
+
+/// Content of all files in the dir keyed by their paths relative to it.
+fn read_files(dir: &Path) -> Result<BTreeMap<PathBuf, String>> {
+    let mut files = BTreeMap::new();
+    let mut dirs = vec![dir.to_owned()];
+    while let Some(current) = dirs.pop() {
+        for entry in fs::read_dir(&current)
+            .with_context(|| format!("While reading dir {}", current.display()))?
+        {
+            let path = entry?.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else {
+                let content = fs::read_to_string(&path)
+                    .with_context(|| format!("While reading {}", path.display()))?;
+                files.insert(path.strip_prefix(dir)?.to_owned(), content);
+            }
+        }
+    }
+    Ok(files)
+}
+
+fn write_files(dir: &Path, files: &BTreeMap<PathBuf, String>) -> Result<()> {
+    if dir.exists() {
+        fs::remove_dir_all(dir).with_context(|| format!("While removing {}", dir.display()))?;
+    }
+    for (path, content) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, content).with_context(|| format!("While writing {}", path.display()))?;
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn golden_test() {
+    let update = var_os(UPDATE_GOLDEN).is_some();
+    let mut case_dirs = fs::read_dir(GOLDEN_DIR)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_dir())
+        .collect::<Vec<_>>();
+    case_dirs.sort();
+    assert!(!case_dirs.is_empty(), "No golden cases in {GOLDEN_DIR}");
+
+    for case_dir in case_dirs {
+        let case = case_dir.file_name().unwrap().to_string_lossy();
+        let generated = generate_case(&case_dir)
+            .await
+            .with_context(|| format!("In golden case {case}"))
+            .unwrap();
+        // The inputs are mostly held in HashMaps, whose iteration order differs
+        // between instances, so a second run catches output that depends on it.
+        assert_eq!(
+            generate_case(&case_dir).await.unwrap(),
+            generated,
+            "Output of golden case {case} is not deterministic",
+        );
+
+        let expected_dir = case_dir.join("expected");
+        if update {
+            write_files(&expected_dir, &generated).unwrap();
+        } else {
+            assert_eq!(
+                read_files(&expected_dir).unwrap(),
+                generated,
+                "Output of golden case {case} differs, rerun with {UPDATE_GOLDEN}=1 if \
+                the change is intended",
+            );
+        }
+    }
+}
@@ -8,5 +8,7 @@
  */
 
 pub mod command_runner;
+pub mod concurrency;
 pub mod deserialize;
 pub mod future_timeout;
+pub mod serialize;
@@ -0,0 +1,75 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::sync::Arc;
+
+use futures::Future;
+use tokio::sync::Semaphore;
+
+/// Limit on the number of filesystem operations, like reading Buck outputs or
+/// glob-searching project files, that are running at the same time. Clones
+/// share the same limit. The default value doesn't limit anything.
+#[derive(Clone, Debug)]
+pub struct ConcurrencyLimit(Arc<Semaphore>);
+
+impl ConcurrencyLimit {
+    /// Allow at most max operations at once, None means no limit.
+    pub fn new(max: Option<usize>) -> Self {
+        Self(Arc::new(Semaphore::new(
+            max.unwrap_or(Semaphore::MAX_PERMITS)
+                .clamp(1, Semaphore::MAX_PERMITS),
+        )))
+    }
+
+    /// Run the Future once there is room for it within the limit.
+    pub async fn run<Fut: Future>(&self, fut: Fut) -> Fut::Output {
+        let _permit = self
+            .0
+            .acquire()
+            .await
+            .expect("Logic error: the semaphore is never closed");
+        fut.await
+    }
+}
+
+impl Default for ConcurrencyLimit {
+    fn default() -> Self {
+        Self::new(None)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+
+    use futures::future::join_all;
+    use tokio::task::yield_now;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn concurrency_limit_test() {
+        let limit = ConcurrencyLimit::new(Some(2));
+        let running = AtomicUsize::new(0);
+        let max_running = AtomicUsize::new(0);
+
+        join_all((0..10).map(|_| {
+            limit.run(async {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                max_running.fetch_max(now, Ordering::SeqCst);
+                yield_now().await;
+                running.fetch_sub(1, Ordering::SeqCst);
+            })
+        }))
+        .await;
+
+        assert_eq!(max_running.load(Ordering::SeqCst), 2);
+    }
+}
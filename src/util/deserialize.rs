@@ -7,9 +7,11 @@
  * of this source tree.
  */
 
+use std::collections::HashMap;
 use std::collections::HashSet;
 
 use glob::Pattern;
+use regex::Regex;
 use serde::de::Deserialize;
 use serde::de::Deserializer;
 use serde::de::Error;
@@ -19,20 +21,58 @@ where
     D: Deserializer<'de>,
 {
     let input: Vec<String> = Deserialize::deserialize(deserializer)?;
+    input.into_iter().map(parse_glob).collect()
+}
+
+pub fn deserialize_glob<'de, D>(deserializer: D) -> Result<Pattern, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    parse_glob(Deserialize::deserialize(deserializer)?)
+}
+
+pub fn deserialize_globs_map<'de, D>(
+    deserializer: D,
+) -> Result<HashMap<String, HashSet<Pattern>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let input: HashMap<String, Vec<String>> = Deserialize::deserialize(deserializer)?;
     input
         .into_iter()
-        .map(|s| {
-            if is_target_like(&s) {
-                Err(Error::custom(format!(
-                    "expected path glob but `{s}` looks like a buck target"
-                )))
-            } else {
-                Pattern::new(&s).map_err(Error::custom)
-            }
+        .map(|(key, globs)| {
+            Ok((
+                key,
+                globs
+                    .into_iter()
+                    .map(parse_glob)
+                    .collect::<Result<_, _>>()?,
+            ))
         })
         .collect()
 }
 
+pub fn deserialize_regexes<'de, D>(deserializer: D) -> Result<Vec<Regex>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let input: Vec<String> = Deserialize::deserialize(deserializer)?;
+    input
+        .iter()
+        .map(|s| Regex::new(s).map_err(D::Error::custom))
+        .collect()
+}
+
+fn parse_glob<E: Error>(s: String) -> Result<Pattern, E> {
+    if is_target_like(&s) {
+        Err(E::custom(format!(
+            "expected path glob but `{s}` looks like a buck target"
+        )))
+    } else {
+        Pattern::new(&s).map_err(E::custom)
+    }
+}
+
 fn is_target_like(s: &str) -> bool {
     if let Some((_head, tail)) = s.rsplit_once('/') {
         if tail == "..." || tail.contains(':') {
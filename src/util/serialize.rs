@@ -0,0 +1,60 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This source code is licensed under both the MIT license found in the
+ * LICENSE-MIT file in the root directory of this source tree and the Apache
+ * License, Version 2.0 found in the LICENSE-APACHE file in the root directory
+ * of this source tree.
+ */
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use glob::Pattern;
+use itertools::Itertools;
+use serde::ser::Serialize;
+use serde::ser::Serializer;
+
+pub fn serialize_globs<S>(globs: &HashSet<Pattern>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    sorted_globs(globs).serialize(serializer)
+}
+
+pub fn serialize_glob<S>(glob: &Pattern, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    glob.as_str().serialize(serializer)
+}
+
+pub fn serialize_globs_map<S>(
+    globs_map: &HashMap<String, HashSet<Pattern>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    globs_map
+        .iter()
+        .map(|(key, globs)| (key, sorted_globs(globs)))
+        .collect::<BTreeMap<_, _>>()
+        .serialize(serializer)
+}
+
+pub fn serialize_sorted<S, T>(set: &HashSet<T>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+    T: Serialize + Ord,
+{
+    set.iter()
+        .sorted()
+        .collect::<Vec<_>>()
+        .serialize(serializer)
+}
+
+fn sorted_globs(globs: &HashSet<Pattern>) -> Vec<&str> {
+    globs.iter().map(|glob| glob.as_str()).sorted().collect()
+}